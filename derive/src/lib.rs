@@ -0,0 +1,337 @@
+//! Derive macros for clarity.
+//!
+//! `#[derive(AbiToken)]` implements `clarity::abi::AbiToken` for a struct
+//! as the Solidity tuple of its fields, in order, and adds `into_tokens`
+//! and `from_tokens` for using the fields as the arguments or return values
+//! of a call:
+//!
+//! ```rust,ignore
+//! use clarity::abi::{encode_call, AbiToken};
+//! use clarity::Address;
+//! use clarity_derive::AbiToken;
+//!
+//! #[derive(AbiToken)]
+//! struct Transfer {
+//!     to: Address,
+//!     amount: u128,
+//! }
+//!
+//! let transfer = Transfer { to, amount: 1_000 };
+//! let calldata = encode_call("transfer(address,uint256)", &transfer.into_tokens());
+//! ```
+//!
+//! Every field has to implement `AbiToken`, including the fields of other
+//! derived structs, which become nested tuples.
+//!
+//! `#[derive(EthEvent)]` makes a struct an event, whose fields are the
+//! parameters of the event in order. It adds the `SIGNATURE` and `TOPIC0`
+//! constants and a `decode_log` constructor:
+//!
+//! ```rust,ignore
+//! use clarity::Address;
+//! use num256::Uint256;
+//! use clarity_derive::EthEvent;
+//!
+//! #[derive(EthEvent)]
+//! struct Transfer {
+//!     #[event(indexed)]
+//!     from: Address,
+//!     #[event(indexed)]
+//!     to: Address,
+//!     value: Uint256,
+//! }
+//!
+//! assert_eq!(Transfer::SIGNATURE, "Transfer(address,address,uint256)");
+//! let transfer = Transfer::decode_log(&log.topics, &log.data)?;
+//! ```
+//!
+//! The event is named after the struct unless `#[event(name = "...")]` is
+//! given, and `#[event(anonymous)]` makes it anonymous. The ABI types of the
+//! fields are read from their Rust types, which have to be `Address`,
+//! `Uint256`, `Int256`, `bool`, `String`, `ByteBuf`, the unsigned integers,
+//! `[u8; N]` or `Vec`s of them; other fields need
+//! `#[event(abi_type = "...")]`. Indexed strings, bytes, arrays and structs
+//! are logged as their hash, which is decoded into a `[u8; 32]` field.
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use sha3::{Digest, Keccak256};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Expr, Fields, Index, Lit, LitStr, Type,
+};
+
+#[proc_macro_derive(AbiToken)]
+pub fn derive_abi_token(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match abi_token(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn abi_token(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "AbiToken can only be derived for structs",
+            ))
+        }
+    };
+
+    let types: Vec<_> = fields.iter().map(|field| &field.ty).collect();
+    let len = types.len();
+    // How to read each field of `self`, and how to build the struct from
+    // the values in `items`
+    let (accessors, construct) = match *fields {
+        Fields::Named(ref named) => {
+            let idents: Vec<_> = named.named.iter().map(|f| &f.ident).collect();
+            (
+                idents
+                    .iter()
+                    .map(|ident| quote!(#ident))
+                    .collect::<Vec<_>>(),
+                quote! {
+                    #name {
+                        #(#idents: ::clarity::abi::AbiToken::from_token(
+                            items.next().unwrap()
+                        )?,)*
+                    }
+                },
+            )
+        }
+        Fields::Unnamed(_) => {
+            let indices: Vec<_> = (0..len).map(Index::from).collect();
+            let values = (0..len)
+                .map(|_| quote!(::clarity::abi::AbiToken::from_token(items.next().unwrap())?));
+            (
+                indices.iter().map(|index| quote!(#index)).collect(),
+                quote!(#name(#(#values,)*)),
+            )
+        }
+        Fields::Unit => (Vec::new(), quote!(#name)),
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::clarity::abi::AbiToken for #name #ty_generics #where_clause {
+            fn abi_type() -> ::std::string::String {
+                let members: ::std::vec::Vec<::std::string::String> = vec![
+                    #(<#types as ::clarity::abi::AbiToken>::abi_type(),)*
+                ];
+                format!("({})", members.join(","))
+            }
+
+            fn into_token(self) -> ::clarity::abi::Token {
+                ::clarity::abi::Token::Tuple(self.into_tokens())
+            }
+
+            #[allow(unused_mut, unused_variables)]
+            fn from_token(
+                token: ::clarity::abi::Token,
+            ) -> ::std::result::Result<Self, ::clarity::abi::AbiDecodeError> {
+                let mut items = ::clarity::abi::tuple_items(token, #len)?.into_iter();
+                Ok(#construct)
+            }
+        }
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// The fields as tokens, in order.
+            pub fn into_tokens(self) -> ::std::vec::Vec<::clarity::abi::Token> {
+                vec![#(::clarity::abi::AbiToken::into_token(self.#accessors),)*]
+            }
+
+            /// Builds the struct from one token per field, such as decoded
+            /// arguments or return values.
+            pub fn from_tokens(
+                tokens: ::std::vec::Vec<::clarity::abi::Token>,
+            ) -> ::std::result::Result<Self, ::clarity::abi::AbiDecodeError> {
+                <Self as ::clarity::abi::AbiToken>::from_token(
+                    ::clarity::abi::Token::Tuple(tokens),
+                )
+            }
+        }
+    })
+}
+
+#[proc_macro_derive(EthEvent, attributes(event))]
+pub fn derive_eth_event(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match eth_event(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+/// The ABI type of a field, read from its Rust type where the type is one
+/// `AbiToken` is implemented for.
+fn abi_type_of(ty: &Type) -> Option<String> {
+    match *ty {
+        Type::Path(ref path) => {
+            let segment = path.path.segments.last()?;
+            let name = segment.ident.to_string();
+            Some(match name.as_str() {
+                "Address" => "address".to_owned(),
+                "Uint256" => "uint256".to_owned(),
+                "Int256" => "int256".to_owned(),
+                "bool" => "bool".to_owned(),
+                "String" => "string".to_owned(),
+                "ByteBuf" => "bytes".to_owned(),
+                "u8" | "u16" | "u32" | "u64" | "u128" => format!("uint{}", &name[1..]),
+                "Vec" => match segment.arguments {
+                    syn::PathArguments::AngleBracketed(ref args) => match args.args.first()? {
+                        syn::GenericArgument::Type(ref item) => format!("{}[]", abi_type_of(item)?),
+                        _ => return None,
+                    },
+                    _ => return None,
+                },
+                _ => return None,
+            })
+        }
+        Type::Array(ref array) => {
+            let is_u8 = matches!(*array.elem, Type::Path(ref path) if path.path.is_ident("u8"));
+            match array.len {
+                Expr::Lit(ref len) if is_u8 => match len.lit {
+                    Lit::Int(ref len) => Some(format!("bytes{}", len.base10_digits())),
+                    _ => None,
+                },
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// The `#[event(...)]` options of a struct or field.
+#[derive(Default)]
+struct EventOptions {
+    name: Option<String>,
+    anonymous: bool,
+    indexed: bool,
+    abi_type: Option<String>,
+}
+
+fn event_options(attrs: &[Attribute]) -> syn::Result<EventOptions> {
+    let mut options = EventOptions::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("event")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                options.name = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else if meta.path.is_ident("anonymous") {
+                options.anonymous = true;
+            } else if meta.path.is_ident("indexed") {
+                options.indexed = true;
+            } else if meta.path.is_ident("abi_type") {
+                options.abi_type = Some(meta.value()?.parse::<LitStr>()?.value());
+            } else {
+                return Err(meta.error("unknown event option"));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(options)
+}
+
+fn eth_event(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let fields = match input.data {
+        Data::Struct(ref data) => &data.fields,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                input,
+                "EthEvent can only be derived for structs",
+            ))
+        }
+    };
+    let options = event_options(&input.attrs)?;
+    let event_name = options.name.unwrap_or_else(|| name.to_string());
+    let anonymous = options.anonymous;
+
+    let mut params = Vec::new();
+    let mut types = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let field_options = event_options(&field.attrs)?;
+        let abi_type = match field_options.abi_type {
+            Some(abi_type) => abi_type,
+            None => abi_type_of(&field.ty).ok_or_else(|| {
+                syn::Error::new_spanned(
+                    &field.ty,
+                    "the ABI type of this field is unknown, set it with #[event(abi_type = \"...\")]",
+                )
+            })?,
+        };
+        let param_name = match field.ident {
+            Some(ref ident) => ident.to_string(),
+            None => format!("_{}", i),
+        };
+        let indexed = field_options.indexed;
+        params.push(quote! {
+            ::clarity::abi::Param {
+                name: #param_name.to_owned(),
+                canonical_type: #abi_type.to_owned(),
+                indexed: #indexed,
+            }
+        });
+        types.push(abi_type);
+    }
+
+    let signature = format!("{}({})", event_name, types.join(","));
+    let topic0 = Keccak256::digest(signature.as_bytes());
+    let topic0 = topic0.iter();
+    let value = quote!(::clarity::abi::AbiToken::from_token(
+        values.next().unwrap()
+    )?);
+    let construct = match *fields {
+        Fields::Named(ref named) => {
+            let idents = named.named.iter().map(|f| &f.ident);
+            let values = (0..fields.len()).map(|_| value.clone());
+            quote!(#name { #(#idents: #values,)* })
+        }
+        Fields::Unnamed(_) => {
+            let values = (0..fields.len()).map(|_| value.clone());
+            quote!(#name(#(#values,)*))
+        }
+        Fields::Unit => quote!(#name),
+    };
+
+    Ok(quote! {
+        impl #impl_generics #name #ty_generics #where_clause {
+            /// The signature of the event.
+            pub const SIGNATURE: &'static str = #signature;
+
+            /// The hash of the signature, which is the first topic of the
+            /// logs of the event unless it is anonymous.
+            pub const TOPIC0: [u8; 32] = [#(#topic0),*];
+
+            /// The event, for decoding its logs.
+            pub fn event() -> ::clarity::abi::Event {
+                ::clarity::abi::Event {
+                    name: #event_name.to_owned(),
+                    inputs: vec![#(#params),*],
+                    anonymous: #anonymous,
+                }
+            }
+
+            /// Decodes a log of the event out of its topics and data.
+            #[allow(unused_mut, unused_variables)]
+            pub fn decode_log(
+                topics: &[[u8; 32]],
+                data: &[u8],
+            ) -> ::std::result::Result<Self, ::clarity::event::LogDecodeError> {
+                let params = ::clarity::event::decode_log(&Self::event(), topics, data)?;
+                let mut values = params.into_iter().map(|param| match param.value {
+                    ::clarity::event::LogValue::Value(token) => token,
+                    ::clarity::event::LogValue::Hashed(hash) => {
+                        ::clarity::abi::Token::Bytes(hash.to_vec())
+                    }
+                });
+                Ok(#construct)
+            }
+        }
+    })
+}
@@ -0,0 +1,115 @@
+extern crate clarity;
+extern crate clarity_derive;
+extern crate num256;
+extern crate serde_bytes;
+
+use clarity::abi::{decode_tokens, encode_call, encode_tokens, parse_param_types};
+use clarity::abi::{AbiDecodeError, AbiToken, Token};
+use clarity::Address;
+use clarity_derive::AbiToken;
+use num256::Uint256;
+use serde_bytes::ByteBuf;
+
+#[derive(AbiToken, Debug, Clone, PartialEq)]
+struct Order {
+    maker: Address,
+    amount: u128,
+    expired: bool,
+}
+
+#[derive(AbiToken, Debug, Clone, PartialEq)]
+struct Batch(Vec<Order>, ByteBuf, [u8; 4]);
+
+#[derive(AbiToken, Debug, Clone, PartialEq)]
+struct Fill {
+    order: Order,
+    taker: Address,
+    batch: Batch,
+}
+
+fn order(amount: u128) -> Order {
+    Order {
+        maker: Address::from([0x11; 20]),
+        amount,
+        expired: false,
+    }
+}
+
+#[test]
+fn structs_are_tuples() {
+    assert_eq!(Order::abi_type(), "(address,uint128,bool)");
+    assert_eq!(Batch::abi_type(), "((address,uint128,bool)[],bytes,bytes4)");
+
+    let tokens = order(5).into_tokens();
+    assert_eq!(
+        tokens,
+        vec![
+            Token::Address(Address::from([0x11; 20])),
+            5u8.into(),
+            false.into(),
+        ]
+    );
+    let calldata = encode_call("fill(address,uint128,bool)", &tokens);
+    let types = parse_param_types("fill(address,uint128,bool)").unwrap();
+    let decoded = decode_tokens(&types, &calldata[4..]).unwrap();
+    assert_eq!(Order::from_tokens(decoded).unwrap(), order(5));
+}
+
+#[test]
+fn nested_structs() {
+    let batch = Batch(
+        vec![order(1), order(2)],
+        ByteBuf::from(vec![0xab; 3]),
+        *b"abcd",
+    );
+    let token = batch.clone().into_token();
+    assert_eq!(
+        encode_tokens(&[token.clone()]),
+        encode_tokens(&[Token::Tuple(batch.clone().into_tokens())])
+    );
+    assert_eq!(Batch::from_token(token).unwrap(), batch);
+}
+
+#[test]
+fn nested_structs_from_calldata() {
+    let fill = Fill {
+        order: order(3),
+        taker: Address::from([0x22; 20]),
+        batch: Batch(vec![order(4)], ByteBuf::from(vec![0xcd; 40]), *b"wxyz"),
+    };
+    let signature = format!("fill{}", Fill::abi_type());
+    assert_eq!(
+        signature,
+        "fill((address,uint128,bool),address,((address,uint128,bool)[],bytes,bytes4))"
+    );
+    let calldata = encode_call(&signature, &fill.clone().into_tokens());
+    let types = parse_param_types(&signature).unwrap();
+    let decoded = decode_tokens(&types, &calldata[4..]).unwrap();
+    assert_eq!(Fill::from_tokens(decoded).unwrap(), fill);
+
+    // A struct passed as a single argument
+    let signature = format!("submit({})", Batch::abi_type());
+    let calldata = encode_call(&signature, &[fill.batch.clone().into_token()]);
+    let types = parse_param_types(&signature).unwrap();
+    let mut decoded = decode_tokens(&types, &calldata[4..]).unwrap();
+    assert_eq!(Batch::from_token(decoded.remove(0)).unwrap(), fill.batch);
+}
+
+#[test]
+fn mismatched_tokens() {
+    assert_eq!(
+        Order::from_tokens(vec![Token::Bool(true), 5u8.into(), false.into()]).unwrap_err(),
+        AbiDecodeError::UnexpectedToken("address".to_owned())
+    );
+    assert_eq!(
+        Order::from_tokens(vec![false.into()]).unwrap_err(),
+        AbiDecodeError::ArgumentCount {
+            expected: 3,
+            given: 1,
+        }
+    );
+    // Values out of the range of the field
+    let too_large = Token::Uint(Uint256::from(u128::MAX) * Uint256::from(2u8));
+    assert!(Order::from_tokens(vec![Address::default().into(), too_large, false.into()]).is_err());
+    assert!(<[u8; 4]>::from_token(Token::Bytes(vec![0; 5])).is_err());
+}
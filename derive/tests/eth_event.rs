@@ -0,0 +1,104 @@
+extern crate clarity;
+extern crate clarity_derive;
+extern crate num256;
+
+use clarity::abi::{derive_signature, encode_tokens, Token};
+use clarity::event::{topic, LogDecodeError};
+use clarity::Address;
+use clarity_derive::EthEvent;
+use num256::{Int256, Uint256};
+
+#[derive(EthEvent, Debug, PartialEq)]
+struct Transfer {
+    #[event(indexed)]
+    from: Address,
+    #[event(indexed)]
+    to: Address,
+    value: Uint256,
+}
+
+#[derive(EthEvent, Debug, PartialEq)]
+#[event(name = "Registered", anonymous)]
+struct NameRegistered(
+    #[event(indexed, abi_type = "string")] [u8; 32],
+    Vec<u32>,
+    #[event(abi_type = "uint64")] u64,
+);
+
+#[derive(EthEvent, Debug, PartialEq)]
+struct Swap {
+    #[event(indexed)]
+    sender: Address,
+    amount0: Int256,
+    amount1: Int256,
+    #[event(indexed, abi_type = "int24")]
+    tick: Int256,
+}
+
+#[test]
+fn event_constants() {
+    assert_eq!(Transfer::SIGNATURE, "Transfer(address,address,uint256)");
+    assert_eq!(Transfer::TOPIC0, derive_signature(Transfer::SIGNATURE));
+    assert_eq!(
+        NameRegistered::SIGNATURE,
+        "Registered(string,uint32[],uint64)"
+    );
+    assert!(NameRegistered::event().anonymous);
+}
+
+#[test]
+fn decode_typed_logs() {
+    let from = Address::from([0x11; 20]);
+    let to = Address::from([0x22; 20]);
+    let topics = vec![
+        Transfer::TOPIC0,
+        topic(&Token::Address(from)),
+        topic(&Token::Address(to)),
+    ];
+    let data = encode_tokens(&[5u8.into()]);
+    assert_eq!(
+        Transfer::decode_log(&topics, &data).unwrap(),
+        Transfer {
+            from,
+            to,
+            value: 5u8.into(),
+        }
+    );
+    assert_eq!(
+        Transfer::decode_log(&topics[..2], &data).unwrap_err(),
+        LogDecodeError::TopicCount {
+            expected: 3,
+            found: 2,
+        }
+    );
+
+    let name = topic(&"alice".into());
+    let data = encode_tokens(&[vec![1u32, 2].into(), 7u8.into()]);
+    assert_eq!(
+        NameRegistered::decode_log(&[name], &data).unwrap(),
+        NameRegistered(name, vec![1, 2], 7)
+    );
+    assert!(NameRegistered::decode_log(&[name], &data[..32]).is_err());
+}
+
+#[test]
+fn decode_signed_fields() {
+    assert_eq!(Swap::SIGNATURE, "Swap(address,int256,int256,int24)");
+    let sender = Address::from([0x11; 20]);
+    let tick = Int256::from(-887_272i64);
+    let topics = vec![
+        Swap::TOPIC0,
+        topic(&Token::Address(sender)),
+        topic(&tick.clone().into()),
+    ];
+    let data = encode_tokens(&[Int256::from(-5i64).into(), 7i64.into()]);
+    assert_eq!(
+        Swap::decode_log(&topics, &data).unwrap(),
+        Swap {
+            sender,
+            amount0: Int256::from(-5i64),
+            amount1: Int256::from(7i64),
+            tick,
+        }
+    );
+}
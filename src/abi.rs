@@ -4,7 +4,11 @@
 //! tokens and they will be automatically encoded.
 //!
 //! Additionally there are helpers to help deal with deriving a function
-//! signatures.
+//! signatures, to decode parameters and return data
+//! (see [decode_tokens](fn.decode_tokens.html)), and to compare two calls of
+//! the same function (see [diff_calldata](fn.diff_calldata.html)). The
+//! functions, events and errors of a contract can be read from its ABI JSON
+//! (see [Abi](struct.Abi.html)).
 //!
 //! This is not a full fledged implemementation of ABI encoder, it is more
 //! like a bunch of helpers that would help to successfuly encode a contract
@@ -12,22 +16,37 @@
 //!
 //! ## Limitation
 //!
-//! Currently this module can only serialize types that can be represented by a [Token](#struct.Token).
+//! Currently this module can only serialize and decode types that can be represented by a [Token](#struct.Token).
 //!
 //! Unfortunately if you need to support custom type that is not currently supported you are welcome to open an issue [on issues page](https://github.com/althea-mesh/clarity/issues/new),
 //! or do the serialization yourself by converting your custom type into a `[u8; 32]` array and creating a proper Token instance.
 use address::Address;
-use num256::Uint256;
-use sha3::{Digest, Keccak256};
+#[cfg(feature = "derive")]
+pub use clarity_derive::AbiToken;
+use failure::Error;
+use hash::keccak256;
+use instrument::Span;
+use num256::{Int256, Uint256};
+use num_bigint::BigInt;
+use num_traits::{Bounded, Signed, ToPrimitive};
+use serde_bytes::ByteBuf;
+use serde_crate::de;
+use serde_crate::{Deserialize, Deserializer};
+use std::convert::TryFrom;
+use std::fmt;
+use std::iter;
+use std::str::FromStr;
 
 /// A token represents a value of parameter of the contract call.
 ///
 /// For each supported type there is separate entry that later is helpful to determine
 /// actual byte representation.
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Token {
     /// Unsigned type with value already encoded.
     Uint(Uint256),
+    /// Signed integer of any of the `int<M>` types, in two's complement.
+    Int(Int256),
     /// Ethereum Address
     Address(Address),
     /// A boolean logic
@@ -38,8 +57,15 @@ pub enum Token {
     Bytes(Vec<u8>),
     /// This is a dynamic array of bytes that reflects dynamic "bytes" type in Solidity
     UnboundedBytes(Vec<u8>),
-    /// Dynamic array with supported values of supported types already converted
+    /// Dynamic array with supported values of supported types already converted.
+    /// The items can be dynamic themselves, as in `string[]` or `uint256[][]`.
     Dynamic(Vec<Token>),
+    /// A tuple, such as a Solidity struct, with its members in order. It is
+    /// dynamic if any of its members is.
+    Tuple(Vec<Token>),
+    /// Fixed size array (`T[N]`), which is encoded like a tuple of its items:
+    /// in place, without a length.
+    FixedArray(Vec<Token>),
 }
 
 /// Representation of a serialized token.
@@ -59,6 +85,9 @@ pub enum SerializedToken {
     /// This data should be saved up in a buffer, and an offset should be
     /// appended to the output stream instead.
     Dynamic(Vec<u8>),
+    /// A tuple of static types, which takes several words and is appended to
+    /// the output stream as is.
+    StaticTuple(Vec<u8>),
 }
 
 impl SerializedToken {
@@ -69,9 +98,29 @@ impl SerializedToken {
             _ => None,
         }
     }
+
+    /// How many bytes the token takes in the head of a tuple.
+    fn head_len(&self) -> usize {
+        match *self {
+            SerializedToken::StaticTuple(ref data) => data.len(),
+            _ => 32,
+        }
+    }
 }
 
 impl Token {
+    /// Whether the token is encoded in the tail of the enclosing tuple,
+    /// with an offset in its head.
+    pub(crate) fn is_dynamic(&self) -> bool {
+        match *self {
+            Token::String(_) | Token::UnboundedBytes(_) | Token::Dynamic(_) => true,
+            Token::Tuple(ref tokens) | Token::FixedArray(ref tokens) => {
+                tokens.iter().any(Token::is_dynamic)
+            }
+            _ => false,
+        }
+    }
+
     /// Serializes a token into a [SerializedToken]()
     pub fn serialize(&self) -> SerializedToken {
         match *self {
@@ -82,39 +131,43 @@ impl Token {
                 res[32 - bytes.len()..].copy_from_slice(&bytes);
                 SerializedToken::Static(res)
             }
+            Token::Int(ref value) => {
+                assert!(*value >= Int256::min_value() && *value <= Int256::max_value());
+                let bytes = value.to_signed_bytes_be();
+                // Sign extended to the whole word
+                let fill = if value.is_negative() { 0xff } else { 0x00 };
+                let mut res = [fill; 32];
+                res[32 - bytes.len()..].copy_from_slice(&bytes);
+                SerializedToken::Static(res)
+            }
             Token::Bool(value) => {
                 let mut res: [u8; 32] = Default::default();
                 res[31] = value as u8;
                 SerializedToken::Static(res)
             }
             Token::Dynamic(ref tokens) => {
-                // This one supports only 1 dimension, and in theory
-                // adding support for multiple dimmension mixed with static
-                // or dynamic bounds (i.e. string[10][9]) could be trivial
-                // and we could call serialize recursively, and return multiple
-                // SerializedTokens. For our needs it implements just simple case
-                // with one dimension max.
+                // The length, followed by the items encoded like a tuple
                 let mut wtr = vec![];
                 let prefix: Token = (tokens.len() as u64).into();
                 wtr.extend(prefix.serialize().as_static_ref().unwrap());
-                for token in tokens.iter() {
-                    wtr.extend(
-                        token
-                            .serialize()
-                            .as_static_ref()
-                            .expect("Only nested tokens of static size are supported"),
-                    );
-                }
+                wtr.extend(encode_sequence(tokens));
                 SerializedToken::Dynamic(wtr)
             }
+            Token::Tuple(ref tokens) | Token::FixedArray(ref tokens) => {
+                if self.is_dynamic() {
+                    SerializedToken::Dynamic(encode_sequence(tokens))
+                } else {
+                    SerializedToken::StaticTuple(encode_sequence(tokens))
+                }
+            }
             Token::UnboundedBytes(ref v) => {
                 let mut wtr = vec![];
                 // Encode prefix
                 let prefix: Token = (v.len() as u64).into();
                 wtr.extend(prefix.serialize().as_static_ref().unwrap());
-                // Pad on the right
+                // Pad on the right, to no words at all when empty
                 wtr.extend(v);
-                let pad_right = (((v.len() - 1) / 32) + 1) * 32;
+                let pad_right = v.len().div_ceil(32) * 32;
                 wtr.extend(vec![0x00u8; pad_right - v.len()]);
                 SerializedToken::Dynamic(wtr)
             }
@@ -126,7 +179,7 @@ impl Token {
                 // Pad on the right
                 wtr.extend(s.as_bytes());
 
-                let pad_right = (((s.len() - 1) / 32) + 1) * 32;
+                let pad_right = s.len().div_ceil(32) * 32;
                 wtr.extend(vec![0x00u8; pad_right - s.len()]);
                 SerializedToken::Dynamic(wtr)
             }
@@ -173,6 +226,42 @@ impl From<u64> for Token {
     }
 }
 
+impl From<i8> for Token {
+    fn from(v: i8) -> Token {
+        Token::Int(Int256::from(v))
+    }
+}
+
+impl From<i16> for Token {
+    fn from(v: i16) -> Token {
+        Token::Int(Int256::from(v))
+    }
+}
+
+impl From<i32> for Token {
+    fn from(v: i32) -> Token {
+        Token::Int(Int256::from(v))
+    }
+}
+
+impl From<i64> for Token {
+    fn from(v: i64) -> Token {
+        Token::Int(Int256::from(v))
+    }
+}
+
+impl From<i128> for Token {
+    fn from(v: i128) -> Token {
+        Token::Int(Int256::from(v))
+    }
+}
+
+impl From<Int256> for Token {
+    fn from(v: Int256) -> Token {
+        Token::Int(v)
+    }
+}
+
 impl From<bool> for Token {
     fn from(v: bool) -> Token {
         Token::Bool(v)
@@ -209,16 +298,223 @@ impl From<Uint256> for Token {
     }
 }
 
+/// Rust values of an ABI type, which convert to and from its token.
+///
+/// Structs implement it with `#[derive(AbiToken)]` from the
+/// `clarity-derive` crate (re-exported with the `derive` feature), as the
+/// tuple of their fields in order:
+///
+/// ```rust,ignore
+/// #[derive(AbiToken)]
+/// struct Order {
+///     maker: Address,
+///     amount: Uint256,
+///     expired: bool,
+/// }
+///
+/// assert_eq!(Order::abi_type(), "(address,uint256,bool)");
+/// let tokens = order.into_tokens();
+/// let order = Order::from_tokens(tokens)?;
+/// ```
+///
+/// `bytes` are held in a `ByteBuf`, as `Vec<u8>` is a `uint8[]`, and
+/// `bytes<M>` in a `[u8; M]`.
+pub trait AbiToken: Sized {
+    /// The type as it appears in signatures, such as `uint256[]`
+    fn abi_type() -> String;
+
+    fn into_token(self) -> Token;
+
+    fn from_token(token: Token) -> Result<Self, AbiDecodeError>;
+}
+
+/// The items of a tuple token of `len` members, for implementations of
+/// [AbiToken](trait.AbiToken.html) for structs.
+pub fn tuple_items(token: Token, len: usize) -> Result<Vec<Token>, AbiDecodeError> {
+    match token {
+        Token::Tuple(items) => {
+            if items.len() != len {
+                return Err(AbiDecodeError::ArgumentCount {
+                    expected: len,
+                    given: items.len(),
+                });
+            }
+            Ok(items)
+        }
+        _ => Err(AbiDecodeError::UnexpectedToken("tuple".to_owned())),
+    }
+}
+
+macro_rules! abi_token_uint {
+    ($($t:ty => $to:ident, $bits:expr),*) => {
+        $(
+            impl AbiToken for $t {
+                fn abi_type() -> String {
+                    format!("uint{}", $bits)
+                }
+
+                fn into_token(self) -> Token {
+                    Token::Uint(Uint256::from(self))
+                }
+
+                fn from_token(token: Token) -> Result<$t, AbiDecodeError> {
+                    match token {
+                        Token::Uint(ref value) => value.$to(),
+                        _ => None,
+                    }
+                    .ok_or_else(|| AbiDecodeError::UnexpectedToken(<$t>::abi_type()))
+                }
+            }
+        )*
+    };
+}
+
+abi_token_uint!(u8 => to_u8, 8, u16 => to_u16, 16, u32 => to_u32, 32, u64 => to_u64, 64, u128 => to_u128, 128);
+
+impl AbiToken for Uint256 {
+    fn abi_type() -> String {
+        "uint256".to_owned()
+    }
+
+    fn into_token(self) -> Token {
+        Token::Uint(self)
+    }
+
+    fn from_token(token: Token) -> Result<Uint256, AbiDecodeError> {
+        match token {
+            Token::Uint(value) => Ok(value),
+            _ => Err(AbiDecodeError::UnexpectedToken(Uint256::abi_type())),
+        }
+    }
+}
+
+impl AbiToken for Int256 {
+    fn abi_type() -> String {
+        "int256".to_owned()
+    }
+
+    fn into_token(self) -> Token {
+        Token::Int(self)
+    }
+
+    fn from_token(token: Token) -> Result<Int256, AbiDecodeError> {
+        match token {
+            Token::Int(value) => Ok(value),
+            _ => Err(AbiDecodeError::UnexpectedToken(Int256::abi_type())),
+        }
+    }
+}
+
+impl AbiToken for Address {
+    fn abi_type() -> String {
+        "address".to_owned()
+    }
+
+    fn into_token(self) -> Token {
+        Token::Address(self)
+    }
+
+    fn from_token(token: Token) -> Result<Address, AbiDecodeError> {
+        match token {
+            Token::Address(address) => Ok(address),
+            _ => Err(AbiDecodeError::UnexpectedToken(Address::abi_type())),
+        }
+    }
+}
+
+impl AbiToken for bool {
+    fn abi_type() -> String {
+        "bool".to_owned()
+    }
+
+    fn into_token(self) -> Token {
+        Token::Bool(self)
+    }
+
+    fn from_token(token: Token) -> Result<bool, AbiDecodeError> {
+        match token {
+            Token::Bool(value) => Ok(value),
+            _ => Err(AbiDecodeError::UnexpectedToken(bool::abi_type())),
+        }
+    }
+}
+
+impl AbiToken for String {
+    fn abi_type() -> String {
+        "string".to_owned()
+    }
+
+    fn into_token(self) -> Token {
+        Token::String(self)
+    }
+
+    fn from_token(token: Token) -> Result<String, AbiDecodeError> {
+        match token {
+            Token::String(value) => Ok(value),
+            _ => Err(AbiDecodeError::UnexpectedToken(String::abi_type())),
+        }
+    }
+}
+
+impl AbiToken for ByteBuf {
+    fn abi_type() -> String {
+        "bytes".to_owned()
+    }
+
+    fn into_token(self) -> Token {
+        Token::UnboundedBytes(self.into())
+    }
+
+    fn from_token(token: Token) -> Result<ByteBuf, AbiDecodeError> {
+        match token {
+            Token::UnboundedBytes(bytes) => Ok(ByteBuf::from(bytes)),
+            _ => Err(AbiDecodeError::UnexpectedToken(ByteBuf::abi_type())),
+        }
+    }
+}
+
+impl<const N: usize> AbiToken for [u8; N] {
+    fn abi_type() -> String {
+        format!("bytes{}", N)
+    }
+
+    fn into_token(self) -> Token {
+        Token::Bytes(self.to_vec())
+    }
+
+    fn from_token(token: Token) -> Result<[u8; N], AbiDecodeError> {
+        match token {
+            Token::Bytes(ref bytes) => <[u8; N]>::try_from(&bytes[..]).ok(),
+            _ => None,
+        }
+        .ok_or_else(|| AbiDecodeError::UnexpectedToken(<[u8; N]>::abi_type()))
+    }
+}
+
+impl<T: AbiToken> AbiToken for Vec<T> {
+    fn abi_type() -> String {
+        format!("{}[]", T::abi_type())
+    }
+
+    fn into_token(self) -> Token {
+        Token::Dynamic(self.into_iter().map(AbiToken::into_token).collect())
+    }
+
+    fn from_token(token: Token) -> Result<Vec<T>, AbiDecodeError> {
+        match token {
+            Token::Dynamic(items) => items.into_iter().map(T::from_token).collect(),
+            _ => Err(AbiDecodeError::UnexpectedToken(Self::abi_type())),
+        }
+    }
+}
+
 /// Raw derive for a Keccak256 digest from a string
 ///
 /// This function should be used when trying to filter out interesting
 /// events from a contract. This is different than contract function
 /// calls because it uses whole 32 bytes of the hash digest.
 pub fn derive_signature(data: &str) -> [u8; 32] {
-    let digest = Keccak256::digest(data.as_bytes());
-    let mut result: [u8; 32] = Default::default();
-    result.copy_from_slice(&digest);
-    result
+    keccak256(data.as_bytes())
 }
 
 /// Given a signature it derives a Method ID
@@ -276,35 +572,38 @@ fn derive_f() {
 }
 
 /// This one is a very simplified ABI encoder that takes a bunch of tokens,
-/// and serializes them.
+/// and serializes them as a tuple: static values, and offsets of dynamic
+/// values, in the head, followed by the dynamic values.
 ///
-/// This version is greatly simplified and doesn't support nested arrays etc.
-///
-/// Use with caution!
+/// Tuples and arrays can be nested in each other, as long as the items of an
+/// array are all of the same type.
 pub fn encode_tokens(tokens: &[Token]) -> Vec<u8> {
+    let span = Span::encode_tokens();
+    let result = encode_sequence(tokens);
+    span.finish(|| format!("{} tokens, {} bytes", tokens.len(), result.len()));
+    result
+}
+
+/// The encoding of `tokens` as a tuple, which is also that of the items of
+/// nested tuples and arrays.
+fn encode_sequence(tokens: &[Token]) -> Vec<u8> {
+    let serialized: Vec<SerializedToken> = tokens.iter().map(Token::serialize).collect();
+    // Offsets of dynamic data are relative to the start of the tuple, so
+    // the first one is the length of the head
+    let head_len: usize = serialized.iter().map(SerializedToken::head_len).sum();
+
     // This is the result data buffer
     let mut res = Vec::new();
 
     // A cache of dynamic data buffers that are stored here.
     let mut dynamic_data: Vec<Vec<u8>> = Vec::new();
+    let mut dynamic_offset = head_len as u64;
 
-    for token in tokens.iter() {
-        match token.serialize() {
+    for token in serialized {
+        match token {
             SerializedToken::Static(data) => res.extend(&data),
+            SerializedToken::StaticTuple(data) => res.extend(data),
             SerializedToken::Dynamic(data) => {
-                // This is the offset for dynamic data that is calculated
-                // based on the lengtho f all dynamic data buffers stored,
-                // and added to the "base" offset which is all tokens length.
-                // The base offset is assumed to be 32 * len(tokens) which is true
-                // since dynamic data is actually an static variable of size of
-                // 32 bytes.
-                let dynamic_offset = dynamic_data
-                    .iter()
-                    .map(|data| data.len() as u64)
-                    .fold(tokens.len() as u64 * 32, |r, v| r + v);
-
-                // Store next dynamic buffer *after* dynamic offset is calculated.
-                dynamic_data.push(data);
                 // Convert into token for easy serialization
                 let offset: Token = dynamic_offset.into();
                 // Write the offset of the dynamic data as a value of static size.
@@ -312,13 +611,14 @@ pub fn encode_tokens(tokens: &[Token]) -> Vec<u8> {
                     SerializedToken::Static(bytes) => res.extend(&bytes),
                     _ => panic!("Offset token is expected to be static"),
                 }
+                dynamic_offset += data.len() as u64;
+                dynamic_data.push(data);
             }
         }
     }
     // Concat all the dynamic data buffers at the end of the process
     // All the offsets are calculated while iterating and properly stored
     // in a single pass.
-    // let valuse = &dynamic_data.iter();
     for data in dynamic_data.iter() {
         res.extend(&data[..]);
     }
@@ -471,10 +771,1990 @@ fn encode_dynamic_only() {
     );
 }
 
+#[cfg(test)]
+fn words(data: &[u8]) -> Vec<String> {
+    use utils::bytes_to_hex_str;
+    data.chunks(32).map(bytes_to_hex_str).collect()
+}
+
+#[cfg(test)]
+fn word(value: u64) -> String {
+    format!("{:064x}", value)
+}
+
+#[test]
+fn encode_static_tuple() {
+    // f((uint256,bool),uint256) is encoded in place, without offsets
+    let tuple = Token::Tuple(vec![1u8.into(), true.into()]);
+    assert_eq!(
+        words(&encode_tokens(&[tuple, 2u8.into()])),
+        vec![word(1), word(1), word(2)]
+    );
+}
+
+#[test]
+fn encode_dynamic_tuple() {
+    // f((uint256,string),uint256) puts the tuple in the tail, with offsets
+    // in the tuple relative to its own start
+    let tuple = Token::Tuple(vec![1u8.into(), "foo".into()]);
+    assert_eq!(
+        words(&encode_tokens(&[tuple, 2u8.into()])),
+        vec![
+            word(0x40),
+            word(2),
+            word(1),
+            word(0x40),
+            word(3),
+            format!("{:0<64}", "666f6f"),
+        ]
+    );
+}
+
+#[test]
+fn encode_arrays_of_tuples() {
+    let a = Address::from([0x11u8; 20]);
+    let b = Address::from([0x22u8; 20]);
+    // f((uint256,address)[])
+    let call = encode_call(
+        "f((uint256,address)[])",
+        &[Token::Dynamic(vec![
+            Token::Tuple(vec![1u8.into(), a.into()]),
+            Token::Tuple(vec![2u8.into(), b.into()]),
+        ])],
+    );
+    assert_eq!(call[..4], derive_method_id("f((uint256,address)[])"));
+    assert_eq!(
+        words(&call[4..]),
+        vec![
+            word(0x20),
+            word(2),
+            word(1),
+            format!("{:0>64}", "11".repeat(20)),
+            word(2),
+            format!("{:0>64}", "22".repeat(20)),
+        ]
+    );
+    // Arrays of dynamic items have offsets relative to the first item
+    assert_eq!(
+        words(&encode_tokens(&[Token::Dynamic(vec![
+            "a".into(),
+            "b".into()
+        ])])),
+        vec![
+            word(0x20),
+            word(2),
+            word(0x40),
+            word(0x80),
+            word(1),
+            format!("{:0<64}", "61"),
+            word(1),
+            format!("{:0<64}", "62"),
+        ]
+    );
+}
+
+#[test]
+fn encode_nested_arrays() {
+    // uint256[][] with [[1, 2], [3]]: offsets of the inner arrays, each
+    // followed by its length and items
+    let nested = Token::Dynamic(vec![
+        Token::Dynamic(vec![1u8.into(), 2u8.into()]),
+        Token::Dynamic(vec![3u8.into()]),
+    ]);
+    assert_eq!(
+        words(&encode_tokens(&[nested])),
+        vec![
+            word(0x20),
+            word(2),
+            word(0x40),
+            word(0xa0),
+            word(2),
+            word(1),
+            word(2),
+            word(1),
+            word(3),
+        ]
+    );
+
+    // f(bytes[],uint256) with the array before a static value
+    let bytes = Token::Dynamic(vec![
+        Token::UnboundedBytes(vec![0xaa; 33]),
+        Token::UnboundedBytes(vec![0xbb]),
+    ]);
+    assert_eq!(
+        words(&encode_tokens(&[bytes, 7u8.into()])),
+        vec![
+            word(0x40),
+            word(7),
+            word(2),
+            word(0x40),
+            word(0xa0),
+            word(33),
+            "aa".repeat(32),
+            format!("{:0<64}", "aa"),
+            word(1),
+            format!("{:0<64}", "bb"),
+        ]
+    );
+}
+
+#[test]
+fn encode_fixed_arrays() {
+    // The example of the Solidity documentation, bar(bytes3[2])
+    let call = encode_call(
+        "bar(bytes3[2])",
+        &[Token::FixedArray(vec![
+            Token::Bytes(b"abc".to_vec()),
+            Token::Bytes(b"def".to_vec()),
+        ])],
+    );
+    assert_eq!(
+        words(&call[4..]),
+        vec![format!("{:0<64}", "616263"), format!("{:0<64}", "646566")]
+    );
+    // f(string[2],uint256): a dynamic array goes to the tail, with offsets
+    // relative to its start but no length
+    let strings = Token::FixedArray(vec!["a".into(), "b".into()]);
+    assert_eq!(
+        words(&encode_tokens(&[strings, 1u8.into()])),
+        vec![
+            word(0x40),
+            word(1),
+            word(0x40),
+            word(0x80),
+            word(1),
+            format!("{:0<64}", "61"),
+            word(1),
+            format!("{:0<64}", "62"),
+        ]
+    );
+}
+
+#[test]
+fn encode_signed_integers() {
+    assert_eq!(words(&encode_tokens(&[1i8.into()])), vec![word(1)]);
+    // A Uniswap v3 tick, and the minimum one
+    assert_eq!(
+        words(&encode_tokens(&[(-1i32).into(), (-887_272i32).into()])),
+        vec![format!("{:f>64}", ""), format!("{:f>64}", "fff27618"),]
+    );
+    assert_eq!(
+        words(&encode_tokens(&[
+            Int256::min_value().into(),
+            i128::MIN.into()
+        ])),
+        vec![
+            format!("{:0<64}", "8"),
+            // Sixteen bytes of sign extension, then 0x80 followed by zeros
+            format!("{:f<32}{:0<32}", "", "8"),
+        ]
+    );
+}
+
 /// A helper function that encodes both signature and a list of tokens.
 pub fn encode_call(sig: &str, tokens: &[Token]) -> Vec<u8> {
+    let span = Span::encode_call();
     let mut wtr = vec![];
     wtr.extend(&derive_method_id(sig));
-    wtr.extend(encode_tokens(tokens));
+    wtr.extend(encode_sequence(tokens));
+    span.finish(|| format!("{}, {} bytes", sig, wtr.len()));
+    wtr
+}
+
+/// Like [encode_call](fn.encode_call.html), but checks that the tokens are
+/// values of the parameter types of `sig` first, so that a `uint8` is not
+/// given a value the contract would truncate or reject.
+///
+/// `sig` may be a human-readable function, with parameter names; the
+/// method id is derived from its canonical signature.
+///
+/// ```rust
+/// use clarity::abi::{encode_call, encode_call_checked};
+/// use clarity::Address;
+///
+/// let sig = "function setFee(address pool, uint8 fee)";
+/// let pool = Address::from([0x11u8; 20]);
+/// assert_eq!(
+///     encode_call_checked(sig, &[pool.into(), 255u16.into()]).unwrap(),
+///     encode_call("setFee(address,uint8)", &[pool.into(), 255u16.into()])
+/// );
+/// assert!(encode_call_checked(sig, &[pool.into(), 256u16.into()]).is_err());
+/// ```
+pub fn encode_call_checked(sig: &str, tokens: &[Token]) -> Result<Vec<u8>, Error> {
+    sig.parse::<Function>()?.encode_input(tokens)
+}
+
+/// The non-standard packed encoding of Solidity's `abi.encodePacked`, as
+/// hashed for commitments and signature digests.
+///
+/// Values are concatenated without offsets or lengths: an address takes 20
+/// bytes, a bool one, fixed bytes their size, and strings and dynamic bytes
+/// their contents, without padding. Integers take 32 bytes, as a `uint256`
+/// or an `int256`. Narrower integers, such as a `uint16`, are given as
+/// [Token::Bytes](enum.Token.html#variant.Bytes) of their size.
+///
+/// The items of arrays are padded to 32 bytes each, and have to be static.
+/// Tuples cannot be packed.
+///
+/// # Panics
+///
+/// On tuples, arrays of dynamic items and arrays of tuples, which
+/// `abi.encodePacked` does not accept either.
+///
+/// ```rust
+/// use clarity::abi::{encode_packed, Token};
+/// use clarity::utils::bytes_to_hex_str;
+///
+/// // abi.encodePacked(int16(-1), bytes1(0x42), uint16(0x03), string("Hello, world!"))
+/// let packed = encode_packed(&[
+///     Token::Bytes(vec![0xff, 0xff]),
+///     Token::Bytes(vec![0x42]),
+///     Token::Bytes(vec![0x00, 0x03]),
+///     "Hello, world!".into(),
+/// ]);
+/// assert_eq!(
+///     bytes_to_hex_str(&packed),
+///     "ffff42000348656c6c6f2c20776f726c6421"
+/// );
+/// ```
+pub fn encode_packed(tokens: &[Token]) -> Vec<u8> {
+    let mut wtr = vec![];
+    for token in tokens {
+        match *token {
+            Token::Address(ref address) => wtr.extend(address.as_bytes()),
+            Token::Bool(value) => wtr.push(value as u8),
+            Token::Bytes(ref bytes) | Token::UnboundedBytes(ref bytes) => wtr.extend(bytes),
+            Token::String(ref s) => wtr.extend(s.as_bytes()),
+            Token::Uint(_) | Token::Int(_) => match token.serialize() {
+                SerializedToken::Static(word) => wtr.extend(&word),
+                _ => unreachable!(),
+            },
+            Token::Dynamic(ref items) | Token::FixedArray(ref items) => {
+                for item in items {
+                    match item.serialize() {
+                        SerializedToken::Static(word) => wtr.extend(&word),
+                        _ => panic!("Only arrays of static items can be packed"),
+                    }
+                }
+            }
+            Token::Tuple(_) => panic!("Tuples can not be packed"),
+        }
+    }
     wtr
 }
+
+#[test]
+fn abi_token_values() {
+    assert_eq!(Vec::<[u8; 32]>::abi_type(), "bytes32[]");
+    assert_eq!(u64::from_token(7u8.into()).unwrap(), 7);
+    assert_eq!(
+        u8::from_token(0x100u16.into()).unwrap_err(),
+        AbiDecodeError::UnexpectedToken("uint8".to_owned())
+    );
+    let bytes = ByteBuf::from(vec![1, 2, 3]);
+    assert_eq!(
+        bytes.clone().into_token(),
+        Token::UnboundedBytes(vec![1, 2, 3])
+    );
+    assert_eq!(
+        ByteBuf::from_token(bytes.clone().into_token()).unwrap(),
+        bytes
+    );
+    let strings = vec!["a".to_owned(), "b".to_owned()];
+    assert_eq!(
+        Vec::<String>::from_token(strings.clone().into_token()).unwrap(),
+        strings
+    );
+    assert!(tuple_items(Token::Bool(true), 1).is_err());
+}
+
+#[test]
+fn encode_packed_values() {
+    use utils::bytes_to_hex_str;
+    let address = Address::from([0x11u8; 20]);
+    // keccak256(abi.encodePacked(address, uint256)), as used for allowlists
+    assert_eq!(
+        bytes_to_hex_str(&encode_packed(&[address.into(), 5u8.into()])),
+        format!("{}{}", "11".repeat(20), word(5))
+    );
+    assert_eq!(
+        encode_packed(&[true.into(), Token::UnboundedBytes(Vec::new()), "".into()]),
+        vec![1]
+    );
+    // Array items are padded, including those of address[]
+    assert_eq!(
+        words(&encode_packed(&[
+            Token::Dynamic(vec![address.into()]),
+            Token::FixedArray(vec![(-1i8).into()]),
+        ])),
+        vec![format!("{:0>64}", "11".repeat(20)), "f".repeat(64)]
+    );
+}
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum AbiDecodeError {
+    #[fail(display = "Unsupported ABI type {:?}", _0)]
+    UnsupportedType(String),
+    #[fail(display = "Invalid function signature {:?}", _0)]
+    InvalidSignature(String),
+    #[fail(display = "Calldata does not start with the method id of the signature")]
+    MethodIdMismatch,
+    #[fail(display = "ABI data ends unexpectedly")]
+    UnexpectedEnd,
+    #[fail(display = "ABI data contains an offset out of bounds")]
+    InvalidOffset,
+    #[fail(display = "ABI data contains an invalid {}", _0)]
+    InvalidValue(ParamType),
+    #[fail(display = "Expected {} arguments but {} were given", expected, given)]
+    ArgumentCount { expected: usize, given: usize },
+    #[fail(display = "Argument {} is not a valid {}", _0, _1)]
+    ArgumentMismatch(usize, String),
+    #[fail(display = "Token is not a valid {}", _0)]
+    UnexpectedToken(String),
+    #[fail(display = "The {} calldata is not in the canonical encoding", _0)]
+    NonCanonical(&'static str),
+    #[fail(display = "ABI data decodes to more bytes than it holds")]
+    AliasedData,
+}
+
+/// Type of a parameter as it appears in a function signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParamType {
+    /// `uint<M>`, with the size in bits
+    Uint(usize),
+    /// `int<M>`, with the size in bits
+    Int(usize),
+    Address,
+    Bool,
+    String,
+    /// `bytes<M>`, with the size in bytes
+    FixedBytes(usize),
+    /// Dynamic `bytes`
+    Bytes,
+    /// Dynamic array (`T[]`)
+    Array(Box<ParamType>),
+    /// Fixed size array (`T[N]`)
+    FixedArray(Box<ParamType>, usize),
+    /// Tuple, for Solidity structs, written `(T1,T2)` in signatures
+    Tuple(Vec<ParamType>),
+}
+
+impl ParamType {
+    pub(crate) fn is_dynamic(&self) -> bool {
+        match *self {
+            ParamType::String | ParamType::Bytes | ParamType::Array(_) => true,
+            ParamType::FixedArray(ref inner, _) => inner.is_dynamic(),
+            ParamType::Tuple(ref members) => members.iter().any(ParamType::is_dynamic),
+            _ => false,
+        }
+    }
+
+    /// How many bytes a value of the type takes in the head of a tuple: a
+    /// word, or for static tuples and fixed arrays, the words of their
+    /// items.
+    pub(crate) fn head_len(&self) -> usize {
+        if self.is_dynamic() {
+            return 32;
+        }
+        match *self {
+            ParamType::FixedArray(ref inner, size) => inner.head_len() * size,
+            ParamType::Tuple(ref members) => members.iter().map(ParamType::head_len).sum(),
+            _ => 32,
+        }
+    }
+
+    /// Whether `token` is a value of the type.
+    pub fn accepts(&self, token: &Token) -> bool {
+        let all = |kind: &ParamType, items: &[Token]| items.iter().all(|item| kind.accepts(item));
+        match (self, token) {
+            (ParamType::Uint(bits), Token::Uint(value)) => value.bits() <= *bits,
+            (ParamType::Int(bits), Token::Int(value)) => {
+                // -2^(M-1) <= value < 2^(M-1)
+                let magnitude = if value.is_negative() {
+                    -(value.clone() + Int256::from(1))
+                } else {
+                    value.clone()
+                };
+                magnitude.bits() < *bits
+            }
+            (ParamType::Address, Token::Address(_))
+            | (ParamType::Bool, Token::Bool(_))
+            | (ParamType::String, Token::String(_))
+            | (ParamType::Bytes, Token::UnboundedBytes(_)) => true,
+            (ParamType::FixedBytes(size), Token::Bytes(bytes)) => bytes.len() == *size,
+            (ParamType::Array(kind), Token::Dynamic(items)) => all(kind, items),
+            (ParamType::FixedArray(kind, size), Token::FixedArray(items)) => {
+                items.len() == *size && all(kind, items)
+            }
+            (ParamType::Tuple(kinds), Token::Tuple(members)) => {
+                kinds.len() == members.len()
+                    && kinds
+                        .iter()
+                        .zip(members)
+                        .all(|(kind, member)| kind.accepts(member))
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ParamType {
+    /// The canonical type.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ParamType::Uint(bits) => write!(f, "uint{}", bits),
+            ParamType::Int(bits) => write!(f, "int{}", bits),
+            ParamType::Address => write!(f, "address"),
+            ParamType::Bool => write!(f, "bool"),
+            ParamType::String => write!(f, "string"),
+            ParamType::FixedBytes(size) => write!(f, "bytes{}", size),
+            ParamType::Bytes => write!(f, "bytes"),
+            ParamType::Array(ref inner) => write!(f, "{}[]", inner),
+            ParamType::FixedArray(ref inner, size) => write!(f, "{}[{}]", inner, size),
+            ParamType::Tuple(ref members) => {
+                let members: Vec<String> = members.iter().map(ParamType::to_string).collect();
+                write!(f, "({})", members.join(","))
+            }
+        }
+    }
+}
+
+/// Splits `s` at the commas that are not within parentheses, `None` if the
+/// parentheses do not match.
+fn split_top_level(s: &str) -> Option<Vec<&str>> {
+    if s.trim().is_empty() {
+        return Some(Vec::new());
+    }
+    let mut parts = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.checked_sub(1)?,
+            ',' if depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if depth != 0 {
+        return None;
+    }
+    parts.push(&s[start..]);
+    Some(parts)
+}
+
+/// The index of the parenthesis that closes the one at `open`.
+fn closing_paren(s: &str, open: usize) -> Option<usize> {
+    let mut depth = 0usize;
+    for (i, c) in s[open..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(open + i);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Size in bits of `int<M>` and `uint<M>`, `int` and `uint` being 256 bits.
+fn int_bits(bits: &str) -> Option<usize> {
+    if bits.is_empty() {
+        return Some(256);
+    }
+    match bits.parse::<usize>() {
+        Ok(n) if n > 0 && n <= 256 && n % 8 == 0 && !bits.starts_with('0') => Some(n),
+        _ => None,
+    }
+}
+
+impl FromStr for ParamType {
+    type Err = AbiDecodeError;
+
+    /// Parses a type as it appears in signatures, such as `uint256`,
+    /// `(address,bytes)[]` or `int24[3]`.
+    fn from_str(s: &str) -> Result<ParamType, AbiDecodeError> {
+        let unsupported = || AbiDecodeError::UnsupportedType(s.to_string());
+        if s.ends_with(']') {
+            let open = s.rfind('[').ok_or_else(unsupported)?;
+            let inner = Box::new(s[..open].parse()?);
+            let size = &s[open + 1..s.len() - 1];
+            if size.is_empty() {
+                return Ok(ParamType::Array(inner));
+            }
+            return match size.parse::<usize>() {
+                Ok(n) if n > 0 && !size.starts_with('0') => Ok(ParamType::FixedArray(inner, n)),
+                _ => Err(unsupported()),
+            };
+        }
+        if s.starts_with('(') && s.ends_with(')') {
+            return split_top_level(&s[1..s.len() - 1])
+                .ok_or_else(unsupported)?
+                .into_iter()
+                .map(str::parse)
+                .collect::<Result<_, _>>()
+                .map(ParamType::Tuple);
+        }
+        match s {
+            "address" => return Ok(ParamType::Address),
+            "bool" => return Ok(ParamType::Bool),
+            "string" => return Ok(ParamType::String),
+            "bytes" => return Ok(ParamType::Bytes),
+            _ => {}
+        }
+        if let Some(bits) = s.strip_prefix("uint") {
+            int_bits(bits).map(ParamType::Uint).ok_or_else(unsupported)
+        } else if let Some(bits) = s.strip_prefix("int") {
+            int_bits(bits).map(ParamType::Int).ok_or_else(unsupported)
+        } else if let Some(size) = s.strip_prefix("bytes") {
+            match size.parse::<usize>() {
+                Ok(n) if n > 0 && n <= 32 && !size.starts_with('0') => Ok(ParamType::FixedBytes(n)),
+                _ => Err(unsupported()),
+            }
+        } else {
+            Err(unsupported())
+        }
+    }
+}
+
+/// Parses the parameter types out of a function signature such as
+/// `"transfer(address,uint256)"`.
+pub fn parse_param_types(signature: &str) -> Result<Vec<ParamType>, Error> {
+    let invalid = || AbiDecodeError::InvalidSignature(signature.to_string());
+    let open = signature.find('(').ok_or_else(invalid)?;
+    if open == 0 || !signature.ends_with(')') {
+        return Err(invalid().into());
+    }
+    Ok(split_top_level(&signature[open + 1..signature.len() - 1])
+        .ok_or_else(invalid)?
+        .into_iter()
+        .map(str::parse)
+        .collect::<Result<_, _>>()?)
+}
+
+/// Reads the 32 byte word at `offset`.
+fn read_word(data: &[u8], offset: usize) -> Result<&[u8], AbiDecodeError> {
+    offset
+        .checked_add(32)
+        .and_then(|end| data.get(offset..end))
+        .ok_or(AbiDecodeError::UnexpectedEnd)
+}
+
+/// Reads a word that is used as an offset or a length.
+fn read_usize(data: &[u8], offset: usize) -> Result<usize, AbiDecodeError> {
+    let word = read_word(data, offset)?;
+    if word[..24].iter().any(|b| *b != 0) {
+        return Err(AbiDecodeError::InvalidOffset);
+    }
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&word[24..]);
+    let value = u64::from_be_bytes(value);
+    if value > data.len() as u64 {
+        return Err(AbiDecodeError::InvalidOffset);
+    }
+    Ok(value as usize)
+}
+
+/// Decodes a value of an elementary static type from a single word.
+fn decode_word(kind: &ParamType, word: &[u8]) -> Result<Token, AbiDecodeError> {
+    let invalid = || AbiDecodeError::InvalidValue(kind.clone());
+    match *kind {
+        ParamType::Uint(bits) => {
+            if word[..32 - bits / 8].iter().any(|b| *b != 0) {
+                return Err(invalid());
+            }
+            Ok(Token::Uint(Uint256::from_bytes_be(word)))
+        }
+        ParamType::Int(bits) => {
+            // Sign extended from the highest bit of the type
+            let start = 32 - bits / 8;
+            let fill = if word[start] & 0x80 != 0 { 0xff } else { 0x00 };
+            if word[..start].iter().any(|b| *b != fill) {
+                return Err(invalid());
+            }
+            Ok(Token::Int(Int256(BigInt::from_signed_bytes_be(word))))
+        }
+        ParamType::Address => {
+            if word[..12].iter().any(|b| *b != 0) {
+                return Err(invalid());
+            }
+            let mut address = [0u8; 20];
+            address.copy_from_slice(&word[12..]);
+            Ok(Token::Address(address.into()))
+        }
+        ParamType::Bool => match (word[..31].iter().all(|b| *b == 0), word[31]) {
+            (true, 0) => Ok(Token::Bool(false)),
+            (true, 1) => Ok(Token::Bool(true)),
+            _ => Err(invalid()),
+        },
+        ParamType::FixedBytes(size) => {
+            if word[size..].iter().any(|b| *b != 0) {
+                return Err(invalid());
+            }
+            Ok(Token::Bytes(word[..size].to_vec()))
+        }
+        _ => unreachable!("Only elementary types are decoded from a single word"),
+    }
+}
+
+/// Takes `bytes` off what is left to decode. Every byte of a canonical
+/// encoding is read once, so decoding more bytes than the data holds
+/// means that offsets point at the same values over and over, which would
+/// let a small input decode to a huge one.
+fn consume(budget: &mut usize, bytes: usize) -> Result<(), AbiDecodeError> {
+    *budget = budget
+        .checked_sub(bytes)
+        .ok_or(AbiDecodeError::AliasedData)?;
+    Ok(())
+}
+
+/// Decodes values of `types` whose heads follow each other from the start
+/// of `data`, which is where the enclosing tuple or array starts.
+fn decode_sequence<'a, I>(
+    types: I,
+    data: &[u8],
+    budget: &mut usize,
+) -> Result<Vec<Token>, AbiDecodeError>
+where
+    I: IntoIterator<Item = &'a ParamType>,
+{
+    let mut head = 0;
+    let mut tokens = Vec::new();
+    for kind in types {
+        tokens.push(decode_param(kind, data, head, budget)?);
+        head += kind.head_len();
+    }
+    Ok(tokens)
+}
+
+/// Decodes a value whose head is at `offset` of `data`, where `data` starts
+/// at the beginning of the enclosing tuple.
+fn decode_param(
+    kind: &ParamType,
+    data: &[u8],
+    offset: usize,
+    budget: &mut usize,
+) -> Result<Token, AbiDecodeError> {
+    if !kind.is_dynamic() {
+        // Static tuples and arrays are encoded in place
+        let items = data.get(offset..).ok_or(AbiDecodeError::UnexpectedEnd);
+        return match *kind {
+            ParamType::FixedArray(ref inner, size) => {
+                decode_sequence(iter::repeat_n(&**inner, size), items?, budget)
+                    .map(Token::FixedArray)
+            }
+            ParamType::Tuple(ref members) => {
+                decode_sequence(members, items?, budget).map(Token::Tuple)
+            }
+            _ => {
+                let word = read_word(data, offset)?;
+                consume(budget, 32)?;
+                decode_word(kind, word)
+            }
+        };
+    }
+    // Offsets within the tail are relative to its start
+    let tail = &data[read_usize(data, offset)?..];
+    consume(budget, 32)?;
+    match *kind {
+        ParamType::Bytes | ParamType::String => {
+            let length = read_usize(tail, 0)?;
+            // Padded with zeros to a whole number of words
+            let padded = length.div_ceil(32) * 32;
+            let word = tail
+                .get(32..32 + padded)
+                .ok_or(AbiDecodeError::UnexpectedEnd)?;
+            consume(budget, 32 + padded)?;
+            if word[length..].iter().any(|b| *b != 0) {
+                return Err(AbiDecodeError::InvalidValue(kind.clone()));
+            }
+            let bytes = word[..length].to_vec();
+            if *kind == ParamType::Bytes {
+                Ok(Token::UnboundedBytes(bytes))
+            } else {
+                String::from_utf8(bytes)
+                    .map(Token::String)
+                    .map_err(|_| AbiDecodeError::InvalidValue(kind.clone()))
+            }
+        }
+        ParamType::Array(ref inner) => {
+            let length = read_usize(tail, 0)?;
+            consume(budget, 32)?;
+            let items = &tail[32..];
+            // The heads of the items have to be within the data, so that a
+            // length is refused before anything is decoded
+            if length.saturating_mul(inner.head_len()) > items.len() {
+                return Err(AbiDecodeError::UnexpectedEnd);
+            }
+            decode_sequence(iter::repeat_n(&**inner, length), items, budget).map(Token::Dynamic)
+        }
+        ParamType::FixedArray(ref inner, size) => {
+            decode_sequence(iter::repeat_n(&**inner, size), tail, budget).map(Token::FixedArray)
+        }
+        ParamType::Tuple(ref members) => decode_sequence(members, tail, budget).map(Token::Tuple),
+        _ => unreachable!("Elementary types are static"),
+    }
+}
+
+/// Decodes ABI encoded parameters (without a method id), such as the return
+/// data of an `eth_call`.
+///
+/// The heads of `types` are read in order from the start of `data`, and
+/// dynamic values are read from the offsets in their heads. Padding has to
+/// be zero, and values have to fit their types, so that `data` is decoded
+/// the way the contract meant it. Offsets may not make the values add up
+/// to more bytes than `data` holds, which bounds the work and memory of
+/// decoding by its length. Bytes after the values are ignored.
+///
+/// ```rust
+/// use clarity::abi::{decode_tokens, encode_tokens, parse_param_types, Token};
+///
+/// // The return data of `function name() returns (string)`
+/// let data = encode_tokens(&["Wrapped Ether".into()]);
+/// let types = parse_param_types("name(string)").unwrap();
+/// assert_eq!(
+///     decode_tokens(&types, &data).unwrap(),
+///     vec![Token::String("Wrapped Ether".to_owned())]
+/// );
+/// ```
+pub fn decode_tokens(types: &[ParamType], data: &[u8]) -> Result<Vec<Token>, AbiDecodeError> {
+    let span = Span::decode_tokens();
+    let result = decode_sequence(types, data, &mut data.len());
+    span.finish(|| match result {
+        Ok(ref tokens) => format!("{} tokens from {} bytes", tokens.len(), data.len()),
+        Err(ref e) => format!("failed: {}", e),
+    });
+    result
+}
+
+/// A parameter that differs between two calls of the same function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldDiff {
+    /// Position of the parameter in the signature
+    pub index: usize,
+    pub kind: ParamType,
+    pub left: Token,
+    pub right: Token,
+}
+
+/// Decodes two calldata blobs against `signature` and reports the
+/// parameters that differ.
+///
+/// This is meant for policy checks, such as comparing a proposed
+/// transaction against an approved template. Both blobs have to be valid
+/// calls of `signature`, in the encoding [encode_call](fn.encode_call.html)
+/// gives them, otherwise an error is returned. Trailing bytes and offsets
+/// other than the canonical ones are refused with
+/// `AbiDecodeError::NonCanonical` naming the side, since they would be
+/// passed to the contract without showing up in the diff. An empty result
+/// means the calls are equivalent.
+pub fn diff_calldata(signature: &str, left: &[u8], right: &[u8]) -> Result<Vec<FieldDiff>, Error> {
+    let types = parse_param_types(signature)?;
+    let method_id = derive_method_id(signature);
+    let decode = |side: &'static str, calldata: &[u8]| -> Result<Vec<Token>, Error> {
+        if calldata.len() < 4 || calldata[..4] != method_id {
+            return Err(AbiDecodeError::MethodIdMismatch.into());
+        }
+        let tokens = decode_tokens(&types, &calldata[4..])?;
+        if encode_call(signature, &tokens) != calldata {
+            return Err(AbiDecodeError::NonCanonical(side).into());
+        }
+        Ok(tokens)
+    };
+    let left = decode("left", left)?;
+    let right = decode("right", right)?;
+    Ok(types
+        .into_iter()
+        .zip(left.into_iter().zip(right))
+        .enumerate()
+        .filter(|&(_, (_, (ref left, ref right)))| left != right)
+        .map(|(index, (kind, (left, right)))| FieldDiff {
+            index,
+            kind,
+            left,
+            right,
+        })
+        .collect())
+}
+
+#[test]
+fn parse_types() {
+    assert_eq!(
+        parse_param_types("f(uint256,uint32[],bytes10,bytes)").unwrap(),
+        vec![
+            ParamType::Uint(256),
+            ParamType::Array(Box::new(ParamType::Uint(32))),
+            ParamType::FixedBytes(10),
+            ParamType::Bytes,
+        ]
+    );
+    assert_eq!(parse_param_types("foo()").unwrap(), vec![]);
+    assert_eq!(
+        parse_param_types("foo(uint,address,bool,string)").unwrap(),
+        vec![
+            ParamType::Uint(256),
+            ParamType::Address,
+            ParamType::Bool,
+            ParamType::String,
+        ]
+    );
+    assert!(parse_param_types("foo").is_err());
+    assert!(parse_param_types("(uint256)").is_err());
+    assert!(parse_param_types("foo(uint7)").is_err());
+    assert!(parse_param_types("foo(uint264)").is_err());
+    assert!(parse_param_types("foo(bytes33)").is_err());
+    assert!(parse_param_types("foo(bytes0)").is_err());
+    assert!(parse_param_types("foo(uint256,)").is_err());
+    assert!(parse_param_types("foo(int7)").is_err());
+    assert!(parse_param_types("foo(uint256[0])").is_err());
+    assert!(parse_param_types("foo((uint256,bool)").is_err());
+
+    let types = parse_param_types("foo(int,string[],(address,(bytes,int24[3]))[],())").unwrap();
+    assert_eq!(
+        types,
+        vec![
+            ParamType::Int(256),
+            ParamType::Array(Box::new(ParamType::String)),
+            ParamType::Array(Box::new(ParamType::Tuple(vec![
+                ParamType::Address,
+                ParamType::Tuple(vec![
+                    ParamType::Bytes,
+                    ParamType::FixedArray(Box::new(ParamType::Int(24)), 3),
+                ]),
+            ]))),
+            ParamType::Tuple(vec![]),
+        ]
+    );
+    let canonical: Vec<String> = types.iter().map(ParamType::to_string).collect();
+    assert_eq!(
+        canonical.join(","),
+        "int256,string[],(address,(bytes,int24[3]))[],()"
+    );
+    // Static tuples and fixed arrays take the words of their items
+    let point: ParamType = "(uint256,int8[2])[3]".parse().unwrap();
+    assert!(!point.is_dynamic());
+    assert_eq!(point.head_len(), 9 * 32);
+    assert!("(uint256,bytes)[3]"
+        .parse::<ParamType>()
+        .unwrap()
+        .is_dynamic());
+}
+
+#[test]
+fn decode_roundtrip() {
+    let types = parse_param_types("f(uint256,uint32[],bytes10,bytes,string,address,bool)").unwrap();
+    let tokens = vec![
+        Token::Uint(0x123u32.into()),
+        vec![0x456u32, 0x789].into(),
+        Token::Bytes(b"1234567890".to_vec()),
+        Token::UnboundedBytes(b"Hello, world!".to_vec()),
+        "Hello, world!".into(),
+        Token::Address([0x11u8; 20].into()),
+        true.into(),
+    ];
+    assert_eq!(
+        decode_tokens(&types, &encode_tokens(&tokens)).unwrap(),
+        tokens
+    );
+}
+
+#[test]
+fn decode_nested_types() {
+    let types = parse_param_types(
+        "f(int8,(address,bytes)[],uint16[2][],string[],(uint256,int24[2]),(bool,string)[2])",
+    )
+    .unwrap();
+    let tokens = vec![
+        (-5i8).into(),
+        Token::Dynamic(vec![
+            Token::Tuple(vec![
+                Address::from([0x11u8; 20]).into(),
+                vec![1u8, 2].into(),
+            ]),
+            Token::Tuple(vec![
+                Address::from([0x22u8; 20]).into(),
+                Token::UnboundedBytes(Vec::new()),
+            ]),
+        ]),
+        Token::Dynamic(vec![Token::FixedArray(vec![1u16.into(), 2u16.into()])]),
+        Token::Dynamic(vec!["a".into(), "".into()]),
+        Token::Tuple(vec![
+            7u8.into(),
+            Token::FixedArray(vec![(-1i32).into(), 8_388_607i32.into()]),
+        ]),
+        Token::FixedArray(vec![
+            Token::Tuple(vec![true.into(), "x".into()]),
+            Token::Tuple(vec![false.into(), "y".into()]),
+        ]),
+    ];
+    assert!(types
+        .iter()
+        .zip(&tokens)
+        .all(|(kind, token)| kind.accepts(token)));
+    assert_eq!(
+        decode_tokens(&types, &encode_tokens(&tokens)).unwrap(),
+        tokens
+    );
+
+    // Negative numbers are sign extended from the highest bit of the type
+    let minus_one = encode_tokens(&[(-1i8).into()]);
+    assert_eq!(
+        decode_tokens(&[ParamType::Int(8)], &minus_one).unwrap(),
+        vec![(-1i8).into()]
+    );
+    assert_eq!(
+        decode_tokens(&[ParamType::Int(256)], &minus_one).unwrap(),
+        vec![(-1i8).into()]
+    );
+    assert!(decode_tokens(&[ParamType::Int(8)], &encode_tokens(&[255u8.into()])).is_err());
+    assert!(decode_tokens(&[ParamType::Int(16)], &encode_tokens(&[(-129i16).into()])).is_ok());
+    assert!(decode_tokens(&[ParamType::Int(8)], &encode_tokens(&[(-129i16).into()])).is_err());
+
+    // A length of more items than the data has heads for
+    let mut long = encode_tokens(&[Token::Dynamic(vec![Token::Dynamic(Vec::new())])]);
+    long[63] = 3;
+    assert_eq!(
+        decode_tokens(&parse_param_types("f(uint8[][])").unwrap(), &long),
+        Err(AbiDecodeError::UnexpectedEnd)
+    );
+}
+
+#[test]
+fn encode_empty_bytes() {
+    use utils::bytes_to_hex_str;
+    let expected = concat!(
+        "0000000000000000000000000000000000000000000000000000000000000020",
+        "0000000000000000000000000000000000000000000000000000000000000000"
+    );
+    let bytes = encode_tokens(&[Token::UnboundedBytes(Vec::new())]);
+    assert_eq!(bytes_to_hex_str(&bytes), expected);
+    assert_eq!(bytes_to_hex_str(&encode_tokens(&["".into()])), expected);
+    assert_eq!(
+        decode_tokens(&[ParamType::Bytes], &bytes).unwrap(),
+        vec![Token::UnboundedBytes(Vec::new())]
+    );
+}
+
+#[test]
+fn encode_empty_dynamic_values() {
+    use utils::bytes_to_hex_str;
+    // As solc encodes `safeTransferFrom(from, to, 1, "")` of ERC-721
+    let call = encode_call(
+        "safeTransferFrom(address,address,uint256,bytes)",
+        &[
+            Token::Address([0x11u8; 20].into()),
+            Token::Address([0x22u8; 20].into()),
+            1u8.into(),
+            Token::UnboundedBytes(Vec::new()),
+        ],
+    );
+    assert_eq!(
+        bytes_to_hex_str(&call),
+        concat!(
+            "b88d4fde",
+            "0000000000000000000000001111111111111111111111111111111111111111",
+            "0000000000000000000000002222222222222222222222222222222222222222",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000080",
+            "0000000000000000000000000000000000000000000000000000000000000000"
+        )
+    );
+
+    // As solc encodes `abi.encode("", new uint256[](0), "")`
+    let tokens = vec![
+        "".into(),
+        Token::Dynamic(Vec::new()),
+        Token::UnboundedBytes(Vec::new()),
+    ];
+    let expected = concat!(
+        "0000000000000000000000000000000000000000000000000000000000000060",
+        "0000000000000000000000000000000000000000000000000000000000000080",
+        "00000000000000000000000000000000000000000000000000000000000000a0",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "0000000000000000000000000000000000000000000000000000000000000000",
+        "0000000000000000000000000000000000000000000000000000000000000000"
+    );
+    let data = encode_tokens(&tokens);
+    assert_eq!(bytes_to_hex_str(&data), expected);
+    let types = parse_param_types("f(string,uint256[],bytes)").unwrap();
+    assert_eq!(decode_tokens(&types, &data).unwrap(), tokens);
+
+    // An empty `string[]`, and an array of empty arrays
+    assert_eq!(
+        bytes_to_hex_str(&encode_tokens(&[Token::Dynamic(Vec::new())])),
+        concat!(
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000000"
+        )
+    );
+    let nested = Token::Dynamic(vec![Token::Dynamic(Vec::new()), Token::Dynamic(Vec::new())]);
+    assert_eq!(
+        bytes_to_hex_str(&encode_tokens(&[nested])),
+        concat!(
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000000"
+        )
+    );
+}
+
+#[test]
+fn decode_rejects_invalid_data() {
+    let encoded = encode_tokens(&[Token::UnboundedBytes(vec![0x01u8; 40])]);
+    let types = [ParamType::Bytes];
+    assert!(decode_tokens(&types, &encoded[..encoded.len() - 32]).is_err());
+    let mut bad_offset = encoded.clone();
+    bad_offset[31] = 0xff;
+    assert!(decode_tokens(&types, &bad_offset).is_err());
+    let mut huge_offset = encoded.clone();
+    huge_offset[0] = 0x01;
+    assert!(decode_tokens(&types, &huge_offset).is_err());
+
+    let dirty_address = [0xffu8; 32];
+    assert!(decode_tokens(&[ParamType::Address], &dirty_address).is_err());
+    assert!(decode_tokens(&[ParamType::Uint(8)], &dirty_address).is_err());
+    assert!(decode_tokens(&[ParamType::Bool], &encode_tokens(&[2u8.into()])).is_err());
+    assert!(decode_tokens(&[ParamType::FixedBytes(1)], &dirty_address).is_err());
+    assert!(decode_tokens(
+        &[ParamType::String],
+        &encode_tokens(&[Token::UnboundedBytes(vec![0xff])])
+    )
+    .is_err());
+}
+
+#[test]
+fn decode_requires_zero_padding() {
+    let types = [ParamType::Bytes];
+    let encoded = encode_tokens(&[Token::UnboundedBytes(vec![0x01u8; 40])]);
+    assert_eq!(encoded.len(), 4 * 32);
+    decode_tokens(&types, &encoded).unwrap();
+    let mut dirty = encoded.clone();
+    dirty[64 + 40] = 1;
+    assert_eq!(
+        decode_tokens(&types, &dirty),
+        Err(AbiDecodeError::InvalidValue(ParamType::Bytes))
+    );
+    let mut dirty = encoded.clone();
+    dirty[127] = 1;
+    assert_eq!(
+        decode_tokens(&types, &dirty),
+        Err(AbiDecodeError::InvalidValue(ParamType::Bytes))
+    );
+    // The padding has to be there, even at the end of the data
+    assert_eq!(
+        decode_tokens(&types, &encoded[..64 + 40]),
+        Err(AbiDecodeError::UnexpectedEnd)
+    );
+}
+
+#[test]
+fn decode_rejects_aliased_offsets() {
+    // A `bytes[]` whose items all point at the same 32 byte value
+    let aliased = |items: u32| {
+        let mut data = encode_tokens(&[32u8.into(), items.into()]);
+        for _ in 0..items {
+            data.extend_from_slice(&encode_tokens(&[(items * 32).into()]));
+        }
+        data.extend_from_slice(&encode_tokens(&[32u8.into()]));
+        data.extend_from_slice(&[0xaa; 32]);
+        data
+    };
+    let types = [ParamType::Array(Box::new(ParamType::Bytes))];
+    assert_eq!(
+        decode_tokens(&types, &aliased(1)).unwrap(),
+        vec![Token::Dynamic(vec![Token::UnboundedBytes(vec![0xaa; 32])])]
+    );
+    // Each item decodes to more bytes than its head, so a thousand of them
+    // would decode to three times the data
+    let data = aliased(1000);
+    assert_eq!(
+        decode_tokens(&types, &data),
+        Err(AbiDecodeError::AliasedData)
+    );
+}
+
+#[test]
+fn decode_return_data() {
+    use utils::hex_str_to_bytes;
+    // `balanceOf(address)` returning one ether
+    let data =
+        hex_str_to_bytes("0x0000000000000000000000000000000000000000000000000de0b6b3a7640000")
+            .unwrap();
+    assert_eq!(
+        decode_tokens(&[ParamType::Uint(256)], &data).unwrap(),
+        vec![Token::Uint(1_000_000_000_000_000_000u64.into())]
+    );
+    assert_eq!(
+        decode_tokens(&[ParamType::Uint(256), ParamType::Bool], &data),
+        Err(AbiDecodeError::UnexpectedEnd)
+    );
+    // `(string, uint8)` with the string after the static head
+    let data = hex_str_to_bytes(concat!(
+        "0000000000000000000000000000000000000000000000000000000000000040",
+        "0000000000000000000000000000000000000000000000000000000000000012",
+        "0000000000000000000000000000000000000000000000000000000000000004",
+        "5745544800000000000000000000000000000000000000000000000000000000",
+    ))
+    .unwrap();
+    assert_eq!(
+        decode_tokens(&[ParamType::String, ParamType::Uint(8)], &data).unwrap(),
+        vec!["WETH".into(), 18u8.into()]
+    );
+}
+
+#[test]
+fn diff_transfer_calls() {
+    let signature = "transfer(address,uint256)";
+    let approved = encode_call(
+        signature,
+        &[Token::Address([0x11u8; 20].into()), 100u32.into()],
+    );
+    let proposed = encode_call(
+        signature,
+        &[Token::Address([0x11u8; 20].into()), 1000u32.into()],
+    );
+    assert_eq!(
+        diff_calldata(signature, &approved, &approved).unwrap(),
+        vec![]
+    );
+    assert_eq!(
+        diff_calldata(signature, &approved, &proposed).unwrap(),
+        vec![FieldDiff {
+            index: 1,
+            kind: ParamType::Uint(256),
+            left: 100u32.into(),
+            right: 1000u32.into(),
+        }]
+    );
+}
+
+#[test]
+fn diff_dynamic_fields() {
+    let signature = "multicall(bytes,address[],string)";
+    let left = encode_call(
+        signature,
+        &[
+            Token::UnboundedBytes(vec![1, 2, 3]),
+            Token::Dynamic(vec![Token::Address([0x11u8; 20].into())]),
+            "same".into(),
+        ],
+    );
+    let right = encode_call(
+        signature,
+        &[
+            Token::UnboundedBytes(vec![1, 2, 3]),
+            Token::Dynamic(vec![
+                Token::Address([0x11u8; 20].into()),
+                Token::Address([0x22u8; 20].into()),
+            ]),
+            "same".into(),
+        ],
+    );
+    let diff = diff_calldata(signature, &left, &right).unwrap();
+    assert_eq!(diff.len(), 1);
+    assert_eq!(diff[0].index, 1);
+    assert_eq!(diff[0].kind.to_string(), "address[]");
+}
+
+#[test]
+fn diff_requires_matching_method() {
+    let call = encode_call(
+        "transfer(address,uint256)",
+        &[Token::Address(Address::default()), 1u8.into()],
+    );
+    let other = encode_call(
+        "approve(address,uint256)",
+        &[Token::Address(Address::default()), 1u8.into()],
+    );
+    assert!(diff_calldata("transfer(address,uint256)", &call, &other).is_err());
+    assert!(diff_calldata("transfer(address,uint256)", &call, &call[..4]).is_err());
+    assert!(diff_calldata("transfer(address,uint256)", &[], &call).is_err());
+}
+
+#[test]
+fn diff_requires_canonical_calldata() {
+    let signature = "setName(string)";
+    let call = encode_call(signature, &["vitalik".into()]);
+    let error = |left: &[u8], right: &[u8]| {
+        diff_calldata(signature, left, right)
+            .unwrap_err()
+            .downcast::<AbiDecodeError>()
+            .unwrap()
+    };
+    // Bytes after the arguments, which the contract may well read
+    let mut trailing = call.clone();
+    trailing.extend_from_slice(&[0u8; 32]);
+    assert_eq!(
+        error(&call, &trailing),
+        AbiDecodeError::NonCanonical("right")
+    );
+    assert_eq!(
+        error(&trailing, &call),
+        AbiDecodeError::NonCanonical("left")
+    );
+    // The string moved one word further, behind a word of garbage
+    let mut moved = call[..4].to_vec();
+    moved.extend_from_slice(&encode_tokens(&[Token::Uint(0x40u8.into())]));
+    moved.extend_from_slice(&[0xffu8; 32]);
+    moved.extend_from_slice(&call[4 + 32..]);
+    assert_eq!(
+        decode_tokens(&[ParamType::String], &moved[4..]).unwrap(),
+        vec!["vitalik".into()]
+    );
+    assert_eq!(error(&moved, &call), AbiDecodeError::NonCanonical("left"));
+    assert_eq!(diff_calldata(signature, &call, &call).unwrap(), vec![]);
+}
+
+/// Whether a function reads or changes state, and accepts ether.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StateMutability {
+    Pure,
+    View,
+    NonPayable,
+    Payable,
+}
+
+/// A parameter of a function, event, error or constructor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Param {
+    pub name: String,
+    /// The type as it appears in signatures, with the members of tuples
+    /// spelled out, as in `(address,uint256)[]`
+    pub canonical_type: String,
+    /// Whether the parameter of an event is indexed
+    pub indexed: bool,
+}
+
+impl Param {
+    /// The type, if it is a valid ABI type.
+    pub fn param_type(&self) -> Result<ParamType, AbiDecodeError> {
+        self.canonical_type.parse()
+    }
+}
+
+/// The signature of `name` with parameters `params`.
+fn signature_of(name: &str, params: &[Param]) -> String {
+    let types: Vec<&str> = params
+        .iter()
+        .map(|param| param.canonical_type.as_str())
+        .collect();
+    format!("{}({})", name, types.join(","))
+}
+
+/// The types of `params`, if all of them are valid ABI types.
+fn param_types(params: &[Param]) -> Result<Vec<ParamType>, AbiDecodeError> {
+    params.iter().map(Param::param_type).collect()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub inputs: Vec<Param>,
+    pub outputs: Vec<Param>,
+    pub state_mutability: StateMutability,
+}
+
+impl Function {
+    /// The signature, such as `transfer(address,uint256)`.
+    pub fn signature(&self) -> String {
+        signature_of(&self.name, &self.inputs)
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        derive_method_id(&self.signature())
+    }
+
+    /// Checks that `tokens` are as many as the inputs, and of their types.
+    pub fn check_input(&self, tokens: &[Token]) -> Result<(), AbiDecodeError> {
+        check_args(&self.inputs, tokens)
+    }
+
+    /// Encodes a call with `tokens` as the arguments, after checking them
+    /// with [check_input](#method.check_input).
+    pub fn encode_input(&self, tokens: &[Token]) -> Result<Vec<u8>, Error> {
+        self.check_input(tokens)?;
+        Ok(encode_call(&self.signature(), tokens))
+    }
+
+    /// Decodes the arguments of a call of the function.
+    pub fn decode_input(&self, calldata: &[u8]) -> Result<Vec<Token>, AbiDecodeError> {
+        if calldata.len() < 4 || calldata[..4] != self.selector() {
+            return Err(AbiDecodeError::MethodIdMismatch);
+        }
+        decode_tokens(&param_types(&self.inputs)?, &calldata[4..])
+    }
+
+    /// Decodes the return data of a call of the function.
+    pub fn decode_output(&self, data: &[u8]) -> Result<Vec<Token>, AbiDecodeError> {
+        decode_tokens(&param_types(&self.outputs)?, data)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event {
+    pub name: String,
+    pub inputs: Vec<Param>,
+    /// Anonymous events are logged without their signature as the first
+    /// topic
+    pub anonymous: bool,
+}
+
+impl Event {
+    pub fn signature(&self) -> String {
+        signature_of(&self.name, &self.inputs)
+    }
+
+    /// The first topic of the logs of the event, unless it is anonymous.
+    pub fn topic(&self) -> [u8; 32] {
+        derive_signature(&self.signature())
+    }
+}
+
+/// A custom error, raised with `revert`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractError {
+    pub name: String,
+    pub inputs: Vec<Param>,
+}
+
+impl ContractError {
+    pub fn signature(&self) -> String {
+        signature_of(&self.name, &self.inputs)
+    }
+
+    pub fn selector(&self) -> [u8; 4] {
+        derive_method_id(&self.signature())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Constructor {
+    pub inputs: Vec<Param>,
+    pub state_mutability: StateMutability,
+}
+
+impl Constructor {
+    /// The arguments appended to the init code of a deployment, which are
+    /// checked like those of a [Function](struct.Function.html#method.check_input).
+    pub fn encode_input(&self, tokens: &[Token]) -> Result<Vec<u8>, Error> {
+        check_args(&self.inputs, tokens)?;
+        Ok(encode_tokens(tokens))
+    }
+}
+
+/// Checks that `tokens` are values of `params`. Parameters of types that
+/// are not understood, such as `function`, accept any token.
+fn check_args(params: &[Param], tokens: &[Token]) -> Result<(), AbiDecodeError> {
+    if params.len() != tokens.len() {
+        return Err(AbiDecodeError::ArgumentCount {
+            expected: params.len(),
+            given: tokens.len(),
+        });
+    }
+    for (i, (param, token)) in params.iter().zip(tokens).enumerate() {
+        if let Ok(kind) = param.canonical_type.parse::<ParamType>() {
+            if !kind.accepts(token) {
+                return Err(AbiDecodeError::ArgumentMismatch(
+                    i,
+                    param.canonical_type.clone(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '$' => {}
+        _ => return false,
+    }
+    chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+}
+
+/// Parses a parameter of a human-readable signature: its type, then
+/// optionally `indexed`, a data location and a name.
+fn parse_human_param(s: &str) -> Option<(ParamType, Param)> {
+    let s = s.trim();
+    let (mut kind, mut rest) = if s.starts_with("tuple(") || s.starts_with('(') {
+        let open = s.find('(')?;
+        let close = closing_paren(s, open)?;
+        let members = parse_human_params(&s[open + 1..close])?
+            .into_iter()
+            .map(|(kind, _)| kind)
+            .collect();
+        (ParamType::Tuple(members), &s[close + 1..])
+    } else {
+        let end = s
+            .find(|c: char| c.is_whitespace() || c == '[')
+            .unwrap_or(s.len());
+        (s[..end].parse().ok()?, &s[end..])
+    };
+    while rest.starts_with('[') {
+        let close = rest.find(']')?;
+        let size = &rest[1..close];
+        kind = if size.is_empty() {
+            ParamType::Array(Box::new(kind))
+        } else {
+            match size.parse::<usize>() {
+                Ok(n) if n > 0 => ParamType::FixedArray(Box::new(kind), n),
+                _ => return None,
+            }
+        };
+        rest = &rest[close + 1..];
+    }
+    if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+        return None;
+    }
+
+    let mut words = rest.split_whitespace().peekable();
+    let indexed = words.peek() == Some(&"indexed");
+    if indexed {
+        words.next();
+    }
+    if let Some(&"memory") | Some(&"calldata") | Some(&"storage") = words.peek() {
+        words.next();
+    }
+    let name = words.next().unwrap_or("");
+    if words.next().is_some() || !(name.is_empty() || is_identifier(name)) {
+        return None;
+    }
+    let param = Param {
+        name: name.to_owned(),
+        canonical_type: kind.to_string(),
+        indexed,
+    };
+    Some((kind, param))
+}
+
+fn parse_human_params(s: &str) -> Option<Vec<(ParamType, Param)>> {
+    split_top_level(s)?
+        .into_iter()
+        .map(parse_human_param)
+        .collect()
+}
+
+impl FromStr for Function {
+    type Err = AbiDecodeError;
+
+    /// Parses a function in the human-readable form of ethers and viem,
+    /// such as `function transfer(address to, uint256 amount) returns
+    /// (bool)`.
+    ///
+    /// Parameter names, data locations and visibility are optional, `uint`
+    /// and `int` stand for their 256 bit types, and tuples are written as
+    /// `(address maker, uint256 amount)` or `tuple(...)`.
+    fn from_str(s: &str) -> Result<Function, AbiDecodeError> {
+        let invalid = || AbiDecodeError::InvalidSignature(s.to_string());
+        let params = |s: &str| -> Result<Vec<Param>, AbiDecodeError> {
+            Ok(parse_human_params(s)
+                .ok_or_else(invalid)?
+                .into_iter()
+                .map(|(_, param)| param)
+                .collect())
+        };
+        let trimmed = s.trim();
+        let body = trimmed.strip_prefix("function ").unwrap_or(trimmed);
+        let open = body.find('(').ok_or_else(invalid)?;
+        let name = body[..open].trim();
+        if !is_identifier(name) {
+            return Err(invalid());
+        }
+        let close = closing_paren(body, open).ok_or_else(invalid)?;
+        let inputs = params(&body[open + 1..close])?;
+
+        let mut outputs = Vec::new();
+        let mut state_mutability = StateMutability::NonPayable;
+        let mut rest = body[close + 1..].trim_start();
+        while !rest.is_empty() {
+            if let Some(returns) = rest.strip_prefix("returns") {
+                let returns = returns.trim_start();
+                if !returns.starts_with('(') {
+                    return Err(invalid());
+                }
+                let close = closing_paren(returns, 0).ok_or_else(invalid)?;
+                outputs = params(&returns[1..close])?;
+                rest = returns[close + 1..].trim_start();
+                continue;
+            }
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            match &rest[..end] {
+                "external" | "public" | "virtual" => {}
+                "view" => state_mutability = StateMutability::View,
+                "pure" => state_mutability = StateMutability::Pure,
+                "payable" => state_mutability = StateMutability::Payable,
+                "nonpayable" => state_mutability = StateMutability::NonPayable,
+                _ => return Err(invalid()),
+            }
+            rest = rest[end..].trim_start();
+        }
+        Ok(Function {
+            name: name.to_owned(),
+            inputs,
+            outputs,
+            state_mutability,
+        })
+    }
+}
+
+impl FromStr for Event {
+    type Err = AbiDecodeError;
+
+    /// Parses an event in the human-readable form, such as `event
+    /// Transfer(address indexed from, address indexed to, uint256 value)`,
+    /// optionally followed by `anonymous`.
+    fn from_str(s: &str) -> Result<Event, AbiDecodeError> {
+        let invalid = || AbiDecodeError::InvalidSignature(s.to_string());
+        let trimmed = s.trim();
+        let body = trimmed.strip_prefix("event ").unwrap_or(trimmed);
+        let open = body.find('(').ok_or_else(invalid)?;
+        let name = body[..open].trim();
+        let close = closing_paren(body, open).ok_or_else(invalid)?;
+        if !is_identifier(name) {
+            return Err(invalid());
+        }
+        let inputs = parse_human_params(&body[open + 1..close])
+            .ok_or_else(invalid)?
+            .into_iter()
+            .map(|(_, param)| param)
+            .collect();
+        let anonymous = match body[close + 1..].trim() {
+            "" => false,
+            "anonymous" => true,
+            _ => return Err(invalid()),
+        };
+        Ok(Event {
+            name: name.to_owned(),
+            inputs,
+            anonymous,
+        })
+    }
+}
+
+#[test]
+fn uint_ranges() {
+    use num_traits::Bounded;
+    let sig = "f(uint8,uint64,uint256)";
+    let call = |tokens: &[Token]| encode_call_checked(sig, tokens);
+    let max = [
+        Token::Uint(u8::MAX.into()),
+        Token::Uint(u64::MAX.into()),
+        Token::Uint(Uint256::max_value()),
+    ];
+    let data = call(&max).unwrap();
+    assert_eq!(
+        decode_tokens(&parse_param_types(sig).unwrap(), &data[4..]).unwrap(),
+        max
+    );
+    let mismatch = |tokens: &[Token]| {
+        call(tokens)
+            .unwrap_err()
+            .downcast::<AbiDecodeError>()
+            .unwrap()
+    };
+    assert_eq!(
+        mismatch(&[256u16.into(), max[1].clone(), max[2].clone()]),
+        AbiDecodeError::ArgumentMismatch(0, "uint8".to_owned())
+    );
+    let above_u64 = Token::Uint(Uint256::from(u64::MAX) + Uint256::from(1u8));
+    assert_eq!(
+        mismatch(&[max[0].clone(), above_u64, max[2].clone()]),
+        AbiDecodeError::ArgumentMismatch(1, "uint64".to_owned())
+    );
+
+    // A value with bits above its width, as a contract would not write it
+    for (word, dirty) in &[(0, 30), (1, 23)] {
+        let mut data = encode_tokens(&max);
+        data[word * 32 + dirty] = 1;
+        assert_eq!(
+            decode_tokens(&parse_param_types(sig).unwrap(), &data),
+            Err(AbiDecodeError::InvalidValue(
+                parse_param_types(sig).unwrap()[*word].clone()
+            ))
+        );
+    }
+}
+
+#[test]
+fn parse_human_readable_function() {
+    let transfer: Function = "function transfer(address to, uint256 amount) returns (bool)"
+        .parse()
+        .unwrap();
+    assert_eq!(transfer.signature(), "transfer(address,uint256)");
+    assert_eq!(transfer.selector(), [0xa9, 0x05, 0x9c, 0xbb]);
+    assert_eq!(transfer.inputs[1].name, "amount");
+    assert_eq!(transfer.outputs[0].canonical_type, "bool");
+    assert_eq!(transfer.state_mutability, StateMutability::NonPayable);
+
+    let to = Address::from([0x11u8; 20]);
+    assert_eq!(
+        transfer.encode_input(&[to.into(), 5u8.into()]).unwrap(),
+        encode_call("transfer(address,uint256)", &[to.into(), 5u8.into()])
+    );
+    // Arguments swapped, or missing
+    assert_eq!(
+        transfer.check_input(&[5u8.into(), to.into()]),
+        Err(AbiDecodeError::ArgumentMismatch(0, "address".to_owned()))
+    );
+    assert_eq!(
+        transfer.check_input(&[to.into()]),
+        Err(AbiDecodeError::ArgumentCount {
+            expected: 2,
+            given: 1
+        })
+    );
+
+    let swap: Function = "function swap(int24 tick, uint8 fee, bytes4 id, (address maker, uint[] amounts)[2] calldata orders) external view returns (int)"
+        .parse()
+        .unwrap();
+    assert_eq!(
+        swap.signature(),
+        "swap(int24,uint8,bytes4,(address,uint256[])[2])"
+    );
+    assert_eq!(swap.state_mutability, StateMutability::View);
+    let order = Token::Tuple(vec![to.into(), vec![1u32, 2].into()]);
+    let orders = Token::FixedArray(vec![order.clone(), order]);
+    let args = |tick: i32, fee: u32| {
+        vec![
+            tick.into(),
+            fee.into(),
+            Token::Bytes(vec![0; 4]),
+            orders.clone(),
+        ]
+    };
+    assert!(swap.check_input(&args(-887_272, 255)).is_ok());
+    // Out of the range of int24 and uint8
+    assert!(swap.check_input(&args(-8_388_609, 255)).is_err());
+    assert!(swap.check_input(&args(8_388_607, 256)).is_err());
+    let calldata = swap.encode_input(&args(-887_272, 255)).unwrap();
+    assert_eq!(swap.decode_input(&calldata).unwrap(), args(-887_272, 255));
+    assert_eq!(
+        swap.decode_output(&encode_tokens(&[(-1i8).into()]))
+            .unwrap(),
+        vec![(-1i8).into()]
+    );
+
+    let event_like = "function f(address indexed from) pure".parse::<Function>();
+    assert!(event_like.unwrap().inputs[0].indexed);
+    for invalid in &[
+        "function f(uint7)",
+        "function f(address to amount)",
+        "function (address)",
+        "function f(address",
+        "function f() returns bool",
+        "function f() constant",
+    ] {
+        assert!(invalid.parse::<Function>().is_err(), "{}", invalid);
+    }
+
+    let transfer: Event = "event Transfer(address indexed from, address indexed to, uint256 value)"
+        .parse()
+        .unwrap();
+    assert_eq!(transfer.signature(), "Transfer(address,address,uint256)");
+    assert!(!transfer.anonymous && transfer.inputs[1].indexed);
+    assert!("event Ping() anonymous".parse::<Event>().unwrap().anonymous);
+    assert!("event Ping() view".parse::<Event>().is_err());
+}
+
+/// The ABI of a contract, as written by solc and found in the `abi` field
+/// of Hardhat and Foundry artifacts.
+///
+/// Besides functions, events, errors and the constructor, the JSON lists
+/// whether the contract has a `fallback` and a `receive` function. ABIs of
+/// old compilers, with `constant` and `payable` instead of
+/// `stateMutability`, are understood as well.
+///
+/// ```rust
+/// # extern crate clarity;
+/// # extern crate serde_json;
+/// use clarity::abi::Abi;
+///
+/// # fn main() {
+/// let abi: Abi = serde_json::from_str(r#"[{
+///     "type": "function",
+///     "name": "transfer",
+///     "inputs": [
+///         {"name": "to", "type": "address"},
+///         {"name": "amount", "type": "uint256"}
+///     ],
+///     "outputs": [{"name": "", "type": "bool"}],
+///     "stateMutability": "nonpayable"
+/// }]"#).unwrap();
+/// let transfer = abi.function("transfer").unwrap();
+/// assert_eq!(transfer.signature(), "transfer(address,uint256)");
+/// assert_eq!(transfer.selector(), [0xa9, 0x05, 0x9c, 0xbb]);
+/// # }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Abi {
+    pub constructor: Option<Constructor>,
+    pub functions: Vec<Function>,
+    pub events: Vec<Event>,
+    pub errors: Vec<ContractError>,
+    pub has_fallback: bool,
+    pub has_receive: bool,
+}
+
+impl Abi {
+    /// The function called `name`, the first one if it is overloaded.
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+
+    /// The function a call is of, by its method id.
+    pub fn function_by_selector(&self, selector: [u8; 4]) -> Option<&Function> {
+        self.functions
+            .iter()
+            .find(|function| function.selector() == selector)
+    }
+
+    /// The event called `name`, the first one if it is overloaded.
+    pub fn event(&self, name: &str) -> Option<&Event> {
+        self.events.iter().find(|event| event.name == name)
+    }
+
+    /// The error called `name`, the first one if it is overloaded.
+    pub fn error(&self, name: &str) -> Option<&ContractError> {
+        self.errors.iter().find(|error| error.name == name)
+    }
+}
+
+/// A parameter as it is written in the JSON.
+#[derive(Deserialize)]
+struct JsonParam {
+    #[serde(default)]
+    name: String,
+    #[serde(rename = "type")]
+    kind: String,
+    #[serde(default)]
+    components: Vec<JsonParam>,
+    #[serde(default)]
+    indexed: bool,
+}
+
+impl JsonParam {
+    /// The canonical type, with tuples spelled out.
+    fn canonical_type(&self) -> String {
+        match self.kind.strip_prefix("tuple") {
+            Some(suffix) => {
+                let members: Vec<String> =
+                    self.components.iter().map(Self::canonical_type).collect();
+                format!("({}){}", members.join(","), suffix)
+            }
+            None => self.kind.clone(),
+        }
+    }
+
+    fn into_param(self) -> Param {
+        Param {
+            canonical_type: self.canonical_type(),
+            name: self.name,
+            indexed: self.indexed,
+        }
+    }
+}
+
+fn into_params(params: Vec<JsonParam>) -> Vec<Param> {
+    params.into_iter().map(JsonParam::into_param).collect()
+}
+
+/// An entry of the JSON.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonEntry {
+    // Old compilers left out the type of functions
+    #[serde(rename = "type", default = "function_type")]
+    kind: String,
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    inputs: Vec<JsonParam>,
+    #[serde(default)]
+    outputs: Vec<JsonParam>,
+    state_mutability: Option<StateMutability>,
+    #[serde(default)]
+    constant: bool,
+    #[serde(default)]
+    payable: bool,
+    #[serde(default)]
+    anonymous: bool,
+}
+
+fn function_type() -> String {
+    "function".to_owned()
+}
+
+impl JsonEntry {
+    fn state_mutability(&self) -> StateMutability {
+        match self.state_mutability {
+            Some(state_mutability) => state_mutability,
+            None if self.payable => StateMutability::Payable,
+            None if self.constant => StateMutability::View,
+            None => StateMutability::NonPayable,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Abi {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Abi, D::Error> {
+        let mut abi = Abi::default();
+        for entry in Vec::<JsonEntry>::deserialize(deserializer)? {
+            let state_mutability = entry.state_mutability();
+            match entry.kind.as_str() {
+                "function" => abi.functions.push(Function {
+                    name: entry.name,
+                    inputs: into_params(entry.inputs),
+                    outputs: into_params(entry.outputs),
+                    state_mutability,
+                }),
+                "event" => abi.events.push(Event {
+                    name: entry.name,
+                    inputs: into_params(entry.inputs),
+                    anonymous: entry.anonymous,
+                }),
+                "error" => abi.errors.push(ContractError {
+                    name: entry.name,
+                    inputs: into_params(entry.inputs),
+                }),
+                "constructor" => {
+                    abi.constructor = Some(Constructor {
+                        inputs: into_params(entry.inputs),
+                        state_mutability,
+                    })
+                }
+                "fallback" => abi.has_fallback = true,
+                "receive" => abi.has_receive = true,
+                kind => {
+                    return Err(de::Error::unknown_variant(
+                        kind,
+                        &[
+                            "function",
+                            "event",
+                            "error",
+                            "constructor",
+                            "fallback",
+                            "receive",
+                        ],
+                    ))
+                }
+            }
+        }
+        Ok(abi)
+    }
+}
+
+#[test]
+fn parse_abi_json() {
+    let abi: Abi = serde_json::from_str(
+        r#"[
+        {"type": "constructor", "inputs": [{"name": "owner", "type": "address"}], "stateMutability": "payable"},
+        {"type": "function", "name": "balanceOf", "inputs": [{"name": "", "type": "address"}],
+         "outputs": [{"name": "", "type": "uint256"}], "stateMutability": "view"},
+        {"type": "function", "name": "submit", "inputs": [{"name": "orders", "type": "tuple[]", "components": [
+            {"name": "maker", "type": "address"},
+            {"name": "amounts", "type": "uint256[2]"},
+            {"name": "fee", "type": "tuple", "components": [{"name": "bps", "type": "uint16"}]}
+        ]}], "outputs": [], "stateMutability": "nonpayable"},
+        {"type": "event", "name": "Transfer", "anonymous": false, "inputs": [
+            {"name": "from", "type": "address", "indexed": true},
+            {"name": "to", "type": "address", "indexed": true},
+            {"name": "value", "type": "uint256", "indexed": false}
+        ]},
+        {"type": "error", "name": "InsufficientBalance", "inputs": [
+            {"name": "available", "type": "uint256"}, {"name": "required", "type": "uint256"}
+        ]},
+        {"type": "receive", "stateMutability": "payable"},
+        {"constant": true, "inputs": [], "name": "name", "outputs": [{"name": "", "type": "string"}], "payable": false}
+    ]"#,
+    )
+    .unwrap();
+
+    let constructor = abi.constructor.as_ref().unwrap();
+    assert_eq!(constructor.state_mutability, StateMutability::Payable);
+    let owner = Address::from([0x11u8; 20]);
+    assert_eq!(
+        constructor.encode_input(&[owner.into()]).unwrap(),
+        encode_tokens(&[owner.into()])
+    );
+
+    let balance_of = abi.function("balanceOf").unwrap();
+    assert_eq!(balance_of.state_mutability, StateMutability::View);
+    assert_eq!(
+        abi.function_by_selector([0x70, 0xa0, 0x82, 0x31]),
+        Some(balance_of)
+    );
+    let calldata = balance_of.encode_input(&[owner.into()]).unwrap();
+    assert_eq!(
+        balance_of.decode_input(&calldata).unwrap(),
+        vec![owner.into()]
+    );
+    assert!(balance_of.encode_input(&[]).is_err());
+    assert_eq!(
+        balance_of
+            .decode_output(&encode_tokens(&[7u8.into()]))
+            .unwrap(),
+        vec![7u8.into()]
+    );
+
+    let submit = abi.function("submit").unwrap();
+    assert_eq!(
+        submit.signature(),
+        "submit((address,uint256[2],(uint16))[])"
+    );
+    let orders = vec![Token::Dynamic(vec![
+        Token::Tuple(vec![
+            owner.into(),
+            Token::FixedArray(vec![1u8.into(), 2u8.into()]),
+            Token::Tuple(vec![3u8.into()]),
+        ]),
+        Token::Tuple(vec![
+            Address::from([0x22u8; 20]).into(),
+            Token::FixedArray(vec![4u8.into(), 5u8.into()]),
+            Token::Tuple(vec![u16::MAX.into()]),
+        ]),
+    ])];
+    let calldata = submit.encode_input(&orders).unwrap();
+    // The offset and length of the array, then two items of four words
+    assert_eq!(calldata.len(), 4 + 2 * 32 + 2 * 4 * 32);
+    assert_eq!(submit.decode_input(&calldata).unwrap(), orders);
+    assert_eq!(
+        submit.decode_input(&calldata[..calldata.len() - 32]),
+        Err(AbiDecodeError::UnexpectedEnd)
+    );
+    assert_eq!(
+        submit.decode_input(&submit.selector()),
+        Err(AbiDecodeError::UnexpectedEnd)
+    );
+
+    let transfer = abi.event("Transfer").unwrap();
+    assert_eq!(
+        transfer.topic(),
+        derive_signature("Transfer(address,address,uint256)")
+    );
+    let indexed: Vec<bool> = transfer.inputs.iter().map(|param| param.indexed).collect();
+    assert_eq!(indexed, vec![true, true, false]);
+    assert_eq!(
+        abi.error("InsufficientBalance").unwrap().signature(),
+        "InsufficientBalance(uint256,uint256)"
+    );
+    assert!(abi.has_receive && !abi.has_fallback);
+    // The legacy entry, without a type
+    assert_eq!(
+        abi.function("name").unwrap().state_mutability,
+        StateMutability::View
+    );
+
+    assert!(serde_json::from_str::<Abi>(r#"[{"type": "method", "name": "f"}]"#).is_err());
+}
@@ -0,0 +1,362 @@
+//! ABI coding that refuses anything the specification does not allow.
+//!
+//! [encode_tokens](../abi/fn.encode_tokens.html) panics on tokens that have
+//! no ABI encoding, and [decode_tokens](../abi/fn.decode_tokens.html)
+//! accepts data that another encoder could have produced, such as tails in
+//! another order or with gaps between them, as contracts do. Where both
+//! sides of a protocol should agree on one encoding, such as when hashes or
+//! signatures are taken over it, the functions here encode and decode only
+//! what the [specification](https://docs.soliditylang.org/en/latest/abi-spec.html)
+//! describes, and name the rule that was broken otherwise:
+//!
+//! ```rust
+//! use clarity::abi::{ParamType, Token};
+//! use clarity::abi_strict::{decode_tokens_strict, encode_tokens_strict};
+//!
+//! let data = encode_tokens_strict(&[Token::UnboundedBytes(Vec::new())]).unwrap();
+//! assert_eq!(data.len(), 64);
+//!
+//! // An empty `bytes` padded with a word of zeros, as some encoders do
+//! let mut padded = data.clone();
+//! padded.extend_from_slice(&[0u8; 32]);
+//! let error = decode_tokens_strict(&[ParamType::Bytes], &padded).unwrap_err();
+//! assert!(error.to_string().contains("pad_right"));
+//! ```
+use abi::{decode_tokens, encode_tokens, AbiDecodeError, ParamType, Token};
+use num256::Int256;
+use num_traits::Bounded;
+use std::fmt;
+use std::iter;
+
+/// A rule of the specification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AbiRule {
+    /// Unsigned integers have at most 256 bits
+    UintSize,
+    /// Signed integers have at most 256 bits
+    IntSize,
+    /// Fixed size byte arrays have 1 to 32 bytes
+    FixedBytesSize,
+    /// The items of an array are of one type
+    ArrayItemType,
+    /// The tail of a dynamic value starts right after the head and the
+    /// tails before it
+    TailOffset,
+    /// `bytes` and `string` are padded with the fewest zero bytes that make
+    /// a whole number of words, which for an empty value is none
+    TailPadding,
+    /// The encoding ends with the last tail
+    EncodingLength,
+}
+
+impl AbiRule {
+    /// The words of the specification that state the rule.
+    pub fn citation(self) -> &'static str {
+        match self {
+            AbiRule::UintSize => "uint<M>: unsigned integer type of M bits, 0 < M <= 256",
+            AbiRule::IntSize => {
+                "int<M>: two's complement signed integer type of M bits, 0 < M <= 256"
+            }
+            AbiRule::FixedBytesSize => "bytes<M>: binary type of M bytes, 0 < M <= 32",
+            AbiRule::ArrayItemType => {
+                "<type>[]: a variable-length array of elements of the given type"
+            }
+            AbiRule::TailOffset => {
+                "head(X(i)) = enc(len( head(X(1)) ... head(X(k)) tail(X(1)) ... tail(X(i-1)) ))"
+            }
+            AbiRule::TailPadding => {
+                "enc(X) = enc(k) pad_right(X), followed by the minimum number of zero-bytes \
+                 such that len(enc(X)) is a multiple of 32"
+            }
+            AbiRule::EncodingLength => {
+                "enc(X) = head(X(1)) ... head(X(k)) tail(X(1)) ... tail(X(k))"
+            }
+        }
+    }
+}
+
+impl fmt::Display for AbiRule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self.citation())
+    }
+}
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum StrictAbiError {
+    #[fail(display = "Token {} can not be encoded, as {}", index, rule)]
+    InvalidToken { index: usize, rule: AbiRule },
+    #[fail(display = "Encoding is not canonical at byte {}, as {}", offset, rule)]
+    NonCanonical { offset: usize, rule: AbiRule },
+    #[fail(display = "{}", _0)]
+    Decode(AbiDecodeError),
+}
+
+impl From<AbiDecodeError> for StrictAbiError {
+    fn from(error: AbiDecodeError) -> StrictAbiError {
+        StrictAbiError::Decode(error)
+    }
+}
+
+/// Encodes `tokens` like [encode_tokens](../abi/fn.encode_tokens.html),
+/// after checking that all of them have an encoding. Errors refer to the
+/// top level token that has none.
+pub fn encode_tokens_strict(tokens: &[Token]) -> Result<Vec<u8>, StrictAbiError> {
+    for (index, token) in tokens.iter().enumerate() {
+        if let Err(rule) = check_token(token) {
+            return Err(StrictAbiError::InvalidToken { index, rule });
+        }
+    }
+    Ok(encode_tokens(tokens))
+}
+
+fn check_token(token: &Token) -> Result<(), AbiRule> {
+    match *token {
+        Token::Uint(ref value) if value.bits() > 256 => Err(AbiRule::UintSize),
+        Token::Int(ref value) if *value < Int256::min_value() || *value > Int256::max_value() => {
+            Err(AbiRule::IntSize)
+        }
+        Token::Bytes(ref bytes) if bytes.is_empty() || bytes.len() > 32 => {
+            Err(AbiRule::FixedBytesSize)
+        }
+        Token::Dynamic(ref items) => {
+            if items.windows(2).any(|pair| !same_type(&pair[0], &pair[1])) {
+                return Err(AbiRule::ArrayItemType);
+            }
+            items.iter().try_for_each(check_token)
+        }
+        Token::Tuple(ref items) => items.iter().try_for_each(check_token),
+        Token::FixedArray(ref items) => {
+            if items.windows(2).any(|pair| !same_type(&pair[0], &pair[1])) {
+                return Err(AbiRule::ArrayItemType);
+            }
+            items.iter().try_for_each(check_token)
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Whether two tokens can be of one type. Integers carry no size, so any
+/// two of one signedness can.
+fn same_type(a: &Token, b: &Token) -> bool {
+    match (a, b) {
+        (Token::Bytes(a), Token::Bytes(b)) => a.len() == b.len(),
+        (Token::Tuple(a), Token::Tuple(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_type(a, b))
+        }
+        (Token::FixedArray(a), Token::FixedArray(b)) => {
+            a.len() == b.len() && a.iter().zip(b).all(|(a, b)| same_type(a, b))
+        }
+        (Token::Dynamic(a), Token::Dynamic(b)) => match (a.first(), b.first()) {
+            (Some(a), Some(b)) => same_type(a, b),
+            _ => true,
+        },
+        _ => std::mem::discriminant(a) == std::mem::discriminant(b),
+    }
+}
+
+/// Decodes `data` like [decode_tokens](../abi/fn.decode_tokens.html), but
+/// only if it is the one encoding of its values that the specification
+/// allows: tails in the order of their heads without gaps, padding of the
+/// fewest zero bytes, and nothing after the last tail.
+pub fn decode_tokens_strict(
+    types: &[ParamType],
+    data: &[u8],
+) -> Result<Vec<Token>, StrictAbiError> {
+    let tokens = decode_tokens(types, data)?;
+    let tail = check_sequence(types, data, 0)?;
+    if data.len() != tail {
+        // An empty `bytes` or `string` at the end, followed by a word of
+        // zeros, was padded
+        let padded_empty = types.last().is_some_and(|kind| {
+            matches!(*kind, ParamType::Bytes | ParamType::String)
+                && tail >= 32
+                && read_usize(data, tail - 32) == 0
+        });
+        let rule = if padded_empty && data.len() == tail + 32 {
+            AbiRule::TailPadding
+        } else {
+            AbiRule::EncodingLength
+        };
+        return Err(non_canonical(tail, rule));
+    }
+    Ok(tokens)
+}
+
+fn non_canonical(offset: usize, rule: AbiRule) -> StrictAbiError {
+    StrictAbiError::NonCanonical { offset, rule }
+}
+
+/// Checks the tails of values of `types` whose heads follow each other from
+/// `base`, and returns where the last tail ends.
+fn check_sequence<'a, I>(types: I, data: &[u8], base: usize) -> Result<usize, StrictAbiError>
+where
+    I: IntoIterator<Item = &'a ParamType>,
+    I::IntoIter: Clone,
+{
+    let types = types.into_iter();
+    let mut head = base;
+    let mut tail = base + types.clone().map(ParamType::head_len).sum::<usize>();
+    for kind in types {
+        if kind.is_dynamic() {
+            // Offsets and lengths were checked to be within `data`
+            if base + read_usize(data, head) != tail {
+                return Err(non_canonical(head, AbiRule::TailOffset));
+            }
+            tail = check_tail(kind, data, tail)?;
+        }
+        head += kind.head_len();
+    }
+    Ok(tail)
+}
+
+/// Checks the tail of a dynamic value that starts at `start`, and returns
+/// where it ends.
+fn check_tail(kind: &ParamType, data: &[u8], start: usize) -> Result<usize, StrictAbiError> {
+    match *kind {
+        ParamType::Bytes | ParamType::String => {
+            // The padding was checked to be there and to be zero
+            let length = read_usize(data, start);
+            Ok(start + 32 + length.div_ceil(32) * 32)
+        }
+        ParamType::Array(ref inner) => {
+            let length = read_usize(data, start);
+            check_sequence(iter::repeat_n(&**inner, length), data, start + 32)
+        }
+        ParamType::FixedArray(ref inner, size) => {
+            check_sequence(iter::repeat_n(&**inner, size), data, start)
+        }
+        ParamType::Tuple(ref members) => check_sequence(members, data, start),
+        _ => unreachable!("Elementary types are static"),
+    }
+}
+
+/// Reads a word already checked to be a small number.
+fn read_usize(data: &[u8], offset: usize) -> usize {
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&data[offset + 24..offset + 32]);
+    u64::from_be_bytes(value) as usize
+}
+
+#[test]
+fn strict_encoding() {
+    assert_eq!(
+        encode_tokens_strict(&[true.into(), Token::Bytes(Vec::new())]),
+        Err(StrictAbiError::InvalidToken {
+            index: 1,
+            rule: AbiRule::FixedBytesSize,
+        })
+    );
+    assert_eq!(
+        encode_tokens_strict(&[Token::Dynamic(vec![1u8.into(), true.into()])]),
+        Err(StrictAbiError::InvalidToken {
+            index: 0,
+            rule: AbiRule::ArrayItemType,
+        })
+    );
+    let error = encode_tokens_strict(&[Token::Bytes(vec![0; 33])]).unwrap_err();
+    assert!(error.to_string().contains("0 < M <= 32"));
+    let tokens = [
+        Token::UnboundedBytes(Vec::new()),
+        "".into(),
+        Token::Dynamic(vec![
+            Token::Dynamic(vec![]),
+            Token::Dynamic(vec![1u8.into()]),
+        ]),
+    ];
+    assert_eq!(
+        encode_tokens_strict(&tokens).unwrap(),
+        encode_tokens(&tokens)
+    );
+}
+
+#[test]
+fn strict_decoding() {
+    let types = [ParamType::String, ParamType::Bool, ParamType::Bytes];
+    let tokens = vec![
+        "dave".into(),
+        true.into(),
+        Token::UnboundedBytes(vec![1, 2, 3]),
+    ];
+    let data = encode_tokens(&tokens);
+    assert_eq!(decode_tokens_strict(&types, &data).unwrap(), tokens);
+
+    // The tails swapped, which decode_tokens accepts
+    let mut swapped = data[..96].to_vec();
+    swapped[31] = 0xa0;
+    swapped[95] = 0x60;
+    swapped.extend_from_slice(&data[160..]);
+    swapped.extend_from_slice(&data[96..160]);
+    assert_eq!(decode_tokens(&types, &swapped).unwrap(), tokens);
+    assert_eq!(
+        decode_tokens_strict(&types, &swapped),
+        Err(StrictAbiError::NonCanonical {
+            offset: 0,
+            rule: AbiRule::TailOffset,
+        })
+    );
+
+    // decode_tokens refuses padding that is not zero itself
+    let mut dirty = data.clone();
+    dirty[128 + 4] = 1;
+    assert_eq!(
+        decode_tokens_strict(&types, &dirty),
+        Err(StrictAbiError::Decode(AbiDecodeError::InvalidValue(
+            ParamType::String
+        )))
+    );
+    let mut trailing = data.clone();
+    trailing.push(0);
+    assert_eq!(
+        decode_tokens_strict(&types, &trailing),
+        Err(StrictAbiError::NonCanonical {
+            offset: data.len(),
+            rule: AbiRule::EncodingLength,
+        })
+    );
+
+    // Empty values, then an empty value padded to a word
+    let types = [ParamType::Bytes, ParamType::String];
+    let empty = encode_tokens(&[Token::UnboundedBytes(Vec::new()), "".into()]);
+    assert_eq!(empty.len(), 4 * 32);
+    decode_tokens_strict(&types, &empty).unwrap();
+    let mut padded = empty.clone();
+    padded.extend_from_slice(&[0u8; 32]);
+    assert_eq!(
+        decode_tokens_strict(&types, &padded),
+        Err(StrictAbiError::NonCanonical {
+            offset: empty.len(),
+            rule: AbiRule::TailPadding,
+        })
+    );
+    assert!(matches!(
+        decode_tokens_strict(&types, &empty[..96]),
+        Err(StrictAbiError::Decode(_))
+    ));
+}
+
+#[test]
+fn strict_nested_decoding() {
+    use abi::parse_param_types;
+    let types = parse_param_types("f((bytes,bytes),(uint8,string)[],int16[2])").unwrap();
+    let tokens = vec![
+        Token::Tuple(vec![vec![0xabu8].into(), vec![0xabu8].into()]),
+        Token::Dynamic(vec![Token::Tuple(vec![1u8.into(), "one".into()])]),
+        Token::FixedArray(vec![(-1i16).into(), 2i16.into()]),
+    ];
+    let data = encode_tokens(&tokens);
+    assert_eq!(decode_tokens_strict(&types, &data).unwrap(), tokens);
+
+    // Both members of the first tuple read from one tail, which leaves the
+    // other one unread
+    let mut aliased = data.clone();
+    assert_eq!(aliased[32 * 6 - 1], 0x80);
+    aliased[32 * 6 - 1] = 0x40;
+    assert_eq!(decode_tokens(&types, &aliased).unwrap(), tokens);
+    assert_eq!(
+        decode_tokens_strict(&types, &aliased),
+        Err(StrictAbiError::NonCanonical {
+            offset: 32 * 5,
+            rule: AbiRule::TailOffset,
+        })
+    );
+}
@@ -1,13 +1,16 @@
 use failure::Error;
-use serde::Deserialize;
-use serde::Deserializer;
-use serde::Serialize;
-use serde::Serializer;
+use hash::keccak256;
+use serde_crate::Deserialize;
+use serde_crate::Deserializer;
+use serde_crate::Serialize;
+use serde_crate::Serializer;
+use std::convert::TryFrom;
 use std::fmt::{self, Display};
 use std::str;
 use std::str::FromStr;
+use types::{fixed_bytes, LengthError};
 use utils::bytes_to_hex_str;
-use utils::{hex_str_to_bytes, ByteDecodeError};
+use utils::{hex_str_to_bytes, strip_hex_prefix, ByteDecodeError};
 
 /// Representation of an Ethereum address.
 ///
@@ -26,14 +29,52 @@ impl Address {
     ///
     /// This requires a slice to be exactly 20 bytes in length,
     pub fn from_slice(data: &[u8]) -> Result<Address, Error> {
-        ensure!(
-            data.len() == 20,
-            "Address requires exactly 20 bytes but {} were found",
-            data.len()
-        );
-        let mut result: [u8; 20] = Default::default();
-        result.copy_from_slice(&data);
-        Ok(Address(result))
+        Ok(Address::try_from(data)?)
+    }
+
+    /// Get the EIP-55 mixed-case checksummed representation of the address,
+    /// including the `0x` prefix.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use clarity::Address;
+    /// let address: Address = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse().unwrap();
+    /// assert_eq!(
+    ///     address.to_checksum_string(),
+    ///     "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+    /// );
+    /// ```
+    pub fn to_checksum_string(&self) -> String {
+        let lower = bytes_to_hex_str(&self.0);
+        let hash = keccak256(lower.as_bytes());
+        let mut result = "0x".to_owned();
+        for (i, c) in lower.chars().enumerate() {
+            // Every hex character is paired with a nibble of the hash
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+            if nibble >= 8 {
+                result.push(c.to_ascii_uppercase());
+            } else {
+                result.push(c);
+            }
+        }
+        result
+    }
+
+    /// Parses an address and verifies its EIP-55 checksum.
+    ///
+    /// Addresses written entirely in lower or upper case carry no checksum
+    /// and are accepted as is, mixed-case addresses have to match the
+    /// checksum exactly.
+    pub fn parse_and_validate(s: &str) -> Result<Address, Error> {
+        let address: Address = s.parse()?;
+        let digits = strip_hex_prefix(s);
+        let is_lower = !digits.chars().any(|c| c.is_ascii_uppercase());
+        let is_upper = !digits.chars().any(|c| c.is_ascii_lowercase());
+        if !is_lower && !is_upper && address.to_checksum_string()[2..] != *digits {
+            return Err(AddressError::InvalidChecksum.into());
+        }
+        Ok(address)
     }
 }
 
@@ -52,11 +93,9 @@ impl<'de> Deserialize<'de> for Address {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        let s = if s.starts_with("0x") { &s[2..] } else { &s };
-
         hex_str_to_bytes(&s)
             .and_then(move |bytes| Address::from_slice(&bytes))
-            .map_err(serde::de::Error::custom)
+            .map_err(serde_crate::de::Error::custom)
     }
 }
 
@@ -66,18 +105,24 @@ impl From<[u8; 20]> for Address {
     }
 }
 
+impl<'a> TryFrom<&'a [u8]> for Address {
+    type Error = LengthError;
+
+    fn try_from(data: &'a [u8]) -> Result<Address, LengthError> {
+        fixed_bytes("Address", data).map(Address)
+    }
+}
+
 impl Into<[u8; 20]> for Address {
     fn into(self) -> [u8; 20] {
-        let mut data: [u8; 20] = Default::default();
-        data[12..].copy_from_slice(&self.as_bytes());
-        data
+        self.0
     }
 }
 
 impl Into<[u8; 32]> for Address {
     fn into(self) -> [u8; 32] {
         let mut data: [u8; 32] = Default::default();
-        data[12..].copy_from_slice(&self.as_bytes());
+        data[12..].copy_from_slice(&self.0);
         data
     }
 }
@@ -163,7 +208,7 @@ impl FromStr for Address {
         if s.is_empty() {
             return Ok(Address::default());
         }
-        let s = if s.starts_with("0x") { &s[2..] } else { &s };
+        let s = strip_hex_prefix(s);
         if s.len() == 40 {
             Ok(Address::from_slice(&hex_str_to_bytes(&s)?)?)
         } else {
@@ -246,6 +291,25 @@ fn handle_prefixed() {
     );
 }
 
+#[test]
+fn prefix_and_garbage() {
+    let expected = Address::from([0x11u8; 20]);
+    let digits = "1111111111111111111111111111111111111111";
+    assert_eq!(digits.parse::<Address>().unwrap(), expected);
+    assert_eq!(
+        format!("0x{}", digits).parse::<Address>().unwrap(),
+        expected
+    );
+    assert_eq!(
+        format!("0X{}", digits).parse::<Address>().unwrap(),
+        expected
+    );
+    assert!(format!("0x0x{}", &digits[4..]).parse::<Address>().is_err());
+    assert!(format!("+1{}", &digits[2..]).parse::<Address>().is_err());
+    assert!(format!(" {}", &digits[1..]).parse::<Address>().is_err());
+    assert!("0x".parse::<Address>().is_err());
+}
+
 #[test]
 fn hashed() {
     // One of the use cases for Address could be a key in a HashMap to store some
@@ -307,3 +371,54 @@ fn display() {
         "0x1234567890123456789abcdef678901234567890"
     );
 }
+
+#[test]
+fn checksum_eip55_vectors() {
+    // https://eips.ethereum.org/EIPS/eip-55#test-cases
+    for checksummed in &[
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+        "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+        "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+        "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+    ] {
+        let address: Address = checksummed.to_lowercase().parse().unwrap();
+        assert_eq!(address.to_checksum_string(), *checksummed);
+        assert_eq!(Address::parse_and_validate(checksummed).unwrap(), address);
+    }
+}
+
+#[test]
+fn checksum_validation() {
+    // No checksum in a single case form
+    assert!(Address::parse_and_validate("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed").is_ok());
+    assert!(Address::parse_and_validate("0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED").is_ok());
+    assert!(Address::parse_and_validate("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_ok());
+    // Wrong case of a single character
+    let e = Address::parse_and_validate("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD")
+        .unwrap_err()
+        .downcast::<AddressError>()
+        .unwrap();
+    assert_eq!(e, AddressError::InvalidChecksum);
+}
+
+#[test]
+fn length_checked_conversions() {
+    let bytes = [0x11u8; 20];
+    let address = Address::try_from(&bytes[..]).unwrap();
+    let raw: [u8; 20] = address.into();
+    assert_eq!(raw, bytes);
+    let word: [u8; 32] = address.into();
+    assert_eq!(&word[12..], &bytes[..]);
+    assert_eq!(
+        Address::try_from(&[0u8; 32][..]).unwrap_err(),
+        LengthError {
+            what: "Address",
+            expected: 20,
+            found: 32,
+        }
+    );
+    assert_eq!(
+        Address::from_slice(&[0u8; 19]).unwrap_err().to_string(),
+        "Address should be 20 bytes but 19 were found"
+    );
+}
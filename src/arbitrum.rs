@@ -0,0 +1,383 @@
+//! Arbitrum retryable tickets.
+//!
+//! Messages from L1 to an Arbitrum chain are sent as retryable tickets,
+//! created through `createRetryableTicket` on the Inbox. The call pays for
+//! the submission and the L2 gas up front, and yields a ticket that is
+//! redeemed on L2, automatically if the gas was enough or by hand later
+//! on. The ticket is identified on L2 by an id derived from the message the
+//! Bridge delivered, which [retryable_ticket_id](fn.retryable_ticket_id.html)
+//! computes from the logs of the L1 transaction.
+use abi::{decode_tokens, derive_signature, encode_call, ParamType, Token};
+use address::Address;
+use event::Log;
+use failure::Error;
+use hash::keccak256;
+use num256::Uint256;
+use num_traits::{CheckedAdd, CheckedMul};
+use rlp::AddressDef;
+use serde_bytes::ByteBuf;
+use serde_rlp::ser::to_bytes;
+use types::BigEndianInt;
+
+/// Signature of `createRetryableTicket` on the Inbox.
+pub const CREATE_RETRYABLE_TICKET: &str =
+    "createRetryableTicket(address,uint256,uint256,address,address,uint256,uint256,bytes)";
+/// Logged by the Inbox with the data of the message.
+pub const INBOX_MESSAGE_DELIVERED: &str = "InboxMessageDelivered(uint256,bytes)";
+/// Logged by the Bridge for every message, with its sender and the L1 base
+/// fee.
+pub const MESSAGE_DELIVERED: &str =
+    "MessageDelivered(uint256,bytes32,address,uint8,address,bytes32,uint256,uint64)";
+
+/// Kind of the messages that submit retryable tickets.
+pub const SUBMIT_RETRYABLE_KIND: u8 = 9;
+
+/// Type byte of submitted retryable tickets, as hashed into their id.
+const SUBMIT_RETRYABLE_TX_TYPE: u8 = 0x69;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ArbitrumError {
+    #[fail(display = "Log is not a {} event", _0)]
+    UnexpectedEvent(&'static str),
+    #[fail(display = "Message of kind {} is not a retryable ticket", _0)]
+    NotRetryable(u8),
+    #[fail(display = "Message data is malformed")]
+    MalformedMessage,
+    #[fail(display = "Deposit overflows")]
+    DepositOverflow,
+}
+
+/// The fee for submitting a ticket with this much data, given the L1 base
+/// fee. `maxSubmissionCost` has to be at least this much, and the base fee
+/// may rise before the transaction is included, so some margin is due.
+pub fn submission_fee(data_length: usize, base_fee: &Uint256) -> Uint256 {
+    let size: Uint256 = (1400 + 6 * data_length as u64).into();
+    size * base_fee.clone()
+}
+
+/// The parameters of a retryable ticket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryableTicket {
+    /// Destination of the L2 call
+    pub to: Address,
+    /// Value of the L2 call
+    pub l2_call_value: Uint256,
+    /// See [submission_fee](fn.submission_fee.html)
+    pub max_submission_cost: Uint256,
+    /// Receives the submission and gas fees that were not used
+    pub excess_fee_refund_address: Address,
+    /// Receives the call value if the ticket expires or is cancelled
+    pub call_value_refund_address: Address,
+    /// L2 gas limit of the automatic redemption
+    pub gas_limit: Uint256,
+    /// L2 gas price bid of the automatic redemption
+    pub max_fee_per_gas: Uint256,
+    pub data: Vec<u8>,
+}
+
+impl RetryableTicket {
+    /// The value to send along with `createRetryableTicket`, which covers
+    /// the submission, the gas of the automatic redemption and the call
+    /// value.
+    pub fn deposit(&self) -> Result<Uint256, Error> {
+        self.gas_limit
+            .checked_mul(&self.max_fee_per_gas)
+            .and_then(|gas| gas.checked_add(&self.max_submission_cost))
+            .and_then(|total| total.checked_add(&self.l2_call_value))
+            .ok_or_else(|| ArbitrumError::DepositOverflow.into())
+    }
+
+    /// Calldata of the `createRetryableTicket` call.
+    pub fn create_calldata(&self) -> Vec<u8> {
+        encode_call(
+            CREATE_RETRYABLE_TICKET,
+            &[
+                Token::Address(self.to),
+                Token::Uint(self.l2_call_value.clone()),
+                Token::Uint(self.max_submission_cost.clone()),
+                Token::Address(self.excess_fee_refund_address),
+                Token::Address(self.call_value_refund_address),
+                Token::Uint(self.gas_limit.clone()),
+                Token::Uint(self.max_fee_per_gas.clone()),
+                Token::UnboundedBytes(self.data.clone()),
+            ],
+        )
+    }
+}
+
+/// A retryable ticket as logged by `InboxMessageDelivered`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryableMessage {
+    pub message_number: Uint256,
+    /// The value that was sent along, see [deposit](struct.RetryableTicket.html#method.deposit)
+    pub deposit: Uint256,
+    pub ticket: RetryableTicket,
+}
+
+fn word_address(word: &[u8]) -> Result<Address, Error> {
+    if word[..12].iter().any(|&b| b != 0) {
+        return Err(ArbitrumError::MalformedMessage.into());
+    }
+    Address::from_slice(&word[12..])
+}
+
+impl RetryableMessage {
+    /// Decodes an `InboxMessageDelivered` log of a retryable ticket.
+    pub fn from_log(log: &Log) -> Result<RetryableMessage, Error> {
+        if log.topics.len() != 2 || log.topics[0] != derive_signature(INBOX_MESSAGE_DELIVERED) {
+            return Err(ArbitrumError::UnexpectedEvent(INBOX_MESSAGE_DELIVERED).into());
+        }
+        let message = match decode_tokens(&[ParamType::Bytes], &log.data)?.pop() {
+            Some(Token::UnboundedBytes(message)) => message,
+            _ => return Err(ArbitrumError::MalformedMessage.into()),
+        };
+        // The parameters are packed into words, followed by the length of
+        // the call data and the call data itself.
+        if message.len() < 9 * 32 {
+            return Err(ArbitrumError::MalformedMessage.into());
+        }
+        let word = |i: usize| &message[i * 32..(i + 1) * 32];
+        let uint = |i: usize| Uint256::from_bytes_be(word(i));
+        let data = &message[9 * 32..];
+        if uint(8) != data.len().into() {
+            return Err(ArbitrumError::MalformedMessage.into());
+        }
+        Ok(RetryableMessage {
+            message_number: Uint256::from_bytes_be(&log.topics[1]),
+            deposit: uint(2),
+            ticket: RetryableTicket {
+                to: word_address(word(0))?,
+                l2_call_value: uint(1),
+                max_submission_cost: uint(3),
+                excess_fee_refund_address: word_address(word(4))?,
+                call_value_refund_address: word_address(word(5))?,
+                gas_limit: uint(6),
+                max_fee_per_gas: uint(7),
+                data: data.to_vec(),
+            },
+        })
+    }
+}
+
+/// The part of a `MessageDelivered` log of the Bridge that the ticket id
+/// depends on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MessageDelivered {
+    pub message_index: Uint256,
+    pub kind: u8,
+    /// The sender of the message, aliased if it is a contract
+    pub sender: Address,
+    pub base_fee_l1: Uint256,
+}
+
+impl MessageDelivered {
+    pub fn from_log(log: &Log) -> Result<MessageDelivered, Error> {
+        if log.topics.len() != 3 || log.topics[0] != derive_signature(MESSAGE_DELIVERED) {
+            return Err(ArbitrumError::UnexpectedEvent(MESSAGE_DELIVERED).into());
+        }
+        let types = [
+            ParamType::Address,
+            ParamType::Uint(8),
+            ParamType::Address,
+            ParamType::FixedBytes(32),
+            ParamType::Uint(256),
+            ParamType::Uint(64),
+        ];
+        match decode_tokens(&types, &log.data)?[..] {
+            [_, Token::Uint(ref kind), Token::Address(sender), _, Token::Uint(ref base_fee_l1), _] =>
+            {
+                let kind: [u8; 32] = kind.clone().into();
+                Ok(MessageDelivered {
+                    message_index: Uint256::from_bytes_be(&log.topics[1]),
+                    kind: kind[31],
+                    sender,
+                    base_fee_l1: base_fee_l1.clone(),
+                })
+            }
+            _ => Err(ArbitrumError::MalformedMessage.into()),
+        }
+    }
+}
+
+/// The id of the ticket on L2, from the logs the Inbox and Bridge emitted
+/// for the same message.
+pub fn retryable_ticket_id(
+    l2_chain_id: u64,
+    message: &RetryableMessage,
+    delivered: &MessageDelivered,
+) -> Result<[u8; 32], Error> {
+    if delivered.kind != SUBMIT_RETRYABLE_KIND {
+        return Err(ArbitrumError::NotRetryable(delivered.kind).into());
+    }
+    let ticket = &message.ticket;
+    let message_number: [u8; 32] = message.message_number.clone().into();
+    let fields = (
+        &BigEndianInt(l2_chain_id.into()),
+        &ByteBuf::from(message_number.to_vec()),
+        &ByteBuf::from(delivered.sender.as_bytes().to_vec()),
+        &BigEndianInt(delivered.base_fee_l1.clone()),
+        &BigEndianInt(message.deposit.clone()),
+        &BigEndianInt(ticket.max_fee_per_gas.clone()),
+        &BigEndianInt(ticket.gas_limit.clone()),
+        &AddressDef(&ticket.to),
+        &BigEndianInt(ticket.l2_call_value.clone()),
+        &ByteBuf::from(ticket.call_value_refund_address.as_bytes().to_vec()),
+        &BigEndianInt(ticket.max_submission_cost.clone()),
+        &ByteBuf::from(ticket.excess_fee_refund_address.as_bytes().to_vec()),
+        &ByteBuf::from(ticket.data.clone()),
+    );
+    let mut encoded = vec![SUBMIT_RETRYABLE_TX_TYPE];
+    encoded.extend(to_bytes(&fields)?);
+    Ok(keccak256(&encoded))
+}
+
+#[cfg(test)]
+fn test_ticket() -> RetryableTicket {
+    RetryableTicket {
+        to: Address::from([0x11u8; 20]),
+        l2_call_value: 1_000u32.into(),
+        max_submission_cost: submission_fee(4, &30_000_000_000u64.into()),
+        excess_fee_refund_address: Address::from([0x22u8; 20]),
+        call_value_refund_address: Address::from([0x33u8; 20]),
+        gas_limit: 100_000u32.into(),
+        max_fee_per_gas: 100_000_000u32.into(),
+        data: vec![0xd0, 0x9d, 0xe0, 0x8a],
+    }
+}
+
+#[cfg(test)]
+fn inbox_log(message_number: u64, ticket: &RetryableTicket) -> Log {
+    use abi::encode_tokens;
+    let mut message = encode_tokens(&[
+        Token::Address(ticket.to),
+        Token::Uint(ticket.l2_call_value.clone()),
+        Token::Uint(ticket.deposit().unwrap()),
+        Token::Uint(ticket.max_submission_cost.clone()),
+        Token::Address(ticket.excess_fee_refund_address),
+        Token::Address(ticket.call_value_refund_address),
+        Token::Uint(ticket.gas_limit.clone()),
+        Token::Uint(ticket.max_fee_per_gas.clone()),
+        (ticket.data.len() as u64).into(),
+    ]);
+    message.extend_from_slice(&ticket.data);
+    let number: Uint256 = message_number.into();
+    Log {
+        address: Address::default(),
+        topics: vec![derive_signature(INBOX_MESSAGE_DELIVERED), number.into()],
+        data: encode_tokens(&[Token::UnboundedBytes(message)]),
+    }
+}
+
+#[test]
+fn create_ticket() {
+    use abi::derive_method_id;
+    use num_traits::Bounded;
+    let ticket = test_ticket();
+    assert_eq!(
+        ticket.max_submission_cost,
+        (1424u64 * 30_000_000_000u64).into()
+    );
+    assert_eq!(
+        ticket.deposit().unwrap(),
+        (42_720_000_000_000u64 + 10_000_000_000_000 + 1_000).into()
+    );
+    let calldata = ticket.create_calldata();
+    assert_eq!(calldata[..4], derive_method_id(CREATE_RETRYABLE_TICKET));
+    // Eight head words, then the length and a single word of data
+    assert_eq!(calldata.len(), 4 + 10 * 32);
+
+    let overflowing = RetryableTicket {
+        gas_limit: Uint256::max_value(),
+        ..ticket
+    };
+    assert!(overflowing.deposit().is_err());
+}
+
+#[test]
+fn ticket_id_from_logs() {
+    use abi::encode_tokens;
+    let ticket = test_ticket();
+    let message = RetryableMessage::from_log(&inbox_log(1_234, &ticket)).unwrap();
+    assert_eq!(message.ticket, ticket);
+    assert_eq!(message.message_number, 1_234u32.into());
+    assert_eq!(message.deposit, ticket.deposit().unwrap());
+
+    let index: Uint256 = 1_234u32.into();
+    let delivered_log = |kind: u8| Log {
+        address: Address::default(),
+        topics: vec![
+            derive_signature(MESSAGE_DELIVERED),
+            index.clone().into(),
+            [0u8; 32],
+        ],
+        data: encode_tokens(&[
+            Token::Address(Address::from([0x44u8; 20])),
+            kind.into(),
+            Token::Address(Address::from([0x55u8; 20])),
+            Token::Bytes(vec![0u8; 32]),
+            30_000_000_000u64.into(),
+            1_700_000_000u64.into(),
+        ]),
+    };
+    let delivered = MessageDelivered::from_log(&delivered_log(SUBMIT_RETRYABLE_KIND)).unwrap();
+    assert_eq!(delivered.sender, Address::from([0x55u8; 20]));
+    assert_eq!(delivered.message_index, message.message_number);
+
+    let id = retryable_ticket_id(42_161, &message, &delivered).unwrap();
+    assert_ne!(
+        retryable_ticket_id(421_614, &message, &delivered).unwrap(),
+        id
+    );
+    let eth_deposit = MessageDelivered::from_log(&delivered_log(12)).unwrap();
+    assert_eq!(
+        retryable_ticket_id(42_161, &message, &eth_deposit)
+            .unwrap_err()
+            .downcast::<ArbitrumError>()
+            .unwrap(),
+        ArbitrumError::NotRetryable(12)
+    );
+
+    // Logs of other events are rejected
+    assert_eq!(
+        RetryableMessage::from_log(&delivered_log(SUBMIT_RETRYABLE_KIND))
+            .unwrap_err()
+            .downcast::<ArbitrumError>()
+            .unwrap(),
+        ArbitrumError::UnexpectedEvent(INBOX_MESSAGE_DELIVERED)
+    );
+    let mut truncated = inbox_log(1_234, &ticket);
+    truncated.data = encode_tokens(&[Token::UnboundedBytes(vec![0u8; 64])]);
+    assert!(RetryableMessage::from_log(&truncated).is_err());
+}
+
+#[test]
+fn ticket_id_of_nitro() {
+    use utils::hex_str_to_bytes32;
+    // The hash vector of SubmitRetryableTx in the types of Nitro, as
+    // reproduced by arbitrum-alloy-consensus
+    let message = RetryableMessage {
+        message_number: Uint256::from_bytes_be(&[0xaa; 32]),
+        deposit: 200u32.into(),
+        ticket: RetryableTicket {
+            to: Address::from([0x22u8; 20]),
+            l2_call_value: 50u32.into(),
+            max_submission_cost: 600u32.into(),
+            excess_fee_refund_address: Address::from([0x55u8; 20]),
+            call_value_refund_address: Address::from([0x44u8; 20]),
+            gas_limit: 400_000u32.into(),
+            max_fee_per_gas: 300u32.into(),
+            data: vec![0x01, 0x02, 0x03, 0x04],
+        },
+    };
+    let delivered = MessageDelivered {
+        message_index: message.message_number.clone(),
+        kind: SUBMIT_RETRYABLE_KIND,
+        sender: Address::from([0x11u8; 20]),
+        base_fee_l1: 100u32.into(),
+    };
+    assert_eq!(
+        retryable_ticket_id(42_161, &message, &delivered).unwrap(),
+        hex_str_to_bytes32("0x7c21633a95b29d2af5bf71d2efe4f36352e5aa8cf1980c968245d4b1069ad95e")
+            .unwrap()
+    );
+}
@@ -0,0 +1,360 @@
+//! Planning `eth_getLogs` backfills.
+//!
+//! Providers limit both the block range of a single `eth_getLogs` query and
+//! the number of logs it may return. [BackfillPlanner](struct.BackfillPlanner.html)
+//! splits a block range into queries that respect those limits, bisecting
+//! any query the provider rejects and adapting its window size to how dense
+//! the logs turn out to be.
+//!
+//! Clarity does not make the queries itself. Call
+//! [next_query](struct.BackfillPlanner.html#method.next_query), run it, and
+//! [report](struct.BackfillPlanner.html#method.report) the outcome.
+use failure::Error;
+use std::cmp;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum BackfillError {
+    #[fail(display = "Block range ends before it starts")]
+    InvalidRange,
+    #[fail(display = "Maximum block range should be at least 1")]
+    ZeroRangeLimit,
+    #[fail(display = "Block {} alone has too many logs to query", _0)]
+    CannotSplit(u64),
+    #[fail(display = "An outcome was reported for a query that is not in flight")]
+    UnknownQuery,
+}
+
+/// An inclusive range of blocks, as used by `fromBlock` and `toBlock`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockRange {
+    pub from: u64,
+    pub to: u64,
+}
+
+impl BlockRange {
+    /// Number of blocks in the range.
+    pub fn blocks(&self) -> u64 {
+        self.to - self.from + 1
+    }
+
+    /// Splits the range in two halves, or returns `None` for a single block.
+    pub fn bisect(&self) -> Option<(BlockRange, BlockRange)> {
+        if self.from == self.to {
+            return None;
+        }
+        let middle = self.from + (self.to - self.from) / 2;
+        Some((
+            BlockRange {
+                from: self.from,
+                to: middle,
+            },
+            BlockRange {
+                from: middle + 1,
+                to: self.to,
+            },
+        ))
+    }
+}
+
+/// Limits imposed by the provider.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LogLimits {
+    /// Largest number of blocks a single query may cover
+    pub max_block_range: u64,
+    /// Largest number of logs a single query may return, if known. The
+    /// window is only grown back while queries return well under it.
+    pub max_results: Option<usize>,
+}
+
+/// What happened to a query.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryOutcome {
+    /// The query succeeded and returned this many logs
+    Success(usize),
+    /// The provider refused the query because it matches too many logs
+    TooManyResults,
+    /// The provider refused the query because its block range is too large,
+    /// i.e. the configured limit was wrong
+    RangeTooLarge,
+}
+
+impl QueryOutcome {
+    /// Recognizes the error messages popular providers and clients use for
+    /// refused `eth_getLogs` queries.
+    pub fn from_error_message(message: &str) -> Option<QueryOutcome> {
+        let message = message.to_lowercase();
+        let too_many = [
+            "more than",
+            "too many",
+            "response size exceeded",
+            "response size should not",
+            "limit exceeded",
+        ];
+        let too_large = ["block range", "range is too", "range too"];
+        if too_large.iter().any(|pattern| message.contains(pattern)) {
+            Some(QueryOutcome::RangeTooLarge)
+        } else if too_many.iter().any(|pattern| message.contains(pattern)) {
+            Some(QueryOutcome::TooManyResults)
+        } else {
+            None
+        }
+    }
+}
+
+/// Splits a block range into `eth_getLogs` queries.
+///
+/// Queries are handed out in block order, and several can be in flight at
+/// the same time. A refused query is split in two, and both halves are
+/// handed out before anything after them.
+#[derive(Debug, Clone)]
+pub struct BackfillPlanner {
+    limits: LogLimits,
+    /// Current window size, adapted to the density of logs
+    window: u64,
+    /// First block that has not been handed out yet
+    cursor: u64,
+    end: u64,
+    exhausted: bool,
+    /// Refused queries that were split, to be handed out first. The next
+    /// one is at the end.
+    retries: Vec<BlockRange>,
+    in_flight: Vec<BlockRange>,
+}
+
+impl BackfillPlanner {
+    pub fn new(range: BlockRange, limits: LogLimits) -> Result<BackfillPlanner, Error> {
+        if range.to < range.from {
+            return Err(BackfillError::InvalidRange.into());
+        }
+        if limits.max_block_range == 0 {
+            return Err(BackfillError::ZeroRangeLimit.into());
+        }
+        Ok(BackfillPlanner {
+            limits,
+            window: limits.max_block_range,
+            cursor: range.from,
+            end: range.to,
+            exhausted: false,
+            retries: Vec::new(),
+            in_flight: Vec::new(),
+        })
+    }
+
+    /// The next query to run, or `None` when every block has been handed
+    /// out. More queries can become available once outcomes are reported.
+    pub fn next_query(&mut self) -> Option<BlockRange> {
+        let query = if let Some(retry) = self.retries.pop() {
+            retry
+        } else if self.exhausted {
+            return None;
+        } else {
+            let to = cmp::min(self.cursor.saturating_add(self.window - 1), self.end);
+            let query = BlockRange {
+                from: self.cursor,
+                to,
+            };
+            if to == self.end {
+                self.exhausted = true;
+            } else {
+                self.cursor = to + 1;
+            }
+            query
+        };
+        self.in_flight.push(query);
+        Some(query)
+    }
+
+    /// Reports the outcome of a query returned by
+    /// [next_query](#method.next_query).
+    pub fn report(&mut self, query: BlockRange, outcome: QueryOutcome) -> Result<(), Error> {
+        let position = self
+            .in_flight
+            .iter()
+            .position(|q| *q == query)
+            .ok_or(BackfillError::UnknownQuery)?;
+        match outcome {
+            QueryOutcome::Success(results) => {
+                self.in_flight.swap_remove(position);
+                if let Some(max_results) = self.limits.max_results {
+                    // Grow the window while results stay well under the cap
+                    if results < max_results / 4 && query.blocks() >= self.window {
+                        self.window =
+                            cmp::min(self.window.saturating_mul(2), self.limits.max_block_range);
+                    }
+                }
+            }
+            QueryOutcome::TooManyResults | QueryOutcome::RangeTooLarge => {
+                let (first, second) = query
+                    .bisect()
+                    .ok_or(BackfillError::CannotSplit(query.from))?;
+                self.in_flight.swap_remove(position);
+                self.window = cmp::max(first.blocks(), 1);
+                if outcome == QueryOutcome::RangeTooLarge {
+                    self.limits.max_block_range =
+                        cmp::min(self.limits.max_block_range, first.blocks());
+                }
+                self.retries.push(second);
+                self.retries.push(first);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether every block has been queried successfully.
+    pub fn is_done(&self) -> bool {
+        self.exhausted && self.retries.is_empty() && self.in_flight.is_empty()
+    }
+}
+
+#[cfg(test)]
+fn range(from: u64, to: u64) -> BlockRange {
+    BlockRange { from, to }
+}
+
+#[test]
+fn splits_by_block_range() {
+    let mut planner = BackfillPlanner::new(
+        range(100, 349),
+        LogLimits {
+            max_block_range: 100,
+            max_results: None,
+        },
+    )
+    .unwrap();
+    let mut queries = Vec::new();
+    while let Some(query) = planner.next_query() {
+        queries.push(query);
+        planner.report(query, QueryOutcome::Success(10)).unwrap();
+    }
+    assert_eq!(
+        queries,
+        vec![range(100, 199), range(200, 299), range(300, 349)]
+    );
+    assert!(planner.is_done());
+}
+
+#[test]
+fn bisects_refused_queries() {
+    let mut planner = BackfillPlanner::new(
+        range(0, 999),
+        LogLimits {
+            max_block_range: 1000,
+            max_results: Some(10_000),
+        },
+    )
+    .unwrap();
+    let first = planner.next_query().unwrap();
+    assert_eq!(first, range(0, 999));
+    planner.report(first, QueryOutcome::TooManyResults).unwrap();
+    let left = planner.next_query().unwrap();
+    assert_eq!(left, range(0, 499));
+    planner.report(left, QueryOutcome::TooManyResults).unwrap();
+    let mut queries = Vec::new();
+    while let Some(query) = planner.next_query() {
+        queries.push(query);
+        planner.report(query, QueryOutcome::Success(6_000)).unwrap();
+    }
+    // Blocks are still covered in order, with the smaller window kept
+    assert_eq!(
+        queries,
+        vec![range(0, 249), range(250, 499), range(500, 999)]
+    );
+    assert!(planner.is_done());
+}
+
+#[test]
+fn adapts_window_to_density() {
+    let mut planner = BackfillPlanner::new(
+        range(0, 10_000),
+        LogLimits {
+            max_block_range: 2_000,
+            max_results: Some(10_000),
+        },
+    )
+    .unwrap();
+    let first = planner.next_query().unwrap();
+    planner.report(first, QueryOutcome::TooManyResults).unwrap();
+    let half = planner.next_query().unwrap();
+    assert_eq!(half.blocks(), 1_000);
+    // Sparse results make the window grow again, up to the limit
+    planner.report(half, QueryOutcome::Success(10)).unwrap();
+    let other_half = planner.next_query().unwrap();
+    planner
+        .report(other_half, QueryOutcome::Success(10))
+        .unwrap();
+    let next = planner.next_query().unwrap();
+    assert_eq!(next, range(2_000, 3_999));
+}
+
+#[test]
+fn range_too_large_lowers_limit() {
+    let mut planner = BackfillPlanner::new(
+        range(0, 99),
+        LogLimits {
+            max_block_range: 100,
+            max_results: Some(10_000),
+        },
+    )
+    .unwrap();
+    let query = planner.next_query().unwrap();
+    planner.report(query, QueryOutcome::RangeTooLarge).unwrap();
+    let mut queries = Vec::new();
+    while let Some(query) = planner.next_query() {
+        queries.push(query);
+        planner.report(query, QueryOutcome::Success(0)).unwrap();
+    }
+    assert_eq!(queries, vec![range(0, 49), range(50, 99)]);
+}
+
+#[test]
+fn planner_errors() {
+    let limits = LogLimits {
+        max_block_range: 10,
+        max_results: None,
+    };
+    assert!(BackfillPlanner::new(range(10, 9), limits).is_err());
+    assert!(BackfillPlanner::new(
+        range(0, 9),
+        LogLimits {
+            max_block_range: 0,
+            max_results: None
+        }
+    )
+    .is_err());
+    let mut planner = BackfillPlanner::new(range(5, 5), limits).unwrap();
+    let query = planner.next_query().unwrap();
+    assert_eq!(
+        planner
+            .report(query, QueryOutcome::TooManyResults)
+            .unwrap_err()
+            .downcast::<BackfillError>()
+            .unwrap(),
+        BackfillError::CannotSplit(5)
+    );
+    assert_eq!(
+        planner
+            .report(range(0, 1), QueryOutcome::Success(0))
+            .unwrap_err()
+            .downcast::<BackfillError>()
+            .unwrap(),
+        BackfillError::UnknownQuery
+    );
+    assert!(!planner.is_done());
+}
+
+#[test]
+fn classify_provider_errors() {
+    assert_eq!(
+        QueryOutcome::from_error_message("query returned more than 10000 results"),
+        Some(QueryOutcome::TooManyResults)
+    );
+    assert_eq!(
+        QueryOutcome::from_error_message("Log response size exceeded."),
+        Some(QueryOutcome::TooManyResults)
+    );
+    assert_eq!(
+        QueryOutcome::from_error_message("eth_getLogs block range is too large"),
+        Some(QueryOutcome::RangeTooLarge)
+    );
+    assert_eq!(QueryOutcome::from_error_message("execution reverted"), None);
+}
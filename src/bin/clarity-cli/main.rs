@@ -0,0 +1,224 @@
+//! Offline decoding and signing of transactions, for machines that have no
+//! node to talk to.
+//!
+//! ```text
+//! clarity-cli decode-tx <raw tx>
+//! clarity-cli recover-sender <raw tx>
+//! clarity-cli encode-call <signature> [<arg>...]
+//! clarity-cli sign-tx <unsigned tx> --keystore <file> [--password-file <file>] [--chain-id <id>]
+//! ```
+//!
+//! Transactions are given and printed in hex, as sent with
+//! `eth_sendRawTransaction`; an unsigned transaction is encoded with a zero
+//! signature. Call arguments are given as text: integers in decimal or `0x`
+//! hex, byte strings in hex, and arrays as comma separated lists. Without
+//! `--password-file` the keystore password is read from the first line of
+//! standard input.
+extern crate clarity;
+#[macro_use]
+extern crate failure;
+extern crate num256;
+
+use clarity::abi::{encode_call, parse_param_types, ParamType, Token};
+use clarity::explain::explain;
+use clarity::keystore::Keystore;
+use clarity::utils::{bytes_to_hex_str, hex_str_to_bytes};
+use clarity::{Address, TypedTransaction};
+use failure::Error;
+use num256::Uint256;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::process;
+
+const USAGE: &str = "Usage:
+    clarity-cli decode-tx <raw tx>
+    clarity-cli recover-sender <raw tx>
+    clarity-cli encode-call <signature> [<arg>...]
+    clarity-cli sign-tx <unsigned tx> --keystore <file> [--password-file <file>] [--chain-id <id>]";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    match run(&args) {
+        Ok(output) => println!("{}", output),
+        Err(e) => {
+            eprintln!("error: {}", e);
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs a subcommand, returning what it prints.
+fn run(args: &[String]) -> Result<String, Error> {
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => bail!("{}", USAGE),
+    };
+    match (command, args) {
+        ("decode-tx", [raw]) => decode_tx(raw),
+        ("recover-sender", [raw]) => {
+            let tx = TypedTransaction::from_bytes(&hex_str_to_bytes(raw)?)?;
+            Ok(tx.sender()?.to_string())
+        }
+        ("encode-call", [signature, call_args @ ..]) => {
+            let types = parse_param_types(signature)?;
+            ensure!(
+                types.len() == call_args.len(),
+                "{} takes {} arguments but {} were given",
+                signature,
+                types.len(),
+                call_args.len()
+            );
+            let tokens = types
+                .iter()
+                .zip(call_args)
+                .map(|(kind, arg)| parse_token(kind, arg))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(format!(
+                "0x{}",
+                bytes_to_hex_str(&encode_call(signature, &tokens))
+            ))
+        }
+        ("sign-tx", [raw, options @ ..]) => sign_tx(raw, options),
+        ("help", []) | ("--help", []) => Ok(USAGE.to_owned()),
+        _ => bail!("{}", USAGE),
+    }
+}
+
+fn decode_tx(raw: &str) -> Result<String, Error> {
+    let raw = hex_str_to_bytes(raw)?;
+    let tx = TypedTransaction::from_bytes(&raw)?;
+    let mut output = format!("{}\n", explain(&raw, None, &[])?);
+    for issue in tx.validity_report(None).issues {
+        output += &format!("issue: {}\n", issue);
+    }
+    output += &format!("{:#?}", tx);
+    Ok(output)
+}
+
+fn sign_tx(raw: &str, options: &[String]) -> Result<String, Error> {
+    let mut keystore = None;
+    let mut password_file = None;
+    let mut chain_id = None;
+    for option in options.chunks(2) {
+        match option {
+            [name, value] if name == "--keystore" => keystore = Some(value),
+            [name, value] if name == "--password-file" => password_file = Some(value),
+            [name, value] if name == "--chain-id" => chain_id = Some(value.parse::<u64>()?),
+            _ => bail!("{}", USAGE),
+        }
+    }
+    let keystore = keystore.ok_or_else(|| format_err!("--keystore is required"))?;
+    let tx = TypedTransaction::from_bytes(&hex_str_to_bytes(raw)?)?;
+    ensure!(tx.signature().is_none(), "Transaction is already signed");
+    // A legacy transaction signed without a chain id can be replayed on
+    // every chain
+    ensure!(
+        tx.tx_type() != 0 || chain_id.is_some(),
+        "Legacy transactions need --chain-id"
+    );
+
+    let password = match password_file {
+        Some(path) => fs::read_to_string(path)?,
+        None => {
+            let mut line = String::new();
+            io::stdin().lock().read_line(&mut line)?;
+            line
+        }
+    };
+    let password = password.trim_end_matches(&['\r', '\n'][..]);
+    let key = Keystore::from_json(&fs::read_to_string(keystore)?)?.decrypt(password)?;
+    let signed = tx.sign(&key, chain_id)?;
+    Ok(format!("0x{}", bytes_to_hex_str(&signed.to_bytes()?)))
+}
+
+/// Parses a call argument of type `kind`.
+fn parse_token(kind: &ParamType, arg: &str) -> Result<Token, Error> {
+    Ok(match *kind {
+        ParamType::Uint(bits) => {
+            let value: Uint256 = arg.parse()?;
+            ensure!(value.bits() <= bits, "{} does not fit {}", arg, kind);
+            Token::Uint(value)
+        }
+        ParamType::Address => Token::Address(arg.parse::<Address>()?),
+        ParamType::Bool => match arg {
+            "true" => Token::Bool(true),
+            "false" => Token::Bool(false),
+            _ => bail!("{} is not a bool", arg),
+        },
+        ParamType::String => Token::String(arg.to_owned()),
+        ParamType::FixedBytes(size) => {
+            let bytes = hex_str_to_bytes(arg)?;
+            ensure!(bytes.len() == size, "{} is not {} bytes", arg, size);
+            Token::Bytes(bytes)
+        }
+        ParamType::Bytes => Token::UnboundedBytes(hex_str_to_bytes(arg)?),
+        ParamType::Array(ref inner) => Token::Dynamic(
+            arg.split(',')
+                .filter(|item| !item.is_empty())
+                .map(|item| parse_token(inner, item))
+                .collect::<Result<_, _>>()?,
+        ),
+        ref other => bail!("{} arguments are not supported", other),
+    })
+}
+
+#[test]
+fn encode_transfer() {
+    let args: Vec<String> = vec![
+        "encode-call".into(),
+        "transfer(address,uint256)".into(),
+        "0x1111111111111111111111111111111111111111".into(),
+        "0x10".into(),
+    ];
+    assert_eq!(
+        run(&args).unwrap(),
+        concat!(
+            "0xa9059cbb",
+            "0000000000000000000000001111111111111111111111111111111111111111",
+            "0000000000000000000000000000000000000000000000000000000000000010"
+        )
+    );
+    let args: Vec<String> = vec!["encode-call".into(), "f(uint8)".into(), "256".into()];
+    assert!(run(&args).is_err());
+}
+
+#[test]
+fn decode_and_recover() {
+    use clarity::{PrivateKey, Transaction};
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let tx = Transaction {
+        nonce: 1u32.into(),
+        gas_price: 1_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to: "0x1111111111111111111111111111111111111111"
+            .parse()
+            .unwrap(),
+        value: 100u32.into(),
+        data: Vec::new(),
+        signature: None,
+    };
+    let raw = format!(
+        "0x{}",
+        bytes_to_hex_str(&tx.sign(&key, Some(1)).to_bytes().unwrap())
+    );
+    let sender = run(&["recover-sender".to_owned(), raw.clone()]).unwrap();
+    assert_eq!(sender, key.to_public_key().unwrap().to_string());
+    let decoded = run(&["decode-tx".to_owned(), raw]).unwrap();
+    assert!(decoded.starts_with("type 0 transaction 0x"));
+    assert!(decoded.contains(&format!("\nfrom {}\n", sender)));
+
+    let unsigned = format!("0x{}", bytes_to_hex_str(&tx.to_bytes().unwrap()));
+    let decoded = run(&["decode-tx".to_owned(), unsigned.clone()]).unwrap();
+    assert!(decoded.contains("\nnot signed\n"));
+    // Legacy transactions are only signed for a chain
+    let args = vec![
+        "sign-tx".to_owned(),
+        unsigned,
+        "--keystore".to_owned(),
+        "/nonexistent".to_owned(),
+    ];
+    assert!(run(&args).is_err());
+}
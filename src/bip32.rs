@@ -0,0 +1,495 @@
+//! BIP-32 hierarchical deterministic keys.
+//!
+//! Wallets derive all of their accounts from one seed, along the paths of
+//! [hd_path](../hd_path/index.html). An
+//! [ExtendedPrivateKey](struct.ExtendedPrivateKey.html) is a key together
+//! with the chain code its children are derived with:
+//!
+//! ```rust
+//! use clarity::bip32::ExtendedPrivateKey;
+//!
+//! // The seed of a BIP-39 mnemonic, or any 16 to 64 random bytes
+//! let seed = [0x42u8; 64];
+//! let account = ExtendedPrivateKey::derive_path(&seed, "m/44'/60'/0'/0/3").unwrap();
+//! let address = account.private_key().to_public_key().unwrap();
+//! ```
+//!
+//! An [ExtendedPublicKey](struct.ExtendedPublicKey.html), such as the
+//! `xpub` of an account that a wallet exports, derives the addresses of
+//! the non-hardened levels below it without any private key, for services
+//! that only watch deposits:
+//!
+//! ```rust
+//! use clarity::bip32::ExtendedPublicKey;
+//! use clarity::hd_path::ChildNumber;
+//!
+//! let xpub: ExtendedPublicKey = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw"
+//!     .parse()
+//!     .unwrap();
+//! let deposit = xpub.derive_child(ChildNumber::normal(3)).unwrap().address();
+//! ```
+//!
+//! Extended keys are not serialized as `xprv` or `xpub` strings, which
+//! would take the RIPEMD-160 hash of the parent key.
+use address::Address;
+use context::SECP256K1;
+use failure::Error;
+use hash::keccak256;
+use hd_path::{ChildNumber, DerivationPath};
+use hmac::{Hmac, Mac, NewMac};
+use private_key::PrivateKey;
+use secp256k1::{PublicKey, SecretKey};
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt;
+use std::str::FromStr;
+
+/// The key of the HMAC that derives a master key from a seed.
+const MASTER_KEY: &[u8] = b"Bitcoin seed";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum Bip32Error {
+    #[fail(display = "Seed of {} bytes is not between 16 and 64 bytes", _0)]
+    InvalidSeedLength(usize),
+    #[fail(display = "Child {} is not a valid key, use the next index", _0)]
+    InvalidChild(ChildNumber),
+    #[fail(display = "Path is too deep")]
+    TooDeep,
+    #[fail(display = "Hardened child {} can not be derived from a public key", _0)]
+    HardenedFromPublic(ChildNumber),
+    #[fail(display = "Invalid Base58 character {:?}", _0)]
+    InvalidBase58(char),
+    #[fail(display = "Invalid Base58Check checksum")]
+    InvalidChecksum,
+    #[fail(display = "Extended key of {} bytes is not 78 bytes", _0)]
+    InvalidLength(usize),
+    #[fail(display = "Unknown extended public key version {:08x}", _0)]
+    UnknownVersion(u32),
+    #[fail(display = "Invalid public key in extended key")]
+    InvalidPublicKey,
+}
+
+/// Version bytes of mainnet extended public keys, `xpub`.
+const XPUB_VERSION: u32 = 0x0488_b21e;
+/// Version bytes of testnet extended public keys, `tpub`.
+const TPUB_VERSION: u32 = 0x0435_87cf;
+
+/// A private key and chain code, at some depth of a tree of keys.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExtendedPrivateKey {
+    key: PrivateKey,
+    chain_code: [u8; 32],
+    depth: u8,
+    child_number: Option<ChildNumber>,
+}
+
+impl ExtendedPrivateKey {
+    /// The master key of a seed, which BIP-32 allows to be 16 to 64 bytes.
+    pub fn master(seed: &[u8]) -> Result<ExtendedPrivateKey, Error> {
+        if seed.len() < 16 || seed.len() > 64 {
+            return Err(Bip32Error::InvalidSeedLength(seed.len()).into());
+        }
+        let (key, chain_code) = hmac_sha512(MASTER_KEY, &[seed]);
+        // Fails when the key is zero or not below the curve order, for
+        // which BIP-32 has no remedy but another seed
+        SecretKey::from_slice(&key)?;
+        Ok(ExtendedPrivateKey {
+            key: PrivateKey::from(key),
+            chain_code,
+            depth: 0,
+            child_number: None,
+        })
+    }
+
+    /// The key at `path` below the master key of `seed`, with the path in
+    /// the form `m/44'/60'/0'/0/3`.
+    pub fn derive_path(seed: &[u8], path: &str) -> Result<ExtendedPrivateKey, Error> {
+        let path: DerivationPath = path.parse()?;
+        ExtendedPrivateKey::master(seed)?.derive(&path)
+    }
+
+    /// The key at `path` below this one.
+    pub fn derive(&self, path: &DerivationPath) -> Result<ExtendedPrivateKey, Error> {
+        let mut key = self.clone();
+        for child in &path.0 {
+            key = key.derive_child(*child)?;
+        }
+        Ok(key)
+    }
+
+    /// The child key at `child`.
+    ///
+    /// Fails, with a probability below 2^-127, when the child is not a
+    /// valid key. BIP-32 then has wallets skip to the next index.
+    pub fn derive_child(&self, child: ChildNumber) -> Result<ExtendedPrivateKey, Error> {
+        let depth = self.depth.checked_add(1).ok_or(Bip32Error::TooDeep)?;
+        let mut secret = SecretKey::from_slice(&self.key.to_bytes())?;
+        let index = child.to_u32().to_be_bytes();
+        let (tweak, chain_code) = if child.hardened {
+            hmac_sha512(&self.chain_code, &[&[0u8], &secret[..], &index])
+        } else {
+            let public = SECP256K1
+                .with(|object| PublicKey::from_secret_key(&object.borrow(), &secret).serialize());
+            hmac_sha512(&self.chain_code, &[&public, &index])
+        };
+        // Adds the tweak modulo the curve order, refusing a tweak that is
+        // not below it or a sum of zero
+        secret
+            .add_assign(&tweak)
+            .map_err(|_| Bip32Error::InvalidChild(child))?;
+        let mut key = [0u8; 32];
+        key.copy_from_slice(&secret[..]);
+        Ok(ExtendedPrivateKey {
+            key: PrivateKey::from(key),
+            chain_code,
+            depth,
+            child_number: Some(child),
+        })
+    }
+
+    pub fn private_key(&self) -> &PrivateKey {
+        &self.key
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    /// How many derivations away from the master key this key is.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// The last derivation step, `None` for the master key.
+    pub fn child_number(&self) -> Option<ChildNumber> {
+        self.child_number
+    }
+
+    /// The public key and chain code, which derive the same public keys
+    /// below this key as long as no level is hardened.
+    pub fn to_extended_public_key(&self) -> ExtendedPublicKey {
+        // The key was checked to be valid when it was derived
+        let secret = SecretKey::from_slice(&self.key.to_bytes()).unwrap();
+        ExtendedPublicKey {
+            key: SECP256K1.with(|object| PublicKey::from_secret_key(&object.borrow(), &secret)),
+            chain_code: self.chain_code,
+            depth: self.depth,
+            child_number: self.child_number,
+        }
+    }
+}
+
+impl fmt::Debug for ExtendedPrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Neither the key nor the chain code, which together give away
+        // every key below this one
+        f.debug_struct("ExtendedPrivateKey")
+            .field("depth", &self.depth)
+            .field("child_number", &self.child_number)
+            .finish()
+    }
+}
+
+/// A public key and chain code, which derive the public keys of the
+/// non-hardened children of the private key they belong to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedPublicKey {
+    key: PublicKey,
+    chain_code: [u8; 32],
+    depth: u8,
+    child_number: Option<ChildNumber>,
+}
+
+impl ExtendedPublicKey {
+    /// The key at `path` below this one, which can not have hardened
+    /// levels.
+    pub fn derive(&self, path: &DerivationPath) -> Result<ExtendedPublicKey, Error> {
+        let mut key = *self;
+        for child in &path.0 {
+            key = key.derive_child(*child)?;
+        }
+        Ok(key)
+    }
+
+    /// The child key at `child`, which can not be hardened.
+    pub fn derive_child(&self, child: ChildNumber) -> Result<ExtendedPublicKey, Error> {
+        if child.hardened {
+            return Err(Bip32Error::HardenedFromPublic(child).into());
+        }
+        let depth = self.depth.checked_add(1).ok_or(Bip32Error::TooDeep)?;
+        let index = child.to_u32().to_be_bytes();
+        let (tweak, chain_code) = hmac_sha512(&self.chain_code, &[&self.key.serialize(), &index]);
+        let mut key = self.key;
+        // Adds the tweak times the generator, refusing a tweak that is not
+        // below the curve order or a sum at infinity
+        SECP256K1
+            .with(|object| key.add_exp_assign(&object.borrow(), &tweak))
+            .map_err(|_| Bip32Error::InvalidChild(child))?;
+        Ok(ExtendedPublicKey {
+            key,
+            chain_code,
+            depth,
+            child_number: Some(child),
+        })
+    }
+
+    /// The address of the public key.
+    pub fn address(&self) -> Address {
+        let public = self.key.serialize_uncompressed();
+        // The hash is 32 bytes
+        Address::from_slice(&keccak256(&public[1..])[12..]).unwrap()
+    }
+
+    /// The public key in its compressed form.
+    pub fn public_key(&self) -> [u8; 33] {
+        self.key.serialize()
+    }
+
+    pub fn chain_code(&self) -> &[u8; 32] {
+        &self.chain_code
+    }
+
+    /// How many derivations away from the master key this key is.
+    pub fn depth(&self) -> u8 {
+        self.depth
+    }
+
+    /// The last derivation step, `None` for the master key.
+    pub fn child_number(&self) -> Option<ChildNumber> {
+        self.child_number
+    }
+}
+
+impl FromStr for ExtendedPublicKey {
+    type Err = Error;
+
+    /// Parses an `xpub` or `tpub` string. The fingerprint of the parent
+    /// is not kept, as it is only needed to serialize the key again.
+    fn from_str(s: &str) -> Result<ExtendedPublicKey, Error> {
+        let bytes = base58check_decode(s)?;
+        if bytes.len() != 78 {
+            return Err(Bip32Error::InvalidLength(bytes.len()).into());
+        }
+        let u32_at =
+            |i: usize| u32::from_be_bytes([bytes[i], bytes[i + 1], bytes[i + 2], bytes[i + 3]]);
+        let version = u32_at(0);
+        // An xprv or tprv is refused as any other version
+        if version != XPUB_VERSION && version != TPUB_VERSION {
+            return Err(Bip32Error::UnknownVersion(version).into());
+        }
+        let depth = bytes[4];
+        let number = u32_at(9);
+        let child_number = match depth {
+            0 => None,
+            _ => Some(ChildNumber {
+                index: number & 0x7fff_ffff,
+                hardened: number & 0x8000_0000 != 0,
+            }),
+        };
+        let mut chain_code = [0u8; 32];
+        chain_code.copy_from_slice(&bytes[13..45]);
+        if bytes[45] != 0x02 && bytes[45] != 0x03 {
+            return Err(Bip32Error::InvalidPublicKey.into());
+        }
+        let key = PublicKey::from_slice(&bytes[45..]).map_err(|_| Bip32Error::InvalidPublicKey)?;
+        Ok(ExtendedPublicKey {
+            key,
+            chain_code,
+            depth,
+            child_number,
+        })
+    }
+}
+
+const BASE58_ALPHABET: &[u8] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+/// Decodes Base58 and checks the first four bytes of the double SHA-256
+/// hash that follow the payload.
+fn base58check_decode(s: &str) -> Result<Vec<u8>, Bip32Error> {
+    // Little endian digits of base 256
+    let mut bytes: Vec<u8> = Vec::new();
+    for c in s.chars() {
+        let digit = BASE58_ALPHABET
+            .iter()
+            .position(|a| *a as char == c)
+            .ok_or(Bip32Error::InvalidBase58(c))?;
+        let mut carry = digit as u32;
+        for byte in &mut bytes {
+            carry += *byte as u32 * 58;
+            *byte = carry as u8;
+            carry >>= 8;
+        }
+        while carry > 0 {
+            bytes.push(carry as u8);
+            carry >>= 8;
+        }
+    }
+    // Each leading '1' is a leading zero byte
+    let zeros = s.bytes().take_while(|b| *b == b'1').count();
+    bytes.extend(std::iter::repeat_n(0, zeros));
+    bytes.reverse();
+    if bytes.len() < 4 {
+        return Err(Bip32Error::InvalidChecksum);
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    if Sha256::digest(&Sha256::digest(payload))[..4] != *checksum {
+        return Err(Bip32Error::InvalidChecksum);
+    }
+    Ok(payload.to_vec())
+}
+
+/// HMAC-SHA512 of the concatenation of `data`, split into its halves.
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = Hmac::<Sha512>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for part in data {
+        mac.update(part);
+    }
+    let output = mac.finalize().into_bytes();
+    let mut left = [0u8; 32];
+    let mut right = [0u8; 32];
+    left.copy_from_slice(&output[..32]);
+    right.copy_from_slice(&output[32..]);
+    (left, right)
+}
+
+#[cfg(test)]
+fn check_vector(seed: &str, path: &str, key: &str, chain_code: &str) {
+    use utils::{bytes_to_hex_str, hex_str_to_bytes};
+    let seed = hex_str_to_bytes(seed).unwrap();
+    let derived = ExtendedPrivateKey::derive_path(&seed, path).unwrap();
+    assert_eq!(
+        bytes_to_hex_str(&derived.private_key().to_bytes()),
+        key,
+        "{}",
+        path
+    );
+    assert_eq!(
+        bytes_to_hex_str(derived.chain_code()),
+        chain_code,
+        "{}",
+        path
+    );
+}
+
+#[test]
+fn bip32_test_vector_1() {
+    let seed = "000102030405060708090a0b0c0d0e0f";
+    check_vector(
+        seed,
+        "m",
+        "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35",
+        "873dff81c02f525623fd1fe5167eac3a55a049de3d314bb42ee227ffed37d508",
+    );
+    check_vector(
+        seed,
+        "m/0'",
+        "edb2e14f9ee77d26dd93b4ecede8d16ed408ce149b6cd80b0715a2d911a0afea",
+        "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141",
+    );
+    check_vector(
+        seed,
+        "m/0'/1",
+        "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368",
+        "2a7857631386ba23dacac34180dd1983734e444fdbf774041578e9b6adb37c19",
+    );
+    check_vector(
+        seed,
+        "m/0'/1/2'/2/1000000000",
+        "471b76e389e528d6de6d816857e012c5455051cad6660850e58372a6c3e6e7c8",
+        "c783e67b921d2beb8f6b389cc646d7263b4145701dadd2161548a8b078e65e9e",
+    );
+}
+
+#[test]
+fn derive_child_by_child() {
+    let seed = [0x42u8; 32];
+    let path: DerivationPath = "m/44'/60'/0'/0/3".parse().unwrap();
+    let master = ExtendedPrivateKey::master(&seed).unwrap();
+    let mut key = master.clone();
+    for child in &path.0 {
+        key = key.derive_child(*child).unwrap();
+    }
+    assert_eq!(key, master.derive(&path).unwrap());
+    assert_eq!(
+        key,
+        ExtendedPrivateKey::derive_path(&seed, "m/44'/60'/0'/0/3").unwrap()
+    );
+    assert_eq!(key.depth(), 5);
+    assert_eq!(key.child_number(), Some(ChildNumber::normal(3)));
+    assert_eq!(master.child_number(), None);
+    assert!(!format!("{:?}", key).contains(&format!("{:?}", key.private_key())));
+
+    assert_eq!(
+        ExtendedPrivateKey::master(&[0u8; 15])
+            .unwrap_err()
+            .downcast::<Bip32Error>()
+            .unwrap(),
+        Bip32Error::InvalidSeedLength(15)
+    );
+    assert!(ExtendedPrivateKey::derive_path(&seed, "m/44'/x").is_err());
+}
+
+#[test]
+fn watch_only_derivation() {
+    use utils::bytes_to_hex_str;
+    // m/0' of the first test vector of BIP-32
+    let xpub: ExtendedPublicKey = "xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnw"
+        .parse()
+        .unwrap();
+    assert_eq!(xpub.depth(), 1);
+    assert_eq!(xpub.child_number(), Some(ChildNumber::hardened(0)));
+    assert_eq!(
+        bytes_to_hex_str(xpub.chain_code()),
+        "47fdacbd0f1097043b78c63c20c34ef4ed9a111d980047ad16282c7ae6236141"
+    );
+    let seed = [0u8, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15];
+    let account = ExtendedPrivateKey::derive_path(&seed, "m/0'").unwrap();
+    assert_eq!(account.to_extended_public_key(), xpub);
+
+    // m/0'/1 and m/0'/1/7/3, from the public and from the private side
+    let child = xpub.derive_child(ChildNumber::normal(1)).unwrap();
+    let private_child = account.derive_child(ChildNumber::normal(1)).unwrap();
+    assert_eq!(child, private_child.to_extended_public_key());
+    assert_eq!(
+        child.address(),
+        private_child.private_key().to_public_key().unwrap()
+    );
+    let path: DerivationPath = "m/1/7/3".parse().unwrap();
+    assert_eq!(
+        xpub.derive(&path).unwrap().address(),
+        account
+            .derive(&path)
+            .unwrap()
+            .private_key()
+            .to_public_key()
+            .unwrap()
+    );
+
+    assert_eq!(
+        xpub.derive_child(ChildNumber::hardened(1))
+            .unwrap_err()
+            .downcast::<Bip32Error>()
+            .unwrap(),
+        Bip32Error::HardenedFromPublic(ChildNumber::hardened(1))
+    );
+}
+
+#[test]
+fn invalid_extended_public_keys() {
+    let parse = |s: &str| -> Bip32Error {
+        s.parse::<ExtendedPublicKey>()
+            .unwrap_err()
+            .downcast::<Bip32Error>()
+            .unwrap()
+    };
+    // The last character changed
+    assert_eq!(
+        parse("xpub68Gmy5EdvgibQVfPdqkBBCHxA5htiqg55crXYuXoQRKfDBFA1WEjWgP6LHhwBZeNK1VTsfTFUHCdrfp1bgwQ9xv5ski8PX9rL2dZXvgGDnx"),
+        Bip32Error::InvalidChecksum
+    );
+    assert_eq!(parse("xpub0"), Bip32Error::InvalidBase58('0'));
+    // The xprv of m/0' of the first test vector
+    assert_eq!(
+        parse("xprv9uHRZZhk6KAJC1avXpDAp4MDc3sQKNxDiPvvkX8Br5ngLNv1TxvUxt4cV1rGL5hj6KCesnDYUhd7oWgT11eZG7XnxHrnYeSvkzY7d2bhkJ7"),
+        Bip32Error::UnknownVersion(0x0488_ade4)
+    );
+}
@@ -0,0 +1,545 @@
+//! Reading blocks from block files.
+//!
+//! `geth export` writes blocks one after the other as RLP, with nothing in
+//! between. [BlockReader](struct.BlockReader.html) reads such a file from
+//! any `Read`, one block at a time, so a dump of the whole chain can be
+//! walked through without an execution client and without holding more
+//! than a block in memory. Exports written to a `.gz` file are compressed,
+//! and have to be read through a gzip decoder.
+//!
+//! Era1 archives of pre-merge history are read by the `era1` module, behind
+//! the `era1` feature.
+use address::Address;
+use chain::BlockHeader;
+use failure::Error;
+use hash::keccak256;
+use num256::Uint256;
+use rlp::{
+    decode_item_with, decode_raw_list, decode_with, encode_list, DecodeConfig, RlpDecode,
+    RlpDecodeError, RlpEncode, RlpItem,
+};
+use serde_bytes::ByteBuf;
+use std::io::{self, Read};
+use transaction::TypedTransaction;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum BlockFileError {
+    #[fail(display = "Block is not a list of 3 or 4 items")]
+    MalformedBlock,
+    #[fail(display = "Block of {} bytes is too large", _0)]
+    BlockTooLarge(u64),
+    #[fail(display = "Block file ends in the middle of a block")]
+    Truncated,
+}
+
+/// A block header, from any fork up to Prague.
+///
+/// Fields added by later forks are `None` in blocks from before them. Each
+/// one only appears once all the ones before it do.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub parent_hash: [u8; 32],
+    pub ommers_hash: [u8; 32],
+    pub beneficiary: Address,
+    pub state_root: [u8; 32],
+    pub transactions_root: [u8; 32],
+    pub receipts_root: [u8; 32],
+    pub logs_bloom: [u8; 256],
+    pub difficulty: Uint256,
+    pub number: u64,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub timestamp: u64,
+    pub extra_data: Vec<u8>,
+    pub mix_hash: [u8; 32],
+    pub nonce: [u8; 8],
+    /// London
+    pub base_fee_per_gas: Option<Uint256>,
+    /// Shanghai
+    pub withdrawals_root: Option<[u8; 32]>,
+    /// Cancun
+    pub blob_gas_used: Option<u64>,
+    /// Cancun
+    pub excess_blob_gas: Option<u64>,
+    /// Cancun
+    pub parent_beacon_block_root: Option<[u8; 32]>,
+    /// Prague
+    pub requests_hash: Option<[u8; 32]>,
+}
+
+/// Number of header fields before London.
+const BASE_HEADER_FIELDS: usize = 15;
+/// Number of header fields added since.
+const OPTIONAL_HEADER_FIELDS: usize = 6;
+
+impl Header {
+    /// The block hash.
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256(&::rlp::encode(self))
+    }
+
+    /// The parts of the header [ChainTracker](../chain/struct.ChainTracker.html)
+    /// follows the chain with.
+    pub fn block_header(&self) -> BlockHeader {
+        BlockHeader {
+            number: self.number,
+            hash: self.hash(),
+            parent_hash: self.parent_hash,
+        }
+    }
+}
+
+impl RlpEncode for Header {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        let extra_data = ByteBuf::from(self.extra_data.clone());
+        let mut fields: Vec<&dyn RlpEncode> = vec![
+            &self.parent_hash,
+            &self.ommers_hash,
+            &self.beneficiary,
+            &self.state_root,
+            &self.transactions_root,
+            &self.receipts_root,
+            &self.logs_bloom,
+            &self.difficulty,
+            &self.number,
+            &self.gas_limit,
+            &self.gas_used,
+            &self.timestamp,
+            &extra_data,
+            &self.mix_hash,
+            &self.nonce,
+        ];
+        let optional: [Option<&dyn RlpEncode>; OPTIONAL_HEADER_FIELDS] = [
+            self.base_fee_per_gas.as_ref().map(|f| f as &dyn RlpEncode),
+            self.withdrawals_root.as_ref().map(|f| f as &dyn RlpEncode),
+            self.blob_gas_used.as_ref().map(|f| f as &dyn RlpEncode),
+            self.excess_blob_gas.as_ref().map(|f| f as &dyn RlpEncode),
+            self.parent_beacon_block_root
+                .as_ref()
+                .map(|f| f as &dyn RlpEncode),
+            self.requests_hash.as_ref().map(|f| f as &dyn RlpEncode),
+        ];
+        fields.extend(optional.iter().map_while(|field| *field));
+        out.extend(encode_list(&fields));
+    }
+}
+
+impl RlpDecode for Header {
+    fn rlp_decode(item: &RlpItem) -> Result<Header, RlpDecodeError> {
+        let items = item.as_list()?;
+        if items.len() < BASE_HEADER_FIELDS {
+            return Err(RlpDecodeError::ListLength(BASE_HEADER_FIELDS, items.len()));
+        }
+        if items.len() > BASE_HEADER_FIELDS + OPTIONAL_HEADER_FIELDS {
+            return Err(RlpDecodeError::ListLength(
+                BASE_HEADER_FIELDS + OPTIONAL_HEADER_FIELDS,
+                items.len(),
+            ));
+        }
+        fn optional<T: RlpDecode>(
+            items: &[RlpItem],
+            index: usize,
+        ) -> Result<Option<T>, RlpDecodeError> {
+            items.get(index).map(T::rlp_decode).transpose()
+        }
+        Ok(Header {
+            parent_hash: RlpDecode::rlp_decode(&items[0])?,
+            ommers_hash: RlpDecode::rlp_decode(&items[1])?,
+            beneficiary: RlpDecode::rlp_decode(&items[2])?,
+            state_root: RlpDecode::rlp_decode(&items[3])?,
+            transactions_root: RlpDecode::rlp_decode(&items[4])?,
+            receipts_root: RlpDecode::rlp_decode(&items[5])?,
+            logs_bloom: RlpDecode::rlp_decode(&items[6])?,
+            difficulty: RlpDecode::rlp_decode(&items[7])?,
+            number: RlpDecode::rlp_decode(&items[8])?,
+            gas_limit: RlpDecode::rlp_decode(&items[9])?,
+            gas_used: RlpDecode::rlp_decode(&items[10])?,
+            timestamp: RlpDecode::rlp_decode(&items[11])?,
+            extra_data: items[12].as_bytes()?.to_vec(),
+            mix_hash: RlpDecode::rlp_decode(&items[13])?,
+            nonce: RlpDecode::rlp_decode(&items[14])?,
+            base_fee_per_gas: optional(items, 15)?,
+            withdrawals_root: optional(items, 16)?,
+            blob_gas_used: optional(items, 17)?,
+            excess_blob_gas: optional(items, 18)?,
+            parent_beacon_block_root: optional(items, 19)?,
+            requests_hash: optional(items, 20)?,
+        })
+    }
+}
+
+/// A withdrawal from the beacon chain, included in blocks since Shanghai.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: Address,
+    /// Amount in gwei
+    pub amount: u64,
+}
+
+impl RlpEncode for Withdrawal {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        (self.index, self.validator_index, self.address, self.amount).rlp_append(out)
+    }
+}
+
+impl RlpDecode for Withdrawal {
+    fn rlp_decode(item: &RlpItem) -> Result<Withdrawal, RlpDecodeError> {
+        let (index, validator_index, address, amount) = RlpDecode::rlp_decode(item)?;
+        Ok(Withdrawal {
+            index,
+            validator_index,
+            address,
+            amount,
+        })
+    }
+}
+
+/// A block with its transactions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    pub header: Header,
+    pub transactions: Vec<TypedTransaction>,
+    pub ommers: Vec<Header>,
+    /// `None` before Shanghai
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+/// An item that is already encoded.
+pub(crate) struct Encoded<'a>(pub(crate) &'a [u8]);
+
+impl<'a> RlpEncode for Encoded<'a> {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.0)
+    }
+}
+
+impl Block {
+    /// The encoding of the block, as written to block files.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let mut transactions = Vec::new();
+        for tx in &self.transactions {
+            let bytes = tx.to_bytes()?;
+            match *tx {
+                TypedTransaction::Legacy(_) => transactions.extend(bytes),
+                _ => ByteBuf::from(bytes).rlp_append(&mut transactions),
+            }
+        }
+        // The transactions are encoded already, so this is their list
+        let transactions = encode_list(&[&Encoded(&transactions)]);
+        let transactions = Encoded(&transactions);
+        let mut fields: Vec<&dyn RlpEncode> = vec![&self.header, &transactions, &self.ommers];
+        if let Some(ref withdrawals) = self.withdrawals {
+            fields.push(withdrawals);
+        }
+        Ok(encode_list(&fields))
+    }
+
+    /// Decodes a block as encoded in block files and devp2p messages.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Block, Error> {
+        Block::from_bytes_with(bytes, &DecodeConfig::default())
+    }
+
+    /// Decodes a block like [from_bytes](#method.from_bytes), within the
+    /// limits of `config`.
+    pub fn from_bytes_with(bytes: &[u8], config: &DecodeConfig) -> Result<Block, Error> {
+        let parts = decode_raw_list(bytes, config)?;
+        if parts.len() != 3 && parts.len() != 4 {
+            return Err(BlockFileError::MalformedBlock.into());
+        }
+        let (transactions, ommers) = decode_body(parts[1], parts[2], config)?;
+        Ok(Block {
+            header: decode_with(parts[0], config)?,
+            transactions,
+            ommers,
+            withdrawals: match parts.get(3) {
+                Some(withdrawals) => Some(decode_with(withdrawals, config)?),
+                None => None,
+            },
+        })
+    }
+}
+
+/// Decodes the transactions and ommers of a block body.
+pub(crate) fn decode_body(
+    transactions: &[u8],
+    ommers: &[u8],
+    config: &DecodeConfig,
+) -> Result<(Vec<TypedTransaction>, Vec<Header>), Error> {
+    let transactions = decode_raw_list(transactions, config)?
+        .into_iter()
+        .map(|raw| {
+            // Typed transactions are wrapped in a string, legacy ones are
+            // the list they are encoded as
+            if raw[0] >= 0xc0 {
+                TypedTransaction::from_bytes(raw)
+            } else {
+                TypedTransaction::from_bytes(decode_item_with(raw, config)?.as_bytes()?)
+            }
+        })
+        .collect::<Result<_, Error>>()?;
+    Ok((transactions, decode_with(ommers, config)?))
+}
+
+/// Reads exactly `buf.len()` bytes, or returns `false` if the input ends
+/// before the first one.
+pub(crate) fn read_or_end<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(BlockFileError::Truncated.into()),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(true)
+}
+
+/// Reads one RLP item from `reader`, at most `max_len` bytes long, or
+/// returns `None` at the end of the input.
+fn read_rlp_item<R: Read>(reader: &mut R, max_len: usize) -> Result<Option<Vec<u8>>, Error> {
+    let mut item = vec![0u8];
+    if !read_or_end(reader, &mut item)? {
+        return Ok(None);
+    }
+    let payload_len = match item[0] {
+        0x00..=0x7f => 0,
+        prefix @ 0x80..=0xb7 => u64::from(prefix - 0x80),
+        prefix @ 0xc0..=0xf7 => u64::from(prefix - 0xc0),
+        prefix => {
+            let len_of_len = if prefix < 0xc0 {
+                prefix - 0xb7
+            } else {
+                prefix - 0xf7
+            } as usize;
+            item.resize(1 + len_of_len, 0);
+            if !read_or_end(reader, &mut item[1..])? {
+                return Err(BlockFileError::Truncated.into());
+            }
+            item[1..]
+                .iter()
+                .fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte))
+        }
+    };
+    if payload_len > max_len as u64 {
+        return Err(BlockFileError::BlockTooLarge(payload_len).into());
+    }
+    // Grows with what is actually read, so a length that claims more than
+    // the input holds costs nothing up front
+    let read = reader.take(payload_len).read_to_end(&mut item)?;
+    if (read as u64) < payload_len {
+        return Err(BlockFileError::Truncated.into());
+    }
+    Ok(Some(item))
+}
+
+/// Reads the blocks of a block file, in order.
+///
+/// A block that is read in full but does not decode is returned as an
+/// error, and reading goes on with the next one. Reading stops after an
+/// error of the input itself, or a block that is too large or cut off.
+#[derive(Debug)]
+pub struct BlockReader<R> {
+    reader: R,
+    config: DecodeConfig,
+    done: bool,
+}
+
+impl<R: Read> BlockReader<R> {
+    pub fn new(reader: R) -> BlockReader<R> {
+        BlockReader::with_config(reader, DecodeConfig::default())
+    }
+
+    /// Decodes blocks within the limits of `config`. Its item length limit
+    /// also limits the size of whole blocks.
+    pub fn with_config(reader: R, config: DecodeConfig) -> BlockReader<R> {
+        BlockReader {
+            reader,
+            config,
+            done: false,
+        }
+    }
+
+    /// The underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for BlockReader<R> {
+    type Item = Result<Block, Error>;
+
+    fn next(&mut self) -> Option<Result<Block, Error>> {
+        if self.done {
+            return None;
+        }
+        match read_rlp_item(&mut self.reader, self.config.max_item_len) {
+            Ok(Some(bytes)) => Some(Block::from_bytes_with(&bytes, &self.config)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_header(number: u64) -> Header {
+    Header {
+        parent_hash: [0x11u8; 32],
+        ommers_hash: [0x22u8; 32],
+        beneficiary: Address::from([0x33u8; 20]),
+        state_root: [0x44u8; 32],
+        transactions_root: [0x55u8; 32],
+        receipts_root: [0x66u8; 32],
+        logs_bloom: [0u8; 256],
+        difficulty: 0u8.into(),
+        number,
+        gas_limit: 30_000_000,
+        gas_used: 42_000,
+        timestamp: 1_700_000_000,
+        extra_data: b"clarity".to_vec(),
+        mix_hash: [0x77u8; 32],
+        nonce: [0u8; 8],
+        base_fee_per_gas: Some(7u8.into()),
+        withdrawals_root: Some([0x88u8; 32]),
+        blob_gas_used: None,
+        excess_blob_gas: None,
+        parent_beacon_block_root: None,
+        requests_hash: None,
+    }
+}
+
+#[cfg(test)]
+pub(crate) fn test_transactions() -> Vec<TypedTransaction> {
+    use private_key::PrivateKey;
+    use transaction::{Eip1559Transaction, Transaction};
+    let key = PrivateKey::from([0x42u8; 32]);
+    let legacy = Transaction {
+        nonce: 0u8.into(),
+        gas_price: 1_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 1u32.into(),
+        data: Vec::new(),
+        signature: None,
+    }
+    .sign(&key, Some(1));
+    let eip1559 = Eip1559Transaction {
+        chain_id: 1u8.into(),
+        nonce: 1u8.into(),
+        max_priority_fee_per_gas: 1u8.into(),
+        max_fee_per_gas: 10u8.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 1u32.into(),
+        data: Vec::new(),
+        access_list: Vec::new(),
+        signature: None,
+    }
+    .sign(&key);
+    vec![legacy.into(), eip1559.into()]
+}
+
+#[test]
+fn header_roundtrip() {
+    let header = test_header(100);
+    let encoded = ::rlp::encode(&header);
+    assert_eq!(::rlp::decode::<Header>(&encoded).unwrap(), header);
+    assert_eq!(header.block_header().number, 100);
+    assert_eq!(header.block_header().hash, keccak256(&encoded));
+
+    // A header from before London has none of the optional fields
+    let frontier = Header {
+        base_fee_per_gas: None,
+        withdrawals_root: None,
+        ..header
+    };
+    let encoded = ::rlp::encode(&frontier);
+    match ::rlp::decode_item(&encoded).unwrap() {
+        RlpItem::List(items) => assert_eq!(items.len(), BASE_HEADER_FIELDS),
+        other => panic!("unexpected {:?}", other),
+    }
+    assert_eq!(::rlp::decode::<Header>(&encoded).unwrap(), frontier);
+    assert_eq!(
+        ::rlp::decode::<Header>(&encode_list(&[&0u8])),
+        Err(RlpDecodeError::ListLength(BASE_HEADER_FIELDS, 1))
+    );
+}
+
+#[test]
+fn read_block_file() {
+    let blocks = vec![
+        Block {
+            header: test_header(1),
+            transactions: test_transactions(),
+            ommers: Vec::new(),
+            withdrawals: None,
+        },
+        Block {
+            header: test_header(2),
+            transactions: Vec::new(),
+            ommers: vec![test_header(0)],
+            withdrawals: Some(vec![Withdrawal {
+                index: 1,
+                validator_index: 2,
+                address: Address::from([0x99u8; 20]),
+                amount: 32_000_000_000,
+            }]),
+        },
+    ];
+    let mut file = Vec::new();
+    for block in &blocks {
+        file.extend(block.to_bytes().unwrap());
+    }
+    let read: Vec<Block> = BlockReader::new(&file[..])
+        .collect::<Result<_, Error>>()
+        .unwrap();
+    assert_eq!(read, blocks);
+    assert_eq!(
+        read[0].transactions[1].hash().unwrap(),
+        test_transactions()[1].hash().unwrap()
+    );
+
+    // A block that does not decode is skipped, one that is cut off ends
+    // the file
+    let mut damaged = encode_list(&[&0u8]);
+    damaged.extend(&file);
+    damaged.truncate(damaged.len() - 1);
+    let mut reader = BlockReader::new(&damaged[..]);
+    assert_eq!(
+        reader
+            .next()
+            .unwrap()
+            .unwrap_err()
+            .downcast::<BlockFileError>()
+            .unwrap(),
+        BlockFileError::MalformedBlock
+    );
+    assert_eq!(reader.next().unwrap().unwrap(), blocks[0]);
+    assert_eq!(
+        reader
+            .next()
+            .unwrap()
+            .unwrap_err()
+            .downcast::<BlockFileError>()
+            .unwrap(),
+        BlockFileError::Truncated
+    );
+    assert!(reader.next().is_none());
+
+    let config = DecodeConfig {
+        max_item_len: 100,
+        ..DecodeConfig::default()
+    };
+    let mut reader = BlockReader::with_config(&file[..], config);
+    assert!(reader.next().unwrap().is_err());
+    assert!(reader.next().is_none());
+}
@@ -0,0 +1,724 @@
+//! BLS12-381 keys for consensus-layer (validator) tooling.
+//!
+//! This module is only available with the `bls` feature. It covers what is
+//! needed to manage validator keys next to execution-layer keys:
+//!
+//! * Key derivation from a seed as specified in EIP-2333 (and EIP-2334 paths)
+//! * EIP-2335 keystores (scrypt or pbkdf2, AES-128-CTR, SHA-256 checksum)
+//! * Signatures using the Ethereum proof-of-possession ciphersuite, including
+//!   deposit message signatures over the deposit domain (see `staking`).
+use aes::cipher::{NewCipher, StreamCipher};
+use aes::Aes128Ctr;
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{pairing, G1Affine, G2Affine, G2Projective, Scalar};
+use failure::Error;
+use hkdf::Hkdf;
+use hmac::Hmac;
+use sha2::{Digest, Sha256};
+use staking::{deposit_signing_root, BlsToExecutionChange};
+use std::fmt;
+use unicode_normalization::UnicodeNormalization;
+use utils::{bytes_to_hex_str, hex_str_to_bytes};
+
+/// Domain separation tag of the ciphersuite used by Ethereum consensus.
+const DST: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum BlsError {
+    #[fail(display = "Invalid BLS secret key")]
+    InvalidSecretKey,
+    #[fail(display = "Invalid BLS public key")]
+    InvalidPublicKey,
+    #[fail(display = "Invalid BLS signature")]
+    InvalidSignature,
+    #[fail(display = "Seed should be at least 32 bytes")]
+    SeedTooShort,
+    #[fail(display = "Invalid derivation path: {}", _0)]
+    InvalidPath(String),
+    #[fail(display = "Unsupported keystore version {}", _0)]
+    UnsupportedVersion(u32),
+    #[fail(display = "Unsupported keystore function: {}", _0)]
+    UnsupportedFunction(String),
+    #[fail(display = "Invalid keystore parameters")]
+    InvalidKeystoreParams,
+    #[fail(display = "Keystore checksum mismatch (wrong password?)")]
+    ChecksumMismatch,
+}
+
+/// A BLS12-381 secret key.
+#[derive(Clone, PartialEq)]
+pub struct BlsSecretKey(Scalar);
+
+/// A BLS12-381 public key, which is a point in G1.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BlsPublicKey(G1Affine);
+
+/// A BLS12-381 signature, which is a point in G2.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct BlsSignature(G2Affine);
+
+/// Performs `HKDF_mod_r` from EIP-2333.
+fn hkdf_mod_r(ikm: &[u8]) -> Scalar {
+    let mut salt = b"BLS-SIG-KEYGEN-SALT-".to_vec();
+    let mut ikm = ikm.to_vec();
+    // I2OSP(0, 1)
+    ikm.push(0);
+    loop {
+        salt = Sha256::digest(&salt).to_vec();
+        let hk = Hkdf::<Sha256>::new(Some(&salt), &ikm);
+        let mut okm = [0u8; 48];
+        // key_info is empty, followed by I2OSP(L, 2)
+        hk.expand(&[0, 48], &mut okm)
+            .expect("48 bytes is a valid HKDF output length");
+        // OS2IP(OKM) mod r, where Scalar expects a little endian input
+        let mut wide = [0u8; 64];
+        for (i, byte) in okm.iter().rev().enumerate() {
+            wide[i] = *byte;
+        }
+        let sk = Scalar::from_bytes_wide(&wide);
+        if sk != Scalar::zero() {
+            return sk;
+        }
+    }
+}
+
+/// Expands an input key material into 255 Lamport secret key chunks, hashes
+/// each chunk, and appends the results to `lamport_pk`.
+fn ikm_to_lamport_pk(ikm: &[u8], salt: &[u8], lamport_pk: &mut Vec<u8>) {
+    let hk = Hkdf::<Sha256>::new(Some(salt), ikm);
+    let mut okm = vec![0u8; 32 * 255];
+    hk.expand(&[], &mut okm)
+        .expect("8160 bytes is a valid HKDF output length");
+    for chunk in okm.chunks(32) {
+        lamport_pk.extend(Sha256::digest(chunk).iter());
+    }
+}
+
+impl BlsSecretKey {
+    /// Creates a secret key from its 32 byte big endian representation.
+    ///
+    /// The value has to be non-zero and smaller than the curve order.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BlsSecretKey, Error> {
+        if bytes.len() != 32 {
+            return Err(BlsError::InvalidSecretKey.into());
+        }
+        let mut le = [0u8; 32];
+        for (i, byte) in bytes.iter().rev().enumerate() {
+            le[i] = *byte;
+        }
+        let scalar: Option<Scalar> = Scalar::from_bytes(&le).into();
+        match scalar {
+            Some(scalar) if scalar != Scalar::zero() => Ok(BlsSecretKey(scalar)),
+            _ => Err(BlsError::InvalidSecretKey.into()),
+        }
+    }
+
+    /// Get the 32 byte big endian representation of a secret key.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut result = self.0.to_bytes();
+        result.reverse();
+        result
+    }
+
+    /// Derives a master secret key from a seed (`derive_master_SK` in EIP-2333).
+    ///
+    /// The seed is required to be at least 32 bytes long.
+    pub fn derive_master(seed: &[u8]) -> Result<BlsSecretKey, Error> {
+        if seed.len() < 32 {
+            return Err(BlsError::SeedTooShort.into());
+        }
+        Ok(BlsSecretKey(hkdf_mod_r(seed)))
+    }
+
+    /// Derives a child secret key at a given index (`derive_child_SK` in EIP-2333).
+    pub fn derive_child(&self, index: u32) -> BlsSecretKey {
+        let salt = index.to_be_bytes();
+        let ikm = self.to_bytes();
+        let not_ikm: Vec<u8> = ikm.iter().map(|b| !b).collect();
+        let mut lamport_pk = Vec::with_capacity(2 * 255 * 32);
+        ikm_to_lamport_pk(&ikm, &salt, &mut lamport_pk);
+        ikm_to_lamport_pk(&not_ikm, &salt, &mut lamport_pk);
+        let compressed_lamport_pk = Sha256::digest(&lamport_pk);
+        BlsSecretKey(hkdf_mod_r(&compressed_lamport_pk))
+    }
+
+    /// Derives a secret key from a seed and an EIP-2334 path such as
+    /// `m/12381/3600/0/0/0`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use clarity::bls::BlsSecretKey;
+    /// let seed = [0x42u8; 32];
+    /// let signing_key = BlsSecretKey::derive_path(&seed, "m/12381/3600/0/0/0").unwrap();
+    /// ```
+    pub fn derive_path(seed: &[u8], path: &str) -> Result<BlsSecretKey, Error> {
+        let mut segments = path.split('/');
+        if segments.next() != Some("m") {
+            return Err(BlsError::InvalidPath(path.to_owned()).into());
+        }
+        let mut key = BlsSecretKey::derive_master(seed)?;
+        for segment in segments {
+            let index: u32 = segment
+                .parse()
+                .map_err(|_| BlsError::InvalidPath(path.to_owned()))?;
+            key = key.derive_child(index);
+        }
+        Ok(key)
+    }
+
+    /// Get the public key of this secret key.
+    pub fn to_public_key(&self) -> BlsPublicKey {
+        BlsPublicKey(G1Affine::from(G1Affine::generator() * self.0))
+    }
+
+    /// Signs an arbitrary message.
+    pub fn sign(&self, msg: &[u8]) -> BlsSignature {
+        let point = <G2Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(msg, DST);
+        BlsSignature(G2Affine::from(point * self.0))
+    }
+
+    /// Signs a deposit message (public key, withdrawal credentials and amount
+    /// in Gwei) over the deposit domain of a network identified by its genesis
+    /// fork version.
+    pub fn sign_deposit_message(
+        &self,
+        withdrawal_credentials: &[u8; 32],
+        amount: u64,
+        genesis_fork_version: [u8; 4],
+    ) -> BlsSignature {
+        self.sign(&deposit_signing_root(
+            &self.to_public_key().to_bytes(),
+            withdrawal_credentials,
+            amount,
+            genesis_fork_version,
+        ))
+    }
+
+    /// Signs a change of `0x00` withdrawal credentials to an execution
+    /// address. This key has to be the BLS withdrawal key of the change.
+    pub fn sign_bls_to_execution_change(
+        &self,
+        change: &BlsToExecutionChange,
+        genesis_fork_version: [u8; 4],
+        genesis_validators_root: &[u8; 32],
+    ) -> BlsSignature {
+        self.sign(&change.signing_root(genesis_fork_version, genesis_validators_root))
+    }
+}
+
+impl fmt::Debug for BlsSecretKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Never print the secret itself
+        write!(f, "BlsSecretKey({})", self.to_public_key())
+    }
+}
+
+impl BlsPublicKey {
+    /// Parses a public key from its 48 byte compressed form.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BlsPublicKey, Error> {
+        if bytes.len() != 48 {
+            return Err(BlsError::InvalidPublicKey.into());
+        }
+        let mut data = [0u8; 48];
+        data.copy_from_slice(bytes);
+        let point: Option<G1Affine> = G1Affine::from_compressed(&data).into();
+        match point {
+            Some(point) if !bool::from(point.is_identity()) => Ok(BlsPublicKey(point)),
+            _ => Err(BlsError::InvalidPublicKey.into()),
+        }
+    }
+
+    /// Get the 48 byte compressed form of a public key.
+    pub fn to_bytes(&self) -> [u8; 48] {
+        self.0.to_compressed()
+    }
+
+    /// Verifies a signature of a message against this public key.
+    pub fn verify(&self, msg: &[u8], signature: &BlsSignature) -> bool {
+        let point = <G2Projective as HashToCurve<ExpandMsgXmd<Sha256>>>::hash_to_curve(msg, DST);
+        pairing(&self.0, &G2Affine::from(point)) == pairing(&G1Affine::generator(), &signature.0)
+    }
+}
+
+impl fmt::Display for BlsPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", bytes_to_hex_str(&self.to_bytes()))
+    }
+}
+
+impl fmt::Debug for BlsPublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BlsPublicKey({})", self)
+    }
+}
+
+impl BlsSignature {
+    /// Parses a signature from its 96 byte compressed form.
+    pub fn from_bytes(bytes: &[u8]) -> Result<BlsSignature, Error> {
+        if bytes.len() != 96 {
+            return Err(BlsError::InvalidSignature.into());
+        }
+        let mut data = [0u8; 96];
+        data.copy_from_slice(bytes);
+        let point: Option<G2Affine> = G2Affine::from_compressed(&data).into();
+        point
+            .map(BlsSignature)
+            .ok_or_else(|| BlsError::InvalidSignature.into())
+    }
+
+    /// Get the 96 byte compressed form of a signature.
+    pub fn to_bytes(&self) -> [u8; 96] {
+        self.0.to_compressed()
+    }
+}
+
+impl fmt::Display for BlsSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", bytes_to_hex_str(&self.to_bytes()))
+    }
+}
+
+impl fmt::Debug for BlsSignature {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "BlsSignature({})", self)
+    }
+}
+
+/// Key derivation function parameters of an EIP-2335 keystore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeystoreKdfParams {
+    pub dklen: u32,
+    /// Scrypt cost parameter
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub n: Option<u32>,
+    /// Scrypt block size
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub r: Option<u32>,
+    /// Scrypt parallelization
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub p: Option<u32>,
+    /// Pbkdf2 iteration count
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub c: Option<u32>,
+    /// Pbkdf2 pseudo random function, only `hmac-sha256` is supported
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub prf: Option<String>,
+    pub salt: String,
+}
+
+/// Cipher parameters of an EIP-2335 keystore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeystoreCipherParams {
+    pub iv: String,
+}
+
+/// A single step of the EIP-2335 crypto pipeline.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeystoreModule<P> {
+    pub function: String,
+    pub params: P,
+    pub message: String,
+}
+
+/// The `crypto` object of an EIP-2335 keystore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeystoreCrypto {
+    pub kdf: KeystoreModule<KeystoreKdfParams>,
+    pub checksum: KeystoreModule<::std::collections::BTreeMap<String, String>>,
+    pub cipher: KeystoreModule<KeystoreCipherParams>,
+}
+
+/// Key derivation function used when creating an EIP-2335 keystore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeystoreKdf {
+    /// Scrypt with a given cost (`n`), block size (`r`) and parallelization (`p`)
+    Scrypt { n: u32, r: u32, p: u32 },
+    /// PBKDF2 with HMAC-SHA256 and a given iteration count
+    Pbkdf2 { c: u32 },
+}
+
+/// An EIP-2335 BLS keystore.
+///
+/// This type (de)serializes to the standard JSON format, so any serde data
+/// format such as `serde_json` can be used to read or write keystore files.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BlsKeystore {
+    pub crypto: KeystoreCrypto,
+    #[serde(default)]
+    pub description: String,
+    pub pubkey: String,
+    pub path: String,
+    pub uuid: String,
+    pub version: u32,
+}
+
+/// Normalizes a password as required by EIP-2335: NFKD normalization with
+/// C0, C1 and Delete control codes stripped.
+fn process_password(password: &str) -> Vec<u8> {
+    password
+        .nfkd()
+        .filter(|c| {
+            let c = *c as u32;
+            !(c <= 0x1f || (0x7f..=0x9f).contains(&c))
+        })
+        .collect::<String>()
+        .into_bytes()
+}
+
+/// Runs the keystore KDF over a password.
+fn derive_decryption_key(
+    kdf: &KeystoreModule<KeystoreKdfParams>,
+    password: &[u8],
+) -> Result<Vec<u8>, Error> {
+    let params = &kdf.params;
+    let salt = hex_str_to_bytes(&params.salt)?;
+    if params.dklen < 32 {
+        return Err(BlsError::InvalidKeystoreParams.into());
+    }
+    let mut key = vec![0u8; params.dklen as usize];
+    match kdf.function.as_str() {
+        "scrypt" => {
+            let (n, r, p) = match (params.n, params.r, params.p) {
+                (Some(n), Some(r), Some(p)) if n.is_power_of_two() && n > 1 => (n, r, p),
+                _ => return Err(BlsError::InvalidKeystoreParams.into()),
+            };
+            let log_n = (31 - n.leading_zeros()) as u8;
+            let params =
+                scrypt::Params::new(log_n, r, p).map_err(|_| BlsError::InvalidKeystoreParams)?;
+            scrypt::scrypt(password, &salt, &params, &mut key)
+                .map_err(|_| BlsError::InvalidKeystoreParams)?;
+        }
+        "pbkdf2" => {
+            match params.prf.as_deref() {
+                Some("hmac-sha256") => {}
+                Some(prf) => return Err(BlsError::UnsupportedFunction(prf.to_owned()).into()),
+                None => return Err(BlsError::InvalidKeystoreParams.into()),
+            }
+            let c = params.c.ok_or(BlsError::InvalidKeystoreParams)?;
+            pbkdf2::pbkdf2::<Hmac<Sha256>>(password, &salt, c, &mut key);
+        }
+        function => return Err(BlsError::UnsupportedFunction(function.to_owned()).into()),
+    }
+    Ok(key)
+}
+
+/// SHA-256 checksum over the second half of the decryption key and the
+/// cipher message.
+fn keystore_checksum(decryption_key: &[u8], cipher_message: &[u8]) -> Vec<u8> {
+    let mut hasher = Sha256::new();
+    hasher.update(&decryption_key[16..32]);
+    hasher.update(cipher_message);
+    hasher.finalize().to_vec()
+}
+
+/// Applies AES-128-CTR in place, which is the same operation for encryption
+/// and decryption.
+fn aes_128_ctr(key: &[u8], iv: &[u8], data: &mut [u8]) -> Result<(), Error> {
+    let mut cipher =
+        Aes128Ctr::new_from_slices(key, iv).map_err(|_| BlsError::InvalidKeystoreParams)?;
+    cipher.apply_keystream(data);
+    Ok(())
+}
+
+impl BlsKeystore {
+    /// Decrypts the secret key stored in this keystore.
+    ///
+    /// Returns `BlsError::ChecksumMismatch` when the password is wrong.
+    pub fn decrypt(&self, password: &str) -> Result<BlsSecretKey, Error> {
+        if self.version != 4 {
+            return Err(BlsError::UnsupportedVersion(self.version).into());
+        }
+        let crypto = &self.crypto;
+        if crypto.checksum.function != "sha256" {
+            return Err(BlsError::UnsupportedFunction(crypto.checksum.function.clone()).into());
+        }
+        if crypto.cipher.function != "aes-128-ctr" {
+            return Err(BlsError::UnsupportedFunction(crypto.cipher.function.clone()).into());
+        }
+        let decryption_key = derive_decryption_key(&crypto.kdf, &process_password(password))?;
+        let mut secret = hex_str_to_bytes(&crypto.cipher.message)?;
+        let checksum = hex_str_to_bytes(&crypto.checksum.message)?;
+        if keystore_checksum(&decryption_key, &secret) != checksum {
+            return Err(BlsError::ChecksumMismatch.into());
+        }
+        let iv = hex_str_to_bytes(&crypto.cipher.params.iv)?;
+        aes_128_ctr(&decryption_key[..16], &iv, &mut secret)?;
+        BlsSecretKey::from_bytes(&secret)
+    }
+
+    /// Encrypts a secret key into a new keystore.
+    ///
+    /// Clarity has no source of randomness of its own, so the caller has to
+    /// provide a fresh random `salt` and `iv` for every keystore. The `uuid`
+    /// of the keystore is derived from them.
+    pub fn encrypt(
+        secret: &BlsSecretKey,
+        password: &str,
+        path: &str,
+        kdf: KeystoreKdf,
+        salt: &[u8; 32],
+        iv: &[u8; 16],
+    ) -> Result<BlsKeystore, Error> {
+        let salt_hex = bytes_to_hex_str(salt);
+        let kdf = match kdf {
+            KeystoreKdf::Scrypt { n, r, p } => KeystoreModule {
+                function: "scrypt".to_owned(),
+                params: KeystoreKdfParams {
+                    dklen: 32,
+                    n: Some(n),
+                    r: Some(r),
+                    p: Some(p),
+                    c: None,
+                    prf: None,
+                    salt: salt_hex,
+                },
+                message: String::new(),
+            },
+            KeystoreKdf::Pbkdf2 { c } => KeystoreModule {
+                function: "pbkdf2".to_owned(),
+                params: KeystoreKdfParams {
+                    dklen: 32,
+                    n: None,
+                    r: None,
+                    p: None,
+                    c: Some(c),
+                    prf: Some("hmac-sha256".to_owned()),
+                    salt: salt_hex,
+                },
+                message: String::new(),
+            },
+        };
+        let decryption_key = derive_decryption_key(&kdf, &process_password(password))?;
+        let mut message = secret.to_bytes().to_vec();
+        aes_128_ctr(&decryption_key[..16], iv, &mut message)?;
+        let checksum = keystore_checksum(&decryption_key, &message);
+
+        // A version 4 UUID made out of the random inputs
+        let mut uuid = [0u8; 16];
+        uuid.copy_from_slice(&Sha256::digest(&[&salt[..], &iv[..]].concat())[..16]);
+        uuid[6] = (uuid[6] & 0x0f) | 0x40;
+        uuid[8] = (uuid[8] & 0x3f) | 0x80;
+        let uuid = bytes_to_hex_str(&uuid);
+
+        Ok(BlsKeystore {
+            crypto: KeystoreCrypto {
+                kdf,
+                checksum: KeystoreModule {
+                    function: "sha256".to_owned(),
+                    params: Default::default(),
+                    message: bytes_to_hex_str(&checksum),
+                },
+                cipher: KeystoreModule {
+                    function: "aes-128-ctr".to_owned(),
+                    params: KeystoreCipherParams {
+                        iv: bytes_to_hex_str(iv),
+                    },
+                    message: bytes_to_hex_str(&message),
+                },
+            },
+            description: String::new(),
+            pubkey: bytes_to_hex_str(&secret.to_public_key().to_bytes()),
+            path: path.to_owned(),
+            uuid: format!(
+                "{}-{}-{}-{}-{}",
+                &uuid[0..8],
+                &uuid[8..12],
+                &uuid[12..16],
+                &uuid[16..20],
+                &uuid[20..32]
+            ),
+            version: 4,
+        })
+    }
+}
+
+#[test]
+fn eip2333_test_case_0() {
+    use num256::Uint256;
+    // https://eips.ethereum.org/EIPS/eip-2333#test-case-0
+    let seed = hex_str_to_bytes("c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04").unwrap();
+    let master = BlsSecretKey::derive_master(&seed).unwrap();
+    assert_eq!(
+        Uint256::from_bytes_be(&master.to_bytes()),
+        "6083874454709270928345386274498605044986640685124978867557563392430687146096"
+            .parse()
+            .unwrap()
+    );
+    let child = master.derive_child(0);
+    assert_eq!(
+        Uint256::from_bytes_be(&child.to_bytes()),
+        "20397789859736650942317412262472558107875392172444076792671091975210932703118"
+            .parse()
+            .unwrap()
+    );
+    assert_eq!(BlsSecretKey::derive_path(&seed, "m/0").unwrap(), child);
+}
+
+#[test]
+fn derive_invalid_path() {
+    let seed = [0x42u8; 32];
+    assert!(BlsSecretKey::derive_path(&seed, "12381/3600").is_err());
+    assert!(BlsSecretKey::derive_path(&seed, "m/12381'/3600").is_err());
+    assert!(BlsSecretKey::derive_master(&seed[..31]).is_err());
+}
+
+#[test]
+fn secret_key_roundtrip() {
+    let key = BlsSecretKey::derive_master(&[0x42u8; 32]).unwrap();
+    assert_eq!(BlsSecretKey::from_bytes(&key.to_bytes()).unwrap(), key);
+    assert!(BlsSecretKey::from_bytes(&[0u8; 32]).is_err());
+    // Curve order is not a valid secret key
+    let r = hex_str_to_bytes("73eda753299d7d483339d80809a1d80553bda402fffe5bfeffffffff00000001")
+        .unwrap();
+    assert!(BlsSecretKey::from_bytes(&r).is_err());
+}
+
+#[test]
+fn sign_and_verify() {
+    let key = BlsSecretKey::derive_master(&[0x42u8; 32]).unwrap();
+    let public_key = key.to_public_key();
+    let signature = key.sign(b"Hello, world!");
+    assert!(public_key.verify(b"Hello, world!", &signature));
+    assert!(!public_key.verify(b"Hello, world?", &signature));
+
+    let other = key.derive_child(1).to_public_key();
+    assert!(!other.verify(b"Hello, world!", &signature));
+
+    let public_key = BlsPublicKey::from_bytes(&public_key.to_bytes()).unwrap();
+    let signature = BlsSignature::from_bytes(&signature.to_bytes()).unwrap();
+    assert!(public_key.verify(b"Hello, world!", &signature));
+}
+
+#[test]
+fn sign_deposit_message() {
+    use staking::{DepositData, MAINNET_GENESIS_FORK_VERSION};
+    let key = BlsSecretKey::derive_master(&[0x42u8; 32]).unwrap();
+    let credentials = [0x11u8; 32];
+    let signature =
+        key.sign_deposit_message(&credentials, 32_000_000_000, MAINNET_GENESIS_FORK_VERSION);
+    let deposit = DepositData {
+        pubkey: key.to_public_key().to_bytes(),
+        withdrawal_credentials: credentials,
+        amount: 32_000_000_000,
+        signature: signature.to_bytes(),
+    };
+    assert!(key.to_public_key().verify(
+        &deposit.signing_root(MAINNET_GENESIS_FORK_VERSION),
+        &signature
+    ));
+    // Signatures are bound to a network
+    assert!(!key
+        .to_public_key()
+        .verify(&deposit.signing_root([0x00, 0x00, 0x10, 0x20]), &signature));
+}
+
+#[test]
+fn sign_bls_to_execution_change() {
+    use address::Address;
+    use staking::{
+        WithdrawalCredentials, MAINNET_GENESIS_FORK_VERSION, MAINNET_GENESIS_VALIDATORS_ROOT,
+    };
+    let withdrawal_key = BlsSecretKey::derive_path(&[0x42u8; 32], "m/12381/3600/0/0").unwrap();
+    let from_bls_pubkey = withdrawal_key.to_public_key().to_bytes();
+    let credentials = WithdrawalCredentials::from_bls_pubkey(&from_bls_pubkey);
+    let change = BlsToExecutionChange {
+        validator_index: 1234,
+        from_bls_pubkey,
+        to_execution_address: Address::from([0x11u8; 20]),
+    };
+    assert!(credentials.is_bls_pubkey(&change.from_bls_pubkey));
+    let signature = withdrawal_key.sign_bls_to_execution_change(
+        &change,
+        MAINNET_GENESIS_FORK_VERSION,
+        &MAINNET_GENESIS_VALIDATORS_ROOT,
+    );
+    assert!(withdrawal_key.to_public_key().verify(
+        &change.signing_root(
+            MAINNET_GENESIS_FORK_VERSION,
+            &MAINNET_GENESIS_VALIDATORS_ROOT
+        ),
+        &signature
+    ));
+}
+
+#[test]
+fn eip2335_pbkdf2_test_vector() {
+    // https://eips.ethereum.org/EIPS/eip-2335#pbkdf2-test-vector
+    let keystore: BlsKeystore = serde_json::from_str(
+        r#"{
+        "crypto": {
+            "kdf": {
+                "function": "pbkdf2",
+                "params": {
+                    "dklen": 32,
+                    "c": 262144,
+                    "prf": "hmac-sha256",
+                    "salt": "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+                },
+                "message": ""
+            },
+            "checksum": {
+                "function": "sha256",
+                "params": {},
+                "message": "8a9f5d9912ed7e75ea794bc5a89bca5f193721d30868ade6f73043c6ea6febf1"
+            },
+            "cipher": {
+                "function": "aes-128-ctr",
+                "params": {
+                    "iv": "264daa3f303d7259501c93d997d84fe6"
+                },
+                "message": "cee03fde2af33149775b7223e7845e4fb2c8ae1792e5f99fe9ecf474cc8c16ad"
+            }
+        },
+        "description": "This is a test keystore that uses PBKDF2 to secure the secret.",
+        "pubkey": "9612d7a727c9d0a22e185a1c768478dfe919cada9266988cb32359c11f2b7b27f4ae4040902382ae2910c15e2b420d07",
+        "path": "m/12381/60/0/0",
+        "uuid": "64625def-3331-4eea-ab6f-782f3ed16a83",
+        "version": 4
+    }"#,
+    )
+    .unwrap();
+    let password = "\u{1d531}\u{1d522}\u{1d530}\u{1d531}\u{1d52d}\u{1d51e}\u{1d530}\u{1d530}\u{1d534}\u{1d52c}\u{1d52f}\u{1d521}\u{1f511}";
+    let secret = keystore.decrypt(password).unwrap();
+    assert_eq!(
+        bytes_to_hex_str(&secret.to_bytes()),
+        "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&secret.to_public_key().to_bytes()),
+        keystore.pubkey
+    );
+    assert_eq!(
+        keystore
+            .decrypt("testpassword")
+            .unwrap_err()
+            .downcast::<BlsError>()
+            .unwrap(),
+        BlsError::ChecksumMismatch
+    );
+}
+
+#[test]
+fn keystore_roundtrip() {
+    let key = BlsSecretKey::derive_master(&[0x42u8; 32]).unwrap();
+    for kdf in &[
+        KeystoreKdf::Scrypt { n: 16, r: 8, p: 1 },
+        KeystoreKdf::Pbkdf2 { c: 16 },
+    ] {
+        let keystore = BlsKeystore::encrypt(
+            &key,
+            "password\u{7f}",
+            "m/12381/3600/0/0/0",
+            *kdf,
+            &[1u8; 32],
+            &[2u8; 16],
+        )
+        .unwrap();
+        let json = serde_json::to_string(&keystore).unwrap();
+        let keystore: BlsKeystore = serde_json::from_str(&json).unwrap();
+        // Control codes are stripped of a password
+        assert_eq!(keystore.decrypt("password").unwrap(), key);
+        assert!(keystore.decrypt("Password").is_err());
+    }
+}
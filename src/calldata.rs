@@ -0,0 +1,327 @@
+//! What calldata costs, and what it would cost encoded differently.
+//!
+//! Every byte of calldata costs gas: 4 if it is zero and 16 otherwise
+//! (EIP-2028). [CalldataCost](struct.CalldataCost.html) counts them.
+//!
+//! The ABI encoding is wasteful for some values: a short `string` takes an
+//! offset, a length and a padded word where a `bytes32` takes one word, and
+//! small numbers are padded to 32 bytes. Given a function and sample
+//! arguments, [golf_report](fn.golf_report.html) prices the call as it is
+//! and under such alternatives, so that contract authors can see what
+//! changing a signature would save:
+//!
+//! ```rust
+//! use clarity::abi::Function;
+//! use clarity::calldata::golf_report;
+//!
+//! let function: Function = "function register(string name, uint256 fee)".parse().unwrap();
+//! let report = golf_report(&function, &["alice".into(), 1_000u32.into()]).unwrap();
+//! let best = report.best();
+//! assert!(best.cost.gas() < report.standard.cost.gas());
+//! println!("{}", report);
+//! ```
+//!
+//! The order of the arguments does not change the size of either the ABI
+//! or the packed encoding, so orderings are not compared.
+use abi::{encode_call, encode_packed, Function, Token};
+use failure::Error;
+use opcodes::{GTXDATANONZERO_EIP2028, GTXDATAZERO};
+use std::fmt;
+use utils::uint256_be_bytes;
+
+/// The zero and non-zero bytes of calldata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CalldataCost {
+    pub zero_bytes: usize,
+    pub non_zero_bytes: usize,
+}
+
+impl CalldataCost {
+    pub fn of(data: &[u8]) -> CalldataCost {
+        let zero_bytes = bytecount::count(data, 0u8);
+        CalldataCost {
+            zero_bytes,
+            non_zero_bytes: data.len() - zero_bytes,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.zero_bytes + self.non_zero_bytes
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The gas the bytes cost, on top of the base cost of a transaction.
+    pub fn gas(&self) -> u64 {
+        self.zero_bytes as u64 * u64::from(GTXDATAZERO)
+            + self.non_zero_bytes as u64 * u64::from(GTXDATANONZERO_EIP2028)
+    }
+}
+
+/// A way of encoding the call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Encoding {
+    pub description: String,
+    /// The signature the function would need, or `None` for packed
+    /// encodings, which the contract has to decode itself
+    pub signature: Option<String>,
+    pub calldata: Vec<u8>,
+    pub cost: CalldataCost,
+}
+
+impl Encoding {
+    fn new(description: String, signature: Option<String>, calldata: Vec<u8>) -> Encoding {
+        Encoding {
+            description,
+            signature,
+            cost: CalldataCost::of(&calldata),
+            calldata,
+        }
+    }
+}
+
+/// The cost of a call as it is and under alternative encodings.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GolfReport {
+    pub standard: Encoding,
+    /// Cheapest first
+    pub alternatives: Vec<Encoding>,
+}
+
+impl GolfReport {
+    /// The cheapest encoding, which is the standard one if no alternative
+    /// saves anything.
+    pub fn best(&self) -> &Encoding {
+        match self.alternatives.first() {
+            Some(best) if best.cost.gas() < self.standard.cost.gas() => best,
+            _ => &self.standard,
+        }
+    }
+
+    /// The gas an encoding saves over the standard one, negative if it costs
+    /// more.
+    pub fn saving(&self, encoding: &Encoding) -> i64 {
+        self.standard.cost.gas() as i64 - encoding.cost.gas() as i64
+    }
+}
+
+/// Whether `encode_packed` accepts the token.
+fn packable(token: &Token) -> bool {
+    match *token {
+        Token::Tuple(_) => false,
+        Token::Dynamic(ref items) | Token::FixedArray(ref items) => items.iter().all(|item| {
+            !item.is_dynamic() && !matches!(*item, Token::Tuple(_) | Token::FixedArray(_))
+        }),
+        _ => true,
+    }
+}
+
+/// A string or bytes short enough for a `bytes32`, padded to it.
+fn as_bytes32(token: &Token) -> Option<Token> {
+    let bytes = match *token {
+        Token::String(ref s) => s.as_bytes(),
+        Token::UnboundedBytes(ref bytes) => bytes,
+        _ => return None,
+    };
+    if bytes.len() > 32 {
+        return None;
+    }
+    let mut word = bytes.to_vec();
+    word.resize(32, 0);
+    Some(Token::Bytes(word))
+}
+
+/// Prices a call of `function` with `args` under the standard encoding and
+/// under each alternative that applies to the arguments:
+///
+/// * every `string` or `bytes` argument of at most 32 bytes passed as a
+///   `bytes32`, one at a time and all together,
+/// * the arguments packed, as `abi.encodePacked` does, after the method id,
+/// * packed with every `uint` argument as wide as its sample needs.
+///
+/// The arguments are type checked against the function first.
+pub fn golf_report(function: &Function, args: &[Token]) -> Result<GolfReport, Error> {
+    function.check_input(args)?;
+    let standard = Encoding::new(
+        "standard".to_owned(),
+        Some(function.signature()),
+        encode_call(&function.signature(), args),
+    );
+
+    let mut alternatives = Vec::new();
+    let mut all = function.clone();
+    let mut all_args = args.to_vec();
+    let mut shortened = 0;
+    for (i, arg) in args.iter().enumerate() {
+        let word = match as_bytes32(arg) {
+            Some(word) => word,
+            None => continue,
+        };
+        let mut alternative = function.clone();
+        alternative.inputs[i].canonical_type = "bytes32".to_owned();
+        let mut alternative_args = args.to_vec();
+        alternative_args[i] = word.clone();
+        let name = &function.inputs[i].name;
+        let description = if name.is_empty() {
+            format!("argument {} as bytes32", i)
+        } else {
+            format!("argument {} ({}) as bytes32", i, name)
+        };
+        alternatives.push(Encoding::new(
+            description,
+            Some(alternative.signature()),
+            encode_call(&alternative.signature(), &alternative_args),
+        ));
+        all.inputs[i].canonical_type = "bytes32".to_owned();
+        all_args[i] = word;
+        shortened += 1;
+    }
+    if shortened > 1 {
+        alternatives.push(Encoding::new(
+            "short strings and bytes as bytes32".to_owned(),
+            Some(all.signature()),
+            encode_call(&all.signature(), &all_args),
+        ));
+    }
+
+    if args.iter().all(packable) {
+        let selector = function.selector();
+        let mut packed = selector.to_vec();
+        packed.extend(encode_packed(args));
+        let packed_len = packed.len();
+        alternatives.push(Encoding::new("packed".to_owned(), None, packed));
+
+        let mut narrowed = selector.to_vec();
+        for arg in args {
+            match *arg {
+                Token::Uint(ref value) => {
+                    let mut buf = [0u8; 32];
+                    let bytes = uint256_be_bytes(value, &mut buf);
+                    if bytes.is_empty() {
+                        narrowed.push(0);
+                    } else {
+                        narrowed.extend(bytes);
+                    }
+                }
+                _ => narrowed.extend(encode_packed(std::slice::from_ref(arg))),
+            }
+        }
+        if narrowed.len() < packed_len {
+            alternatives.push(Encoding::new(
+                "packed, uints as wide as their values".to_owned(),
+                None,
+                narrowed,
+            ));
+        }
+    }
+
+    alternatives.sort_by_key(|encoding| encoding.cost.gas());
+    Ok(GolfReport {
+        standard,
+        alternatives,
+    })
+}
+
+impl fmt::Display for GolfReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>6}  {:>6}  {:>6}  encoding", "gas", "bytes", "saves")?;
+        let mut line = |encoding: &Encoding, last: bool| {
+            write!(
+                f,
+                "{:>6}  {:>6}  {:>6}  {}",
+                encoding.cost.gas(),
+                encoding.cost.len(),
+                self.saving(encoding),
+                encoding.description
+            )?;
+            if let Some(ref signature) = encoding.signature {
+                write!(f, ", {}", signature)?;
+            }
+            if !last {
+                writeln!(f)?;
+            }
+            Ok(())
+        };
+        line(&self.standard, self.alternatives.is_empty())?;
+        for (i, encoding) in self.alternatives.iter().enumerate() {
+            line(encoding, i + 1 == self.alternatives.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn calldata_cost() {
+    let cost = CalldataCost::of(&[0, 1, 0, 0, 0xff]);
+    assert_eq!(
+        cost,
+        CalldataCost {
+            zero_bytes: 3,
+            non_zero_bytes: 2,
+        }
+    );
+    assert_eq!(cost.len(), 5);
+    assert_eq!(cost.gas(), 3 * 4 + 2 * 16);
+    assert!(CalldataCost::of(&[]).is_empty());
+}
+
+#[test]
+fn golf_alternatives() {
+    let function: Function = "function register(string name, bytes data, uint256 fee)"
+        .parse()
+        .unwrap();
+    let args = vec![
+        "alice".into(),
+        Token::UnboundedBytes(vec![0xab; 40]),
+        1_000u32.into(),
+    ];
+    let report = golf_report(&function, &args).unwrap();
+    assert_eq!(
+        report.standard.signature.as_ref().unwrap(),
+        "register(string,bytes,uint256)"
+    );
+    // 4 + 3 head words + 2 length words + 1 word of the name + 2 of the data
+    assert_eq!(report.standard.cost.len(), 4 + 8 * 32);
+
+    let descriptions: Vec<&str> = report
+        .alternatives
+        .iter()
+        .map(|encoding| encoding.description.as_str())
+        .collect();
+    assert_eq!(
+        descriptions,
+        vec![
+            "packed, uints as wide as their values",
+            "packed",
+            "argument 0 (name) as bytes32"
+        ]
+    );
+    let bytes32 = &report.alternatives[2];
+    assert_eq!(
+        bytes32.signature.as_ref().unwrap(),
+        "register(bytes32,bytes,uint256)"
+    );
+    assert_eq!(bytes32.cost.len(), report.standard.cost.len() - 2 * 32);
+    assert!(report.saving(bytes32) > 0);
+    let narrowed = report.best();
+    assert_eq!(narrowed.calldata.len(), 4 + 5 + 40 + 2);
+    assert_eq!(
+        &narrowed.calldata[narrowed.calldata.len() - 2..],
+        &[0x03, 0xe8]
+    );
+
+    let listing = report.to_string();
+    assert!(listing.starts_with("   gas   bytes   saves  encoding\n"));
+    assert!(listing.contains("      0  standard, register(string,bytes,uint256)\n"));
+
+    // Tuples are not packed, and arguments have to match
+    let function: Function = "function f((uint256,bool) pair)".parse().unwrap();
+    let pair = Token::Tuple(vec![1u8.into(), true.into()]);
+    assert!(golf_report(&function, &[pair])
+        .unwrap()
+        .alternatives
+        .is_empty());
+    assert!(golf_report(&function, &[1u8.into()]).is_err());
+}
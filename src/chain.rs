@@ -0,0 +1,453 @@
+//! Tracking the head of the chain through reorgs.
+//!
+//! [ChainTracker](struct.ChainTracker.html) keeps the most recent blocks it
+//! has seen along with the transactions they include. Blocks are linked by
+//! parent hash, so a block that does not fit the tracked chain reveals a
+//! reorg, and the transactions of the blocks it replaces are reported as
+//! invalidated.
+//!
+//! [ConfirmationPolicy](enum.ConfirmationPolicy.html) decides when an
+//! included transaction, such as an incoming payment, is final.
+use failure::Error;
+use num256::Uint256;
+use std::collections::{HashMap, VecDeque};
+
+/// The parts of a block header needed to follow the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockHeader {
+    pub number: u64,
+    pub hash: [u8; 32],
+    pub parent_hash: [u8; 32],
+}
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ChainError {
+    /// The parent of the block is not tracked. Ingest the block with this
+    /// number (the parent of the rejected header) first, then try again.
+    #[fail(display = "Parent block {} has to be ingested first", _0)]
+    MissingParent(u64),
+    /// The block would replace blocks that are no longer tracked
+    #[fail(display = "Reorg goes deeper than the tracked {} blocks", _0)]
+    ReorgTooDeep(usize),
+}
+
+/// What ingesting a block did to the tracked chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainUpdate {
+    /// The block extends the tracked chain
+    Extended,
+    /// The block was already tracked
+    Known,
+    /// The block replaced the tracked blocks from `fork_block` onwards
+    Reorg {
+        /// Number of the first block that was replaced
+        fork_block: u64,
+        /// Number of blocks that were replaced
+        depth: u64,
+        /// Transactions of the replaced blocks that are not included in the
+        /// new block
+        invalidated: Vec<[u8; 32]>,
+    },
+}
+
+#[derive(Debug, Clone)]
+struct TrackedBlock {
+    header: BlockHeader,
+    transactions: Vec<[u8; 32]>,
+}
+
+/// Follows the head of the chain, detecting reorgs up to a fixed depth.
+#[derive(Debug, Clone)]
+pub struct ChainTracker {
+    depth: usize,
+    /// Tracked blocks, oldest first; numbers are consecutive
+    blocks: VecDeque<TrackedBlock>,
+    /// Block number of every tracked transaction
+    transactions: HashMap<[u8; 32], u64>,
+}
+
+impl ChainTracker {
+    /// Creates a tracker that remembers the last `depth` blocks, which is
+    /// also the deepest reorg it can handle.
+    pub fn new(depth: usize) -> ChainTracker {
+        assert!(depth > 0, "A tracker has to keep at least one block");
+        ChainTracker {
+            depth,
+            blocks: VecDeque::new(),
+            transactions: HashMap::new(),
+        }
+    }
+
+    /// The most recent block.
+    pub fn head(&self) -> Option<&BlockHeader> {
+        self.blocks.back().map(|block| &block.header)
+    }
+
+    /// The tracked block with the given number.
+    pub fn block(&self, number: u64) -> Option<&BlockHeader> {
+        self.index_of(number).map(|i| &self.blocks[i].header)
+    }
+
+    /// Number of the tracked block that includes a transaction.
+    pub fn transaction_block(&self, hash: &[u8; 32]) -> Option<u64> {
+        self.transactions.get(hash).cloned()
+    }
+
+    /// Number of confirmations of a transaction, counting the block that
+    /// includes it, or `None` if it is not in a tracked block.
+    pub fn confirmations(&self, hash: &[u8; 32]) -> Option<u64> {
+        let block = self.transaction_block(hash)?;
+        Some(self.head()?.number - block + 1)
+    }
+
+    /// Whether a tracked transaction moving `value` is final under `policy`,
+    /// given the number of the latest finalized block if the node reports
+    /// one. Transactions that are not in a tracked block are not final.
+    pub fn is_final(
+        &self,
+        policy: &ConfirmationPolicy,
+        hash: &[u8; 32],
+        value: &Uint256,
+        finalized: Option<u64>,
+    ) -> bool {
+        match (self.transaction_block(hash), self.head()) {
+            (Some(block_number), Some(head)) => policy.is_final(
+                value,
+                block_number,
+                &ChainHeads {
+                    latest: head.number,
+                    finalized,
+                },
+            ),
+            _ => false,
+        }
+    }
+
+    fn index_of(&self, number: u64) -> Option<usize> {
+        let oldest = self.blocks.front()?.header.number;
+        if number < oldest {
+            return None;
+        }
+        let index = (number - oldest) as usize;
+        if index < self.blocks.len() {
+            Some(index)
+        } else {
+            None
+        }
+    }
+
+    /// Adds a block and the hashes of the transactions it includes.
+    ///
+    /// The first block is accepted as is. After that, a block has to be a
+    /// child of a tracked block. If it is not, the tracker asks for its
+    /// parent with [MissingParent](enum.ChainError.html#variant.MissingParent),
+    /// which is how a reorg is walked back to where it forked.
+    pub fn ingest(
+        &mut self,
+        header: BlockHeader,
+        transactions: Vec<[u8; 32]>,
+    ) -> Result<ChainUpdate, Error> {
+        if self.blocks.is_empty() {
+            self.push(header, transactions);
+            return Ok(ChainUpdate::Extended);
+        }
+        if let Some(index) = self.index_of(header.number) {
+            if self.blocks[index].header.hash == header.hash {
+                return Ok(ChainUpdate::Known);
+            }
+        }
+        let oldest = self.blocks.front().unwrap().header.number;
+        let head = self.head().unwrap().number;
+        let parent_number = match header.number.checked_sub(1) {
+            Some(parent_number) => parent_number,
+            None => return Err(ChainError::ReorgTooDeep(self.depth).into()),
+        };
+        if parent_number > head {
+            return Err(ChainError::MissingParent(parent_number).into());
+        }
+        if parent_number < oldest {
+            return Err(ChainError::ReorgTooDeep(self.depth).into());
+        }
+        let parent = &self.blocks[self.index_of(parent_number).unwrap()].header;
+        if parent.hash != header.parent_hash {
+            if parent_number == oldest {
+                return Err(ChainError::ReorgTooDeep(self.depth).into());
+            }
+            return Err(ChainError::MissingParent(parent_number).into());
+        }
+        if parent_number == head {
+            self.push(header, transactions);
+            return Ok(ChainUpdate::Extended);
+        }
+        // The block forks off the tracked chain right after its parent
+        let keep = self.index_of(header.number).unwrap();
+        let replaced: Vec<TrackedBlock> = self.blocks.drain(keep..).collect();
+        let mut invalidated = Vec::new();
+        for block in &replaced {
+            for hash in &block.transactions {
+                self.transactions.remove(hash);
+                if !transactions.contains(hash) {
+                    invalidated.push(*hash);
+                }
+            }
+        }
+        self.push(header, transactions);
+        Ok(ChainUpdate::Reorg {
+            fork_block: header.number,
+            depth: replaced.len() as u64,
+            invalidated,
+        })
+    }
+
+    fn push(&mut self, header: BlockHeader, transactions: Vec<[u8; 32]>) {
+        for hash in &transactions {
+            self.transactions.insert(*hash, header.number);
+        }
+        self.blocks.push_back(TrackedBlock {
+            header,
+            transactions,
+        });
+        while self.blocks.len() > self.depth {
+            let oldest = self.blocks.pop_front().unwrap();
+            for hash in &oldest.transactions {
+                self.transactions.remove(hash);
+            }
+        }
+    }
+}
+
+/// The latest and finalized block numbers, as reported by the `latest` and
+/// `finalized` block tags.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainHeads {
+    pub latest: u64,
+    /// `None` for nodes that do not support the `finalized` tag
+    pub finalized: Option<u64>,
+}
+
+/// When a transaction counts as final.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfirmationPolicy {
+    /// Once it has this many confirmations, counting the block that
+    /// includes it
+    Blocks(u64),
+    /// Once the block that includes it is finalized
+    Finalized,
+    /// Depending on the value moved: the policy of the highest threshold the
+    /// value reaches applies, or `default` if it reaches none of them
+    ByValue {
+        default: Box<ConfirmationPolicy>,
+        thresholds: Vec<(Uint256, ConfirmationPolicy)>,
+    },
+}
+
+impl ConfirmationPolicy {
+    /// Whether a transaction moving `value`, included in the block
+    /// `block_number`, is final.
+    pub fn is_final(&self, value: &Uint256, block_number: u64, heads: &ChainHeads) -> bool {
+        match *self {
+            ConfirmationPolicy::Blocks(confirmations) => {
+                heads.latest >= block_number && heads.latest - block_number + 1 >= confirmations
+            }
+            ConfirmationPolicy::Finalized => heads
+                .finalized
+                .is_some_and(|finalized| finalized >= block_number),
+            ConfirmationPolicy::ByValue {
+                ref default,
+                ref thresholds,
+            } => thresholds
+                .iter()
+                .filter(|(threshold, _)| value >= threshold)
+                .max_by(|a, b| a.0.cmp(&b.0))
+                .map_or(&**default, |(_, policy)| policy)
+                .is_final(value, block_number, heads),
+        }
+    }
+}
+
+#[cfg(test)]
+fn header(number: u64, fork: u8, parent_fork: u8) -> BlockHeader {
+    let hash = |number: u64, fork: u8| {
+        let mut hash = [fork; 32];
+        hash[..8].copy_from_slice(&number.to_be_bytes());
+        hash
+    };
+    BlockHeader {
+        number,
+        hash: hash(number, fork),
+        parent_hash: hash(number.wrapping_sub(1), parent_fork),
+    }
+}
+
+#[test]
+fn follows_chain() {
+    let mut tracker = ChainTracker::new(10);
+    let tx = [0xaau8; 32];
+    assert_eq!(
+        tracker.ingest(header(100, 0, 0), vec![tx]).unwrap(),
+        ChainUpdate::Extended
+    );
+    assert_eq!(tracker.confirmations(&tx), Some(1));
+    for number in 101..105 {
+        assert_eq!(
+            tracker.ingest(header(number, 0, 0), vec![]).unwrap(),
+            ChainUpdate::Extended
+        );
+    }
+    assert_eq!(tracker.confirmations(&tx), Some(5));
+    assert_eq!(tracker.head().unwrap().number, 104);
+    assert_eq!(
+        tracker.ingest(header(103, 0, 0), vec![]).unwrap(),
+        ChainUpdate::Known
+    );
+}
+
+#[test]
+fn forgets_old_blocks() {
+    let mut tracker = ChainTracker::new(3);
+    let tx = [0xaau8; 32];
+    tracker.ingest(header(1, 0, 0), vec![tx]).unwrap();
+    for number in 2..5 {
+        tracker.ingest(header(number, 0, 0), vec![]).unwrap();
+    }
+    assert_eq!(tracker.block(1), None);
+    assert_eq!(tracker.transaction_block(&tx), None);
+    assert!(tracker.block(2).is_some());
+}
+
+#[test]
+fn detects_reorg() {
+    let mut tracker = ChainTracker::new(10);
+    let kept = [0x01u8; 32];
+    let dropped = [0x02u8; 32];
+    let moved = [0x03u8; 32];
+    tracker.ingest(header(10, 0, 0), vec![]).unwrap();
+    tracker.ingest(header(11, 0, 0), vec![kept]).unwrap();
+    tracker
+        .ingest(header(12, 0, 0), vec![dropped, moved])
+        .unwrap();
+    tracker.ingest(header(13, 0, 0), vec![]).unwrap();
+
+    // Block 13 of another fork, whose 12 is not known yet
+    assert_eq!(
+        tracker
+            .ingest(header(13, 1, 1), vec![])
+            .unwrap_err()
+            .downcast::<ChainError>()
+            .unwrap(),
+        ChainError::MissingParent(12)
+    );
+    // Its 12 forks off our 11
+    assert_eq!(
+        tracker.ingest(header(12, 1, 0), vec![moved]).unwrap(),
+        ChainUpdate::Reorg {
+            fork_block: 12,
+            depth: 2,
+            invalidated: vec![dropped],
+        }
+    );
+    assert_eq!(
+        tracker.ingest(header(13, 1, 1), vec![]).unwrap(),
+        ChainUpdate::Extended
+    );
+    assert_eq!(tracker.transaction_block(&kept), Some(11));
+    assert_eq!(tracker.transaction_block(&moved), Some(12));
+    assert_eq!(tracker.transaction_block(&dropped), None);
+    assert_eq!(tracker.confirmations(&moved), Some(2));
+}
+
+#[test]
+fn rejects_unknown_blocks() {
+    let mut tracker = ChainTracker::new(2);
+    tracker.ingest(header(10, 0, 0), vec![]).unwrap();
+    tracker.ingest(header(11, 0, 0), vec![]).unwrap();
+    // A gap
+    assert_eq!(
+        tracker
+            .ingest(header(13, 0, 0), vec![])
+            .unwrap_err()
+            .downcast::<ChainError>()
+            .unwrap(),
+        ChainError::MissingParent(12)
+    );
+    // A fork below the tracked blocks
+    assert_eq!(
+        tracker
+            .ingest(header(11, 1, 1), vec![])
+            .unwrap_err()
+            .downcast::<ChainError>()
+            .unwrap(),
+        ChainError::ReorgTooDeep(2)
+    );
+    assert_eq!(
+        tracker
+            .ingest(header(10, 1, 1), vec![])
+            .unwrap_err()
+            .downcast::<ChainError>()
+            .unwrap(),
+        ChainError::ReorgTooDeep(2)
+    );
+}
+
+#[test]
+fn confirmation_policies() {
+    let heads = ChainHeads {
+        latest: 110,
+        finalized: Some(100),
+    };
+    let value: Uint256 = 1u8.into();
+    assert!(ConfirmationPolicy::Blocks(1).is_final(&value, 110, &heads));
+    assert!(!ConfirmationPolicy::Blocks(2).is_final(&value, 110, &heads));
+    assert!(ConfirmationPolicy::Blocks(11).is_final(&value, 100, &heads));
+    // Not included yet as far as the node is concerned
+    assert!(!ConfirmationPolicy::Blocks(1).is_final(&value, 111, &heads));
+
+    assert!(ConfirmationPolicy::Finalized.is_final(&value, 100, &heads));
+    assert!(!ConfirmationPolicy::Finalized.is_final(&value, 101, &heads));
+    assert!(!ConfirmationPolicy::Finalized.is_final(
+        &value,
+        1,
+        &ChainHeads {
+            latest: 110,
+            finalized: None
+        }
+    ));
+}
+
+#[test]
+fn confirmation_by_value() {
+    let policy = ConfirmationPolicy::ByValue {
+        default: Box::new(ConfirmationPolicy::Blocks(1)),
+        thresholds: vec![
+            (1_000_000u32.into(), ConfirmationPolicy::Finalized),
+            (1_000u32.into(), ConfirmationPolicy::Blocks(6)),
+        ],
+    };
+    let heads = ChainHeads {
+        latest: 105,
+        finalized: Some(90),
+    };
+    assert!(policy.is_final(&999u32.into(), 105, &heads));
+    assert!(!policy.is_final(&1_000u32.into(), 101, &heads));
+    assert!(policy.is_final(&1_000u32.into(), 100, &heads));
+    assert!(!policy.is_final(&2_000_000u32.into(), 95, &heads));
+    assert!(policy.is_final(&2_000_000u32.into(), 90, &heads));
+}
+
+#[test]
+fn tracked_finality() {
+    let mut tracker = ChainTracker::new(10);
+    let tx = [0xaau8; 32];
+    let policy = ConfirmationPolicy::Blocks(3);
+    let value: Uint256 = 1u8.into();
+    tracker.ingest(header(1, 0, 0), vec![]).unwrap();
+    tracker.ingest(header(2, 0, 0), vec![tx]).unwrap();
+    tracker.ingest(header(3, 0, 0), vec![]).unwrap();
+    assert!(!tracker.is_final(&policy, &tx, &value, None));
+    tracker.ingest(header(4, 0, 0), vec![]).unwrap();
+    assert!(tracker.is_final(&policy, &tx, &value, None));
+    // A reorg undoes it
+    tracker.ingest(header(2, 1, 0), vec![]).unwrap();
+    assert!(!tracker.is_final(&policy, &tx, &value, None));
+    assert!(!tracker.is_final(&policy, &[0u8; 32], &value, None));
+}
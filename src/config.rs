@@ -0,0 +1,307 @@
+//! Policy for decoding and signing, set once.
+//!
+//! Applications that decode untrusted transactions and sign their own
+//! usually want the same rules everywhere: the same RLP limits, the same
+//! treatment of address checksums, the same accepted transaction types and
+//! the same chain. A [ClarityConfig](struct.ClarityConfig.html) holds those
+//! rules. It is built when the application starts and never changes after
+//! that, so it can be shared between threads, e.g. in an `Arc`, and passed
+//! to whatever decodes or signs:
+//!
+//! ```rust
+//! use clarity::{ClarityConfig, PrivateKey, Transaction, TypedTransaction};
+//!
+//! let config = ClarityConfig {
+//!     chain_id: Some(1),
+//!     strict: true,
+//!     ..ClarityConfig::default()
+//! };
+//! let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+//!     .parse()
+//!     .unwrap();
+//! let tx = Transaction {
+//!     nonce: 0u8.into(),
+//!     gas_price: 1_000_000_000u64.into(),
+//!     gas_limit: 21_000u32.into(),
+//!     to: config.parse_address("0x1111111111111111111111111111111111111111").unwrap(),
+//!     value: 1u8.into(),
+//!     data: Vec::new(),
+//!     signature: None,
+//! };
+//! let signed = config.sign(&tx.into(), &key).unwrap();
+//! let decoded = config.decode_transaction(&signed.to_bytes().unwrap()).unwrap();
+//! assert_eq!(decoded, signed);
+//! ```
+use address::Address;
+use failure::Error;
+use private_key::PrivateKey;
+use rlp::{decode_item_with, DecodeConfig};
+use transaction::{
+    TypedTransaction, ValidityIssue, EIP1559_TX_TYPE, EIP2930_TX_TYPE, EIP4844_TX_TYPE,
+    EIP7702_TX_TYPE,
+};
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ConfigError {
+    #[fail(display = "Transactions of type {} are not allowed", _0)]
+    TxTypeNotAllowed(u8),
+    #[fail(display = "Address is not checksummed")]
+    MissingChecksum,
+    #[fail(display = "Transaction is rejected: {}", _0)]
+    Rejected(ValidityIssue),
+}
+
+/// How the EIP-55 checksums of addresses are treated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumPolicy {
+    /// Any case is accepted, without checking
+    Ignore,
+    /// Mixed-case addresses have to match their checksum, addresses in a
+    /// single case carry none and are accepted, as
+    /// [Address::parse_and_validate](../struct.Address.html#method.parse_and_validate)
+    /// does
+    Validate,
+    /// Addresses have to be checksummed
+    Require,
+}
+
+/// Rules for decoding and signing, see the [module](index.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClarityConfig {
+    /// Limits of the RLP of decoded transactions. RLP is always decoded
+    /// strictly, rejecting non-canonical encodings and trailing bytes.
+    pub rlp: DecodeConfig,
+    /// Whether transactions with any
+    /// [validity issue](../enum.ValidityIssue.html) are rejected, instead of
+    /// only those for another chain
+    pub strict: bool,
+    pub checksum: ChecksumPolicy,
+    /// Type bytes of the transactions that are decoded and signed, 0 being
+    /// legacy transactions
+    pub allowed_tx_types: Vec<u8>,
+    /// The chain transactions are signed for, and decoded transactions have
+    /// to be for. Without one, legacy transactions are signed without
+    /// replay protection.
+    pub chain_id: Option<u64>,
+}
+
+/// Every transaction type the crate supports.
+const ALL_TX_TYPES: [u8; 5] = [
+    0,
+    EIP2930_TX_TYPE,
+    EIP1559_TX_TYPE,
+    EIP4844_TX_TYPE,
+    EIP7702_TX_TYPE,
+];
+
+impl Default for ClarityConfig {
+    /// The rules of the crate's own functions: no limits besides the
+    /// nesting of RLP, validated checksums, every transaction type and no
+    /// chain.
+    fn default() -> ClarityConfig {
+        ClarityConfig {
+            rlp: DecodeConfig::default(),
+            strict: false,
+            checksum: ChecksumPolicy::Validate,
+            allowed_tx_types: ALL_TX_TYPES.to_vec(),
+            chain_id: None,
+        }
+    }
+}
+
+impl ClarityConfig {
+    /// Parses an address, checking its checksum as configured.
+    pub fn parse_address(&self, s: &str) -> Result<Address, Error> {
+        match self.checksum {
+            ChecksumPolicy::Ignore => s.parse(),
+            ChecksumPolicy::Validate => Address::parse_and_validate(s),
+            ChecksumPolicy::Require => {
+                let address: Address = s.parse()?;
+                let digits = s.strip_prefix("0x").unwrap_or(s);
+                if address.to_checksum_string()[2..] != *digits {
+                    return Err(ConfigError::MissingChecksum.into());
+                }
+                Ok(address)
+            }
+        }
+    }
+
+    fn check_tx_type(&self, tx_type: u8) -> Result<(), ConfigError> {
+        if self.allowed_tx_types.contains(&tx_type) {
+            Ok(())
+        } else {
+            Err(ConfigError::TxTypeNotAllowed(tx_type))
+        }
+    }
+
+    /// Checks a transaction against the configured chain and, when
+    /// `strict`, against every rule of its validity report.
+    pub fn check_transaction(&self, tx: &TypedTransaction) -> Result<(), Error> {
+        self.check_tx_type(tx.tx_type())?;
+        let report = tx.validity_report(self.chain_id);
+        let rejected = report.issues.into_iter().find(|issue| {
+            self.strict
+                || matches!(
+                    *issue,
+                    ValidityIssue::NetworkIdMismatch { .. } | ValidityIssue::MissingNetworkId
+                )
+        });
+        match rejected {
+            Some(issue) => Err(ConfigError::Rejected(issue).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Decodes an untrusted transaction, such as the argument of
+    /// `eth_sendRawTransaction`, and checks it with
+    /// [check_transaction](#method.check_transaction).
+    ///
+    /// The type and the RLP limits are checked before the transaction is
+    /// decoded.
+    pub fn decode_transaction(&self, raw: &[u8]) -> Result<TypedTransaction, Error> {
+        let payload = match raw.first() {
+            Some(&prefix) if prefix >= 0xc0 => {
+                self.check_tx_type(0)?;
+                raw
+            }
+            Some(&tx_type) => {
+                self.check_tx_type(tx_type)?;
+                &raw[1..]
+            }
+            None => raw,
+        };
+        decode_item_with(payload, &self.rlp)?;
+        let tx = TypedTransaction::from_bytes(raw)?;
+        self.check_transaction(&tx)?;
+        Ok(tx)
+    }
+
+    /// Signs a transaction for the configured chain, after checking that
+    /// its type is allowed. Typed transactions have to be for that chain.
+    pub fn sign(&self, tx: &TypedTransaction, key: &PrivateKey) -> Result<TypedTransaction, Error> {
+        self.check_tx_type(tx.tx_type())?;
+        let signed = tx.sign(key, self.chain_id)?;
+        if self.strict {
+            self.check_transaction(&signed)?;
+        }
+        Ok(signed)
+    }
+}
+
+#[test]
+fn config_is_shareable() {
+    fn shareable<T: Send + Sync>() {}
+    shareable::<ClarityConfig>();
+}
+
+#[test]
+fn checksum_policies() {
+    let checksummed = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    let lower = checksummed.to_lowercase();
+    let wrong = "0x5aaeb6053F3E94C9b9A09f33669435E7Ef1BeAed";
+    let config = |checksum| ClarityConfig {
+        checksum,
+        ..ClarityConfig::default()
+    };
+
+    let ignore = config(ChecksumPolicy::Ignore);
+    assert!(ignore.parse_address(wrong).is_ok());
+    let validate = config(ChecksumPolicy::Validate);
+    assert!(validate.parse_address(&lower).is_ok());
+    assert!(validate.parse_address(wrong).is_err());
+    let require = config(ChecksumPolicy::Require);
+    assert_eq!(
+        require.parse_address(checksummed).unwrap(),
+        lower.parse().unwrap()
+    );
+    assert!(require.parse_address(&lower).is_err());
+    assert!(require.parse_address(wrong).is_err());
+}
+
+#[test]
+fn transaction_policies() {
+    use transaction::{Eip1559Transaction, Transaction};
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let legacy: TypedTransaction = Transaction {
+        nonce: 0u8.into(),
+        gas_price: 1u8.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 1u8.into(),
+        data: Vec::new(),
+        signature: None,
+    }
+    .into();
+    let eip1559: TypedTransaction = Eip1559Transaction {
+        chain_id: 5u8.into(),
+        nonce: 0u8.into(),
+        max_priority_fee_per_gas: 1u8.into(),
+        max_fee_per_gas: 1u8.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 1u8.into(),
+        data: Vec::new(),
+        access_list: Vec::new(),
+        signature: None,
+    }
+    .into();
+
+    let mainnet = ClarityConfig {
+        chain_id: Some(1),
+        allowed_tx_types: vec![0, 2],
+        ..ClarityConfig::default()
+    };
+    let signed = mainnet.sign(&legacy, &key).unwrap();
+    assert_eq!(
+        mainnet
+            .decode_transaction(&signed.to_bytes().unwrap())
+            .unwrap(),
+        signed
+    );
+    // Typed transactions for another chain are neither signed nor decoded
+    assert!(mainnet.sign(&eip1559, &key).is_err());
+    let goerli = eip1559.sign(&key, None).unwrap().to_bytes().unwrap();
+    assert!(mainnet.decode_transaction(&goerli).is_err());
+    assert!(ClarityConfig::default().decode_transaction(&goerli).is_ok());
+    // Nor are legacy transactions without replay protection
+    let unprotected = legacy.sign(&key, None).unwrap().to_bytes().unwrap();
+    assert!(mainnet.decode_transaction(&unprotected).is_err());
+
+    let legacy_only = ClarityConfig {
+        allowed_tx_types: vec![0],
+        ..ClarityConfig::default()
+    };
+    assert_eq!(
+        legacy_only
+            .decode_transaction(&goerli)
+            .unwrap_err()
+            .downcast::<ConfigError>()
+            .unwrap(),
+        ConfigError::TxTypeNotAllowed(2)
+    );
+
+    let limited = ClarityConfig {
+        rlp: DecodeConfig {
+            max_item_len: 8,
+            ..DecodeConfig::default()
+        },
+        ..ClarityConfig::default()
+    };
+    assert!(limited.decode_transaction(&unprotected).is_err());
+
+    // Strict configurations also reject what only breaks network rules,
+    // such as a gas limit below the intrinsic gas
+    let strict = ClarityConfig {
+        strict: true,
+        ..ClarityConfig::default()
+    };
+    let mut cheap = legacy.clone();
+    if let TypedTransaction::Legacy(ref mut tx) = cheap {
+        tx.gas_limit = 1u8.into();
+    }
+    let cheap = cheap.sign(&key, Some(1)).unwrap().to_bytes().unwrap();
+    assert!(ClarityConfig::default().decode_transaction(&cheap).is_ok());
+    assert!(strict.decode_transaction(&cheap).is_err());
+}
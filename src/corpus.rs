@@ -0,0 +1,307 @@
+//! Boundary cases of ABI and RLP encodings, for seeding fuzzers and testing
+//! decoders.
+//!
+//! [abi_corpus](fn.abi_corpus.html) and [rlp_corpus](fn.rlp_corpus.html)
+//! always return the same inputs in the same order: valid encodings at the
+//! edges of what is allowed, and the ways of getting them slightly wrong,
+//! such as offsets and lengths past the end of the data, non-canonical
+//! headers, nesting past the limits and truncated payloads. A fuzzer that
+//! starts from them reaches the interesting branches of a decoder much
+//! sooner than one that starts from nothing.
+//!
+//! ```rust
+//! use clarity::abi::{decode_tokens, ParamType};
+//! use clarity::corpus::abi_corpus;
+//!
+//! for seed in abi_corpus() {
+//!     // Decoding returns an error rather than panicking, whatever the input
+//!     let _ = decode_tokens(&[ParamType::Bytes], &seed.data);
+//! }
+//! ```
+//!
+//! [write_corpus](fn.write_corpus.html) writes a corpus to a directory, one
+//! file per input, the way cargo-fuzz and AFL read them.
+use abi::{encode_tokens, Token};
+use rlp::{append_bytes, append_header, MAX_DEPTH};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// An input of a corpus.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Seed {
+    /// What the input is a case of, unique within its corpus, and usable as
+    /// a file name
+    pub name: String,
+    pub data: Vec<u8>,
+}
+
+impl Seed {
+    fn new<S: Into<String>>(name: S, data: Vec<u8>) -> Seed {
+        Seed {
+            name: name.into(),
+            data,
+        }
+    }
+}
+
+/// A big endian word holding `value`.
+fn word(value: u64) -> Vec<u8> {
+    let mut word = vec![0u8; 24];
+    word.extend_from_slice(&value.to_be_bytes());
+    word
+}
+
+/// Words concatenated.
+fn words(words: &[Vec<u8>]) -> Vec<u8> {
+    words.concat()
+}
+
+/// Adds the truncations of an encoding: every prefix that ends on a word
+/// boundary, and the encoding without its last byte.
+fn truncations(name: &str, data: &[u8], seeds: &mut Vec<Seed>) {
+    for end in (0..data.len()).step_by(32).skip(1) {
+        seeds.push(Seed::new(
+            format!("{}_truncated_{}", name, end),
+            data[..end].to_vec(),
+        ));
+    }
+    if !data.is_empty() {
+        seeds.push(Seed::new(
+            format!("{}_truncated_{}", name, data.len() - 1),
+            data[..data.len() - 1].to_vec(),
+        ));
+    }
+}
+
+/// Array of `depth` nested arrays, the innermost one being empty.
+fn nested_arrays(depth: usize) -> Token {
+    (0..depth).fold(Token::Dynamic(Vec::new()), |inner, _| {
+        Token::Dynamic(vec![inner])
+    })
+}
+
+/// Boundary cases of ABI encoded parameters, without a method id.
+///
+/// Most are meant as the parameters or return data of a function with a
+/// single dynamic parameter, such as `bytes`, `string`, `uint256[]` or
+/// `uint256[][]`, whose head is an offset.
+pub fn abi_corpus() -> Vec<Seed> {
+    let mut seeds = vec![
+        Seed::new("empty", Vec::new()),
+        Seed::new("zero_word", word(0)),
+        Seed::new("max_word", vec![0xff; 32]),
+        // Static values that do not fit their type, if it is a bool or an
+        // address
+        Seed::new("bool_of_two", word(2)),
+        Seed::new("address_with_dirty_upper_bytes", {
+            let mut word = vec![0x11; 32];
+            word[0] = 0x01;
+            word
+        }),
+        Seed::new("short_word", vec![0; 31]),
+        // Offsets
+        Seed::new("offset_to_itself", words(&[word(0)])),
+        Seed::new("offset_to_end", words(&[word(0x20)])),
+        Seed::new("offset_past_end", words(&[word(0x40), word(0)])),
+        Seed::new("offset_unaligned", words(&[word(0x21), word(0), word(0)])),
+        Seed::new("offset_u64_max", words(&[word(u64::MAX), word(0)])),
+        Seed::new("offset_max_word", words(&[vec![0xff; 32], word(0)])),
+        // Lengths
+        Seed::new("length_zero", words(&[word(0x20), word(0)])),
+        Seed::new(
+            "length_one_past_end",
+            words(&[word(0x20), word(33), word(0)]),
+        ),
+        Seed::new("length_u64_max", words(&[word(0x20), word(u64::MAX)])),
+        Seed::new("length_max_word", words(&[word(0x20), vec![0xff; 32]])),
+        // Payloads
+        Seed::new(
+            "bytes_with_dirty_padding",
+            words(&[word(0x20), word(1), vec![0x61; 32]]),
+        ),
+        Seed::new(
+            "string_invalid_utf8",
+            words(&[word(0x20), word(2), {
+                let mut word = vec![0xc3, 0x28];
+                word.resize(32, 0);
+                word
+            }]),
+        ),
+        Seed::new(
+            "bytes_exactly_one_word",
+            encode_tokens(&[Token::UnboundedBytes(vec![0xab; 32])]),
+        ),
+        Seed::new(
+            "bytes_one_past_word",
+            encode_tokens(&[Token::UnboundedBytes(vec![0xab; 33])]),
+        ),
+        Seed::new("array_of_empty_array", encode_tokens(&[nested_arrays(1)])),
+        // Decoders that recurse into nested arrays should limit the depth
+        Seed::new("arrays_nested_32", encode_tokens(&[nested_arrays(32)])),
+        Seed::new("arrays_nested_256", encode_tokens(&[nested_arrays(256)])),
+        // Two dynamic values sharing a tail
+        Seed::new(
+            "shared_tail",
+            words(&[word(0x40), word(0x40), word(1), vec![0x61; 32]]),
+        ),
+    ];
+
+    let string = encode_tokens(&["The quick brown fox jumps over the lazy dog".into()]);
+    truncations("string", &string, &mut seeds);
+    let strings = encode_tokens(&[Token::Dynamic(vec!["a".into(), "bc".into()])]);
+    truncations("strings", &strings, &mut seeds);
+    let numbers = encode_tokens(&[Token::Dynamic(vec![1u8.into(), 2u8.into(), 3u8.into()])]);
+    truncations("numbers", &numbers, &mut seeds);
+    seeds
+}
+
+/// `depth` nested lists, the innermost one being empty.
+fn nested_lists(depth: usize) -> Vec<u8> {
+    (1..depth).fold(vec![0xc0], |inner, _| {
+        let mut list = Vec::new();
+        append_header(0xc0, inner.len(), &mut list);
+        list.extend(inner);
+        list
+    })
+}
+
+/// A string with the header of a long string, for a payload that a short
+/// one would hold.
+fn long_header(payload: &[u8]) -> Vec<u8> {
+    let mut data = vec![0xb8, payload.len() as u8];
+    data.extend_from_slice(payload);
+    data
+}
+
+/// Boundary cases of RLP items.
+pub fn rlp_corpus() -> Vec<Seed> {
+    let string = |len: usize| {
+        let mut out = Vec::new();
+        append_bytes(&vec![0x61; len], &mut out);
+        out
+    };
+    let list = |items: &[Vec<u8>]| {
+        let payload = items.concat();
+        let mut out = Vec::new();
+        append_header(0xc0, payload.len(), &mut out);
+        out.extend(payload);
+        out
+    };
+    let mut seeds = vec![
+        Seed::new("empty", Vec::new()),
+        // Single bytes and short strings
+        Seed::new("byte_zero", vec![0x00]),
+        Seed::new("byte_max", vec![0x7f]),
+        Seed::new("empty_string", vec![0x80]),
+        Seed::new("single_byte_as_string", vec![0x81, 0x00]),
+        Seed::new("single_byte_max_as_string", vec![0x81, 0x7f]),
+        Seed::new("single_byte_min_string", vec![0x81, 0x80]),
+        Seed::new("string_55", string(55)),
+        Seed::new("string_56", string(56)),
+        Seed::new("string_256", string(256)),
+        // Non-canonical and impossible headers
+        Seed::new("short_string_long_header", long_header(&[0x61; 55])),
+        Seed::new("long_header_zero_length", vec![0xb8, 0x00]),
+        Seed::new("long_length_leading_zero", {
+            let mut data = vec![0xb9, 0x00, 0x38];
+            data.extend_from_slice(&[0x61; 56]);
+            data
+        }),
+        Seed::new("length_u64_max", {
+            let mut data = vec![0xbf];
+            data.extend_from_slice(&[0xff; 8]);
+            data
+        }),
+        Seed::new("list_length_u64_max", {
+            let mut data = vec![0xff];
+            data.extend_from_slice(&[0xff; 8]);
+            data
+        }),
+        Seed::new("missing_length_bytes", vec![0xbb, 0x01]),
+        Seed::new("string_past_end", vec![0x83, 0x61, 0x62]),
+        Seed::new("trailing_bytes", vec![0x80, 0x80]),
+        // Lists
+        Seed::new("empty_list", vec![0xc0]),
+        Seed::new("list_of_empty_lists", vec![0xc3, 0xc0, 0xc0, 0xc0]),
+        Seed::new("list_payload_55", list(&[string(53)])),
+        Seed::new("list_payload_56", list(&[string(54)])),
+        Seed::new("short_list_long_header", vec![0xf8, 0x01, 0x80]),
+        Seed::new("list_past_end", vec![0xc2, 0x80]),
+        Seed::new("list_item_past_list", vec![0xc1, 0x82, 0x61, 0x62]),
+        Seed::new("lists_nested_to_limit", nested_lists(MAX_DEPTH)),
+        Seed::new("lists_nested_past_limit", nested_lists(MAX_DEPTH + 1)),
+        // Decoders without a depth limit overflow their stack
+        Seed::new("lists_nested_10000", nested_lists(10_000)),
+        Seed::new("many_items", list(&vec![vec![0x80]; 1024])),
+        // Typed transaction envelopes
+        Seed::new("tx_type_without_payload", vec![0x02]),
+        Seed::new("tx_type_empty_list", vec![0x02, 0xc0]),
+        Seed::new("tx_type_reserved", vec![0x7f, 0xc0]),
+    ];
+
+    let nine_fields: Vec<Vec<u8>> = (0..9).map(|i| string(i * 4)).collect();
+    truncations("legacy_shape", &list(&nine_fields), &mut seeds);
+    let nested = list(&[list(&[string(20), list(&[string(32), string(32)])])]);
+    truncations("access_list_shape", &nested, &mut seeds);
+    seeds
+}
+
+/// Writes a corpus to `dir`, one file per input, named after it.
+pub fn write_corpus<P: AsRef<Path>>(dir: P, seeds: &[Seed]) -> io::Result<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    for seed in seeds {
+        fs::write(dir.join(&seed.name), &seed.data)?;
+    }
+    Ok(())
+}
+
+#[test]
+fn corpora_are_deterministic() {
+    use std::collections::HashSet;
+    for corpus in &[abi_corpus(), rlp_corpus()] {
+        let names: HashSet<&str> = corpus.iter().map(|seed| seed.name.as_str()).collect();
+        assert_eq!(names.len(), corpus.len());
+    }
+    assert_eq!(abi_corpus(), abi_corpus());
+    assert_eq!(rlp_corpus(), rlp_corpus());
+}
+
+#[test]
+fn abi_decoders_reject_corpus() {
+    use abi::{decode_tokens, parse_param_types};
+    let types = parse_param_types("f(bytes,string,uint256[],address,bool)").unwrap();
+    let mut decoded = 0;
+    for seed in abi_corpus() {
+        for kind in &types {
+            // Nothing panics
+            if decode_tokens(std::slice::from_ref(kind), &seed.data).is_ok() {
+                decoded += 1;
+            }
+        }
+    }
+    assert!(decoded > 0);
+    assert!(decode_tokens(&types[..1], &abi_corpus()[0].data).is_err());
+}
+
+#[test]
+fn rlp_decoders_reject_corpus() {
+    use rlp::decode_item;
+    use transaction::TypedTransaction;
+    let corpus = rlp_corpus();
+    let valid = |name: &str| {
+        let seed = corpus.iter().find(|seed| seed.name == name).unwrap();
+        decode_item(&seed.data).is_ok()
+    };
+    assert!(valid("string_56"));
+    assert!(valid("lists_nested_to_limit"));
+    assert!(!valid("lists_nested_past_limit"));
+    assert!(!valid("lists_nested_10000"));
+    assert!(!valid("short_string_long_header"));
+    assert!(!valid("legacy_shape_truncated_32"));
+    for seed in &corpus {
+        let _ = decode_item(&seed.data);
+        assert!(TypedTransaction::from_bytes(&seed.data).is_err());
+    }
+}
@@ -0,0 +1,232 @@
+//! JSON-RPC requests for the test methods of development nodes.
+//!
+//! Anvil and Hardhat both let tests impersonate accounts, set balances, mine
+//! blocks on demand and snapshot the chain. [DevNodeCall](enum.DevNodeCall.html)
+//! builds those requests as [RpcRequest](struct.RpcRequest.html)s, which
+//! serialize to the JSON the nodes expect with any serde serializer.
+//!
+//! ```rust
+//! # extern crate clarity;
+//! # extern crate serde_json;
+//! use clarity::devnode::{DevNode, DevNodeCall};
+//! use clarity::Address;
+//!
+//! let whale: Address = "0x47ac0Fb4F2D84898e4D9E7b4DaB3C24507a6D503".parse().unwrap();
+//! let request = DevNodeCall::ImpersonateAccount(whale).request(DevNode::Anvil, 1);
+//! assert_eq!(
+//!     serde_json::to_string(&request).unwrap(),
+//!     r#"{"jsonrpc":"2.0","id":1,"method":"anvil_impersonateAccount","params":["0x47ac0fb4f2d84898e4d9e7b4dab3c24507a6d503"]}"#
+//! );
+//! ```
+use address::Address;
+use num256::Uint256;
+use utils::bytes_to_hex_str;
+
+/// The development node a request is for. Methods that are not part of the
+/// shared `evm_` namespace are prefixed with its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DevNode {
+    Anvil,
+    Hardhat,
+}
+
+impl DevNode {
+    fn prefix(self) -> &'static str {
+        match self {
+            DevNode::Anvil => "anvil",
+            DevNode::Hardhat => "hardhat",
+        }
+    }
+}
+
+/// The chain to fork, given to [Reset](enum.DevNodeCall.html#variant.Reset).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fork {
+    /// RPC endpoint of the chain to fork
+    #[serde(rename = "jsonRpcUrl")]
+    pub url: String,
+    /// Block to fork at, the latest one if `None`
+    #[serde(rename = "blockNumber", skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<u64>,
+}
+
+/// A single JSON-RPC parameter.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(untagged)]
+pub enum Param {
+    String(String),
+    Number(u64),
+    Bool(bool),
+    Forking { forking: Fork },
+}
+
+/// A JSON-RPC 2.0 request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RpcRequest {
+    pub jsonrpc: &'static str,
+    pub id: u64,
+    pub method: String,
+    pub params: Vec<Param>,
+}
+
+/// A test method of a development node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DevNodeCall {
+    /// Lets transactions from the address be sent without its key
+    ImpersonateAccount(Address),
+    StopImpersonatingAccount(Address),
+    /// Sets the balance of the address, in wei
+    SetBalance(Address, Uint256),
+    SetNonce(Address, Uint256),
+    /// Replaces the code of the address
+    SetCode(Address, Vec<u8>),
+    /// Mines a single block
+    Mine,
+    /// Mines this many blocks, this many seconds apart
+    MineBlocks {
+        blocks: u64,
+        interval: u64,
+    },
+    /// Moves the time of the next block forward, in seconds
+    IncreaseTime(u64),
+    SetNextBlockTimestamp(u64),
+    /// Snapshots the state of the chain; the node returns an id to revert to
+    Snapshot,
+    /// Reverts to the snapshot with this id, as returned by the node
+    Revert(String),
+    /// Resets the node to a fresh fork of a chain, or to an empty chain
+    Reset(Option<Fork>),
+}
+
+fn quantity(value: &Uint256) -> Param {
+    Param::String(format!("{:#x}", value))
+}
+
+impl DevNodeCall {
+    /// Name of the method on `node`.
+    pub fn method(&self, node: DevNode) -> String {
+        let name = match *self {
+            DevNodeCall::ImpersonateAccount(_) => "impersonateAccount",
+            DevNodeCall::StopImpersonatingAccount(_) => "stopImpersonatingAccount",
+            DevNodeCall::SetBalance(_, _) => "setBalance",
+            DevNodeCall::SetNonce(_, _) => "setNonce",
+            DevNodeCall::SetCode(_, _) => "setCode",
+            DevNodeCall::MineBlocks { .. } => "mine",
+            DevNodeCall::Reset(_) => "reset",
+            DevNodeCall::Mine => return "evm_mine".to_string(),
+            DevNodeCall::IncreaseTime(_) => return "evm_increaseTime".to_string(),
+            DevNodeCall::SetNextBlockTimestamp(_) => {
+                return "evm_setNextBlockTimestamp".to_string()
+            }
+            DevNodeCall::Snapshot => return "evm_snapshot".to_string(),
+            DevNodeCall::Revert(_) => return "evm_revert".to_string(),
+        };
+        format!("{}_{}", node.prefix(), name)
+    }
+
+    /// Parameters of the method.
+    pub fn params(&self) -> Vec<Param> {
+        match *self {
+            DevNodeCall::ImpersonateAccount(address)
+            | DevNodeCall::StopImpersonatingAccount(address) => {
+                vec![Param::String(address.to_string())]
+            }
+            DevNodeCall::SetBalance(address, ref value)
+            | DevNodeCall::SetNonce(address, ref value) => {
+                vec![Param::String(address.to_string()), quantity(value)]
+            }
+            DevNodeCall::SetCode(address, ref code) => vec![
+                Param::String(address.to_string()),
+                Param::String(format!("0x{}", bytes_to_hex_str(code))),
+            ],
+            DevNodeCall::MineBlocks { blocks, interval } => {
+                vec![quantity(&blocks.into()), quantity(&interval.into())]
+            }
+            DevNodeCall::IncreaseTime(seconds) | DevNodeCall::SetNextBlockTimestamp(seconds) => {
+                vec![Param::Number(seconds)]
+            }
+            DevNodeCall::Revert(ref id) => vec![Param::String(id.clone())],
+            DevNodeCall::Reset(Some(ref fork)) => vec![Param::Forking {
+                forking: fork.clone(),
+            }],
+            DevNodeCall::Reset(None) => Vec::new(),
+            DevNodeCall::Mine | DevNodeCall::Snapshot => Vec::new(),
+        }
+    }
+
+    /// The request for `node`, with the given request id.
+    pub fn request(&self, node: DevNode, id: u64) -> RpcRequest {
+        RpcRequest {
+            jsonrpc: "2.0",
+            id,
+            method: self.method(node),
+            params: self.params(),
+        }
+    }
+}
+
+#[test]
+fn method_names() {
+    let address = Address::default();
+    assert_eq!(
+        DevNodeCall::SetBalance(address, 0u8.into()).method(DevNode::Hardhat),
+        "hardhat_setBalance"
+    );
+    assert_eq!(
+        DevNodeCall::StopImpersonatingAccount(address).method(DevNode::Anvil),
+        "anvil_stopImpersonatingAccount"
+    );
+    // The evm_ namespace is shared
+    assert_eq!(DevNodeCall::Mine.method(DevNode::Hardhat), "evm_mine");
+    assert_eq!(DevNodeCall::Snapshot.method(DevNode::Anvil), "evm_snapshot");
+}
+
+#[test]
+fn serialize_requests() {
+    let address: Address = "0x1111111111111111111111111111111111111111"
+        .parse()
+        .unwrap();
+    let to_json =
+        |call: DevNodeCall| serde_json::to_string(&call.request(DevNode::Anvil, 7)).unwrap();
+    assert_eq!(
+        to_json(DevNodeCall::SetBalance(
+            address,
+            1_000_000_000_000_000_000u64.into()
+        )),
+        r#"{"jsonrpc":"2.0","id":7,"method":"anvil_setBalance","params":["0x1111111111111111111111111111111111111111","0xde0b6b3a7640000"]}"#
+    );
+    assert_eq!(
+        to_json(DevNodeCall::SetCode(address, vec![0x60, 0x00])),
+        r#"{"jsonrpc":"2.0","id":7,"method":"anvil_setCode","params":["0x1111111111111111111111111111111111111111","0x6000"]}"#
+    );
+    assert_eq!(
+        to_json(DevNodeCall::MineBlocks {
+            blocks: 10,
+            interval: 12
+        }),
+        r#"{"jsonrpc":"2.0","id":7,"method":"anvil_mine","params":["0xa","0xc"]}"#
+    );
+    assert_eq!(
+        to_json(DevNodeCall::IncreaseTime(3600)),
+        r#"{"jsonrpc":"2.0","id":7,"method":"evm_increaseTime","params":[3600]}"#
+    );
+    assert_eq!(
+        to_json(DevNodeCall::Mine),
+        r#"{"jsonrpc":"2.0","id":7,"method":"evm_mine","params":[]}"#
+    );
+    assert_eq!(
+        to_json(DevNodeCall::Revert("0x1".to_string())),
+        r#"{"jsonrpc":"2.0","id":7,"method":"evm_revert","params":["0x1"]}"#
+    );
+    assert_eq!(
+        to_json(DevNodeCall::Reset(Some(Fork {
+            url: "http://localhost:8545".to_string(),
+            block_number: Some(100),
+        }))),
+        r#"{"jsonrpc":"2.0","id":7,"method":"anvil_reset","params":[{"forking":{"jsonRpcUrl":"http://localhost:8545","blockNumber":100}}]}"#
+    );
+    assert_eq!(
+        to_json(DevNodeCall::SetNonce(address, 0u8.into())),
+        r#"{"jsonrpc":"2.0","id":7,"method":"anvil_setNonce","params":["0x1111111111111111111111111111111111111111","0x0"]}"#
+    );
+}
@@ -0,0 +1,431 @@
+//! Payment request URIs, as put in QR codes (EIP-681).
+//!
+//! A wallet shows `ethereum:0x...` for an address, and a payment request
+//! adds the chain, the amount, or a token transfer to it, such as
+//! `ethereum:0xToken@1/transfer?address=0xRecipient&uint256=1e18`.
+//!
+//! Scanned payloads come from anywhere, so parsing is strict: targets have
+//! to be addresses (not ENS names) with a valid checksum if they are mixed
+//! case, numbers have to be integers that fit their type, every parameter
+//! has to be known, and none may be repeated. Only arguments of types that
+//! have an unambiguous textual form are accepted: `address`, `uint<M>` and
+//! `bool`.
+use abi::{encode_call, ParamType, Token};
+use address::Address;
+use failure::Error;
+use num256::Uint256;
+use std::fmt;
+use std::str::FromStr;
+
+/// Decimal exponents above this can not give a number that fits 256 bits.
+const MAX_EXPONENT: u32 = 78;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum PaymentUriError {
+    #[fail(display = "Not an ethereum: URI")]
+    InvalidScheme,
+    #[fail(display = "Invalid target address {:?}", _0)]
+    InvalidAddress(String),
+    #[fail(display = "Invalid chain id {:?}", _0)]
+    InvalidChainId(String),
+    #[fail(display = "Invalid function name {:?}", _0)]
+    InvalidFunction(String),
+    #[fail(display = "Invalid number {:?}", _0)]
+    InvalidNumber(String),
+    #[fail(display = "Parameter {:?} is given more than once", _0)]
+    DuplicateParameter(String),
+    #[fail(display = "Unknown parameter {:?}", _0)]
+    UnknownParameter(String),
+    #[fail(display = "Invalid {} argument {:?}", _0, _1)]
+    InvalidArgument(ParamType, String),
+    #[fail(display = "Arguments of type {} are not supported", _0)]
+    UnsupportedArgument(ParamType),
+    #[fail(display = "Arguments are only allowed with a function")]
+    ArgumentsWithoutFunction,
+}
+
+/// A payment request, or just an address to send to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentRequest {
+    /// The recipient, or the contract to call
+    pub target: Address,
+    pub chain_id: Option<u64>,
+    /// Name of the function to call on the target
+    pub function: Option<String>,
+    /// Arguments of the function, with their types, which are limited to
+    /// addresses, unsigned integers and booleans
+    pub arguments: Vec<(ParamType, Token)>,
+    /// Wei to send
+    pub value: Option<Uint256>,
+    pub gas_limit: Option<Uint256>,
+    pub gas_price: Option<Uint256>,
+}
+
+impl PaymentRequest {
+    /// The payload of an address, with nothing requested.
+    pub fn address(target: Address) -> PaymentRequest {
+        PaymentRequest {
+            target,
+            chain_id: None,
+            function: None,
+            arguments: Vec::new(),
+            value: None,
+            gas_limit: None,
+            gas_price: None,
+        }
+    }
+
+    /// A request to send `value` wei to `to`.
+    pub fn transfer(to: Address, value: Uint256, chain_id: Option<u64>) -> PaymentRequest {
+        PaymentRequest {
+            chain_id,
+            value: Some(value),
+            ..PaymentRequest::address(to)
+        }
+    }
+
+    /// A request to send `amount` of the ERC20 `token` to `to`.
+    pub fn erc20_transfer(
+        token: Address,
+        to: Address,
+        amount: Uint256,
+        chain_id: Option<u64>,
+    ) -> PaymentRequest {
+        PaymentRequest {
+            chain_id,
+            function: Some("transfer".to_string()),
+            arguments: vec![
+                (ParamType::Address, Token::Address(to)),
+                (ParamType::Uint(256), Token::Uint(amount)),
+            ],
+            ..PaymentRequest::address(token)
+        }
+    }
+
+    /// The signature of the function, such as `transfer(address,uint256)`.
+    pub fn function_signature(&self) -> Option<String> {
+        self.function.as_ref().map(|name| {
+            let types: Vec<String> = self.arguments.iter().map(|(t, _)| t.to_string()).collect();
+            format!("{}({})", name, types.join(","))
+        })
+    }
+
+    /// The data of the transaction that pays the request, empty when no
+    /// function is called.
+    pub fn call_data(&self) -> Vec<u8> {
+        match self.function_signature() {
+            Some(signature) => {
+                let tokens: Vec<Token> = self.arguments.iter().map(|(_, t)| t.clone()).collect();
+                encode_call(&signature, &tokens)
+            }
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Parses a number as EIP-681 writes them, such as `2014` or `2.014e18`,
+/// which has to be an integer below 2^`bits`.
+fn parse_number(s: &str, bits: usize) -> Result<Uint256, PaymentUriError> {
+    let invalid = || PaymentUriError::InvalidNumber(s.to_string());
+    let (mantissa, exponent) = match s.find(['e', 'E']) {
+        Some(i) => (&s[..i], &s[i + 1..]),
+        None => (s, "0"),
+    };
+    let (integer, fraction) = match mantissa.find('.') {
+        Some(i) => (&mantissa[..i], &mantissa[i + 1..]),
+        None => (mantissa, ""),
+    };
+    let all_digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if integer.is_empty()
+        || !all_digits(integer)
+        || (mantissa.contains('.') && fraction.is_empty())
+        || !all_digits(fraction)
+        || exponent.is_empty()
+        || !all_digits(exponent)
+    {
+        return Err(invalid());
+    }
+    let exponent: u32 = exponent
+        .parse()
+        .ok()
+        .filter(|e| *e <= MAX_EXPONENT)
+        .ok_or_else(invalid)?;
+    let mut digits = format!("{}{}", integer, fraction.trim_end_matches('0'));
+    let fraction_len = fraction.trim_end_matches('0').len() as u32;
+    if fraction_len > exponent {
+        return Err(invalid());
+    }
+    digits.extend((fraction_len..exponent).map(|_| '0'));
+    let value = Uint256::from_str_radix(&digits, 10).map_err(|_| invalid())?;
+    if value.bits() > bits {
+        return Err(invalid());
+    }
+    Ok(value)
+}
+
+fn parse_argument(kind: &ParamType, value: &str) -> Result<Token, PaymentUriError> {
+    let invalid = || PaymentUriError::InvalidArgument(kind.clone(), value.to_string());
+    match *kind {
+        ParamType::Address => {
+            if !value.starts_with("0x") || value.len() != 42 {
+                return Err(invalid());
+            }
+            Ok(Token::Address(
+                Address::parse_and_validate(value).map_err(|_| invalid())?,
+            ))
+        }
+        ParamType::Uint(bits) => Ok(Token::Uint(
+            parse_number(value, bits).map_err(|_| invalid())?,
+        )),
+        ParamType::Bool => match value {
+            "true" => Ok(Token::Bool(true)),
+            "false" => Ok(Token::Bool(false)),
+            _ => Err(invalid()),
+        },
+        ref other => Err(PaymentUriError::UnsupportedArgument(other.clone())),
+    }
+}
+
+impl FromStr for PaymentRequest {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let scheme_len = "ethereum:".len();
+        if !s
+            .get(..scheme_len)
+            .is_some_and(|scheme| scheme.eq_ignore_ascii_case("ethereum:"))
+        {
+            return Err(PaymentUriError::InvalidScheme.into());
+        }
+        let rest = &s[scheme_len..];
+        let rest = rest.strip_prefix("pay-").unwrap_or(rest);
+        let (path, query) = match rest.find('?') {
+            Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+            None => (rest, None),
+        };
+        let (target, function) = match path.find('/') {
+            Some(i) => (&path[..i], Some(&path[i + 1..])),
+            None => (path, None),
+        };
+        let (address, chain_id) = match target.find('@') {
+            Some(i) => (&target[..i], Some(&target[i + 1..])),
+            None => (target, None),
+        };
+
+        if !address.starts_with("0x") || address.len() != 42 {
+            return Err(PaymentUriError::InvalidAddress(address.to_string()).into());
+        }
+        let mut request = PaymentRequest::address(
+            Address::parse_and_validate(address)
+                .map_err(|_| PaymentUriError::InvalidAddress(address.to_string()))?,
+        );
+        if let Some(chain_id) = chain_id {
+            let invalid = || PaymentUriError::InvalidChainId(chain_id.to_string());
+            if chain_id.starts_with('0') || !chain_id.bytes().all(|b| b.is_ascii_digit()) {
+                return Err(invalid().into());
+            }
+            request.chain_id = Some(chain_id.parse().map_err(|_| invalid())?);
+        }
+        if let Some(function) = function {
+            let valid = function
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && function
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$');
+            if !valid {
+                return Err(PaymentUriError::InvalidFunction(function.to_string()).into());
+            }
+            request.function = Some(function.to_string());
+        }
+
+        let mut seen = Vec::new();
+        for parameter in query.into_iter().flat_map(|query| query.split('&')) {
+            let (key, value) = match parameter.find('=') {
+                Some(i) => (&parameter[..i], &parameter[i + 1..]),
+                None => return Err(PaymentUriError::UnknownParameter(parameter.to_string()).into()),
+            };
+            let known = |field: &mut Option<Uint256>| -> Result<(), PaymentUriError> {
+                *field = Some(parse_number(value, 256)?);
+                Ok(())
+            };
+            match key {
+                "value" => known(&mut request.value)?,
+                "gas" | "gasLimit" => known(&mut request.gas_limit)?,
+                "gasPrice" => known(&mut request.gas_price)?,
+                _ => {
+                    let kind: ParamType = key
+                        .parse()
+                        .map_err(|_| PaymentUriError::UnknownParameter(key.to_string()))?;
+                    if request.function.is_none() {
+                        return Err(PaymentUriError::ArgumentsWithoutFunction.into());
+                    }
+                    let token = parse_argument(&kind, value)?;
+                    request.arguments.push((kind, token));
+                    // Arguments of one type may repeat, they are positional
+                    continue;
+                }
+            }
+            // `gas` and `gasLimit` are the same parameter
+            let name = if key == "gas" { "gasLimit" } else { key };
+            if seen.contains(&name) {
+                return Err(PaymentUriError::DuplicateParameter(key.to_string()).into());
+            }
+            seen.push(name);
+        }
+        Ok(request)
+    }
+}
+
+impl fmt::Display for PaymentRequest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ethereum:{}", self.target.to_checksum_string())?;
+        if let Some(chain_id) = self.chain_id {
+            write!(f, "@{}", chain_id)?;
+        }
+        if let Some(ref function) = self.function {
+            write!(f, "/{}", function)?;
+        }
+        let mut parameters = Vec::new();
+        for (kind, token) in &self.arguments {
+            let value = match *token {
+                Token::Address(ref address) => address.to_checksum_string(),
+                Token::Uint(ref value) => value.to_string(),
+                Token::Bool(value) => value.to_string(),
+                ref other => format!("{:?}", other),
+            };
+            parameters.push(format!("{}={}", kind, value));
+        }
+        let numbers = [
+            ("value", &self.value),
+            ("gasLimit", &self.gas_limit),
+            ("gasPrice", &self.gas_price),
+        ];
+        for (name, number) in numbers.iter() {
+            if let Some(ref number) = **number {
+                parameters.push(format!("{}={}", name, number));
+            }
+        }
+        if !parameters.is_empty() {
+            write!(f, "?{}", parameters.join("&"))?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn parse_numbers() {
+    assert_eq!(parse_number("2014", 256).unwrap(), 2014u32.into());
+    assert_eq!(
+        parse_number("2.014e18", 256).unwrap(),
+        2_014_000_000_000_000_000u64.into()
+    );
+    assert_eq!(parse_number("1.50E1", 256).unwrap(), 15u8.into());
+    assert_eq!(parse_number("255", 8).unwrap(), 255u8.into());
+    for invalid in &[
+        "", "-1", "+1", "1.", ".5", "1.5", "1e", "1e79", "0x10", "1 ",
+    ] {
+        assert_eq!(
+            parse_number(invalid, 256),
+            Err(PaymentUriError::InvalidNumber(invalid.to_string()))
+        );
+    }
+    assert!(parse_number("256", 8).is_err());
+    assert!(parse_number("1e78", 256).is_err());
+}
+
+#[test]
+fn payment_request_roundtrip() {
+    use abi::derive_method_id;
+    let token: Address = "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed"
+        .parse()
+        .unwrap();
+    let to: Address = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359"
+        .parse()
+        .unwrap();
+
+    let request = PaymentRequest::erc20_transfer(token, to, 1_000u32.into(), Some(1));
+    let uri = request.to_string();
+    assert_eq!(
+        uri,
+        "ethereum:0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed@1/transfer\
+         ?address=0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359&uint256=1000"
+    );
+    assert_eq!(uri.parse::<PaymentRequest>().unwrap(), request);
+    assert_eq!(
+        request.call_data()[..4],
+        derive_method_id("transfer(address,uint256)")
+    );
+
+    let payment: PaymentRequest =
+        "ethereum:pay-0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359@10?value=2.014e18&gas=21000"
+            .parse()
+            .unwrap();
+    assert_eq!(payment.target, to);
+    assert_eq!(payment.chain_id, Some(10));
+    assert_eq!(payment.value, Some(2_014_000_000_000_000_000u64.into()));
+    assert_eq!(payment.gas_limit, Some(21_000u32.into()));
+    assert!(payment.call_data().is_empty());
+
+    let plain = PaymentRequest::address(to);
+    assert_eq!(
+        plain.to_string(),
+        "ethereum:0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359"
+    );
+    assert_eq!(plain.to_string().parse::<PaymentRequest>().unwrap(), plain);
+}
+
+#[test]
+fn reject_malformed_requests() {
+    let parse = |uri: &str| -> PaymentUriError {
+        uri.parse::<PaymentRequest>()
+            .unwrap_err()
+            .downcast::<PaymentUriError>()
+            .unwrap()
+    };
+    let to = "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+    assert_eq!(parse(to), PaymentUriError::InvalidScheme);
+    assert_eq!(
+        parse("ethereum:vitalik.eth"),
+        PaymentUriError::InvalidAddress("vitalik.eth".to_string())
+    );
+    // A single flipped case breaks the checksum
+    let tampered = "ethereum:0xFB6916095ca1df60bB79Ce92cE3Ea74c37c5d359";
+    assert_eq!(
+        parse(tampered),
+        PaymentUriError::InvalidAddress(tampered[9..].to_string())
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}@01", to)),
+        PaymentUriError::InvalidChainId("01".to_string())
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}?value=1&value=2", to)),
+        PaymentUriError::DuplicateParameter("value".to_string())
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}?gas=1&gasLimit=2", to)),
+        PaymentUriError::DuplicateParameter("gasLimit".to_string())
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}?amount=1", to)),
+        PaymentUriError::UnknownParameter("amount".to_string())
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}?uint256=1", to)),
+        PaymentUriError::ArgumentsWithoutFunction
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}/transfer?uint8=256", to)),
+        PaymentUriError::InvalidArgument(ParamType::Uint(8), "256".to_string())
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}/approve?string=x", to)),
+        PaymentUriError::UnsupportedArgument(ParamType::String)
+    );
+    assert_eq!(
+        parse(&format!("ethereum:{}/trans-fer", to)),
+        PaymentUriError::InvalidFunction("trans-fer".to_string())
+    );
+}
@@ -0,0 +1,802 @@
+//! EIP-712 hashing of typed structured data.
+//!
+//! Permits, orders and meta-transactions are signed as typed data rather
+//! than as opaque messages, so that wallets can show what is being signed.
+//! The signed digest is `keccak256(0x1901 || domainSeparator ||
+//! hashStruct(message))`, which is what `eth_signTypedData_v4` signs.
+//!
+//! Struct types are given as [Types](type.Types.html), a map from type name
+//! to its members in order, and values as tokens: a struct is a
+//! `Token::Tuple` of its members in the order of its type, `bytes<N>` is
+//! `Token::Bytes`, `bytes` is `Token::UnboundedBytes` and arrays are
+//! `Token::Dynamic` or `Token::FixedArray`. Requests in the JSON format
+//! wallets take are read with [TypedData](struct.TypedData.html).
+//!
+//! ```rust
+//! use clarity::abi::Token;
+//! use clarity::eip712::{Eip712Domain, Member, Types};
+//! use clarity::PrivateKey;
+//!
+//! let mut types = Types::new();
+//! types.insert(
+//!     "Order".to_owned(),
+//!     vec![Member::new("maker", "address"), Member::new("amount", "uint256")],
+//! );
+//! let domain = Eip712Domain {
+//!     name: Some("Exchange".to_owned()),
+//!     version: Some("1".to_owned()),
+//!     chain_id: Some(1u8.into()),
+//!     ..Eip712Domain::default()
+//! };
+//! let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+//!     .parse()
+//!     .unwrap();
+//! let order = Token::Tuple(vec![key.to_public_key().unwrap().into(), 100u8.into()]);
+//! let signature = key.sign_typed_data(&domain, &types, "Order", &order).unwrap();
+//! ```
+use abi::{encode_tokens, ParamType, Token};
+use address::Address;
+use failure::Error;
+use hash::keccak256;
+use num256::{Int256, Uint256};
+use num_bigint::{BigInt, Sign};
+use serde_crate::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+use utils::hex_str_to_bytes;
+
+/// The name of the type of the domain.
+pub const DOMAIN_TYPE_NAME: &str = "EIP712Domain";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum Eip712Error {
+    #[fail(display = "Type {} is not defined", _0)]
+    UnknownType(String),
+    #[fail(display = "Value of {} does not match its type {}", _0, _1)]
+    InvalidValue(String, String),
+    #[fail(display = "Value of {} has no member {}", _0, _1)]
+    MissingMember(String, String),
+    #[fail(
+        display = "Struct {} has {} members but its value has {}",
+        name, expected, found
+    )]
+    MemberCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+}
+
+/// A member of a struct type.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct Member {
+    pub name: String,
+    /// The type of the member, such as `address`, `Person` or `uint256[]`
+    #[serde(rename = "type")]
+    pub type_name: String,
+}
+
+impl Member {
+    pub fn new(name: &str, type_name: &str) -> Member {
+        Member {
+            name: name.to_owned(),
+            type_name: type_name.to_owned(),
+        }
+    }
+}
+
+/// The struct types, by name.
+pub type Types = BTreeMap<String, Vec<Member>>;
+
+/// The domain of a signature, which keeps signatures for one contract,
+/// chain or version of a protocol from being valid for another. Only the
+/// fields that are set are part of the domain type.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Eip712Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<Uint256>,
+    pub verifying_contract: Option<Address>,
+    pub salt: Option<[u8; 32]>,
+}
+
+impl Eip712Domain {
+    /// The members of the domain type and the value of the domain.
+    pub fn to_struct(&self) -> (Vec<Member>, Token) {
+        let mut members = Vec::new();
+        let mut values = Vec::new();
+        if let Some(ref name) = self.name {
+            members.push(Member::new("name", "string"));
+            values.push(Token::String(name.clone()));
+        }
+        if let Some(ref version) = self.version {
+            members.push(Member::new("version", "string"));
+            values.push(Token::String(version.clone()));
+        }
+        if let Some(ref chain_id) = self.chain_id {
+            members.push(Member::new("chainId", "uint256"));
+            values.push(Token::Uint(chain_id.clone()));
+        }
+        if let Some(verifying_contract) = self.verifying_contract {
+            members.push(Member::new("verifyingContract", "address"));
+            values.push(Token::Address(verifying_contract));
+        }
+        if let Some(salt) = self.salt {
+            members.push(Member::new("salt", "bytes32"));
+            values.push(Token::Bytes(salt.to_vec()));
+        }
+        (members, Token::Tuple(values))
+    }
+
+    /// The domain separator, the struct hash of the domain.
+    pub fn separator(&self) -> [u8; 32] {
+        let (members, value) = self.to_struct();
+        let mut types = Types::new();
+        types.insert(DOMAIN_TYPE_NAME.to_owned(), members);
+        // The domain has only atomic and string members, which always hash
+        struct_hash(&types, DOMAIN_TYPE_NAME, &value).unwrap()
+    }
+}
+
+/// The type of the items of an array type, `None` if it is not one.
+fn array_item(type_name: &str) -> Option<&str> {
+    if !type_name.ends_with(']') {
+        return None;
+    }
+    type_name.rfind('[').map(|open| &type_name[..open])
+}
+
+/// The struct type at the root of `type_name`, stripped of array suffixes,
+/// if it is one.
+fn struct_name<'a>(types: &Types, type_name: &'a str) -> Option<&'a str> {
+    let mut name = type_name;
+    while let Some(item) = array_item(name) {
+        name = item;
+    }
+    if types.contains_key(name) {
+        Some(name)
+    } else {
+        None
+    }
+}
+
+fn members<'a>(types: &'a Types, name: &str) -> Result<&'a [Member], Eip712Error> {
+    types
+        .get(name)
+        .map(|members| &members[..])
+        .ok_or_else(|| Eip712Error::UnknownType(name.to_owned()))
+}
+
+/// Adds the struct types `name` references, directly or not, to `found`.
+fn collect_dependencies<'a>(
+    types: &'a Types,
+    name: &str,
+    found: &mut BTreeSet<&'a str>,
+) -> Result<(), Eip712Error> {
+    for member in members(types, name)? {
+        if let Some(dependency) = struct_name(types, &member.type_name) {
+            let (dependency, _) = types.get_key_value(dependency).unwrap();
+            if found.insert(dependency) {
+                collect_dependencies(types, dependency, found)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The encoding of the struct type `name`, such as
+/// `Mail(Person from,Person to,string contents)Person(string name,address wallet)`:
+/// the type followed by the types it references, sorted by name.
+pub fn encode_type(types: &Types, name: &str) -> Result<String, Error> {
+    let mut dependencies = BTreeSet::new();
+    collect_dependencies(types, name, &mut dependencies)?;
+    dependencies.remove(name);
+    let mut encoded = String::new();
+    for type_name in Some(name).into_iter().chain(dependencies) {
+        let members: Vec<String> = members(types, type_name)?
+            .iter()
+            .map(|member| format!("{} {}", member.type_name, member.name))
+            .collect();
+        encoded.push_str(&format!("{}({})", type_name, members.join(",")));
+    }
+    Ok(encoded)
+}
+
+/// The hash of the encoding of the struct type `name`.
+pub fn type_hash(types: &Types, name: &str) -> Result<[u8; 32], Error> {
+    Ok(keccak256(encode_type(types, name)?.as_bytes()))
+}
+
+/// The 32 byte encoding of a member value of type `type_name`.
+fn encode_value(types: &Types, type_name: &str, value: &Token) -> Result<[u8; 32], Error> {
+    let invalid = || Eip712Error::InvalidValue(format!("{:?}", value), type_name.to_owned());
+    if types.contains_key(type_name) {
+        return struct_hash(types, type_name, value);
+    }
+    if let Some(item) = array_item(type_name) {
+        let items = match *value {
+            Token::Dynamic(ref items) | Token::FixedArray(ref items) => items,
+            _ => return Err(invalid().into()),
+        };
+        let size = &type_name[item.len() + 1..type_name.len() - 1];
+        if !size.is_empty() && size.parse::<usize>().ok() != Some(items.len()) {
+            return Err(invalid().into());
+        }
+        let mut encoded = Vec::with_capacity(items.len() * 32);
+        for value in items {
+            encoded.extend_from_slice(&encode_value(types, item, value)?);
+        }
+        return Ok(keccak256(&encoded));
+    }
+    let matches = match (type_name, value) {
+        ("string", Token::String(s)) => return Ok(keccak256(s.as_bytes())),
+        ("bytes", Token::UnboundedBytes(bytes)) => return Ok(keccak256(bytes)),
+        ("address", Token::Address(_)) | ("bool", Token::Bool(_)) => true,
+        // The value has to fit the width of `uint<M>` and `int<M>`
+        (name, Token::Uint(_)) if name.starts_with("uint") => name
+            .parse::<ParamType>()
+            .map_err(|_| invalid())?
+            .accepts(value),
+        (name, Token::Int(_)) if name.starts_with("int") => name
+            .parse::<ParamType>()
+            .map_err(|_| invalid())?
+            .accepts(value),
+        (name, Token::Bytes(bytes)) if name.starts_with("bytes") => {
+            name[5..].parse::<usize>().ok() == Some(bytes.len())
+        }
+        _ => false,
+    };
+    if !matches {
+        return Err(invalid().into());
+    }
+    let mut word = [0u8; 32];
+    word.copy_from_slice(&encode_tokens(std::slice::from_ref(value)));
+    Ok(word)
+}
+
+/// `encodeData` of a value of the struct type `name`: its type hash
+/// followed by the encoding of each member.
+pub fn encode_data(types: &Types, name: &str, value: &Token) -> Result<Vec<u8>, Error> {
+    let members = members(types, name)?;
+    let values = match *value {
+        Token::Tuple(ref values) => values,
+        _ => return Err(Eip712Error::InvalidValue(format!("{:?}", value), name.to_owned()).into()),
+    };
+    if values.len() != members.len() {
+        return Err(Eip712Error::MemberCount {
+            name: name.to_owned(),
+            expected: members.len(),
+            found: values.len(),
+        }
+        .into());
+    }
+    let mut encoded = type_hash(types, name)?.to_vec();
+    for (member, value) in members.iter().zip(values) {
+        encoded.extend_from_slice(&encode_value(types, &member.type_name, value)?);
+    }
+    Ok(encoded)
+}
+
+/// `hashStruct` of a value of the struct type `name`.
+pub fn struct_hash(types: &Types, name: &str, value: &Token) -> Result<[u8; 32], Error> {
+    Ok(keccak256(&encode_data(types, name, value)?))
+}
+
+/// The digest that is signed for `message` of the struct type
+/// `primary_type` in `domain`.
+pub fn signing_hash(
+    domain: &Eip712Domain,
+    types: &Types,
+    primary_type: &str,
+    message: &Token,
+) -> Result<[u8; 32], Error> {
+    Ok(digest(
+        &domain.separator(),
+        &struct_hash(types, primary_type, message)?,
+    ))
+}
+
+/// `keccak256(0x1901 || domain separator || struct hash)`.
+fn digest(separator: &[u8; 32], struct_hash: &[u8; 32]) -> [u8; 32] {
+    let mut data = vec![0x19, 0x01];
+    data.extend_from_slice(separator);
+    data.extend_from_slice(struct_hash);
+    keccak256(&data)
+}
+
+/// A value of the JSON of typed data, before it is read as its type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Uint(u64),
+    /// Negative integers
+    Int(i64),
+    Float(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(BTreeMap<String, JsonValue>),
+}
+
+struct JsonValueVisitor;
+
+impl<'de> Visitor<'de> for JsonValueVisitor {
+    type Value = JsonValue;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a JSON value")
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<JsonValue, E> {
+        Ok(JsonValue::Null)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<JsonValue, E> {
+        Ok(JsonValue::Null)
+    }
+
+    fn visit_some<D: Deserializer<'de>>(self, deserializer: D) -> Result<JsonValue, D::Error> {
+        JsonValue::deserialize(deserializer)
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<JsonValue, E> {
+        Ok(JsonValue::Bool(value))
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Uint(value))
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<JsonValue, E> {
+        Ok(if value < 0 {
+            JsonValue::Int(value)
+        } else {
+            JsonValue::Uint(value as u64)
+        })
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<JsonValue, E> {
+        Ok(JsonValue::Float(value))
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(value.to_owned()))
+    }
+
+    fn visit_string<E: de::Error>(self, value: String) -> Result<JsonValue, E> {
+        Ok(JsonValue::String(value))
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<JsonValue, A::Error> {
+        let mut items = Vec::new();
+        while let Some(item) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(JsonValue::Array(items))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<JsonValue, A::Error> {
+        let mut members = BTreeMap::new();
+        while let Some((key, value)) = map.next_entry()? {
+            members.insert(key, value);
+        }
+        Ok(JsonValue::Object(members))
+    }
+}
+
+impl<'de> Deserialize<'de> for JsonValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<JsonValue, D::Error> {
+        deserializer.deserialize_any(JsonValueVisitor)
+    }
+}
+
+/// An integer given as a JSON number, or as a string in decimal or `0x`
+/// hex, optionally negative.
+fn json_integer(value: &JsonValue) -> Option<BigInt> {
+    match *value {
+        JsonValue::Uint(value) => Some(value.into()),
+        JsonValue::Int(value) => Some(value.into()),
+        // Above 2^53 numbers lose precision, which has to be refused
+        JsonValue::Float(value)
+            if value.fract() == 0.0 && value.abs() < 9_007_199_254_740_992.0 =>
+        {
+            Some((value as i64).into())
+        }
+        JsonValue::String(ref s) => {
+            let (sign, digits) = match s.strip_prefix('-') {
+                Some(digits) => (Sign::Minus, digits),
+                None => (Sign::Plus, &s[..]),
+            };
+            let magnitude = match digits.strip_prefix("0x") {
+                Some(hex) if !hex.is_empty() => Uint256::from_str_radix(hex, 16).ok()?,
+                Some(_) => return None,
+                None => Uint256::from_str_radix(digits, 10).ok()?,
+            };
+            Some(BigInt::from_biguint(sign, magnitude.0))
+        }
+        _ => None,
+    }
+}
+
+/// Reads a JSON value as a value of `type_name`.
+pub fn json_to_token(types: &Types, type_name: &str, value: &JsonValue) -> Result<Token, Error> {
+    let invalid = || Eip712Error::InvalidValue(format!("{:?}", value), type_name.to_owned());
+    if types.contains_key(type_name) {
+        let members_json = match *value {
+            JsonValue::Object(ref members) => members,
+            _ => return Err(invalid().into()),
+        };
+        let mut values = Vec::new();
+        for member in members(types, type_name)? {
+            let value = members_json.get(&member.name).ok_or_else(|| {
+                Eip712Error::MissingMember(type_name.to_owned(), member.name.clone())
+            })?;
+            values.push(json_to_token(types, &member.type_name, value)?);
+        }
+        return Ok(Token::Tuple(values));
+    }
+    if let Some(item) = array_item(type_name) {
+        let items = match *value {
+            JsonValue::Array(ref items) => items
+                .iter()
+                .map(|value| json_to_token(types, item, value))
+                .collect::<Result<Vec<Token>, Error>>()?,
+            _ => return Err(invalid().into()),
+        };
+        return Ok(if type_name.ends_with("[]") {
+            Token::Dynamic(items)
+        } else {
+            Token::FixedArray(items)
+        });
+    }
+    let token = match (type_name, value) {
+        ("bool", JsonValue::Bool(value)) => Token::Bool(*value),
+        ("string", JsonValue::String(s)) => Token::String(s.clone()),
+        ("address", JsonValue::String(s)) => {
+            Token::Address(Address::from_str(s).map_err(|_| invalid())?)
+        }
+        ("bytes", JsonValue::String(s)) => {
+            Token::UnboundedBytes(hex_str_to_bytes(s).map_err(|_| invalid())?)
+        }
+        (name, JsonValue::String(s)) if name.starts_with("bytes") => {
+            Token::Bytes(hex_str_to_bytes(s).map_err(|_| invalid())?)
+        }
+        (name, value) if name.starts_with("uint") => match json_integer(value) {
+            Some(value) => match value.to_biguint() {
+                Some(value) => Token::Uint(Uint256(value)),
+                None => return Err(invalid().into()),
+            },
+            None => return Err(invalid().into()),
+        },
+        (name, value) if name.starts_with("int") => match json_integer(value) {
+            Some(value) => Token::Int(Int256(value)),
+            None => return Err(invalid().into()),
+        },
+        _ => return Err(invalid().into()),
+    };
+    Ok(token)
+}
+
+/// A typed data document as `eth_signTypedData_v4` takes it, in JSON:
+///
+/// ```rust
+/// # extern crate clarity;
+/// # extern crate serde_json;
+/// use clarity::eip712::TypedData;
+///
+/// # fn main() {
+/// let typed_data: TypedData = serde_json::from_str(r#"{
+///     "types": {
+///         "EIP712Domain": [{"name": "name", "type": "string"}],
+///         "Greeting": [{"name": "text", "type": "string"}]
+///     },
+///     "domain": {"name": "Greeter"},
+///     "primaryType": "Greeting",
+///     "message": {"text": "Hello"}
+/// }"#).unwrap();
+/// let hash = typed_data.signing_hash().unwrap();
+/// # }
+/// ```
+///
+/// Integers may be JSON numbers or strings in decimal or `0x` hex, and byte
+/// strings are given in hex. The domain is of the `EIP712Domain` type of
+/// `types`, or if there is none, of the fields the domain has.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    pub types: Types,
+    pub domain: JsonValue,
+    pub primary_type: String,
+    pub message: JsonValue,
+}
+
+impl TypedData {
+    /// The members of the type of the domain.
+    fn domain_members(&self) -> Vec<Member> {
+        if let Some(members) = self.types.get(DOMAIN_TYPE_NAME) {
+            return members.clone();
+        }
+        let fields = [
+            ("name", "string"),
+            ("version", "string"),
+            ("chainId", "uint256"),
+            ("verifyingContract", "address"),
+            ("salt", "bytes32"),
+        ];
+        fields
+            .iter()
+            .filter(|(name, _)| match self.domain {
+                JsonValue::Object(ref domain) => domain.contains_key(*name),
+                _ => false,
+            })
+            .map(|(name, type_name)| Member::new(name, type_name))
+            .collect()
+    }
+
+    pub fn domain_separator(&self) -> Result<[u8; 32], Error> {
+        let mut types = self.types.clone();
+        types.insert(DOMAIN_TYPE_NAME.to_owned(), self.domain_members());
+        let domain = json_to_token(&types, DOMAIN_TYPE_NAME, &self.domain)?;
+        struct_hash(&types, DOMAIN_TYPE_NAME, &domain)
+    }
+
+    /// The message, read as a value of the primary type.
+    pub fn message(&self) -> Result<Token, Error> {
+        json_to_token(&self.types, &self.primary_type, &self.message)
+    }
+
+    pub fn struct_hash(&self) -> Result<[u8; 32], Error> {
+        struct_hash(&self.types, &self.primary_type, &self.message()?)
+    }
+
+    /// The digest that is signed for the document.
+    pub fn signing_hash(&self) -> Result<[u8; 32], Error> {
+        Ok(digest(&self.domain_separator()?, &self.struct_hash()?))
+    }
+}
+
+/// The types and message of the example of EIP-712.
+#[cfg(test)]
+fn mail() -> (Eip712Domain, Types, Token) {
+    use utils::hex_str_to_bytes;
+    let address =
+        |s: &str| Token::Address(Address::from_slice(&hex_str_to_bytes(s).unwrap()).unwrap());
+    let domain = Eip712Domain {
+        name: Some("Ether Mail".to_owned()),
+        version: Some("1".to_owned()),
+        chain_id: Some(1u8.into()),
+        verifying_contract: Some(Address::from_slice(&[0xcc; 20]).unwrap()),
+        salt: None,
+    };
+    let mut types = Types::new();
+    types.insert(
+        "Person".to_owned(),
+        vec![
+            Member::new("name", "string"),
+            Member::new("wallet", "address"),
+        ],
+    );
+    types.insert(
+        "Mail".to_owned(),
+        vec![
+            Member::new("from", "Person"),
+            Member::new("to", "Person"),
+            Member::new("contents", "string"),
+        ],
+    );
+    let message = Token::Tuple(vec![
+        Token::Tuple(vec![
+            "Cow".into(),
+            address("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+        ]),
+        Token::Tuple(vec![
+            "Bob".into(),
+            address("bBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"),
+        ]),
+        "Hello, Bob!".into(),
+    ]);
+    (domain, types, message)
+}
+
+#[test]
+fn eip712_example() {
+    use private_key::PrivateKey;
+    use utils::bytes_to_hex_str;
+    let (domain, types, message) = mail();
+    assert_eq!(
+        encode_type(&types, "Mail").unwrap(),
+        "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&type_hash(&types, "Mail").unwrap()),
+        "a0cedeb2dc280ba39b857546d74f5549c3a1d7bdc2dd96bf881f76108e23dac2"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&struct_hash(&types, "Mail", &message).unwrap()),
+        "c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&domain.separator()),
+        "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&signing_hash(&domain, &types, "Mail", &message).unwrap()),
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+    );
+
+    // Signed by the key of Cow in the example, as eth_signTypedData_v4 does
+    let key = PrivateKey::from(keccak256(b"cow"));
+    let signature = key
+        .sign_typed_data(&domain, &types, "Mail", &message)
+        .unwrap();
+    assert_eq!(signature.v, 28u8.into());
+    assert_eq!(
+        bytes_to_hex_str(&signature.to_bytes()[..64]),
+        "4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+         07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562"
+    );
+}
+
+#[test]
+fn eip712_arrays_and_errors() {
+    let (_, mut types, message) = mail();
+    types.insert(
+        "Group".to_owned(),
+        vec![
+            Member::new("members", "Person[]"),
+            Member::new("ids", "uint8[2]"),
+            Member::new("tag", "bytes4"),
+        ],
+    );
+    assert_eq!(
+        encode_type(&types, "Group").unwrap(),
+        "Group(Person[] members,uint8[2] ids,bytes4 tag)Person(string name,address wallet)"
+    );
+    let person = match message {
+        Token::Tuple(ref members) => members[0].clone(),
+        _ => unreachable!(),
+    };
+    let group = |ids: Vec<Token>, tag: Vec<u8>| {
+        Token::Tuple(vec![
+            Token::Dynamic(vec![person.clone(), person.clone()]),
+            Token::FixedArray(ids),
+            Token::Bytes(tag),
+        ])
+    };
+    let data = encode_data(
+        &types,
+        "Group",
+        &group(vec![1u8.into(), 2u8.into()], vec![7; 4]),
+    )
+    .unwrap();
+    assert_eq!(data.len(), 4 * 32);
+    let person_hash = struct_hash(&types, "Person", &person).unwrap();
+    assert_eq!(
+        &data[32..64],
+        &keccak256(&[person_hash, person_hash].concat())
+    );
+
+    let invalid = |value: &Token| {
+        encode_data(&types, "Group", value)
+            .unwrap_err()
+            .downcast::<Eip712Error>()
+            .unwrap()
+    };
+    assert!(matches!(
+        invalid(&group(vec![1u8.into()], vec![7; 4])),
+        Eip712Error::InvalidValue(_, ref type_name) if type_name == "uint8[2]"
+    ));
+    assert!(matches!(
+        invalid(&group(vec![1u8.into(), 256u16.into()], vec![7; 4])),
+        Eip712Error::InvalidValue(_, ref type_name) if type_name == "uint8"
+    ));
+    assert!(matches!(
+        invalid(&group(vec![1u8.into(), 2u8.into()], vec![7; 3])),
+        Eip712Error::InvalidValue(_, ref type_name) if type_name == "bytes4"
+    ));
+    assert_eq!(
+        invalid(&Token::Tuple(vec![])),
+        Eip712Error::MemberCount {
+            name: "Group".to_owned(),
+            expected: 3,
+            found: 0,
+        }
+    );
+
+    // Signed values have to fit their width too
+    types.insert("Tick".to_owned(), vec![Member::new("index", "int8")]);
+    let tick = |index: i32| encode_data(&types, "Tick", &Token::Tuple(vec![index.into()]));
+    for index in &[-128, -1, 0, 127] {
+        tick(*index).unwrap();
+    }
+    for index in &[1000, 128, -129] {
+        assert!(matches!(
+            tick(*index).unwrap_err().downcast::<Eip712Error>().unwrap(),
+            Eip712Error::InvalidValue(_, ref type_name) if type_name == "int8"
+        ));
+    }
+    assert_eq!(
+        encode_type(&types, "Letter")
+            .unwrap_err()
+            .downcast::<Eip712Error>()
+            .unwrap(),
+        Eip712Error::UnknownType("Letter".to_owned())
+    );
+}
+
+#[test]
+fn eip712_json() {
+    use utils::bytes_to_hex_str;
+    let json = r#"{
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"},
+                {"name": "version", "type": "string"},
+                {"name": "chainId", "type": "uint256"},
+                {"name": "verifyingContract", "type": "address"}
+            ],
+            "Person": [
+                {"name": "name", "type": "string"},
+                {"name": "wallet", "type": "address"}
+            ],
+            "Mail": [
+                {"name": "from", "type": "Person"},
+                {"name": "to", "type": "Person"},
+                {"name": "contents", "type": "string"}
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+            "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+    let mut typed_data: TypedData = serde_json::from_str(json).unwrap();
+    let (domain, types, message) = mail();
+    assert_eq!(typed_data.message().unwrap(), message);
+    assert_eq!(typed_data.domain_separator().unwrap(), domain.separator());
+    assert_eq!(
+        bytes_to_hex_str(&typed_data.signing_hash().unwrap()),
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+    );
+    assert_eq!(
+        typed_data.signing_hash().unwrap(),
+        signing_hash(&domain, &types, "Mail", &message).unwrap()
+    );
+
+    // Without the domain type it follows from the fields of the domain
+    typed_data.types.remove(DOMAIN_TYPE_NAME);
+    assert_eq!(typed_data.domain_separator().unwrap(), domain.separator());
+
+    let kinds = |type_name: &str, json: &str| {
+        json_to_token(&types, type_name, &serde_json::from_str(json).unwrap())
+    };
+    assert_eq!(kinds("uint256", r#""0x10""#).unwrap(), 16u8.into());
+    assert_eq!(kinds("uint8", r#""255""#).unwrap(), 255u8.into());
+    assert_eq!(kinds("int32", "-5").unwrap(), Token::Int((-5i32).into()));
+    assert_eq!(
+        kinds("int32", r#""-0x10""#).unwrap(),
+        Token::Int((-16i32).into())
+    );
+    assert!(kinds("uint256", "-1").is_err());
+    assert!(kinds("uint256", "1.5").is_err());
+    assert_eq!(
+        kinds("bytes2[]", r#"["0x0102", "0x0304"]"#).unwrap(),
+        Token::Dynamic(vec![Token::Bytes(vec![1, 2]), Token::Bytes(vec![3, 4])])
+    );
+    assert_eq!(
+        kinds("Person", r#"{"name": "Cow"}"#)
+            .unwrap_err()
+            .downcast::<Eip712Error>()
+            .unwrap(),
+        Eip712Error::MissingMember("Person".to_owned(), "wallet".to_owned())
+    );
+}
@@ -0,0 +1,395 @@
+//! ENS names: their normalization and namehash.
+//!
+//! Names are resolved by the [namehash](fn.namehash.html) of their
+//! normalized form, so two spellings that normalize alike resolve alike,
+//! and a name that looks like another but normalizes differently resolves
+//! elsewhere. [normalize](fn.normalize.html) follows
+//! [ENSIP-15](https://docs.ens.domains/ensip/15) for names written in Latin,
+//! Greek, Cyrillic, Chinese, Japanese and Korean: it lowercases and composes
+//! them, and refuses labels that mix scripts, such as a Cyrillic `а` in
+//! `аpple`, or that are written wholly with letters that look like those of
+//! another script, such as a Cyrillic `арре`, since those are how names are
+//! spoofed.
+//!
+//! This is a subset of ENSIP-15: the letters are those of the Latin-1 and
+//! Latin Extended-A blocks, of modern Greek and basic Cyrillic, kana, the CJK
+//! Unified Ideographs and Hangul syllables. Emoji, other letters and
+//! scripts, and the characters ENSIP-15 ignores are refused rather than
+//! normalized, so a name this accepts is normalized as ENSIP-15 does, but
+//! not every name ENSIP-15 accepts is accepted here. The characters are
+//! taken from its `spec.json`, of Unicode 16.
+use hash::keccak256;
+use std::cmp::Ordering;
+use unicode_normalization::UnicodeNormalization;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum EnsError {
+    #[fail(display = "ENS names have no empty labels")]
+    EmptyLabel,
+    #[fail(display = "{:?} can not be in an ENS name", _0)]
+    DisallowedCharacter(char),
+    #[fail(display = "The label {:?} has an underscore after its start", _0)]
+    MisplacedUnderscore(String),
+    #[fail(
+        display = "The label {:?} has hyphens as its third and fourth characters",
+        _0
+    )]
+    LabelExtension(String),
+    #[fail(display = "The label {:?} mixes scripts", _0)]
+    MixedScripts(String),
+    #[fail(
+        display = "The label {:?} is confusable with one in another script",
+        _0
+    )]
+    Confusable(String),
+}
+
+/// The scripts that a character can be written in, as the groups of
+/// ENSIP-15.
+const LATIN: u8 = 1;
+const GREEK: u8 = 1 << 1;
+const CYRILLIC: u8 = 1 << 2;
+const HAN: u8 = 1 << 3;
+const JAPANESE: u8 = 1 << 4;
+const KOREAN: u8 = 1 << 5;
+const CJK: u8 = HAN | JAPANESE | KOREAN;
+/// The scripts that ASCII letters can be written with.
+const WITH_LATIN: u8 = LATIN | CJK;
+const ANY: u8 = LATIN | GREEK | CYRILLIC | CJK;
+/// Any of the scripts that are not supported, in which a character has a
+/// lookalike.
+const OTHER: u8 = 1 << 6;
+/// A character that looks like no character of another script.
+const UNIQUE: u8 = 1 << 7;
+/// A character that is not of one script, such as a digit.
+const COMMON: u8 = 0;
+
+/// The characters a normalized name may have, as ranges of characters with
+/// the scripts they can be written in, and either the scripts that have
+/// lookalikes of them, `UNIQUE` or `COMMON`.
+const CHARACTERS: &[(char, char, u8, u8)] = &[
+    ('\u{2d}', '\u{2d}', ANY, COMMON),
+    ('\u{30}', '\u{39}', ANY, COMMON),
+    ('\u{5f}', '\u{5f}', ANY, COMMON),
+    ('\u{61}', '\u{7a}', WITH_LATIN, COMMON),
+    ('\u{df}', '\u{ef}', LATIN, UNIQUE),
+    ('\u{f1}', '\u{f6}', LATIN, UNIQUE),
+    ('\u{f8}', '\u{fd}', LATIN, UNIQUE),
+    ('\u{101}', '\u{101}', LATIN, UNIQUE),
+    ('\u{103}', '\u{103}', LATIN, UNIQUE),
+    ('\u{105}', '\u{105}', LATIN, UNIQUE),
+    ('\u{107}', '\u{107}', LATIN, UNIQUE),
+    ('\u{109}', '\u{109}', LATIN, UNIQUE),
+    ('\u{10d}', '\u{10d}', LATIN, UNIQUE),
+    ('\u{113}', '\u{113}', LATIN, UNIQUE),
+    ('\u{119}', '\u{119}', LATIN, UNIQUE),
+    ('\u{11b}', '\u{11b}', LATIN, UNIQUE),
+    ('\u{11f}', '\u{11f}', LATIN, UNIQUE),
+    ('\u{142}', '\u{142}', LATIN, UNIQUE),
+    ('\u{144}', '\u{144}', LATIN, UNIQUE),
+    ('\u{14d}', '\u{14d}', LATIN, UNIQUE),
+    ('\u{15b}', '\u{15b}', LATIN, UNIQUE),
+    ('\u{15f}', '\u{15f}', LATIN, UNIQUE),
+    ('\u{161}', '\u{161}', LATIN, UNIQUE),
+    ('\u{16b}', '\u{16b}', LATIN, UNIQUE),
+    ('\u{17a}', '\u{17a}', LATIN, UNIQUE),
+    ('\u{17c}', '\u{17c}', LATIN, UNIQUE),
+    ('\u{17e}', '\u{17e}', LATIN, UNIQUE),
+    ('\u{390}', '\u{390}', GREEK, UNIQUE),
+    ('\u{3ac}', '\u{3b0}', GREEK, UNIQUE),
+    ('\u{3b1}', '\u{3b1}', GREEK, WITH_LATIN | CYRILLIC | OTHER),
+    ('\u{3b2}', '\u{3b2}', GREEK, LATIN | OTHER),
+    ('\u{3b3}', '\u{3b3}', GREEK, WITH_LATIN | CYRILLIC | OTHER),
+    ('\u{3b4}', '\u{3b4}', GREEK, OTHER),
+    ('\u{3b5}', '\u{3b5}', GREEK, CYRILLIC | OTHER),
+    ('\u{3b6}', '\u{3b8}', GREEK, UNIQUE),
+    ('\u{3b9}', '\u{3b9}', GREEK, WITH_LATIN | CYRILLIC | OTHER),
+    ('\u{3ba}', '\u{3ba}', GREEK, CYRILLIC | OTHER),
+    ('\u{3bb}', '\u{3bc}', GREEK, UNIQUE),
+    ('\u{3bd}', '\u{3bd}', GREEK, WITH_LATIN | OTHER),
+    ('\u{3be}', '\u{3be}', ANY, COMMON),
+    ('\u{3bf}', '\u{3bf}', GREEK, WITH_LATIN | CYRILLIC | OTHER),
+    ('\u{3c0}', '\u{3c0}', LATIN | GREEK, COMMON),
+    ('\u{3c1}', '\u{3c1}', GREEK, WITH_LATIN | CYRILLIC | OTHER),
+    ('\u{3c2}', '\u{3c2}', GREEK, UNIQUE),
+    ('\u{3c3}', '\u{3c3}', GREEK, WITH_LATIN | CYRILLIC | OTHER),
+    ('\u{3c4}', '\u{3c4}', GREEK, CYRILLIC),
+    ('\u{3c5}', '\u{3c5}', GREEK, WITH_LATIN | OTHER),
+    ('\u{3c6}', '\u{3c6}', GREEK, CYRILLIC | OTHER),
+    ('\u{3c7}', '\u{3ce}', GREEK, UNIQUE),
+    ('\u{430}', '\u{430}', CYRILLIC, WITH_LATIN | GREEK | OTHER),
+    ('\u{431}', '\u{431}', CYRILLIC, OTHER),
+    ('\u{432}', '\u{432}', CYRILLIC, UNIQUE),
+    ('\u{433}', '\u{433}', CYRILLIC, WITH_LATIN | OTHER),
+    ('\u{434}', '\u{434}', CYRILLIC, UNIQUE),
+    ('\u{435}', '\u{435}', CYRILLIC, WITH_LATIN | OTHER),
+    ('\u{436}', '\u{439}', CYRILLIC, UNIQUE),
+    ('\u{43a}', '\u{43a}', CYRILLIC, GREEK | OTHER),
+    ('\u{43b}', '\u{43d}', CYRILLIC, UNIQUE),
+    ('\u{43e}', '\u{43e}', CYRILLIC, WITH_LATIN | GREEK | OTHER),
+    ('\u{43f}', '\u{43f}', CYRILLIC, UNIQUE),
+    ('\u{440}', '\u{440}', CYRILLIC, WITH_LATIN | GREEK | OTHER),
+    ('\u{441}', '\u{441}', CYRILLIC, WITH_LATIN | OTHER),
+    ('\u{442}', '\u{442}', CYRILLIC, GREEK),
+    ('\u{443}', '\u{443}', CYRILLIC, WITH_LATIN | GREEK | OTHER),
+    ('\u{444}', '\u{444}', CYRILLIC, GREEK | OTHER),
+    ('\u{445}', '\u{445}', CYRILLIC, WITH_LATIN | OTHER),
+    ('\u{446}', '\u{44f}', CYRILLIC, UNIQUE),
+    ('\u{451}', '\u{453}', CYRILLIC, UNIQUE),
+    ('\u{454}', '\u{454}', CYRILLIC, GREEK | OTHER),
+    ('\u{455}', '\u{455}', CYRILLIC, WITH_LATIN | OTHER),
+    ('\u{456}', '\u{456}', CYRILLIC, WITH_LATIN | GREEK | OTHER),
+    ('\u{457}', '\u{457}', CYRILLIC, UNIQUE),
+    ('\u{458}', '\u{458}', CYRILLIC, WITH_LATIN | OTHER),
+    ('\u{459}', '\u{45c}', CYRILLIC, UNIQUE),
+    ('\u{45e}', '\u{45f}', CYRILLIC, UNIQUE),
+    ('\u{3041}', '\u{304e}', JAPANESE, UNIQUE),
+    ('\u{304f}', '\u{304f}', JAPANESE, COMMON),
+    ('\u{3050}', '\u{3077}', JAPANESE, UNIQUE),
+    ('\u{3078}', '\u{3078}', JAPANESE, COMMON),
+    ('\u{3079}', '\u{3096}', JAPANESE, UNIQUE),
+    ('\u{309d}', '\u{309e}', JAPANESE, UNIQUE),
+    ('\u{30a1}', '\u{30a3}', JAPANESE, UNIQUE),
+    ('\u{30a4}', '\u{30a4}', JAPANESE, COMMON),
+    ('\u{30a5}', '\u{30a7}', JAPANESE, UNIQUE),
+    ('\u{30a8}', '\u{30a8}', JAPANESE, COMMON),
+    ('\u{30a9}', '\u{30aa}', JAPANESE, UNIQUE),
+    ('\u{30ab}', '\u{30ab}', JAPANESE, COMMON),
+    ('\u{30ac}', '\u{30be}', JAPANESE, UNIQUE),
+    ('\u{30bf}', '\u{30bf}', JAPANESE, COMMON),
+    ('\u{30c0}', '\u{30c7}', JAPANESE, UNIQUE),
+    ('\u{30c8}', '\u{30c8}', JAPANESE, COMMON),
+    ('\u{30c9}', '\u{30cd}', JAPANESE, UNIQUE),
+    ('\u{30ce}', '\u{30ce}', JAPANESE, COMMON),
+    ('\u{30cf}', '\u{30d7}', JAPANESE, UNIQUE),
+    ('\u{30d8}', '\u{30d8}', JAPANESE, COMMON),
+    ('\u{30d9}', '\u{30ec}', JAPANESE, UNIQUE),
+    ('\u{30ed}', '\u{30ed}', JAPANESE, COMMON),
+    ('\u{30ee}', '\u{30fa}', JAPANESE, UNIQUE),
+    ('\u{30fc}', '\u{30fc}', JAPANESE, COMMON),
+    ('\u{30fd}', '\u{30fe}', JAPANESE, UNIQUE),
+    ('\u{4e00}', '\u{9fff}', CJK, COMMON),
+    ('\u{ac00}', '\u{d7a3}', KOREAN, UNIQUE),
+];
+
+/// The scripts of a character of a normalized name and those with
+/// lookalikes of it, or `None` if it can not be in one.
+fn scripts(c: char) -> Option<(u8, u8)> {
+    CHARACTERS
+        .binary_search_by(|&(first, last, _, _)| {
+            if last < c {
+                Ordering::Less
+            } else if first > c {
+                Ordering::Greater
+            } else {
+                Ordering::Equal
+            }
+        })
+        .ok()
+        .map(|i| (CHARACTERS[i].2, CHARACTERS[i].3))
+}
+
+/// A combining mark, which may compose with the letter before it. ENSIP-15
+/// disallows U+0340 and U+0341, although they normalize to U+0300 and U+0301.
+fn is_mark(c: char) -> bool {
+    ('\u{300}'..='\u{36f}').contains(&c) && c != '\u{340}' && c != '\u{341}'
+}
+
+/// Normalizes a name, the labels of which are separated by dots.
+///
+/// ```rust
+/// use clarity::ens::{normalize, EnsError};
+///
+/// assert_eq!(normalize("Nick.ETH").unwrap(), "nick.eth");
+/// // A Cyrillic `а` among Latin letters
+/// assert_eq!(
+///     normalize("\u{430}pple.eth"),
+///     Err(EnsError::MixedScripts("\u{430}pple".to_owned()))
+/// );
+/// ```
+pub fn normalize(name: &str) -> Result<String, EnsError> {
+    if name.is_empty() {
+        return Ok(String::new());
+    }
+    let labels = name
+        .split('.')
+        .map(normalize_label)
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(labels.join("."))
+}
+
+fn normalize_label(label: &str) -> Result<String, EnsError> {
+    let mut lowercase = String::with_capacity(label.len());
+    for c in label.chars() {
+        // Marks that do not compose with the letters before them are
+        // refused below
+        if !is_mark(c) && !c.is_uppercase() && scripts(c).is_none() {
+            return Err(EnsError::DisallowedCharacter(c));
+        }
+        lowercase.extend(c.to_lowercase());
+    }
+    let label: String = lowercase.nfc().collect();
+    if label.is_empty() {
+        return Err(EnsError::EmptyLabel);
+    }
+    let mut groups = ANY;
+    let mut common = ANY;
+    let mut lookalikes = None;
+    let mut unique = false;
+    for c in label.chars() {
+        let (scripts, whole) = scripts(c).ok_or(EnsError::DisallowedCharacter(c))?;
+        groups &= scripts;
+        match whole {
+            COMMON => common &= scripts,
+            UNIQUE => unique = true,
+            _ => lookalikes = Some(lookalikes.unwrap_or(whole) & whole),
+        }
+    }
+    if label.trim_start_matches('_').contains('_') {
+        return Err(EnsError::MisplacedUnderscore(label));
+    }
+    if label.is_ascii() {
+        // Saved for the `xn--` of punycode
+        if label.get(2..4) == Some("--") {
+            return Err(EnsError::LabelExtension(label));
+        }
+        return Ok(label);
+    }
+    if groups == 0 {
+        return Err(EnsError::MixedScripts(label));
+    }
+    // Written wholly with characters that have lookalikes in one script,
+    // which also has its common characters. The scripts of `OTHER` are not
+    // known, so a lookalike in any of them is taken to be enough.
+    if let Some(lookalikes) = lookalikes {
+        if !unique && (lookalikes & OTHER != 0 || lookalikes & common != 0) {
+            return Err(EnsError::Confusable(label));
+        }
+    }
+    Ok(label)
+}
+
+/// The node of a name in the ENS registry, which has to be normalized
+/// first.
+///
+/// ```rust
+/// use clarity::ens::{namehash, normalize};
+///
+/// assert_eq!(namehash(""), [0; 32]);
+/// assert_eq!(namehash(&normalize("Vitalik.eth").unwrap()), namehash("vitalik.eth"));
+/// ```
+pub fn namehash(name: &str) -> [u8; 32] {
+    let mut node = [0u8; 32];
+    if name.is_empty() {
+        return node;
+    }
+    for label in name.rsplit('.') {
+        let mut data = node.to_vec();
+        data.extend_from_slice(&keccak256(label.as_bytes()));
+        node = keccak256(&data);
+    }
+    node
+}
+
+#[test]
+fn normalize_names() {
+    // From the validation tests of ENSIP-15
+    for &(name, normalized) in &[
+        ("", ""),
+        ("vitalik.eth", "vitalik.eth"),
+        ("123.eth", "123.eth"),
+        (
+            "abcdefghijklmnopqrstuvwxyz-0123456789",
+            "abcdefghijklmnopqrstuvwxyz-0123456789",
+        ),
+        ("bRAnTlY.eTh", "brantly.eth"),
+        ("BRANTLYMILLEGAN.COM", "brantlymillegan.com"),
+        ("nowzad.loopring.eth", "nowzad.loopring.eth"),
+        ("\u{d6}bb.at", "\u{f6}bb.at"),
+        ("\u{df}", "\u{df}"),
+        ("\u{3c2}", "\u{3c2}"),
+        ("_____a", "_____a"),
+        ("test-", "test-"),
+        ("t-e--s---t", "t-e--s---t"),
+        (
+            "\u{3ac}\u{3ad}\u{3ae}\u{3af}\u{3ca}\u{390}\u{3cb}\u{3cc}\u{3cd}\u{3b0}\u{3ce}",
+            "\u{3ac}\u{3ad}\u{3ae}\u{3af}\u{3ca}\u{390}\u{3cb}\u{3cc}\u{3cd}\u{3b0}\u{3ce}",
+        ),
+        (
+            "\u{304c}\u{304e}\u{3050}\u{3052}\u{3054}",
+            "\u{304c}\u{304e}\u{3050}\u{3052}\u{3054}",
+        ),
+        ("0x", "0x"),
+        ("\u{9ed1}a8", "\u{9ed1}a8"),
+        ("\u{30fc}\u{4e00}a", "\u{30fc}\u{4e00}a"),
+    ] {
+        assert_eq!(normalize(name).as_ref().map(|n| &n[..]), Ok(normalized));
+    }
+    // Decomposed letters are composed
+    assert_eq!(normalize("o\u{308}bb.at").unwrap(), "\u{f6}bb.at");
+
+    assert_eq!(normalize(".eth"), Err(EnsError::EmptyLabel));
+    assert_eq!(normalize("eth."), Err(EnsError::EmptyLabel));
+    assert_eq!(normalize("te st"), Err(EnsError::DisallowedCharacter(' ')));
+    assert_eq!(normalize("'a"), Err(EnsError::DisallowedCharacter('\'')));
+    assert_eq!(
+        normalize("\u{fe}"),
+        Err(EnsError::DisallowedCharacter('\u{fe}'))
+    );
+    // A combining mark with nothing to compose with
+    assert_eq!(
+        normalize("\u{303}.eth"),
+        Err(EnsError::DisallowedCharacter('\u{303}'))
+    );
+    assert_eq!(
+        normalize("a_b"),
+        Err(EnsError::MisplacedUnderscore("a_b".to_owned()))
+    );
+    assert_eq!(
+        normalize("xn--ls8h"),
+        Err(EnsError::LabelExtension("xn--ls8h".to_owned()))
+    );
+    assert_eq!(
+        normalize("te--st"),
+        Err(EnsError::LabelExtension("te--st".to_owned()))
+    );
+    // Digits and a Cyrillic `х`, and a Cyrillic `apple`
+    assert_eq!(
+        normalize("0\u{445}"),
+        Err(EnsError::Confusable("0\u{445}".to_owned()))
+    );
+    assert_eq!(
+        normalize("\u{430}\u{440}\u{440}\u{4cf}\u{435}"),
+        Err(EnsError::DisallowedCharacter('\u{4cf}'))
+    );
+    assert_eq!(
+        normalize("\u{430}\u{440}\u{440}\u{435}"),
+        Err(EnsError::Confusable(
+            "\u{430}\u{440}\u{440}\u{435}".to_owned()
+        ))
+    );
+    // Russian for "peace", which has letters that no other script has
+    assert_eq!(
+        normalize("\u{41c}\u{438}\u{440}").unwrap(),
+        "\u{43c}\u{438}\u{440}"
+    );
+    assert_eq!(
+        normalize("\u{3b1}\u{43c}"),
+        Err(EnsError::MixedScripts("\u{3b1}\u{43c}".to_owned()))
+    );
+}
+
+#[test]
+fn namehash_of_names() {
+    use utils::bytes_to_hex_str;
+    // From EIP-137
+    assert_eq!(namehash(""), [0; 32]);
+    assert_eq!(
+        bytes_to_hex_str(&namehash("eth")),
+        "93cdeb708b7545dc668eb9280176169d1c33cfd8ed6f04690a0bcc88a93fc4ae"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&namehash("foo.eth")),
+        "de9b09fd7c5f901e23a3f19fecc54828e9c848539801e86591bd9801b019f84f"
+    );
+}
@@ -0,0 +1,452 @@
+//! Reading blocks from Era1 archives.
+//!
+//! Era1 files hold pre-merge history in groups of 8192 blocks, as the
+//! entries of an e2store file: each block is a header, a body, its receipts
+//! and its total difficulty, followed at the end of the file by an
+//! accumulator and an index. Headers, bodies and receipts are compressed
+//! with the framing format of snappy, which is decompressed here.
+//!
+//! [Era1Reader](struct.Era1Reader.html) yields the blocks of one or more
+//! Era1 files read one after the other, skipping their receipts.
+use block_file::{decode_body, read_or_end, Block, BlockFileError, Header};
+use failure::Error;
+use num256::Uint256;
+use rlp::{decode_raw_list, decode_with, DecodeConfig};
+use std::io::Read;
+
+/// Entry types of Era1 files.
+const VERSION: [u8; 2] = [0x65, 0x32];
+const COMPRESSED_HEADER: [u8; 2] = [0x03, 0x00];
+const COMPRESSED_BODY: [u8; 2] = [0x04, 0x00];
+const TOTAL_DIFFICULTY: [u8; 2] = [0x06, 0x00];
+
+/// Identifies a snappy frame stream.
+const SNAPPY_STREAM_ID: &[u8] = b"sNaPpY";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum Era1Error {
+    #[fail(display = "File does not start with an e2store version entry")]
+    MissingVersion,
+    #[fail(display = "Invalid e2store entry header")]
+    InvalidEntry,
+    #[fail(display = "Block {} is missing its header or body", _0)]
+    IncompleteBlock(usize),
+    #[fail(display = "Invalid snappy data")]
+    InvalidSnappy,
+    #[fail(display = "Snappy checksum does not match")]
+    ChecksumMismatch,
+}
+
+/// A block of an Era1 file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Era1Block {
+    pub block: Block,
+    pub total_difficulty: Uint256,
+}
+
+const fn crc32c_table() -> [u32; 256] {
+    let mut table = [0u32; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u32;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+}
+
+const CRC32C_TABLE: [u32; 256] = crc32c_table();
+
+fn crc32c(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, byte| {
+        CRC32C_TABLE[((crc ^ u32::from(*byte)) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+/// The checksum snappy frames carry.
+fn masked_crc32c(data: &[u8]) -> u32 {
+    crc32c(data).rotate_right(15).wrapping_add(0xa282_ead8)
+}
+
+fn le_bytes(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |acc, byte| (acc << 8) | usize::from(*byte))
+}
+
+/// Decompresses a snappy block onto `out`, which may already hold earlier
+/// blocks, without letting it grow past `max_len`.
+fn decompress_snappy_block(input: &[u8], out: &mut Vec<u8>, max_len: usize) -> Result<(), Error> {
+    let mut length = 0u64;
+    let mut pos = 0;
+    loop {
+        let byte = *input.get(pos).ok_or(Era1Error::InvalidSnappy)?;
+        if pos == 5 {
+            return Err(Era1Error::InvalidSnappy.into());
+        }
+        length |= u64::from(byte & 0x7f) << (7 * pos);
+        pos += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+    }
+    let start = out.len();
+    if length > (max_len - start) as u64 {
+        return Err(BlockFileError::BlockTooLarge(start as u64 + length).into());
+    }
+    let end = start + length as usize;
+    while pos < input.len() {
+        let tag = input[pos];
+        pos += 1;
+        let (copy_len, offset_len) = match tag & 0x03 {
+            0 => {
+                let mut literal_len = usize::from(tag >> 2);
+                if literal_len >= 60 {
+                    let len_bytes = literal_len - 59;
+                    let bytes = input
+                        .get(pos..pos + len_bytes)
+                        .ok_or(Era1Error::InvalidSnappy)?;
+                    literal_len = le_bytes(bytes);
+                    pos += len_bytes;
+                }
+                let literal = input
+                    .get(pos..pos + literal_len + 1)
+                    .ok_or(Era1Error::InvalidSnappy)?;
+                if out.len() + literal.len() > end {
+                    return Err(Era1Error::InvalidSnappy.into());
+                }
+                out.extend_from_slice(literal);
+                pos += literal.len();
+                continue;
+            }
+            1 => (usize::from((tag >> 2) & 0x07) + 4, 1),
+            2 => (usize::from(tag >> 2) + 1, 2),
+            _ => (usize::from(tag >> 2) + 1, 4),
+        };
+        let offset_bytes = input
+            .get(pos..pos + offset_len)
+            .ok_or(Era1Error::InvalidSnappy)?;
+        let mut offset = le_bytes(offset_bytes);
+        if offset_len == 1 {
+            offset |= usize::from(tag >> 5) << 8;
+        }
+        pos += offset_len;
+        if offset == 0 || offset > out.len() - start || out.len() + copy_len > end {
+            return Err(Era1Error::InvalidSnappy.into());
+        }
+        // Copies may overlap what they produce, so go byte by byte
+        for _ in 0..copy_len {
+            let byte = out[out.len() - offset];
+            out.push(byte);
+        }
+    }
+    if out.len() != end {
+        return Err(Era1Error::InvalidSnappy.into());
+    }
+    Ok(())
+}
+
+/// Decompresses data in the framing format of snappy, up to `max_len`
+/// bytes.
+fn decompress_snappy_frames(input: &[u8], max_len: usize) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::new();
+    let mut rest = input;
+    let mut started = false;
+    while !rest.is_empty() {
+        let header = rest.get(..4).ok_or(Era1Error::InvalidSnappy)?;
+        let chunk_len = le_bytes(&header[1..]);
+        let chunk = rest.get(4..4 + chunk_len).ok_or(Era1Error::InvalidSnappy)?;
+        rest = &rest[4 + chunk_len..];
+        match header[0] {
+            0xff => {
+                if chunk != SNAPPY_STREAM_ID {
+                    return Err(Era1Error::InvalidSnappy.into());
+                }
+                started = true;
+            }
+            chunk_type @ 0x00..=0x01 => {
+                if !started || chunk.len() < 4 {
+                    return Err(Era1Error::InvalidSnappy.into());
+                }
+                let start = out.len();
+                if chunk_type == 0x00 {
+                    decompress_snappy_block(&chunk[4..], &mut out, max_len)?;
+                } else {
+                    if chunk.len() - 4 > max_len - start {
+                        return Err(BlockFileError::BlockTooLarge(
+                            (start + chunk.len() - 4) as u64,
+                        )
+                        .into());
+                    }
+                    out.extend_from_slice(&chunk[4..]);
+                }
+                if masked_crc32c(&out[start..]) != le_bytes(&chunk[..4]) as u32 {
+                    return Err(Era1Error::ChecksumMismatch.into());
+                }
+            }
+            // Reserved chunks that cannot be skipped
+            0x02..=0x7f => return Err(Era1Error::InvalidSnappy.into()),
+            // Padding and skippable chunks
+            _ => {}
+        }
+    }
+    Ok(out)
+}
+
+/// An e2store entry.
+struct Entry {
+    entry_type: [u8; 2],
+    data: Vec<u8>,
+}
+
+/// Reads the next e2store entry, or returns `None` at the end of the input.
+fn read_entry<R: Read>(reader: &mut R) -> Result<Option<Entry>, Error> {
+    let mut header = [0u8; 8];
+    if !read_or_end(reader, &mut header)? {
+        return Ok(None);
+    }
+    if header[6..] != [0, 0] {
+        return Err(Era1Error::InvalidEntry.into());
+    }
+    let length = le_bytes(&header[2..6]) as u64;
+    let mut data = Vec::new();
+    // Grows with what is actually read, like block files
+    if (reader.take(length).read_to_end(&mut data)? as u64) < length {
+        return Err(BlockFileError::Truncated.into());
+    }
+    Ok(Some(Entry {
+        entry_type: [header[0], header[1]],
+        data,
+    }))
+}
+
+/// Reads the blocks of Era1 files, in order.
+///
+/// Reading stops after the first error.
+#[derive(Debug)]
+pub struct Era1Reader<R> {
+    reader: R,
+    config: DecodeConfig,
+    started: bool,
+    done: bool,
+    blocks: usize,
+}
+
+impl<R: Read> Era1Reader<R> {
+    pub fn new(reader: R) -> Era1Reader<R> {
+        Era1Reader::with_config(reader, DecodeConfig::default())
+    }
+
+    /// Decodes blocks within the limits of `config`. Its item length limit
+    /// also limits the size of headers and bodies once decompressed.
+    pub fn with_config(reader: R, config: DecodeConfig) -> Era1Reader<R> {
+        Era1Reader {
+            reader,
+            config,
+            started: false,
+            done: false,
+            blocks: 0,
+        }
+    }
+
+    fn next_block(&mut self) -> Result<Option<Era1Block>, Error> {
+        let mut header: Option<Header> = None;
+        let mut body = None;
+        while let Some(Entry { entry_type, data }) = read_entry(&mut self.reader)? {
+            if !self.started && entry_type != VERSION {
+                return Err(Era1Error::MissingVersion.into());
+            }
+            self.started = true;
+            match entry_type {
+                COMPRESSED_HEADER => {
+                    let bytes = decompress_snappy_frames(&data, self.config.max_item_len)?;
+                    header = Some(decode_with(&bytes, &self.config)?);
+                }
+                COMPRESSED_BODY => {
+                    let bytes = decompress_snappy_frames(&data, self.config.max_item_len)?;
+                    let parts = decode_raw_list(&bytes, &self.config)?;
+                    if parts.len() != 2 {
+                        return Err(BlockFileError::MalformedBlock.into());
+                    }
+                    body = Some(decode_body(parts[0], parts[1], &self.config)?);
+                }
+                TOTAL_DIFFICULTY => {
+                    let (header, (transactions, ommers)) = match (header.take(), body.take()) {
+                        (Some(header), Some(body)) => (header, body),
+                        _ => return Err(Era1Error::IncompleteBlock(self.blocks).into()),
+                    };
+                    if data.len() != 32 {
+                        return Err(Era1Error::InvalidEntry.into());
+                    }
+                    // The only little-endian number here, as it is in SSZ
+                    let mut total_difficulty = [0u8; 32];
+                    total_difficulty.copy_from_slice(&data);
+                    total_difficulty.reverse();
+                    self.blocks += 1;
+                    return Ok(Some(Era1Block {
+                        block: Block {
+                            header,
+                            transactions,
+                            ommers,
+                            withdrawals: None,
+                        },
+                        total_difficulty: Uint256::from_bytes_be(&total_difficulty),
+                    }));
+                }
+                // Versions of files that follow, receipts, accumulators and
+                // indices
+                _ => {}
+            }
+        }
+        if header.is_some() || body.is_some() {
+            return Err(Era1Error::IncompleteBlock(self.blocks).into());
+        }
+        Ok(None)
+    }
+}
+
+impl<R: Read> Iterator for Era1Reader<R> {
+    type Item = Result<Era1Block, Error>;
+
+    fn next(&mut self) -> Option<Result<Era1Block, Error>> {
+        if self.done {
+            return None;
+        }
+        let block = self.next_block();
+        self.done = !matches!(block, Ok(Some(_)));
+        block.transpose()
+    }
+}
+
+/// The framing of `data` as a single compressed chunk holding one literal.
+#[cfg(test)]
+fn snappy_literal_frames(data: &[u8]) -> Vec<u8> {
+    let mut block = Vec::new();
+    let mut length = data.len();
+    while length >= 0x80 {
+        block.push(length as u8 | 0x80);
+        length >>= 7;
+    }
+    block.push(length as u8);
+    // A literal with a two byte length
+    block.push(61 << 2);
+    block.extend_from_slice(&(data.len() as u16 - 1).to_le_bytes());
+    block.extend_from_slice(data);
+
+    let mut frames = vec![0xff, 6, 0, 0];
+    frames.extend_from_slice(SNAPPY_STREAM_ID);
+    let chunk_len = (block.len() + 4) as u32;
+    frames.push(0x00);
+    frames.extend_from_slice(&chunk_len.to_le_bytes()[..3]);
+    frames.extend_from_slice(&masked_crc32c(data).to_le_bytes());
+    frames.extend(block);
+    frames
+}
+
+#[test]
+fn snappy() {
+    assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+
+    // "abc" as a literal, then a copy of 9 bytes from 3 bytes back
+    let mut out = Vec::new();
+    decompress_snappy_block(&[12, 0x08, b'a', b'b', b'c', 0x15, 0x03], &mut out, 100).unwrap();
+    assert_eq!(out, b"abcabcabcabc");
+    let mut out = Vec::new();
+    assert!(
+        decompress_snappy_block(&[12, 0x08, b'a', b'b', b'c', 0x15, 0x04], &mut out, 100).is_err()
+    );
+    let mut out = Vec::new();
+    assert!(
+        decompress_snappy_block(&[12, 0x08, b'a', b'b', b'c', 0x15, 0x03], &mut out, 11).is_err()
+    );
+
+    let data = vec![0x42u8; 300];
+    let frames = snappy_literal_frames(&data);
+    assert_eq!(decompress_snappy_frames(&frames, 1000).unwrap(), data);
+    let mut corrupted = frames.clone();
+    *corrupted.last_mut().unwrap() = 0;
+    assert_eq!(
+        decompress_snappy_frames(&corrupted, 1000)
+            .unwrap_err()
+            .downcast::<Era1Error>()
+            .unwrap(),
+        Era1Error::ChecksumMismatch
+    );
+    // Without the stream identifier
+    assert!(decompress_snappy_frames(&frames[10..], 1000).is_err());
+}
+
+#[test]
+fn read_era1() {
+    use block_file::{test_header, test_transactions, Encoded};
+    use rlp::{encode, encode_list};
+    let entry = |entry_type: [u8; 2], data: &[u8]| {
+        let mut entry = entry_type.to_vec();
+        entry.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        entry.extend_from_slice(&[0, 0]);
+        entry.extend_from_slice(data);
+        entry
+    };
+    let block = Block {
+        header: Header {
+            base_fee_per_gas: None,
+            withdrawals_root: None,
+            ..test_header(1)
+        },
+        transactions: test_transactions()[..1].to_vec(),
+        ommers: Vec::new(),
+        withdrawals: None,
+    };
+    // A body is the block without its header
+    let encoded = block.to_bytes().unwrap();
+    let parts = decode_raw_list(&encoded, &DecodeConfig::default()).unwrap();
+    let body = encode_list(&[&Encoded(parts[1]), &Encoded(parts[2])]);
+    let mut total_difficulty = [0u8; 32];
+    total_difficulty[0] = 0x10;
+
+    let mut file = entry(VERSION, &[]);
+    file.extend(entry(
+        COMPRESSED_HEADER,
+        &snappy_literal_frames(&encode(&block.header)),
+    ));
+    file.extend(entry(COMPRESSED_BODY, &snappy_literal_frames(&body)));
+    file.extend(entry(
+        [0x05, 0x00],
+        &snappy_literal_frames(&encode_list(&[])),
+    ));
+    file.extend(entry(TOTAL_DIFFICULTY, &total_difficulty));
+    file.extend(entry([0x07, 0x00], &[0u8; 32]));
+
+    let read: Vec<Era1Block> = Era1Reader::new(&file[..])
+        .collect::<Result<_, Error>>()
+        .unwrap();
+    assert_eq!(
+        read,
+        vec![Era1Block {
+            block,
+            total_difficulty: 0x10u8.into(),
+        }]
+    );
+
+    let mut reader = Era1Reader::new(&file[8..]);
+    assert_eq!(
+        reader
+            .next()
+            .unwrap()
+            .unwrap_err()
+            .downcast::<Era1Error>()
+            .unwrap(),
+        Era1Error::MissingVersion
+    );
+    assert!(reader.next().is_none());
+}
@@ -0,0 +1,228 @@
+//! Signature verification for both externally owned and contract accounts.
+//!
+//! Externally owned accounts are verified with `ecrecover`. Contract accounts
+//! (smart wallets, multisigs) implement ERC-1271 instead, which means they
+//! have to be asked through an `isValidSignature(bytes32,bytes)` call whether
+//! a signature is valid. Clarity does not talk to nodes, so for contract
+//! accounts the call is returned to the caller to execute, and its result can
+//! be interpreted with [is_valid_signature_result](fn.is_valid_signature_result.html).
+use abi::{derive_method_id, encode_call, Token};
+use address::Address;
+use failure::Error;
+use hash::ethereum_msg_hash;
+use signature::Signature;
+
+/// Signature of the ERC-1271 verification function.
+pub const IS_VALID_SIGNATURE: &str = "isValidSignature(bytes32,bytes)";
+
+/// Something that knows whether an address has code deployed.
+///
+/// In practice this is backed by `eth_getCode`, or a cache of it.
+pub trait CodeOracle {
+    fn has_code(&self, address: &Address) -> Result<bool, Error>;
+}
+
+impl<F> CodeOracle for F
+where
+    F: Fn(&Address) -> Result<bool, Error>,
+{
+    fn has_code(&self, address: &Address) -> Result<bool, Error> {
+        self(address)
+    }
+}
+
+/// What was signed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignedPayload<'a> {
+    /// A 32 byte digest, such as an EIP-712 typed data hash, signed as is
+    Digest(&'a [u8; 32]),
+    /// A message signed with `personal_sign`, which is hashed with the
+    /// `"\x19Ethereum Signed Message:\n"` prefix and its length
+    PersonalMessage(&'a [u8]),
+}
+
+impl<'a> SignedPayload<'a> {
+    /// The digest that is actually signed for this payload.
+    pub fn digest(&self) -> [u8; 32] {
+        match *self {
+            SignedPayload::Digest(digest) => *digest,
+            SignedPayload::PersonalMessage(msg) => ethereum_msg_hash(msg),
+        }
+    }
+}
+
+/// Outcome of [verify_signature](fn.verify_signature.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureVerification {
+    /// The signature was made by the private key of the address
+    Valid,
+    /// The address is a contract, and the signature has to be checked by
+    /// calling it. Pass the result of `eth_call` to
+    /// [is_valid_signature_result](fn.is_valid_signature_result.html).
+    ContractCall { to: Address, data: Vec<u8> },
+    /// The signature is not valid for the address
+    Invalid,
+}
+
+/// Verifies that `signature` over `payload` was made by `address`, whether it
+/// is an externally owned account or an ERC-1271 contract.
+///
+/// `ecrecover` is always tried first, so the code oracle is only consulted
+/// when the signature does not recover to the address.
+pub fn verify_signature<O: CodeOracle>(
+    address: &Address,
+    payload: SignedPayload,
+    signature: &[u8],
+    code_oracle: &O,
+) -> Result<SignatureVerification, Error> {
+    let digest = payload.digest();
+    if signature.len() == 65 {
+        let mut sig = Signature::from_bytes(signature)?;
+        // Some wallets produce recovery ids of 0 and 1 instead of 27 and 28
+        if sig.v < 27u32.into() {
+            sig.v += 27u32.into();
+        }
+        if let Ok(recovered) = sig.recover(&digest) {
+            if recovered == *address {
+                return Ok(SignatureVerification::Valid);
+            }
+        }
+    }
+    if code_oracle.has_code(address)? {
+        Ok(SignatureVerification::ContractCall {
+            to: *address,
+            data: encode_call(
+                IS_VALID_SIGNATURE,
+                &[
+                    Token::Bytes(digest.to_vec()),
+                    Token::UnboundedBytes(signature.to_vec()),
+                ],
+            ),
+        })
+    } else {
+        Ok(SignatureVerification::Invalid)
+    }
+}
+
+/// Interprets the return data of an `isValidSignature(bytes32,bytes)` call.
+///
+/// A signature is only valid when the contract returns the ERC-1271 magic
+/// value (the selector of the function itself). Anything else, including
+/// empty return data, is treated as invalid.
+pub fn is_valid_signature_result(return_data: &[u8]) -> bool {
+    return_data.len() == 32
+        && return_data[..4] == derive_method_id(IS_VALID_SIGNATURE)
+        && return_data[4..].iter().all(|b| *b == 0)
+}
+
+#[cfg(test)]
+fn no_code(_: &Address) -> Result<bool, Error> {
+    Ok(false)
+}
+
+#[cfg(test)]
+fn has_code(_: &Address) -> Result<bool, Error> {
+    Ok(true)
+}
+
+#[test]
+fn verify_eoa_signature() {
+    use private_key::PrivateKey;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let address = key.to_public_key().unwrap();
+    let payload = SignedPayload::PersonalMessage(b"Hello, world!");
+    let signature = key.sign_hash(&payload.digest()).to_bytes();
+    assert_eq!(
+        verify_signature(&address, payload, &signature, &no_code).unwrap(),
+        SignatureVerification::Valid
+    );
+    // Recovery id without the offset of 27
+    let mut raw_v = signature;
+    raw_v[64] -= 27;
+    assert_eq!(
+        verify_signature(&address, payload, &raw_v, &no_code).unwrap(),
+        SignatureVerification::Valid
+    );
+    // A different message
+    assert_eq!(
+        verify_signature(
+            &address,
+            SignedPayload::PersonalMessage(b"Hello, world?"),
+            &signature,
+            &no_code
+        )
+        .unwrap(),
+        SignatureVerification::Invalid
+    );
+}
+
+#[test]
+fn personal_message_digest() {
+    use utils::bytes_to_hex_str;
+    // Matches web3.eth.accounts.hashMessage("Hello World")
+    assert_eq!(
+        bytes_to_hex_str(&SignedPayload::PersonalMessage(b"Hello World").digest()),
+        "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2"
+    );
+}
+
+#[test]
+fn verify_contract_signature() {
+    use utils::bytes_to_hex_str;
+    let wallet = Address::from([0x11u8; 20]);
+    let digest = [0x22u8; 32];
+    // Contract signatures can be of any length
+    let signature = vec![0x33u8; 130];
+    match verify_signature(
+        &wallet,
+        SignedPayload::Digest(&digest),
+        &signature,
+        &has_code,
+    )
+    .unwrap()
+    {
+        SignatureVerification::ContractCall { to, data } => {
+            assert_eq!(to, wallet);
+            assert_eq!(bytes_to_hex_str(&data[..4]), "1626ba7e");
+            assert_eq!(&data[4..36], &digest);
+            assert_eq!(data.len(), 4 + 32 * 3 + 160);
+        }
+        other => panic!("Unexpected result {:?}", other),
+    }
+    assert_eq!(
+        verify_signature(
+            &wallet,
+            SignedPayload::Digest(&digest),
+            &signature,
+            &no_code
+        )
+        .unwrap(),
+        SignatureVerification::Invalid
+    );
+}
+
+#[test]
+fn code_oracle_errors_are_propagated() {
+    let failing = |_: &Address| -> Result<bool, Error> { Err(format_err!("Node unavailable")) };
+    assert!(verify_signature(
+        &Address::default(),
+        SignedPayload::Digest(&[0u8; 32]),
+        &[0u8; 65],
+        &failing
+    )
+    .is_err());
+}
+
+#[test]
+fn interpret_is_valid_signature_result() {
+    use utils::hex_str_to_bytes;
+    let magic =
+        hex_str_to_bytes("1626ba7e00000000000000000000000000000000000000000000000000000000")
+            .unwrap();
+    assert!(is_valid_signature_result(&magic));
+    assert!(!is_valid_signature_result(&magic[..4]));
+    assert!(!is_valid_signature_result(&[]));
+    assert!(!is_valid_signature_result(&[0xffu8; 32]));
+}
@@ -0,0 +1,377 @@
+//! Helpers for ERC-20 tokens.
+//!
+//! Approvals are easy to get subtly wrong. Unlimited approvals expose every
+//! token the owner will ever hold, and some tokens (most notably USDT)
+//! refuse to change an allowance from one non-zero value to another, so it
+//! has to be reset to zero first.
+//!
+//! Tokens also disagree on what `transfer` and `approve` return, see
+//! [decode_bool_result](fn.decode_bool_result.html), and on the type of
+//! their name and symbol, which
+//! [TokenMetadataBatch](struct.TokenMetadataBatch.html) takes care of.
+use abi::{decode_tokens, derive_method_id, encode_call, ParamType, Token};
+use address::Address;
+use constants::TT128;
+use failure::Error;
+use multicall::{aggregate3, decode_aggregate3, Call3};
+use num256::Uint256;
+use num_traits::{ToPrimitive, Zero};
+
+/// Signature of the ERC-20 `approve` function.
+pub const APPROVE: &str = "approve(address,uint256)";
+
+/// Calldata of an `approve(spender, amount)` call.
+pub fn approve(spender: Address, amount: Uint256) -> Vec<u8> {
+    encode_call(APPROVE, &[Token::Address(spender), Token::Uint(amount)])
+}
+
+/// Decodes the spender and amount out of `approve` calldata.
+///
+/// Returns `None` when the calldata is not a valid `approve` call.
+pub fn decode_approve(calldata: &[u8]) -> Option<(Address, Uint256)> {
+    if calldata.len() < 4 || calldata[..4] != derive_method_id(APPROVE) {
+        return None;
+    }
+    let tokens = decode_tokens(&[ParamType::Address, ParamType::Uint(256)], &calldata[4..]).ok()?;
+    match (&tokens[0], &tokens[1]) {
+        (&Token::Address(spender), Token::Uint(amount)) => Some((spender, amount.clone())),
+        _ => None,
+    }
+}
+
+/// The smallest amount [is_infinite_approval](fn.is_infinite_approval.html)
+/// takes for unlimited, 2^128. "Infinite" approvals are usually of the
+/// maximum amount, but no token has a supply anywhere near this, so an
+/// approval of any amount above it is just as unlimited.
+pub fn unlimited_approval_threshold() -> Uint256 {
+    TT128.clone()
+}
+
+/// Whether the calldata approves an amount of at least
+/// [unlimited_approval_threshold](fn.unlimited_approval_threshold.html),
+/// which is how "infinite" approvals are expressed.
+pub fn is_infinite_approval(calldata: &[u8]) -> bool {
+    is_approval_above(calldata, &unlimited_approval_threshold())
+}
+
+/// Whether the calldata approves an amount of at least `threshold`.
+pub fn is_approval_above(calldata: &[u8], threshold: &Uint256) -> bool {
+    match decode_approve(calldata) {
+        Some((_, amount)) => amount >= *threshold,
+        None => false,
+    }
+}
+
+/// Calls needed to change the allowance of `spender` from `current` to
+/// `amount`, in the order they have to be sent.
+///
+/// Changing a non-zero allowance to another non-zero value is done by
+/// resetting it to zero first. This is required by tokens such as USDT, and
+/// it is harmless for the others, so the sequence is the same for every
+/// token. The second call must only be sent once the first one is mined.
+/// Nothing has to be sent when the allowance already has the right value.
+pub fn approval_sequence(current: &Uint256, spender: Address, amount: Uint256) -> Vec<Vec<u8>> {
+    if *current == amount {
+        Vec::new()
+    } else if current.is_zero() || amount.is_zero() {
+        vec![approve(spender, amount)]
+    } else {
+        vec![approve(spender, Uint256::zero()), approve(spender, amount)]
+    }
+}
+
+#[test]
+fn approve_roundtrip() {
+    use utils::bytes_to_hex_str;
+    let spender = Address::from([0x22u8; 20]);
+    let calldata = approve(spender, 1000u32.into());
+    assert_eq!(bytes_to_hex_str(&calldata[..4]), "095ea7b3");
+    assert_eq!(decode_approve(&calldata), Some((spender, 1000u32.into())));
+    assert_eq!(decode_approve(&calldata[..35]), None);
+    assert_eq!(
+        decode_approve(&encode_call(
+            "transfer(address,uint256)",
+            &[Token::Address(spender), 1000u32.into()]
+        )),
+        None
+    );
+}
+
+#[test]
+fn detect_infinite_approval() {
+    use num_traits::Bounded;
+    let spender = Address::from([0x22u8; 20]);
+    assert!(is_infinite_approval(&approve(
+        spender,
+        Uint256::max_value()
+    )));
+    // Amounts just short of the maximum are no less unlimited
+    let almost = Uint256::max_value() - 1u32.into();
+    assert!(is_infinite_approval(&approve(spender, almost)));
+    let threshold = unlimited_approval_threshold();
+    assert!(is_infinite_approval(&approve(spender, threshold.clone())));
+    let below = threshold.clone() - 1u32.into();
+    assert!(!is_infinite_approval(&approve(spender, below.clone())));
+    assert!(is_approval_above(&approve(spender, below), &1000u32.into()));
+    assert!(!is_approval_above(
+        &approve(spender, 999u32.into()),
+        &1000u32.into()
+    ));
+    assert!(!is_infinite_approval(&[]));
+}
+
+#[test]
+fn approval_sequences() {
+    let spender = Address::from([0x22u8; 20]);
+    let zero = Uint256::zero();
+    let hundred: Uint256 = 100u32.into();
+    let thousand: Uint256 = 1000u32.into();
+    assert_eq!(
+        approval_sequence(&zero, spender, thousand.clone()),
+        vec![approve(spender, thousand.clone())]
+    );
+    assert_eq!(
+        approval_sequence(&hundred, spender, thousand.clone()),
+        vec![
+            approve(spender, zero.clone()),
+            approve(spender, thousand.clone())
+        ]
+    );
+    assert_eq!(
+        approval_sequence(&hundred, spender, zero.clone()),
+        vec![approve(spender, zero.clone())]
+    );
+    assert_eq!(
+        approval_sequence(&thousand, spender, thousand.clone()),
+        Vec::<Vec<u8>>::new()
+    );
+}
+
+/// How strictly the return data of `transfer`, `transferFrom` and
+/// `approve` is interpreted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnMode {
+    /// The call has to return a `bool`, as required by the standard
+    Strict,
+    /// Empty return data also counts as success, which is needed for tokens
+    /// such as USDT that do not return anything
+    Lenient,
+}
+
+/// Interprets the return data of a successful (i.e. not reverted)
+/// `transfer`, `transferFrom` or `approve` call.
+///
+/// Returns whether the token reports that the operation succeeded. Return
+/// data that is neither a `bool` nor, in lenient mode, empty is an error.
+pub fn decode_bool_result(return_data: &[u8], mode: ReturnMode) -> Result<bool, Error> {
+    if return_data.is_empty() {
+        return match mode {
+            ReturnMode::Lenient => Ok(true),
+            ReturnMode::Strict => Err(format_err!("Token call returned no data")),
+        };
+    }
+    ensure!(
+        return_data.len() == 32,
+        "Token call returned {} bytes instead of a bool",
+        return_data.len()
+    );
+    match decode_tokens(&[ParamType::Bool], return_data)?[0] {
+        Token::Bool(value) => Ok(value),
+        _ => unreachable!(),
+    }
+}
+
+#[test]
+fn decode_results() {
+    use abi::encode_tokens;
+    let success = encode_tokens(&[true.into()]);
+    let failure = encode_tokens(&[false.into()]);
+    for &mode in &[ReturnMode::Strict, ReturnMode::Lenient] {
+        assert!(decode_bool_result(&success, mode).unwrap());
+        assert!(!decode_bool_result(&failure, mode).unwrap());
+        assert!(decode_bool_result(&[0x01], mode).is_err());
+        assert!(decode_bool_result(&encode_tokens(&[2u8.into()]), mode).is_err());
+        let mut trailing = success.clone();
+        trailing.extend_from_slice(&[0u8; 32]);
+        assert!(decode_bool_result(&trailing, mode).is_err());
+    }
+    assert!(decode_bool_result(&[], ReturnMode::Strict).is_err());
+    assert!(decode_bool_result(&[], ReturnMode::Lenient).unwrap());
+}
+
+/// Metadata of a token, as read by a
+/// [TokenMetadataBatch](struct.TokenMetadataBatch.html).
+///
+/// A field is `None` when the token does not implement the function, or
+/// returns something that can not be interpreted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub address: Address,
+    pub name: Option<String>,
+    pub symbol: Option<String>,
+    pub decimals: Option<u8>,
+    pub total_supply: Option<Uint256>,
+}
+
+const METADATA_FUNCTIONS: [&str; 4] = ["name()", "symbol()", "decimals()", "totalSupply()"];
+
+/// Reads `name`, `symbol`, `decimals` and `totalSupply` of many tokens with
+/// a single Multicall3 `eth_call`.
+///
+/// Send [calldata](#method.calldata) to
+/// [MULTICALL3_ADDRESS](../multicall/struct.MULTICALL3_ADDRESS.html), and pass
+/// the result to [decode](#method.decode).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenMetadataBatch {
+    tokens: Vec<Address>,
+}
+
+/// Decodes a `string`, or a `bytes32` padded with zeros as returned by
+/// older tokens such as MKR.
+fn decode_string_or_bytes32(data: &[u8]) -> Option<String> {
+    if let Ok(mut tokens) = decode_tokens(&[ParamType::String], data) {
+        if let Some(Token::String(s)) = tokens.pop() {
+            return Some(s);
+        }
+    }
+    if data.len() != 32 {
+        return None;
+    }
+    let end = data.iter().position(|b| *b == 0).unwrap_or(32);
+    if data[end..].iter().any(|b| *b != 0) {
+        return None;
+    }
+    String::from_utf8(data[..end].to_vec()).ok()
+}
+
+fn decode_uint(data: &[u8]) -> Option<Uint256> {
+    if data.len() != 32 {
+        return None;
+    }
+    match decode_tokens(&[ParamType::Uint(256)], data).ok()?.pop() {
+        Some(Token::Uint(value)) => Some(value),
+        _ => None,
+    }
+}
+
+impl TokenMetadataBatch {
+    pub fn new(tokens: Vec<Address>) -> TokenMetadataBatch {
+        TokenMetadataBatch { tokens }
+    }
+
+    /// Calldata of the `aggregate3` call. Every call is allowed to fail, so
+    /// one broken token does not fail the whole batch.
+    pub fn calldata(&self) -> Vec<u8> {
+        let calls: Vec<Call3> = self
+            .tokens
+            .iter()
+            .flat_map(|token| {
+                METADATA_FUNCTIONS.iter().map(move |function| Call3 {
+                    target: *token,
+                    allow_failure: true,
+                    call_data: derive_method_id(function).to_vec(),
+                })
+            })
+            .collect();
+        aggregate3(&calls)
+    }
+
+    /// Decodes the result of the `aggregate3` call.
+    pub fn decode(&self, return_data: &[u8]) -> Result<Vec<TokenMetadata>, Error> {
+        let results = decode_aggregate3(return_data)?;
+        ensure!(
+            results.len() == self.tokens.len() * METADATA_FUNCTIONS.len(),
+            "Expected {} results but found {}",
+            self.tokens.len() * METADATA_FUNCTIONS.len(),
+            results.len()
+        );
+        Ok(self
+            .tokens
+            .iter()
+            .zip(results.chunks(METADATA_FUNCTIONS.len()))
+            .map(|(address, results)| {
+                let data = |i: usize| {
+                    if results[i].success {
+                        Some(&results[i].return_data[..])
+                    } else {
+                        None
+                    }
+                };
+                TokenMetadata {
+                    address: *address,
+                    name: data(0).and_then(decode_string_or_bytes32),
+                    symbol: data(1).and_then(decode_string_or_bytes32),
+                    decimals: data(2).and_then(decode_uint).and_then(|d| d.to_u8()),
+                    total_supply: data(3).and_then(decode_uint),
+                }
+            })
+            .collect())
+    }
+}
+
+#[test]
+fn token_metadata_batch() {
+    use abi::encode_tokens;
+    use multicall::{encode_aggregate3_result, AGGREGATE3};
+    let usdc = Address::from([0x11u8; 20]);
+    let mkr = Address::from([0x22u8; 20]);
+    let batch = TokenMetadataBatch::new(vec![usdc, mkr]);
+    let calldata = batch.calldata();
+    assert_eq!(calldata[..4], derive_method_id(AGGREGATE3));
+
+    let mut mkr_name = [0u8; 32];
+    mkr_name[..5].copy_from_slice(b"Maker");
+    let mut mkr_symbol = [0u8; 32];
+    mkr_symbol[..3].copy_from_slice(b"MKR");
+    let results = vec![
+        (true, encode_tokens(&["USD Coin".into()])),
+        (true, encode_tokens(&["USDC".into()])),
+        (true, encode_tokens(&[6u8.into()])),
+        (true, encode_tokens(&[1_000_000u32.into()])),
+        (true, mkr_name.to_vec()),
+        (true, mkr_symbol.to_vec()),
+        (true, encode_tokens(&[18u8.into()])),
+        // totalSupply reverted
+        (false, Vec::new()),
+    ];
+    let return_data = encode_aggregate3_result(&results);
+    assert_eq!(
+        batch.decode(&return_data).unwrap(),
+        vec![
+            TokenMetadata {
+                address: usdc,
+                name: Some("USD Coin".to_string()),
+                symbol: Some("USDC".to_string()),
+                decimals: Some(6),
+                total_supply: Some(1_000_000u32.into()),
+            },
+            TokenMetadata {
+                address: mkr,
+                name: Some("Maker".to_string()),
+                symbol: Some("MKR".to_string()),
+                decimals: Some(18),
+                total_supply: None,
+            },
+        ]
+    );
+    assert!(TokenMetadataBatch::new(vec![usdc])
+        .decode(&return_data)
+        .is_err());
+}
+
+#[test]
+fn decode_metadata_values() {
+    use abi::encode_tokens;
+    assert_eq!(decode_string_or_bytes32(&[0u8; 32]), Some(String::new()));
+    let mut dirty = [0u8; 32];
+    dirty[0] = b'A';
+    dirty[31] = b'B';
+    assert_eq!(decode_string_or_bytes32(&dirty), None);
+    assert_eq!(decode_string_or_bytes32(&[0xffu8; 32]), None);
+    assert_eq!(decode_string_or_bytes32(&[]), None);
+    assert_eq!(
+        decode_uint(&encode_tokens(&[300u32.into()])),
+        Some(300u32.into())
+    );
+    assert_eq!(decode_uint(&[0u8; 31]), None);
+}
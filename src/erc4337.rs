@@ -0,0 +1,691 @@
+//! ERC-4337 account abstraction.
+//!
+//! A [UserOperation](struct.UserOperation.html) is what a smart contract
+//! account signs instead of a transaction. Bundlers take them over their own
+//! JSON-RPC methods and submit them to the EntryPoint contract; the types
+//! here serialize to and from the JSON those methods use.
+//!
+//! The v0.7 EntryPoint takes a [PackedUserOperation](struct.PackedUserOperation.html)
+//! instead, and [VerifyingPaymaster](struct.VerifyingPaymaster.html) builds
+//! `paymasterAndData` for either version.
+//!
+//! Indexers can follow operations without a bundler through the events the
+//! EntryPoint logs, see [EntryPointEvent](enum.EntryPointEvent.html).
+use abi::{decode_tokens, derive_signature, encode_tokens, ParamType, Token};
+use address::Address;
+use erc1271::SignedPayload;
+use event::{topic_address, Log};
+use hash::keccak256;
+use num256::Uint256;
+use private_key::PrivateKey;
+use serde::{hex_bytes, hex_bytes32, quantity};
+
+/// Submits a user operation, returning its hash.
+pub const SEND_USER_OPERATION: &str = "eth_sendUserOperation";
+/// Estimates the gas limits of a user operation.
+pub const ESTIMATE_USER_OPERATION_GAS: &str = "eth_estimateUserOperationGas";
+/// Fetches the receipt of an included user operation by its hash.
+pub const GET_USER_OPERATION_RECEIPT: &str = "eth_getUserOperationReceipt";
+
+/// Logged by the EntryPoint for every operation it executes.
+pub const USER_OPERATION_EVENT: &str =
+    "UserOperationEvent(bytes32,address,address,uint256,bool,uint256,uint256)";
+/// Logged by the EntryPoint when an operation deploys its account.
+pub const ACCOUNT_DEPLOYED: &str = "AccountDeployed(bytes32,address,address,address)";
+/// Logged by the EntryPoint when the call of an account reverts.
+pub const USER_OPERATION_REVERT_REASON: &str =
+    "UserOperationRevertReason(bytes32,address,uint256,bytes)";
+
+lazy_static! {
+    /// Address of the v0.6 EntryPoint on all chains.
+    pub static ref ENTRY_POINT_V06: Address =
+        "0x5FF137D4b0FDCD49DcA30c7CF57E578a026d2789".parse().unwrap();
+    /// Address of the v0.7 EntryPoint on all chains.
+    pub static ref ENTRY_POINT_V07: Address =
+        "0x0000000071727De22E5E9d8BAf0edAc6f37da032".parse().unwrap();
+}
+
+/// Packs two 128 bit values into a word, the first one in the upper half,
+/// as `accountGasLimits` and `gasFees` are packed.
+pub fn pack_u128_pair(high: u128, low: u128) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[..16].copy_from_slice(&high.to_be_bytes());
+    word[16..].copy_from_slice(&low.to_be_bytes());
+    word
+}
+
+/// A user operation, as defined by the v0.6 EntryPoint.
+#[derive(Debug, Clone, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperation {
+    pub sender: Address,
+    #[serde(with = "quantity")]
+    pub nonce: Uint256,
+    /// Factory address and calldata if the account is not deployed yet
+    #[serde(with = "hex_bytes")]
+    pub init_code: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub call_data: Vec<u8>,
+    #[serde(with = "quantity")]
+    pub call_gas_limit: Uint256,
+    #[serde(with = "quantity")]
+    pub verification_gas_limit: Uint256,
+    #[serde(with = "quantity")]
+    pub pre_verification_gas: Uint256,
+    #[serde(with = "quantity")]
+    pub max_fee_per_gas: Uint256,
+    #[serde(with = "quantity")]
+    pub max_priority_fee_per_gas: Uint256,
+    /// Paymaster address and data, empty if the account pays for itself
+    #[serde(with = "hex_bytes")]
+    pub paymaster_and_data: Vec<u8>,
+    #[serde(with = "hex_bytes")]
+    pub signature: Vec<u8>,
+}
+
+impl UserOperation {
+    /// The hash of the fields covered by the signature, before the
+    /// EntryPoint and chain id are mixed in.
+    pub fn packed_hash(&self) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Address(self.sender),
+            Token::Uint(self.nonce.clone()),
+            Token::Bytes(keccak256(&self.init_code).to_vec()),
+            Token::Bytes(keccak256(&self.call_data).to_vec()),
+            Token::Uint(self.call_gas_limit.clone()),
+            Token::Uint(self.verification_gas_limit.clone()),
+            Token::Uint(self.pre_verification_gas.clone()),
+            Token::Uint(self.max_fee_per_gas.clone()),
+            Token::Uint(self.max_priority_fee_per_gas.clone()),
+            Token::Bytes(keccak256(&self.paymaster_and_data).to_vec()),
+        ]))
+    }
+
+    /// The user operation hash, `userOpHash`, which identifies the operation
+    /// and which the account signs.
+    pub fn hash(&self, entry_point: &Address, chain_id: u64) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Bytes(self.packed_hash().to_vec()),
+            Token::Address(*entry_point),
+            chain_id.into(),
+        ]))
+    }
+}
+
+/// A user operation, as defined by the v0.7 EntryPoint.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PackedUserOperation {
+    pub sender: Address,
+    pub nonce: Uint256,
+    pub init_code: Vec<u8>,
+    pub call_data: Vec<u8>,
+    /// `verificationGasLimit` and `callGasLimit`, see
+    /// [pack_u128_pair](fn.pack_u128_pair.html)
+    pub account_gas_limits: [u8; 32],
+    pub pre_verification_gas: Uint256,
+    /// `maxPriorityFeePerGas` and `maxFeePerGas`, see
+    /// [pack_u128_pair](fn.pack_u128_pair.html)
+    pub gas_fees: [u8; 32],
+    pub paymaster_and_data: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl PackedUserOperation {
+    /// The hash of the fields covered by the signature, before the
+    /// EntryPoint and chain id are mixed in.
+    pub fn packed_hash(&self) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Address(self.sender),
+            Token::Uint(self.nonce.clone()),
+            Token::Bytes(keccak256(&self.init_code).to_vec()),
+            Token::Bytes(keccak256(&self.call_data).to_vec()),
+            Token::Bytes(self.account_gas_limits.to_vec()),
+            Token::Uint(self.pre_verification_gas.clone()),
+            Token::Bytes(self.gas_fees.to_vec()),
+            Token::Bytes(keccak256(&self.paymaster_and_data).to_vec()),
+        ]))
+    }
+
+    /// The user operation hash, `userOpHash`.
+    pub fn hash(&self, entry_point: &Address, chain_id: u64) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Bytes(self.packed_hash().to_vec()),
+            Token::Address(*entry_point),
+            chain_id.into(),
+        ]))
+    }
+}
+
+/// A paymaster that sponsors operations signed off-chain by its signer, like
+/// the `VerifyingPaymaster` of the reference implementation.
+///
+/// The signer signs the paymaster hash of an operation with `personal_sign`,
+/// and the signature goes into `paymasterAndData` along with the validity
+/// window. The operation has to be final apart from its own signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyingPaymaster {
+    pub address: Address,
+    /// Timestamp after which the sponsorship expires, 0 for never. At most
+    /// 48 bits.
+    pub valid_until: u64,
+    /// Timestamp before which the sponsorship is not valid yet. At most 48
+    /// bits.
+    pub valid_after: u64,
+    /// Gas limit of the paymaster validation, only part of v0.7 operations
+    pub verification_gas_limit: u128,
+    /// Gas limit of the `postOp` call, only part of v0.7 operations
+    pub post_op_gas_limit: u128,
+}
+
+impl VerifyingPaymaster {
+    fn validity(&self) -> Vec<u8> {
+        debug_assert!(self.valid_until < 1 << 48 && self.valid_after < 1 << 48);
+        encode_tokens(&[self.valid_until.into(), self.valid_after.into()])
+    }
+
+    fn paymaster_and_data(&self, prefix: &[u8], hash: &[u8; 32], key: &PrivateKey) -> Vec<u8> {
+        let digest = SignedPayload::PersonalMessage(hash).digest();
+        let mut data = self.address.as_bytes().to_vec();
+        data.extend_from_slice(prefix);
+        data.extend(self.validity());
+        data.extend_from_slice(&key.sign_hash(&digest).to_bytes());
+        data
+    }
+
+    /// The hash the signer signs for a v0.6 operation.
+    pub fn hash_v06(&self, op: &UserOperation, chain_id: u64) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Address(op.sender),
+            Token::Uint(op.nonce.clone()),
+            Token::Bytes(keccak256(&op.init_code).to_vec()),
+            Token::Bytes(keccak256(&op.call_data).to_vec()),
+            Token::Uint(op.call_gas_limit.clone()),
+            Token::Uint(op.verification_gas_limit.clone()),
+            Token::Uint(op.pre_verification_gas.clone()),
+            Token::Uint(op.max_fee_per_gas.clone()),
+            Token::Uint(op.max_priority_fee_per_gas.clone()),
+            chain_id.into(),
+            Token::Address(self.address),
+            self.valid_until.into(),
+            self.valid_after.into(),
+        ]))
+    }
+
+    /// `paymasterAndData` of a v0.6 operation: the paymaster, the validity
+    /// window and the signature.
+    pub fn paymaster_and_data_v06(
+        &self,
+        op: &UserOperation,
+        chain_id: u64,
+        key: &PrivateKey,
+    ) -> Vec<u8> {
+        self.paymaster_and_data(&[], &self.hash_v06(op, chain_id), key)
+    }
+
+    /// The hash the signer signs for a v0.7 operation.
+    pub fn hash_v07(&self, op: &PackedUserOperation, chain_id: u64) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Address(op.sender),
+            Token::Uint(op.nonce.clone()),
+            Token::Bytes(keccak256(&op.init_code).to_vec()),
+            Token::Bytes(keccak256(&op.call_data).to_vec()),
+            Token::Bytes(op.account_gas_limits.to_vec()),
+            Token::Bytes(self.gas_limits().to_vec()),
+            Token::Uint(op.pre_verification_gas.clone()),
+            Token::Bytes(op.gas_fees.to_vec()),
+            chain_id.into(),
+            Token::Address(self.address),
+            self.valid_until.into(),
+            self.valid_after.into(),
+        ]))
+    }
+
+    fn gas_limits(&self) -> [u8; 32] {
+        pack_u128_pair(self.verification_gas_limit, self.post_op_gas_limit)
+    }
+
+    /// `paymasterAndData` of a v0.7 operation: the paymaster, its gas
+    /// limits, the validity window and the signature.
+    pub fn paymaster_and_data_v07(
+        &self,
+        op: &PackedUserOperation,
+        chain_id: u64,
+        key: &PrivateKey,
+    ) -> Vec<u8> {
+        self.paymaster_and_data(&self.gas_limits(), &self.hash_v07(op, chain_id), key)
+    }
+}
+
+/// Result of `eth_estimateUserOperationGas`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationGasEstimate {
+    #[serde(with = "quantity")]
+    pub pre_verification_gas: Uint256,
+    #[serde(with = "quantity")]
+    pub verification_gas_limit: Uint256,
+    #[serde(with = "quantity")]
+    pub call_gas_limit: Uint256,
+}
+
+impl UserOperationGasEstimate {
+    /// Copies the estimated limits into a user operation.
+    pub fn apply(&self, op: &mut UserOperation) {
+        op.pre_verification_gas = self.pre_verification_gas.clone();
+        op.verification_gas_limit = self.verification_gas_limit.clone();
+        op.call_gas_limit = self.call_gas_limit.clone();
+    }
+}
+
+/// The transaction a user operation was included in.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationTransaction {
+    #[serde(with = "hex_bytes32")]
+    pub transaction_hash: [u8; 32],
+    #[serde(with = "hex_bytes32")]
+    pub block_hash: [u8; 32],
+    #[serde(with = "quantity")]
+    pub block_number: Uint256,
+}
+
+/// Result of `eth_getUserOperationReceipt`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UserOperationReceipt {
+    #[serde(with = "hex_bytes32")]
+    pub user_op_hash: [u8; 32],
+    pub entry_point: Address,
+    pub sender: Address,
+    #[serde(with = "quantity")]
+    pub nonce: Uint256,
+    /// Paymaster that paid for the operation, the zero address if none
+    #[serde(default)]
+    pub paymaster: Address,
+    #[serde(with = "quantity")]
+    pub actual_gas_cost: Uint256,
+    #[serde(with = "quantity")]
+    pub actual_gas_used: Uint256,
+    /// Whether the call of the account succeeded
+    pub success: bool,
+    /// Revert reason of the call, if it failed
+    #[serde(default)]
+    pub reason: Option<String>,
+    pub receipt: UserOperationTransaction,
+}
+
+/// `UserOperationEvent`, which has what the operation cost.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserOperationEvent {
+    pub user_op_hash: [u8; 32],
+    pub sender: Address,
+    /// `None` if the account paid for itself
+    pub paymaster: Option<Address>,
+    pub nonce: Uint256,
+    /// Whether the call of the account succeeded
+    pub success: bool,
+    /// What the account or paymaster was charged, in wei
+    pub actual_gas_cost: Uint256,
+    pub actual_gas_used: Uint256,
+}
+
+/// `AccountDeployed`, logged before the `UserOperationEvent` of the
+/// operation that deployed the account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AccountDeployed {
+    pub user_op_hash: [u8; 32],
+    pub sender: Address,
+    pub factory: Address,
+    pub paymaster: Option<Address>,
+}
+
+/// `UserOperationRevertReason`, with the revert data of the account call.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserOperationRevertReason {
+    pub user_op_hash: [u8; 32],
+    pub sender: Address,
+    pub nonce: Uint256,
+    /// Revert data, see [decode_revert](../revert/fn.decode_revert.html)
+    pub revert_reason: Vec<u8>,
+}
+
+/// An event of the EntryPoint. Both v0.6 and v0.7 log the same events.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryPointEvent {
+    UserOperation(UserOperationEvent),
+    AccountDeployed(AccountDeployed),
+    RevertReason(UserOperationRevertReason),
+}
+
+fn paymaster(address: Address) -> Option<Address> {
+    if address == Address::default() {
+        None
+    } else {
+        Some(address)
+    }
+}
+
+impl EntryPointEvent {
+    /// Decodes a log of the EntryPoint, `None` if it is not one of these
+    /// events. The emitter is not checked, filter the logs by the address of
+    /// the EntryPoint first.
+    pub fn decode(log: &Log) -> Option<EntryPointEvent> {
+        let (&signature, topics) = log.topics.split_first()?;
+        if signature == derive_signature(USER_OPERATION_EVENT) {
+            let (user_op_hash, sender, paymaster_topic) = match *topics {
+                [hash, sender, paymaster] => (hash, sender, paymaster),
+                _ => return None,
+            };
+            let types = [
+                ParamType::Uint(256),
+                ParamType::Bool,
+                ParamType::Uint(256),
+                ParamType::Uint(256),
+            ];
+            match decode_tokens(&types, &log.data).ok()?[..] {
+                [Token::Uint(ref nonce), Token::Bool(success), Token::Uint(ref cost), Token::Uint(ref used)] => {
+                    Some(EntryPointEvent::UserOperation(UserOperationEvent {
+                        user_op_hash,
+                        sender: topic_address(&sender)?,
+                        paymaster: paymaster(topic_address(&paymaster_topic)?),
+                        nonce: nonce.clone(),
+                        success,
+                        actual_gas_cost: cost.clone(),
+                        actual_gas_used: used.clone(),
+                    }))
+                }
+                _ => None,
+            }
+        } else if signature == derive_signature(ACCOUNT_DEPLOYED) {
+            let (user_op_hash, sender) = match *topics {
+                [hash, sender] => (hash, sender),
+                _ => return None,
+            };
+            let types = [ParamType::Address, ParamType::Address];
+            match decode_tokens(&types, &log.data).ok()?[..] {
+                [Token::Address(factory), Token::Address(address)] => {
+                    Some(EntryPointEvent::AccountDeployed(AccountDeployed {
+                        user_op_hash,
+                        sender: topic_address(&sender)?,
+                        factory,
+                        paymaster: paymaster(address),
+                    }))
+                }
+                _ => None,
+            }
+        } else if signature == derive_signature(USER_OPERATION_REVERT_REASON) {
+            let (user_op_hash, sender) = match *topics {
+                [hash, sender] => (hash, sender),
+                _ => return None,
+            };
+            let types = [ParamType::Uint(256), ParamType::Bytes];
+            match decode_tokens(&types, &log.data).ok()?[..] {
+                [Token::Uint(ref nonce), Token::UnboundedBytes(ref reason)] => {
+                    Some(EntryPointEvent::RevertReason(UserOperationRevertReason {
+                        user_op_hash,
+                        sender: topic_address(&sender)?,
+                        nonce: nonce.clone(),
+                        revert_reason: reason.clone(),
+                    }))
+                }
+                _ => None,
+            }
+        } else {
+            None
+        }
+    }
+
+    /// Hash of the operation the event is about.
+    pub fn user_op_hash(&self) -> &[u8; 32] {
+        match *self {
+            EntryPointEvent::UserOperation(ref event) => &event.user_op_hash,
+            EntryPointEvent::AccountDeployed(ref event) => &event.user_op_hash,
+            EntryPointEvent::RevertReason(ref event) => &event.user_op_hash,
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_user_operation() -> UserOperation {
+    UserOperation {
+        sender: "0x1111111111111111111111111111111111111111"
+            .parse()
+            .unwrap(),
+        nonce: 1u8.into(),
+        init_code: Vec::new(),
+        call_data: vec![0xb6, 0x1d, 0x27, 0xf6],
+        call_gas_limit: 100_000u32.into(),
+        verification_gas_limit: 150_000u32.into(),
+        pre_verification_gas: 50_000u32.into(),
+        max_fee_per_gas: 1_000_000_000u32.into(),
+        max_priority_fee_per_gas: 100_000_000u32.into(),
+        paymaster_and_data: Vec::new(),
+        signature: Vec::new(),
+    }
+}
+
+#[test]
+fn user_operation_json() {
+    let op = test_user_operation();
+    let json = serde_json::to_string(&op).unwrap();
+    assert_eq!(
+        json,
+        concat!(
+            r#"{"sender":"0x1111111111111111111111111111111111111111","nonce":"0x1","#,
+            r#""initCode":"0x","callData":"0xb61d27f6","callGasLimit":"0x186a0","#,
+            r#""verificationGasLimit":"0x249f0","preVerificationGas":"0xc350","#,
+            r#""maxFeePerGas":"0x3b9aca00","maxPriorityFeePerGas":"0x5f5e100","#,
+            r#""paymasterAndData":"0x","signature":"0x"}"#
+        )
+    );
+    assert_eq!(serde_json::from_str::<UserOperation>(&json).unwrap(), op);
+}
+
+#[test]
+fn user_operation_hash() {
+    let op = test_user_operation();
+    let hash = op.hash(&ENTRY_POINT_V06, 1);
+    // The signature is not covered
+    let mut signed = op.clone();
+    signed.signature = vec![0x01; 65];
+    assert_eq!(signed.hash(&ENTRY_POINT_V06, 1), hash);
+    // Everything else is, along with the EntryPoint and chain
+    assert_ne!(op.hash(&ENTRY_POINT_V06, 5), hash);
+    assert_ne!(op.hash(&Address::default(), 1), hash);
+    let mut changed = op.clone();
+    changed.paymaster_and_data = vec![0x22; 20];
+    assert_ne!(changed.hash(&ENTRY_POINT_V06, 1), hash);
+}
+
+#[test]
+fn bundler_results() {
+    let estimate: UserOperationGasEstimate = serde_json::from_str(
+        r#"{"preVerificationGas":"0xb5d8","verificationGasLimit":"0x16e360","callGasLimit":"0x2c2a"}"#,
+    )
+    .unwrap();
+    let mut op = test_user_operation();
+    estimate.apply(&mut op);
+    assert_eq!(op.verification_gas_limit, 1_500_000u32.into());
+
+    let receipt: UserOperationReceipt = serde_json::from_str(
+        r#"{
+            "userOpHash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+            "entryPoint": "0x5ff137d4b0fdcd49dca30c7cf57e578a026d2789",
+            "sender": "0x1111111111111111111111111111111111111111",
+            "nonce": "0x1",
+            "actualGasCost": "0x2c2a0",
+            "actualGasUsed": "0x2c2a",
+            "success": false,
+            "reason": "0x",
+            "logs": [],
+            "receipt": {
+                "transactionHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+                "blockHash": "0x4444444444444444444444444444444444444444444444444444444444444444",
+                "blockNumber": "0x10",
+                "logs": []
+            }
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(receipt.entry_point, *ENTRY_POINT_V06);
+    assert_eq!(receipt.paymaster, Address::default());
+    assert_eq!(receipt.receipt.block_number, 16u8.into());
+    assert!(!receipt.success);
+}
+
+#[cfg(test)]
+fn test_paymaster() -> VerifyingPaymaster {
+    VerifyingPaymaster {
+        address: "0x2222222222222222222222222222222222222222"
+            .parse()
+            .unwrap(),
+        valid_until: 1_700_000_000,
+        valid_after: 0,
+        verification_gas_limit: 100_000,
+        post_op_gas_limit: 50_000,
+    }
+}
+
+#[test]
+fn paymaster_and_data_layouts() {
+    use signature::Signature;
+    use utils::bytes_to_hex_str;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let paymaster = test_paymaster();
+
+    let op = test_user_operation();
+    let data = paymaster.paymaster_and_data_v06(&op, 1, &key);
+    assert_eq!(data.len(), 20 + 64 + 65);
+    assert_eq!(&data[..20], paymaster.address.as_bytes());
+    assert_eq!(
+        bytes_to_hex_str(&data[20..84]),
+        concat!(
+            "000000000000000000000000000000000000000000000000000000006553f100",
+            "0000000000000000000000000000000000000000000000000000000000000000"
+        )
+    );
+    let signature = Signature::from_bytes(&data[84..]).unwrap();
+    let digest = SignedPayload::PersonalMessage(&paymaster.hash_v06(&op, 1)).digest();
+    assert_eq!(
+        signature.recover(&digest).unwrap(),
+        key.to_public_key().unwrap()
+    );
+
+    let packed = PackedUserOperation {
+        sender: op.sender,
+        nonce: op.nonce.clone(),
+        account_gas_limits: pack_u128_pair(150_000, 100_000),
+        pre_verification_gas: op.pre_verification_gas.clone(),
+        gas_fees: pack_u128_pair(100_000_000, 1_000_000_000),
+        call_data: op.call_data.clone(),
+        ..Default::default()
+    };
+    let data = paymaster.paymaster_and_data_v07(&packed, 1, &key);
+    assert_eq!(data.len(), 20 + 32 + 64 + 65);
+    assert_eq!(
+        bytes_to_hex_str(&data[20..52]),
+        "000000000000000000000000000186a00000000000000000000000000000c350"
+    );
+    let signature = Signature::from_bytes(&data[116..]).unwrap();
+    let digest = SignedPayload::PersonalMessage(&paymaster.hash_v07(&packed, 1)).digest();
+    assert_eq!(
+        signature.recover(&digest).unwrap(),
+        key.to_public_key().unwrap()
+    );
+    // The hash depends on the paymaster gas limits
+    let mut other = paymaster;
+    other.post_op_gas_limit += 1;
+    assert_ne!(other.hash_v07(&packed, 1), paymaster.hash_v07(&packed, 1));
+}
+
+#[test]
+fn packed_user_operation_hash() {
+    let op = PackedUserOperation {
+        sender: Address::from([0x11u8; 20]),
+        ..Default::default()
+    };
+    let hash = op.hash(&ENTRY_POINT_V07, 1);
+    let mut signed = op.clone();
+    signed.signature = vec![0x01; 65];
+    assert_eq!(signed.hash(&ENTRY_POINT_V07, 1), hash);
+    assert_ne!(op.hash(&ENTRY_POINT_V06, 1), hash);
+}
+
+#[test]
+fn decode_entry_point_events() {
+    use event::topic;
+    let hash = [0x33u8; 32];
+    let sender = Address::from([0x11u8; 20]);
+    let paymaster = Address::from([0x22u8; 20]);
+    let log = |signature: &str, indexed: &[Token], data: &[Token]| Log {
+        address: *ENTRY_POINT_V07,
+        topics: std::iter::once(derive_signature(signature))
+            .chain(indexed.iter().map(topic))
+            .collect(),
+        data: encode_tokens(data),
+    };
+
+    let executed = log(
+        USER_OPERATION_EVENT,
+        &[
+            Token::Bytes(hash.to_vec()),
+            Token::Address(sender),
+            Token::Address(paymaster),
+        ],
+        &[
+            1u8.into(),
+            Token::Bool(true),
+            210_000_000_000_000u64.into(),
+            70_000u32.into(),
+        ],
+    );
+    assert_eq!(
+        EntryPointEvent::decode(&executed),
+        Some(EntryPointEvent::UserOperation(UserOperationEvent {
+            user_op_hash: hash,
+            sender,
+            paymaster: Some(paymaster),
+            nonce: 1u8.into(),
+            success: true,
+            actual_gas_cost: 210_000_000_000_000u64.into(),
+            actual_gas_used: 70_000u32.into(),
+        }))
+    );
+
+    let deployed = log(
+        ACCOUNT_DEPLOYED,
+        &[Token::Bytes(hash.to_vec()), Token::Address(sender)],
+        &[
+            Token::Address(Address::from([0x44u8; 20])),
+            Token::Address(Address::default()),
+        ],
+    );
+    match EntryPointEvent::decode(&deployed) {
+        Some(EntryPointEvent::AccountDeployed(event)) => {
+            assert_eq!(event.factory, Address::from([0x44u8; 20]));
+            assert_eq!(event.paymaster, None);
+        }
+        other => panic!("unexpected {:?}", other),
+    }
+
+    let reverted = log(
+        USER_OPERATION_REVERT_REASON,
+        &[Token::Bytes(hash.to_vec()), Token::Address(sender)],
+        &[1u8.into(), Token::UnboundedBytes(vec![0xde, 0xad])],
+    );
+    let event = EntryPointEvent::decode(&reverted).unwrap();
+    assert_eq!(event.user_op_hash(), &hash);
+
+    // Other events and malformed logs
+    let mut transfer = executed.clone();
+    transfer.topics[0] = derive_signature("Transfer(address,address,uint256)");
+    assert_eq!(EntryPointEvent::decode(&transfer), None);
+    let mut truncated = executed.clone();
+    truncated.data.truncate(64);
+    assert_eq!(EntryPointEvent::decode(&truncated), None);
+    let mut missing_topic = executed;
+    missing_topic.topics.pop();
+    assert_eq!(EntryPointEvent::decode(&missing_topic), None);
+}
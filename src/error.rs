@@ -16,4 +16,10 @@ pub enum ClarityError {
     ZeroPrivKey,
     #[fail(display = "Invalid private key")]
     InvalidPrivKey,
+    #[fail(display = "Replay protected signature before EIP-155")]
+    ReplayProtectionNotActive,
+    #[fail(display = "Signature without a network id is not accepted")]
+    UnprotectedSignature,
+    #[fail(display = "Unknown transaction type {}", _0)]
+    UnknownTransactionType(u8),
 }
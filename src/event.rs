@@ -0,0 +1,272 @@
+//! Logs emitted by contracts.
+//!
+//! Events are logged with the hash of their signature as the first topic,
+//! followed by one topic per indexed parameter, and the other parameters
+//! ABI encoded in the data.
+use abi::{decode_tokens, encode_tokens, AbiDecodeError, Event, ParamType, Token};
+use address::Address;
+#[cfg(feature = "derive")]
+pub use clarity_derive::EthEvent;
+use failure::Error;
+use hash::keccak256;
+use std::slice;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum LogDecodeError {
+    #[fail(display = "Log is not of the event {}", _0)]
+    EventMismatch(String),
+    #[fail(display = "Expected {} topics but the log has {}", expected, found)]
+    TopicCount { expected: usize, found: usize },
+    #[fail(display = "{}", _0)]
+    Abi(AbiDecodeError),
+}
+
+impl From<AbiDecodeError> for LogDecodeError {
+    fn from(e: AbiDecodeError) -> LogDecodeError {
+        LogDecodeError::Abi(e)
+    }
+}
+
+/// A log emitted by a transaction, as found in its receipt.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<[u8; 32]>,
+    pub data: Vec<u8>,
+}
+
+/// Topic an indexed parameter with the given value is logged with.
+pub fn topic(token: &Token) -> [u8; 32] {
+    match *token {
+        // Dynamic values are logged by their hash
+        Token::String(ref s) => keccak256(s.as_bytes()),
+        Token::UnboundedBytes(ref bytes) => keccak256(bytes),
+        // And structs and arrays by the hash of their items, which for
+        // static items is their ABI encoding
+        Token::Tuple(ref tokens) | Token::FixedArray(ref tokens) | Token::Dynamic(ref tokens) => {
+            keccak256(&encode_tokens(tokens))
+        }
+        _ => {
+            let mut topic = [0u8; 32];
+            topic.copy_from_slice(&encode_tokens(std::slice::from_ref(token))[..32]);
+            topic
+        }
+    }
+}
+
+/// The value of a parameter of a decoded log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LogValue {
+    Value(Token),
+    /// An indexed string, `bytes`, array or struct, which is logged as the
+    /// hash of its value. It can be compared with [topic](fn.topic.html) of
+    /// an expected value, but the value itself cannot be recovered.
+    Hashed([u8; 32]),
+}
+
+/// A parameter of a decoded log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogParam {
+    pub name: String,
+    pub indexed: bool,
+    pub value: LogValue,
+}
+
+/// Whether an indexed parameter of `kind` is logged as a hash rather than
+/// as its value.
+fn is_hashed(kind: &ParamType) -> bool {
+    matches!(
+        *kind,
+        ParamType::String
+            | ParamType::Bytes
+            | ParamType::Array(_)
+            | ParamType::FixedArray(..)
+            | ParamType::Tuple(_)
+    )
+}
+
+impl Log {
+    /// Decodes the parameters of `event` out of the log, in the order they
+    /// are declared: the indexed ones from the topics, the others from the
+    /// data.
+    pub fn decode(&self, event: &Event) -> Result<Vec<LogParam>, Error> {
+        decode_log(event, &self.topics, &self.data).map_err(|e| match e {
+            LogDecodeError::Abi(e) => e.into(),
+            e => e.into(),
+        })
+    }
+}
+
+/// Decodes the parameters of `event` out of the topics and data of a log,
+/// as [Log::decode](struct.Log.html#method.decode) does.
+pub fn decode_log(
+    event: &Event,
+    topics: &[[u8; 32]],
+    data: &[u8],
+) -> Result<Vec<LogParam>, LogDecodeError> {
+    let mut remaining = topics.iter();
+    if !event.anonymous && remaining.next() != Some(&event.topic()) {
+        return Err(LogDecodeError::EventMismatch(event.signature()));
+    }
+    let indexed = event.inputs.iter().filter(|param| param.indexed).count();
+    let expected = indexed + if event.anonymous { 0 } else { 1 };
+    if topics.len() != expected {
+        return Err(LogDecodeError::TopicCount {
+            expected,
+            found: topics.len(),
+        });
+    }
+
+    let kinds = event
+        .inputs
+        .iter()
+        .map(|param| param.param_type())
+        .collect::<Result<Vec<ParamType>, _>>()?;
+    let data_types: Vec<ParamType> = event
+        .inputs
+        .iter()
+        .zip(&kinds)
+        .filter(|(param, _)| !param.indexed)
+        .map(|(_, kind)| kind.clone())
+        .collect();
+    let mut data = decode_tokens(&data_types, data)?.into_iter();
+    let mut params = Vec::with_capacity(event.inputs.len());
+    for (param, kind) in event.inputs.iter().zip(&kinds) {
+        let value = if !param.indexed {
+            // As many tokens as types were decoded
+            LogValue::Value(data.next().unwrap())
+        } else {
+            let topic = remaining.next().unwrap();
+            if is_hashed(kind) {
+                LogValue::Hashed(*topic)
+            } else {
+                LogValue::Value(decode_tokens(slice::from_ref(kind), topic)?.remove(0))
+            }
+        };
+        params.push(LogParam {
+            name: param.name.clone(),
+            indexed: param.indexed,
+            value,
+        });
+    }
+    Ok(params)
+}
+
+/// The address an indexed `address` parameter was logged as, `None` if the
+/// topic is not a valid address.
+pub fn topic_address(topic: &[u8; 32]) -> Option<Address> {
+    if topic[..12].iter().any(|&b| b != 0) {
+        return None;
+    }
+    Address::from_slice(&topic[12..]).ok()
+}
+
+#[test]
+fn address_topics() {
+    let address = Address::from([0x11u8; 20]);
+    let logged = topic(&Token::Address(address));
+    assert_eq!(&logged[12..], address.as_bytes());
+    assert_eq!(topic_address(&logged), Some(address));
+    assert_eq!(topic_address(&[0xffu8; 32]), None);
+}
+
+#[test]
+fn decode_logs() {
+    let event: Event =
+        "event Registered(address indexed owner, string indexed name, uint256[] ids, string note)"
+            .parse()
+            .unwrap();
+    let owner = Address::from([0x11u8; 20]);
+    let log = Log {
+        address: Address::default(),
+        topics: vec![event.topic(), topic(&owner.into()), topic(&"alice".into())],
+        data: encode_tokens(&[vec![1u32, 2].into(), "hello".into()]),
+    };
+    let params = log.decode(&event).unwrap();
+    let values: Vec<&LogValue> = params.iter().map(|param| &param.value).collect();
+    assert_eq!(
+        values,
+        vec![
+            &LogValue::Value(owner.into()),
+            &LogValue::Hashed(keccak256(b"alice")),
+            &LogValue::Value(vec![1u32, 2].into()),
+            &LogValue::Value("hello".into()),
+        ]
+    );
+    assert_eq!(params[1].name, "name");
+    assert!(params[1].indexed);
+
+    let mut other = log.clone();
+    other.topics[0] = [0u8; 32];
+    assert!(other.decode(&event).is_err());
+    let mut short = log.clone();
+    short.topics.pop();
+    assert_eq!(
+        short
+            .decode(&event)
+            .unwrap_err()
+            .downcast::<LogDecodeError>()
+            .unwrap(),
+        LogDecodeError::TopicCount {
+            expected: 3,
+            found: 2
+        }
+    );
+
+    // Anonymous events have no signature topic
+    let anonymous = Event {
+        anonymous: true,
+        ..event
+    };
+    let mut log = log;
+    log.topics.remove(0);
+    assert_eq!(log.decode(&anonymous).unwrap(), params);
+}
+
+#[test]
+fn array_topics() {
+    let ids: Token = vec![1u32, 2].into();
+    assert_eq!(
+        topic(&ids),
+        keccak256(&encode_tokens(&[1u32.into(), 2u32.into()]))
+    );
+}
+
+#[test]
+fn decode_signed_fields() {
+    use num256::Int256;
+    // Uniswap V3 pools log swaps with signed amounts and ticks
+    let event: Event = "event Swap(address indexed sender, address indexed recipient, int256 amount0, int256 amount1, uint160 sqrtPriceX96, uint128 liquidity, int24 tick)"
+        .parse()
+        .unwrap();
+    let sender = Address::from([0x11u8; 20]);
+    let amount0 = Int256::from(-1_000_000i64);
+    let log = Log {
+        address: Address::default(),
+        topics: vec![event.topic(), topic(&sender.into()), topic(&sender.into())],
+        data: encode_tokens(&[
+            amount0.clone().into(),
+            500i32.into(),
+            Token::Uint(1u8.into()),
+            Token::Uint(2u8.into()),
+            (-887_272i32).into(),
+        ]),
+    };
+    let params = log.decode(&event).unwrap();
+    assert_eq!(params[2].value, LogValue::Value(amount0.into()));
+    assert_eq!(params[6].value, LogValue::Value((-887_272i32).into()));
+
+    // Indexed signed values are logged sign extended
+    let event: Event = "event Tick(int24 indexed tick, (int8,bool) indexed key)"
+        .parse()
+        .unwrap();
+    let key = Token::Tuple(vec![(-1i8).into(), true.into()]);
+    let log = Log {
+        address: Address::default(),
+        topics: vec![event.topic(), topic(&(-5i32).into()), topic(&key)],
+        data: Vec::new(),
+    };
+    let params = log.decode(&event).unwrap();
+    assert_eq!(params[0].value, LogValue::Value((-5i32).into()));
+    assert_eq!(params[1].value, LogValue::Hashed(topic(&key)));
+}
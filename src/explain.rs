@@ -0,0 +1,732 @@
+//! Explanations of transactions for approval screens.
+//!
+//! [explain](fn.explain.html) turns a raw transaction into an
+//! [ExplainedTx](struct.ExplainedTx.html): what it does, the amounts it
+//! moves with their decimals, the addresses it involves and what it can
+//! cost at most. The structure serializes for approval UIs, and its
+//! `Display` form is what the CLI prints:
+//!
+//! ```text
+//! type 2 transaction 0x1f…, chain 1, nonce 7
+//! from 0x3333333333333333333333333333333333333333 (Treasury)
+//! transfer 1.5 USDC to 0x1111111111111111111111111111111111111111 (Alice)
+//! fee up to 0.00042 ETH
+//! ```
+//!
+//! ERC-20 transfers and approvals are recognized on their own. Other calls
+//! are decoded with the functions and labels of a
+//! [TraceDecoder](../trace/struct.TraceDecoder.html), if one is given.
+use abi::{decode_tokens, derive_method_id, parse_param_types, Token};
+use address::Address;
+use erc20::{decode_approve, is_infinite_approval, TokenMetadata};
+use failure::Error;
+use num256::Uint256;
+use num_traits::{Bounded, CheckedAdd, CheckedMul, Zero};
+use serde::hex_bytes32;
+use std::fmt;
+use trace::{format_token, TraceDecoder};
+use transaction::TypedTransaction;
+use utils::bytes_to_hex_str;
+
+const TRANSFER: &str = "transfer(address,uint256)";
+const TRANSFER_FROM: &str = "transferFrom(address,address,uint256)";
+
+/// Formats `value` as a decimal number with `decimals` digits after the
+/// point, without trailing zeros, e.g. `1.5` for 1500000 with 6 decimals.
+pub fn format_units(value: &Uint256, decimals: u8) -> String {
+    let digits = value.to_string();
+    let decimals = decimals as usize;
+    let digits = format!("{:0>width$}", digits, width = decimals + 1);
+    let (whole, fraction) = digits.split_at(digits.len() - decimals);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        whole.to_owned()
+    } else {
+        format!("{}.{}", whole, fraction)
+    }
+}
+
+/// An amount of ether or of a token.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TokenAmount {
+    /// The amount in the smallest unit, in decimal
+    pub raw: Uint256,
+    /// `None` for tokens whose metadata is not known
+    pub decimals: Option<u8>,
+    pub symbol: Option<String>,
+    /// The amount with its decimals applied, if they are known
+    pub formatted: Option<String>,
+}
+
+impl TokenAmount {
+    /// An amount of wei, formatted in ether.
+    pub fn ether(raw: Uint256) -> TokenAmount {
+        TokenAmount::new(raw, Some(18), Some("ETH".to_owned()))
+    }
+
+    pub fn new(raw: Uint256, decimals: Option<u8>, symbol: Option<String>) -> TokenAmount {
+        TokenAmount {
+            formatted: decimals.map(|decimals| format_units(&raw, decimals)),
+            raw,
+            decimals,
+            symbol,
+        }
+    }
+}
+
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.formatted {
+            Some(ref formatted) => write!(f, "{}", formatted)?,
+            None => write!(f, "{} units", self.raw)?,
+        }
+        if let Some(ref symbol) = self.symbol {
+            write!(f, " {}", symbol)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a transaction does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+    /// Sends ether, without calldata
+    Transfer { to: Address, amount: TokenAmount },
+    /// An ERC-20 `transfer`, or a `transferFrom` with its `from`
+    TokenTransfer {
+        token: Address,
+        from: Option<Address>,
+        to: Address,
+        amount: TokenAmount,
+    },
+    /// An ERC-20 `approve`
+    TokenApproval {
+        token: Address,
+        spender: Address,
+        amount: TokenAmount,
+        unlimited: bool,
+    },
+    /// A call of a function of the registry, with its formatted arguments
+    Call {
+        contract: Address,
+        function: String,
+        args: Vec<String>,
+    },
+    /// A call that could not be decoded, with its selector if the calldata
+    /// has one
+    UnknownCall {
+        contract: Address,
+        selector: Option<String>,
+    },
+    /// Deploys a contract with init code of `code_size` bytes
+    Deployment { code_size: usize },
+}
+
+/// How an address is involved in a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    Sender,
+    /// Receives ether or tokens
+    Recipient,
+    /// The contract that is called, unless it is a token
+    Contract,
+    Token,
+    /// Gets an allowance
+    Spender,
+    /// Any other address among the arguments of a call
+    Argument,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct InvolvedAddress {
+    pub address: Address,
+    pub role: Role,
+    pub label: Option<String>,
+}
+
+/// The most a transaction can pay for gas.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FeeSummary {
+    pub gas_limit: Uint256,
+    /// Gas price of legacy and EIP-2930 transactions
+    pub gas_price: Option<Uint256>,
+    pub max_fee_per_gas: Option<Uint256>,
+    pub max_priority_fee_per_gas: Option<Uint256>,
+    /// Blob gas and its price of EIP-4844 transactions
+    pub blob_gas: Option<Uint256>,
+    pub max_fee_per_blob_gas: Option<Uint256>,
+    /// Gas limit times the (maximum) gas price, plus the blob fee
+    pub max_fee: TokenAmount,
+    /// The maximum fee plus the value sent
+    pub max_cost: TokenAmount,
+}
+
+/// A decoded transaction, for showing to whoever approves it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExplainedTx {
+    pub tx_type: u8,
+    #[serde(with = "hex_bytes32")]
+    pub hash: [u8; 32],
+    /// `None` for legacy transactions signed without replay protection, or
+    /// not signed yet
+    pub chain_id: Option<Uint256>,
+    pub nonce: Uint256,
+    /// `None` if the transaction is not signed
+    pub from: Option<Address>,
+    pub action: Action,
+    /// Ether sent along, which for plain transfers is also in the action
+    pub value: TokenAmount,
+    pub fee: FeeSummary,
+    pub addresses: Vec<InvolvedAddress>,
+}
+
+/// The fields of a transaction of any type that matter to an explanation.
+struct Fields<'a> {
+    chain_id: Option<Uint256>,
+    to: Option<Address>,
+    value: &'a Uint256,
+    data: &'a [u8],
+    gas_limit: &'a Uint256,
+    gas_price: Option<&'a Uint256>,
+    max_fee_per_gas: Option<&'a Uint256>,
+    max_priority_fee_per_gas: Option<&'a Uint256>,
+    blob_gas: Option<Uint256>,
+    max_fee_per_blob_gas: Option<&'a Uint256>,
+}
+
+/// Contract creations have no recipient, which is encoded as the zero
+/// address.
+fn recipient(to: &Address) -> Option<Address> {
+    if *to == Address::default() {
+        None
+    } else {
+        Some(*to)
+    }
+}
+
+fn fields(tx: &TypedTransaction) -> Fields<'_> {
+    match *tx {
+        TypedTransaction::Legacy(ref tx) => Fields {
+            // EIP-155 puts the chain id into v, as 35 + 2 * chain id + parity
+            chain_id: tx.signature.as_ref().and_then(|sig| {
+                if sig.v >= 35u8.into() {
+                    Some((sig.v.clone() - 35u8.into()) / 2u8.into())
+                } else {
+                    None
+                }
+            }),
+            to: recipient(&tx.to),
+            value: &tx.value,
+            data: &tx.data,
+            gas_limit: &tx.gas_limit,
+            gas_price: Some(&tx.gas_price),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            blob_gas: None,
+            max_fee_per_blob_gas: None,
+        },
+        TypedTransaction::Eip2930(ref tx) => Fields {
+            chain_id: Some(tx.chain_id.clone()),
+            to: recipient(&tx.to),
+            value: &tx.value,
+            data: &tx.data,
+            gas_limit: &tx.gas_limit,
+            gas_price: Some(&tx.gas_price),
+            max_fee_per_gas: None,
+            max_priority_fee_per_gas: None,
+            blob_gas: None,
+            max_fee_per_blob_gas: None,
+        },
+        TypedTransaction::Eip1559(ref tx) => Fields {
+            chain_id: Some(tx.chain_id.clone()),
+            to: recipient(&tx.to),
+            value: &tx.value,
+            data: &tx.data,
+            gas_limit: &tx.gas_limit,
+            gas_price: None,
+            max_fee_per_gas: Some(&tx.max_fee_per_gas),
+            max_priority_fee_per_gas: Some(&tx.max_priority_fee_per_gas),
+            blob_gas: None,
+            max_fee_per_blob_gas: None,
+        },
+        TypedTransaction::Eip4844(ref tx) => Fields {
+            chain_id: Some(tx.chain_id.clone()),
+            to: Some(tx.to),
+            value: &tx.value,
+            data: &tx.data,
+            gas_limit: &tx.gas_limit,
+            gas_price: None,
+            max_fee_per_gas: Some(&tx.max_fee_per_gas),
+            max_priority_fee_per_gas: Some(&tx.max_priority_fee_per_gas),
+            blob_gas: Some(tx.blob_gas()),
+            max_fee_per_blob_gas: Some(&tx.max_fee_per_blob_gas),
+        },
+        TypedTransaction::Eip7702(ref tx) => Fields {
+            chain_id: Some(tx.chain_id.clone()),
+            to: Some(tx.to),
+            value: &tx.value,
+            data: &tx.data,
+            gas_limit: &tx.gas_limit,
+            gas_price: None,
+            max_fee_per_gas: Some(&tx.max_fee_per_gas),
+            max_priority_fee_per_gas: Some(&tx.max_priority_fee_per_gas),
+            blob_gas: None,
+            max_fee_per_blob_gas: None,
+        },
+    }
+}
+
+/// Decodes the arguments of `data` if it is a call of `signature`.
+fn decode_call(data: &[u8], signature: &str) -> Option<Vec<Token>> {
+    if data.len() < 4 || data[..4] != derive_method_id(signature) {
+        return None;
+    }
+    let types = parse_param_types(signature).expect("Signatures are valid");
+    decode_tokens(&types, &data[4..]).ok()
+}
+
+/// The product of two amounts, which saturates rather than overflow 256 bits.
+fn saturating_mul(a: &Uint256, b: &Uint256) -> Uint256 {
+    a.checked_mul(b).unwrap_or_else(Uint256::max_value)
+}
+
+fn saturating_add(a: &Uint256, b: &Uint256) -> Uint256 {
+    a.checked_add(b).unwrap_or_else(Uint256::max_value)
+}
+
+/// Works out what the transaction encoded in `raw` does.
+///
+/// `registry` decodes calls of other functions than those of ERC-20, and
+/// labels addresses. Token amounts get the decimals and symbols of
+/// `tokens`, and are left in the smallest unit for other tokens.
+pub fn explain(
+    raw: &[u8],
+    registry: Option<&TraceDecoder>,
+    tokens: &[TokenMetadata],
+) -> Result<ExplainedTx, Error> {
+    let tx = TypedTransaction::from_bytes(raw)?;
+    let fields = fields(&tx);
+    let from = match tx.signature() {
+        Some(_) => Some(tx.sender()?),
+        None => None,
+    };
+    let token_amount = |token: &Address, raw: &Uint256| {
+        let metadata = tokens.iter().find(|metadata| metadata.address == *token);
+        TokenAmount::new(
+            raw.clone(),
+            metadata.and_then(|metadata| metadata.decimals),
+            metadata.and_then(|metadata| metadata.symbol.clone()),
+        )
+    };
+
+    let mut addresses = Vec::new();
+    if let Some(from) = from {
+        addresses.push((from, Role::Sender));
+    }
+    let action = match fields.to {
+        None => Action::Deployment {
+            code_size: fields.data.len(),
+        },
+        Some(to) if fields.data.is_empty() => {
+            addresses.push((to, Role::Recipient));
+            Action::Transfer {
+                to,
+                amount: TokenAmount::ether(fields.value.clone()),
+            }
+        }
+        Some(contract) => {
+            let transfer = decode_call(fields.data, TRANSFER)
+                .map(|args| (None, args[0].clone(), args[1].clone()))
+                .or_else(|| {
+                    decode_call(fields.data, TRANSFER_FROM)
+                        .map(|args| (Some(args[0].clone()), args[1].clone(), args[2].clone()))
+                });
+            if let Some((token_from, Token::Address(to), Token::Uint(amount))) = transfer {
+                let token_from = match token_from {
+                    Some(Token::Address(token_from)) => Some(token_from),
+                    _ => None,
+                };
+                addresses.push((contract, Role::Token));
+                if let Some(token_from) = token_from {
+                    addresses.push((token_from, Role::Argument));
+                }
+                addresses.push((to, Role::Recipient));
+                Action::TokenTransfer {
+                    token: contract,
+                    from: token_from,
+                    to,
+                    amount: token_amount(&contract, &amount),
+                }
+            } else if let Some((spender, amount)) = decode_approve(fields.data) {
+                addresses.push((contract, Role::Token));
+                addresses.push((spender, Role::Spender));
+                Action::TokenApproval {
+                    token: contract,
+                    spender,
+                    amount: token_amount(&contract, &amount),
+                    unlimited: is_infinite_approval(fields.data),
+                }
+            } else if let Some((function, args)) =
+                registry.and_then(|registry| registry.decode_call(fields.data))
+            {
+                addresses.push((contract, Role::Contract));
+                for arg in &args {
+                    if let Token::Address(address) = *arg {
+                        addresses.push((address, Role::Argument));
+                    }
+                }
+                Action::Call {
+                    contract,
+                    function: function.to_owned(),
+                    args: args.iter().map(format_token).collect(),
+                }
+            } else {
+                addresses.push((contract, Role::Contract));
+                Action::UnknownCall {
+                    contract,
+                    selector: if fields.data.len() >= 4 {
+                        Some(format!("0x{}", bytes_to_hex_str(&fields.data[..4])))
+                    } else {
+                        None
+                    },
+                }
+            }
+        }
+    };
+
+    let price = fields
+        .gas_price
+        .or(fields.max_fee_per_gas)
+        .cloned()
+        .unwrap_or_else(Uint256::zero);
+    let mut max_fee = saturating_mul(&price, fields.gas_limit);
+    if let (Some(blob_gas), Some(blob_price)) = (&fields.blob_gas, fields.max_fee_per_blob_gas) {
+        max_fee = saturating_add(&max_fee, &saturating_mul(blob_gas, blob_price));
+    }
+    let max_cost = saturating_add(&max_fee, fields.value);
+    let fee = FeeSummary {
+        gas_limit: fields.gas_limit.clone(),
+        gas_price: fields.gas_price.cloned(),
+        max_fee_per_gas: fields.max_fee_per_gas.cloned(),
+        max_priority_fee_per_gas: fields.max_priority_fee_per_gas.cloned(),
+        blob_gas: fields.blob_gas.clone(),
+        max_fee_per_blob_gas: fields.max_fee_per_blob_gas.cloned(),
+        max_fee: TokenAmount::ether(max_fee),
+        max_cost: TokenAmount::ether(max_cost),
+    };
+
+    let label = |address: &Address, role: Role| {
+        registry
+            .and_then(|registry| registry.label_of(address))
+            .map(str::to_owned)
+            .or_else(|| match role {
+                Role::Token => tokens
+                    .iter()
+                    .find(|metadata| metadata.address == *address)
+                    .and_then(|metadata| metadata.name.clone().or(metadata.symbol.clone())),
+                _ => None,
+            })
+    };
+    let mut involved: Vec<InvolvedAddress> = Vec::new();
+    for (address, role) in addresses {
+        if !involved.iter().any(|involved| involved.address == address) {
+            involved.push(InvolvedAddress {
+                address,
+                role,
+                label: label(&address, role),
+            });
+        }
+    }
+
+    Ok(ExplainedTx {
+        tx_type: tx.tx_type(),
+        hash: tx.hash()?,
+        chain_id: fields.chain_id,
+        nonce: tx.nonce().clone(),
+        from,
+        action,
+        value: TokenAmount::ether(fields.value.clone()),
+        fee,
+        addresses: involved,
+    })
+}
+
+impl ExplainedTx {
+    /// Writes an address with its label, if it has one.
+    fn fmt_address(&self, f: &mut fmt::Formatter, address: &Address) -> fmt::Result {
+        write!(f, "{}", address)?;
+        let label = self
+            .addresses
+            .iter()
+            .find(|involved| involved.address == *address)
+            .and_then(|involved| involved.label.as_ref());
+        match label {
+            Some(label) => write!(f, " ({})", label),
+            None => Ok(()),
+        }
+    }
+}
+
+impl fmt::Display for ExplainedTx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "type {} transaction 0x{}",
+            self.tx_type,
+            bytes_to_hex_str(&self.hash)
+        )?;
+        if let Some(ref chain_id) = self.chain_id {
+            write!(f, ", chain {}", chain_id)?;
+        }
+        writeln!(f, ", nonce {}", self.nonce)?;
+        match self.from {
+            Some(ref from) => {
+                write!(f, "from ")?;
+                self.fmt_address(f, from)?;
+                writeln!(f)?;
+            }
+            None => writeln!(f, "not signed")?,
+        }
+        match self.action {
+            Action::Transfer { ref to, ref amount } => {
+                write!(f, "send {} to ", amount)?;
+                self.fmt_address(f, to)?;
+            }
+            Action::TokenTransfer {
+                ref token,
+                ref from,
+                ref to,
+                ref amount,
+            } => {
+                write!(f, "transfer {}", amount)?;
+                if amount.symbol.is_none() {
+                    write!(f, " of token ")?;
+                    self.fmt_address(f, token)?;
+                }
+                if let Some(ref from) = *from {
+                    write!(f, " from ")?;
+                    self.fmt_address(f, from)?;
+                }
+                write!(f, " to ")?;
+                self.fmt_address(f, to)?;
+            }
+            Action::TokenApproval {
+                ref token,
+                ref spender,
+                ref amount,
+                unlimited,
+            } => {
+                write!(f, "allow ")?;
+                self.fmt_address(f, spender)?;
+                if unlimited {
+                    write!(f, " to spend an unlimited amount of token ")?;
+                } else {
+                    write!(f, " to spend {} of token ", amount)?;
+                }
+                self.fmt_address(f, token)?;
+            }
+            Action::Call {
+                ref contract,
+                ref function,
+                ref args,
+            } => {
+                let name = &function[..function.find('(').unwrap_or(function.len())];
+                write!(f, "call ")?;
+                self.fmt_address(f, contract)?;
+                write!(f, ".{}({})", name, args.join(", "))?;
+            }
+            Action::UnknownCall {
+                ref contract,
+                ref selector,
+            } => {
+                write!(f, "call ")?;
+                self.fmt_address(f, contract)?;
+                match *selector {
+                    Some(ref selector) => write!(f, " with unknown function {}", selector)?,
+                    None => write!(f, " with invalid calldata")?,
+                }
+            }
+            Action::Deployment { code_size } => {
+                write!(f, "deploy a contract with {} bytes of init code", code_size)?
+            }
+        }
+        let sends_value = !self.value.raw.is_zero();
+        match self.action {
+            Action::Transfer { .. } => {}
+            _ if sends_value => write!(f, ", sending {}", self.value)?,
+            _ => {}
+        }
+        write!(f, "\nfee up to {}", self.fee.max_fee)
+    }
+}
+
+#[cfg(test)]
+fn test_key() -> ::private_key::PrivateKey {
+    "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap()
+}
+
+#[cfg(test)]
+fn eip1559(to: Address, value: Uint256, data: Vec<u8>) -> Vec<u8> {
+    use transaction::Eip1559Transaction;
+    Eip1559Transaction {
+        chain_id: 1u8.into(),
+        nonce: 7u8.into(),
+        max_priority_fee_per_gas: 1_000_000_000u64.into(),
+        max_fee_per_gas: 20_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to,
+        value,
+        data,
+        access_list: Vec::new(),
+        signature: None,
+    }
+    .sign(&test_key())
+    .to_bytes()
+    .unwrap()
+}
+
+#[test]
+fn units() {
+    assert_eq!(format_units(&0u8.into(), 18), "0");
+    assert_eq!(format_units(&1_500_000u32.into(), 6), "1.5");
+    assert_eq!(format_units(&1u8.into(), 18), "0.000000000000000001");
+    assert_eq!(format_units(&42u8.into(), 0), "42");
+    assert_eq!(format_units(&1_000_000u32.into(), 6), "1");
+}
+
+#[test]
+fn explain_transfers() {
+    use abi::encode_call;
+    let alice = Address::from([0x11u8; 20]);
+    let usdc = Address::from([0x22u8; 20]);
+    let sender = test_key().to_public_key().unwrap();
+    let mut registry = TraceDecoder::new();
+    registry.label(alice, "Alice");
+
+    let raw = eip1559(alice, 1_500_000_000_000_000_000u64.into(), Vec::new());
+    let explained = explain(&raw, Some(&registry), &[]).unwrap();
+    assert_eq!(explained.from, Some(sender));
+    assert_eq!(explained.chain_id, Some(1u8.into()));
+    assert_eq!(
+        explained.action,
+        Action::Transfer {
+            to: alice,
+            amount: TokenAmount::ether(1_500_000_000_000_000_000u64.into()),
+        }
+    );
+    assert_eq!(explained.fee.max_fee.formatted.as_deref(), Some("0.00042"));
+    assert_eq!(explained.fee.max_cost.formatted.as_deref(), Some("1.50042"));
+    let text = explained.to_string();
+    assert!(text.contains(&format!("\nsend 1.5 ETH to {} (Alice)\n", alice)));
+    assert!(text.ends_with("\nfee up to 0.00042 ETH"));
+
+    let metadata = TokenMetadata {
+        address: usdc,
+        name: Some("USD Coin".to_owned()),
+        symbol: Some("USDC".to_owned()),
+        decimals: Some(6),
+        total_supply: None,
+    };
+    let data = encode_call(TRANSFER, &[Token::Address(alice), 1_500_000u32.into()]);
+    let raw = eip1559(usdc, 0u8.into(), data);
+    let explained = explain(&raw, Some(&registry), &[metadata]).unwrap();
+    assert_eq!(
+        explained.action,
+        Action::TokenTransfer {
+            token: usdc,
+            from: None,
+            to: alice,
+            amount: TokenAmount::new(1_500_000u32.into(), Some(6), Some("USDC".to_owned())),
+        }
+    );
+    assert_eq!(
+        explained.addresses,
+        vec![
+            InvolvedAddress {
+                address: sender,
+                role: Role::Sender,
+                label: None,
+            },
+            InvolvedAddress {
+                address: usdc,
+                role: Role::Token,
+                label: Some("USD Coin".to_owned()),
+            },
+            InvolvedAddress {
+                address: alice,
+                role: Role::Recipient,
+                label: Some("Alice".to_owned()),
+            },
+        ]
+    );
+    assert!(explained
+        .to_string()
+        .contains(&format!("\ntransfer 1.5 USDC to {} (Alice)\n", alice)));
+
+    // Without metadata the amount stays in the smallest unit
+    let explained = explain(&raw, None, &[]).unwrap();
+    assert!(explained
+        .to_string()
+        .contains("\ntransfer 1500000 units of token "));
+}
+
+#[test]
+fn explain_calls() {
+    use abi::encode_call;
+    use erc20::approve;
+    let router = Address::from([0x33u8; 20]);
+    let usdc = Address::from([0x22u8; 20]);
+    let data = approve(router, Uint256::max_value());
+    let explained = explain(&eip1559(usdc, 0u8.into(), data), None, &[]).unwrap();
+    match explained.action {
+        Action::TokenApproval {
+            spender, unlimited, ..
+        } => {
+            assert_eq!(spender, router);
+            assert!(unlimited);
+        }
+        ref action => panic!("Unexpected action {:?}", action),
+    }
+
+    let mut registry = TraceDecoder::new();
+    registry
+        .register_function("swap(address,uint256)", "uint256")
+        .unwrap();
+    registry.label(router, "Router");
+    let data = encode_call(
+        "swap(address,uint256)",
+        &[Token::Address(usdc), 100u8.into()],
+    );
+    let raw = eip1559(router, 0u8.into(), data.clone());
+    let explained = explain(&raw, Some(&registry), &[]).unwrap();
+    assert_eq!(
+        explained.action,
+        Action::Call {
+            contract: router,
+            function: "swap(address,uint256)".to_owned(),
+            args: vec![usdc.to_string(), "100".to_owned()],
+        }
+    );
+    assert!(explained
+        .to_string()
+        .contains(&format!("\ncall {} (Router).swap({}, 100)\n", router, usdc)));
+
+    let explained = explain(&raw, None, &[]).unwrap();
+    assert_eq!(
+        explained.action,
+        Action::UnknownCall {
+            contract: router,
+            selector: Some(format!("0x{}", bytes_to_hex_str(&data[..4]))),
+        }
+    );
+
+    // Machine readable
+    let json = serde_json::to_value(&explained).unwrap();
+    assert_eq!(json["action"]["kind"], "unknown_call");
+    assert_eq!(json["fee"]["max_fee"]["formatted"], "0.00042");
+    assert_eq!(json["addresses"][1]["role"], "contract");
+}
@@ -0,0 +1,78 @@
+//! Keccak-256, the hash of addresses, transactions, signatures and tries.
+//!
+//! All of Clarity hashes through here. The permutation is that of the
+//! `keccak` crate, which with the `keccak-asm` feature uses the SHA-3
+//! instructions of ARMv8.2 processors (such as Apple silicon and Graviton)
+//! when they are there, and falls back to portable code otherwise. On other
+//! architectures the feature changes nothing.
+//!
+//! Whichever backend is used, the outputs are pinned by the tests below.
+use sha3::{Digest, Keccak256};
+
+/// The Keccak-256 hash of `data`.
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&Keccak256::digest(data));
+    hash
+}
+
+/// The digest `personal_sign` signs for `msg`, as defined by EIP-191:
+/// Keccak-256 of `"\x19Ethereum Signed Message:\n"`, the length of the
+/// message in decimal, and the message.
+pub fn ethereum_msg_hash(msg: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256Hasher::new();
+    hasher.update(format!("\x19Ethereum Signed Message:\n{}", msg.len()).as_bytes());
+    hasher.update(msg);
+    hasher.finalize()
+}
+
+/// Keccak-256 of data given in parts.
+#[derive(Debug, Clone, Default)]
+pub struct Keccak256Hasher(Keccak256);
+
+impl Keccak256Hasher {
+    pub fn new() -> Keccak256Hasher {
+        Keccak256Hasher::default()
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        self.0.input(data);
+    }
+
+    pub fn finalize(self) -> [u8; 32] {
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&self.0.result());
+        hash
+    }
+}
+
+#[test]
+fn pinned_outputs() {
+    use utils::bytes_to_hex_str;
+    let hex = |data: &[u8]| bytes_to_hex_str(&keccak256(data));
+    assert_eq!(
+        hex(b""),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+    assert_eq!(
+        hex(b"abc"),
+        "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+    );
+    // Exactly one block of 136 bytes, which needs a block of padding
+    assert_eq!(
+        hex(&[0u8; 136]),
+        "3a5912a7c5faa06ee4fe906253e339467a9ce87d533c65be3c15cb231cdb25f9"
+    );
+    // A function selector
+    assert_eq!(
+        &keccak256(b"transfer(address,uint256)")[..4],
+        &[0xa9, 0x05, 0x9c, 0xbb]
+    );
+
+    let data: Vec<u8> = (0..1000u32).map(|i| i as u8).collect();
+    let mut hasher = Keccak256Hasher::new();
+    for chunk in data.chunks(137) {
+        hasher.update(chunk);
+    }
+    assert_eq!(hasher.finalize(), keccak256(&data));
+}
@@ -0,0 +1,249 @@
+//! BIP-44 derivation paths, and the ones Ethereum wallets use.
+//!
+//! Keys are derived from a seed along these paths, see
+//! [bip32](../bip32/index.html), and the path decides which accounts show
+//! up in a wallet. Wallets agree on BIP-44 with the coin types of SLIP-44,
+//! and still differ on which level of the path they count accounts on. [PathStyle](enum.PathStyle.html) gives
+//! the paths of account `n` the way each of them does, so the same accounts
+//! can be found from the same seed.
+use failure::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// SLIP-44 coin type of Ethereum, also used on L2s and by most wallets on
+/// testnets, since the addresses are the same.
+pub const COIN_TYPE_ETHEREUM: u32 = 60;
+/// SLIP-44 coin type of Ethereum Classic.
+pub const COIN_TYPE_ETHEREUM_CLASSIC: u32 = 61;
+/// SLIP-44 coin type shared by all testnets, which some hardware wallets
+/// use.
+pub const COIN_TYPE_TESTNET: u32 = 1;
+
+/// Chain id of Ethereum Classic.
+const ETHEREUM_CLASSIC_CHAIN_ID: u64 = 61;
+
+/// Indices at or above this are hardened.
+const HARDENED: u32 = 1 << 31;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum DerivationPathError {
+    #[fail(display = "Invalid derivation path: {}", _0)]
+    InvalidPath(String),
+}
+
+/// One level of a derivation path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ChildNumber {
+    /// Index below 2^31
+    pub index: u32,
+    pub hardened: bool,
+}
+
+impl ChildNumber {
+    pub fn normal(index: u32) -> ChildNumber {
+        ChildNumber {
+            index,
+            hardened: false,
+        }
+    }
+
+    pub fn hardened(index: u32) -> ChildNumber {
+        ChildNumber {
+            index,
+            hardened: true,
+        }
+    }
+
+    /// The index as used in BIP-32 derivation, with the top bit set when
+    /// hardened.
+    pub fn to_u32(self) -> u32 {
+        if self.hardened {
+            self.index | HARDENED
+        } else {
+            self.index
+        }
+    }
+}
+
+impl fmt::Display for ChildNumber {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.index)?;
+        if self.hardened {
+            write!(f, "'")?;
+        }
+        Ok(())
+    }
+}
+
+/// A BIP-32 derivation path such as `m/44'/60'/0'/0/0`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Default)]
+pub struct DerivationPath(pub Vec<ChildNumber>);
+
+impl DerivationPath {
+    /// `m/44'/coin_type'/account'/change/index`
+    pub fn bip44(coin_type: u32, account: u32, change: u32, index: u32) -> DerivationPath {
+        DerivationPath(vec![
+            ChildNumber::hardened(44),
+            ChildNumber::hardened(coin_type),
+            ChildNumber::hardened(account),
+            ChildNumber::normal(change),
+            ChildNumber::normal(index),
+        ])
+    }
+}
+
+impl FromStr for DerivationPath {
+    type Err = Error;
+
+    /// Parses a path starting with `m`, whose hardened levels are marked
+    /// with `'` or `h`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || DerivationPathError::InvalidPath(s.to_owned());
+        let mut segments = s.split('/');
+        if segments.next() != Some("m") {
+            return Err(invalid().into());
+        }
+        let mut path = Vec::new();
+        for segment in segments {
+            let (digits, hardened) = match segment.strip_suffix(['\'', 'h']) {
+                Some(digits) => (digits, true),
+                None => (segment, false),
+            };
+            // No signs, and no leading zeros that would make two spellings
+            // of one path
+            if digits.is_empty()
+                || !digits.bytes().all(|b| b.is_ascii_digit())
+                || (digits.len() > 1 && digits.starts_with('0'))
+            {
+                return Err(invalid().into());
+            }
+            let index: u32 = digits.parse().map_err(|_| invalid())?;
+            if index >= HARDENED {
+                return Err(invalid().into());
+            }
+            path.push(ChildNumber { index, hardened });
+        }
+        Ok(DerivationPath(path))
+    }
+}
+
+impl fmt::Display for DerivationPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "m")?;
+        for child in &self.0 {
+            write!(f, "/{}", child)?;
+        }
+        Ok(())
+    }
+}
+
+/// Which level of the BIP-44 path a wallet counts accounts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathStyle {
+    /// `m/44'/60'/0'/0/n`, used by MetaMask, Trezor, Rabby, and the Ledger
+    /// Ethereum app in its default mode
+    Bip44,
+    /// `m/44'/60'/n'/0/0`, used by Ledger Live
+    LedgerLive,
+    /// `m/44'/60'/0'/n`, used by MyEtherWallet with Ledger before Ledger
+    /// Live, and shown as "Legacy" by most wallets
+    LedgerLegacy,
+}
+
+impl PathStyle {
+    /// The path of account `n` for `coin_type`.
+    pub fn path(self, coin_type: u32, n: u32) -> DerivationPath {
+        match self {
+            PathStyle::Bip44 => DerivationPath::bip44(coin_type, 0, 0, n),
+            PathStyle::LedgerLive => DerivationPath::bip44(coin_type, n, 0, 0),
+            PathStyle::LedgerLegacy => DerivationPath(vec![
+                ChildNumber::hardened(44),
+                ChildNumber::hardened(coin_type),
+                ChildNumber::hardened(0),
+                ChildNumber::normal(n),
+            ]),
+        }
+    }
+
+    /// The paths of the first `count` accounts, in the order the wallet
+    /// lists them.
+    pub fn paths(self, coin_type: u32, count: u32) -> impl Iterator<Item = DerivationPath> {
+        (0..count).map(move |n| self.path(coin_type, n))
+    }
+}
+
+/// The coin type wallets derive keys for `chain_id` with.
+///
+/// Ethereum Classic has its own. Every other EVM chain, L2 or testnet is
+/// derived as Ethereum by the common wallets, which is what users expect
+/// their accounts to be found with; use
+/// [COIN_TYPE_TESTNET](constant.COIN_TYPE_TESTNET.html) explicitly for
+/// wallets that follow SLIP-44 to the letter on testnets.
+pub fn coin_type(chain_id: u64) -> u32 {
+    if chain_id == ETHEREUM_CLASSIC_CHAIN_ID {
+        COIN_TYPE_ETHEREUM_CLASSIC
+    } else {
+        COIN_TYPE_ETHEREUM
+    }
+}
+
+#[test]
+fn parse_and_display() {
+    let path: DerivationPath = "m/44'/60'/0'/0/7".parse().unwrap();
+    assert_eq!(path, DerivationPath::bip44(COIN_TYPE_ETHEREUM, 0, 0, 7));
+    assert_eq!(path.to_string(), "m/44'/60'/0'/0/7");
+    assert_eq!(path.0[0].to_u32(), 0x8000_002c);
+    assert_eq!(path.0[4].to_u32(), 7);
+    assert_eq!("m/44h/60h/0h/0/7".parse::<DerivationPath>().unwrap(), path);
+    assert_eq!(
+        "m".parse::<DerivationPath>().unwrap(),
+        DerivationPath::default()
+    );
+
+    for invalid in &[
+        "",
+        "44'/60'",
+        "m/",
+        "m/44''",
+        "m/-1",
+        "m/+1",
+        "m/01",
+        "m/2147483648",
+        "m/44'/x",
+    ] {
+        assert_eq!(
+            invalid
+                .parse::<DerivationPath>()
+                .unwrap_err()
+                .downcast::<DerivationPathError>()
+                .unwrap(),
+            DerivationPathError::InvalidPath(invalid.to_string())
+        );
+    }
+}
+
+#[test]
+fn wallet_presets() {
+    let paths = |style: PathStyle, coin_type: u32| {
+        style
+            .paths(coin_type, 2)
+            .map(|path| path.to_string())
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        paths(PathStyle::Bip44, coin_type(1)),
+        vec!["m/44'/60'/0'/0/0", "m/44'/60'/0'/0/1"]
+    );
+    assert_eq!(
+        paths(PathStyle::LedgerLive, coin_type(10)),
+        vec!["m/44'/60'/0'/0/0", "m/44'/60'/1'/0/0"]
+    );
+    assert_eq!(
+        paths(PathStyle::LedgerLegacy, coin_type(61)),
+        vec!["m/44'/61'/0'/0", "m/44'/61'/0'/1"]
+    );
+    assert_eq!(
+        paths(PathStyle::Bip44, COIN_TYPE_TESTNET)[0],
+        "m/44'/1'/0'/0/0"
+    );
+}
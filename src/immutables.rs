@@ -0,0 +1,295 @@
+//! The values of the immutable variables of a deployed contract.
+//!
+//! Solidity compiles an `immutable` into the runtime code with zeros where
+//! its value goes, and the constructor writes the value in before
+//! returning the code to deploy. The values are thus only in the deployed
+//! code, at the places solc lists as the `immutableReferences` of the
+//! deployed bytecode, by AST id of the variable:
+//!
+//! ```json
+//! "immutableReferences": {"7": [{"start": 57, "length": 32}, {"start": 190, "length": 32}]}
+//! ```
+//!
+//! [Immutables](struct.Immutables.html) reads them back, checking that the
+//! rest of the deployed code is what was compiled, so that parameters set
+//! by a constructor can be recovered from the chain.
+use abi::{decode_tokens, ParamType, Token};
+use failure::Error;
+use std::collections::BTreeMap;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ImmutablesError {
+    #[fail(
+        display = "Reference to immutable {} at {} is out of the code",
+        ast_id, start
+    )]
+    OutOfBounds { ast_id: String, start: usize },
+    #[fail(display = "Immutable {} has different values in different places", _0)]
+    Inconsistent(String),
+    #[fail(display = "Unknown immutable {}", _0)]
+    UnknownImmutable(String),
+    #[fail(
+        display = "Deployed code of {} bytes is not the compiled code of {} bytes",
+        deployed, compiled
+    )]
+    LengthMismatch { compiled: usize, deployed: usize },
+    #[fail(display = "Deployed code differs from the compiled code at {}", _0)]
+    CodeMismatch(usize),
+    #[fail(display = "Deployed code is not in the creation code")]
+    NotInCreationCode,
+}
+
+/// A place of an immutable in the runtime code.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ImmutableReference {
+    pub start: usize,
+    pub length: usize,
+}
+
+/// The `immutableReferences` of solc: the places of each immutable, by the
+/// AST id of the variable.
+pub type ImmutableReferences = BTreeMap<String, Vec<ImmutableReference>>;
+
+/// The values of the immutables of a deployed contract, by AST id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Immutables {
+    values: BTreeMap<String, Vec<u8>>,
+}
+
+impl Immutables {
+    /// Reads the immutables from deployed code alone, checking only that
+    /// the places of each immutable agree.
+    pub fn from_deployed(
+        deployed: &[u8],
+        references: &ImmutableReferences,
+    ) -> Result<Immutables, Error> {
+        let mut values = BTreeMap::new();
+        for (ast_id, places) in references {
+            let mut value: Option<&[u8]> = None;
+            for place in places {
+                let bytes = place_in(deployed, ast_id, place)?;
+                match value {
+                    Some(value) if value != bytes => {
+                        return Err(ImmutablesError::Inconsistent(ast_id.clone()).into());
+                    }
+                    _ => value = Some(bytes),
+                }
+            }
+            if let Some(value) = value {
+                values.insert(ast_id.clone(), value.to_vec());
+            }
+        }
+        Ok(Immutables { values })
+    }
+
+    /// Reads the immutables from deployed code after checking it against
+    /// the runtime code solc compiled, the `deployedBytecode`, everywhere
+    /// but at the places of the immutables.
+    pub fn from_runtime_code(
+        compiled: &[u8],
+        deployed: &[u8],
+        references: &ImmutableReferences,
+    ) -> Result<Immutables, Error> {
+        let immutables = Immutables::from_deployed(deployed, references)?;
+        if compiled.len() != deployed.len() {
+            return Err(ImmutablesError::LengthMismatch {
+                compiled: compiled.len(),
+                deployed: deployed.len(),
+            }
+            .into());
+        }
+        let mask = immutable_mask(deployed.len(), references);
+        let mismatch = (0..deployed.len()).find(|&i| !mask[i] && compiled[i] != deployed[i]);
+        if let Some(offset) = mismatch {
+            return Err(ImmutablesError::CodeMismatch(offset).into());
+        }
+        Ok(immutables)
+    }
+
+    /// Reads the immutables from deployed code after finding the runtime
+    /// code in the creation code, the `bytecode` of solc or the input of the
+    /// deployment, which carries it after the code of the constructor.
+    pub fn from_creation_code(
+        creation: &[u8],
+        deployed: &[u8],
+        references: &ImmutableReferences,
+    ) -> Result<Immutables, Error> {
+        let immutables = Immutables::from_deployed(deployed, references)?;
+        let mask = immutable_mask(deployed.len(), references);
+        let matches_at = |start: usize| {
+            let template = &creation[start..start + deployed.len()];
+            template
+                .iter()
+                .zip(deployed)
+                .zip(&mask)
+                .all(|((a, b), masked)| *masked || a == b)
+        };
+        let last_start = match creation.len().checked_sub(deployed.len()) {
+            Some(last_start) => last_start,
+            None => return Err(ImmutablesError::NotInCreationCode.into()),
+        };
+        if !(0..=last_start).any(matches_at) {
+            return Err(ImmutablesError::NotInCreationCode.into());
+        }
+        Ok(immutables)
+    }
+
+    /// The raw value of an immutable, usually one 32 byte word.
+    pub fn get(&self, ast_id: &str) -> Option<&[u8]> {
+        self.values.get(ast_id).map(Vec::as_slice)
+    }
+
+    /// The value of an immutable of a value type, as a token.
+    pub fn decode(&self, ast_id: &str, param_type: &ParamType) -> Result<Token, Error> {
+        let value = match self.get(ast_id) {
+            Some(value) => value,
+            None => return Err(ImmutablesError::UnknownImmutable(ast_id.to_string()).into()),
+        };
+        let mut tokens = decode_tokens(std::slice::from_ref(param_type), value)?;
+        Ok(tokens.remove(0))
+    }
+
+    /// The values by AST id.
+    pub fn values(&self) -> &BTreeMap<String, Vec<u8>> {
+        &self.values
+    }
+}
+
+fn place_in<'a>(
+    code: &'a [u8],
+    ast_id: &str,
+    place: &ImmutableReference,
+) -> Result<&'a [u8], ImmutablesError> {
+    match place.start.checked_add(place.length) {
+        Some(end) if end <= code.len() => Ok(&code[place.start..end]),
+        _ => Err(ImmutablesError::OutOfBounds {
+            ast_id: ast_id.to_string(),
+            start: place.start,
+        }),
+    }
+}
+
+/// Which bytes of code of `len` bytes belong to immutables. The places have
+/// been checked to be within the code.
+fn immutable_mask(len: usize, references: &ImmutableReferences) -> Vec<bool> {
+    let mut mask = vec![false; len];
+    for place in references.values().flatten() {
+        for masked in &mut mask[place.start..place.start + place.length] {
+            *masked = true;
+        }
+    }
+    mask
+}
+
+#[cfg(test)]
+fn contract() -> (Vec<u8>, Vec<u8>, ImmutableReferences) {
+    // Immutable 7 is an address read twice, immutable 12 an uint256
+    let references: ImmutableReferences = serde_json::from_str(
+        r#"{
+            "7": [{"start": 3, "length": 32}, {"start": 40, "length": 32}],
+            "12": [{"start": 80, "length": 32}]
+        }"#,
+    )
+    .unwrap();
+    let mut runtime: Vec<u8> = (0..120u8).map(|i| i | 0x80).collect();
+    for place in references.values().flatten() {
+        for byte in &mut runtime[place.start..place.start + place.length] {
+            *byte = 0;
+        }
+    }
+    let mut deployed = runtime.clone();
+    deployed[3 + 12..3 + 32].copy_from_slice(&[0x11; 20]);
+    deployed[40 + 12..40 + 32].copy_from_slice(&[0x11; 20]);
+    deployed[80 + 31] = 42;
+    let mut creation = vec![0x60, 0x80, 0x60, 0x40, 0x52];
+    creation.extend(&runtime);
+    // Constructor arguments
+    creation.extend(&[0u8; 32]);
+    (creation, deployed, references)
+}
+
+#[test]
+fn extract_immutables() {
+    use address::Address;
+    let (creation, deployed, references) = contract();
+    let immutables = Immutables::from_creation_code(&creation, &deployed, &references).unwrap();
+    assert_eq!(
+        immutables.decode("7", &ParamType::Address).unwrap(),
+        Token::Address(Address::from([0x11; 20]))
+    );
+    assert_eq!(
+        immutables.decode("12", &ParamType::Uint(256)).unwrap(),
+        Token::Uint(42u8.into())
+    );
+    assert_eq!(immutables.get("12").unwrap().len(), 32);
+    assert_eq!(
+        immutables
+            .decode("13", &ParamType::Bool)
+            .unwrap_err()
+            .downcast::<ImmutablesError>()
+            .unwrap(),
+        ImmutablesError::UnknownImmutable("13".to_string())
+    );
+    let runtime = &creation[5..5 + deployed.len()];
+    assert_eq!(
+        Immutables::from_runtime_code(runtime, &deployed, &references).unwrap(),
+        immutables
+    );
+    assert_eq!(
+        Immutables::from_deployed(&deployed, &references).unwrap(),
+        immutables
+    );
+}
+
+#[test]
+fn refuse_other_code() {
+    let (creation, deployed, references) = contract();
+    let mut patched = deployed.clone();
+    patched[2] ^= 1;
+    assert_eq!(
+        Immutables::from_creation_code(&creation, &patched, &references)
+            .unwrap_err()
+            .downcast::<ImmutablesError>()
+            .unwrap(),
+        ImmutablesError::NotInCreationCode
+    );
+    let runtime = &creation[5..5 + deployed.len()];
+    assert_eq!(
+        Immutables::from_runtime_code(runtime, &patched, &references)
+            .unwrap_err()
+            .downcast::<ImmutablesError>()
+            .unwrap(),
+        ImmutablesError::CodeMismatch(2)
+    );
+    assert_eq!(
+        Immutables::from_runtime_code(&runtime[..119], &deployed, &references)
+            .unwrap_err()
+            .downcast::<ImmutablesError>()
+            .unwrap(),
+        ImmutablesError::LengthMismatch {
+            compiled: 119,
+            deployed: 120,
+        }
+    );
+
+    // The two reads of immutable 7 disagree
+    let mut patched = deployed.clone();
+    patched[40 + 31] = 0;
+    assert_eq!(
+        Immutables::from_deployed(&patched, &references)
+            .unwrap_err()
+            .downcast::<ImmutablesError>()
+            .unwrap(),
+        ImmutablesError::Inconsistent("7".to_string())
+    );
+    assert_eq!(
+        Immutables::from_deployed(&deployed[..100], &references)
+            .unwrap_err()
+            .downcast::<ImmutablesError>()
+            .unwrap(),
+        ImmutablesError::OutOfBounds {
+            ast_id: "12".to_string(),
+            start: 80,
+        }
+    );
+}
@@ -0,0 +1,397 @@
+//! Word by word listings of ABI encoded data.
+//!
+//! When calldata from another encoder does not decode, or decodes to the
+//! wrong values, the fault is usually an offset or a length. An
+//! [Inspection](struct.Inspection.html) labels every 32 byte word of the
+//! data with what the parameter types make it: the value of a static
+//! parameter, the offset of a dynamic one, the length at that offset, and
+//! the data after it. Words that no parameter points to are labelled as
+//! such, which is often where the bug is.
+//!
+//! ```rust
+//! use clarity::abi::encode_call;
+//! use clarity::inspect::inspect_calldata;
+//!
+//! let calldata = encode_call("f(uint256,bytes)", &[1u8.into(), vec![0xabu8].into()]);
+//! println!("{}", inspect_calldata("f(uint256,bytes)", &calldata).unwrap());
+//! ```
+//!
+//! prints
+//!
+//! ```text
+//! selector  cdc9dc79                                                          f(uint256,bytes)
+//! 0x0000    0000000000000000000000000000000000000000000000000000000000000001  0: uint256
+//! 0x0020    0000000000000000000000000000000000000000000000000000000000000040  1: bytes, offset 0x40
+//! 0x0040    0000000000000000000000000000000000000000000000000000000000000001  1: length 1
+//! 0x0060    ab00000000000000000000000000000000000000000000000000000000000000  1: data
+//! ```
+use abi::{derive_method_id, parse_param_types, AbiDecodeError, ParamType};
+use failure::Error;
+use std::fmt;
+use utils::bytes_to_hex_str;
+
+/// What a word of the data is, for the parameter at index `param`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WordRole {
+    /// The value of a static parameter, in the head
+    Value { param: usize },
+    /// The offset of a dynamic parameter, in the head, or `None` if it is
+    /// not a 64 bit number
+    Offset { param: usize, target: Option<u64> },
+    /// The length of a dynamic parameter, or `None` if it is not a 64 bit
+    /// number
+    Length { param: usize, length: Option<u64> },
+    /// Contents of `bytes` or a `string`
+    Data { param: usize },
+    /// An item of an array or a member of a tuple, or the offset of one
+    /// if it is dynamic
+    Item { param: usize, item: usize },
+    /// A word no parameter points to
+    Unused,
+}
+
+/// A word of the data, which is short if the data ends within it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    /// Where the word starts, after the method id
+    pub position: usize,
+    pub bytes: Vec<u8>,
+    pub role: WordRole,
+}
+
+/// The words of ABI encoded data, labelled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Inspection {
+    /// The signature, for calldata
+    pub signature: Option<String>,
+    /// The method id of calldata
+    pub selector: Option<[u8; 4]>,
+    pub types: Vec<ParamType>,
+    pub words: Vec<Word>,
+}
+
+impl Inspection {
+    /// Whether the method id of calldata is the one of its signature.
+    pub fn selector_matches(&self) -> bool {
+        match (self.selector, self.signature.as_ref()) {
+            (Some(selector), Some(signature)) => selector == derive_method_id(signature),
+            _ => true,
+        }
+    }
+
+    /// The words no parameter points to.
+    pub fn unused(&self) -> impl Iterator<Item = &Word> {
+        self.words
+            .iter()
+            .filter(|word| word.role == WordRole::Unused)
+    }
+}
+
+/// A word as an offset or a length, if it fits 64 bits.
+fn to_u64(word: &[u8]) -> Option<u64> {
+    if word.len() != 32 || word[..24].iter().any(|b| *b != 0) {
+        return None;
+    }
+    let mut value = [0u8; 8];
+    value.copy_from_slice(&word[24..]);
+    Some(u64::from_be_bytes(value))
+}
+
+/// Labels the words of ABI encoded parameters of `types`, such as return
+/// data. Roles are only given to words that start on a word boundary, and
+/// the first parameter that points to a word labels it.
+pub fn inspect_data(types: &[ParamType], data: &[u8]) -> Inspection {
+    let mut labeller = Labeller {
+        data,
+        roles: vec![WordRole::Unused; data.len().div_ceil(32)],
+    };
+    let mut head = 0u64;
+    for (param, kind) in types.iter().enumerate() {
+        if kind.is_dynamic() {
+            let target = labeller.word_at(head);
+            labeller.label(head, WordRole::Offset { param, target });
+            if let Some(start) = target {
+                labeller.tail(kind, start, param);
+            }
+        } else {
+            labeller.words(head, kind.head_len(), WordRole::Value { param });
+        }
+        head += kind.head_len() as u64;
+    }
+
+    let words = data
+        .chunks(32)
+        .zip(labeller.roles)
+        .enumerate()
+        .map(|(i, (bytes, role))| Word {
+            position: i * 32,
+            bytes: bytes.to_vec(),
+            role,
+        })
+        .collect();
+    Inspection {
+        signature: None,
+        selector: None,
+        types: types.to_vec(),
+        words,
+    }
+}
+
+struct Labeller<'a> {
+    data: &'a [u8],
+    roles: Vec<WordRole>,
+}
+
+impl<'a> Labeller<'a> {
+    fn label(&mut self, position: u64, role: WordRole) {
+        if position.is_multiple_of(32) {
+            if let Some(slot) = self.roles.get_mut((position / 32) as usize) {
+                if *slot == WordRole::Unused {
+                    *slot = role;
+                }
+            }
+        }
+    }
+
+    /// Labels the words of `len` bytes at `position`.
+    fn words(&mut self, position: u64, len: usize, role: WordRole) {
+        for i in 0..len.div_ceil(32) as u64 {
+            self.label(position.saturating_add(i * 32), role);
+        }
+    }
+
+    fn word_at(&self, position: u64) -> Option<u64> {
+        let start = position as usize;
+        self.data
+            .get(start..start.saturating_add(32))
+            .and_then(to_u64)
+    }
+
+    /// Labels the tail of a dynamic value of `kind` that starts at `start`.
+    fn tail(&mut self, kind: &ParamType, start: u64, param: usize) {
+        match *kind {
+            ParamType::Bytes | ParamType::String | ParamType::Array(_) => {
+                let length = self.word_at(start);
+                self.label(start, WordRole::Length { param, length });
+                // Lengths past the data label every word after them
+                let length = match length {
+                    Some(length) => length.min(self.data.len() as u64),
+                    None => return,
+                };
+                let body = start.saturating_add(32);
+                match *kind {
+                    ParamType::Array(ref inner) => {
+                        let items = vec![&**inner; length as usize];
+                        self.items(&items, body, param);
+                    }
+                    _ => self.words(body, length as usize, WordRole::Data { param }),
+                }
+            }
+            ParamType::FixedArray(ref inner, size) => {
+                self.items(&vec![&**inner; size], start, param)
+            }
+            ParamType::Tuple(ref members) => {
+                self.items(&members.iter().collect::<Vec<_>>(), start, param)
+            }
+            _ => {}
+        }
+    }
+
+    /// Labels the heads of array items or tuple members encoded at `base`,
+    /// and the tails of the dynamic ones.
+    fn items(&mut self, kinds: &[&ParamType], base: u64, param: usize) {
+        let mut head = base;
+        for (item, kind) in kinds.iter().enumerate() {
+            if head >= self.data.len() as u64 {
+                return;
+            }
+            self.words(head, kind.head_len(), WordRole::Item { param, item });
+            if kind.is_dynamic() {
+                if let Some(offset) = self.word_at(head) {
+                    self.tail(kind, base.saturating_add(offset), param);
+                }
+            }
+            head += kind.head_len() as u64;
+        }
+    }
+}
+
+/// Labels the words of calldata for the function `signature`, such as
+/// `"transfer(address,uint256)"`.
+pub fn inspect_calldata(signature: &str, calldata: &[u8]) -> Result<Inspection, Error> {
+    let types = parse_param_types(signature)?;
+    if calldata.len() < 4 {
+        return Err(AbiDecodeError::UnexpectedEnd.into());
+    }
+    let mut selector = [0u8; 4];
+    selector.copy_from_slice(&calldata[..4]);
+    let mut inspection = inspect_data(&types, &calldata[4..]);
+    inspection.signature = Some(signature.to_owned());
+    inspection.selector = Some(selector);
+    Ok(inspection)
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(selector) = self.selector {
+            write!(f, "selector  {:<64}  ", bytes_to_hex_str(&selector))?;
+            match self.signature {
+                Some(ref signature) if self.selector_matches() => writeln!(f, "{}", signature)?,
+                Some(ref signature) => writeln!(f, "does not match {}", signature)?,
+                None => writeln!(f)?,
+            }
+        }
+        let kind = |param: usize| &self.types[param];
+        let hex = |value: Option<u64>| match value {
+            Some(value) => format!("{:#x}", value),
+            None => "out of range".to_owned(),
+        };
+        for (i, word) in self.words.iter().enumerate() {
+            write!(
+                f,
+                "{:#06x}    {:<64}  ",
+                word.position,
+                bytes_to_hex_str(&word.bytes)
+            )?;
+            match word.role {
+                WordRole::Value { param } => write!(f, "{}: {}", param, kind(param))?,
+                WordRole::Offset { param, target } => {
+                    write!(f, "{}: {}, offset {}", param, kind(param), hex(target))?
+                }
+                WordRole::Length { param, length } => match length {
+                    Some(length) => write!(f, "{}: length {}", param, length)?,
+                    None => write!(f, "{}: length out of range", param)?,
+                },
+                WordRole::Data { param } => write!(f, "{}: data", param)?,
+                WordRole::Item { param, item } => write!(f, "{}: item {}", param, item)?,
+                WordRole::Unused => write!(f, "unused")?,
+            }
+            if i + 1 < self.words.len() {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn inspect_dynamic_params() {
+    use abi::{encode_call, Token};
+    let signature = "f(uint256,uint32[],bytes10,bytes)";
+    let calldata = encode_call(
+        signature,
+        &[
+            0x123u32.into(),
+            vec![0x456u32, 0x789].into(),
+            Token::Bytes(b"1234567890".to_vec()),
+            Token::UnboundedBytes(b"Hello, world!".to_vec()),
+        ],
+    );
+    let inspection = inspect_calldata(signature, &calldata).unwrap();
+    assert!(inspection.selector_matches());
+    let roles: Vec<WordRole> = inspection.words.iter().map(|word| word.role).collect();
+    assert_eq!(
+        roles,
+        vec![
+            WordRole::Value { param: 0 },
+            WordRole::Offset {
+                param: 1,
+                target: Some(0x80)
+            },
+            WordRole::Value { param: 2 },
+            WordRole::Offset {
+                param: 3,
+                target: Some(0xe0)
+            },
+            WordRole::Length {
+                param: 1,
+                length: Some(2)
+            },
+            WordRole::Item { param: 1, item: 0 },
+            WordRole::Item { param: 1, item: 1 },
+            WordRole::Length {
+                param: 3,
+                length: Some(13)
+            },
+            WordRole::Data { param: 3 },
+        ]
+    );
+    assert_eq!(inspection.unused().count(), 0);
+    let listing = inspection.to_string();
+    assert!(listing.starts_with("selector  8be65246 "));
+    assert!(listing.contains("\n0x0020    "));
+    assert!(listing.ends_with("  3: data"));
+}
+
+#[test]
+fn inspect_broken_offsets() {
+    // bytes whose offset skips a word, so the word is unused, and a string
+    // whose offset does not fit 64 bits
+    let mut data = vec![0u8; 32 * 5];
+    data[31] = 0x60;
+    data[32] = 0xff;
+    data[127] = 1;
+    data[128] = 0xab;
+    let inspection = inspect_data(&[ParamType::Bytes, ParamType::String], &data);
+    let roles: Vec<WordRole> = inspection.words.iter().map(|word| word.role).collect();
+    assert_eq!(
+        roles,
+        vec![
+            WordRole::Offset {
+                param: 0,
+                target: Some(0x60)
+            },
+            WordRole::Offset {
+                param: 1,
+                target: None
+            },
+            WordRole::Unused,
+            WordRole::Length {
+                param: 0,
+                length: Some(1)
+            },
+            WordRole::Data { param: 0 },
+        ]
+    );
+    assert_eq!(inspection.unused().next().unwrap().position, 0x40);
+    assert!(inspection
+        .to_string()
+        .contains("1: string, offset out of range"));
+
+    let wrong = inspect_calldata("g(uint256)", &data[..36]).unwrap();
+    assert!(!wrong.selector_matches());
+    assert!(inspect_calldata("g(uint256)", &[0x01]).is_err());
+}
+
+#[test]
+fn inspect_tuples() {
+    use abi::{encode_call, Token};
+    let signature = "f((uint256,bytes),uint8[2])";
+    let calldata = encode_call(
+        signature,
+        &[
+            Token::Tuple(vec![7u8.into(), vec![0xabu8].into()]),
+            Token::FixedArray(vec![1u8.into(), 2u8.into()]),
+        ],
+    );
+    let inspection = inspect_calldata(signature, &calldata).unwrap();
+    let roles: Vec<WordRole> = inspection.words.iter().map(|word| word.role).collect();
+    assert_eq!(
+        roles,
+        vec![
+            WordRole::Offset {
+                param: 0,
+                target: Some(0x60)
+            },
+            WordRole::Value { param: 1 },
+            WordRole::Value { param: 1 },
+            WordRole::Item { param: 0, item: 0 },
+            WordRole::Item { param: 0, item: 1 },
+            WordRole::Length {
+                param: 0,
+                length: Some(1)
+            },
+            WordRole::Data { param: 0 },
+        ]
+    );
+}
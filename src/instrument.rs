@@ -0,0 +1,168 @@
+//! Tracing of signing, sender recovery and ABI coding
+//!
+//! With the `instrument` feature every such operation runs in a `debug`
+//! level `tracing` span under the `clarity::instrument` target, named after
+//! the operation: `sign_hash`, `recover`, `recover_sender`, `encode_call`,
+//! `encode_tokens` and `decode_tokens`. When it finishes, its `outcome`
+//! field is set to the address that signed or was recovered, or to what was
+//! encoded or decoded. Keys are never recorded. The subscriber measures the
+//! time spent in the spans, and services that still use `log` collect them
+//! with the `log` feature of `tracing`.
+//!
+//! Without the feature the spans compile to nothing.
+use address::Address;
+use failure::Error;
+#[cfg(all(test, feature = "instrument"))]
+use std::fmt;
+
+pub(crate) struct Span {
+    #[cfg(feature = "instrument")]
+    span: ::tracing::span::EnteredSpan,
+}
+
+/// A constructor of [Span](struct.Span.html) for each operation, since the
+/// name of a span is part of its static metadata.
+macro_rules! operations {
+    ($($operation:ident),*) => {
+        impl Span {
+            $(
+                #[inline]
+                pub(crate) fn $operation() -> Span {
+                    Span {
+                        #[cfg(feature = "instrument")]
+                        span: debug_span!(
+                            target: "clarity::instrument",
+                            stringify!($operation),
+                            outcome = ::tracing::field::Empty
+                        )
+                        .entered(),
+                    }
+                }
+            )*
+        }
+    };
+}
+
+operations!(
+    sign_hash,
+    recover,
+    recover_sender,
+    encode_call,
+    encode_tokens,
+    decode_tokens
+);
+
+impl Span {
+    /// Records the outcome and closes the span. `details` is only called
+    /// when the span is enabled, so that the addresses it derives cost
+    /// nothing otherwise.
+    #[inline]
+    pub(crate) fn finish<F: FnOnce() -> String>(self, details: F) {
+        #[cfg(feature = "instrument")]
+        {
+            if !self.span.is_disabled() {
+                self.span
+                    .record("outcome", ::tracing::field::display(details()));
+            }
+        }
+        #[cfg(not(feature = "instrument"))]
+        let _ = details;
+    }
+}
+
+/// Details of a recovery.
+pub(crate) fn outcome(result: &Result<Address, Error>) -> String {
+    match *result {
+        Ok(ref address) => address.to_string(),
+        Err(ref e) => format!("failed: {}", e),
+    }
+}
+
+/// Collects the spans of the `clarity::instrument` target, with the
+/// outcome they recorded.
+#[cfg(all(test, feature = "instrument"))]
+#[derive(Default)]
+struct TestSubscriber(::std::sync::Mutex<Vec<(&'static str, String)>>);
+
+#[cfg(all(test, feature = "instrument"))]
+struct Outcome<'a>(&'a mut String);
+
+#[cfg(all(test, feature = "instrument"))]
+impl<'a> ::tracing::field::Visit for Outcome<'a> {
+    fn record_debug(&mut self, field: &::tracing::field::Field, value: &dyn fmt::Debug) {
+        if field.name() == "outcome" {
+            *self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+#[cfg(all(test, feature = "instrument"))]
+impl ::tracing::Subscriber for TestSubscriber {
+    fn enabled(&self, metadata: &::tracing::Metadata) -> bool {
+        metadata.target() == "clarity::instrument"
+    }
+
+    fn new_span(&self, span: &::tracing::span::Attributes) -> ::tracing::span::Id {
+        let mut spans = self.0.lock().unwrap();
+        spans.push((span.metadata().name(), String::new()));
+        ::tracing::span::Id::from_u64(spans.len() as u64)
+    }
+
+    fn record(&self, id: &::tracing::span::Id, values: &::tracing::span::Record) {
+        let mut spans = self.0.lock().unwrap();
+        values.record(&mut Outcome(&mut spans[id.into_u64() as usize - 1].1));
+    }
+
+    fn record_follows_from(&self, _: &::tracing::span::Id, _: &::tracing::span::Id) {}
+
+    fn event(&self, _: &::tracing::Event) {}
+
+    fn enter(&self, _: &::tracing::span::Id) {}
+
+    fn exit(&self, _: &::tracing::span::Id) {}
+}
+
+#[cfg(all(test, feature = "instrument"))]
+#[test]
+fn operations_are_traced() {
+    use abi::{decode_tokens, encode_call, encode_tokens, parse_param_types, Token};
+    use private_key::PrivateKey;
+    use std::sync::Arc;
+    use transaction::Transaction;
+
+    let secret = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0";
+    let key: PrivateKey = secret.parse().unwrap();
+    let signer = key.to_public_key().unwrap();
+    let subscriber = Arc::new(TestSubscriber::default());
+    // The subscriber is only that of this thread, so other tests running in
+    // parallel do not show up in it
+    ::tracing::subscriber::with_default(subscriber.clone(), || {
+        let tx = Transaction {
+            nonce: 0u8.into(),
+            gas_price: 1u8.into(),
+            gas_limit: 21_000u32.into(),
+            to: signer,
+            value: 1u8.into(),
+            data: encode_call("transfer(address,uint256)", &[signer.into(), 1u8.into()]),
+            signature: None,
+        }
+        .sign(&key, Some(1));
+        assert_eq!(tx.sender().unwrap(), signer);
+        let types = parse_param_types("transfer(address,uint256)").unwrap();
+        let args = decode_tokens(&types, &tx.data[4..]).unwrap();
+        assert_eq!(args[0], Token::Address(signer));
+        assert_eq!(encode_tokens(&args), tx.data[4..]);
+    });
+
+    let spans = subscriber.0.lock().unwrap();
+    let traced = |name: &str, outcome: String| spans.contains(&(name, outcome));
+    assert!(traced(
+        "encode_call",
+        "transfer(address,uint256), 68 bytes".to_owned()
+    ));
+    assert!(traced("sign_hash", format!("by {}", signer)));
+    assert!(traced("recover_sender", signer.to_string()));
+    assert!(traced("decode_tokens", "2 tokens from 64 bytes".to_owned()));
+    assert!(traced("encode_tokens", "2 tokens, 64 bytes".to_owned()));
+    assert!(!spans.iter().any(|(_, outcome)| outcome.contains(secret)));
+}
@@ -0,0 +1,327 @@
+//! Append-only journal of signed transactions.
+//!
+//! Signing services can use a journal to durably record every transaction
+//! they sign, and later replay it to verify what was signed.
+//!
+//! # Format
+//!
+//! A journal starts with the 8 byte magic `CLTXJRN1`, followed by records:
+//!
+//! * the length of the raw transaction as a big endian `u32`
+//! * the raw transaction bytes
+//! * a 32 byte checksum, which is the Keccak-256 of the previous record's
+//!   checksum (zeros for the first record) followed by the raw bytes
+//!
+//! Since every checksum covers the one before it, the reader notices
+//! accidental corruption of, or within, the records it reads: a record that
+//! was changed, or one removed or moved from between others. The checksums
+//! are not keyed, so they do not prove anything against someone who can
+//! write the journal: records dropped from its end leave a shorter valid
+//! journal, and a rewritten one can be checksummed anew. To notice that,
+//! keep the last checksum and the number of records elsewhere, such as in
+//! a database or a signed log, and compare them to what the reader reports.
+use failure::Error;
+use hash::Keccak256Hasher;
+use std::io::{self, Read, Write};
+use transaction::SignedTx;
+
+/// Magic bytes at the start of every journal.
+pub const JOURNAL_MAGIC: &[u8; 8] = b"CLTXJRN1";
+
+/// Largest record the reader accepts, so a corrupted length can not cause
+/// a huge allocation.
+pub const MAX_RECORD_LENGTH: u32 = 1 << 24;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum JournalError {
+    #[fail(display = "Journal does not start with the expected magic bytes")]
+    InvalidMagic,
+    #[fail(display = "Journal record {} is {} bytes long", _0, _1)]
+    RecordTooLarge(u64, u32),
+    #[fail(display = "Journal record {} does not match its checksum", _0)]
+    ChecksumMismatch(u64),
+    #[fail(display = "Journal record {} is incomplete", _0)]
+    TruncatedRecord(u64),
+}
+
+fn chain_checksum(previous: &[u8; 32], raw: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256Hasher::new();
+    hasher.update(previous);
+    hasher.update(raw);
+    hasher.finalize()
+}
+
+/// Writes signed transactions to a journal.
+pub struct JournalWriter<W: Write> {
+    inner: W,
+    checksum: [u8; 32],
+}
+
+impl<W: Write> JournalWriter<W> {
+    /// Starts a new journal, writing its magic bytes.
+    pub fn new(mut inner: W) -> Result<JournalWriter<W>, Error> {
+        inner.write_all(JOURNAL_MAGIC)?;
+        inner.flush()?;
+        Ok(JournalWriter {
+            inner,
+            checksum: [0u8; 32],
+        })
+    }
+
+    /// Continues an existing journal.
+    ///
+    /// `inner` has to be positioned at the end of the journal (i.e. a file
+    /// opened in append mode), and `checksum` is the last checksum reported
+    /// by a [JournalReader](struct.JournalReader.html) that read it.
+    pub fn resume(inner: W, checksum: [u8; 32]) -> JournalWriter<W> {
+        JournalWriter { inner, checksum }
+    }
+
+    /// Appends a transaction and flushes it, returning the new checksum.
+    pub fn append(&mut self, tx: &SignedTx) -> Result<[u8; 32], Error> {
+        let raw = tx.as_bytes();
+        ensure!(
+            raw.len() <= MAX_RECORD_LENGTH as usize,
+            "Transaction is too large for the journal"
+        );
+        let checksum = chain_checksum(&self.checksum, raw);
+        let mut record = Vec::with_capacity(4 + raw.len() + 32);
+        record.extend_from_slice(&(raw.len() as u32).to_be_bytes());
+        record.extend_from_slice(raw);
+        record.extend_from_slice(&checksum);
+        self.inner.write_all(&record)?;
+        self.inner.flush()?;
+        self.checksum = checksum;
+        Ok(checksum)
+    }
+
+    /// Checksum of the last record written.
+    pub fn checksum(&self) -> [u8; 32] {
+        self.checksum
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+/// Reads and verifies the transactions in a journal.
+///
+/// This is an iterator that stops at the first error. A crash in the
+/// middle of an append shows up as
+/// [TruncatedRecord](enum.JournalError.html#variant.TruncatedRecord) on the
+/// last record.
+pub struct JournalReader<R: Read> {
+    inner: R,
+    checksum: [u8; 32],
+    index: u64,
+    failed: bool,
+}
+
+impl<R: Read> JournalReader<R> {
+    /// Opens a journal, checking its magic bytes.
+    pub fn new(mut inner: R) -> Result<JournalReader<R>, Error> {
+        let mut magic = [0u8; 8];
+        match inner.read_exact(&mut magic) {
+            Ok(()) if &magic == JOURNAL_MAGIC => {}
+            Ok(()) => return Err(JournalError::InvalidMagic.into()),
+            Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return Err(JournalError::InvalidMagic.into())
+            }
+            Err(e) => return Err(e.into()),
+        }
+        Ok(JournalReader {
+            inner,
+            checksum: [0u8; 32],
+            index: 0,
+            failed: false,
+        })
+    }
+
+    /// Checksum of the last record read, which is what
+    /// [JournalWriter::resume](struct.JournalWriter.html#method.resume)
+    /// needs to continue the journal.
+    pub fn checksum(&self) -> [u8; 32] {
+        self.checksum
+    }
+
+    /// Fills `buf`, distinguishing a clean end of the journal (`Ok(false)`)
+    /// from a record that ends early.
+    fn read_record_part(&mut self, buf: &mut [u8], allow_eof: bool) -> Result<bool, Error> {
+        let mut read = 0;
+        while read < buf.len() {
+            match self.inner.read(&mut buf[read..]) {
+                Ok(0) if read == 0 && allow_eof => return Ok(false),
+                Ok(0) => return Err(JournalError::TruncatedRecord(self.index).into()),
+                Ok(n) => read += n,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(true)
+    }
+
+    fn read_record(&mut self) -> Result<Option<SignedTx>, Error> {
+        let mut length = [0u8; 4];
+        if !self.read_record_part(&mut length, true)? {
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes(length);
+        if length > MAX_RECORD_LENGTH {
+            return Err(JournalError::RecordTooLarge(self.index, length).into());
+        }
+        let mut raw = vec![0u8; length as usize];
+        self.read_record_part(&mut raw, false)?;
+        let mut checksum = [0u8; 32];
+        self.read_record_part(&mut checksum, false)?;
+        if chain_checksum(&self.checksum, &raw) != checksum {
+            return Err(JournalError::ChecksumMismatch(self.index).into());
+        }
+        self.checksum = checksum;
+        self.index += 1;
+        Ok(Some(SignedTx::from_raw(raw)))
+    }
+}
+
+impl<R: Read> Iterator for JournalReader<R> {
+    type Item = Result<SignedTx, Error>;
+
+    fn next(&mut self) -> Option<Result<SignedTx, Error>> {
+        if self.failed {
+            return None;
+        }
+        let record = self.read_record();
+        self.failed = record.is_err();
+        record.transpose()
+    }
+}
+
+#[cfg(test)]
+fn signed_transactions() -> Vec<SignedTx> {
+    use private_key::PrivateKey;
+    use transaction::Transaction;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    (0u32..3)
+        .map(|nonce| {
+            let tx = Transaction {
+                nonce: nonce.into(),
+                gas_price: 1_000_000_000u32.into(),
+                gas_limit: 21_000u32.into(),
+                to: [0x22u8; 20].into(),
+                value: 1u32.into(),
+                data: Vec::new(),
+                signature: None,
+            };
+            SignedTx::new(tx.sign(&key, Some(1))).unwrap()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+fn write_journal(txs: &[SignedTx]) -> Vec<u8> {
+    let mut writer = JournalWriter::new(Vec::new()).unwrap();
+    for tx in txs {
+        writer.append(tx).unwrap();
+    }
+    writer.into_inner()
+}
+
+#[test]
+fn journal_roundtrip() {
+    let txs = signed_transactions();
+    let journal = write_journal(&txs);
+    let read: Vec<SignedTx> = JournalReader::new(&journal[..])
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(read, txs);
+    assert_eq!(
+        read[2].sender().unwrap(),
+        txs[2].transaction().unwrap().sender().unwrap()
+    );
+}
+
+#[test]
+fn journal_resume() {
+    let txs = signed_transactions();
+    let mut writer = JournalWriter::new(Vec::new()).unwrap();
+    writer.append(&txs[0]).unwrap();
+    let partial = writer.into_inner();
+
+    let mut reader = JournalReader::new(&partial[..]).unwrap();
+    assert_eq!(reader.by_ref().count(), 1);
+    let mut writer = JournalWriter::resume(partial.clone(), reader.checksum());
+    writer.append(&txs[1]).unwrap();
+    writer.append(&txs[2]).unwrap();
+    assert_eq!(writer.into_inner(), write_journal(&txs));
+}
+
+#[test]
+fn journal_detects_tampering() {
+    let txs = signed_transactions();
+    let mut journal = write_journal(&txs);
+    // Flip a byte inside the second record
+    let second = 8 + 4 + txs[0].as_bytes().len() + 32 + 10;
+    journal[second] ^= 1;
+    let results: Vec<_> = JournalReader::new(&journal[..]).unwrap().collect();
+    assert_eq!(results.len(), 2);
+    assert!(results[0].is_ok());
+    assert_eq!(
+        results[1]
+            .as_ref()
+            .unwrap_err()
+            .downcast_ref::<JournalError>(),
+        Some(&JournalError::ChecksumMismatch(1))
+    );
+}
+
+#[test]
+fn journal_detects_removed_record() {
+    let txs = signed_transactions();
+    // Records are chained, so dropping the second one breaks the third
+    let mut spliced = write_journal(&txs[..1]);
+    let third = 8 + (4 + txs[0].as_bytes().len() + 32) + (4 + txs[1].as_bytes().len() + 32);
+    spliced.extend_from_slice(&write_journal(&txs)[third..]);
+    let results: Vec<_> = JournalReader::new(&spliced[..]).unwrap().collect();
+    assert_eq!(results.len(), 2);
+    assert_eq!(
+        results[1]
+            .as_ref()
+            .unwrap_err()
+            .downcast_ref::<JournalError>(),
+        Some(&JournalError::ChecksumMismatch(1))
+    );
+}
+
+#[test]
+fn journal_truncated_and_invalid() {
+    let txs = signed_transactions();
+    let journal = write_journal(&txs);
+    let truncated = &journal[..journal.len() - 1];
+    let results: Vec<_> = JournalReader::new(truncated).unwrap().collect();
+    assert_eq!(results.len(), 3);
+    assert_eq!(
+        results[2]
+            .as_ref()
+            .unwrap_err()
+            .downcast_ref::<JournalError>(),
+        Some(&JournalError::TruncatedRecord(2))
+    );
+    assert!(JournalReader::new(&b"CLTX"[..]).is_err());
+    assert!(JournalReader::new(&b"NOTAJRNL"[..]).is_err());
+    let mut huge = JOURNAL_MAGIC.to_vec();
+    huge.extend_from_slice(&[0xff, 0xff, 0xff, 0xff]);
+    let results: Vec<_> = JournalReader::new(&huge[..]).unwrap().collect();
+    assert_eq!(
+        results[0]
+            .as_ref()
+            .unwrap_err()
+            .downcast_ref::<JournalError>(),
+        Some(&JournalError::RecordTooLarge(0, 0xffff_ffff))
+    );
+    // An empty journal has no records
+    assert_eq!(JournalReader::new(&JOURNAL_MAGIC[..]).unwrap().count(), 0);
+}
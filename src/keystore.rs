@@ -0,0 +1,1017 @@
+//! Web3 Secret Storage (V3) keystores, as written by geth and MetaMask.
+//!
+//! A keystore holds a private key encrypted with AES-128-CTR under a key
+//! derived from a password with scrypt or pbkdf2, and a MAC that tells a
+//! wrong password from a corrupt file. [Keystore](struct.Keystore.html)
+//! (de)serializes to the JSON of a keystore file, and
+//! [PrivateKey::from_keystore](../private_key/struct.PrivateKey.html#method.from_keystore)
+//! and [PrivateKey::to_keystore](../private_key/struct.PrivateKey.html#method.to_keystore)
+//! go between that JSON and a key directly:
+//!
+//! ```rust
+//! use clarity::keystore::{KeystoreKdf, KeystoreParams};
+//! use clarity::PrivateKey;
+//!
+//! let key: PrivateKey = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+//!     .parse()
+//!     .unwrap();
+//! // Fresh random salt and iv for every keystore in real use
+//! let params = KeystoreParams {
+//!     kdf: KeystoreKdf::Pbkdf2 { c: 1024 },
+//!     salt: [1u8; 32],
+//!     iv: [2u8; 16],
+//! };
+//! let json = key.to_keystore("password", &params).unwrap();
+//! assert_eq!(PrivateKey::from_keystore(&json, "password").unwrap(), key);
+//! ```
+//!
+//! [KeystoreStrength](enum.KeystoreStrength.html) presets the cost of the
+//! KDF, [KeystoreKdf::calibrate_scrypt](enum.KeystoreKdf.html#method.calibrate_scrypt)
+//! picks the cost that unlocks in a given time on the machine at hand, and
+//! [KeystoreLimits](struct.KeystoreLimits.html) caps the cost a keystore
+//! read from elsewhere may ask for.
+//! [Keystore::decrypt_with](struct.Keystore.html#method.decrypt_with) lends
+//! the decrypted key to a closure and wipes it, and everything derived from
+//! the password, when the closure returns.
+//!
+//! geth keeps its keystores in one directory, one file per account named
+//! `UTC--<time of creation>--<address>`, with the address also in the clear
+//! in the file. [KeystoreDir](struct.KeystoreDir.html) reads such a
+//! directory, lists the accounts in it without decrypting anything, and
+//! unlocks one of them on demand:
+//!
+//! ```rust,no_run
+//! use clarity::keystore::KeystoreDir;
+//!
+//! let dir = KeystoreDir::open("/home/geth/.ethereum/keystore").unwrap();
+//! for address in dir.addresses() {
+//!     println!("{}", address);
+//! }
+//! let key = dir.unlock(&dir.addresses()[0], "password").unwrap();
+//! ```
+use address::Address;
+use aes::cipher::{NewCipher, StreamCipher};
+use aes::Aes128Ctr;
+use failure::Error;
+use hash::Keccak256Hasher;
+use hmac::Hmac;
+use private_key::PrivateKey;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::fs;
+use std::hint::black_box;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use utils::{bytes_to_hex_str, hex_str_to_bytes};
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum KeystoreError {
+    #[fail(display = "Unsupported keystore version {}", _0)]
+    UnsupportedVersion(u32),
+    #[fail(display = "Unsupported keystore function: {}", _0)]
+    UnsupportedFunction(String),
+    #[fail(display = "Invalid keystore parameters")]
+    InvalidParams,
+    #[fail(display = "Keystore MAC mismatch (wrong password?)")]
+    MacMismatch,
+    #[fail(display = "Keystore of {} holds the key of {}", _0, _1)]
+    WrongAddress(Address, Address),
+    #[fail(display = "No keystore for {}", _0)]
+    UnknownAddress(Address),
+    #[fail(display = "Unknown keystore strength {}", _0)]
+    UnknownStrength(String),
+    #[fail(display = "Keystore parameters {:?} exceed the limits", _0)]
+    TooCostly(KeystoreKdf),
+}
+
+/// Key derivation function parameters of a keystore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeystoreKdfParams {
+    pub dklen: u32,
+    pub salt: String,
+    /// Scrypt cost parameter
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub n: Option<u32>,
+    /// Scrypt block size
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub r: Option<u32>,
+    /// Scrypt parallelization
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub p: Option<u32>,
+    /// Pbkdf2 iteration count
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub c: Option<u32>,
+    /// Pbkdf2 pseudo random function, only `hmac-sha256` is supported
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub prf: Option<String>,
+}
+
+/// Cipher parameters of a keystore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeystoreCipherParams {
+    pub iv: String,
+}
+
+/// The `crypto` object of a keystore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct KeystoreCrypto {
+    pub cipher: String,
+    pub cipherparams: KeystoreCipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: KeystoreKdfParams,
+    pub mac: String,
+}
+
+/// A V3 keystore.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Keystore {
+    // geth used to write it capitalized
+    #[serde(alias = "Crypto")]
+    pub crypto: KeystoreCrypto,
+    /// The address of the key, in hex, which geth writes but the format
+    /// does not require
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub id: String,
+    pub version: u32,
+}
+
+/// Key derivation function used when creating a keystore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeystoreKdf {
+    /// Scrypt with a given cost (`n`), block size (`r`) and parallelization (`p`)
+    Scrypt { n: u32, r: u32, p: u32 },
+    /// PBKDF2 with HMAC-SHA256 and a given iteration count
+    Pbkdf2 { c: u32 },
+}
+
+impl KeystoreKdf {
+    /// Scrypt at a preset strength.
+    pub fn scrypt(strength: KeystoreStrength) -> KeystoreKdf {
+        match strength {
+            // `geth --lightkdf`
+            KeystoreStrength::Light => KeystoreKdf::Scrypt {
+                n: 1 << 12,
+                r: 8,
+                p: 6,
+            },
+            // What geth writes by default
+            KeystoreStrength::Standard => KeystoreKdf::Scrypt {
+                n: 1 << 18,
+                r: 8,
+                p: 1,
+            },
+            KeystoreStrength::Paranoid => KeystoreKdf::Scrypt {
+                n: 1 << 20,
+                r: 8,
+                p: 1,
+            },
+        }
+    }
+
+    /// Pbkdf2 at a preset strength.
+    pub fn pbkdf2(strength: KeystoreStrength) -> KeystoreKdf {
+        let c = match strength {
+            KeystoreStrength::Light => 1 << 16,
+            // As in the test vector of the definition, and web3.js
+            KeystoreStrength::Standard => 1 << 18,
+            KeystoreStrength::Paranoid => 1 << 20,
+        };
+        KeystoreKdf::Pbkdf2 { c }
+    }
+
+    /// Checks that the parameters are ones the function takes: a power of two
+    /// above one for `n`, and no zeros.
+    pub fn check(&self) -> Result<(), KeystoreError> {
+        let valid = match *self {
+            KeystoreKdf::Scrypt { n, r, p } => {
+                n.is_power_of_two()
+                    && n > 1
+                    && r > 0
+                    && p > 0
+                    && u64::from(r) * u64::from(p) < 1 << 30
+            }
+            KeystoreKdf::Pbkdf2 { c } => c > 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(KeystoreError::InvalidParams)
+        }
+    }
+
+    /// Scrypt with `r = 8` and `p = 1`, and the `n` that makes it take
+    /// about `target` on this machine, as measured by running it. `n` is at
+    /// least that of the `Light` preset and at most what `limits` allow, and
+    /// `KeystoreError::TooCostly` is returned if they do not allow that.
+    pub fn calibrate_scrypt(
+        target: Duration,
+        limits: &KeystoreLimits,
+    ) -> Result<KeystoreKdf, KeystoreError> {
+        let scrypt = |n: u32| KeystoreKdf::Scrypt { n, r: 8, p: 1 };
+        calibrate(target, 1 << 12, |n| limits.check(&scrypt(n)), scrypt).map(scrypt)
+    }
+
+    /// Pbkdf2 with the iteration count that makes it take about `target` on
+    /// this machine, at least that of the `Light` preset and at most what
+    /// `limits` allow, as with
+    /// [calibrate_scrypt](#method.calibrate_scrypt).
+    pub fn calibrate_pbkdf2(
+        target: Duration,
+        limits: &KeystoreLimits,
+    ) -> Result<KeystoreKdf, KeystoreError> {
+        let pbkdf2 = |c: u32| KeystoreKdf::Pbkdf2 { c };
+        calibrate(target, 1 << 16, |c| limits.check(&pbkdf2(c)), pbkdf2).map(pbkdf2)
+    }
+
+    /// Runs the function over `password` and `salt`, filling `key`.
+    fn derive(&self, password: &[u8], salt: &[u8], key: &mut [u8]) -> Result<(), KeystoreError> {
+        self.check()?;
+        match *self {
+            KeystoreKdf::Scrypt { n, r, p } => {
+                let log_n = (31 - n.leading_zeros()) as u8;
+                let params =
+                    scrypt::Params::new(log_n, r, p).map_err(|_| KeystoreError::InvalidParams)?;
+                scrypt::scrypt(password, salt, &params, key)
+                    .map_err(|_| KeystoreError::InvalidParams)
+            }
+            KeystoreKdf::Pbkdf2 { c } => {
+                pbkdf2::pbkdf2::<Hmac<Sha256>>(password, salt, c, key);
+                Ok(())
+            }
+        }
+    }
+
+    /// The memory scrypt takes, in bytes, or zero for pbkdf2.
+    pub fn memory(&self) -> u64 {
+        match *self {
+            KeystoreKdf::Scrypt { n, r, .. } => 128 * u64::from(n) * u64::from(r),
+            KeystoreKdf::Pbkdf2 { .. } => 0,
+        }
+    }
+}
+
+/// The largest power of two cost, from `min`, that `allowed` admits and that
+/// runs in at most about `target`, or the error of `allowed` for `min`.
+/// Each cost is timed until one takes a tenth of a second, and the time of
+/// the costs after that is extrapolated, since it grows linearly with the
+/// cost.
+fn calibrate<A, K>(target: Duration, min: u32, allowed: A, kdf: K) -> Result<u32, KeystoreError>
+where
+    A: Fn(u32) -> Result<(), KeystoreError>,
+    K: Fn(u32) -> KeystoreKdf,
+{
+    allowed(min)?;
+    let time = |cost: u32| {
+        let mut key = [0u8; 32];
+        let start = Instant::now();
+        // The parameters were checked by `allowed`
+        let _ = kdf(cost).derive(b"password", &[0u8; 32], &mut key);
+        start.elapsed()
+    };
+    let mut cost = min;
+    let mut elapsed = time(cost);
+    while let Some(next) = cost.checked_mul(2) {
+        if allowed(next).is_err() || elapsed * 2 > target {
+            break;
+        }
+        cost = next;
+        elapsed = if elapsed < Duration::from_millis(100) {
+            time(cost)
+        } else {
+            elapsed * 2
+        };
+    }
+    Ok(cost)
+}
+
+/// Presets of the [KeystoreKdf](enum.KeystoreKdf.html) parameters, trading
+/// the time it takes to unlock a keystore against the time it takes to
+/// brute force its password.
+///
+/// With scrypt, `Light` takes a few milliseconds and 4 MiB, `Standard` about
+/// a second and 256 MiB, and `Paranoid` several seconds and 1 GiB.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeystoreStrength {
+    Light,
+    Standard,
+    Paranoid,
+}
+
+impl FromStr for KeystoreStrength {
+    type Err = KeystoreError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "light" => Ok(KeystoreStrength::Light),
+            "standard" => Ok(KeystoreStrength::Standard),
+            "paranoid" => Ok(KeystoreStrength::Paranoid),
+            _ => Err(KeystoreError::UnknownStrength(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for KeystoreStrength {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            KeystoreStrength::Light => "light",
+            KeystoreStrength::Standard => "standard",
+            KeystoreStrength::Paranoid => "paranoid",
+        })
+    }
+}
+
+/// The most work a keystore may ask for before it is decrypted, so that a
+/// keystore from elsewhere can not make a server spend minutes or gigabytes
+/// on it. The default admits the `Paranoid` presets and nothing costlier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeystoreLimits {
+    /// The most memory scrypt may take, in bytes
+    pub max_scrypt_memory: u64,
+    /// The most scrypt `p` may be, each of which repeats the work
+    pub max_scrypt_parallelization: u32,
+    /// The most pbkdf2 iterations
+    pub max_pbkdf2_iterations: u32,
+}
+
+impl Default for KeystoreLimits {
+    fn default() -> KeystoreLimits {
+        KeystoreLimits {
+            max_scrypt_memory: KeystoreKdf::scrypt(KeystoreStrength::Paranoid).memory(),
+            max_scrypt_parallelization: 6,
+            max_pbkdf2_iterations: 1 << 20,
+        }
+    }
+}
+
+impl KeystoreLimits {
+    /// Checks the parameters of a keystore against the limits.
+    pub fn check(&self, kdf: &KeystoreKdf) -> Result<(), KeystoreError> {
+        let within = match *kdf {
+            KeystoreKdf::Scrypt { p, .. } => {
+                kdf.memory() <= self.max_scrypt_memory && p <= self.max_scrypt_parallelization
+            }
+            KeystoreKdf::Pbkdf2 { c } => c <= self.max_pbkdf2_iterations,
+        };
+        if within {
+            Ok(())
+        } else {
+            Err(KeystoreError::TooCostly(*kdf))
+        }
+    }
+}
+
+/// How to encrypt a new keystore.
+///
+/// Clarity has no source of randomness of its own, so the caller has to
+/// provide a fresh random `salt` and `iv` for every keystore.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeystoreParams {
+    pub kdf: KeystoreKdf,
+    pub salt: [u8; 32],
+    pub iv: [u8; 16],
+}
+
+/// Runs the KDF of a keystore over a password.
+fn derive_key(crypto: &KeystoreCrypto, password: &[u8]) -> Result<Vec<u8>, Error> {
+    let params = &crypto.kdfparams;
+    // Half of the key is for AES-128 and the other half for the MAC
+    if params.dklen != 32 {
+        return Err(KeystoreError::InvalidParams.into());
+    }
+    let kdf = match crypto.kdf.as_str() {
+        "scrypt" => match (params.n, params.r, params.p) {
+            (Some(n), Some(r), Some(p)) => KeystoreKdf::Scrypt { n, r, p },
+            _ => return Err(KeystoreError::InvalidParams.into()),
+        },
+        "pbkdf2" => {
+            match params.prf.as_deref() {
+                Some("hmac-sha256") => {}
+                Some(prf) => return Err(KeystoreError::UnsupportedFunction(prf.to_owned()).into()),
+                None => return Err(KeystoreError::InvalidParams.into()),
+            }
+            KeystoreKdf::Pbkdf2 {
+                c: params.c.ok_or(KeystoreError::InvalidParams)?,
+            }
+        }
+        kdf => return Err(KeystoreError::UnsupportedFunction(kdf.to_owned()).into()),
+    };
+    let salt = hex_str_to_bytes(&params.salt)?;
+    let mut key = vec![0u8; params.dklen as usize];
+    kdf.derive(password, &salt, &mut key)?;
+    Ok(key)
+}
+
+/// Overwrites secrets with zeros before their memory is released.
+fn wipe(bytes: &mut [u8]) {
+    bytes.iter_mut().for_each(|b| *b = 0);
+    black_box(bytes);
+}
+
+/// Keccak-256 over the second half of the derived key and the ciphertext.
+fn keystore_mac(derived_key: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut mac = Keccak256Hasher::new();
+    mac.update(&derived_key[16..32]);
+    mac.update(ciphertext);
+    mac.finalize()
+}
+
+impl Keystore {
+    /// Reads the JSON of a keystore file.
+    pub fn from_json(json: &str) -> Result<Keystore, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
+
+    /// The address the keystore claims to hold the key of, if it says.
+    /// Nothing is decrypted, so this is only as trustworthy as the file.
+    pub fn address(&self) -> Option<Address> {
+        match self.address {
+            Some(ref address) if !address.is_empty() => Address::from_str(address).ok(),
+            _ => None,
+        }
+    }
+
+    /// The key derivation function of the keystore and its parameters,
+    /// which have to derive a key of 32 bytes.
+    pub fn kdf(&self) -> Result<KeystoreKdf, KeystoreError> {
+        let params = &self.crypto.kdfparams;
+        if params.dklen != 32 {
+            return Err(KeystoreError::InvalidParams);
+        }
+        let kdf = match self.crypto.kdf.as_str() {
+            "scrypt" => match (params.n, params.r, params.p) {
+                (Some(n), Some(r), Some(p)) => KeystoreKdf::Scrypt { n, r, p },
+                _ => return Err(KeystoreError::InvalidParams),
+            },
+            "pbkdf2" => match params.c {
+                Some(c) => KeystoreKdf::Pbkdf2 { c },
+                None => return Err(KeystoreError::InvalidParams),
+            },
+            kdf => return Err(KeystoreError::UnsupportedFunction(kdf.to_owned())),
+        };
+        kdf.check()?;
+        Ok(kdf)
+    }
+
+    /// Decrypts the private key, unless its parameters ask for more work
+    /// than `limits` allow, which is checked before any is done.
+    pub fn decrypt_with_limits(
+        &self,
+        password: &str,
+        limits: &KeystoreLimits,
+    ) -> Result<PrivateKey, Error> {
+        limits.check(&self.kdf()?)?;
+        self.decrypt(password)
+    }
+
+    /// Decrypts the private key, however much work the keystore asks for.
+    ///
+    /// Returns `KeystoreError::MacMismatch` when the password is wrong.
+    pub fn decrypt(&self, password: &str) -> Result<PrivateKey, Error> {
+        self.decrypt_with(password, |key| *key)
+    }
+
+    /// Decrypts the private key and lends it to `f`. The key, and the key
+    /// derived from the password, are wiped from memory as soon as `f`
+    /// returns, so that they are held no longer than they are used; `f`
+    /// should not keep a copy.
+    ///
+    /// Returns `KeystoreError::MacMismatch` when the password is wrong.
+    pub fn decrypt_with<T, F>(&self, password: &str, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&PrivateKey) -> T,
+    {
+        if self.version != 3 {
+            return Err(KeystoreError::UnsupportedVersion(self.version).into());
+        }
+        let crypto = &self.crypto;
+        if crypto.cipher != "aes-128-ctr" {
+            return Err(KeystoreError::UnsupportedFunction(crypto.cipher.clone()).into());
+        }
+        let mut derived_key = derive_key(crypto, password.as_bytes())?;
+        let mut plaintext = hex_str_to_bytes(&crypto.ciphertext)?;
+        let checked =
+            if keystore_mac(&derived_key, &plaintext)[..] != hex_str_to_bytes(&crypto.mac)?[..] {
+                Err(KeystoreError::MacMismatch.into())
+            } else {
+                let iv = hex_str_to_bytes(&crypto.cipherparams.iv)?;
+                Aes128Ctr::new_from_slices(&derived_key[..16], &iv)
+                    .map_err(|_| KeystoreError::InvalidParams.into())
+                    .map(|mut cipher| cipher.apply_keystream(&mut plaintext))
+            };
+        wipe(&mut derived_key);
+        let key = checked.and_then(|()| PrivateKey::from_slice(&plaintext));
+        wipe(&mut plaintext);
+        let mut key = key?;
+        let result = f(&key);
+        key = PrivateKey::default();
+        black_box(&key);
+        Ok(result)
+    }
+
+    /// Encrypts a private key into a new keystore, with the address of the
+    /// key in the clear like geth writes it. The `id` of the keystore is
+    /// derived from the salt and iv.
+    pub fn encrypt(
+        key: &PrivateKey,
+        password: &str,
+        params: &KeystoreParams,
+    ) -> Result<Keystore, Error> {
+        params.kdf.check()?;
+        let salt = bytes_to_hex_str(&params.salt);
+        let kdfparams = match params.kdf {
+            KeystoreKdf::Scrypt { n, r, p } => KeystoreKdfParams {
+                dklen: 32,
+                salt,
+                n: Some(n),
+                r: Some(r),
+                p: Some(p),
+                c: None,
+                prf: None,
+            },
+            KeystoreKdf::Pbkdf2 { c } => KeystoreKdfParams {
+                dklen: 32,
+                salt,
+                n: None,
+                r: None,
+                p: None,
+                c: Some(c),
+                prf: Some("hmac-sha256".to_owned()),
+            },
+        };
+        let kdf = match params.kdf {
+            KeystoreKdf::Scrypt { .. } => "scrypt",
+            KeystoreKdf::Pbkdf2 { .. } => "pbkdf2",
+        };
+        let mut crypto = KeystoreCrypto {
+            cipher: "aes-128-ctr".to_owned(),
+            cipherparams: KeystoreCipherParams {
+                iv: bytes_to_hex_str(&params.iv),
+            },
+            ciphertext: String::new(),
+            kdf: kdf.to_owned(),
+            kdfparams,
+            mac: String::new(),
+        };
+        let mut derived_key = derive_key(&crypto, password.as_bytes())?;
+        let mut ciphertext = key.to_bytes().to_vec();
+        Aes128Ctr::new_from_slices(&derived_key[..16], &params.iv)
+            .map_err(|_| KeystoreError::InvalidParams)?
+            .apply_keystream(&mut ciphertext);
+        crypto.mac = bytes_to_hex_str(&keystore_mac(&derived_key, &ciphertext));
+        crypto.ciphertext = bytes_to_hex_str(&ciphertext);
+        wipe(&mut derived_key);
+
+        // A version 4 UUID made out of the random inputs
+        let mut id = [0u8; 16];
+        id.copy_from_slice(&Sha256::digest(&[&params.salt[..], &params.iv[..]].concat())[..16]);
+        id[6] = (id[6] & 0x0f) | 0x40;
+        id[8] = (id[8] & 0x3f) | 0x80;
+        let id = bytes_to_hex_str(&id);
+
+        Ok(Keystore {
+            crypto,
+            address: Some(bytes_to_hex_str(key.to_public_key()?.as_bytes())),
+            id: format!(
+                "{}-{}-{}-{}-{}",
+                &id[0..8],
+                &id[8..12],
+                &id[12..16],
+                &id[16..20],
+                &id[20..32]
+            ),
+            version: 3,
+        })
+    }
+
+    /// The JSON of the keystore file.
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+impl PrivateKey {
+    /// Decrypts the key in the JSON of a V3 keystore file, as written by
+    /// geth or MetaMask.
+    ///
+    /// Returns `KeystoreError::MacMismatch` when the password is wrong.
+    pub fn from_keystore(json: &str, password: &str) -> Result<PrivateKey, Error> {
+        Keystore::from_json(json)?.decrypt(password)
+    }
+
+    /// Encrypts the key into the JSON of a V3 keystore file.
+    pub fn to_keystore(&self, password: &str, params: &KeystoreParams) -> Result<String, Error> {
+        Keystore::encrypt(self, password, params)?.to_json()
+    }
+}
+
+/// A keystore file of a [KeystoreDir](struct.KeystoreDir.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeystoreEntry {
+    pub address: Address,
+    pub path: PathBuf,
+}
+
+/// The address of a file named the way geth names keystores.
+fn address_of_file_name(path: &Path) -> Option<Address> {
+    let name = path.file_name()?.to_str()?;
+    match name.rsplit_once("--") {
+        Some((_, address)) if !address.is_empty() => Address::from_str(address).ok(),
+        _ => None,
+    }
+}
+
+/// A directory of keystore files, as geth keeps them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeystoreDir {
+    path: PathBuf,
+    entries: Vec<KeystoreEntry>,
+}
+
+impl KeystoreDir {
+    /// Scans the directory for keystores.
+    ///
+    /// Like geth, hidden files, editor backups ending in `~`, directories
+    /// and files that are not keystores are skipped. The address of a
+    /// keystore is read from the file, and else from its name.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<KeystoreDir, Error> {
+        let mut dir = KeystoreDir {
+            path: path.as_ref().to_path_buf(),
+            entries: Vec::new(),
+        };
+        dir.rescan()?;
+        Ok(dir)
+    }
+
+    /// Scans the directory again, for keystores added or removed since.
+    pub fn rescan(&mut self) -> Result<(), Error> {
+        let mut entries = Vec::new();
+        for file in fs::read_dir(&self.path)? {
+            let path = file?.path();
+            let hidden = match path.file_name().and_then(|name| name.to_str()) {
+                Some(name) => name.starts_with('.') || name.ends_with('~'),
+                None => true,
+            };
+            if hidden || !path.is_file() {
+                continue;
+            }
+            let keystore = match fs::read_to_string(&path)
+                .ok()
+                .and_then(|json| Keystore::from_json(&json).ok())
+            {
+                Some(keystore) => keystore,
+                None => continue,
+            };
+            if let Some(address) = keystore.address().or_else(|| address_of_file_name(&path)) {
+                entries.push(KeystoreEntry { address, path });
+            }
+        }
+        // In the order geth lists them, which is by file name, so by time
+        // of creation
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        self.entries = entries;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[KeystoreEntry] {
+        &self.entries
+    }
+
+    /// The addresses of the keystores, each once.
+    pub fn addresses(&self) -> Vec<Address> {
+        let mut addresses: Vec<Address> = Vec::new();
+        for entry in &self.entries {
+            if !addresses.contains(&entry.address) {
+                addresses.push(entry.address);
+            }
+        }
+        addresses
+    }
+
+    /// Decrypts the key of `address`, making sure it is the key of that
+    /// address. If several files claim the address, the first that the
+    /// password opens is used.
+    pub fn unlock(&self, address: &Address, password: &str) -> Result<PrivateKey, Error> {
+        let mut error: Error = KeystoreError::UnknownAddress(*address).into();
+        for entry in self
+            .entries
+            .iter()
+            .filter(|entry| entry.address == *address)
+        {
+            let result = fs::read_to_string(&entry.path)
+                .map_err(Error::from)
+                .and_then(|json| Keystore::from_json(&json))
+                .and_then(|keystore| keystore.decrypt(password));
+            match result {
+                Ok(key) => {
+                    let holder = key.to_public_key()?;
+                    if holder == *address {
+                        return Ok(key);
+                    }
+                    error = KeystoreError::WrongAddress(*address, holder).into();
+                }
+                Err(e) => error = e,
+            }
+        }
+        Err(error)
+    }
+}
+
+/// The test vector of the Web3 Secret Storage definition.
+#[cfg(test)]
+const TEST_KEYSTORE: &str = r#"{
+    "crypto": {
+        "cipher": "aes-128-ctr",
+        "cipherparams": {"iv": "6087dab2f9fdbbfaddc31a909735c1e6"},
+        "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+        "kdf": "pbkdf2",
+        "kdfparams": {
+            "c": 262144,
+            "dklen": 32,
+            "prf": "hmac-sha256",
+            "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+        },
+        "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+    },
+    "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+    "version": 3
+}"#;
+
+#[test]
+fn decrypt_pbkdf2() {
+    let keystore = Keystore::from_json(TEST_KEYSTORE).unwrap();
+    let key = keystore.decrypt("testpassword").unwrap();
+    assert_eq!(
+        key,
+        "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+            .parse()
+            .unwrap()
+    );
+    assert_eq!(
+        keystore
+            .decrypt("wrong")
+            .unwrap_err()
+            .downcast::<KeystoreError>()
+            .unwrap(),
+        KeystoreError::MacMismatch
+    );
+    assert_eq!(keystore.address(), None);
+}
+
+#[test]
+fn encrypt_keystore() {
+    use std::convert::TryInto;
+    let key: PrivateKey = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+        .parse()
+        .unwrap();
+    // The parameters of the test vector, which give back its ciphertext
+    let params = KeystoreParams {
+        kdf: KeystoreKdf::Pbkdf2 { c: 262144 },
+        salt: hex_str_to_bytes("ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd")
+            .unwrap()[..]
+            .try_into()
+            .unwrap(),
+        iv: hex_str_to_bytes("6087dab2f9fdbbfaddc31a909735c1e6").unwrap()[..]
+            .try_into()
+            .unwrap(),
+    };
+    let keystore = Keystore::encrypt(&key, "testpassword", &params).unwrap();
+    let expected = Keystore::from_json(TEST_KEYSTORE).unwrap();
+    assert_eq!(keystore.crypto, expected.crypto);
+    assert_eq!(keystore.address(), Some(key.to_public_key().unwrap()));
+    assert_eq!(keystore.id.len(), 36);
+    assert_eq!(&keystore.id[14..15], "4");
+
+    let params = KeystoreParams {
+        kdf: KeystoreKdf::Scrypt { n: 16, r: 8, p: 1 },
+        ..params
+    };
+    let json = key.to_keystore("testpassword", &params).unwrap();
+    assert!(json.contains(r#""kdf":"scrypt""#));
+    assert_eq!(
+        PrivateKey::from_keystore(&json, "testpassword").unwrap(),
+        key
+    );
+    assert_eq!(
+        PrivateKey::from_keystore(&json, "wrong")
+            .unwrap_err()
+            .downcast::<KeystoreError>()
+            .unwrap(),
+        KeystoreError::MacMismatch
+    );
+    assert_eq!(
+        PrivateKey::from_keystore(TEST_KEYSTORE, "testpassword").unwrap(),
+        key
+    );
+}
+
+#[test]
+fn keystore_limits() {
+    let keystore = Keystore::from_json(TEST_KEYSTORE).unwrap();
+    assert_eq!(keystore.kdf(), Ok(KeystoreKdf::Pbkdf2 { c: 262144 }));
+    let limits = KeystoreLimits {
+        max_pbkdf2_iterations: 100_000,
+        ..Default::default()
+    };
+    assert_eq!(
+        keystore
+            .decrypt_with_limits("testpassword", &limits)
+            .unwrap_err()
+            .downcast::<KeystoreError>()
+            .unwrap(),
+        KeystoreError::TooCostly(KeystoreKdf::Pbkdf2 { c: 262144 })
+    );
+    keystore
+        .decrypt_with_limits("testpassword", &Default::default())
+        .unwrap();
+    // A derived key of 4 GiB is refused before anything is allocated
+    let mut oversized = keystore.clone();
+    oversized.crypto.kdfparams.dklen = u32::MAX;
+    assert_eq!(oversized.kdf(), Err(KeystoreError::InvalidParams));
+    for decrypted in &[
+        oversized.decrypt_with_limits("testpassword", &Default::default()),
+        oversized.decrypt("testpassword"),
+    ] {
+        match *decrypted {
+            Err(ref e) => assert_eq!(
+                e.downcast_ref::<KeystoreError>(),
+                Some(&KeystoreError::InvalidParams)
+            ),
+            Ok(_) => panic!("decrypted with a dklen of {}", u32::MAX),
+        }
+    }
+
+    let defaults = KeystoreLimits::default();
+    for strength in &["light", "standard", "paranoid"] {
+        let strength: KeystoreStrength = strength.parse().unwrap();
+        for kdf in &[KeystoreKdf::scrypt(strength), KeystoreKdf::pbkdf2(strength)] {
+            kdf.check().unwrap();
+            defaults.check(kdf).unwrap();
+        }
+    }
+    assert_eq!(
+        defaults.check(&KeystoreKdf::Scrypt {
+            n: 1 << 21,
+            r: 8,
+            p: 1
+        }),
+        Err(KeystoreError::TooCostly(KeystoreKdf::Scrypt {
+            n: 1 << 21,
+            r: 8,
+            p: 1
+        }))
+    );
+    assert_eq!(
+        KeystoreKdf::scrypt(KeystoreStrength::Standard).memory(),
+        256 << 20
+    );
+    assert_eq!(
+        "extreme".parse::<KeystoreStrength>(),
+        Err(KeystoreError::UnknownStrength("extreme".to_owned()))
+    );
+
+    let params = KeystoreParams {
+        kdf: KeystoreKdf::Scrypt {
+            n: 1000,
+            r: 8,
+            p: 1,
+        },
+        salt: [1; 32],
+        iv: [2; 16],
+    };
+    let key = PrivateKey::from([0x11; 32]);
+    assert_eq!(
+        Keystore::encrypt(&key, "password", &params)
+            .unwrap_err()
+            .downcast::<KeystoreError>()
+            .unwrap(),
+        KeystoreError::InvalidParams
+    );
+}
+
+#[test]
+fn calibrate_keystore() {
+    // Nothing runs fast enough for no time at all, which leaves the least
+    // cost
+    assert_eq!(
+        KeystoreKdf::calibrate_scrypt(Duration::from_secs(0), &Default::default()),
+        Ok(KeystoreKdf::Scrypt {
+            n: 1 << 12,
+            r: 8,
+            p: 1
+        })
+    );
+    assert_eq!(
+        KeystoreKdf::calibrate_pbkdf2(Duration::from_secs(0), &Default::default()),
+        Ok(KeystoreKdf::Pbkdf2 { c: 1 << 16 })
+    );
+    // However long it may take, the limits hold
+    let limits = KeystoreLimits {
+        max_scrypt_memory: 8 << 20,
+        max_scrypt_parallelization: 1,
+        max_pbkdf2_iterations: 1 << 17,
+    };
+    let kdf = KeystoreKdf::calibrate_scrypt(Duration::from_secs(3600), &limits).unwrap();
+    assert_eq!(
+        kdf,
+        KeystoreKdf::Scrypt {
+            n: 1 << 13,
+            r: 8,
+            p: 1
+        }
+    );
+    assert_eq!(
+        KeystoreKdf::calibrate_pbkdf2(Duration::from_secs(3600), &limits),
+        Ok(KeystoreKdf::Pbkdf2 { c: 1 << 17 })
+    );
+    // Limits below the least cost are not overridden by it
+    let limits = KeystoreLimits {
+        max_scrypt_memory: 1 << 20,
+        max_scrypt_parallelization: 1,
+        max_pbkdf2_iterations: 1 << 10,
+    };
+    assert_eq!(
+        KeystoreKdf::calibrate_scrypt(Duration::from_secs(1), &limits),
+        Err(KeystoreError::TooCostly(KeystoreKdf::Scrypt {
+            n: 1 << 12,
+            r: 8,
+            p: 1
+        }))
+    );
+    assert_eq!(
+        KeystoreKdf::calibrate_pbkdf2(Duration::from_secs(1), &limits),
+        Err(KeystoreError::TooCostly(KeystoreKdf::Pbkdf2 { c: 1 << 16 }))
+    );
+}
+
+#[test]
+fn decrypt_with_closure() {
+    let keystore = Keystore::from_json(TEST_KEYSTORE).unwrap();
+    let address = keystore
+        .decrypt_with("testpassword", |key| key.to_public_key().unwrap())
+        .unwrap();
+    let key = keystore.decrypt("testpassword").unwrap();
+    assert_eq!(address, key.to_public_key().unwrap());
+    assert!(keystore.decrypt_with("wrong", |_| panic!()).is_err());
+}
+
+#[test]
+fn keystore_dir() {
+    use utils::bytes_to_hex_str;
+    let key: PrivateKey = "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+        .parse()
+        .unwrap();
+    let address = key.to_public_key().unwrap();
+    let hex = bytes_to_hex_str(address.as_bytes());
+    let path = std::env::temp_dir().join(format!("clarity-keystore-{}", std::process::id()));
+    fs::create_dir_all(&path).unwrap();
+    // Named like geth names it, without the address inside
+    let name = format!("UTC--2016-03-22T12-57-55.920751759Z--{}", hex);
+    fs::write(path.join(&name), TEST_KEYSTORE).unwrap();
+    // With the address inside, under another name
+    let mut keystore = Keystore::from_json(TEST_KEYSTORE).unwrap();
+    keystore.address = Some(hex.clone());
+    fs::write(path.join("copy"), serde_json::to_string(&keystore).unwrap()).unwrap();
+    // Claiming an address it does not hold the key of
+    let other = Address::from([0x11u8; 20]);
+    keystore.address = Some(bytes_to_hex_str(other.as_bytes()));
+    fs::write(
+        path.join("wrong"),
+        serde_json::to_string(&keystore).unwrap(),
+    )
+    .unwrap();
+    for skipped in &[".hidden", "backup~", "notes.txt"] {
+        fs::write(path.join(skipped), TEST_KEYSTORE).unwrap();
+    }
+    fs::write(path.join("notes.txt"), "not a keystore").unwrap();
+
+    let dir = KeystoreDir::open(&path).unwrap();
+    let files: Vec<&Path> = dir
+        .entries()
+        .iter()
+        .map(|entry| entry.path.as_path())
+        .collect();
+    assert_eq!(
+        files,
+        vec![path.join(&name), path.join("copy"), path.join("wrong")]
+    );
+    assert_eq!(dir.addresses(), vec![address, other]);
+    assert_eq!(dir.unlock(&address, "testpassword").unwrap(), key);
+    let error = |address: &Address| {
+        dir.unlock(address, "testpassword")
+            .unwrap_err()
+            .downcast::<KeystoreError>()
+            .unwrap()
+    };
+    assert_eq!(error(&other), KeystoreError::WrongAddress(other, address));
+    let unknown = Address::from([0x22u8; 20]);
+    assert_eq!(error(&unknown), KeystoreError::UnknownAddress(unknown));
+    fs::remove_dir_all(&path).unwrap();
+}
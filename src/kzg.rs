@@ -0,0 +1,118 @@
+//! KZG proofs of EIP-4844 blobs.
+//!
+//! This module is only available with the `kzg` feature. A blob is a
+//! polynomial over the BLS12-381 scalar field, committed to by a point of
+//! G1. A KZG proof shows the polynomial takes the value `y` at `z`, which is
+//! what the point evaluation precompile checks and what rollups prove their
+//! data with.
+//!
+//! Verification needs a single point of the trusted setup, `[τ]G2`, which is
+//! the second G2 point of the setup file. It is taken as a parameter rather
+//! than bundled with the crate.
+use bls12_381::{pairing, G1Affine, G2Affine, G2Projective, Scalar};
+use failure::Error;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum KzgError {
+    #[fail(display = "Invalid KZG commitment")]
+    InvalidCommitment,
+    #[fail(display = "Invalid KZG proof")]
+    InvalidProof,
+    #[fail(display = "Field element is not below the BLS12-381 modulus")]
+    InvalidFieldElement,
+    #[fail(display = "Invalid trusted setup point")]
+    InvalidSetup,
+}
+
+/// Decodes a big endian field element, as blobs and the precompile encode
+/// them.
+fn field_element(bytes: &[u8; 32]) -> Result<Scalar, Error> {
+    let mut le = *bytes;
+    le.reverse();
+    let scalar: Option<Scalar> = Scalar::from_bytes(&le).into();
+    Ok(scalar.ok_or(KzgError::InvalidFieldElement)?)
+}
+
+fn g1_point(bytes: &[u8; 48], error: KzgError) -> Result<G1Affine, Error> {
+    let point: Option<G1Affine> = G1Affine::from_compressed(bytes).into();
+    Ok(point.ok_or(error)?)
+}
+
+/// The part of the trusted setup proofs are verified with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KzgSetup {
+    tau_g2: G2Affine,
+}
+
+impl KzgSetup {
+    /// Takes `[τ]G2` in compressed form.
+    pub fn new(tau_g2: &[u8; 96]) -> Result<KzgSetup, Error> {
+        let point: Option<G2Affine> = G2Affine::from_compressed(tau_g2).into();
+        Ok(KzgSetup {
+            tau_g2: point.ok_or(KzgError::InvalidSetup)?,
+        })
+    }
+
+    /// Verifies that the polynomial committed to by `commitment` takes the
+    /// value `y` at `z`, where `z` and `y` are big endian field elements.
+    ///
+    /// Malformed inputs are errors, a well formed proof that does not hold
+    /// is `Ok(false)`.
+    pub fn verify_proof(
+        &self,
+        commitment: &[u8; 48],
+        z: &[u8; 32],
+        y: &[u8; 32],
+        proof: &[u8; 48],
+    ) -> Result<bool, Error> {
+        let commitment = g1_point(commitment, KzgError::InvalidCommitment)?;
+        let proof = g1_point(proof, KzgError::InvalidProof)?;
+        let z = field_element(z)?;
+        let y = field_element(y)?;
+        // e(proof, [τ - z]G2) == e(commitment - [y]G1, G2)
+        let x_minus_z = G2Affine::from(self.tau_g2 - G2Projective::generator() * z);
+        let p_minus_y = G1Affine::from(commitment - G1Affine::generator() * y);
+        Ok(pairing(&proof, &x_minus_z) == pairing(&p_minus_y, &G2Affine::generator()))
+    }
+}
+
+#[cfg(test)]
+fn field_bytes(scalar: &Scalar) -> [u8; 32] {
+    let mut bytes = scalar.to_bytes();
+    bytes.reverse();
+    bytes
+}
+
+#[test]
+fn verify_linear_polynomial() {
+    // A made up setup, with τ known, and the polynomial p(x) = 3 + 5x,
+    // whose quotient (p(x) - p(z)) / (x - z) is the constant 5
+    let tau = Scalar::from(123_456_789u64);
+    let setup =
+        KzgSetup::new(&G2Affine::from(G2Affine::generator() * tau).to_compressed()).unwrap();
+    let (a, b) = (Scalar::from(3u64), Scalar::from(5u64));
+    let commitment = G1Affine::from(G1Affine::generator() * (a + b * tau)).to_compressed();
+    let proof = G1Affine::from(G1Affine::generator() * b).to_compressed();
+    let z = Scalar::from(42u64);
+    let y = a + b * z;
+    assert!(setup
+        .verify_proof(&commitment, &field_bytes(&z), &field_bytes(&y), &proof)
+        .unwrap());
+    assert!(!setup
+        .verify_proof(
+            &commitment,
+            &field_bytes(&z),
+            &field_bytes(&(y + Scalar::one())),
+            &proof
+        )
+        .unwrap());
+    assert_eq!(
+        setup
+            .verify_proof(&commitment, &[0xffu8; 32], &field_bytes(&y), &proof)
+            .unwrap_err()
+            .downcast::<KzgError>()
+            .unwrap(),
+        KzgError::InvalidFieldElement
+    );
+    assert!(KzgSetup::new(&[0u8; 96]).is_err());
+}
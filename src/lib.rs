@@ -3,9 +3,19 @@
 //!
 //! ## Features
 //! * Any-endian, 32/64-bit support
-//! * Public/private key handling
+//! * Public/private key handling, with SEC1 compressed and uncompressed public keys
 //! * Transaction signing and verification
 //! * ABI enconding for common data types (see `abi::Token` variants)
+//! * Strict ABI coding that names the rule of the specification an encoding breaks (`strict` feature)
+//! * EIP-712 typed data hashing and signing (see `eip712`)
+//! * `#[derive(AbiToken)]` for structs as Solidity tuples, and `#[derive(EthEvent)]` for typed logs (`derive` feature)
+//! * Private key generation (`keygen` feature)
+//! * Random ABI values for property-based testing (`random` feature)
+//! * Beacon chain deposit calldata (see `staking`)
+//! * BIP-32 key derivation from a seed, and watch-only derivation from an xpub (`bip32` feature)
+//! * V3 keystores and geth keystore directories (`keystore` feature)
+//! * BLS12-381 validator keys, keystores and deposit signatures (`bls` feature)
+//! * `tracing` spans around signing, sender recovery and ABI coding (`instrument` feature)
 //!
 //! ## Getting started
 //! Here's an example lifetime of an Alice-to-Bob Ethereum transaction made with Clarity:
@@ -60,7 +70,7 @@
 
 extern crate num_bigint;
 extern crate num_traits;
-extern crate serde;
+extern crate serde as serde_crate;
 extern crate serde_bytes;
 extern crate serde_rlp;
 #[macro_use]
@@ -69,27 +79,124 @@ extern crate secp256k1;
 extern crate sha3;
 #[macro_use]
 extern crate lazy_static;
+#[cfg(feature = "instrument")]
+#[macro_use]
+extern crate tracing;
 #[macro_use]
 extern crate serde_derive;
+#[cfg(any(feature = "bls", feature = "keystore"))]
+extern crate aes;
+#[cfg(any(feature = "bls", feature = "kzg"))]
+extern crate bls12_381;
 extern crate bytecount;
+#[cfg(feature = "derive")]
+extern crate clarity_derive;
+#[cfg(feature = "bls")]
+extern crate hkdf;
+#[cfg(any(feature = "bip32", feature = "bls", feature = "keystore"))]
+extern crate hmac;
 extern crate num256;
+#[cfg(any(feature = "bls", feature = "keystore"))]
+extern crate pbkdf2;
+#[cfg(any(feature = "keygen", feature = "random"))]
+extern crate rand;
+#[cfg(any(feature = "bls", feature = "keystore"))]
+extern crate scrypt;
+#[cfg(feature = "serde_json")]
+extern crate serde_json;
+extern crate sha2;
+#[cfg(any(feature = "bls", feature = "ens"))]
+extern crate unicode_normalization;
 
 pub mod abi;
+#[cfg(feature = "strict")]
+pub mod abi_strict;
 pub mod address;
+pub mod arbitrum;
+pub mod backfill;
+#[cfg(feature = "bip32")]
+pub mod bip32;
+pub mod block_file;
+#[cfg(feature = "bls")]
+pub mod bls;
+pub mod calldata;
+pub mod chain;
+pub mod config;
 pub mod constants;
 mod context;
+pub mod corpus;
+#[cfg(feature = "devnode")]
+pub mod devnode;
+pub mod eip681;
+pub mod eip712;
+#[cfg(feature = "ens")]
+pub mod ens;
+#[cfg(feature = "era1")]
+pub mod era1;
+pub mod erc1271;
+pub mod erc20;
+pub mod erc4337;
 pub mod error;
+pub mod event;
+pub mod explain;
+pub mod hash;
+pub mod hd_path;
+pub mod immutables;
+pub mod inspect;
+mod instrument;
+pub mod journal;
+#[cfg(feature = "keystore")]
+pub mod keystore;
+#[cfg(feature = "kzg")]
+pub mod kzg;
+pub mod message_record;
+pub mod multicall;
+pub mod multisig;
+pub mod nonce_audit;
 pub mod opcodes;
+pub mod optimism;
 pub mod private_key;
-mod rlp;
+pub mod public_key;
+#[cfg(feature = "random")]
+pub mod random_tokens;
+pub mod rebroadcast;
+pub mod revert;
+pub mod risk;
+pub mod rlp;
+pub mod rlp_serde;
+pub mod rpc_view;
+#[cfg(feature = "devnode")]
+pub mod scenario;
+pub mod screening;
+pub mod serde;
 mod signature;
+mod ssz;
+pub mod staking;
+pub mod storage_diff;
+pub mod storage_layout;
+pub mod template;
+#[cfg(feature = "test-keys")]
+pub mod test_accounts;
+pub mod timelock;
+pub mod trace;
 pub mod transaction;
+pub mod tx_audit;
+pub mod tx_store;
 pub mod types;
 pub mod utils;
+pub mod versioned;
+pub mod zksync;
 
 pub use address::Address;
+pub use config::ClarityConfig;
 pub use error::ClarityError;
 pub use private_key::PrivateKey;
+pub use public_key::PublicKey;
 pub use signature::Signature;
-pub use transaction::Transaction;
+pub use transaction::{
+    blob_versioned_hash, AccessList, AccessListItem, Authorization, Eip1559Transaction,
+    Eip2930Transaction, Eip4844Transaction, Eip7702Transaction, SignatureRules,
+    SignedAuthorization, SignedTx, Transaction, TransactionBuilder, TypedTransaction,
+    ValidityIssue, ValidityReport,
+};
 pub use types::BigEndianInt;
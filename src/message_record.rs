@@ -0,0 +1,327 @@
+//! Audit records of signed messages.
+//!
+//! A signature over a message is only evidence of what was agreed to if
+//! what was hashed can be shown later: the bytes of a `personal_sign`
+//! message with its prefix, or the typed data of an EIP-712 signature.
+//! [SignedMessageRecord](struct.SignedMessageRecord.html) keeps the message,
+//! the exact bytes that were hashed, the digest, the signature and the
+//! signer, and (de)serializes to JSON for storage. Its
+//! [verify](struct.SignedMessageRecord.html#method.verify) recomputes every
+//! step, so a record that was tampered with, or one that never matched, is
+//! found out in a later review:
+//!
+//! ```rust
+//! use clarity::message_record::{SignedMessage, SignedMessageRecord};
+//! use clarity::PrivateKey;
+//!
+//! let key: PrivateKey = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f1e"
+//!     .parse()
+//!     .unwrap();
+//! let message = SignedMessage::Personal {
+//!     data: b"I approve payout batch 42".to_vec(),
+//! };
+//! let record = SignedMessageRecord::sign(&key, message).unwrap();
+//! record.verify().unwrap();
+//! ```
+//!
+//! With the `serde_json` feature (which `keystore` turns on), `verify` also
+//! reads the typed data from the recorded JSON and checks the recorded
+//! hashes against it. Without it, the hashes are checked against typed data
+//! the caller read from the JSON by
+//! [verify_typed_data](struct.SignedMessageRecord.html#method.verify_typed_data).
+use address::Address;
+use eip712::TypedData;
+use failure::Error;
+use hash::keccak256;
+use private_key::PrivateKey;
+use serde::{hex_bytes, hex_bytes32};
+use signature::Signature;
+
+/// The prefix of `personal_sign` (and `eth_sign`) messages, which is
+/// followed by the length of the message in decimal.
+pub const PERSONAL_MESSAGE_PREFIX: &str = "\x19Ethereum Signed Message:\n";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum MessageRecordError {
+    #[fail(display = "Recorded bytes are not the encoding of the message")]
+    HashedBytesMismatch,
+    #[fail(display = "Recorded digest is not the hash of the message")]
+    DigestMismatch,
+    #[fail(display = "Recorded hashes are not those of the typed data")]
+    TypedDataMismatch,
+    #[fail(display = "Signature is by {}, not by {}", recovered, recorded)]
+    SignerMismatch {
+        recorded: Address,
+        recovered: Address,
+    },
+}
+
+/// A message as it was signed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SignedMessage {
+    /// Bytes hashed as they are, as
+    /// [sign_msg](../private_key/struct.PrivateKey.html#method.sign_msg)
+    /// signs them
+    Raw {
+        #[serde(with = "hex_bytes")]
+        data: Vec<u8>,
+    },
+    /// A `personal_sign` message, hashed after the prefix and its length
+    Personal {
+        #[serde(with = "hex_bytes")]
+        data: Vec<u8>,
+    },
+    /// EIP-712 typed data, hashed as `0x1901`, the domain separator and
+    /// the hash of the message struct. The typed data is kept as the JSON
+    /// that was presented for signing.
+    TypedData {
+        json: String,
+        #[serde(with = "hex_bytes32")]
+        domain_separator: [u8; 32],
+        #[serde(with = "hex_bytes32")]
+        struct_hash: [u8; 32],
+    },
+}
+
+impl SignedMessage {
+    /// Hashes `typed_data`, which was read from `json`, the JSON presented
+    /// for signing.
+    pub fn typed_data(json: &str, typed_data: &TypedData) -> Result<SignedMessage, Error> {
+        Ok(SignedMessage::TypedData {
+            json: json.to_owned(),
+            domain_separator: typed_data.domain_separator()?,
+            struct_hash: typed_data.struct_hash()?,
+        })
+    }
+
+    /// The bytes that are hashed into the digest.
+    pub fn hashed_bytes(&self) -> Vec<u8> {
+        match *self {
+            SignedMessage::Raw { ref data } => data.clone(),
+            SignedMessage::Personal { ref data } => {
+                let mut bytes = format!("{}{}", PERSONAL_MESSAGE_PREFIX, data.len()).into_bytes();
+                bytes.extend_from_slice(data);
+                bytes
+            }
+            SignedMessage::TypedData {
+                ref domain_separator,
+                ref struct_hash,
+                ..
+            } => {
+                let mut bytes = vec![0x19, 0x01];
+                bytes.extend_from_slice(domain_separator);
+                bytes.extend_from_slice(struct_hash);
+                bytes
+            }
+        }
+    }
+
+    /// The Keccak-256 digest that is signed.
+    pub fn digest(&self) -> [u8; 32] {
+        keccak256(&self.hashed_bytes())
+    }
+}
+
+/// What was signed, how, and by whom.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedMessageRecord {
+    pub message: SignedMessage,
+    /// The exact bytes that were hashed
+    #[serde(with = "hex_bytes")]
+    pub hashed: Vec<u8>,
+    #[serde(with = "hex_bytes32")]
+    pub digest: [u8; 32],
+    pub signature: Signature,
+    pub signer: Address,
+}
+
+impl SignedMessageRecord {
+    /// Signs `message` with `key` and records it.
+    pub fn sign(key: &PrivateKey, message: SignedMessage) -> Result<SignedMessageRecord, Error> {
+        let hashed = message.hashed_bytes();
+        let digest = message.digest();
+        Ok(SignedMessageRecord {
+            signature: key.sign_hash(&digest),
+            signer: key.to_public_key()?,
+            message,
+            hashed,
+            digest,
+        })
+    }
+
+    /// Recomputes the hashed bytes and the digest from the message, and the
+    /// signer from the signature, and checks them against the record.
+    ///
+    /// The hashes of typed data are recomputed from the recorded JSON with
+    /// the `serde_json` feature, and taken as recorded without it; see
+    /// [verify_typed_data](#method.verify_typed_data) to check them then.
+    pub fn verify(&self) -> Result<(), Error> {
+        if self.message.hashed_bytes() != self.hashed {
+            return Err(MessageRecordError::HashedBytesMismatch.into());
+        }
+        if self.message.digest() != self.digest {
+            return Err(MessageRecordError::DigestMismatch.into());
+        }
+        #[cfg(feature = "serde_json")]
+        {
+            if let SignedMessage::TypedData { ref json, .. } = self.message {
+                let typed_data: TypedData = ::serde_json::from_str(json)?;
+                if SignedMessage::typed_data(json, &typed_data)? != self.message {
+                    return Err(MessageRecordError::TypedDataMismatch.into());
+                }
+            }
+        }
+        let recovered = self.signature.recover(&self.digest)?;
+        if recovered != self.signer {
+            return Err(MessageRecordError::SignerMismatch {
+                recorded: self.signer,
+                recovered,
+            }
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Checks the recorded hashes of typed data against `typed_data`, read
+    /// from the recorded JSON, then verifies the record.
+    pub fn verify_typed_data(&self, typed_data: &TypedData) -> Result<(), Error> {
+        let json = match self.message {
+            SignedMessage::TypedData { ref json, .. } => json,
+            _ => return Err(MessageRecordError::TypedDataMismatch.into()),
+        };
+        if SignedMessage::typed_data(json, typed_data)? != self.message {
+            return Err(MessageRecordError::TypedDataMismatch.into());
+        }
+        self.verify()
+    }
+}
+
+#[test]
+fn personal_message_digest() {
+    use utils::bytes_to_hex_str;
+    // As geth and ethers hash `personal_sign("hello")`
+    let message = SignedMessage::Personal {
+        data: b"hello".to_vec(),
+    };
+    assert_eq!(
+        message.hashed_bytes(),
+        b"\x19Ethereum Signed Message:\n5hello"
+    );
+    assert_eq!(
+        bytes_to_hex_str(&message.digest()),
+        "50b2c43fd39106bafbba0da34fc430e1f91e3c96ea2acee2bc34119f92b37750"
+    );
+}
+
+#[test]
+fn verify_records() {
+    let key = PrivateKey::from([0x42u8; 32]);
+    let raw = SignedMessageRecord::sign(
+        &key,
+        SignedMessage::Raw {
+            data: b"Hello, world!".to_vec(),
+        },
+    )
+    .unwrap();
+    raw.verify().unwrap();
+    assert_eq!(raw.signature, key.sign_msg(b"Hello, world!"));
+
+    let json = r#"{
+        "types": {"Greeting": [{"name": "text", "type": "string"}]},
+        "domain": {"name": "Greeter", "chainId": 1},
+        "primaryType": "Greeting",
+        "message": {"text": "Hello"}
+    }"#;
+    let typed_data: TypedData = serde_json::from_str(json).unwrap();
+    let typed =
+        SignedMessageRecord::sign(&key, SignedMessage::typed_data(json, &typed_data).unwrap())
+            .unwrap();
+    assert_eq!(typed.digest, typed_data.signing_hash().unwrap());
+    let record = serde_json::to_string(&typed).unwrap();
+    assert!(record.contains(r#""kind":"typed_data""#));
+    let stored: SignedMessageRecord = serde_json::from_str(&record).unwrap();
+    assert_eq!(stored, typed);
+    stored.verify_typed_data(&typed_data).unwrap();
+
+    let error = |record: &SignedMessageRecord| {
+        record
+            .verify()
+            .unwrap_err()
+            .downcast::<MessageRecordError>()
+            .unwrap()
+    };
+    // The message edited after the fact
+    let mut edited = typed.clone();
+    if let SignedMessage::TypedData {
+        ref mut struct_hash,
+        ..
+    } = edited.message
+    {
+        struct_hash[0] ^= 1;
+    }
+    assert_eq!(error(&edited), MessageRecordError::HashedBytesMismatch);
+    // Hashes of other typed data than the JSON holds
+    let goodbye: TypedData = serde_json::from_str(&json.replace("Hello", "Goodbye")).unwrap();
+    assert_eq!(
+        typed
+            .verify_typed_data(&goodbye)
+            .unwrap_err()
+            .downcast::<MessageRecordError>()
+            .unwrap(),
+        MessageRecordError::TypedDataMismatch
+    );
+    assert!(raw.verify_typed_data(&typed_data).is_err());
+    let mut edited = raw.clone();
+    edited.hashed.push(0);
+    assert_eq!(error(&edited), MessageRecordError::HashedBytesMismatch);
+    let mut edited = raw.clone();
+    edited.digest[0] ^= 1;
+    assert_eq!(error(&edited), MessageRecordError::DigestMismatch);
+    let mut edited = raw.clone();
+    edited.signer = Address::from([0x11u8; 20]);
+    assert_eq!(
+        error(&edited),
+        MessageRecordError::SignerMismatch {
+            recorded: Address::from([0x11u8; 20]),
+            recovered: raw.signer,
+        }
+    );
+}
+
+#[cfg(feature = "serde_json")]
+#[test]
+fn verify_recorded_json() {
+    let key = PrivateKey::from([0x42u8; 32]);
+    let json = r#"{
+        "types": {"Greeting": [{"name": "text", "type": "string"}]},
+        "domain": {"name": "Greeter", "chainId": 1},
+        "primaryType": "Greeting",
+        "message": {"text": "Hello"}
+    }"#;
+    let typed_data: TypedData = serde_json::from_str(json).unwrap();
+    let record =
+        SignedMessageRecord::sign(&key, SignedMessage::typed_data(json, &typed_data).unwrap())
+            .unwrap();
+    record.verify().unwrap();
+
+    // JSON other than that which was hashed, with the hashes, digest and
+    // signature left as they were
+    let mut edited = record.clone();
+    if let SignedMessage::TypedData { ref mut json, .. } = edited.message {
+        *json = json.replace("Hello", "Goodbye");
+    }
+    for error in &[
+        edited.verify().unwrap_err(),
+        edited.verify_typed_data(&typed_data).unwrap_err(),
+    ] {
+        assert_eq!(
+            error.downcast_ref::<MessageRecordError>(),
+            Some(&MessageRecordError::TypedDataMismatch)
+        );
+    }
+    if let SignedMessage::TypedData { ref mut json, .. } = edited.message {
+        *json = "not json".to_owned();
+    }
+    assert!(edited.verify().is_err());
+}
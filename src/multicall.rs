@@ -0,0 +1,164 @@
+//! Batching contract reads with Multicall3.
+//!
+//! Multicall3 is deployed at the same address on most chains, and lets many
+//! calls be made with a single `eth_call`. Its arguments and results are
+//! arrays of tuples.
+use abi::{decode_tokens, encode_call, ParamType, Token};
+use address::Address;
+use failure::Error;
+
+/// Signature of the Multicall3 `aggregate3` function.
+pub const AGGREGATE3: &str = "aggregate3((address,bool,bytes)[])";
+
+lazy_static! {
+    /// Address Multicall3 is deployed at on most chains.
+    pub static ref MULTICALL3_ADDRESS: Address =
+        "0xcA11bde05977b3631167028862bE2a173976CA11".parse().unwrap();
+}
+
+/// A single call in a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Call3 {
+    pub target: Address,
+    /// Whether the whole batch should still succeed if this call reverts
+    pub allow_failure: bool,
+    pub call_data: Vec<u8>,
+}
+
+/// Result of a single call in a batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallResult {
+    pub success: bool,
+    pub return_data: Vec<u8>,
+}
+
+/// Calldata of an `aggregate3` call that makes all `calls`.
+pub fn aggregate3(calls: &[Call3]) -> Vec<u8> {
+    let calls = calls
+        .iter()
+        .map(|call| {
+            Token::Tuple(vec![
+                call.target.into(),
+                call.allow_failure.into(),
+                Token::UnboundedBytes(call.call_data.clone()),
+            ])
+        })
+        .collect();
+    encode_call(AGGREGATE3, &[Token::Dynamic(calls)])
+}
+
+/// Decodes the `(bool,bytes)[]` returned by `aggregate3`.
+pub fn decode_aggregate3(return_data: &[u8]) -> Result<Vec<CallResult>, Error> {
+    let result = ParamType::Tuple(vec![ParamType::Bool, ParamType::Bytes]);
+    let results = match decode_tokens(&[ParamType::Array(Box::new(result))], return_data)?.pop() {
+        Some(Token::Dynamic(results)) => results,
+        _ => unreachable!(),
+    };
+    Ok(results
+        .into_iter()
+        .map(|result| match result {
+            Token::Tuple(ref members) => match (&members[0], &members[1]) {
+                (Token::Bool(success), Token::UnboundedBytes(return_data)) => CallResult {
+                    success: *success,
+                    return_data: return_data.clone(),
+                },
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        })
+        .collect())
+}
+
+#[test]
+fn encode_aggregate3() {
+    use utils::{bytes_to_hex_str, hex_str_to_bytes};
+    // Two calls, the first one allowed to fail
+    let calls = vec![
+        Call3 {
+            target: "0x1111111111111111111111111111111111111111"
+                .parse()
+                .unwrap(),
+            allow_failure: true,
+            call_data: hex_str_to_bytes("06fdde03").unwrap(),
+        },
+        Call3 {
+            target: "0x2222222222222222222222222222222222222222"
+                .parse()
+                .unwrap(),
+            allow_failure: false,
+            call_data: hex_str_to_bytes("313ce567").unwrap(),
+        },
+    ];
+    assert_eq!(
+        bytes_to_hex_str(&aggregate3(&calls)),
+        concat!(
+            "82ad56cb",
+            "0000000000000000000000000000000000000000000000000000000000000020",
+            "0000000000000000000000000000000000000000000000000000000000000002",
+            "0000000000000000000000000000000000000000000000000000000000000040",
+            "00000000000000000000000000000000000000000000000000000000000000e0",
+            "0000000000000000000000001111111111111111111111111111111111111111",
+            "0000000000000000000000000000000000000000000000000000000000000001",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000004",
+            "06fdde0300000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000002222222222222222222222222222222222222222",
+            "0000000000000000000000000000000000000000000000000000000000000000",
+            "0000000000000000000000000000000000000000000000000000000000000060",
+            "0000000000000000000000000000000000000000000000000000000000000004",
+            "313ce56700000000000000000000000000000000000000000000000000000000",
+        )
+    );
+}
+
+#[test]
+fn encode_empty_call_data() {
+    let data = aggregate3(&[Call3 {
+        target: Address::default(),
+        allow_failure: false,
+        call_data: Vec::new(),
+    }]);
+    // Selector, offset, length, tuple offset, 3 head words and the length
+    assert_eq!(data.len(), 4 + 32 * 7);
+}
+
+#[test]
+fn decode_aggregate3_results() {
+    use abi::encode_tokens;
+    let results = vec![
+        CallResult {
+            success: true,
+            return_data: encode_tokens(&[18u8.into()]),
+        },
+        CallResult {
+            success: false,
+            return_data: Vec::new(),
+        },
+    ];
+    let return_data = encode_aggregate3_result(
+        &results
+            .iter()
+            .map(|result| (result.success, result.return_data.clone()))
+            .collect::<Vec<_>>(),
+    );
+    assert_eq!(decode_aggregate3(&return_data).unwrap(), results);
+
+    assert!(decode_aggregate3(&return_data[..return_data.len() - 32]).is_err());
+    assert!(decode_aggregate3(&[]).is_err());
+}
+
+/// Encodes `aggregate3` return data, to test the decoders built on it.
+#[cfg(test)]
+pub(crate) fn encode_aggregate3_result(results: &[(bool, Vec<u8>)]) -> Vec<u8> {
+    use abi::encode_tokens;
+    let results = results
+        .iter()
+        .map(|&(success, ref return_data)| {
+            Token::Tuple(vec![
+                success.into(),
+                Token::UnboundedBytes(return_data.clone()),
+            ])
+        })
+        .collect();
+    encode_tokens(&[Token::Dynamic(results)])
+}
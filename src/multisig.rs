@@ -0,0 +1,257 @@
+//! Collecting the signatures of several owners over one digest.
+//!
+//! Multisig wallets such as Gnosis Safe execute a transaction once enough
+//! of their owners have signed its hash. The signatures are gathered off
+//! chain, from signers that may send the same signature twice or sign with
+//! a key that is not an owner. [SignatureSet](struct.SignatureSet.html)
+//! keeps one signature per signer, checks them against the owners and the
+//! threshold, and packs them the way the wallet takes them:
+//!
+//! ```rust
+//! use clarity::multisig::SignatureSet;
+//! use clarity::PrivateKey;
+//!
+//! let digest = [0x42u8; 32];
+//! let keys: Vec<PrivateKey> = (1..4u8).map(|i| PrivateKey::from([i + 1; 32])).collect();
+//! let owners: Vec<_> = keys.iter().map(|key| key.to_public_key().unwrap()).collect();
+//!
+//! let mut set = SignatureSet::new(digest);
+//! set.add(keys[0].sign_hash(&digest)).unwrap();
+//! set.add(keys[2].sign_hash(&digest)).unwrap();
+//! set.check_threshold(&owners, 2).unwrap();
+//! let signatures = set.to_safe_bytes();
+//! assert_eq!(signatures.len(), 2 * 65);
+//! ```
+use address::Address;
+use failure::Error;
+use signature::Signature;
+use std::collections::BTreeMap;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum MultisigError {
+    #[fail(display = "{} is not an owner", _0)]
+    NotOwner(Address),
+    #[fail(
+        display = "{} owners signed, but the threshold is {}",
+        signed, threshold
+    )]
+    BelowThreshold { signed: usize, threshold: usize },
+    #[fail(
+        display = "Signature set of {} bytes is not a whole number of entries",
+        _0
+    )]
+    InvalidLength(usize),
+    #[fail(display = "Signature set is not sorted by signer")]
+    NotSorted,
+    #[fail(display = "Signature of {} is by {}", _0, _1)]
+    WrongSigner(Address, Address),
+}
+
+/// Signatures over one digest, at most one per signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignatureSet {
+    digest: [u8; 32],
+    signatures: BTreeMap<Address, Signature>,
+}
+
+/// The length of an entry of the sorted format: a signer and its signature.
+const ENTRY_LEN: usize = 20 + 65;
+
+impl SignatureSet {
+    pub fn new(digest: [u8; 32]) -> SignatureSet {
+        SignatureSet {
+            digest,
+            signatures: BTreeMap::new(),
+        }
+    }
+
+    pub fn digest(&self) -> &[u8; 32] {
+        &self.digest
+    }
+
+    /// Adds a signature, returning its signer. A second signature of the
+    /// same signer is ignored, the first one being kept.
+    ///
+    /// Fails if no signer can be recovered from the signature.
+    pub fn add(&mut self, signature: Signature) -> Result<Address, Error> {
+        let signer = signature.recover(&self.digest)?;
+        self.signatures.entry(signer).or_insert(signature);
+        Ok(signer)
+    }
+
+    pub fn len(&self) -> usize {
+        self.signatures.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signatures.is_empty()
+    }
+
+    /// The signers, in ascending order.
+    pub fn signers(&self) -> Vec<Address> {
+        self.signatures.keys().cloned().collect()
+    }
+
+    pub fn signature(&self, signer: &Address) -> Option<&Signature> {
+        self.signatures.get(signer)
+    }
+
+    /// Checks that at least `threshold` of `owners` signed, returning the
+    /// owners that did. Signatures of others are ignored, see
+    /// [check_owners](#method.check_owners) to refuse them.
+    pub fn check_threshold(
+        &self,
+        owners: &[Address],
+        threshold: usize,
+    ) -> Result<Vec<Address>, Error> {
+        let signed: Vec<Address> = self
+            .signatures
+            .keys()
+            .filter(|signer| owners.contains(signer))
+            .cloned()
+            .collect();
+        if signed.len() < threshold {
+            return Err(MultisigError::BelowThreshold {
+                signed: signed.len(),
+                threshold,
+            }
+            .into());
+        }
+        Ok(signed)
+    }
+
+    /// Checks that every signer is one of `owners`, as the Safe contract
+    /// requires of the signatures it is given.
+    pub fn check_owners(&self, owners: &[Address]) -> Result<(), Error> {
+        match self
+            .signatures
+            .keys()
+            .find(|signer| !owners.contains(signer))
+        {
+            Some(signer) => Err(MultisigError::NotOwner(*signer).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Removes the signatures of signers that are not `owners`.
+    pub fn retain_owners(&mut self, owners: &[Address]) {
+        self.signatures.retain(|signer, _| owners.contains(signer));
+    }
+
+    /// The signatures as Gnosis Safe's `checkSignatures` takes them: `r`,
+    /// `s` and `v` of each, ordered by ascending signer.
+    pub fn to_safe_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.signatures.len() * 65);
+        for signature in self.signatures.values() {
+            bytes.extend_from_slice(&signature.to_bytes());
+        }
+        bytes
+    }
+
+    /// The signatures with their signers, each signer followed by its
+    /// signature and ordered by ascending signer, for passing the set
+    /// between the parties that gather it.
+    pub fn to_sorted_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.signatures.len() * ENTRY_LEN);
+        for (signer, signature) in &self.signatures {
+            bytes.extend_from_slice(signer.as_bytes());
+            bytes.extend_from_slice(&signature.to_bytes());
+        }
+        bytes
+    }
+
+    /// Reads a set written by [to_sorted_bytes](#method.to_sorted_bytes),
+    /// checking every signature against its signer.
+    pub fn from_sorted_bytes(digest: [u8; 32], bytes: &[u8]) -> Result<SignatureSet, Error> {
+        if !bytes.len().is_multiple_of(ENTRY_LEN) {
+            return Err(MultisigError::InvalidLength(bytes.len()).into());
+        }
+        let mut set = SignatureSet::new(digest);
+        let mut previous: Option<Address> = None;
+        for entry in bytes.chunks(ENTRY_LEN) {
+            let signer = Address::from_slice(&entry[..20])?;
+            if previous.is_some_and(|previous| previous >= signer) {
+                return Err(MultisigError::NotSorted.into());
+            }
+            let recovered = set.add(Signature::from_bytes(&entry[20..])?)?;
+            if recovered != signer {
+                return Err(MultisigError::WrongSigner(signer, recovered).into());
+            }
+            previous = Some(signer);
+        }
+        Ok(set)
+    }
+}
+
+#[test]
+fn gather_signatures() {
+    use private_key::PrivateKey;
+    let digest = [0x42u8; 32];
+    let keys: Vec<PrivateKey> = (1..5u8).map(|i| PrivateKey::from([i + 1; 32])).collect();
+    let owners: Vec<Address> = keys[..3]
+        .iter()
+        .map(|key| key.to_public_key().unwrap())
+        .collect();
+    let outsider = keys[3].to_public_key().unwrap();
+
+    let mut set = SignatureSet::new(digest);
+    assert!(set.is_empty());
+    assert_eq!(set.add(keys[2].sign_hash(&digest)).unwrap(), owners[2]);
+    // Sent twice
+    set.add(keys[2].sign_hash(&digest)).unwrap();
+    set.add(keys[3].sign_hash(&digest)).unwrap();
+    assert_eq!(set.len(), 2);
+    assert_eq!(
+        set.check_threshold(&owners, 2)
+            .unwrap_err()
+            .downcast::<MultisigError>()
+            .unwrap(),
+        MultisigError::BelowThreshold {
+            signed: 1,
+            threshold: 2,
+        }
+    );
+    set.add(keys[0].sign_hash(&digest)).unwrap();
+    let mut signed = vec![owners[0], owners[2]];
+    signed.sort();
+    assert_eq!(set.check_threshold(&owners, 2).unwrap(), signed);
+    assert_eq!(
+        set.check_owners(&owners)
+            .unwrap_err()
+            .downcast::<MultisigError>()
+            .unwrap(),
+        MultisigError::NotOwner(outsider)
+    );
+
+    let sorted = set.to_sorted_bytes();
+    assert_eq!(
+        SignatureSet::from_sorted_bytes(digest, &sorted).unwrap(),
+        set
+    );
+    set.retain_owners(&owners);
+    set.check_owners(&owners).unwrap();
+    let safe = set.to_safe_bytes();
+    assert_eq!(safe.len(), 2 * 65);
+    for (i, signer) in set.signers().iter().enumerate() {
+        let signature = Signature::from_bytes(&safe[i * 65..(i + 1) * 65]).unwrap();
+        assert_eq!(signature.recover(&digest).unwrap(), *signer);
+    }
+
+    // Another digest, out of order entries and truncated sets are refused
+    assert!(matches!(
+        SignatureSet::from_sorted_bytes([0x24u8; 32], &sorted)
+            .unwrap_err()
+            .downcast::<MultisigError>()
+            .unwrap(),
+        MultisigError::WrongSigner(..)
+    ));
+    let swapped = [&sorted[ENTRY_LEN..2 * ENTRY_LEN], &sorted[..ENTRY_LEN]].concat();
+    assert_eq!(
+        SignatureSet::from_sorted_bytes(digest, &swapped)
+            .unwrap_err()
+            .downcast::<MultisigError>()
+            .unwrap(),
+        MultisigError::NotSorted
+    );
+    assert!(SignatureSet::from_sorted_bytes(digest, &sorted[1..]).is_err());
+}
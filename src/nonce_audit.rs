@@ -0,0 +1,340 @@
+//! Detection of signing nonces that give away the key.
+//!
+//! An ECDSA signature is only as secret as its nonce `k`. Two signatures of
+//! different hashes with the same nonce, which shows as the same `r`, give
+//! the nonce and then the private key by simple arithmetic modulo the curve
+//! order. So do two signatures whose nonces are linearly related by known
+//! constants, `k2 = a * k1 + b`, as a broken random number generator or a
+//! counter produces. Keys compromised this way have to be treated as
+//! public.
+//!
+//! The functions here are meant for incident response and wallet audits:
+//! given signatures from one signer, they recover the key if one of these
+//! weaknesses is present, and check the recovered key against the signer
+//! before reporting it, so a report is never a false positive.
+use address::Address;
+use constants::SECPK1N;
+use failure::Error;
+use num256::Uint256;
+use num_bigint::BigUint;
+use num_traits::Zero;
+use private_key::PrivateKey;
+use signature::Signature;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum NonceAuditError {
+    #[fail(display = "Signatures are by different signers, {} and {}", _0, _1)]
+    DifferentSigners(Address, Address),
+}
+
+/// A signature with the hash it signs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedHash {
+    pub hash: [u8; 32],
+    pub signature: Signature,
+}
+
+/// How the nonces of two signatures are related.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NonceRelation {
+    /// The same nonce signed both
+    Reused,
+    /// The nonce of the second is `factor` times the nonce of the first,
+    /// plus `offset`, modulo the curve order
+    Linear { factor: Uint256, offset: Uint256 },
+}
+
+/// A key recovered from two of its signatures.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compromise {
+    pub signer: Address,
+    pub relation: NonceRelation,
+    pub key: PrivateKey,
+}
+
+/// Arithmetic modulo the order of secp256k1.
+struct Scalars {
+    n: BigUint,
+}
+
+impl Scalars {
+    fn new() -> Scalars {
+        Scalars {
+            n: SECPK1N.0.clone(),
+        }
+    }
+
+    fn reduce(&self, value: &Uint256) -> BigUint {
+        &value.0 % &self.n
+    }
+
+    fn sub(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a + &self.n - b % &self.n) % &self.n
+    }
+
+    fn mul(&self, a: &BigUint, b: &BigUint) -> BigUint {
+        (a * b) % &self.n
+    }
+
+    fn neg(&self, a: &BigUint) -> BigUint {
+        self.sub(&BigUint::zero(), a)
+    }
+
+    /// The inverse of a non-zero scalar, by Fermat's little theorem, as the
+    /// order is prime.
+    fn inv(&self, a: &BigUint) -> Option<BigUint> {
+        if a.is_zero() {
+            return None;
+        }
+        Some(a.modpow(&(&self.n - 2u32), &self.n))
+    }
+}
+
+/// The signer of both signatures.
+fn common_signer(first: &SignedHash, second: &SignedHash) -> Result<Address, Error> {
+    let signer = first.signature.recover(&first.hash)?;
+    let other = second.signature.recover(&second.hash)?;
+    if signer != other {
+        return Err(NonceAuditError::DifferentSigners(signer, other).into());
+    }
+    Ok(signer)
+}
+
+/// The key of `signer`, if the nonces of the signatures satisfy
+/// `k2 = a * k1 + b`.
+///
+/// With `s1 * k1 = z1 + r1 * d` and `s2 * k2 = z2 + r2 * d`, substituting
+/// `k1` gives `d = (z2 - s2 * b - s2 * a * z1 / s1) / (s2 * a * r1 / s1 - r2)`.
+/// Either `s` may have been negated to be low, which negates its nonce, so
+/// all four signs are tried.
+fn solve(
+    scalars: &Scalars,
+    signer: Address,
+    first: &SignedHash,
+    second: &SignedHash,
+    a: &BigUint,
+    b: &BigUint,
+) -> Option<PrivateKey> {
+    let z1 = scalars.reduce(&Uint256::from_bytes_be(&first.hash));
+    let z2 = scalars.reduce(&Uint256::from_bytes_be(&second.hash));
+    let r1 = scalars.reduce(&first.signature.r);
+    let r2 = scalars.reduce(&second.signature.r);
+    let s1 = scalars.reduce(&first.signature.s);
+    let s2 = scalars.reduce(&second.signature.s);
+    for s1 in &[s1.clone(), scalars.neg(&s1)] {
+        for s2 in &[s2.clone(), scalars.neg(&s2)] {
+            let s1_inv = scalars.inv(s1)?;
+            let ratio = scalars.mul(&scalars.mul(s2, a), &s1_inv);
+            let numerator = scalars.sub(
+                &scalars.sub(&z2, &scalars.mul(s2, b)),
+                &scalars.mul(&ratio, &z1),
+            );
+            let denominator = match scalars.inv(&scalars.sub(&scalars.mul(&ratio, &r1), &r2)) {
+                Some(inverse) => inverse,
+                None => continue,
+            };
+            let d = scalars.mul(&numerator, &denominator);
+            if d.is_zero() {
+                continue;
+            }
+            let bytes: [u8; 32] = Uint256::from_bytes_be(&d.to_bytes_be()).into();
+            let key = match PrivateKey::from_slice_allow_test_keys(&bytes) {
+                Ok(key) => key,
+                Err(_) => continue,
+            };
+            if key.to_public_key().ok() == Some(signer) {
+                return Some(key);
+            }
+        }
+    }
+    None
+}
+
+/// Recovers the key of the signer of both signatures if they share their
+/// nonce, which shows as the same `r` for different hashes.
+///
+/// Fails if the signatures are not by the same signer.
+pub fn check_nonce_reuse(
+    first: &SignedHash,
+    second: &SignedHash,
+) -> Result<Option<Compromise>, Error> {
+    let signer = common_signer(first, second)?;
+    if first.signature.r != second.signature.r || first.hash == second.hash {
+        return Ok(None);
+    }
+    let scalars = Scalars::new();
+    let one = BigUint::from(1u32);
+    Ok(
+        solve(&scalars, signer, first, second, &one, &BigUint::zero()).map(|key| Compromise {
+            signer,
+            relation: NonceRelation::Reused,
+            key,
+        }),
+    )
+}
+
+/// Recovers the key of the signer of both signatures if the nonce of the
+/// second is related to the first by one of `relations`, given as
+/// `(factor, offset)` for `k2 = factor * k1 + offset`, such as `(1, 1)`
+/// for consecutive nonces.
+///
+/// Fails if the signatures are not by the same signer.
+pub fn check_related_nonces(
+    first: &SignedHash,
+    second: &SignedHash,
+    relations: &[(Uint256, Uint256)],
+) -> Result<Option<Compromise>, Error> {
+    let signer = common_signer(first, second)?;
+    let scalars = Scalars::new();
+    for (factor, offset) in relations {
+        let a = scalars.reduce(factor);
+        let b = scalars.reduce(offset);
+        if let Some(key) = solve(&scalars, signer, first, second, &a, &b) {
+            return Ok(Some(Compromise {
+                signer,
+                relation: NonceRelation::Linear {
+                    factor: factor.clone(),
+                    offset: offset.clone(),
+                },
+                key,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Checks every pair of `signatures` that share an `r` for nonce reuse,
+/// returning the indices of the first pair that gives away each signer's
+/// key. Signatures that do not recover are skipped.
+pub fn audit_signatures(signatures: &[SignedHash]) -> Vec<(usize, usize, Compromise)> {
+    let mut found: Vec<(usize, usize, Compromise)> = Vec::new();
+    for (i, first) in signatures.iter().enumerate() {
+        for (j, second) in signatures.iter().enumerate().skip(i + 1) {
+            if first.signature.r != second.signature.r {
+                continue;
+            }
+            if let Ok(Some(compromise)) = check_nonce_reuse(first, second) {
+                if !found.iter().any(|(_, _, c)| c.signer == compromise.signer) {
+                    found.push((i, j, compromise));
+                }
+            }
+        }
+    }
+    found
+}
+
+/// Signs `hash` with the nonce `k`, normalized to a low `s` as
+/// `sign_hash` does.
+#[cfg(test)]
+fn sign_with_nonce(key: &PrivateKey, hash: &[u8; 32], k: &BigUint) -> SignedHash {
+    use context::SECP256K1;
+    use secp256k1::{PublicKey, SecretKey};
+    let scalars = Scalars::new();
+    let nonce: [u8; 32] = Uint256::from_bytes_be(&k.to_bytes_be()).into();
+    let point = SECP256K1.with(|context| {
+        let secret = SecretKey::from_slice(&nonce).unwrap();
+        PublicKey::from_secret_key(&context.borrow(), &secret).serialize_uncompressed()
+    });
+    let r = BigUint::from_bytes_be(&point[1..33]) % &scalars.n;
+    let y_odd = point[64] & 1 == 1;
+    let d = BigUint::from_bytes_be(&key.to_bytes());
+    let z = BigUint::from_bytes_be(hash) % &scalars.n;
+    let mut s = scalars.mul(
+        &scalars.inv(k).unwrap(),
+        &((&z + scalars.mul(&r, &d)) % &scalars.n),
+    );
+    let mut v = 27 + y_odd as u32;
+    if s > &scalars.n / 2u32 {
+        s = scalars.neg(&s);
+        v = 55 - v;
+    }
+    SignedHash {
+        hash: *hash,
+        signature: Signature::new(
+            v.into(),
+            Uint256::from_bytes_be(&r.to_bytes_be()),
+            Uint256::from_bytes_be(&s.to_bytes_be()),
+        ),
+    }
+}
+
+#[cfg(test)]
+fn test_key() -> PrivateKey {
+    "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn recover_reused_nonce() {
+    use hash::keccak256;
+    let key = test_key();
+    let k = BigUint::from_bytes_be(&keccak256(b"a bad nonce"));
+    let first = sign_with_nonce(&key, &keccak256(b"first"), &k);
+    let second = sign_with_nonce(&key, &keccak256(b"second"), &k);
+    assert_eq!(
+        first.signature.recover(&first.hash).unwrap(),
+        key.to_public_key().unwrap()
+    );
+    assert_eq!(
+        check_nonce_reuse(&first, &second).unwrap(),
+        Some(Compromise {
+            signer: key.to_public_key().unwrap(),
+            relation: NonceRelation::Reused,
+            key,
+        })
+    );
+
+    // Signatures of the crate use fresh nonces
+    let third = SignedHash {
+        hash: keccak256(b"third"),
+        signature: key.sign_hash(&keccak256(b"third")),
+    };
+    assert_eq!(check_nonce_reuse(&first, &third).unwrap(), None);
+    let found = audit_signatures(&[third.clone(), first, third, second]);
+    assert_eq!(found.len(), 1);
+    assert_eq!((found[0].0, found[0].1), (1, 3));
+    assert_eq!(found[0].2.key, key);
+}
+
+#[test]
+fn recover_related_nonces() {
+    use hash::keccak256;
+    let key = test_key();
+    let k = BigUint::from_bytes_be(&keccak256(b"a counter"));
+    let first = sign_with_nonce(&key, &keccak256(b"first"), &k);
+    let second = sign_with_nonce(&key, &keccak256(b"second"), &(&k + 1u32));
+    assert_eq!(check_nonce_reuse(&first, &second).unwrap(), None);
+    let relations = [
+        (Uint256::from(2u8), Uint256::from(0u8)),
+        (Uint256::from(1u8), Uint256::from(1u8)),
+    ];
+    let compromise = check_related_nonces(&first, &second, &relations)
+        .unwrap()
+        .unwrap();
+    assert_eq!(compromise.key, key);
+    assert_eq!(
+        compromise.relation,
+        NonceRelation::Linear {
+            factor: 1u8.into(),
+            offset: 1u8.into(),
+        }
+    );
+    assert_eq!(
+        check_related_nonces(&first, &second, &relations[..1]).unwrap(),
+        None
+    );
+
+    let other = PrivateKey::from([0x11u8; 32]);
+    let foreign = sign_with_nonce(&other, &keccak256(b"second"), &k);
+    assert_eq!(
+        check_nonce_reuse(&first, &foreign)
+            .unwrap_err()
+            .downcast::<NonceAuditError>()
+            .unwrap(),
+        NonceAuditError::DifferentSigners(
+            key.to_public_key().unwrap(),
+            other.to_public_key().unwrap()
+        )
+    );
+}
@@ -11,6 +11,12 @@ pub const GLOGBYTE: u32 = 8; // cost of a byte of logdata
 pub const GTXCOST: u32 = 21000; // TX BASE GAS COST
 pub const GTXDATAZERO: u32 = 4; // TX DATA ZERO BYTE GAS COST
 pub const GTXDATANONZERO: u32 = 68; // TX DATA NON ZERO BYTE GAS COST
+pub const GTXDATANONZERO_EIP2028: u32 = 16; // TX DATA NON ZERO BYTE GAS COST since Istanbul
+pub const GTXCREATE: u32 = 32000; // Extra cost of contract creating TX
+pub const GACCESSLISTADDRESS: u32 = 2400; // Cost of an address in an access list
+pub const GACCESSLISTSTORAGE: u32 = 1900; // Cost of a storage key in an access list
+pub const GAS_PER_BLOB: u32 = 131072; // Blob gas used by a blob (EIP-4844)
+pub const GAUTHORIZATION: u32 = 25000; // Cost of an authorization in a set code TX (EIP-7702)
 pub const GSHA3WORD: u32 = 6; // Cost of SHA3 per word
 pub const GSHA256BASE: u32 = 60; // Base c of SHA256
 pub const GSHA256WORD: u32 = 12; // Cost of SHA256 per word
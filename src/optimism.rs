@@ -0,0 +1,305 @@
+//! OP Stack cross-domain messages and withdrawals.
+//!
+//! Messages between L1 and an OP Stack chain go through the
+//! `CrossDomainMessenger` on either side, which relays them as versioned
+//! `relayMessage` calls. Messages to L1 are withdrawals sent through the
+//! `L2ToL1MessagePasser`, which records the hash of every withdrawal in its
+//! storage. Proving a withdrawal on L1 takes that hash, the storage slot it
+//! is kept in, and the output root of the L2 block, all of which can be
+//! computed here from the `MessagePassed` log and the block.
+use abi::{decode_tokens, derive_signature, encode_call, encode_tokens, ParamType, Token};
+use address::Address;
+use event::{topic_address, Log};
+use failure::Error;
+use hash::keccak256;
+use num256::Uint256;
+
+lazy_static! {
+    /// Address of the `L2ToL1MessagePasser` predeploy.
+    pub static ref L2_TO_L1_MESSAGE_PASSER: Address =
+        "0x4200000000000000000000000000000000000016".parse().unwrap();
+}
+
+/// Logged by the `L2ToL1MessagePasser` for every withdrawal.
+pub const MESSAGE_PASSED: &str =
+    "MessagePassed(uint256,address,address,uint256,uint256,bytes,bytes32)";
+/// Signature of `relayMessage` for messages of version 0.
+pub const RELAY_MESSAGE_V0: &str = "relayMessage(address,address,bytes,uint256)";
+/// Signature of `relayMessage` for messages of version 1.
+pub const RELAY_MESSAGE_V1: &str = "relayMessage(uint256,address,address,uint256,uint256,bytes)";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum OptimismError {
+    #[fail(display = "Unknown message version {}", _0)]
+    UnknownVersion(u16),
+    #[fail(display = "Log is not a MessagePassed event")]
+    UnexpectedEvent,
+    #[fail(display = "MessagePassed log is malformed")]
+    MalformedLog,
+    #[fail(display = "Withdrawal hash in the log does not match the withdrawal")]
+    HashMismatch,
+}
+
+/// A message nonce with the message version in its upper two bytes.
+pub fn encode_versioned_nonce(nonce: &Uint256, version: u16) -> Uint256 {
+    let mut bytes: [u8; 32] = nonce.clone().into();
+    bytes[..2].copy_from_slice(&version.to_be_bytes());
+    Uint256::from_bytes_be(&bytes)
+}
+
+/// The version and nonce of a versioned nonce.
+pub fn decode_versioned_nonce(nonce: &Uint256) -> (u16, Uint256) {
+    let mut bytes: [u8; 32] = nonce.clone().into();
+    let version = u16::from_be_bytes([bytes[0], bytes[1]]);
+    bytes[0] = 0;
+    bytes[1] = 0;
+    (version, Uint256::from_bytes_be(&bytes))
+}
+
+/// A message relayed by the `CrossDomainMessenger`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CrossDomainMessage {
+    /// Versioned nonce, see [encode_versioned_nonce](fn.encode_versioned_nonce.html)
+    pub nonce: Uint256,
+    pub sender: Address,
+    pub target: Address,
+    pub value: Uint256,
+    pub min_gas_limit: Uint256,
+    pub message: Vec<u8>,
+}
+
+impl CrossDomainMessage {
+    /// The `relayMessage` call for the version of the message. Version 0
+    /// messages carry neither value nor gas limit.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        match decode_versioned_nonce(&self.nonce).0 {
+            0 => Ok(encode_call(
+                RELAY_MESSAGE_V0,
+                &[
+                    Token::Address(self.target),
+                    Token::Address(self.sender),
+                    Token::UnboundedBytes(self.message.clone()),
+                    Token::Uint(self.nonce.clone()),
+                ],
+            )),
+            1 => Ok(encode_call(
+                RELAY_MESSAGE_V1,
+                &[
+                    Token::Uint(self.nonce.clone()),
+                    Token::Address(self.sender),
+                    Token::Address(self.target),
+                    Token::Uint(self.value.clone()),
+                    Token::Uint(self.min_gas_limit.clone()),
+                    Token::UnboundedBytes(self.message.clone()),
+                ],
+            )),
+            version => Err(OptimismError::UnknownVersion(version).into()),
+        }
+    }
+
+    /// The hash the messengers track the message by.
+    pub fn hash(&self) -> Result<[u8; 32], Error> {
+        Ok(keccak256(&self.encode()?))
+    }
+}
+
+/// A withdrawal sent through the `L2ToL1MessagePasser`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Withdrawal {
+    pub nonce: Uint256,
+    pub sender: Address,
+    pub target: Address,
+    pub value: Uint256,
+    pub gas_limit: Uint256,
+    pub data: Vec<u8>,
+}
+
+impl Withdrawal {
+    /// Decodes a `MessagePassed` log, checking the withdrawal hash it was
+    /// logged with.
+    pub fn from_log(log: &Log) -> Result<Withdrawal, Error> {
+        if log.topics.len() != 4 || log.topics[0] != derive_signature(MESSAGE_PASSED) {
+            return Err(OptimismError::UnexpectedEvent.into());
+        }
+        let types = [
+            ParamType::Uint(256),
+            ParamType::Uint(256),
+            ParamType::Bytes,
+            ParamType::FixedBytes(32),
+        ];
+        let tokens = decode_tokens(&types, &log.data)?;
+        let withdrawal = match tokens[..] {
+            [Token::Uint(ref value), Token::Uint(ref gas_limit), Token::UnboundedBytes(ref data), Token::Bytes(ref hash)] =>
+            {
+                let withdrawal = Withdrawal {
+                    nonce: Uint256::from_bytes_be(&log.topics[1]),
+                    sender: topic_address(&log.topics[2]).ok_or(OptimismError::MalformedLog)?,
+                    target: topic_address(&log.topics[3]).ok_or(OptimismError::MalformedLog)?,
+                    value: value.clone(),
+                    gas_limit: gas_limit.clone(),
+                    data: data.clone(),
+                };
+                if hash[..] != withdrawal.hash()[..] {
+                    return Err(OptimismError::HashMismatch.into());
+                }
+                withdrawal
+            }
+            _ => return Err(OptimismError::MalformedLog.into()),
+        };
+        Ok(withdrawal)
+    }
+
+    /// The withdrawal hash, which the withdrawal is proven and finalized by.
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Uint(self.nonce.clone()),
+            Token::Address(self.sender),
+            Token::Address(self.target),
+            Token::Uint(self.value.clone()),
+            Token::Uint(self.gas_limit.clone()),
+            Token::UnboundedBytes(self.data.clone()),
+        ]))
+    }
+
+    /// Slot of the withdrawal in the `sentMessages` mapping of the
+    /// `L2ToL1MessagePasser`, whose storage proof proves the withdrawal.
+    pub fn storage_slot(&self) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Bytes(self.hash().to_vec()),
+            0u8.into(),
+        ]))
+    }
+}
+
+/// The preimage of an output root, given along with a withdrawal proof.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OutputRootProof {
+    pub version: [u8; 32],
+    /// State root of the L2 block
+    pub state_root: [u8; 32],
+    /// Storage root of the `L2ToL1MessagePasser` at the L2 block
+    pub message_passer_storage_root: [u8; 32],
+    /// Hash of the L2 block
+    pub latest_blockhash: [u8; 32],
+}
+
+impl OutputRootProof {
+    /// The output root, as proposed to L1.
+    pub fn hash(&self) -> [u8; 32] {
+        let mut data = Vec::with_capacity(128);
+        data.extend_from_slice(&self.version);
+        data.extend_from_slice(&self.state_root);
+        data.extend_from_slice(&self.message_passer_storage_root);
+        data.extend_from_slice(&self.latest_blockhash);
+        keccak256(&data)
+    }
+}
+
+#[cfg(test)]
+fn test_withdrawal() -> Withdrawal {
+    Withdrawal {
+        nonce: encode_versioned_nonce(&5u8.into(), 1),
+        sender: Address::from([0x11u8; 20]),
+        target: Address::from([0x22u8; 20]),
+        value: 1_000u32.into(),
+        gas_limit: 200_000u32.into(),
+        data: vec![0xd0, 0x9d, 0xe0, 0x8a],
+    }
+}
+
+#[test]
+fn versioned_nonces() {
+    let nonce = encode_versioned_nonce(&5u8.into(), 1);
+    let bytes: [u8; 32] = nonce.clone().into();
+    assert_eq!(&bytes[..2], &[0x00, 0x01]);
+    assert_eq!(bytes[31], 5);
+    assert_eq!(decode_versioned_nonce(&nonce), (1, 5u8.into()));
+}
+
+#[test]
+fn cross_domain_messages() {
+    use abi::derive_method_id;
+    let message = CrossDomainMessage {
+        nonce: encode_versioned_nonce(&5u8.into(), 1),
+        sender: Address::from([0x11u8; 20]),
+        target: Address::from([0x22u8; 20]),
+        value: 0u8.into(),
+        min_gas_limit: 100_000u32.into(),
+        message: vec![0xd0, 0x9d, 0xe0, 0x8a],
+    };
+    let encoded = message.encode().unwrap();
+    assert_eq!(encoded[..4], derive_method_id(RELAY_MESSAGE_V1));
+    assert_eq!(message.hash().unwrap(), keccak256(&encoded));
+
+    let legacy = CrossDomainMessage {
+        nonce: 5u8.into(),
+        ..message.clone()
+    };
+    assert_eq!(
+        legacy.encode().unwrap()[..4],
+        derive_method_id(RELAY_MESSAGE_V0)
+    );
+    let unknown = CrossDomainMessage {
+        nonce: encode_versioned_nonce(&5u8.into(), 2),
+        ..message
+    };
+    assert_eq!(
+        unknown
+            .hash()
+            .unwrap_err()
+            .downcast::<OptimismError>()
+            .unwrap(),
+        OptimismError::UnknownVersion(2)
+    );
+}
+
+#[test]
+fn withdrawal_from_log() {
+    use event::topic;
+    let withdrawal = test_withdrawal();
+    let log = |hash: [u8; 32]| Log {
+        address: *L2_TO_L1_MESSAGE_PASSER,
+        topics: vec![
+            derive_signature(MESSAGE_PASSED),
+            withdrawal.nonce.clone().into(),
+            topic(&Token::Address(withdrawal.sender)),
+            topic(&Token::Address(withdrawal.target)),
+        ],
+        data: encode_tokens(&[
+            Token::Uint(withdrawal.value.clone()),
+            Token::Uint(withdrawal.gas_limit.clone()),
+            Token::UnboundedBytes(withdrawal.data.clone()),
+            Token::Bytes(hash.to_vec()),
+        ]),
+    };
+    assert_eq!(
+        Withdrawal::from_log(&log(withdrawal.hash())).unwrap(),
+        withdrawal
+    );
+    assert_eq!(
+        Withdrawal::from_log(&log([0u8; 32]))
+            .unwrap_err()
+            .downcast::<OptimismError>()
+            .unwrap(),
+        OptimismError::HashMismatch
+    );
+
+    let mut slot_preimage = withdrawal.hash().to_vec();
+    slot_preimage.extend_from_slice(&[0u8; 32]);
+    assert_eq!(withdrawal.storage_slot(), keccak256(&slot_preimage));
+}
+
+#[test]
+fn output_root() {
+    let proof = OutputRootProof {
+        state_root: [0x11u8; 32],
+        message_passer_storage_root: [0x22u8; 32],
+        latest_blockhash: [0x33u8; 32],
+        ..Default::default()
+    };
+    let mut preimage = vec![0u8; 32];
+    preimage.extend_from_slice(&[0x11u8; 32]);
+    preimage.extend_from_slice(&[0x22u8; 32]);
+    preimage.extend_from_slice(&[0x33u8; 32]);
+    assert_eq!(proof.hash(), keccak256(&preimage));
+}
@@ -1,25 +1,71 @@
+use abi::Token;
 use address::Address;
 use context::SECP256K1;
+use eip712::{signing_hash, Eip712Domain, Types};
 use error::ClarityError;
 use failure::Error;
+use hash::{ethereum_msg_hash, keccak256};
+use instrument::Span;
 use num256::Uint256;
 use secp256k1::{Message, PublicKey, SecretKey};
-use serde::Deserialize;
-use serde::Deserializer;
-use serde::Serialize;
-use serde::Serializer;
-use sha3::{Digest, Keccak256};
+use serde_crate::Deserialize;
+use serde_crate::Deserializer;
+use serde_crate::Serialize;
+use serde_crate::Serializer;
 use signature::Signature;
 use std::fmt;
 use std::str::FromStr;
-use utils::{bytes_to_hex_str, hex_str_to_bytes};
+use utils::{bytes_to_hex_str, hex_str_to_bytes, strip_hex_prefix};
 
 #[derive(Fail, Debug, PartialEq)]
 pub enum PrivateKeyError {
     #[fail(display = "Private key should be exactly 64 bytes")]
     InvalidLengthError,
+    #[fail(display = "Private key is a well known test key or has no entropy")]
+    WeakKey,
 }
 
+/// Keys of the twenty default accounts of Hardhat, the first ten of which
+/// Anvil funds too, derived from the mnemonic
+/// `test test test test test test test test test test test junk`.
+/// Anything sent to them is taken immediately.
+pub(crate) const HARDHAT_KEYS: [&str; 20] = [
+    "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80",
+    "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d",
+    "5de4111afa1a4b94908f83103eb1f1706367c2e68ca870fc3fb9a804cdab365a",
+    "7c852118294e51e653712a81e05800f419141751be58f605c371e15141b007a6",
+    "47e179ec197488593b187f80a00eb0da91f1b9d0b13f8733639f19c30a34926a",
+    "8b3a350cf5c34c9194ca85829a2df0ec3153be0318b5e2d3348e872092edffba",
+    "92db14e403b83dfe3df233f83dfa3a0d7096f21ca9b0d6d6b8d88b2b4ec1564e",
+    "4bbbf85ce3377467afe5d46f804f221813b2bb87f24d81f60f1fcdbf7cbf4356",
+    "dbda1821b80551c9d65939329250298aa3472ba22feea921c0cf5d620ea67b97",
+    "2a871d0798f97d79848a013d4936a73bf4cc922c825d33c1cf7073dff6d409c6",
+    "f214f2b2cd398c806f84e317254e0f0b801d0643303237d97a22a48e01628897",
+    "701b615bbdfb9de65240bc28bd21bbc0d996645a3dd57e7b12bc2bdf6f192c82",
+    "a267530f49f8280200edf313ee7af6b827f2a8bce2897751d06a843f644967b1",
+    "47c99abed3324a2707c28affff1267e45918ec8c3f20b8aa892e8b065d2942dd",
+    "c526ee95bf44d8fc405a158bb884d9d1238d99f0612e9f33d006bb0789009aaa",
+    "8166f546bab6da521a8369cab06c5d2b9e46670292d85c875ee9ec20e84ffb61",
+    "ea6c44ac03bff858b476bba40716402b03e41b8e97e276d1baec7c37d42484a0",
+    "689af8efa8c651a91ad287602527f3af2fe9f6501a7ac4b061667b5a93e037fd",
+    "de9be858da4a475276426320d5e9262ecfc3ba460bfac56360bfa6c4c28b4ee0",
+    "df57089febbacf7ba0bc227dafbffa9fc08a93fdc68e1e42411a14efcf23656e",
+];
+
+/// Keys of the ten accounts of `ganache --deterministic`.
+pub(crate) const GANACHE_KEYS: [&str; 10] = [
+    "4f3edf983ac636a65a842ce7c78d9aa706d3b113bce9c46f30d7d21715b23b1d",
+    "6cbed15c793ce57650b9877cf6fa156fbef513c4e6134f022a85b1ffdd59b2a1",
+    "6370fd033278c143179d81c5526140625662b8daa446c22ee2d73db3707e620c",
+    "646f1ce2fdad0e6deeeb5c7e8e5543bdde65e86029e2fd9fc169899c440a7913",
+    "add53f9a7e588d003326d1cbf9e4a43c061aadd9bc938c843a79e7b4fd2ad743",
+    "395df67f0c2d2d9fe1ad08d1bc8b6627011959b79c53d7dd6a3536a33ab8a4fd",
+    "e485d098507f54e7733a205420dfddbe58db035fa577fc294ebd14db90767a52",
+    "a453611d9419d0e56f499079478fd72c37b251a94bfde4d19872c44cf65386e3",
+    "829e924fdf021ba3dbbc4225edfece9aca04b929d6e75613329ca6f1d31c0bb4",
+    "b0057716d5917badaf911b193b12b910811c1497b5bada8d7711f758981c3773",
+];
+
 /// Representation of an Ethereum private key.
 ///
 /// Private key can be created using a textual representation,
@@ -39,20 +85,37 @@ impl FromStr for PrivateKey {
     /// It has to be a string that represents 64 characters that are hexadecimal
     /// representation of 32 bytes. Optionally this string can be prefixed with `0x`
     /// at the beggining.
+    ///
+    /// Weak keys are rejected, see [from_slice](#method.from_slice).
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         // Strip optional prefix if its there
-        let s = if s.starts_with("0x") { &s[2..] } else { &s };
+        let s = strip_hex_prefix(s);
         if s.len() != 64 {
             return Err(PrivateKeyError::InvalidLengthError.into());
         }
         let bytes = hex_str_to_bytes(&s)?;
         debug_assert_eq!(bytes.len(), 32);
-        let mut res = [0x0u8; 32];
-        res.copy_from_slice(&bytes[..]);
-        Ok(PrivateKey(res))
+        PrivateKey::from_slice(&bytes)
     }
 }
 
+lazy_static! {
+    /// The keys [is_weak](struct.PrivateKey.html#method.is_weak) rejects.
+    static ref WEAK_KEYS: Vec<[u8; 32]> = {
+        let mut one = [0u8; 32];
+        one[31] = 1;
+        let mut keys = vec![[0u8; 32], one];
+        for key in HARDHAT_KEYS.iter().chain(GANACHE_KEYS.iter()) {
+            let mut bytes = [0u8; 32];
+            bytes.copy_from_slice(&hex_str_to_bytes(key).unwrap());
+            keys.push(bytes);
+        }
+        keys
+    };
+}
+
+/// Takes the bytes as they are, without rejecting
+/// [weak](struct.PrivateKey.html#method.is_weak) keys.
 impl From<[u8; 32]> for PrivateKey {
     fn from(val: [u8; 32]) -> PrivateKey {
         PrivateKey(val)
@@ -62,10 +125,21 @@ impl From<[u8; 32]> for PrivateKey {
 impl PrivateKey {
     /// Convert a given slice of bytes into a valid private key.
     ///
-    /// Input bytes are validated for a length only.
+    /// Input bytes are validated for a length, and [weak](#method.is_weak)
+    /// keys are rejected.
     ///
     /// * `slice` - A slice of raw bytes with a length of 32.
     pub fn from_slice(slice: &[u8]) -> Result<PrivateKey, Error> {
+        let key = PrivateKey::from_slice_allow_test_keys(slice)?;
+        if key.is_weak() {
+            return Err(PrivateKeyError::WeakKey.into());
+        }
+        Ok(key)
+    }
+
+    /// Like [from_slice](#method.from_slice), but weak keys are accepted,
+    /// for tests against development nodes.
+    pub fn from_slice_allow_test_keys(slice: &[u8]) -> Result<PrivateKey, Error> {
         if slice.len() != 32 {
             return Err(ClarityError::InvalidPrivKey.into());
         }
@@ -74,6 +148,34 @@ impl PrivateKey {
         Ok(PrivateKey(res))
     }
 
+    /// Generates a new key from the thread-local CSPRNG of `rand`, which is
+    /// seeded by the operating system.
+    #[cfg(feature = "keygen")]
+    pub fn generate() -> PrivateKey {
+        PrivateKey::generate_with(&mut rand::thread_rng())
+    }
+
+    /// Generates a new key from `rng`, drawing again until the bytes are a
+    /// valid secp256k1 secret, that is neither zero nor the curve order or
+    /// above, and not a [weak](#method.is_weak) key.
+    #[cfg(feature = "keygen")]
+    pub fn generate_with<R: rand::RngCore + rand::CryptoRng>(rng: &mut R) -> PrivateKey {
+        loop {
+            let mut bytes = [0u8; 32];
+            rng.fill_bytes(&mut bytes);
+            let key = PrivateKey(bytes);
+            if SecretKey::from_slice(&bytes).is_ok() && !key.is_weak() {
+                return key;
+            }
+        }
+    }
+
+    /// Whether the key is obviously unsafe to hold funds with: zero, one or
+    /// the key of a default development node account.
+    pub fn is_weak(&self) -> bool {
+        WEAK_KEYS.contains(&self.0)
+    }
+
     /// Get bytes back from a PrivateKey
     pub fn to_bytes(&self) -> [u8; 32] {
         self.0
@@ -108,10 +210,17 @@ impl PrivateKey {
             return Err(ClarityError::ZeroPrivKey.into());
         }
         // Finally an address is last 20 bytes of a hash of the public key.
-        let sender = Keccak256::digest(&pkey[1..]);
-        debug_assert_eq!(sender.len(), 32);
+        let sender = keccak256(&pkey[1..]);
         Address::from_slice(&sender[12..])
     }
+    /// The public key, for the encodings other systems want it in.
+    pub fn public_key(&self) -> Result<::public_key::PublicKey, Error> {
+        let sk = SecretKey::from_slice(&self.0)?;
+        Ok(SECP256K1
+            .with(|object| PublicKey::from_secret_key(&object.borrow(), &sk))
+            .into())
+    }
+
     /// Signs a message that is represented by a hash contained in a binary form.
     ///
     /// Requires the data buffer to be exactly 32 bytes in length. You can prepare
@@ -124,16 +233,16 @@ impl PrivateKey {
     /// # Example
     ///
     /// ```rust
-    /// # extern crate sha3;
     /// # extern crate clarity;
     /// # use clarity::PrivateKey;
-    /// # use sha3::{Keccak256, Digest};
+    /// # use clarity::hash::keccak256;
     /// let private_key : PrivateKey = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f1e".parse().unwrap();
-    /// let hash = Keccak256::digest("Hello, world!".as_bytes());
+    /// let hash = keccak256("Hello, world!".as_bytes());
     /// let signature = private_key.sign_hash(&hash);
     /// ```
     pub fn sign_hash(&self, data: &[u8]) -> Signature {
         debug_assert_eq!(data.len(), 32);
+        let span = Span::sign_hash();
         // Create a secret key for Secp256k1 operations
         let sk = SecretKey::from_slice(&self.to_bytes()).unwrap();
         // Acquire SECP256K1 context from thread local storage and
@@ -162,6 +271,10 @@ impl PrivateKey {
         let v: Uint256 = (recovery_id + 27).into();
         let r = Uint256::from_bytes_be(&compact[0..32]);
         let s = Uint256::from_bytes_be(&compact[32..64]);
+        span.finish(|| match self.to_public_key() {
+            Ok(signer) => format!("by {}", signer),
+            Err(e) => format!("by an invalid key: {}", e),
+        });
         // This will swap the signature of a transaction, and returns a new signed TX.
         Signature::new(v, r, s)
     }
@@ -181,8 +294,29 @@ impl PrivateKey {
     /// let signature = private_key.sign_msg("Hello, world!".as_bytes());
     /// ```
     pub fn sign_msg(&self, data: &[u8]) -> Signature {
-        let digest = Keccak256::digest(data);
-        self.sign_hash(&digest)
+        self.sign_hash(&keccak256(data))
+    }
+
+    /// Signs a message as `personal_sign` and `eth_sign` do, over its
+    /// [EIP-191 digest](../hash/fn.ethereum_msg_hash.html), so that the
+    /// signature can not be passed off as the signature of a transaction.
+    /// [recover_ethereum_msg](../signature/struct.Signature.html#method.recover_ethereum_msg)
+    /// gives back the signer.
+    pub fn sign_ethereum_msg(&self, msg: &[u8]) -> Signature {
+        self.sign_hash(&ethereum_msg_hash(msg))
+    }
+
+    /// Signs `message`, a value of the struct type `primary_type`, as
+    /// EIP-712 typed data in `domain`, as `eth_signTypedData_v4` does. See
+    /// the [eip712](../eip712/index.html) module for how values are given.
+    pub fn sign_typed_data(
+        &self,
+        domain: &Eip712Domain,
+        types: &Types,
+        primary_type: &str,
+        message: &Token,
+    ) -> Result<Signature, Error> {
+        Ok(self.sign_hash(&signing_hash(domain, types, primary_type, message)?))
     }
 }
 
@@ -208,11 +342,9 @@ impl<'de> Deserialize<'de> for PrivateKey {
         D: Deserializer<'de>,
     {
         let s = String::deserialize(deserializer)?;
-        let s = if s.starts_with("0x") { &s[2..] } else { &s };
-
         hex_str_to_bytes(&s)
             .and_then(move |bytes| PrivateKey::from_slice(&bytes))
-            .map_err(serde::de::Error::custom)
+            .map_err(serde_crate::de::Error::custom)
     }
 }
 
@@ -252,6 +384,50 @@ impl fmt::UpperHex for PrivateKey {
     }
 }
 
+#[cfg(feature = "keygen")]
+#[test]
+fn generate_keys() {
+    use rand::rngs::StdRng;
+    use rand::{CryptoRng, RngCore, SeedableRng};
+    let key = PrivateKey::generate();
+    assert_ne!(key, PrivateKey::generate());
+    key.to_public_key().unwrap();
+
+    // An rng that first draws zero, then the curve order, then a valid key
+    struct Draws(Vec<[u8; 32]>);
+    impl RngCore for Draws {
+        fn next_u32(&mut self) -> u32 {
+            unreachable!("Keys are drawn 32 bytes at a time with fill_bytes")
+        }
+        fn next_u64(&mut self) -> u64 {
+            unreachable!("Keys are drawn 32 bytes at a time with fill_bytes")
+        }
+        fn fill_bytes(&mut self, dest: &mut [u8]) {
+            dest.copy_from_slice(&self.0.remove(0));
+        }
+        fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+            self.fill_bytes(dest);
+            Ok(())
+        }
+    }
+    impl CryptoRng for Draws {}
+    let order: PrivateKey = "fffffffffffffffffffffffffffffffebaaedce6af48a03bbfd25e8cd0364141"
+        .parse()
+        .unwrap();
+    let mut draws = Draws(vec![[0; 32], order.to_bytes(), [0x42; 32]]);
+    assert_eq!(
+        PrivateKey::generate_with(&mut draws),
+        PrivateKey::from_slice(&[0x42; 32]).unwrap()
+    );
+    assert!(draws.0.is_empty());
+
+    let mut rng = StdRng::seed_from_u64(7);
+    assert_eq!(
+        PrivateKey::generate_with(&mut rng),
+        PrivateKey::generate_with(&mut StdRng::seed_from_u64(7))
+    );
+}
+
 #[test]
 #[should_panic]
 fn too_short() {
@@ -266,6 +442,23 @@ fn invalid_data() {
     PrivateKey::from_str(key).unwrap();
 }
 
+#[test]
+fn prefix_and_garbage() {
+    let digits = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4";
+    let expected = PrivateKey::from_str(digits).unwrap();
+    assert_eq!(
+        PrivateKey::from_str(&format!("0x{}", digits)).unwrap(),
+        expected
+    );
+    assert_eq!(
+        PrivateKey::from_str(&format!("0X{}", digits)).unwrap(),
+        expected
+    );
+    assert!(PrivateKey::from_str(&format!("+c{}", &digits[2..])).is_err());
+    assert!(PrivateKey::from_str(&format!("0x0x{}", &digits[4..])).is_err());
+    assert!(PrivateKey::from_str(&format!("{}\n", &digits[1..])).is_err());
+}
+
 #[test]
 fn parse_address_1() {
     use utils::bytes_to_hex_str;
@@ -368,7 +561,7 @@ fn sign_message() {
         ]
     );
 
-    let hash = Keccak256::digest(b"Hello, world!");
+    let hash = keccak256(b"Hello, world!");
 
     // geth account import <(echo c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0)
     let sig = key.sign_hash(&hash);
@@ -399,6 +592,25 @@ fn sign_message() {
     );
 }
 
+#[test]
+fn sign_ethereum_message() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let signature = key.sign_ethereum_msg(b"Hello World");
+    // The digest is pinned against web3 in erc1271
+    assert_eq!(signature, key.sign_hash(&ethereum_msg_hash(b"Hello World")));
+    assert_ne!(signature, key.sign_msg(b"Hello World"));
+    assert_eq!(
+        signature.recover_ethereum_msg(b"Hello World").unwrap(),
+        key.to_public_key().unwrap()
+    );
+    assert_ne!(
+        signature.recover_ethereum_msg(b"Hello World!").unwrap(),
+        key.to_public_key().unwrap()
+    );
+}
+
 #[test]
 fn serialize_to_json() {
     let unsafe_key: PrivateKey = "0101010101010101010101010101010101010101010101010101010101010101"
@@ -427,3 +639,47 @@ fn from_string_with_prefix_issue_58() {
         ]
     );
 }
+
+#[test]
+fn rejects_weak_keys() {
+    let hardhat = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    assert_eq!(
+        hardhat
+            .parse::<PrivateKey>()
+            .unwrap_err()
+            .downcast::<PrivateKeyError>()
+            .unwrap(),
+        PrivateKeyError::WeakKey
+    );
+    let bytes = hex_str_to_bytes(hardhat).unwrap();
+    assert!(PrivateKey::from_slice(&bytes).is_err());
+    let key = PrivateKey::from_slice_allow_test_keys(&bytes).unwrap();
+    assert!(key.is_weak());
+    assert_eq!(
+        key.to_public_key().unwrap(),
+        "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            .parse()
+            .unwrap()
+    );
+
+    assert!(PrivateKey::from_slice(&[0u8; 32]).is_err());
+    let mut one = [0u8; 32];
+    one[31] = 1;
+    assert!(PrivateKey::from_slice(&one).is_err());
+    assert!(PrivateKey::from_slice_allow_test_keys(&one).is_ok());
+    assert!(PrivateKey::from(one).is_weak());
+    assert!(serde_json::from_str::<PrivateKey>(&format!("\"{}\"", hardhat)).is_err());
+    // The last accounts of Hardhat and of ganache
+    for key in &[
+        "df57089febbacf7ba0bc227dafbffa9fc08a93fdc68e1e42411a14efcf23656e",
+        "b0057716d5917badaf911b193b12b910811c1497b5bada8d7711f758981c3773",
+    ] {
+        assert!(key.parse::<PrivateKey>().is_err());
+    }
+
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    assert!(!key.is_weak());
+    assert_eq!(PrivateKey::from(key.to_bytes()), key);
+}
@@ -0,0 +1,192 @@
+//! secp256k1 public keys, in the encodings other systems expect them in.
+//!
+//! Ethereum mostly deals in addresses, the hash of a public key, but HSMs
+//! hand out public keys in the SEC1 compressed (33 bytes, `02` or `03` then
+//! x) or uncompressed (65 bytes, `04` then x and y) encoding, Cosmos-style
+//! chains use the compressed one, and devp2p identifies nodes by the
+//! uncompressed one without its `04` (64 bytes). [PublicKey](struct.PublicKey.html)
+//! reads all three and writes any of them:
+//!
+//! ```rust
+//! use clarity::{PrivateKey, PublicKey};
+//!
+//! let key: PrivateKey = "0102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f1e"
+//!     .parse()
+//!     .unwrap();
+//! let public = key.public_key().unwrap();
+//! let compressed = public.to_compressed();
+//! assert_eq!(PublicKey::from_slice(&compressed).unwrap(), public);
+//! assert_eq!(PublicKey::from_slice(&public.to_uncompressed()).unwrap(), public);
+//! assert_eq!(public.to_address(), key.to_public_key().unwrap());
+//! ```
+use address::Address;
+use failure::Error;
+use hash::keccak256;
+use secp256k1;
+use serde_crate::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+use utils::{bytes_to_hex_str, hex_str_to_bytes};
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum PublicKeyError {
+    #[fail(display = "Public key of {} bytes, not 33, 64 or 65 bytes", _0)]
+    InvalidLength(usize),
+    #[fail(display = "Public key is not a point of the curve")]
+    InvalidKey,
+}
+
+/// A secp256k1 public key.
+///
+/// It displays, and (de)serializes, as the hex of its compressed encoding.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PublicKey(secp256k1::PublicKey);
+
+impl PublicKey {
+    /// Reads a public key in the compressed, the uncompressed or the devp2p
+    /// encoding, telling them apart by their length.
+    pub fn from_slice(bytes: &[u8]) -> Result<PublicKey, PublicKeyError> {
+        let key = match bytes.len() {
+            33 | 65 => secp256k1::PublicKey::from_slice(bytes),
+            64 => {
+                let mut uncompressed = [0x04u8; 65];
+                uncompressed[1..].copy_from_slice(bytes);
+                secp256k1::PublicKey::from_slice(&uncompressed)
+            }
+            len => return Err(PublicKeyError::InvalidLength(len)),
+        };
+        key.map(PublicKey).map_err(|_| PublicKeyError::InvalidKey)
+    }
+
+    /// The SEC1 compressed encoding: `02` for an even y, `03` for an odd
+    /// one, then x.
+    pub fn to_compressed(&self) -> [u8; 33] {
+        self.0.serialize()
+    }
+
+    /// The SEC1 uncompressed encoding: `04`, then x and y.
+    pub fn to_uncompressed(&self) -> [u8; 65] {
+        self.0.serialize_uncompressed()
+    }
+
+    /// The uncompressed encoding without its `04`, which is the node id of
+    /// devp2p and what addresses are the hash of.
+    pub fn to_node_id(&self) -> [u8; 64] {
+        let mut id = [0u8; 64];
+        id.copy_from_slice(&self.to_uncompressed()[1..]);
+        id
+    }
+
+    /// The address of the key: the last 20 bytes of the hash of its node
+    /// id.
+    pub fn to_address(&self) -> Address {
+        // The hash is 32 bytes
+        Address::from_slice(&keccak256(&self.to_node_id())[12..]).unwrap()
+    }
+}
+
+impl From<secp256k1::PublicKey> for PublicKey {
+    fn from(key: secp256k1::PublicKey) -> PublicKey {
+        PublicKey(key)
+    }
+}
+
+impl From<PublicKey> for secp256k1::PublicKey {
+    fn from(key: PublicKey) -> secp256k1::PublicKey {
+        key.0
+    }
+}
+
+impl FromStr for PublicKey {
+    type Err = Error;
+
+    /// Parses the hex of any of the encodings, with or without `0x`.
+    fn from_str(s: &str) -> Result<PublicKey, Error> {
+        Ok(PublicKey::from_slice(&hex_str_to_bytes(s)?)?)
+    }
+}
+
+impl fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", bytes_to_hex_str(&self.to_compressed()))
+    }
+}
+
+impl fmt::Debug for PublicKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PublicKey({})", self)
+    }
+}
+
+impl Serialize for PublicKey {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for PublicKey {
+    fn deserialize<D>(deserializer: D) -> Result<PublicKey, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde_crate::de::Error::custom)
+    }
+}
+
+#[test]
+fn public_key_encodings() {
+    // The generator point, the public key of the private key 1
+    let compressed = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+    let uncompressed = concat!(
+        "04",
+        "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "483ada7726a3c4655da4fbfc0e1108a8fd17b448a68554199c47d08ffb10d4b8"
+    );
+    let key: PublicKey = compressed.parse().unwrap();
+    assert_eq!(bytes_to_hex_str(&key.to_uncompressed()), uncompressed);
+    assert_eq!(uncompressed.parse::<PublicKey>().unwrap(), key);
+    assert_eq!(PublicKey::from_slice(&key.to_node_id()).unwrap(), key);
+    assert_eq!(key.to_string(), format!("0x{}", compressed));
+    assert_eq!(
+        key.to_address(),
+        "0x7E5F4552091A69125d5DfCb7b8C2659029395Bdf"
+            .parse()
+            .unwrap()
+    );
+    let json = serde_json::to_string(&key).unwrap();
+    assert_eq!(serde_json::from_str::<PublicKey>(&json).unwrap(), key);
+
+    assert_eq!(
+        PublicKey::from_slice(&key.to_compressed()[1..]),
+        Err(PublicKeyError::InvalidLength(32))
+    );
+    // x of the generator with the prefix of an uncompressed key, and a point
+    // off the curve
+    let mut bad_prefix = key.to_compressed();
+    bad_prefix[0] = 0x04;
+    assert_eq!(
+        PublicKey::from_slice(&bad_prefix),
+        Err(PublicKeyError::InvalidKey)
+    );
+    let mut off_curve = key.to_uncompressed();
+    off_curve[64] ^= 1;
+    assert_eq!(
+        PublicKey::from_slice(&off_curve),
+        Err(PublicKeyError::InvalidKey)
+    );
+}
+
+#[test]
+fn recover_public_key() {
+    use private_key::PrivateKey;
+    let key = PrivateKey::from([0x42u8; 32]);
+    let hash = keccak256(b"Hello, world!");
+    let signature = key.sign_hash(&hash);
+    let public = signature.recover_public_key(&hash).unwrap();
+    assert_eq!(public, key.public_key().unwrap());
+    assert_eq!(public.to_address(), signature.recover(&hash).unwrap());
+}
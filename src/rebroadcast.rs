@@ -0,0 +1,279 @@
+//! Re-broadcasting pending transactions.
+//!
+//! Transactions get dropped from mempools, or sit there because their gas
+//! price is too low. A [Rebroadcaster](struct.Rebroadcaster.html) tracks
+//! the pending transactions of a signer and decides, block by block, which
+//! ones to send again and which ones to replace with a higher fee.
+//!
+//! Clarity does not talk to nodes. Feed it what was observed with
+//! [observe](struct.Rebroadcaster.html#method.observe), call
+//! [poll](struct.Rebroadcaster.html#method.poll) on every new block, and
+//! send what it returns.
+use address::Address;
+use failure::Error;
+use num256::Uint256;
+use private_key::PrivateKey;
+use std::collections::BTreeMap;
+use transaction::Transaction;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum RebroadcastError {
+    #[fail(display = "Transaction is not signed by the tracked signer")]
+    WrongSigner,
+    #[fail(display = "A transaction with nonce {} is already tracked", _0)]
+    NonceTracked(Uint256),
+}
+
+/// When to send pending transactions again, and when to raise their fee.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RebroadcastPolicy {
+    /// Blocks to wait before sending a pending transaction again
+    pub resend_interval: u64,
+    /// Blocks to wait after sending or replacing a transaction before
+    /// replacing it with a higher fee
+    pub bump_interval: u64,
+    /// How much each replacement raises the gas price, in percent. Nodes
+    /// require at least 10.
+    pub bump_percent: u32,
+    /// Gas price replacements never go above
+    pub max_gas_price: Uint256,
+}
+
+/// What was observed about a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InclusionStatus {
+    /// Included in the block with this number
+    Included(u64),
+    /// No longer known to the node
+    Dropped,
+}
+
+/// Something to do about a pending transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RebroadcastAction {
+    /// Send the transaction again
+    Resend(Transaction),
+    /// Send the replacement of a transaction, with a higher fee
+    Replace(Transaction),
+}
+
+#[derive(Debug, Clone)]
+struct Pending {
+    current: Transaction,
+    /// Hashes of every version that was sent, any of which may be included
+    hashes: Vec<Vec<u8>>,
+    last_sent: u64,
+    last_bumped: u64,
+    dropped: bool,
+}
+
+/// Keeps track of the pending transactions of a single signer.
+#[derive(Debug, Clone)]
+pub struct Rebroadcaster {
+    key: PrivateKey,
+    address: Address,
+    network_id: Option<u64>,
+    policy: RebroadcastPolicy,
+    pending: BTreeMap<Uint256, Pending>,
+}
+
+impl Rebroadcaster {
+    /// Replacements are signed with `key` for `network_id`.
+    pub fn new(
+        key: PrivateKey,
+        network_id: Option<u64>,
+        policy: RebroadcastPolicy,
+    ) -> Result<Rebroadcaster, Error> {
+        Ok(Rebroadcaster {
+            address: key.to_public_key()?,
+            key,
+            network_id,
+            policy,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    /// Starts tracking a signed transaction, sent in `block`.
+    pub fn track(&mut self, tx: Transaction, block: u64) -> Result<(), Error> {
+        if tx.signature.is_none() || tx.sender()? != self.address {
+            return Err(RebroadcastError::WrongSigner.into());
+        }
+        if self.pending.contains_key(&tx.nonce) {
+            return Err(RebroadcastError::NonceTracked(tx.nonce.clone()).into());
+        }
+        self.pending.insert(
+            tx.nonce.clone(),
+            Pending {
+                hashes: vec![tx.hash()],
+                current: tx,
+                last_sent: block,
+                last_bumped: block,
+                dropped: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Records what was observed about the transaction with this hash,
+    /// which may be any version of a replaced transaction. Once one is
+    /// included, its nonce and every nonce below it are done with.
+    pub fn observe(&mut self, hash: &[u8], status: InclusionStatus) {
+        let nonce = match self
+            .pending
+            .iter()
+            .find(|(_, pending)| pending.hashes.iter().any(|h| h[..] == *hash))
+        {
+            Some((nonce, _)) => nonce.clone(),
+            None => return,
+        };
+        match status {
+            InclusionStatus::Included(_) => {
+                self.pending = self.pending.split_off(&(nonce + 1u8.into()));
+            }
+            InclusionStatus::Dropped => {
+                if let Some(pending) = self.pending.get_mut(&nonce) {
+                    pending.dropped = true;
+                }
+            }
+        }
+    }
+
+    /// The transactions still pending, by nonce.
+    pub fn pending(&self) -> impl Iterator<Item = &Transaction> {
+        self.pending.values().map(|pending| &pending.current)
+    }
+
+    /// What to send at `block`, in nonce order.
+    ///
+    /// A transaction is replaced once the bump interval has passed, unless
+    /// that would go over the maximum gas price, and is otherwise sent
+    /// again once the resend interval has passed or it was dropped.
+    pub fn poll(&mut self, block: u64) -> Vec<RebroadcastAction> {
+        let mut actions = Vec::new();
+        let policy = &self.policy;
+        for pending in self.pending.values_mut() {
+            let replacement = if block >= pending.last_bumped + policy.bump_interval {
+                pending
+                    .current
+                    .bumped(policy.bump_percent)
+                    .filter(|tx| tx.gas_price <= policy.max_gas_price)
+            } else {
+                None
+            };
+            if let Some(replacement) = replacement {
+                let signed = replacement.sign(&self.key, self.network_id);
+                pending.hashes.push(signed.hash());
+                pending.current = signed.clone();
+                pending.last_bumped = block;
+                pending.last_sent = block;
+                pending.dropped = false;
+                actions.push(RebroadcastAction::Replace(signed));
+            } else if pending.dropped || block >= pending.last_sent + policy.resend_interval {
+                pending.last_sent = block;
+                pending.dropped = false;
+                actions.push(RebroadcastAction::Resend(pending.current.clone()));
+            }
+        }
+        actions
+    }
+}
+
+#[cfg(test)]
+fn test_rebroadcaster() -> (Rebroadcaster, Vec<Transaction>) {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let policy = RebroadcastPolicy {
+        resend_interval: 2,
+        bump_interval: 5,
+        bump_percent: 10,
+        max_gas_price: 1_300_000_000u64.into(),
+    };
+    let mut rebroadcaster = Rebroadcaster::new(key, Some(1), policy).unwrap();
+    let txs: Vec<Transaction> = (0..2u32)
+        .map(|nonce| {
+            Transaction {
+                nonce: nonce.into(),
+                gas_price: 1_000_000_000u64.into(),
+                gas_limit: 21_000u32.into(),
+                to: Address::from([0x11u8; 20]),
+                value: 1u32.into(),
+                data: Vec::new(),
+                signature: None,
+            }
+            .sign(&key, Some(1))
+        })
+        .collect();
+    for tx in &txs {
+        rebroadcaster.track(tx.clone(), 100).unwrap();
+    }
+    (rebroadcaster, txs)
+}
+
+#[test]
+fn resend_and_replace() {
+    let (mut rebroadcaster, txs) = test_rebroadcaster();
+    assert_eq!(rebroadcaster.poll(101), Vec::new());
+    assert_eq!(
+        rebroadcaster.poll(102),
+        vec![
+            RebroadcastAction::Resend(txs[0].clone()),
+            RebroadcastAction::Resend(txs[1].clone())
+        ]
+    );
+    rebroadcaster.observe(&txs[1].hash(), InclusionStatus::Dropped);
+    assert_eq!(
+        rebroadcaster.poll(103),
+        vec![RebroadcastAction::Resend(txs[1].clone())]
+    );
+
+    let actions = rebroadcaster.poll(105);
+    assert_eq!(actions.len(), 2);
+    for (action, tx) in actions.iter().zip(&txs) {
+        match *action {
+            RebroadcastAction::Replace(ref replacement) => {
+                assert_eq!(replacement.nonce, tx.nonce);
+                assert_eq!(replacement.gas_price, 1_100_000_000u64.into());
+                assert_eq!(replacement.sender().unwrap(), tx.sender().unwrap());
+            }
+            ref other => panic!("unexpected {:?}", other),
+        }
+    }
+    // The next bump would be 1.21 gwei, the one after over the maximum
+    assert!(matches!(
+        rebroadcaster.poll(110)[0],
+        RebroadcastAction::Replace(_)
+    ));
+    assert!(matches!(
+        rebroadcaster.poll(115)[0],
+        RebroadcastAction::Resend(_)
+    ));
+}
+
+#[test]
+fn inclusion_of_any_version() {
+    let (mut rebroadcaster, txs) = test_rebroadcaster();
+    rebroadcaster.poll(105);
+    // The original of the second transaction made it in after all, which
+    // also settles the first one
+    rebroadcaster.observe(&txs[1].hash(), InclusionStatus::Included(106));
+    assert_eq!(rebroadcaster.pending().count(), 0);
+    assert_eq!(rebroadcaster.poll(200), Vec::new());
+
+    let (mut rebroadcaster, txs) = test_rebroadcaster();
+    rebroadcaster.observe(&txs[0].hash(), InclusionStatus::Included(101));
+    assert_eq!(rebroadcaster.pending().collect::<Vec<_>>(), vec![&txs[1]]);
+    assert_eq!(
+        rebroadcaster
+            .track(txs[1].clone(), 101)
+            .unwrap_err()
+            .downcast::<RebroadcastError>()
+            .unwrap(),
+        RebroadcastError::NonceTracked(1u8.into())
+    );
+    let unsigned = Transaction {
+        signature: None,
+        ..txs[0].clone()
+    };
+    assert!(rebroadcaster.track(unsigned, 101).is_err());
+}
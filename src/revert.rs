@@ -0,0 +1,370 @@
+//! Decoding revert data.
+//!
+//! A reverted call returns either one of the errors built into Solidity,
+//! `Error(string)` and `Panic(uint256)`, or a custom error declared by one of
+//! the contracts it went through. Custom errors are only known by their
+//! selector, so they are registered in an [ErrorRegistry](struct.ErrorRegistry.html)
+//! per contract, which then finds the error matching revert data no matter
+//! which contract raised it.
+use abi::{decode_tokens, derive_method_id, parse_param_types, ParamType, Token};
+use failure::Error;
+use num256::Uint256;
+use num_traits::ToPrimitive;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Signature of the error `require` and `revert` with a message raise.
+pub const ERROR_STRING: &str = "Error(string)";
+/// Signature of the error raised by failed assertions, overflows and the
+/// like.
+pub const PANIC: &str = "Panic(uint256)";
+
+/// The codes of `Panic(uint256)`, as raised by code the Solidity compiler
+/// inserts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PanicCode {
+    /// 0x00, used for generic compiler inserted panics
+    Generic,
+    /// 0x01, a failed `assert`
+    AssertionFailed,
+    /// 0x11, arithmetic overflow or underflow outside of an `unchecked`
+    /// block
+    ArithmeticOverflow,
+    /// 0x12, division or modulo by zero
+    DivisionByZero,
+    /// 0x21, a value out of the range of an enum
+    InvalidEnumValue,
+    /// 0x22, a storage byte array that is incorrectly encoded
+    InvalidStorageByteArray,
+    /// 0x31, `pop()` on an empty array
+    EmptyArrayPop,
+    /// 0x32, an array, `bytesN` or slice index out of bounds
+    IndexOutOfBounds,
+    /// 0x41, too much memory allocated, or an array that is too large
+    OutOfMemory,
+    /// 0x51, a call to a zero initialized internal function variable
+    UninitializedFunction,
+}
+
+impl PanicCode {
+    /// The panic with code `code`, if it is one the compiler raises.
+    pub fn from_code(code: &Uint256) -> Option<PanicCode> {
+        Some(match code.to_u8()? {
+            0x00 => PanicCode::Generic,
+            0x01 => PanicCode::AssertionFailed,
+            0x11 => PanicCode::ArithmeticOverflow,
+            0x12 => PanicCode::DivisionByZero,
+            0x21 => PanicCode::InvalidEnumValue,
+            0x22 => PanicCode::InvalidStorageByteArray,
+            0x31 => PanicCode::EmptyArrayPop,
+            0x32 => PanicCode::IndexOutOfBounds,
+            0x41 => PanicCode::OutOfMemory,
+            0x51 => PanicCode::UninitializedFunction,
+            _ => return None,
+        })
+    }
+
+    pub fn code(self) -> u8 {
+        match self {
+            PanicCode::Generic => 0x00,
+            PanicCode::AssertionFailed => 0x01,
+            PanicCode::ArithmeticOverflow => 0x11,
+            PanicCode::DivisionByZero => 0x12,
+            PanicCode::InvalidEnumValue => 0x21,
+            PanicCode::InvalidStorageByteArray => 0x22,
+            PanicCode::EmptyArrayPop => 0x31,
+            PanicCode::IndexOutOfBounds => 0x32,
+            PanicCode::OutOfMemory => 0x41,
+            PanicCode::UninitializedFunction => 0x51,
+        }
+    }
+}
+
+impl fmt::Display for PanicCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let description = match *self {
+            PanicCode::Generic => "generic compiler panic",
+            PanicCode::AssertionFailed => "assertion failed",
+            PanicCode::ArithmeticOverflow => "arithmetic overflow or underflow",
+            PanicCode::DivisionByZero => "division or modulo by zero",
+            PanicCode::InvalidEnumValue => "conversion to an invalid enum value",
+            PanicCode::InvalidStorageByteArray => "incorrectly encoded storage byte array",
+            PanicCode::EmptyArrayPop => "pop on an empty array",
+            PanicCode::IndexOutOfBounds => "index out of bounds",
+            PanicCode::OutOfMemory => "out of memory",
+            PanicCode::UninitializedFunction => "call to an uninitialized function",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// A custom error, as declared by a contract.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomError {
+    /// Name the contract was registered with
+    pub contract: String,
+    /// Signature of the error, such as `InsufficientBalance(uint256,uint256)`
+    pub signature: String,
+    pub args: Vec<Token>,
+}
+
+/// Decoded revert data.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Revert {
+    /// `Error(string)`, the message of a `require` or `revert`
+    Message(String),
+    /// `Panic(uint256)`, with the panic code
+    Panic(Uint256),
+    Custom(CustomError),
+    /// The call reverted without data, e.g. a bare `revert()`
+    Empty,
+    /// Data that does not match any known error
+    Unknown(Vec<u8>),
+}
+
+impl Revert {
+    /// The kind of panic, for a `Panic(uint256)` with a known code.
+    pub fn panic_code(&self) -> Option<PanicCode> {
+        match *self {
+            Revert::Panic(ref code) => PanicCode::from_code(code),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Revert {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Revert::Message(ref message) => write!(f, "Reverted: {}", message),
+            Revert::Panic(ref code) => match PanicCode::from_code(code) {
+                Some(panic) => write!(f, "Panicked with code {:#x}: {}", code, panic),
+                None => write!(f, "Panicked with code {:#x}", code),
+            },
+            Revert::Custom(ref error) => {
+                write!(f, "Reverted with {}.{}", error.contract, error.signature)
+            }
+            Revert::Empty => write!(f, "Reverted without a reason"),
+            Revert::Unknown(ref data) => {
+                write!(f, "Reverted with {} bytes of unknown data", data.len())
+            }
+        }
+    }
+}
+
+/// Decodes the built in errors only.
+pub fn decode_revert(data: &[u8]) -> Revert {
+    ErrorRegistry::new().decode(data)
+}
+
+#[derive(Debug, Clone)]
+struct RegisteredError {
+    contract: String,
+    signature: String,
+    types: Vec<ParamType>,
+}
+
+/// Custom errors of several contracts, searched by selector.
+#[derive(Debug, Clone, Default)]
+pub struct ErrorRegistry {
+    errors: HashMap<[u8; 4], Vec<RegisteredError>>,
+}
+
+impl ErrorRegistry {
+    pub fn new() -> ErrorRegistry {
+        ErrorRegistry::default()
+    }
+
+    /// Registers a custom error declared by `contract`. The name of the
+    /// contract is only used to report where an error comes from.
+    pub fn register(&mut self, contract: &str, signature: &str) -> Result<(), Error> {
+        let types = parse_param_types(signature)?;
+        let entries = self.errors.entry(derive_method_id(signature)).or_default();
+        if !entries
+            .iter()
+            .any(|e| e.contract == contract && e.signature == signature)
+        {
+            entries.push(RegisteredError {
+                contract: contract.to_string(),
+                signature: signature.to_string(),
+                types,
+            });
+        }
+        Ok(())
+    }
+
+    /// Registers all custom errors of a contract.
+    pub fn register_all(&mut self, contract: &str, signatures: &[&str]) -> Result<(), Error> {
+        for signature in signatures {
+            self.register(contract, signature)?;
+        }
+        Ok(())
+    }
+
+    /// All registered errors that decode `data`, in the order they were
+    /// registered. Several contracts can declare the same error, and
+    /// unrelated errors can share a selector.
+    pub fn matches(&self, data: &[u8]) -> Vec<CustomError> {
+        if data.len() < 4 {
+            return Vec::new();
+        }
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&data[..4]);
+        self.errors
+            .get(&selector)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|entry| {
+                        decode_tokens(&entry.types, &data[4..])
+                            .ok()
+                            .map(|args| CustomError {
+                                contract: entry.contract.clone(),
+                                signature: entry.signature.clone(),
+                                args,
+                            })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Decodes revert data, trying the built in errors first and then the
+    /// registered ones. If several registered errors match, the one
+    /// registered first is picked; see [matches](#method.matches).
+    pub fn decode(&self, data: &[u8]) -> Revert {
+        if data.is_empty() {
+            return Revert::Empty;
+        }
+        if data.len() >= 4 {
+            let args = &data[4..];
+            if data[..4] == derive_method_id(ERROR_STRING) {
+                if let Ok(mut tokens) = decode_tokens(&[ParamType::String], args) {
+                    if let Some(Token::String(message)) = tokens.pop() {
+                        return Revert::Message(message);
+                    }
+                }
+            } else if data[..4] == derive_method_id(PANIC) {
+                if let Ok(mut tokens) = decode_tokens(&[ParamType::Uint(256)], args) {
+                    if let Some(Token::Uint(code)) = tokens.pop() {
+                        return Revert::Panic(code);
+                    }
+                }
+            }
+        }
+        match self.matches(data).into_iter().next() {
+            Some(error) => Revert::Custom(error),
+            None => Revert::Unknown(data.to_vec()),
+        }
+    }
+}
+
+#[test]
+fn decode_builtin_errors() {
+    use abi::encode_call;
+    let message = encode_call(
+        ERROR_STRING,
+        &[Token::String(
+            "Ownable: caller is not the owner".to_string(),
+        )],
+    );
+    assert_eq!(
+        decode_revert(&message),
+        Revert::Message("Ownable: caller is not the owner".to_string())
+    );
+    // An arithmetic overflow
+    let panic = encode_call(PANIC, &[0x11u8.into()]);
+    assert_eq!(decode_revert(&panic), Revert::Panic(0x11u8.into()));
+    assert_eq!(
+        decode_revert(&panic).panic_code(),
+        Some(PanicCode::ArithmeticOverflow)
+    );
+    assert_eq!(
+        decode_revert(&panic).to_string(),
+        "Panicked with code 0x11: arithmetic overflow or underflow"
+    );
+    // Codes the compiler does not raise are still reported
+    let unknown = decode_revert(&encode_call(PANIC, &[0x99u8.into()]));
+    assert_eq!(unknown.panic_code(), None);
+    assert_eq!(unknown.to_string(), "Panicked with code 0x99");
+    assert_eq!(decode_revert(&[]), Revert::Empty);
+    assert_eq!(decode_revert(&message).panic_code(), None);
+    assert_eq!(
+        decode_revert(&[0xde, 0xad]),
+        Revert::Unknown(vec![0xde, 0xad])
+    );
+    // Truncated data is not mistaken for a message
+    assert_eq!(
+        decode_revert(&message[..40]),
+        Revert::Unknown(message[..40].to_vec())
+    );
+}
+
+#[test]
+fn decode_custom_errors() {
+    use abi::encode_call;
+    use address::Address;
+    let mut registry = ErrorRegistry::new();
+    registry
+        .register_all(
+            "Vault",
+            &[
+                "InsufficientBalance(uint256,uint256)",
+                "Unauthorized(address)",
+            ],
+        )
+        .unwrap();
+    registry.register("Token", "Unauthorized(address)").unwrap();
+    assert!(registry.register("Token", "Broken(uint7)").is_err());
+
+    let data = encode_call(
+        "InsufficientBalance(uint256,uint256)",
+        &[10u8.into(), 20u8.into()],
+    );
+    assert_eq!(
+        registry.decode(&data),
+        Revert::Custom(CustomError {
+            contract: "Vault".to_string(),
+            signature: "InsufficientBalance(uint256,uint256)".to_string(),
+            args: vec![10u8.into(), 20u8.into()],
+        })
+    );
+
+    let caller = Address::from([0x11u8; 20]);
+    let data = encode_call("Unauthorized(address)", &[Token::Address(caller)]);
+    let matches = registry.matches(&data);
+    assert_eq!(
+        matches
+            .iter()
+            .map(|error| error.contract.as_str())
+            .collect::<Vec<_>>(),
+        vec!["Vault", "Token"]
+    );
+    assert_eq!(
+        registry.decode(&data).to_string(),
+        "Reverted with Vault.Unauthorized(address)"
+    );
+
+    // Arguments that do not decode do not match
+    assert_eq!(
+        registry.decode(&data[..20]),
+        Revert::Unknown(data[..20].to_vec())
+    );
+}
+
+#[test]
+fn panic_codes() {
+    for code in 0..=0xffu8 {
+        if let Some(panic) = PanicCode::from_code(&code.into()) {
+            assert_eq!(panic.code(), code);
+        }
+    }
+    assert_eq!(
+        PanicCode::from_code(&0x32u8.into()),
+        Some(PanicCode::IndexOutOfBounds)
+    );
+    assert_eq!(PanicCode::from_code(&0x132u16.into()), None);
+    assert_eq!(
+        PanicCode::DivisionByZero.to_string(),
+        "division or modulo by zero"
+    );
+}
@@ -0,0 +1,479 @@
+//! Risk annotations for transactions about to be signed.
+//!
+//! Approval UIs want to warn about a few well known dangerous patterns,
+//! and the calldata has to be decoded to find them.
+//! [annotate](fn.annotate.html) does that and returns structured flags,
+//! which also have a human readable `Display` form.
+//!
+//! Address poisoning, where an attacker plants lookalikes of addresses the
+//! signer uses in their history, is caught separately by
+//! [check_lookalikes](fn.check_lookalikes.html), since it needs the
+//! addresses themselves rather than just whether one is known.
+use abi::{decode_tokens, derive_method_id, parse_param_types, Token};
+use address::Address;
+use erc20::{decode_approve, unlimited_approval_threshold};
+use num256::Uint256;
+use std::collections::HashSet;
+use std::fmt;
+use transaction::TypedTransaction;
+use utils::bytes_to_hex_str;
+
+const TRANSFER: &str = "transfer(address,uint256)";
+const TRANSFER_FROM: &str = "transferFrom(address,address,uint256)";
+const INCREASE_ALLOWANCE: &str = "increaseAllowance(address,uint256)";
+const SET_APPROVAL_FOR_ALL: &str = "setApprovalForAll(address,bool)";
+const SAFE_EXEC_TRANSACTION: &str =
+    "execTransaction(address,uint256,bytes,uint8,uint256,uint256,uint256,address,address,bytes)";
+
+/// Something that knows which addresses the signer has interacted with
+/// before, such as an address book or the transaction history.
+pub trait KnownAddresses {
+    fn is_known(&self, address: &Address) -> bool;
+}
+
+impl KnownAddresses for HashSet<Address> {
+    fn is_known(&self, address: &Address) -> bool {
+        self.contains(address)
+    }
+}
+
+impl<F> KnownAddresses for F
+where
+    F: Fn(&Address) -> bool,
+{
+    fn is_known(&self, address: &Address) -> bool {
+        self(address)
+    }
+}
+
+/// A risky pattern found in a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Risk {
+    /// An ERC-20 approval (or allowance increase) for an amount too large
+    /// to be meant, which lets the spender move every token, now and in the
+    /// future
+    UnlimitedApproval { token: Address, spender: Address },
+    /// `setApprovalForAll(operator, true)`, which lets the operator move
+    /// every NFT of the collection
+    ApprovalForAll {
+        collection: Address,
+        operator: Address,
+    },
+    /// A Safe (Gnosis Safe) transaction with the `DelegateCall` operation,
+    /// which runs the target's code with full control over the Safe
+    SafeDelegateCall { safe: Address, target: Address },
+    /// The transaction goes to an address that is not known to the signer
+    UnknownDestination(Address),
+    /// The transaction, or the token transfer it makes, goes to an address
+    /// that looks like one the signer used before but is not it
+    LookalikeDestination {
+        destination: Address,
+        lookalike: Address,
+    },
+}
+
+impl fmt::Display for Risk {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Risk::UnlimitedApproval { token, spender } => write!(
+                f,
+                "Grants {} an unlimited allowance of token {}",
+                spender, token
+            ),
+            Risk::ApprovalForAll {
+                collection,
+                operator,
+            } => write!(
+                f,
+                "Lets {} transfer all of your tokens in collection {}",
+                operator, collection
+            ),
+            Risk::SafeDelegateCall { safe, target } => write!(
+                f,
+                "Makes Safe {} delegatecall into {}, giving it full control of the Safe",
+                safe, target
+            ),
+            Risk::UnknownDestination(address) => {
+                write!(f, "Sends to {}, which you have not used before", address)
+            }
+            Risk::LookalikeDestination {
+                destination,
+                lookalike,
+            } => write!(
+                f,
+                "Sends to {}, which looks like {} but is a different address",
+                destination, lookalike
+            ),
+        }
+    }
+}
+
+/// Decodes the arguments of `data` if it is a call of `signature`.
+fn decode_call(data: &[u8], signature: &str) -> Option<Vec<Token>> {
+    if data.len() < 4 || data[..4] != derive_method_id(signature) {
+        return None;
+    }
+    let types = parse_param_types(signature).expect("Signatures are valid");
+    decode_tokens(&types, &data[4..]).ok()
+}
+
+/// Looks for risky patterns in a transaction before it is signed.
+///
+/// Approvals are unlimited from
+/// [unlimited_approval_threshold](../erc20/fn.unlimited_approval_threshold.html)
+/// on, see [annotate_with_threshold](fn.annotate_with_threshold.html) to
+/// flag smaller ones. Calldata that claims to be one of the recognized
+/// calls but does not decode is not flagged, as a contract would reject it
+/// as well.
+pub fn annotate<K: KnownAddresses>(tx: &TypedTransaction, known: &K) -> Vec<Risk> {
+    annotate_with_threshold(tx, known, &unlimited_approval_threshold())
+}
+
+/// Like [annotate](fn.annotate.html), flagging approvals of at least
+/// `threshold` as unlimited, such as an amount the signer would never mean
+/// to approve of a given token.
+pub fn annotate_with_threshold<K: KnownAddresses>(
+    tx: &TypedTransaction,
+    known: &K,
+    threshold: &Uint256,
+) -> Vec<Risk> {
+    let (to, data) = (*tx.to(), tx.data());
+    let mut risks = Vec::new();
+    if to != Address::default() && !known.is_known(&to) {
+        risks.push(Risk::UnknownDestination(to));
+    }
+    if let Some((spender, amount)) = decode_approve(data) {
+        if amount >= *threshold {
+            risks.push(Risk::UnlimitedApproval { token: to, spender });
+        }
+    }
+    if let Some(args) = decode_call(data, INCREASE_ALLOWANCE) {
+        if let (&Token::Address(spender), Token::Uint(amount)) = (&args[0], &args[1]) {
+            if amount >= threshold {
+                risks.push(Risk::UnlimitedApproval { token: to, spender });
+            }
+        }
+    }
+    if let Some(args) = decode_call(data, SET_APPROVAL_FOR_ALL) {
+        if let (&Token::Address(operator), &Token::Bool(true)) = (&args[0], &args[1]) {
+            risks.push(Risk::ApprovalForAll {
+                collection: to,
+                operator,
+            });
+        }
+    }
+    if let Some(args) = decode_call(data, SAFE_EXEC_TRANSACTION) {
+        if let (&Token::Address(target), Token::Uint(operation)) = (&args[0], &args[3]) {
+            if *operation == 1u8.into() {
+                risks.push(Risk::SafeDelegateCall { safe: to, target });
+            }
+        }
+    }
+    risks
+}
+
+/// How many leading and trailing hex digits two addresses have to share
+/// to look alike. Wallets often shorten addresses to just those, which is
+/// what address poisoning relies on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LookalikeThreshold {
+    pub prefix: usize,
+    pub suffix: usize,
+}
+
+impl Default for LookalikeThreshold {
+    fn default() -> LookalikeThreshold {
+        LookalikeThreshold {
+            prefix: 4,
+            suffix: 4,
+        }
+    }
+}
+
+/// The addresses of `history` that look like `address` without being it.
+pub fn lookalikes<'a, I>(
+    address: &Address,
+    history: I,
+    threshold: LookalikeThreshold,
+) -> Vec<Address>
+where
+    I: IntoIterator<Item = &'a Address>,
+{
+    let hex = bytes_to_hex_str(address.as_bytes());
+    let prefix = threshold.prefix.min(hex.len());
+    let suffix = threshold.suffix.min(hex.len());
+    let mut found: Vec<Address> = Vec::new();
+    for candidate in history {
+        if candidate == address || found.contains(candidate) {
+            continue;
+        }
+        let other = bytes_to_hex_str(candidate.as_bytes());
+        if hex[..prefix] == other[..prefix]
+            && hex[hex.len() - suffix..] == other[other.len() - suffix..]
+        {
+            found.push(*candidate);
+        }
+    }
+    found
+}
+
+/// Flags the destinations of a transaction that look like addresses of
+/// `history` without being them: the address it is sent to, and the
+/// recipient of an ERC-20 `transfer` or `transferFrom`.
+pub fn check_lookalikes(
+    tx: &TypedTransaction,
+    history: &[Address],
+    threshold: LookalikeThreshold,
+) -> Vec<Risk> {
+    let mut destinations = Vec::new();
+    if *tx.to() != Address::default() {
+        destinations.push(*tx.to());
+    }
+    if let Some(args) = decode_call(tx.data(), TRANSFER) {
+        if let Token::Address(recipient) = args[0] {
+            destinations.push(recipient);
+        }
+    }
+    if let Some(args) = decode_call(tx.data(), TRANSFER_FROM) {
+        if let Token::Address(recipient) = args[1] {
+            destinations.push(recipient);
+        }
+    }
+    let mut risks = Vec::new();
+    for destination in destinations {
+        // An address from the history is what the signer meant
+        if history.contains(&destination) {
+            continue;
+        }
+        for lookalike in lookalikes(&destination, history, threshold) {
+            risks.push(Risk::LookalikeDestination {
+                destination,
+                lookalike,
+            });
+        }
+    }
+    risks
+}
+
+#[cfg(test)]
+fn call(to: Address, data: Vec<u8>) -> TypedTransaction {
+    use transaction::Transaction;
+    Transaction {
+        nonce: 0u8.into(),
+        gas_price: 1_000_000_000u64.into(),
+        gas_limit: 100_000u32.into(),
+        to,
+        value: 0u8.into(),
+        data,
+        signature: None,
+    }
+    .into()
+}
+
+#[cfg(test)]
+fn everything_known(_: &Address) -> bool {
+    true
+}
+
+#[test]
+fn flags_unlimited_approval() {
+    use abi::encode_call;
+    use erc20::approve;
+    use num_traits::Bounded;
+    let token = Address::from([0x11u8; 20]);
+    let spender = Address::from([0x22u8; 20]);
+    let unlimited = call(token, approve(spender, Uint256::max_value()));
+    assert_eq!(
+        annotate(&unlimited, &everything_known),
+        vec![Risk::UnlimitedApproval { token, spender }]
+    );
+    let increase = call(
+        token,
+        encode_call(
+            INCREASE_ALLOWANCE,
+            &[Token::Address(spender), Token::Uint(Uint256::max_value())],
+        ),
+    );
+    assert_eq!(annotate(&increase, &everything_known).len(), 1);
+    let limited = call(token, approve(spender, 1000u32.into()));
+    assert_eq!(annotate(&limited, &everything_known), vec![]);
+    assert_eq!(
+        annotate_with_threshold(&limited, &everything_known, &1000u32.into()),
+        vec![Risk::UnlimitedApproval { token, spender }]
+    );
+    // Anything from 2^128 on is as good as unlimited
+    let almost = Uint256::max_value() - 1u8.into();
+    for amount in &[almost, unlimited_approval_threshold()] {
+        let approval = call(token, approve(spender, amount.clone()));
+        assert_eq!(annotate(&approval, &everything_known).len(), 1);
+    }
+    let below = unlimited_approval_threshold() - 1u8.into();
+    assert_eq!(
+        annotate(&call(token, approve(spender, below)), &everything_known),
+        vec![]
+    );
+}
+
+#[test]
+fn annotate_typed_transactions() {
+    use erc20::approve;
+    use transaction::Eip1559Transaction;
+    let token = Address::from([0x11u8; 20]);
+    let spender = Address::from([0x22u8; 20]);
+    let tx: TypedTransaction = Eip1559Transaction {
+        chain_id: 1u8.into(),
+        nonce: 0u8.into(),
+        max_priority_fee_per_gas: 1u8.into(),
+        max_fee_per_gas: 100u8.into(),
+        gas_limit: 60_000u32.into(),
+        to: token,
+        value: 0u8.into(),
+        data: approve(spender, unlimited_approval_threshold()),
+        access_list: Vec::new(),
+        signature: None,
+    }
+    .into();
+    assert_eq!(
+        annotate(&tx, &|_: &Address| false),
+        vec![
+            Risk::UnknownDestination(token),
+            Risk::UnlimitedApproval { token, spender }
+        ]
+    );
+}
+
+#[test]
+fn flags_approval_for_all() {
+    use abi::encode_call;
+    let collection = Address::from([0x11u8; 20]);
+    let operator = Address::from([0x22u8; 20]);
+    let grant = call(
+        collection,
+        encode_call(
+            SET_APPROVAL_FOR_ALL,
+            &[Token::Address(operator), true.into()],
+        ),
+    );
+    assert_eq!(
+        annotate(&grant, &everything_known),
+        vec![Risk::ApprovalForAll {
+            collection,
+            operator
+        }]
+    );
+    // Revoking is harmless
+    let revoke = call(
+        collection,
+        encode_call(
+            SET_APPROVAL_FOR_ALL,
+            &[Token::Address(operator), false.into()],
+        ),
+    );
+    assert_eq!(annotate(&revoke, &everything_known), vec![]);
+}
+
+#[test]
+fn flags_safe_delegate_call() {
+    use abi::encode_call;
+    let safe = Address::from([0x11u8; 20]);
+    let target = Address::from([0x22u8; 20]);
+    let exec = |operation: u8| {
+        call(
+            safe,
+            encode_call(
+                SAFE_EXEC_TRANSACTION,
+                &[
+                    Token::Address(target),
+                    0u8.into(),
+                    Token::UnboundedBytes(vec![0xde, 0xad, 0xbe, 0xef]),
+                    operation.into(),
+                    0u8.into(),
+                    0u8.into(),
+                    0u8.into(),
+                    Token::Address(Address::default()),
+                    Token::Address(Address::default()),
+                    Token::UnboundedBytes(vec![0u8; 65]),
+                ],
+            ),
+        )
+    };
+    assert_eq!(
+        annotate(&exec(1), &everything_known),
+        vec![Risk::SafeDelegateCall { safe, target }]
+    );
+    assert_eq!(annotate(&exec(0), &everything_known), vec![]);
+}
+
+#[test]
+fn flags_unknown_destination() {
+    let known: HashSet<Address> = vec![Address::from([0x11u8; 20])].into_iter().collect();
+    assert_eq!(
+        annotate(&call(Address::from([0x11u8; 20]), Vec::new()), &known),
+        vec![]
+    );
+    let unknown = Address::from([0x33u8; 20]);
+    let risks = annotate(&call(unknown, Vec::new()), &known);
+    assert_eq!(risks, vec![Risk::UnknownDestination(unknown)]);
+    assert_eq!(
+        risks[0].to_string(),
+        "Sends to 0x3333333333333333333333333333333333333333, which you have not used before"
+    );
+    // Contract deployments have no destination
+    assert_eq!(
+        annotate(&call(Address::default(), vec![0x60]), &known),
+        vec![]
+    );
+}
+
+#[test]
+fn ignores_undecodable_calls() {
+    use erc20::APPROVE;
+    let mut data = derive_method_id(APPROVE).to_vec();
+    data.extend_from_slice(&[0xffu8; 10]);
+    assert_eq!(
+        annotate(&call(Address::from([0x11u8; 20]), data), &everything_known),
+        vec![]
+    );
+}
+
+#[test]
+fn flags_lookalike_destinations() {
+    use abi::encode_call;
+    let parse = |s: &str| s.parse::<Address>().unwrap();
+    let friend = parse("0x1234a0000000000000000000000000000000abcd");
+    let poisoned = parse("0x1234bfffffffffffffffffffffffffffffffabcd");
+    let history = vec![friend, parse("0x9999000000000000000000000000000000009999")];
+    let threshold = LookalikeThreshold::default();
+
+    assert_eq!(
+        check_lookalikes(&call(poisoned, Vec::new()), &history, threshold),
+        vec![Risk::LookalikeDestination {
+            destination: poisoned,
+            lookalike: friend,
+        }]
+    );
+    assert_eq!(
+        check_lookalikes(&call(friend, Vec::new()), &history, threshold),
+        vec![]
+    );
+    // A stricter threshold tells them apart
+    let strict = LookalikeThreshold {
+        prefix: 5,
+        suffix: 4,
+    };
+    assert_eq!(lookalikes(&poisoned, &history, strict), vec![]);
+
+    // Token transfers are checked by recipient
+    let token = Address::from([0x11u8; 20]);
+    let transfer = call(
+        token,
+        encode_call(TRANSFER, &[Token::Address(poisoned), 100u32.into()]),
+    );
+    let risks = check_lookalikes(&transfer, &history, threshold);
+    assert_eq!(risks.len(), 1);
+    assert_eq!(
+        risks[0].to_string(),
+        "Sends to 0x1234bfffffffffffffffffffffffffffffffabcd, which looks like \
+         0x1234a0000000000000000000000000000000abcd but is a different address"
+    );
+}
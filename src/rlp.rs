@@ -1,12 +1,485 @@
-//! A module that defines how types serialize into RLP.
+//! Recursive Length Prefix encoding, the serialization of transactions,
+//! blocks, receipts and trie nodes.
 //!
-//! This module is left as private as this is considered a implementation detail
-//! of Clarity without any intention to be available outside.
+//! Types that encode to RLP implement [RlpEncode](trait.RlpEncode.html), and
+//! types that decode from it [RlpDecode](trait.RlpDecode.html). Integers
+//! encode as their big endian bytes without leading zeros, byte strings as
+//! `ByteBuf`, fixed size byte arrays or `Address`, and lists as `Vec`s of
+//! one item type or as tuples of mixed ones. Structures with more fields
+//! than a tuple holds implement the traits themselves, with
+//! [encode_list](fn.encode_list.html) and
+//! [RlpItem::as_list](enum.RlpItem.html#method.as_list):
 //!
-//! RLP encoder requires a binary data to be encoded in a well specified method.
+//! ```rust
+//! use clarity::rlp::{decode, encode, RlpDecode, RlpDecodeError, RlpEncode, RlpItem};
+//! use clarity::rlp::encode_list;
+//!
+//! #[derive(Debug, PartialEq)]
+//! struct Log {
+//!     address: [u8; 20],
+//!     topics: Vec<[u8; 32]>,
+//! }
+//!
+//! impl RlpEncode for Log {
+//!     fn rlp_append(&self, out: &mut Vec<u8>) {
+//!         out.extend(encode_list(&[&self.address, &self.topics]));
+//!     }
+//! }
+//!
+//! impl RlpDecode for Log {
+//!     fn rlp_decode(item: &RlpItem) -> Result<Log, RlpDecodeError> {
+//!         match item.as_list()? {
+//!             [address, topics] => Ok(Log {
+//!                 address: RlpDecode::rlp_decode(address)?,
+//!                 topics: RlpDecode::rlp_decode(topics)?,
+//!             }),
+//!             items => Err(RlpDecodeError::ListLength(2, items.len())),
+//!         }
+//!     }
+//! }
+//!
+//! let log = Log { address: [0x11; 20], topics: vec![[0x22; 32]] };
+//! assert_eq!(decode::<Log>(&encode(&log)).unwrap(), log);
+//! ```
+//!
+//! RLP of unknown structure decodes to a [Value](enum.Value.html), which
+//! can be looked into first.
+//!
+//! Decoding is done by hand rather than through `serde_rlp`, since its
+//! deserializer panics on truncated input. Transactions are still encoded
+//! through `serde_rlp`, with the helpers at the end of this module.
 use address::Address;
-use serde::Serialize;
-use serde::Serializer;
+use num256::Uint256;
+use serde_bytes::ByteBuf;
+use serde_crate::Serialize;
+use serde_crate::Serializer;
+use std::fmt;
+use utils::{bytes_to_hex_str, uint256_be_bytes};
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum RlpDecodeError {
+    #[fail(display = "Unexpected end of RLP data")]
+    UnexpectedEnd,
+    #[fail(display = "Expected an RLP list")]
+    ExpectedList,
+    #[fail(display = "Expected an RLP string")]
+    ExpectedString,
+    #[fail(display = "Trailing bytes after RLP item")]
+    TrailingBytes,
+    #[fail(display = "RLP item is not canonically encoded")]
+    NonCanonical,
+    #[fail(display = "RLP lists are nested too deeply")]
+    TooDeep,
+    #[fail(display = "RLP integer does not fit its type")]
+    IntegerOverflow,
+    #[fail(display = "Expected {} bytes but found {}", _0, _1)]
+    InvalidLength(usize, usize),
+    #[fail(display = "Expected a list of {} items but found {}", _0, _1)]
+    ListLength(usize, usize),
+    #[fail(display = "RLP item of {} bytes is too long", _0)]
+    ItemTooLong(usize),
+    #[fail(display = "RLP list has more than {} items", _0)]
+    TooManyItems(usize),
+}
+
+/// Lists can be nested this deep, far more than any transaction needs.
+pub(crate) const MAX_DEPTH: usize = 16;
+
+/// Limits on what is decoded, for input that is not trusted.
+///
+/// Every limit is checked before anything is allocated for the item it
+/// applies to, so input that breaks one costs no more than reading up to
+/// it. The default limits nesting only, as [decode_item](fn.decode_item.html)
+/// does.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeConfig {
+    /// How deep lists can be nested, the outermost list being at depth 1
+    pub max_depth: usize,
+    /// Longest payload of any string or list, in bytes
+    pub max_item_len: usize,
+    /// Most items in any one list
+    pub max_list_items: usize,
+}
+
+impl Default for DecodeConfig {
+    fn default() -> DecodeConfig {
+        DecodeConfig {
+            max_depth: MAX_DEPTH,
+            max_item_len: usize::MAX,
+            max_list_items: usize::MAX,
+        }
+    }
+}
+
+/// A decoded RLP item, borrowing the payloads of its strings.
+#[derive(Debug, PartialEq)]
+pub enum RlpItem<'a> {
+    Bytes(&'a [u8]),
+    List(Vec<RlpItem<'a>>),
+}
+
+impl<'a> RlpItem<'a> {
+    /// The payload of a string.
+    pub fn as_bytes(&self) -> Result<&'a [u8], RlpDecodeError> {
+        match *self {
+            RlpItem::Bytes(bytes) => Ok(bytes),
+            RlpItem::List(_) => Err(RlpDecodeError::ExpectedString),
+        }
+    }
+
+    /// The items of a list.
+    pub fn as_list(&self) -> Result<&[RlpItem<'a>], RlpDecodeError> {
+        match *self {
+            RlpItem::List(ref items) => Ok(items),
+            RlpItem::Bytes(_) => Err(RlpDecodeError::ExpectedList),
+        }
+    }
+}
+
+/// A type that can be encoded as RLP.
+pub trait RlpEncode {
+    /// Appends the encoding of `self` to `out`.
+    fn rlp_append(&self, out: &mut Vec<u8>);
+}
+
+/// A type that can be decoded from RLP.
+pub trait RlpDecode: Sized {
+    fn rlp_decode(item: &RlpItem) -> Result<Self, RlpDecodeError>;
+}
+
+/// The RLP encoding of `value`.
+pub fn encode<T: RlpEncode + ?Sized>(value: &T) -> Vec<u8> {
+    let mut out = Vec::new();
+    value.rlp_append(&mut out);
+    out
+}
+
+/// Decodes a value from its RLP encoding, which has to be all of `input`.
+pub fn decode<T: RlpDecode>(input: &[u8]) -> Result<T, RlpDecodeError> {
+    T::rlp_decode(&decode_item(input)?)
+}
+
+/// Decodes a value like [decode](fn.decode.html), within the limits of
+/// `config`.
+pub fn decode_with<T: RlpDecode>(input: &[u8], config: &DecodeConfig) -> Result<T, RlpDecodeError> {
+    T::rlp_decode(&decode_item_with(input, config)?)
+}
+
+/// The encoding of a list of items of any types.
+pub fn encode_list(items: &[&dyn RlpEncode]) -> Vec<u8> {
+    let mut payload = Vec::new();
+    for item in items {
+        item.rlp_append(&mut payload);
+    }
+    let mut out = Vec::with_capacity(payload.len() + 9);
+    append_header(0xc0, payload.len(), &mut out);
+    out.extend(payload);
+    out
+}
+
+/// Appends the header of a string (`offset` 0x80) or a list (0xc0) whose
+/// payload is `length` bytes.
+pub(crate) fn append_header(offset: u8, length: usize, out: &mut Vec<u8>) {
+    if length <= 55 {
+        out.push(offset + length as u8);
+    } else {
+        let length_bytes = (length as u64).to_be_bytes();
+        let skip = length_bytes.iter().take_while(|b| **b == 0).count();
+        out.push(offset + 55 + (8 - skip) as u8);
+        out.extend_from_slice(&length_bytes[skip..]);
+    }
+}
+
+pub(crate) fn append_bytes(bytes: &[u8], out: &mut Vec<u8>) {
+    if bytes.len() == 1 && bytes[0] < 0x80 {
+        out.push(bytes[0]);
+    } else {
+        append_header(0x80, bytes.len(), out);
+        out.extend_from_slice(bytes);
+    }
+}
+
+/// The big endian bytes of an integer, stripped of leading zeros, which
+/// have to fit `size` bytes.
+fn decode_integer<'a>(item: &RlpItem<'a>, size: usize) -> Result<&'a [u8], RlpDecodeError> {
+    let bytes = item.as_bytes()?;
+    if bytes.first() == Some(&0) {
+        return Err(RlpDecodeError::NonCanonical);
+    }
+    if bytes.len() > size {
+        return Err(RlpDecodeError::IntegerOverflow);
+    }
+    Ok(bytes)
+}
+
+macro_rules! rlp_uint {
+    ($($t:ty),*) => {
+        $(
+            impl RlpEncode for $t {
+                fn rlp_append(&self, out: &mut Vec<u8>) {
+                    let bytes = self.to_be_bytes();
+                    let skip = bytes.iter().take_while(|b| **b == 0).count();
+                    append_bytes(&bytes[skip..], out);
+                }
+            }
+
+            impl RlpDecode for $t {
+                fn rlp_decode(item: &RlpItem) -> Result<$t, RlpDecodeError> {
+                    let mut be = <$t>::default().to_be_bytes();
+                    let bytes = decode_integer(item, be.len())?;
+                    let start = be.len() - bytes.len();
+                    be[start..].copy_from_slice(bytes);
+                    Ok(<$t>::from_be_bytes(be))
+                }
+            }
+        )*
+    };
+}
+
+rlp_uint!(u8, u16, u32, u64, u128);
+
+impl RlpEncode for Uint256 {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        let mut buf = [0u8; 32];
+        append_bytes(uint256_be_bytes(self, &mut buf), out);
+    }
+}
+
+impl RlpDecode for Uint256 {
+    fn rlp_decode(item: &RlpItem) -> Result<Uint256, RlpDecodeError> {
+        Ok(Uint256::from_bytes_be(decode_integer(item, 32)?))
+    }
+}
+
+impl RlpEncode for ByteBuf {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        append_bytes(self, out);
+    }
+}
+
+impl RlpDecode for ByteBuf {
+    fn rlp_decode(item: &RlpItem) -> Result<ByteBuf, RlpDecodeError> {
+        Ok(ByteBuf::from(item.as_bytes()?.to_vec()))
+    }
+}
+
+impl RlpEncode for str {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        append_bytes(self.as_bytes(), out);
+    }
+}
+
+impl RlpEncode for String {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        append_bytes(self.as_bytes(), out);
+    }
+}
+
+impl<const N: usize> RlpEncode for [u8; N] {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        append_bytes(self, out);
+    }
+}
+
+impl<const N: usize> RlpDecode for [u8; N] {
+    fn rlp_decode(item: &RlpItem) -> Result<[u8; N], RlpDecodeError> {
+        let bytes = item.as_bytes()?;
+        if bytes.len() != N {
+            return Err(RlpDecodeError::InvalidLength(N, bytes.len()));
+        }
+        let mut array = [0u8; N];
+        array.copy_from_slice(bytes);
+        Ok(array)
+    }
+}
+
+/// Addresses encode as all of their 20 bytes, as in receipts and access
+/// lists. In a transaction the recipient of a contract creation is empty
+/// instead.
+impl RlpEncode for Address {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        append_bytes(self.as_bytes(), out);
+    }
+}
+
+impl RlpDecode for Address {
+    fn rlp_decode(item: &RlpItem) -> Result<Address, RlpDecodeError> {
+        let bytes: [u8; 20] = RlpDecode::rlp_decode(item)?;
+        Ok(Address::from(bytes))
+    }
+}
+
+impl<T: RlpEncode> RlpEncode for [T] {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        let mut payload = Vec::new();
+        for item in self {
+            item.rlp_append(&mut payload);
+        }
+        append_header(0xc0, payload.len(), out);
+        out.extend(payload);
+    }
+}
+
+impl<T: RlpEncode> RlpEncode for Vec<T> {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        self[..].rlp_append(out);
+    }
+}
+
+impl<T: RlpDecode> RlpDecode for Vec<T> {
+    fn rlp_decode(item: &RlpItem) -> Result<Vec<T>, RlpDecodeError> {
+        item.as_list()?.iter().map(T::rlp_decode).collect()
+    }
+}
+
+impl<T: RlpEncode + ?Sized> RlpEncode for &T {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        (**self).rlp_append(out);
+    }
+}
+
+macro_rules! rlp_tuple {
+    ($len:expr; $($name:ident $index:tt),+) => {
+        impl<$($name: RlpEncode),+> RlpEncode for ($($name,)+) {
+            fn rlp_append(&self, out: &mut Vec<u8>) {
+                out.extend(encode_list(&[$(&self.$index),+]));
+            }
+        }
+
+        impl<$($name: RlpDecode),+> RlpDecode for ($($name,)+) {
+            fn rlp_decode(item: &RlpItem) -> Result<($($name,)+), RlpDecodeError> {
+                let items = item.as_list()?;
+                if items.len() != $len {
+                    return Err(RlpDecodeError::ListLength($len, items.len()));
+                }
+                Ok(($($name::rlp_decode(&items[$index])?,)+))
+            }
+        }
+    };
+}
+
+rlp_tuple!(1; A 0);
+rlp_tuple!(2; A 0, B 1);
+rlp_tuple!(3; A 0, B 1, C 2);
+rlp_tuple!(4; A 0, B 1, C 2, D 3);
+rlp_tuple!(5; A 0, B 1, C 2, D 3, E 4);
+rlp_tuple!(6; A 0, B 1, C 2, D 3, E 4, F 5);
+rlp_tuple!(7; A 0, B 1, C 2, D 3, E 4, F 5, G 6);
+rlp_tuple!(8; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7);
+rlp_tuple!(9; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8);
+rlp_tuple!(10; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9);
+rlp_tuple!(11; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10);
+rlp_tuple!(12; A 0, B 1, C 2, D 3, E 4, F 5, G 6, H 7, I 8, J 9, K 10, L 11);
+
+/// An RLP item of any shape, owning its bytes.
+///
+/// RLP of unknown structure, like a block body or a devp2p message, decodes
+/// to a `Value` that can be walked through before deciding on what to
+/// decode it as. It displays as nested lists of hex strings.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Value {
+    Bytes(Vec<u8>),
+    List(Vec<Value>),
+}
+
+impl Value {
+    /// The payload of a string, or `None` for a list.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Value::Bytes(ref bytes) => Some(bytes),
+            Value::List(_) => None,
+        }
+    }
+
+    /// The items of a list, or `None` for a string.
+    pub fn as_list(&self) -> Option<&[Value]> {
+        match *self {
+            Value::List(ref items) => Some(items),
+            Value::Bytes(_) => None,
+        }
+    }
+
+    pub fn is_bytes(&self) -> bool {
+        self.as_bytes().is_some()
+    }
+
+    pub fn is_list(&self) -> bool {
+        self.as_list().is_some()
+    }
+
+    /// Item `index` of a list, or `None` for a string or an index past the
+    /// end.
+    pub fn get(&self, index: usize) -> Option<&Value> {
+        self.as_list().and_then(|items| items.get(index))
+    }
+
+    /// The item found by taking item `index` of a list for each index in
+    /// turn, such as `&[1, 0]` for the first transaction of a block.
+    pub fn pointer(&self, path: &[usize]) -> Option<&Value> {
+        path.iter().try_fold(self, |value, index| value.get(*index))
+    }
+
+    /// Decodes the value as `T`, once its structure is known.
+    pub fn decode<T: RlpDecode>(&self) -> Result<T, RlpDecodeError> {
+        // Values hold their strings in full, so go through their encoding
+        // for T to borrow from
+        decode(&encode(self))
+    }
+}
+
+impl<'a, 'b> From<&'b RlpItem<'a>> for Value {
+    fn from(item: &'b RlpItem<'a>) -> Value {
+        match *item {
+            RlpItem::Bytes(bytes) => Value::Bytes(bytes.to_vec()),
+            RlpItem::List(ref items) => Value::List(items.iter().map(Value::from).collect()),
+        }
+    }
+}
+
+impl From<Vec<u8>> for Value {
+    fn from(bytes: Vec<u8>) -> Value {
+        Value::Bytes(bytes)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(items: Vec<Value>) -> Value {
+        Value::List(items)
+    }
+}
+
+impl RlpEncode for Value {
+    fn rlp_append(&self, out: &mut Vec<u8>) {
+        match *self {
+            Value::Bytes(ref bytes) => append_bytes(bytes, out),
+            Value::List(ref items) => items.rlp_append(out),
+        }
+    }
+}
+
+impl RlpDecode for Value {
+    fn rlp_decode(item: &RlpItem) -> Result<Value, RlpDecodeError> {
+        Ok(Value::from(item))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Bytes(ref bytes) => write!(f, "0x{}", bytes_to_hex_str(bytes)),
+            Value::List(ref items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+        }
+    }
+}
 
 pub(crate) struct AddressDef<'a>(pub(crate) &'a Address);
 
@@ -25,6 +498,156 @@ impl<'a> Serialize for AddressDef<'a> {
     }
 }
 
+/// Decodes the header of the RLP item at the start of `input`.
+///
+/// Returns whether the item is a list, and the range of its payload.
+fn decode_header(input: &[u8]) -> Result<(bool, usize, usize), RlpDecodeError> {
+    let prefix = *input.first().ok_or(RlpDecodeError::UnexpectedEnd)?;
+    let (is_list, offset, length) = match prefix {
+        0x00..=0x7f => return Ok((false, 0, 1)),
+        0x80..=0xb7 => (false, 1, (prefix - 0x80) as usize),
+        0xc0..=0xf7 => (true, 1, (prefix - 0xc0) as usize),
+        _ => {
+            let (is_list, len_of_len) = if prefix < 0xc0 {
+                (false, (prefix - 0xb7) as usize)
+            } else {
+                (true, (prefix - 0xf7) as usize)
+            };
+            let len_bytes = input
+                .get(1..=len_of_len)
+                .ok_or(RlpDecodeError::UnexpectedEnd)?;
+            if len_bytes[0] == 0 || len_of_len > 8 {
+                return Err(RlpDecodeError::NonCanonical);
+            }
+            let length = len_bytes
+                .iter()
+                .fold(0u64, |acc, byte| (acc << 8) | u64::from(*byte));
+            if length <= 55 {
+                return Err(RlpDecodeError::NonCanonical);
+            }
+            if length > input.len() as u64 {
+                return Err(RlpDecodeError::UnexpectedEnd);
+            }
+            (is_list, 1 + len_of_len, length as usize)
+        }
+    };
+    if input.len() - offset < length {
+        return Err(RlpDecodeError::UnexpectedEnd);
+    }
+    // A single byte below 0x80 is its own encoding
+    if !is_list && length == 1 && input[1] < 0x80 {
+        return Err(RlpDecodeError::NonCanonical);
+    }
+    Ok((is_list, offset, length))
+}
+
+/// Decodes an RLP list whose items are all byte strings, such as a legacy
+/// transaction, and returns the payloads of those strings.
+pub(crate) fn decode_bytes_list(input: &[u8]) -> Result<Vec<&[u8]>, RlpDecodeError> {
+    let (is_list, offset, length) = decode_header(input)?;
+    if !is_list {
+        return Err(RlpDecodeError::ExpectedList);
+    }
+    if offset + length != input.len() {
+        return Err(RlpDecodeError::TrailingBytes);
+    }
+    let mut rest = &input[offset..];
+    let mut items = Vec::new();
+    while !rest.is_empty() {
+        let (is_list, offset, length) = decode_header(rest)?;
+        if is_list {
+            return Err(RlpDecodeError::ExpectedString);
+        }
+        items.push(&rest[offset..offset + length]);
+        rest = &rest[offset + length..];
+    }
+    Ok(items)
+}
+
+/// Splits an RLP list, which has to be all of `input`, into the encodings
+/// of its items, for items whose encoding is used as is, like a block header
+/// that is hashed.
+pub(crate) fn decode_raw_list<'a>(
+    input: &'a [u8],
+    config: &DecodeConfig,
+) -> Result<Vec<&'a [u8]>, RlpDecodeError> {
+    let (is_list, offset, length) = decode_header(input)?;
+    if !is_list {
+        return Err(RlpDecodeError::ExpectedList);
+    }
+    if length > config.max_item_len {
+        return Err(RlpDecodeError::ItemTooLong(length));
+    }
+    if offset + length != input.len() {
+        return Err(RlpDecodeError::TrailingBytes);
+    }
+    let mut rest = &input[offset..];
+    let mut items = Vec::new();
+    while !rest.is_empty() {
+        if items.len() == config.max_list_items {
+            return Err(RlpDecodeError::TooManyItems(config.max_list_items));
+        }
+        let (_, offset, length) = decode_header(rest)?;
+        items.push(&rest[..offset + length]);
+        rest = &rest[offset + length..];
+    }
+    Ok(items)
+}
+
+/// Decodes the RLP item at the start of `input`, and returns it with the
+/// bytes that follow it.
+fn decode_item_prefix<'a>(
+    input: &'a [u8],
+    depth: usize,
+    config: &DecodeConfig,
+) -> Result<(RlpItem<'a>, &'a [u8]), RlpDecodeError> {
+    let (is_list, offset, length) = decode_header(input)?;
+    if length > config.max_item_len {
+        return Err(RlpDecodeError::ItemTooLong(length));
+    }
+    let payload = &input[offset..offset + length];
+    let rest = &input[offset + length..];
+    if !is_list {
+        return Ok((RlpItem::Bytes(payload), rest));
+    }
+    if depth >= config.max_depth {
+        return Err(RlpDecodeError::TooDeep);
+    }
+    let mut items = Vec::new();
+    let mut remaining = payload;
+    while !remaining.is_empty() {
+        if items.len() == config.max_list_items {
+            return Err(RlpDecodeError::TooManyItems(config.max_list_items));
+        }
+        let (item, after) = decode_item_prefix(remaining, depth + 1, config)?;
+        items.push(item);
+        remaining = after;
+    }
+    Ok((RlpItem::List(items), rest))
+}
+
+/// Decodes an RLP item that may contain nested lists, such as a typed
+/// transaction with an access list. The item has to be all of `input`.
+pub fn decode_item(input: &[u8]) -> Result<RlpItem<'_>, RlpDecodeError> {
+    decode_item_with(input, &DecodeConfig::default())
+}
+
+/// Decodes an RLP item like [decode_item](fn.decode_item.html), within the
+/// limits of `config`.
+///
+/// Raw transactions from untrusted sources can be checked with this before
+/// they are decoded as transactions.
+pub fn decode_item_with<'a>(
+    input: &'a [u8],
+    config: &DecodeConfig,
+) -> Result<RlpItem<'a>, RlpDecodeError> {
+    let (item, rest) = decode_item_prefix(input, 0, config)?;
+    if !rest.is_empty() {
+        return Err(RlpDecodeError::TrailingBytes);
+    }
+    Ok(item)
+}
+
 #[test]
 fn serialize_null_address() {
     use serde_rlp::ser::to_bytes;
@@ -41,3 +664,255 @@ fn serialize_padded_address() {
         [148, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xc0]
     );
 }
+
+#[test]
+fn decode_list_of_bytes() {
+    use serde_bytes::Bytes;
+    use serde_rlp::ser::to_bytes;
+    let long = vec![0xaau8; 60];
+    let encoded = to_bytes(&(
+        Bytes::new(b""),
+        Bytes::new(&[0x7f]),
+        Bytes::new(&[0x80]),
+        Bytes::new(&long),
+    ))
+    .unwrap();
+    assert_eq!(
+        decode_bytes_list(&encoded).unwrap(),
+        vec![&b""[..], &[0x7f][..], &[0x80][..], &long[..]]
+    );
+}
+
+#[test]
+fn decode_long_list() {
+    use serde_bytes::Bytes;
+    use serde_rlp::ser::to_bytes;
+    let item = vec![0x11u8; 40];
+    let encoded = to_bytes(&(Bytes::new(&item), Bytes::new(&item))).unwrap();
+    assert_eq!(encoded[0], 0xf8);
+    assert_eq!(decode_bytes_list(&encoded).unwrap().len(), 2);
+}
+
+#[test]
+fn decode_rejects_malformed() {
+    assert_eq!(decode_bytes_list(&[]), Err(RlpDecodeError::UnexpectedEnd));
+    assert_eq!(
+        decode_bytes_list(&[0x80]),
+        Err(RlpDecodeError::ExpectedList)
+    );
+    assert_eq!(
+        decode_bytes_list(&[0xc2, 0x80]),
+        Err(RlpDecodeError::UnexpectedEnd)
+    );
+    assert_eq!(
+        decode_bytes_list(&[0xc1, 0x80, 0x80]),
+        Err(RlpDecodeError::TrailingBytes)
+    );
+    assert_eq!(
+        decode_bytes_list(&[0xc1, 0xc0]),
+        Err(RlpDecodeError::ExpectedString)
+    );
+    assert_eq!(
+        decode_bytes_list(&[0xc2, 0x81, 0x01]),
+        Err(RlpDecodeError::NonCanonical)
+    );
+    // Long form used for a short payload
+    assert_eq!(
+        decode_bytes_list(&[0xf8, 0x01, 0x80]),
+        Err(RlpDecodeError::NonCanonical)
+    );
+    // Length far larger than the input
+    assert_eq!(
+        decode_bytes_list(&[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]),
+        Err(RlpDecodeError::UnexpectedEnd)
+    );
+}
+
+#[test]
+fn decode_nested_items() {
+    use serde_bytes::ByteBuf;
+    use serde_rlp::ser::to_bytes;
+    let nested: Vec<(ByteBuf, Vec<ByteBuf>)> = vec![(
+        ByteBuf::from(vec![0x22u8; 20]),
+        vec![ByteBuf::from(vec![0x33u8; 32])],
+    )];
+    let encoded = to_bytes(&(ByteBuf::from(vec![0x01u8]), &nested)).unwrap();
+    assert_eq!(
+        decode_item(&encoded).unwrap(),
+        RlpItem::List(vec![
+            RlpItem::Bytes(&[0x01]),
+            RlpItem::List(vec![RlpItem::List(vec![
+                RlpItem::Bytes(&[0x22u8; 20]),
+                RlpItem::List(vec![RlpItem::Bytes(&[0x33u8; 32])]),
+            ])]),
+        ])
+    );
+    assert_eq!(
+        decode_item(&[0xc0, 0x80]),
+        Err(RlpDecodeError::TrailingBytes)
+    );
+    // Lists nested `n` deep, each holding only the next one
+    let nested = |n: u8| {
+        (0..n)
+            .rev()
+            .map(|length| 0xc0 + length)
+            .collect::<Vec<u8>>()
+    };
+    assert!(decode_item(&nested(MAX_DEPTH as u8)).is_ok());
+    assert_eq!(
+        decode_item(&nested(MAX_DEPTH as u8 + 1)),
+        Err(RlpDecodeError::TooDeep)
+    );
+}
+
+#[test]
+fn encode_integers() {
+    assert_eq!(encode(&0u64), [0x80]);
+    assert_eq!(encode(&0x7fu8), [0x7f]);
+    assert_eq!(encode(&0x80u8), [0x81, 0x80]);
+    assert_eq!(encode(&0x0400u16), [0x82, 0x04, 0x00]);
+    assert_eq!(encode(&Uint256::from(0x0400u16)), [0x82, 0x04, 0x00]);
+    assert_eq!(decode::<u16>(&[0x82, 0x04, 0x00]).unwrap(), 0x0400);
+    assert_eq!(decode::<u64>(&[0x80]).unwrap(), 0);
+    assert_eq!(
+        decode::<u8>(&[0x82, 0x04, 0x00]),
+        Err(RlpDecodeError::IntegerOverflow)
+    );
+    // Leading zeros, and zero as a byte instead of the empty string
+    assert_eq!(
+        decode::<u32>(&[0x82, 0x00, 0x04]),
+        Err(RlpDecodeError::NonCanonical)
+    );
+    assert_eq!(decode::<u32>(&[0x00]), Err(RlpDecodeError::NonCanonical));
+    assert_eq!(decode::<u32>(&[0xc0]), Err(RlpDecodeError::ExpectedString));
+}
+
+#[test]
+fn encode_matches_serde_rlp() {
+    use serde_rlp::ser::to_bytes;
+    use types::BigEndianInt;
+    let long = ByteBuf::from(vec![0xaau8; 60]);
+    let nested: Vec<(ByteBuf, Vec<ByteBuf>)> = vec![(
+        ByteBuf::from(vec![0x22u8; 20]),
+        vec![ByteBuf::from(vec![0x33u8; 32]); 3],
+    )];
+    let value = Uint256::from(1_000_000_000u64);
+    assert_eq!(
+        encode(&(value.clone(), long.clone(), nested.clone())),
+        to_bytes(&(BigEndianInt(value), long, nested)).unwrap()
+    );
+}
+
+#[test]
+fn encode_decode_lists() {
+    // The examples of the specification
+    assert_eq!(encode("dog"), [0x83, b'd', b'o', b'g']);
+    assert_eq!(
+        encode(&vec!["cat".to_string(), "dog".to_string()]),
+        [0xc8, 0x83, b'c', b'a', b't', 0x83, b'd', b'o', b'g']
+    );
+    assert_eq!(encode(&Vec::<u8>::new()), [0xc0]);
+    // The set theoretical representation of three
+    let three = (
+        Vec::<u8>::new(),
+        (Vec::<u8>::new(),),
+        (Vec::<u8>::new(), (Vec::<u8>::new(),)),
+    );
+    assert_eq!(
+        encode(&three),
+        [0xc7, 0xc0, 0xc1, 0xc0, 0xc3, 0xc0, 0xc1, 0xc0]
+    );
+
+    let header = (
+        [0x11u8; 32],
+        Address::from([0x22u8; 20]),
+        vec![1u64, 2, 3],
+        ByteBuf::from(vec![0u8; 100]),
+    );
+    let encoded = encode(&header);
+    assert_eq!(encoded[..2], [0xf8, 0xa0]);
+    assert_eq!(
+        decode::<([u8; 32], Address, Vec<u64>, ByteBuf)>(&encoded).unwrap(),
+        header
+    );
+    assert_eq!(
+        decode::<([u8; 32], Address)>(&encoded),
+        Err(RlpDecodeError::ListLength(2, 4))
+    );
+    assert_eq!(
+        decode::<[u8; 20]>(&encode(&[0u8; 32])),
+        Err(RlpDecodeError::InvalidLength(20, 32))
+    );
+}
+
+#[test]
+fn decode_within_limits() {
+    let config = DecodeConfig {
+        max_depth: 2,
+        max_item_len: 4,
+        max_list_items: 3,
+    };
+    assert_eq!(
+        decode_item_with(&[0xc3, 0xc2, 0x01, 0x02], &config).unwrap(),
+        RlpItem::List(vec![RlpItem::List(vec![
+            RlpItem::Bytes(&[0x01]),
+            RlpItem::Bytes(&[0x02]),
+        ])])
+    );
+    assert_eq!(
+        decode_item_with(&[0xc2, 0xc1, 0xc0], &config),
+        Err(RlpDecodeError::TooDeep)
+    );
+    assert_eq!(
+        decode_item_with(&[0x85, 1, 2, 3, 4, 5], &config),
+        Err(RlpDecodeError::ItemTooLong(5))
+    );
+    assert_eq!(
+        decode_item_with(&[0xc4, 0x01, 0x02, 0x03, 0x04], &config),
+        Err(RlpDecodeError::TooManyItems(3))
+    );
+    assert_eq!(
+        decode_with::<Vec<u8>>(&[0xc3, 0x01, 0x02, 0x03], &config),
+        Ok(vec![1, 2, 3])
+    );
+    assert_eq!(
+        decode_with::<u32>(&[0x84, 0xff, 0xff, 0xff, 0xff], &config),
+        Ok(u32::MAX)
+    );
+    assert!(decode_item_with(&[0xc0], &DecodeConfig::default()).is_ok());
+}
+
+#[test]
+fn values() {
+    let encoded = encode(&(
+        1u8,
+        ByteBuf::from(vec![0xde, 0xad]),
+        (Vec::<u8>::new(), [0x11u8; 2]),
+    ));
+    let value: Value = decode(&encoded).unwrap();
+    assert_eq!(
+        value,
+        Value::List(vec![
+            Value::Bytes(vec![0x01]),
+            Value::Bytes(vec![0xde, 0xad]),
+            Value::List(vec![Value::List(vec![]), Value::Bytes(vec![0x11, 0x11])]),
+        ])
+    );
+    assert_eq!(value.to_string(), "[0x01, 0xdead, [[], 0x1111]]");
+    assert_eq!(encode(&value), encoded);
+    assert_eq!(
+        value.pointer(&[2, 1]).unwrap().as_bytes(),
+        Some(&[0x11u8; 2][..])
+    );
+    assert_eq!(value.pointer(&[]), Some(&value));
+    assert!(value.pointer(&[0, 0]).is_none());
+    assert!(value.get(3).is_none());
+    assert!(value.is_list() && value.get(0).unwrap().is_bytes());
+    assert_eq!(value.get(1).unwrap().decode::<u16>(), Ok(0xdead));
+    assert_eq!(
+        value.decode::<(u8, u8)>(),
+        Err(RlpDecodeError::ListLength(2, 3))
+    );
+    assert_eq!(Value::from(vec![0x80u8]).to_string(), "0x80");
+    assert_eq!(encode(&Value::from(vec![0x80u8])), vec![0x81, 0x80]);
+}
@@ -0,0 +1,959 @@
+//! RLP as a serde data format.
+//!
+//! [to_bytes](fn.to_bytes.html) and [from_bytes](fn.from_bytes.html)
+//! encode and decode anything that derives `Serialize` and `Deserialize`,
+//! the way Ethereum encodes its structures:
+//!
+//! * Structs, tuples and sequences are lists of their fields or items, in
+//!   order. Field names are not encoded.
+//! * Unsigned integers are their big endian bytes without leading zeros, and
+//!   `bool`s the integers 0 and 1.
+//! * Strings and bytes are byte strings. `Vec<u8>` is a sequence, and a
+//!   byte string only with `serde_bytes`.
+//! * Maps are lists of key and value pairs.
+//! * Unit variants are their index, other variants a list of their index
+//!   followed by their fields.
+//! * `None` is an empty list and `Some` a list of its value, `()` an empty
+//!   list.
+//!
+//! Signed integers and floats have no RLP encoding, and fail to serialize.
+//! `Address` and `Uint256` serialize as strings wherever they go, so fields
+//! of those types pick their RLP encoding with
+//! `#[serde(with = "clarity::rlp_serde::address")]` and
+//! `#[serde(with = "clarity::rlp_serde::uint256")]`.
+//!
+//! ```rust
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # extern crate clarity;
+//! use clarity::rlp_serde::{from_bytes, to_bytes};
+//! use clarity::Address;
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Withdrawal {
+//!     index: u64,
+//!     validator_index: u64,
+//!     #[serde(with = "clarity::rlp_serde::address")]
+//!     address: Address,
+//!     amount: u64,
+//! }
+//!
+//! # fn main() {
+//! let withdrawal = Withdrawal {
+//!     index: 1,
+//!     validator_index: 2,
+//!     address: Address::default(),
+//!     amount: 32_000_000_000,
+//! };
+//! let encoded = to_bytes(&withdrawal).unwrap();
+//! assert_eq!(from_bytes::<Withdrawal>(&encoded).unwrap(), withdrawal);
+//! # }
+//! ```
+//!
+//! Strings and bytes are borrowed from the input by types that borrow
+//! them, like `&str` and `&[u8]`.
+use rlp::{
+    append_bytes, append_header, decode_item_with, DecodeConfig, RlpDecode, RlpDecodeError,
+    RlpEncode, RlpItem,
+};
+use serde_crate::de::{self, IntoDeserializer, Visitor};
+use serde_crate::ser::{self, Serialize};
+use serde_crate::Deserialize;
+use std::error;
+use std::fmt;
+use std::str;
+
+/// Errors of RLP serialization and deserialization.
+///
+/// This is what `serde` requires its errors to be, a standard error that can
+/// carry a message from the types being serialized.
+#[derive(Debug, PartialEq)]
+pub enum RlpSerdeError {
+    /// The input is not valid RLP, or does not fit the type
+    Decode(RlpDecodeError),
+    /// A value that RLP cannot encode
+    Unsupported(&'static str),
+    /// A list with more items than the type takes
+    TrailingItems(usize),
+    /// An error reported by the type
+    Message(String),
+}
+
+impl fmt::Display for RlpSerdeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RlpSerdeError::Decode(ref e) => write!(f, "{}", e),
+            RlpSerdeError::Unsupported(what) => write!(f, "RLP cannot encode {}", what),
+            RlpSerdeError::TrailingItems(n) => write!(f, "RLP list has {} items too many", n),
+            RlpSerdeError::Message(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for RlpSerdeError {}
+
+impl ser::Error for RlpSerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> RlpSerdeError {
+        RlpSerdeError::Message(msg.to_string())
+    }
+}
+
+impl de::Error for RlpSerdeError {
+    fn custom<T: fmt::Display>(msg: T) -> RlpSerdeError {
+        RlpSerdeError::Message(msg.to_string())
+    }
+}
+
+impl From<RlpDecodeError> for RlpSerdeError {
+    fn from(e: RlpDecodeError) -> RlpSerdeError {
+        RlpSerdeError::Decode(e)
+    }
+}
+
+/// The RLP encoding of `value`.
+pub fn to_bytes<T: Serialize + ?Sized>(value: &T) -> Result<Vec<u8>, RlpSerdeError> {
+    let mut serializer = Serializer { out: Vec::new() };
+    value.serialize(&mut serializer)?;
+    Ok(serializer.out)
+}
+
+/// Decodes a value from its RLP encoding, which has to be all of `input`.
+pub fn from_bytes<'de, T: Deserialize<'de>>(input: &'de [u8]) -> Result<T, RlpSerdeError> {
+    from_bytes_with(input, &DecodeConfig::default())
+}
+
+/// Decodes a value like [from_bytes](fn.from_bytes.html), within the limits
+/// of `config`.
+pub fn from_bytes_with<'de, T: Deserialize<'de>>(
+    input: &'de [u8],
+    config: &DecodeConfig,
+) -> Result<T, RlpSerdeError> {
+    let item = decode_item_with(input, config)?;
+    T::deserialize(ItemDeserializer { item: &item })
+}
+
+/// Serializes into an RLP encoding.
+pub struct Serializer {
+    out: Vec<u8>,
+}
+
+impl Serializer {
+    /// Starts a list, which [Compound::end](struct.Compound.html) finishes.
+    fn begin_list(&mut self) -> Compound<'_> {
+        let start = self.out.len();
+        Compound {
+            ser: self,
+            start,
+            entry: 0,
+        }
+    }
+
+    fn append<T: RlpEncode + ?Sized>(&mut self, value: &T) {
+        value.rlp_append(&mut self.out);
+    }
+}
+
+/// Prefixes everything written since `start` with a list header.
+fn wrap_list(out: &mut Vec<u8>, start: usize) {
+    let payload = out.split_off(start);
+    append_header(0xc0, payload.len(), out);
+    out.extend(payload);
+}
+
+/// A list being serialized.
+pub struct Compound<'a> {
+    ser: &'a mut Serializer,
+    start: usize,
+    /// Start of the map entry being serialized
+    entry: usize,
+}
+
+impl<'a> Compound<'a> {
+    fn element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RlpSerdeError> {
+        value.serialize(&mut *self.ser)
+    }
+
+    fn finish(self) -> Result<(), RlpSerdeError> {
+        wrap_list(&mut self.ser.out, self.start);
+        Ok(())
+    }
+}
+
+impl<'a> ser::Serializer for &'a mut Serializer {
+    type Ok = ();
+    type Error = RlpSerdeError;
+    type SerializeSeq = Compound<'a>;
+    type SerializeTuple = Compound<'a>;
+    type SerializeTupleStruct = Compound<'a>;
+    type SerializeTupleVariant = Compound<'a>;
+    type SerializeMap = Compound<'a>;
+    type SerializeStruct = Compound<'a>;
+    type SerializeStructVariant = Compound<'a>;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    fn serialize_bool(self, v: bool) -> Result<(), RlpSerdeError> {
+        self.append(&(v as u8));
+        Ok(())
+    }
+
+    fn serialize_i8(self, _: i8) -> Result<(), RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn serialize_i16(self, _: i16) -> Result<(), RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn serialize_i32(self, _: i32) -> Result<(), RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn serialize_i64(self, _: i64) -> Result<(), RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<(), RlpSerdeError> {
+        self.append(&v);
+        Ok(())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<(), RlpSerdeError> {
+        self.append(&v);
+        Ok(())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<(), RlpSerdeError> {
+        self.append(&v);
+        Ok(())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<(), RlpSerdeError> {
+        self.append(&v);
+        Ok(())
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<(), RlpSerdeError> {
+        self.append(&v);
+        Ok(())
+    }
+
+    fn serialize_f32(self, _: f32) -> Result<(), RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("floats"))
+    }
+
+    fn serialize_f64(self, _: f64) -> Result<(), RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("floats"))
+    }
+
+    fn serialize_char(self, v: char) -> Result<(), RlpSerdeError> {
+        self.serialize_str(v.encode_utf8(&mut [0u8; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<(), RlpSerdeError> {
+        self.serialize_bytes(v.as_bytes())
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), RlpSerdeError> {
+        append_bytes(v, &mut self.out);
+        Ok(())
+    }
+
+    fn serialize_none(self) -> Result<(), RlpSerdeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), RlpSerdeError> {
+        let mut list = self.begin_list();
+        list.element(value)?;
+        list.finish()
+    }
+
+    fn serialize_unit(self) -> Result<(), RlpSerdeError> {
+        self.begin_list().finish()
+    }
+
+    fn serialize_unit_struct(self, _: &'static str) -> Result<(), RlpSerdeError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+    ) -> Result<(), RlpSerdeError> {
+        self.serialize_u32(variant_index)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), RlpSerdeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), RlpSerdeError> {
+        let mut list = self.begin_list();
+        list.element(&variant_index)?;
+        list.element(value)?;
+        list.finish()
+    }
+
+    fn serialize_seq(self, _: Option<usize>) -> Result<Compound<'a>, RlpSerdeError> {
+        Ok(self.begin_list())
+    }
+
+    fn serialize_tuple(self, _: usize) -> Result<Compound<'a>, RlpSerdeError> {
+        Ok(self.begin_list())
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Compound<'a>, RlpSerdeError> {
+        Ok(self.begin_list())
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Compound<'a>, RlpSerdeError> {
+        let mut list = self.begin_list();
+        list.element(&variant_index)?;
+        Ok(list)
+    }
+
+    fn serialize_map(self, _: Option<usize>) -> Result<Compound<'a>, RlpSerdeError> {
+        Ok(self.begin_list())
+    }
+
+    fn serialize_struct(self, _: &'static str, _: usize) -> Result<Compound<'a>, RlpSerdeError> {
+        Ok(self.begin_list())
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _: &'static str,
+        variant_index: u32,
+        _: &'static str,
+        _: usize,
+    ) -> Result<Compound<'a>, RlpSerdeError> {
+        let mut list = self.begin_list();
+        list.element(&variant_index)?;
+        Ok(list)
+    }
+}
+
+impl<'a> ser::SerializeSeq for Compound<'a> {
+    type Ok = ();
+    type Error = RlpSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RlpSerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), RlpSerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTuple for Compound<'a> {
+    type Ok = ();
+    type Error = RlpSerdeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RlpSerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), RlpSerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleStruct for Compound<'a> {
+    type Ok = ();
+    type Error = RlpSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RlpSerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), RlpSerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeTupleVariant for Compound<'a> {
+    type Ok = ();
+    type Error = RlpSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RlpSerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), RlpSerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeMap for Compound<'a> {
+    type Ok = ();
+    type Error = RlpSerdeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), RlpSerdeError> {
+        self.entry = self.ser.out.len();
+        self.element(key)
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), RlpSerdeError> {
+        self.element(value)?;
+        wrap_list(&mut self.ser.out, self.entry);
+        Ok(())
+    }
+
+    fn end(self) -> Result<(), RlpSerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStruct for Compound<'a> {
+    type Ok = ();
+    type Error = RlpSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), RlpSerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), RlpSerdeError> {
+        self.finish()
+    }
+}
+
+impl<'a> ser::SerializeStructVariant for Compound<'a> {
+    type Ok = ();
+    type Error = RlpSerdeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        _: &'static str,
+        value: &T,
+    ) -> Result<(), RlpSerdeError> {
+        self.element(value)
+    }
+
+    fn end(self) -> Result<(), RlpSerdeError> {
+        self.finish()
+    }
+}
+
+/// Deserializes a decoded RLP item.
+struct ItemDeserializer<'a, 'de: 'a> {
+    item: &'a RlpItem<'de>,
+}
+
+impl<'a, 'de> ItemDeserializer<'a, 'de> {
+    fn integer<T: RlpDecode>(&self) -> Result<T, RlpSerdeError> {
+        Ok(T::rlp_decode(self.item)?)
+    }
+
+    fn bytes(&self) -> Result<&'de [u8], RlpSerdeError> {
+        Ok(self.item.as_bytes()?)
+    }
+
+    fn str(&self) -> Result<&'de str, RlpSerdeError> {
+        str::from_utf8(self.bytes()?).map_err(|e| RlpSerdeError::Message(e.to_string()))
+    }
+
+    fn list(&self) -> Result<&'a [RlpItem<'de>], RlpSerdeError> {
+        Ok(self.item.as_list()?)
+    }
+}
+
+/// Visits `items` as a sequence, all of which have to be taken.
+fn visit_items<'a, 'de, V: Visitor<'de>>(
+    items: &'a [RlpItem<'de>],
+    visitor: V,
+) -> Result<V::Value, RlpSerdeError> {
+    let mut seq = Items {
+        items: items.iter(),
+    };
+    let value = visitor.visit_seq(&mut seq)?;
+    match seq.items.len() {
+        0 => Ok(value),
+        n => Err(RlpSerdeError::TrailingItems(n)),
+    }
+}
+
+struct Items<'a, 'de: 'a> {
+    items: ::std::slice::Iter<'a, RlpItem<'de>>,
+}
+
+impl<'a, 'de> de::SeqAccess<'de> for Items<'a, 'de> {
+    type Error = RlpSerdeError;
+
+    fn next_element_seed<T: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, RlpSerdeError> {
+        match self.items.next() {
+            Some(item) => seed.deserialize(ItemDeserializer { item }).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.items.len())
+    }
+}
+
+struct Entries<'a, 'de: 'a> {
+    entries: ::std::slice::Iter<'a, RlpItem<'de>>,
+    value: Option<&'a RlpItem<'de>>,
+}
+
+impl<'a, 'de> de::MapAccess<'de> for Entries<'a, 'de> {
+    type Error = RlpSerdeError;
+
+    fn next_key_seed<K: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, RlpSerdeError> {
+        let entry = match self.entries.next() {
+            Some(entry) => entry.as_list()?,
+            None => return Ok(None),
+        };
+        match *entry {
+            [ref key, ref value] => {
+                self.value = Some(value);
+                seed.deserialize(ItemDeserializer { item: key }).map(Some)
+            }
+            _ => Err(RlpDecodeError::ListLength(2, entry.len()).into()),
+        }
+    }
+
+    fn next_value_seed<V: de::DeserializeSeed<'de>>(
+        &mut self,
+        seed: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        let item = self
+            .value
+            .take()
+            .ok_or_else(|| RlpSerdeError::Message("Map value without a key".to_owned()))?;
+        seed.deserialize(ItemDeserializer { item })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+/// The payload of an enum variant, after its index.
+struct Variant<'a, 'de: 'a> {
+    fields: &'a [RlpItem<'de>],
+}
+
+impl<'a, 'de> de::EnumAccess<'de> for ItemDeserializer<'a, 'de> {
+    type Error = RlpSerdeError;
+    type Variant = Variant<'a, 'de>;
+
+    fn variant_seed<V: de::DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Variant<'a, 'de>), RlpSerdeError> {
+        let (index, fields) = match *self.item {
+            RlpItem::Bytes(_) => (self.integer::<u32>()?, &[][..]),
+            RlpItem::List(ref items) => match items.split_first() {
+                Some((index, fields)) => (u32::rlp_decode(index)?, fields),
+                None => return Err(RlpDecodeError::ListLength(1, 0).into()),
+            },
+        };
+        let index: de::value::U32Deserializer<RlpSerdeError> = index.into_deserializer();
+        Ok((seed.deserialize(index)?, Variant { fields }))
+    }
+}
+
+impl<'a, 'de> de::VariantAccess<'de> for Variant<'a, 'de> {
+    type Error = RlpSerdeError;
+
+    fn unit_variant(self) -> Result<(), RlpSerdeError> {
+        match self.fields.len() {
+            0 => Ok(()),
+            n => Err(RlpSerdeError::TrailingItems(n)),
+        }
+    }
+
+    fn newtype_variant_seed<T: de::DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<T::Value, RlpSerdeError> {
+        match *self.fields {
+            [ref item] => seed.deserialize(ItemDeserializer { item }),
+            _ => Err(RlpDecodeError::ListLength(2, self.fields.len() + 1).into()),
+        }
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(
+        self,
+        _: usize,
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        visit_items(self.fields, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        visit_items(self.fields, visitor)
+    }
+}
+
+impl<'a, 'de> de::Deserializer<'de> for ItemDeserializer<'a, 'de> {
+    type Error = RlpSerdeError;
+
+    fn is_human_readable(&self) -> bool {
+        false
+    }
+
+    /// RLP does not say what its strings hold, so without a type they are
+    /// bytes.
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        match *self.item {
+            RlpItem::Bytes(bytes) => visitor.visit_borrowed_bytes(bytes),
+            RlpItem::List(ref items) => visit_items(items, visitor),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        match self.integer::<u8>()? {
+            0 => visitor.visit_bool(false),
+            1 => visitor.visit_bool(true),
+            _ => Err(RlpSerdeError::Message("Expected a boolean".to_owned())),
+        }
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, _: V) -> Result<V::Value, RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn deserialize_i16<V: Visitor<'de>>(self, _: V) -> Result<V::Value, RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn deserialize_i32<V: Visitor<'de>>(self, _: V) -> Result<V::Value, RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn deserialize_i64<V: Visitor<'de>>(self, _: V) -> Result<V::Value, RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    }
+
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_u8(self.integer()?)
+    }
+
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_u16(self.integer()?)
+    }
+
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_u32(self.integer()?)
+    }
+
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_u64(self.integer()?)
+    }
+
+    fn deserialize_u128<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_u128(self.integer()?)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, _: V) -> Result<V::Value, RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("floats"))
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, _: V) -> Result<V::Value, RlpSerdeError> {
+        Err(RlpSerdeError::Unsupported("floats"))
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        let mut chars = self.str()?.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => visitor.visit_char(c),
+            _ => Err(RlpSerdeError::Message(
+                "Expected a single character".to_owned(),
+            )),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_borrowed_str(self.str()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_borrowed_bytes(self.bytes()?)
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        match *self.list()? {
+            [] => visitor.visit_none(),
+            [ref item] => visitor.visit_some(ItemDeserializer { item }),
+            ref items => Err(RlpSerdeError::TrailingItems(items.len() - 1)),
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        match self.list()?.len() {
+            0 => visitor.visit_unit(),
+            n => Err(RlpSerdeError::TrailingItems(n)),
+        }
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visit_items(self.list()?, visitor)
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _: usize,
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: usize,
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_map(Entries {
+            entries: self.list()?.iter(),
+            value: None,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _: &'static str,
+        _: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_enum(self)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        self.deserialize_u32(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, RlpSerdeError> {
+        visitor.visit_unit()
+    }
+}
+
+/// Serializes an `Address` as its 20 bytes.
+pub mod address {
+    use address::Address;
+    use serde_bytes::ByteBuf;
+    use serde_crate::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(address: &Address, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_bytes(address.as_bytes())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Address, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let bytes = ByteBuf::deserialize(deserializer)?;
+        if bytes.len() != 20 {
+            return Err(de::Error::invalid_length(bytes.len(), &"20 bytes"));
+        }
+        Address::from_slice(&bytes).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes an `Uint256` as its big endian bytes without leading zeros.
+pub mod uint256 {
+    use num256::Uint256;
+    use serde_crate::{Deserializer, Serializer};
+    use utils::{big_endian_uint256_deserialize, big_endian_uint256_serialize};
+
+    pub fn serialize<S>(value: &Uint256, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        big_endian_uint256_serialize(value, serializer)
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Uint256, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        big_endian_uint256_deserialize(deserializer)
+    }
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+enum TestEnum {
+    Unit,
+    Newtype(u64),
+    Tuple(u8, bool),
+    Struct { a: String },
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TestStruct<'a> {
+    nonce: u64,
+    #[serde(with = "uint256")]
+    value: ::num256::Uint256,
+    #[serde(with = "address")]
+    to: ::address::Address,
+    #[serde(with = "::serde_bytes")]
+    data: Vec<u8>,
+    name: &'a str,
+    flags: Vec<bool>,
+    memo: Option<String>,
+    kinds: Vec<TestEnum>,
+    map: ::std::collections::BTreeMap<u8, u16>,
+    unit: (),
+}
+
+#[test]
+fn roundtrip_structs() {
+    use address::Address;
+    use std::collections::BTreeMap;
+    let mut map = BTreeMap::new();
+    map.insert(1u8, 0x1234u16);
+    let value = TestStruct {
+        nonce: 9,
+        value: 1_000_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        data: vec![0xde, 0xad],
+        name: "clarity",
+        flags: vec![true, false],
+        memo: None,
+        kinds: vec![
+            TestEnum::Unit,
+            TestEnum::Newtype(5),
+            TestEnum::Tuple(1, true),
+            TestEnum::Struct { a: "x".to_owned() },
+        ],
+        map,
+        unit: (),
+    };
+    let encoded = to_bytes(&value).unwrap();
+    let decoded: TestStruct = from_bytes(&encoded).unwrap();
+    assert_eq!(decoded, value);
+    // Strings are borrowed from the input
+    assert!(encoded.as_ptr_range().contains(&decoded.name.as_ptr()));
+}
+
+#[test]
+fn encoding() {
+    use rlp::encode;
+    // Matches the RlpEncode implementations
+    assert_eq!(to_bytes(&0u64).unwrap(), encode(&0u64));
+    assert_eq!(to_bytes(&0x1234u64).unwrap(), encode(&0x1234u64));
+    assert_eq!(to_bytes("dog").unwrap(), encode("dog"));
+    assert_eq!(to_bytes(&(1u8, "cat")).unwrap(), encode(&(1u8, "cat")));
+    assert_eq!(to_bytes(&vec![1u8, 2]).unwrap(), [0xc2, 0x01, 0x02]);
+    assert_eq!(to_bytes(&true).unwrap(), [0x01]);
+    assert_eq!(to_bytes(&None::<u8>).unwrap(), [0xc0]);
+    assert_eq!(to_bytes(&Some(0u8)).unwrap(), [0xc1, 0x80]);
+    assert_eq!(to_bytes(&TestEnum::Unit).unwrap(), [0x80]);
+    assert_eq!(to_bytes(&TestEnum::Newtype(5)).unwrap(), [0xc2, 0x01, 0x05]);
+
+    assert_eq!(
+        to_bytes(&-1i32),
+        Err(RlpSerdeError::Unsupported("signed integers"))
+    );
+    assert_eq!(
+        from_bytes::<(u8, u8)>(&[0xc3, 0x01, 0x02, 0x03]),
+        Err(RlpSerdeError::TrailingItems(1))
+    );
+    assert!(from_bytes::<(u8, u8)>(&[0xc1, 0x01]).is_err());
+    assert!(from_bytes::<bool>(&[0x02]).is_err());
+    assert!(from_bytes::<TestEnum>(&[0x09]).is_err());
+    // Integers with leading zeros are not canonical
+    assert!(from_bytes::<u64>(&[0x82, 0x00, 0x01]).is_err());
+}
+
+#[test]
+fn roundtrip_signature() {
+    use private_key::PrivateKey;
+    use signature::Signature;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let signature = key.sign_msg(b"clarity");
+    let encoded = to_bytes(&signature).unwrap();
+    assert_eq!(from_bytes::<Signature>(&encoded).unwrap(), signature);
+}
@@ -0,0 +1,426 @@
+//! Borrowed views of JSON-RPC blocks, receipts and transactions.
+//!
+//! Deserializing an RPC response into owned types allocates for every hash,
+//! address and quantity in it, and an indexer replaying cached responses
+//! ends up holding each of them twice. The types here borrow their fields
+//! from the JSON text instead, as [Hex](struct.Hex.html) strings, and only
+//! decode the fields that are asked for:
+//!
+//! ```rust
+//! # extern crate clarity;
+//! # extern crate serde_json;
+//! use clarity::rpc_view::RpcLog;
+//!
+//! # fn main() {
+//! let cached = r#"{
+//!     "address": "0x1111111111111111111111111111111111111111",
+//!     "topics": [],
+//!     "data": "0x2a"
+//! }"#;
+//! let log: RpcLog = serde_json::from_str(cached).unwrap();
+//! assert_eq!(log.data.to_bytes().unwrap(), vec![0x2a]);
+//! # }
+//! ```
+//!
+//! Borrowing needs a deserializer that reads from memory, such as
+//! `serde_json::from_str` or `from_slice`; `from_reader` cannot lend out
+//! what it reads.
+use address::Address;
+use chain::BlockHeader;
+use event::Log;
+use failure::Error;
+use num256::Uint256;
+use serde_crate::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use utils::{hex_str_to_bytes, hex_str_to_bytes32, hex_str_to_uint256, strip_hex_prefix};
+
+/// A hexadecimal string borrowed from the input, such as a hash, an address
+/// or a quantity.
+///
+/// Deserialization only checks that it is hexadecimal, with an optional `0x`
+/// prefix.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Hex<'a>(&'a str);
+
+impl<'a> Hex<'a> {
+    /// The string as it was in the input.
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        hex_str_to_bytes(self.0)
+    }
+
+    /// A 32 byte value, such as a hash.
+    pub fn to_bytes32(&self) -> Result<[u8; 32], Error> {
+        hex_str_to_bytes32(self.0)
+    }
+
+    pub fn to_address(&self) -> Result<Address, Error> {
+        self.0.parse()
+    }
+
+    /// A JSON-RPC quantity.
+    pub fn to_uint256(&self) -> Result<Uint256, Error> {
+        hex_str_to_uint256(self.0)
+    }
+
+    /// A JSON-RPC quantity that fits 64 bits, such as a block number.
+    pub fn to_u64(&self) -> Result<u64, Error> {
+        let digits = strip_hex_prefix(self.0);
+        ensure!(
+            !digits.is_empty(),
+            "Expected at least one hexadecimal digit"
+        );
+        Ok(u64::from_str_radix(digits, 16)?)
+    }
+}
+
+impl<'a> fmt::Display for Hex<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'a> Serialize for Hex<'a> {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.0)
+    }
+}
+
+impl<'de: 'a, 'a> Deserialize<'de> for Hex<'a> {
+    fn deserialize<D>(deserializer: D) -> Result<Hex<'a>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = <&'a str>::deserialize(deserializer)?;
+        if !strip_hex_prefix(s).bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(de::Error::invalid_value(
+                de::Unexpected::Str(s),
+                &"a hexadecimal string",
+            ));
+        }
+        Ok(Hex(s))
+    }
+}
+
+/// A transaction as returned by `eth_getTransactionByHash` and in full
+/// blocks.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcTransaction<'a> {
+    #[serde(borrow)]
+    pub hash: Hex<'a>,
+    #[serde(borrow, rename = "type", default)]
+    pub tx_type: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub nonce: Hex<'a>,
+    /// `None` while pending
+    #[serde(borrow, default)]
+    pub block_hash: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub block_number: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub transaction_index: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub from: Hex<'a>,
+    /// `None` for contract creations
+    #[serde(borrow, default)]
+    pub to: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub value: Hex<'a>,
+    #[serde(borrow)]
+    pub gas: Hex<'a>,
+    #[serde(borrow, default)]
+    pub gas_price: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub max_fee_per_gas: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub max_priority_fee_per_gas: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub input: Hex<'a>,
+    #[serde(borrow, default)]
+    pub chain_id: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub v: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub r: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub s: Option<Hex<'a>>,
+}
+
+/// A log as returned in receipts and by `eth_getLogs`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcLog<'a> {
+    #[serde(borrow)]
+    pub address: Hex<'a>,
+    #[serde(borrow)]
+    pub topics: Vec<Hex<'a>>,
+    #[serde(borrow)]
+    pub data: Hex<'a>,
+    #[serde(borrow, default)]
+    pub block_number: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub transaction_hash: Option<Hex<'a>>,
+    #[serde(borrow, default)]
+    pub log_index: Option<Hex<'a>>,
+    /// Whether the log was removed by a reorg
+    #[serde(default)]
+    pub removed: bool,
+}
+
+impl<'a> RpcLog<'a> {
+    /// Decodes the log, to be matched against events.
+    pub fn to_log(&self) -> Result<Log, Error> {
+        Ok(Log {
+            address: self.address.to_address()?,
+            topics: self
+                .topics
+                .iter()
+                .map(Hex::to_bytes32)
+                .collect::<Result<_, Error>>()?,
+            data: self.data.to_bytes()?,
+        })
+    }
+}
+
+/// A receipt as returned by `eth_getTransactionReceipt`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcReceipt<'a> {
+    #[serde(borrow)]
+    pub transaction_hash: Hex<'a>,
+    #[serde(borrow)]
+    pub transaction_index: Hex<'a>,
+    #[serde(borrow)]
+    pub block_hash: Hex<'a>,
+    #[serde(borrow)]
+    pub block_number: Hex<'a>,
+    #[serde(borrow)]
+    pub from: Hex<'a>,
+    #[serde(borrow, default)]
+    pub to: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub cumulative_gas_used: Hex<'a>,
+    #[serde(borrow)]
+    pub gas_used: Hex<'a>,
+    #[serde(borrow, default)]
+    pub effective_gas_price: Option<Hex<'a>>,
+    /// The created contract, for contract creations
+    #[serde(borrow, default)]
+    pub contract_address: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub logs: Vec<RpcLog<'a>>,
+    #[serde(borrow)]
+    pub logs_bloom: Hex<'a>,
+    /// `0x1` on success, `None` before Byzantium
+    #[serde(borrow, default)]
+    pub status: Option<Hex<'a>>,
+    #[serde(borrow, rename = "type", default)]
+    pub tx_type: Option<Hex<'a>>,
+}
+
+impl<'a> RpcReceipt<'a> {
+    /// Whether the transaction succeeded, `None` for receipts from before
+    /// Byzantium that do not say.
+    pub fn succeeded(&self) -> Result<Option<bool>, Error> {
+        match self.status {
+            Some(status) => Ok(Some(status.to_u64()? == 1)),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The transactions of a block, depending on whether they were asked for in
+/// full.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BlockTransactions<'a> {
+    #[serde(borrow)]
+    Hashes(Vec<Hex<'a>>),
+    #[serde(borrow)]
+    Full(Vec<RpcTransaction<'a>>),
+}
+
+/// A block as returned by `eth_getBlockByNumber` and `eth_getBlockByHash`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RpcBlock<'a> {
+    /// `None` for pending blocks
+    #[serde(borrow, default)]
+    pub hash: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub parent_hash: Hex<'a>,
+    #[serde(borrow, default)]
+    pub number: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub timestamp: Hex<'a>,
+    #[serde(borrow)]
+    pub miner: Hex<'a>,
+    #[serde(borrow)]
+    pub state_root: Hex<'a>,
+    #[serde(borrow)]
+    pub transactions_root: Hex<'a>,
+    #[serde(borrow)]
+    pub receipts_root: Hex<'a>,
+    #[serde(borrow)]
+    pub gas_limit: Hex<'a>,
+    #[serde(borrow)]
+    pub gas_used: Hex<'a>,
+    #[serde(borrow, default)]
+    pub base_fee_per_gas: Option<Hex<'a>>,
+    #[serde(borrow)]
+    pub extra_data: Hex<'a>,
+    #[serde(borrow)]
+    pub transactions: BlockTransactions<'a>,
+}
+
+impl<'a> RpcBlock<'a> {
+    /// The parts of the block [ChainTracker](../chain/struct.ChainTracker.html)
+    /// follows the chain with. Pending blocks have no hash or number yet,
+    /// and fail to decode.
+    pub fn block_header(&self) -> Result<BlockHeader, Error> {
+        let missing = || format_err!("Pending blocks have no hash or number");
+        Ok(BlockHeader {
+            number: self.number.ok_or_else(missing)?.to_u64()?,
+            hash: self.hash.ok_or_else(missing)?.to_bytes32()?,
+            parent_hash: self.parent_hash.to_bytes32()?,
+        })
+    }
+
+    /// Hashes of the transactions of the block, whether they were asked
+    /// for in full or not.
+    pub fn transaction_hashes(&self) -> Vec<Hex<'a>> {
+        match self.transactions {
+            BlockTransactions::Hashes(ref hashes) => hashes.clone(),
+            BlockTransactions::Full(ref transactions) => {
+                transactions.iter().map(|tx| tx.hash).collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+const TEST_TRANSACTION: &str = r#"{
+    "hash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+    "type": "0x2",
+    "nonce": "0x7",
+    "blockHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+    "blockNumber": "0x10",
+    "transactionIndex": "0x0",
+    "from": "0x1111111111111111111111111111111111111111",
+    "to": null,
+    "value": "0xde0b6b3a7640000",
+    "gas": "0x5208",
+    "maxFeePerGas": "0x3b9aca00",
+    "maxPriorityFeePerGas": "0x1",
+    "input": "0x",
+    "chainId": "0x1",
+    "accessList": [],
+    "v": "0x0",
+    "r": "0x1",
+    "s": "0x2"
+}"#;
+
+#[test]
+fn borrowed_transactions() {
+    let tx: RpcTransaction = serde_json::from_str(TEST_TRANSACTION).unwrap();
+    // Fields point into the JSON text rather than copies of it
+    let text = TEST_TRANSACTION.as_bytes().as_ptr_range();
+    assert!(text.contains(&tx.hash.as_str().as_ptr()));
+    assert_eq!(tx.nonce.to_u64().unwrap(), 7);
+    assert_eq!(
+        tx.value.to_uint256().unwrap(),
+        1_000_000_000_000_000_000u64.into()
+    );
+    assert_eq!(tx.from.to_address().unwrap(), Address::from([0x11u8; 20]));
+    assert_eq!(tx.to, None);
+    assert_eq!(tx.gas_price, None);
+    assert_eq!(tx.input.to_bytes().unwrap(), Vec::<u8>::new());
+
+    let json = serde_json::to_string(&tx).unwrap();
+    assert_eq!(serde_json::from_str::<RpcTransaction>(&json).unwrap(), tx);
+
+    assert!(serde_json::from_str::<Hex>(r#""0xzz""#).is_err());
+    // Escapes would have to be copied out
+    assert!(serde_json::from_str::<Hex>(r#""\u0030x00""#).is_err());
+    assert!(serde_json::from_str::<Hex>(r#""0x""#)
+        .unwrap()
+        .to_u64()
+        .is_err());
+}
+
+#[test]
+fn borrowed_blocks_and_receipts() {
+    let hashes = r#"{
+        "hash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+        "parentHash": "0x4444444444444444444444444444444444444444444444444444444444444444",
+        "number": "0x10",
+        "timestamp": "0x6553f100",
+        "miner": "0x5555555555555555555555555555555555555555",
+        "stateRoot": "0x66",
+        "transactionsRoot": "0x77",
+        "receiptsRoot": "0x88",
+        "gasLimit": "0x1c9c380",
+        "gasUsed": "0x5208",
+        "baseFeePerGas": "0x7",
+        "extraData": "0x",
+        "transactions": ["0x2222222222222222222222222222222222222222222222222222222222222222"]
+    }"#;
+    let block: RpcBlock = serde_json::from_str(hashes).unwrap();
+    assert_eq!(
+        block.block_header().unwrap(),
+        BlockHeader {
+            number: 16,
+            hash: [0x33u8; 32],
+            parent_hash: [0x44u8; 32],
+        }
+    );
+    let full = hashes.replace(
+        r#"["0x2222222222222222222222222222222222222222222222222222222222222222"]"#,
+        &format!("[{}]", TEST_TRANSACTION),
+    );
+    let full_block: RpcBlock = serde_json::from_str(&full).unwrap();
+    assert!(matches!(
+        full_block.transactions,
+        BlockTransactions::Full(_)
+    ));
+    assert_eq!(full_block.transaction_hashes(), block.transaction_hashes());
+
+    let receipt = r#"{
+        "transactionHash": "0x2222222222222222222222222222222222222222222222222222222222222222",
+        "transactionIndex": "0x0",
+        "blockHash": "0x3333333333333333333333333333333333333333333333333333333333333333",
+        "blockNumber": "0x10",
+        "from": "0x1111111111111111111111111111111111111111",
+        "to": "0x5555555555555555555555555555555555555555",
+        "cumulativeGasUsed": "0x5208",
+        "gasUsed": "0x5208",
+        "contractAddress": null,
+        "logs": [{
+            "address": "0x5555555555555555555555555555555555555555",
+            "topics": ["0x6666666666666666666666666666666666666666666666666666666666666666"],
+            "data": "0x01",
+            "logIndex": "0x0",
+            "removed": false
+        }],
+        "logsBloom": "0x00",
+        "status": "0x1",
+        "type": "0x2"
+    }"#;
+    let receipt: RpcReceipt = serde_json::from_str(receipt).unwrap();
+    assert_eq!(receipt.succeeded().unwrap(), Some(true));
+    assert_eq!(
+        receipt.logs[0].to_log().unwrap(),
+        Log {
+            address: Address::from([0x55u8; 20]),
+            topics: vec![[0x66u8; 32]],
+            data: vec![0x01],
+        }
+    );
+}
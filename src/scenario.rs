@@ -0,0 +1,259 @@
+//! Tests against a fork of a live chain.
+//!
+//! A [ForkScenario](struct.ForkScenario.html) describes the chain to fork,
+//! the accounts to set up on it and the events a test expects to see. It
+//! turns into the [DevNodeCall](../devnode/enum.DevNodeCall.html)s that
+//! prepare the development node, and checks the logs of the transactions a
+//! test sends against the expected events.
+use abi::{derive_signature, encode_tokens, Token};
+use address::Address;
+use devnode::{DevNode, DevNodeCall, Fork, RpcRequest};
+use event::{topic, Log};
+use failure::Error;
+use num256::Uint256;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ScenarioError {
+    /// No log matches the expected event with this index, after the logs
+    /// that matched the events before it
+    #[fail(display = "Expected event {} ({}) was not emitted", _0, _1)]
+    MissingEvent(usize, String),
+}
+
+/// State to set up for an account before the test runs.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AccountOverride {
+    pub address: Address,
+    pub balance: Option<Uint256>,
+    pub nonce: Option<Uint256>,
+    pub code: Option<Vec<u8>>,
+    /// Whether the test sends transactions from the account without its key
+    pub impersonate: bool,
+}
+
+/// An event a test expects to be emitted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExpectedEvent {
+    /// Contract that emits the event, any if `None`
+    pub address: Option<Address>,
+    /// Event signature, such as `Transfer(address,address,uint256)`
+    pub signature: String,
+    /// Expected values of the indexed parameters, in order. `None` matches
+    /// any value.
+    pub indexed: Vec<Option<Token>>,
+    /// Expected values of the other parameters, any if `None`
+    pub data: Option<Vec<Token>>,
+}
+
+impl ExpectedEvent {
+    /// Whether a log is this event, with the expected values.
+    pub fn matches(&self, log: &Log) -> bool {
+        if self.address.is_some_and(|address| address != log.address) {
+            return false;
+        }
+        if log.topics.len() != self.indexed.len() + 1
+            || log.topics[0] != derive_signature(&self.signature)
+        {
+            return false;
+        }
+        let topics_match = self
+            .indexed
+            .iter()
+            .zip(log.topics[1..].iter())
+            .all(|(expected, actual)| expected.as_ref().is_none_or(|t| topic(t) == *actual));
+        topics_match
+            && self
+                .data
+                .as_ref()
+                .is_none_or(|data| encode_tokens(data) == log.data)
+    }
+}
+
+/// A test on a fork of a live chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForkScenario {
+    pub fork: Fork,
+    pub accounts: Vec<AccountOverride>,
+    /// Events the test expects, in the order they are emitted
+    pub expected_events: Vec<ExpectedEvent>,
+}
+
+impl ForkScenario {
+    /// Calls that fork the chain and set up the accounts, in order.
+    pub fn setup_calls(&self) -> Vec<DevNodeCall> {
+        let mut calls = vec![DevNodeCall::Reset(Some(self.fork.clone()))];
+        for account in &self.accounts {
+            if let Some(ref balance) = account.balance {
+                calls.push(DevNodeCall::SetBalance(account.address, balance.clone()));
+            }
+            if let Some(ref nonce) = account.nonce {
+                calls.push(DevNodeCall::SetNonce(account.address, nonce.clone()));
+            }
+            if let Some(ref code) = account.code {
+                calls.push(DevNodeCall::SetCode(account.address, code.clone()));
+            }
+            if account.impersonate {
+                calls.push(DevNodeCall::ImpersonateAccount(account.address));
+            }
+        }
+        calls
+    }
+
+    /// The setup calls as requests for `node`, numbered from `first_id`.
+    pub fn setup_requests(&self, node: DevNode, first_id: u64) -> Vec<RpcRequest> {
+        self.setup_calls()
+            .iter()
+            .enumerate()
+            .map(|(i, call)| call.request(node, first_id + i as u64))
+            .collect()
+    }
+
+    /// Calls that stop impersonating the accounts again.
+    pub fn teardown_calls(&self) -> Vec<DevNodeCall> {
+        self.accounts
+            .iter()
+            .filter(|account| account.impersonate)
+            .map(|account| DevNodeCall::StopImpersonatingAccount(account.address))
+            .collect()
+    }
+
+    /// Checks that the expected events were emitted in order. Other logs
+    /// may come before, between and after them.
+    pub fn check_events(&self, logs: &[Log]) -> Result<(), Error> {
+        let mut logs = logs.iter();
+        for (i, event) in self.expected_events.iter().enumerate() {
+            if !logs.any(|log| event.matches(log)) {
+                return Err(ScenarioError::MissingEvent(i, event.signature.clone()).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+const TRANSFER: &str = "Transfer(address,address,uint256)";
+
+#[cfg(test)]
+fn transfer_log(token: Address, from: Address, to: Address, amount: u32) -> Log {
+    Log {
+        address: token,
+        topics: vec![
+            derive_signature(TRANSFER),
+            topic(&Token::Address(from)),
+            topic(&Token::Address(to)),
+        ],
+        data: encode_tokens(&[amount.into()]),
+    }
+}
+
+#[test]
+fn setup_and_teardown() {
+    let whale = Address::from([0x11u8; 20]);
+    let contract = Address::from([0x22u8; 20]);
+    let scenario = ForkScenario {
+        fork: Fork {
+            url: "http://localhost:8545".to_string(),
+            block_number: Some(19_000_000),
+        },
+        accounts: vec![
+            AccountOverride {
+                address: whale,
+                balance: Some(1_000_000u32.into()),
+                impersonate: true,
+                ..Default::default()
+            },
+            AccountOverride {
+                address: contract,
+                code: Some(vec![0x60, 0x00]),
+                ..Default::default()
+            },
+        ],
+        expected_events: Vec::new(),
+    };
+    assert_eq!(
+        scenario.setup_calls(),
+        vec![
+            DevNodeCall::Reset(Some(scenario.fork.clone())),
+            DevNodeCall::SetBalance(whale, 1_000_000u32.into()),
+            DevNodeCall::ImpersonateAccount(whale),
+            DevNodeCall::SetCode(contract, vec![0x60, 0x00]),
+        ]
+    );
+    let requests = scenario.setup_requests(DevNode::Hardhat, 10);
+    assert_eq!(requests[0].method, "hardhat_reset");
+    assert_eq!(requests[3].id, 13);
+    assert_eq!(
+        scenario.teardown_calls(),
+        vec![DevNodeCall::StopImpersonatingAccount(whale)]
+    );
+}
+
+#[test]
+fn check_expected_events() {
+    let token = Address::from([0x11u8; 20]);
+    let alice = Address::from([0x22u8; 20]);
+    let bob = Address::from([0x33u8; 20]);
+    let transfer = |to: Address, amount: Option<u32>| ExpectedEvent {
+        address: Some(token),
+        signature: TRANSFER.to_string(),
+        indexed: vec![None, Some(Token::Address(to))],
+        data: amount.map(|amount| vec![amount.into()]),
+    };
+    let scenario = |expected_events: Vec<ExpectedEvent>| ForkScenario {
+        fork: Fork {
+            url: "http://localhost:8545".to_string(),
+            block_number: None,
+        },
+        accounts: Vec::new(),
+        expected_events,
+    };
+    let logs = vec![
+        transfer_log(token, alice, bob, 100),
+        transfer_log(Address::from([0x44u8; 20]), bob, alice, 5),
+        transfer_log(token, bob, alice, 40),
+    ];
+    assert!(
+        scenario(vec![transfer(bob, Some(100)), transfer(alice, None)])
+            .check_events(&logs)
+            .is_ok()
+    );
+    // Wrong amount, order or emitter
+    assert_eq!(
+        scenario(vec![transfer(bob, Some(99))])
+            .check_events(&logs)
+            .unwrap_err()
+            .downcast::<ScenarioError>()
+            .unwrap(),
+        ScenarioError::MissingEvent(0, TRANSFER.to_string())
+    );
+    assert!(scenario(vec![transfer(alice, None), transfer(bob, None)])
+        .check_events(&logs)
+        .is_err());
+    assert!(scenario(vec![transfer(alice, Some(5))])
+        .check_events(&logs)
+        .is_err());
+}
+
+#[test]
+fn dynamic_topics() {
+    let log = Log {
+        address: Address::default(),
+        topics: vec![
+            derive_signature("Registered(string)"),
+            topic(&Token::String("alice".to_string())),
+        ],
+        data: Vec::new(),
+    };
+    let expected = ExpectedEvent {
+        address: None,
+        signature: "Registered(string)".to_string(),
+        indexed: vec![Some(Token::String("alice".to_string()))],
+        data: None,
+    };
+    assert!(expected.matches(&log));
+    assert!(!ExpectedEvent {
+        indexed: vec![Some(Token::String("bob".to_string()))],
+        ..expected
+    }
+    .matches(&log));
+}
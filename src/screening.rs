@@ -0,0 +1,380 @@
+//! Screening of outgoing transactions against lists of addresses.
+//!
+//! Compliance rules ask that nothing be sent to, or on behalf of, a
+//! sanctioned address, and such an address need not be the destination:
+//! it can be the recipient of a token transfer, a swap or a bridge deposit,
+//! anywhere in the calldata. Decoding every contract a service might call
+//! is not possible, so [find_addresses](fn.find_addresses.html) looks for
+//! anything in the calldata that is encoded like an address: a word with
+//! twelve zero bytes followed by twenty that are not all zero.
+//!
+//! Words are looked for where the ABI puts them, right after the method
+//! id, and four bytes further along, where calls passed as `bytes` to
+//! multicalls, Safes and the like put them after their own method id.
+//! Small numbers are encoded the same way and are found too, but they do
+//! not appear on lists of addresses, so the scan errs on the side of
+//! finding too much rather than too little:
+//!
+//! ```rust
+//! use clarity::abi::encode_call;
+//! use clarity::screening::screen_calldata;
+//! use clarity::Address;
+//! use std::collections::HashSet;
+//!
+//! let sanctioned = Address::from([0x66u8; 20]);
+//! let denylist: HashSet<Address> = vec![sanctioned].into_iter().collect();
+//! let data = encode_call(
+//!     "transfer(address,uint256)",
+//!     &[sanctioned.into(), 1_000u32.into()],
+//! );
+//! let hits = screen_calldata(&data, &denylist);
+//! assert_eq!(hits[0].address, sanctioned);
+//! assert_eq!(hits[0].offset, 4);
+//! ```
+//!
+//! A [SigningHook](trait.SigningHook.html) is shown the destination, value,
+//! address-shaped words, access list and EIP-7702 delegates of a
+//! transaction right before
+//! [sign_screened](../transaction/enum.TypedTransaction.html#method.sign_screened)
+//! signs it, and can refuse. [Denylist](struct.Denylist.html) refuses
+//! anything that involves an address on a list.
+//!
+//! Only `sign_screened` asks the hook: `sign` and the signing methods of
+//! each transaction type and of `PrivateKey` do not, so screening holds
+//! only if every signature a service makes goes through `sign_screened`.
+use address::Address;
+use failure::Error;
+use num256::Uint256;
+use private_key::PrivateKey;
+use std::collections::HashSet;
+use transaction::TypedTransaction;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ScreeningError {
+    #[fail(display = "Signing refused: {}", _0)]
+    Refused(String),
+}
+
+/// Something address-shaped found in calldata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EmbeddedAddress {
+    pub address: Address,
+    /// Where the word of the address starts in the calldata
+    pub offset: usize,
+}
+
+/// A list of addresses to screen against, such as a sanctions list or the
+/// addresses a service may pay out to.
+pub trait AddressList {
+    fn is_listed(&self, address: &Address) -> bool;
+}
+
+impl AddressList for HashSet<Address> {
+    fn is_listed(&self, address: &Address) -> bool {
+        self.contains(address)
+    }
+}
+
+impl<F> AddressList for F
+where
+    F: Fn(&Address) -> bool,
+{
+    fn is_listed(&self, address: &Address) -> bool {
+        self(address)
+    }
+}
+
+/// The address-shaped words of `calldata`, in order. The method id is not
+/// looked at, and neither are words that end past the calldata.
+pub fn find_addresses(calldata: &[u8]) -> Vec<EmbeddedAddress> {
+    let mut found = Vec::new();
+    let mut offset = 4;
+    while offset + 32 <= calldata.len() {
+        // Words of the call itself, then calls nested in it
+        for start in &[offset, offset + 4] {
+            if let Some(word) = calldata.get(*start..*start + 32) {
+                if word[..12].iter().all(|b| *b == 0) && word[12..].iter().any(|b| *b != 0) {
+                    let mut address = [0u8; 20];
+                    address.copy_from_slice(&word[12..]);
+                    found.push(EmbeddedAddress {
+                        address: address.into(),
+                        offset: *start,
+                    });
+                }
+            }
+        }
+        offset += 32;
+    }
+    found
+}
+
+/// The address-shaped words of `calldata` that are on `list`.
+pub fn screen_calldata<L: AddressList>(calldata: &[u8], list: &L) -> Vec<EmbeddedAddress> {
+    find_addresses(calldata)
+        .into_iter()
+        .filter(|found| list.is_listed(&found.address))
+        .collect()
+}
+
+/// A transaction about to be signed, as a [SigningHook](trait.SigningHook.html)
+/// is shown it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SigningRequest<'a> {
+    pub transaction: &'a TypedTransaction,
+    pub destination: Address,
+    pub value: &'a Uint256,
+    /// The address-shaped words of the calldata, see
+    /// [find_addresses](fn.find_addresses.html)
+    pub embedded: Vec<EmbeddedAddress>,
+    /// The addresses of the access list
+    pub access_list: Vec<Address>,
+    /// The accounts whose code the authorizations of a set code
+    /// transaction delegate to
+    pub delegates: Vec<Address>,
+}
+
+impl<'a> SigningRequest<'a> {
+    pub fn new(transaction: &'a TypedTransaction) -> SigningRequest<'a> {
+        SigningRequest {
+            transaction,
+            destination: *transaction.to(),
+            value: transaction.value(),
+            embedded: find_addresses(transaction.data()),
+            access_list: transaction
+                .access_list()
+                .iter()
+                .map(|item| item.address)
+                .collect(),
+            delegates: transaction
+                .authorization_list()
+                .iter()
+                .map(|signed| signed.authorization.address)
+                .collect(),
+        }
+    }
+
+    /// The destination, the addresses embedded in the calldata, those of
+    /// the access list, then the delegates.
+    pub fn addresses(&self) -> impl Iterator<Item = &Address> {
+        std::iter::once(&self.destination)
+            .chain(self.embedded.iter().map(|found| &found.address))
+            .chain(self.access_list.iter())
+            .chain(self.delegates.iter())
+    }
+}
+
+/// A check run before every signature, which refuses to sign by returning
+/// the reason.
+pub trait SigningHook {
+    fn check(&self, request: &SigningRequest) -> Result<(), String>;
+}
+
+impl<F> SigningHook for F
+where
+    F: Fn(&SigningRequest) -> Result<(), String>,
+{
+    fn check(&self, request: &SigningRequest) -> Result<(), String> {
+        self(request)
+    }
+}
+
+/// Refuses to sign transactions to, with calldata that holds, with an access
+/// list that names, or that delegate to, an address on the list.
+#[derive(Debug, Clone)]
+pub struct Denylist<L>(pub L);
+
+impl<L: AddressList> SigningHook for Denylist<L> {
+    fn check(&self, request: &SigningRequest) -> Result<(), String> {
+        if self.0.is_listed(&request.destination) {
+            return Err(format!("destination {} is denylisted", request.destination));
+        }
+        if let Some(found) = request
+            .embedded
+            .iter()
+            .find(|found| self.0.is_listed(&found.address))
+        {
+            return Err(format!(
+                "calldata holds denylisted {} at byte {}",
+                found.address, found.offset
+            ));
+        }
+        if let Some(address) = request.access_list.iter().find(|a| self.0.is_listed(a)) {
+            return Err(format!("access list names denylisted {}", address));
+        }
+        match request.delegates.iter().find(|a| self.0.is_listed(a)) {
+            Some(address) => Err(format!("authorization delegates to denylisted {}", address)),
+            None => Ok(()),
+        }
+    }
+}
+
+impl TypedTransaction {
+    /// Signs the transaction like [sign](#method.sign), if `hook` lets it.
+    ///
+    /// Returns `ScreeningError::Refused` with the reason of the hook
+    /// otherwise.
+    pub fn sign_screened<H: SigningHook>(
+        &self,
+        key: &PrivateKey,
+        network_id: Option<u64>,
+        hook: &H,
+    ) -> Result<TypedTransaction, Error> {
+        if let Err(reason) = hook.check(&SigningRequest::new(self)) {
+            return Err(ScreeningError::Refused(reason).into());
+        }
+        self.sign(key, network_id)
+    }
+}
+
+#[test]
+fn find_nested_addresses() {
+    use abi::{encode_call, Token};
+    let recipient = Address::from([0x11u8; 20]);
+    let token = Address::from([0x22u8; 20]);
+    // A transfer of the token, passed to a multicall as bytes
+    let transfer = encode_call(
+        "transfer(address,uint256)",
+        &[recipient.into(), 1_000u32.into()],
+    );
+    let data = encode_call(
+        "aggregate((address,bytes)[])",
+        &[Token::Dynamic(vec![Token::Tuple(vec![
+            token.into(),
+            Token::UnboundedBytes(transfer),
+        ])])],
+    );
+    let found = find_addresses(&data);
+    let addresses: Vec<Address> = found.iter().map(|found| found.address).collect();
+    // The token, the recipient, and the amount, which looks like an address
+    // to the scan
+    let mut amount = [0u8; 20];
+    amount[18..].copy_from_slice(&1_000u16.to_be_bytes());
+    assert!(addresses.contains(&token));
+    assert!(addresses.contains(&recipient));
+    assert!(addresses.contains(&Address::from(amount)));
+    let nested = found
+        .iter()
+        .find(|found| found.address == recipient)
+        .unwrap();
+    assert_eq!(nested.offset % 32, 8);
+    assert_eq!(data[nested.offset + 12..nested.offset + 32], [0x11u8; 20]);
+
+    let denylist = |address: &Address| *address == recipient;
+    assert_eq!(screen_calldata(&data, &denylist), vec![*nested]);
+    assert!(screen_calldata(&data[..nested.offset + 31], &denylist).is_empty());
+    assert!(find_addresses(&[0u8; 100]).is_empty());
+}
+
+#[test]
+fn screen_before_signing() {
+    use abi::encode_call;
+    use transaction::Eip1559Transaction;
+    let sanctioned = Address::from([0x66u8; 20]);
+    let token = Address::from([0x22u8; 20]);
+    let key = PrivateKey::from([0x42u8; 32]);
+    let transfer = |to: Address| -> TypedTransaction {
+        Eip1559Transaction {
+            chain_id: 1u8.into(),
+            nonce: 0u8.into(),
+            max_priority_fee_per_gas: 1u8.into(),
+            max_fee_per_gas: 100u8.into(),
+            gas_limit: 60_000u32.into(),
+            to: token,
+            value: 0u8.into(),
+            data: encode_call("transfer(address,uint256)", &[to.into(), 5u8.into()]),
+            access_list: Default::default(),
+            signature: None,
+        }
+        .into()
+    };
+    let denylist = Denylist(vec![sanctioned].into_iter().collect::<HashSet<_>>());
+    let refused = transfer(sanctioned)
+        .sign_screened(&key, None, &denylist)
+        .unwrap_err()
+        .downcast::<ScreeningError>()
+        .unwrap();
+    assert_eq!(
+        refused,
+        ScreeningError::Refused(format!(
+            "calldata holds denylisted {} at byte 4",
+            sanctioned
+        ))
+    );
+    let allowed = transfer(Address::from([0x11u8; 20]));
+    assert_eq!(
+        allowed.sign_screened(&key, None, &denylist).unwrap(),
+        allowed.sign(&key, None).unwrap()
+    );
+    assert!(allowed
+        .sign_screened(&key, None, &Denylist(|address: &Address| *address == token))
+        .is_err());
+
+    // A hook that caps the value, and sees what the denylist sees
+    let cap = |request: &SigningRequest| {
+        assert_eq!(request.addresses().count(), 3);
+        if *request.value > 0u8.into() {
+            return Err("no ether".to_owned());
+        }
+        Ok(())
+    };
+    allowed.sign_screened(&key, None, &cap).unwrap();
+}
+
+#[test]
+fn screen_access_list_and_delegates() {
+    use transaction::{AccessListItem, Authorization, Eip7702Transaction};
+    let sanctioned = Address::from([0x66u8; 20]);
+    let key = PrivateKey::from([0x42u8; 32]);
+    let delegation = |delegate: Address, accessed: Address| -> TypedTransaction {
+        Eip7702Transaction {
+            chain_id: 1u8.into(),
+            nonce: 0u8.into(),
+            max_priority_fee_per_gas: 1u8.into(),
+            max_fee_per_gas: 100u8.into(),
+            gas_limit: 60_000u32.into(),
+            to: key.to_public_key().unwrap(),
+            value: 0u8.into(),
+            data: Vec::new(),
+            access_list: vec![AccessListItem {
+                address: accessed,
+                storage_keys: Vec::new(),
+            }],
+            authorization_list: vec![Authorization {
+                chain_id: 1u8.into(),
+                address: delegate,
+                nonce: 1u8.into(),
+            }
+            .sign(&key)],
+            signature: None,
+        }
+        .into()
+    };
+    let other = Address::from([0x11u8; 20]);
+    let tx = delegation(sanctioned, other);
+    let request = SigningRequest::new(&tx);
+    assert_eq!(request.access_list, vec![other]);
+    assert_eq!(request.delegates, vec![sanctioned]);
+    assert!(request.addresses().any(|address| *address == sanctioned));
+
+    let denylist = Denylist(vec![sanctioned].into_iter().collect::<HashSet<_>>());
+    let refused = |tx: TypedTransaction| {
+        tx.sign_screened(&key, None, &denylist)
+            .unwrap_err()
+            .downcast::<ScreeningError>()
+            .unwrap()
+    };
+    assert_eq!(
+        refused(delegation(sanctioned, other)),
+        ScreeningError::Refused(format!(
+            "authorization delegates to denylisted {}",
+            sanctioned
+        ))
+    );
+    assert_eq!(
+        refused(delegation(other, sanctioned)),
+        ScreeningError::Refused(format!("access list names denylisted {}", sanctioned))
+    );
+    delegation(other, other)
+        .sign_screened(&key, None, &denylist)
+        .unwrap();
+}
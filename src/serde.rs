@@ -0,0 +1,205 @@
+//! Field level serde helpers.
+//!
+//! By default `Address` serializes as a lowercase `0x` prefixed string. APIs
+//! that expect a different representation can pick one per field without
+//! wrapping addresses in newtypes:
+//!
+//! ```rust
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # extern crate clarity;
+//! # extern crate serde_json;
+//! use clarity::Address;
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Payout {
+//!     #[serde(with = "clarity::serde::address_checksum")]
+//!     to: Address,
+//!     #[serde(with = "clarity::serde::address_lower")]
+//!     from: Address,
+//! }
+//! # fn main() {
+//! # let payout = Payout { to: Address::default(), from: Address::default() };
+//! # serde_json::to_string(&payout).unwrap();
+//! # }
+//! ```
+
+/// Serializes an `Address` in its EIP-55 checksummed form.
+///
+/// Deserialization accepts addresses in a single case (which carry no
+/// checksum), but mixed-case input has to match the checksum.
+pub mod address_checksum {
+    use address::Address;
+    use serde_crate::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(address: &Address, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&address.to_checksum_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Address, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        Address::parse_and_validate(&s).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes an `Address` as a lowercase `0x` prefixed string.
+///
+/// Deserialization accepts any case and does not validate checksums.
+pub mod address_lower {
+    use address::Address;
+    use serde_crate::{de, Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(address: &Address, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&address.to_string())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Address, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+/// Serializes bytes as a `0x` prefixed hexadecimal string, as JSON-RPC
+/// does for data.
+pub mod hex_bytes {
+    use serde_crate::{de, Deserialize, Deserializer, Serializer};
+    use utils::{bytes_to_hex_str, hex_str_to_bytes};
+
+    pub fn serialize<S>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("0x{}", bytes_to_hex_str(bytes)))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<u8>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        hex_str_to_bytes(&s).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes a 32 byte value, such as a hash, as a `0x` prefixed
+/// hexadecimal string.
+pub mod hex_bytes32 {
+    use serde_crate::{de, Deserialize, Deserializer, Serializer};
+    use utils::{bytes_to_hex_str, hex_str_to_bytes32};
+
+    pub fn serialize<S>(bytes: &[u8; 32], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("0x{}", bytes_to_hex_str(bytes)))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<[u8; 32], D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        hex_str_to_bytes32(&s).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes an `Uint256` as a JSON-RPC quantity, i.e. a `0x` prefixed
+/// hexadecimal number without leading zeros.
+pub mod quantity {
+    use num256::Uint256;
+    use serde_crate::{de, Deserialize, Deserializer, Serializer};
+    use utils::hex_str_to_uint256;
+
+    pub fn serialize<S>(value: &Uint256, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&format!("{:#x}", value))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Uint256, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        hex_str_to_uint256(&s).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+struct Payout {
+    #[serde(with = "address_checksum")]
+    to: ::address::Address,
+    #[serde(with = "address_lower")]
+    from: ::address::Address,
+}
+
+#[test]
+fn serialize_per_field() {
+    let payout = Payout {
+        to: "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+            .parse()
+            .unwrap(),
+        from: "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359"
+            .parse()
+            .unwrap(),
+    };
+    let json = serde_json::to_string(&payout).unwrap();
+    assert_eq!(
+        json,
+        r#"{"to":"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed","from":"0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359"}"#
+    );
+    let recovered: Payout = serde_json::from_str(&json).unwrap();
+    assert_eq!(recovered, payout);
+}
+
+#[test]
+fn deserialize_checksum_mismatch() {
+    // Checksum is only enforced on the checksummed field
+    let bad_checksum = r#"{"to":"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD","from":"0xfb6916095ca1df60bb79ce92ce3ea74c37c5d359"}"#;
+    assert!(serde_json::from_str::<Payout>(bad_checksum).is_err());
+    let lower_checksum = r#"{"to":"0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed","from":"0xFB6916095CA1DF60bB79Ce92cE3Ea74c37c5d359"}"#;
+    assert!(serde_json::from_str::<Payout>(lower_checksum).is_ok());
+}
+
+#[test]
+fn hex_fields() {
+    use num256::Uint256;
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Call {
+        #[serde(with = "hex_bytes")]
+        data: Vec<u8>,
+        #[serde(with = "quantity")]
+        value: Uint256,
+    }
+    let call = Call {
+        data: vec![0xde, 0xad],
+        value: 255u8.into(),
+    };
+    let json = serde_json::to_string(&call).unwrap();
+    assert_eq!(json, r#"{"data":"0xdead","value":"0xff"}"#);
+    assert_eq!(serde_json::from_str::<Call>(&json).unwrap(), call);
+    assert_eq!(
+        serde_json::to_string(&Call {
+            data: Vec::new(),
+            value: 0u8.into()
+        })
+        .unwrap(),
+        r#"{"data":"0x","value":"0x0"}"#
+    );
+    assert!(serde_json::from_str::<Call>(r#"{"data":"0x","value":"0x"}"#).is_err());
+    assert!(serde_json::from_str::<Call>(r#"{"data":"0xzz","value":"0x1"}"#).is_err());
+}
@@ -3,16 +3,18 @@ use constants::SECPK1N;
 use context::SECP256K1;
 use error::ClarityError;
 use failure::Error;
+use hash::{ethereum_msg_hash, keccak256};
+use instrument::{outcome, Span};
 use num256::Uint256;
 use num_traits::{ToPrimitive, Zero};
+use public_key::PublicKey;
 use secp256k1::recovery::{RecoverableSignature, RecoveryId};
 use secp256k1::Message;
-use sha3::{Digest, Keccak256};
 use std::fmt;
 use std::str::FromStr;
 use utils::{
     big_endian_uint256_deserialize, big_endian_uint256_serialize, bytes_to_hex_str,
-    hex_str_to_bytes,
+    hex_str_to_bytes, strip_hex_prefix,
 };
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize, Hash)]
@@ -156,6 +158,21 @@ impl Signature {
     ///
     /// This can be called with any arbitrary signature, and a hashed message.
     pub fn recover(&self, hash: &[u8]) -> Result<Address, Error> {
+        let span = Span::recover();
+        let result = self.recover_address(hash);
+        span.finish(|| outcome(&result));
+        result
+    }
+
+    /// Recovers the signer of a message signed with `personal_sign`, as
+    /// [sign_ethereum_msg](../private_key/struct.PrivateKey.html#method.sign_ethereum_msg)
+    /// signs it.
+    pub fn recover_ethereum_msg(&self, msg: &[u8]) -> Result<Address, Error> {
+        self.recover(&ethereum_msg_hash(msg))
+    }
+
+    /// Recovers the public key rather than the address of the signer.
+    pub fn recover_public_key(&self, hash: &[u8]) -> Result<PublicKey, Error> {
         // Create recovery ID which is "v" minus 27. Without this it wouldn't be possible to extract recoverable signature.
         let v = RecoveryId::from_i32(
             self.vee()?
@@ -169,21 +186,23 @@ impl Signature {
         // Get the compact form using bytes, and "v" parameter
         let compact = RecoverableSignature::from_compact(&self.to_bytes()[..64], v)?;
         // Acquire secp256k1 context from thread local storage
-        let pkey = SECP256K1.with(move |object| -> Result<_, Error> {
+        SECP256K1.with(move |object| -> Result<_, Error> {
             // Borrow once and reuse
             let secp256k1 = object.borrow();
             // Recover public key
-            let pkey = secp256k1.recover(&msg, &compact)?;
-            // Serialize the recovered public key in uncompressed format
-            Ok(pkey.serialize_uncompressed())
-        })?;
+            Ok(secp256k1.recover(&msg, &compact)?.into())
+        })
+    }
+
+    fn recover_address(&self, hash: &[u8]) -> Result<Address, Error> {
+        // Serialize the recovered public key in uncompressed format
+        let pkey = self.recover_public_key(hash)?.to_uncompressed();
         assert_eq!(pkey.len(), 65);
         if pkey[1..].to_vec() == [0x00u8; 64].to_vec() {
             return Err(ClarityError::ZeroPrivKey.into());
         }
         // Finally an address is last 20 bytes of a hash of the public key.
-        let sender = Keccak256::digest(&pkey[1..]);
-        debug_assert_eq!(sender.len(), 32);
+        let sender = keccak256(&pkey[1..]);
         Address::from_slice(&sender[12..])
     }
 }
@@ -223,7 +242,7 @@ impl FromStr for Signature {
     /// optional "0x" prefix at the beggining.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         // Strip optional prefix
-        let s = if s.starts_with("0x") { &s[2..] } else { &s };
+        let s = strip_hex_prefix(s);
 
         // Signature has exactly 130 characters (65 as bytes)
         ensure!(
@@ -383,3 +402,16 @@ fn parse_invalid_signature() {
     let _sig: Signature = "deadbeef".parse().unwrap();
     let _sig: Signature = "0x".parse().unwrap();
 }
+
+#[test]
+fn parse_prefix_and_garbage() {
+    let sig = Signature::new(27u32.into(), 1u32.into(), 2u32.into());
+    let digits = bytes_to_hex_str(&sig.to_bytes());
+    assert_eq!(digits.parse::<Signature>().unwrap(), sig);
+    assert_eq!(format!("0x{}", digits).parse::<Signature>().unwrap(), sig);
+    assert_eq!(format!("0X{}", digits).parse::<Signature>().unwrap(), sig);
+    assert!(format!("+0{}", &digits[2..]).parse::<Signature>().is_err());
+    assert!(format!("0x0x{}", &digits[4..])
+        .parse::<Signature>()
+        .is_err());
+}
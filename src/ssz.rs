@@ -0,0 +1,103 @@
+//! Minimal SSZ merkleization used for consensus-layer signing roots.
+//!
+//! This is not a general SSZ implementation. It only covers the fixed-size
+//! containers Clarity needs to sign (fork data, signing data, deposit
+//! messages), which are all built from 32 byte chunks.
+use sha2::{Digest, Sha256};
+
+/// A single 32 byte SSZ chunk.
+pub(crate) type Chunk = [u8; 32];
+
+/// SHA-256 of two chunks concatenated together.
+pub(crate) fn hash_pair(a: &Chunk, b: &Chunk) -> Chunk {
+    let mut hasher = Sha256::new();
+    hasher.update(a);
+    hasher.update(b);
+    let mut result: Chunk = Default::default();
+    result.copy_from_slice(&hasher.finalize());
+    result
+}
+
+/// Merkleizes a list of chunks, padding it with zero chunks up to the next
+/// power of two.
+pub(crate) fn merkleize(chunks: &[Chunk]) -> Chunk {
+    if chunks.is_empty() {
+        return Chunk::default();
+    }
+    let mut layer = chunks.to_vec();
+    let width = layer.len().next_power_of_two();
+    layer.resize(width, Chunk::default());
+    while layer.len() > 1 {
+        layer = layer
+            .chunks(2)
+            .map(|pair| hash_pair(&pair[0], &pair[1]))
+            .collect();
+    }
+    layer[0]
+}
+
+/// Hash tree root of a fixed-size byte vector (i.e. `Bytes48`).
+pub(crate) fn bytes_root(bytes: &[u8]) -> Chunk {
+    let chunks: Vec<Chunk> = bytes
+        .chunks(32)
+        .map(|data| {
+            let mut chunk = Chunk::default();
+            chunk[..data.len()].copy_from_slice(data);
+            chunk
+        })
+        .collect();
+    merkleize(&chunks)
+}
+
+/// Hash tree root of an `uint64`, which is its little endian form padded on
+/// the right.
+pub(crate) fn uint64_root(value: u64) -> Chunk {
+    let mut chunk = Chunk::default();
+    chunk[..8].copy_from_slice(&value.to_le_bytes());
+    chunk
+}
+
+/// Computes a signature domain as defined by the consensus specs
+/// (`compute_domain`).
+pub(crate) fn compute_domain(
+    domain_type: [u8; 4],
+    fork_version: [u8; 4],
+    genesis_validators_root: &Chunk,
+) -> Chunk {
+    let mut version = Chunk::default();
+    version[..4].copy_from_slice(&fork_version);
+    let fork_data_root = hash_pair(&version, genesis_validators_root);
+    let mut domain = Chunk::default();
+    domain[..4].copy_from_slice(&domain_type);
+    domain[4..].copy_from_slice(&fork_data_root[..28]);
+    domain
+}
+
+/// Computes the root that is actually signed for a given object root and
+/// domain (`compute_signing_root`).
+pub(crate) fn signing_root(object_root: &Chunk, domain: &Chunk) -> Chunk {
+    hash_pair(object_root, domain)
+}
+
+#[test]
+fn merkleize_pads_to_power_of_two() {
+    let a = [1u8; 32];
+    let b = [2u8; 32];
+    let c = [3u8; 32];
+    assert_eq!(merkleize(&[a]), a);
+    assert_eq!(merkleize(&[a, b]), hash_pair(&a, &b));
+    assert_eq!(
+        merkleize(&[a, b, c]),
+        hash_pair(&hash_pair(&a, &b), &hash_pair(&c, &Chunk::default()))
+    );
+}
+
+#[test]
+fn mainnet_deposit_domain() {
+    use utils::bytes_to_hex_str;
+    let domain = compute_domain([3, 0, 0, 0], [0, 0, 0, 0], &Chunk::default());
+    assert_eq!(
+        bytes_to_hex_str(&domain),
+        "03000000f5a5fd42d16a20302798ef6ed309979b43003d2320d9f0e8ea9831a9"
+    );
+}
@@ -0,0 +1,497 @@
+//! Helpers to assemble beacon chain deposits and withdrawal credentials offline.
+//!
+//! A deposit is a regular transaction to the deposit contract that carries
+//! the validator public key, withdrawal credentials, a BLS signature over the
+//! deposit message, and the `deposit_data_root` that the contract checks
+//! against the other fields. Everything here is byte-exact with the consensus
+//! specs, so the resulting calldata can be signed with a `PrivateKey` like any
+//! other contract call.
+//!
+//! Producing the BLS signature itself requires the `bls` feature.
+use abi::{derive_method_id, encode_call, Token};
+use address::Address;
+use failure::Error;
+use num256::Uint256;
+use sha2::{Digest, Sha256};
+use ssz::{bytes_root, compute_domain, merkleize, signing_root, uint64_root, Chunk};
+
+/// Domain type of deposit message signatures.
+pub const DOMAIN_DEPOSIT: [u8; 4] = [0x03, 0x00, 0x00, 0x00];
+
+/// Domain type of signed BLS to execution address credential changes.
+pub const DOMAIN_BLS_TO_EXECUTION_CHANGE: [u8; 4] = [0x0a, 0x00, 0x00, 0x00];
+
+/// Genesis fork version of Ethereum mainnet.
+pub const MAINNET_GENESIS_FORK_VERSION: [u8; 4] = [0x00, 0x00, 0x00, 0x00];
+
+/// Genesis validators root of Ethereum mainnet.
+pub const MAINNET_GENESIS_VALIDATORS_ROOT: [u8; 32] = [
+    0x4b, 0x36, 0x3d, 0xb9, 0x4e, 0x28, 0x61, 0x20, 0xd7, 0x6e, 0xb9, 0x05, 0x34, 0x0f, 0xdd, 0x4e,
+    0x54, 0xbf, 0xe9, 0xf0, 0x6b, 0xf3, 0x3f, 0xf6, 0xcf, 0x5a, 0xd2, 0x7f, 0x51, 0x1b, 0xfe, 0x95,
+];
+
+/// Signature of the deposit function of the deposit contract.
+pub const DEPOSIT_SIGNATURE: &str = "deposit(bytes,bytes,bytes,bytes32)";
+
+/// Smallest deposit (in Gwei) accepted by the deposit contract.
+pub const MIN_DEPOSIT_AMOUNT: u64 = 1_000_000_000;
+
+lazy_static! {
+    /// Address of the deposit contract on Ethereum mainnet.
+    pub static ref MAINNET_DEPOSIT_CONTRACT: Address =
+        "0x00000000219ab540356cbb839cbe05303d7705fa".parse().unwrap();
+}
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum DepositError {
+    #[fail(display = "Deposit amount of {} Gwei is below the minimum deposit", _0)]
+    AmountTooLow(u64),
+}
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum WithdrawalCredentialsError {
+    #[fail(display = "Withdrawal credentials should be exactly 32 bytes")]
+    InvalidLength,
+    #[fail(display = "Unknown withdrawal credentials prefix {:#04x}", _0)]
+    UnknownPrefix(u8),
+    #[fail(display = "Withdrawal credentials padding should be zero")]
+    NonZeroPadding,
+}
+
+/// Withdrawal credentials of a validator.
+///
+/// These are the 32 bytes committed to in a deposit that determine where the
+/// staked funds can be withdrawn to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WithdrawalCredentials {
+    /// `0x00` credentials, which commit to the SHA-256 hash of a BLS withdrawal
+    /// key. Only the last 31 bytes of the hash are kept.
+    Bls([u8; 31]),
+    /// `0x01` credentials with withdrawals to an execution layer address
+    Eth1(Address),
+    /// `0x02` compounding credentials (EIP-7251) with withdrawals to an
+    /// execution layer address
+    Compounding(Address),
+}
+
+impl WithdrawalCredentials {
+    /// Creates `0x00` credentials for a BLS withdrawal public key.
+    pub fn from_bls_pubkey(pubkey: &[u8; 48]) -> WithdrawalCredentials {
+        let digest = Sha256::digest(pubkey);
+        let mut hash = [0u8; 31];
+        hash.copy_from_slice(&digest[1..]);
+        WithdrawalCredentials::Bls(hash)
+    }
+
+    /// Parses and validates raw withdrawal credentials.
+    ///
+    /// Address based credentials are required to have all 11 padding bytes
+    /// set to zero.
+    pub fn from_bytes(bytes: &[u8]) -> Result<WithdrawalCredentials, Error> {
+        if bytes.len() != 32 {
+            return Err(WithdrawalCredentialsError::InvalidLength.into());
+        }
+        match bytes[0] {
+            0x00 => {
+                let mut hash = [0u8; 31];
+                hash.copy_from_slice(&bytes[1..]);
+                Ok(WithdrawalCredentials::Bls(hash))
+            }
+            prefix @ 0x01 | prefix @ 0x02 => {
+                if bytes[1..12].iter().any(|b| *b != 0) {
+                    return Err(WithdrawalCredentialsError::NonZeroPadding.into());
+                }
+                let address = Address::from_slice(&bytes[12..])?;
+                if prefix == 0x01 {
+                    Ok(WithdrawalCredentials::Eth1(address))
+                } else {
+                    Ok(WithdrawalCredentials::Compounding(address))
+                }
+            }
+            prefix => Err(WithdrawalCredentialsError::UnknownPrefix(prefix).into()),
+        }
+    }
+
+    /// Get the raw 32 byte form of the credentials.
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut result = [0u8; 32];
+        match *self {
+            WithdrawalCredentials::Bls(ref hash) => result[1..].copy_from_slice(hash),
+            WithdrawalCredentials::Eth1(ref address) => {
+                result[0] = 0x01;
+                result[12..].copy_from_slice(address.as_bytes());
+            }
+            WithdrawalCredentials::Compounding(ref address) => {
+                result[0] = 0x02;
+                result[12..].copy_from_slice(address.as_bytes());
+            }
+        }
+        result
+    }
+
+    /// Get the execution layer address withdrawals are sent to, if any.
+    pub fn execution_address(&self) -> Option<Address> {
+        match *self {
+            WithdrawalCredentials::Bls(_) => None,
+            WithdrawalCredentials::Eth1(address) | WithdrawalCredentials::Compounding(address) => {
+                Some(address)
+            }
+        }
+    }
+
+    /// Checks whether these are `0x00` credentials of a given BLS withdrawal
+    /// public key.
+    ///
+    /// This is the check performed by the beacon chain before accepting a
+    /// `BlsToExecutionChange` signed by that key.
+    pub fn is_bls_pubkey(&self, pubkey: &[u8; 48]) -> bool {
+        *self == WithdrawalCredentials::from_bls_pubkey(pubkey)
+    }
+}
+
+/// A request to change `0x00` (BLS) withdrawal credentials of a validator to
+/// `0x01` credentials with a given execution address.
+///
+/// It has to be signed with the BLS withdrawal key, not the validator key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlsToExecutionChange {
+    pub validator_index: u64,
+    /// BLS withdrawal public key committed to by the current credentials
+    pub from_bls_pubkey: [u8; 48],
+    pub to_execution_address: Address,
+}
+
+impl BlsToExecutionChange {
+    /// Hash tree root of this message.
+    pub fn root(&self) -> [u8; 32] {
+        merkleize(&[
+            uint64_root(self.validator_index),
+            bytes_root(&self.from_bls_pubkey),
+            bytes_root(self.to_execution_address.as_bytes()),
+        ])
+    }
+
+    /// The root that has to be signed with the BLS withdrawal key.
+    ///
+    /// Unlike voluntary exits, credential changes always use the genesis fork
+    /// version so they stay valid across forks of a network.
+    pub fn signing_root(
+        &self,
+        genesis_fork_version: [u8; 4],
+        genesis_validators_root: &[u8; 32],
+    ) -> [u8; 32] {
+        let domain = compute_domain(
+            DOMAIN_BLS_TO_EXECUTION_CHANGE,
+            genesis_fork_version,
+            genesis_validators_root,
+        );
+        signing_root(&self.root(), &domain)
+    }
+
+    /// New withdrawal credentials that will be in effect once this change is
+    /// processed.
+    pub fn new_credentials(&self) -> WithdrawalCredentials {
+        WithdrawalCredentials::Eth1(self.to_execution_address)
+    }
+}
+
+/// Computes the deposit domain for a network identified by its genesis fork
+/// version.
+///
+/// Deposit domains are independent of the genesis validators root, which
+/// makes deposits valid across forks of a network.
+pub fn deposit_domain(genesis_fork_version: [u8; 4]) -> [u8; 32] {
+    compute_domain(DOMAIN_DEPOSIT, genesis_fork_version, &Chunk::default())
+}
+
+/// Hash tree root of a `DepositMessage`, which is the deposit data without a
+/// signature.
+pub fn deposit_message_root(
+    pubkey: &[u8; 48],
+    withdrawal_credentials: &[u8; 32],
+    amount: u64,
+) -> [u8; 32] {
+    merkleize(&[
+        bytes_root(pubkey),
+        *withdrawal_credentials,
+        uint64_root(amount),
+    ])
+}
+
+/// The root that has to be signed with the validator key to produce a
+/// deposit signature.
+pub fn deposit_signing_root(
+    pubkey: &[u8; 48],
+    withdrawal_credentials: &[u8; 32],
+    amount: u64,
+    genesis_fork_version: [u8; 4],
+) -> [u8; 32] {
+    signing_root(
+        &deposit_message_root(pubkey, withdrawal_credentials, amount),
+        &deposit_domain(genesis_fork_version),
+    )
+}
+
+/// A signed deposit, as found in `deposit_data.json` files produced by the
+/// staking deposit tooling.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DepositData {
+    /// Validator BLS public key in compressed form
+    pub pubkey: [u8; 48],
+    pub withdrawal_credentials: [u8; 32],
+    /// Deposited amount in Gwei
+    pub amount: u64,
+    /// BLS signature over the deposit signing root
+    pub signature: [u8; 96],
+}
+
+impl DepositData {
+    /// Hash tree root of this deposit data (`deposit_data_root`).
+    pub fn root(&self) -> [u8; 32] {
+        merkleize(&[
+            bytes_root(&self.pubkey),
+            self.withdrawal_credentials,
+            uint64_root(self.amount),
+            bytes_root(&self.signature),
+        ])
+    }
+
+    /// The root that is signed for this deposit on a given network.
+    pub fn signing_root(&self, genesis_fork_version: [u8; 4]) -> [u8; 32] {
+        deposit_signing_root(
+            &self.pubkey,
+            &self.withdrawal_credentials,
+            self.amount,
+            genesis_fork_version,
+        )
+    }
+
+    /// Value in wei that has to be attached to the deposit transaction.
+    pub fn value(&self) -> Uint256 {
+        Uint256::from(self.amount) * Uint256::from(1_000_000_000u64)
+    }
+
+    /// Encodes a call to `deposit(bytes,bytes,bytes,bytes32)` including the
+    /// computed deposit data root.
+    ///
+    /// Deposits below the minimum amount would be rejected by the contract,
+    /// so they are rejected here as well.
+    pub fn to_calldata(&self) -> Result<Vec<u8>, Error> {
+        if self.amount < MIN_DEPOSIT_AMOUNT {
+            return Err(DepositError::AmountTooLow(self.amount).into());
+        }
+        Ok(encode_call(
+            DEPOSIT_SIGNATURE,
+            &[
+                Token::UnboundedBytes(self.pubkey.to_vec()),
+                Token::UnboundedBytes(self.withdrawal_credentials.to_vec()),
+                Token::UnboundedBytes(self.signature.to_vec()),
+                Token::Bytes(self.root().to_vec()),
+            ],
+        ))
+    }
+}
+
+/// Method ID of the deposit function.
+pub fn deposit_method_id() -> [u8; 4] {
+    derive_method_id(DEPOSIT_SIGNATURE)
+}
+
+#[cfg(test)]
+fn make_deposit() -> DepositData {
+    let mut pubkey = [0u8; 48];
+    pubkey.copy_from_slice(&[0xaau8; 48]);
+    let mut signature = [0u8; 96];
+    signature.copy_from_slice(&[0xccu8; 96]);
+    DepositData {
+        pubkey,
+        withdrawal_credentials: [0xbbu8; 32],
+        amount: 32_000_000_000,
+        signature,
+    }
+}
+
+#[test]
+fn deposit_selector() {
+    use utils::bytes_to_hex_str;
+    assert_eq!(bytes_to_hex_str(&deposit_method_id()), "22895118");
+}
+
+#[test]
+fn deposit_data_root_layout() {
+    use ssz::hash_pair;
+    let deposit = make_deposit();
+    // Signature spans 3 chunks which are padded to 4 when merkleized
+    let signature_root = hash_pair(
+        &hash_pair(&[0xccu8; 32], &[0xccu8; 32]),
+        &hash_pair(&[0xccu8; 32], &Chunk::default()),
+    );
+    let mut pubkey_tail = Chunk::default();
+    pubkey_tail[..16].copy_from_slice(&[0xaau8; 16]);
+    let pubkey_root = hash_pair(&[0xaau8; 32], &pubkey_tail);
+    assert_eq!(
+        deposit.root(),
+        hash_pair(
+            &hash_pair(&pubkey_root, &[0xbbu8; 32]),
+            &hash_pair(&uint64_root(32_000_000_000), &signature_root)
+        )
+    );
+}
+
+#[test]
+fn deposit_calldata() {
+    use utils::bytes_to_hex_str;
+    let deposit = make_deposit();
+    let calldata = deposit.to_calldata().unwrap();
+    assert_eq!(&calldata[..4], &deposit_method_id());
+    let words: Vec<String> = calldata[4..].chunks(32).map(bytes_to_hex_str).collect();
+    assert_eq!(words.len(), 4 + (1 + 2) + (1 + 1) + (1 + 3));
+    // Offsets of the three dynamic arguments
+    assert_eq!(
+        words[0],
+        "0000000000000000000000000000000000000000000000000000000000000080"
+    );
+    assert_eq!(
+        words[1],
+        "00000000000000000000000000000000000000000000000000000000000000e0"
+    );
+    assert_eq!(
+        words[2],
+        "0000000000000000000000000000000000000000000000000000000000000120"
+    );
+    assert_eq!(words[3], bytes_to_hex_str(&deposit.root()));
+    // Public key length, followed by the key padded on the right
+    assert_eq!(
+        words[4],
+        "0000000000000000000000000000000000000000000000000000000000000030"
+    );
+    assert_eq!(
+        words[6],
+        "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa00000000000000000000000000000000"
+    );
+    // Signature length
+    assert_eq!(
+        words[9],
+        "0000000000000000000000000000000000000000000000000000000000000060"
+    );
+}
+
+#[test]
+fn deposit_value_and_minimum() {
+    let mut deposit = make_deposit();
+    assert_eq!(
+        deposit.value(),
+        "32000000000000000000".parse::<Uint256>().unwrap()
+    );
+    deposit.amount = MIN_DEPOSIT_AMOUNT - 1;
+    assert_eq!(
+        deposit
+            .to_calldata()
+            .unwrap_err()
+            .downcast::<DepositError>()
+            .unwrap(),
+        DepositError::AmountTooLow(MIN_DEPOSIT_AMOUNT - 1)
+    );
+}
+
+#[test]
+fn bls_withdrawal_credentials() {
+    use utils::bytes_to_hex_str;
+    let pubkey = [0xaau8; 48];
+    let credentials = WithdrawalCredentials::from_bls_pubkey(&pubkey);
+    let bytes = credentials.to_bytes();
+    assert_eq!(bytes[0], 0x00);
+    assert_eq!(
+        bytes_to_hex_str(&bytes[1..]),
+        bytes_to_hex_str(&Sha256::digest(&pubkey[..])[1..])
+    );
+    assert!(credentials.is_bls_pubkey(&pubkey));
+    assert!(!credentials.is_bls_pubkey(&[0xabu8; 48]));
+    assert_eq!(credentials.execution_address(), None);
+    assert_eq!(
+        WithdrawalCredentials::from_bytes(&bytes).unwrap(),
+        credentials
+    );
+}
+
+#[test]
+fn address_withdrawal_credentials() {
+    use utils::bytes_to_hex_str;
+    let address: Address = "0x00000000219ab540356cbb839cbe05303d7705fa"
+        .parse()
+        .unwrap();
+    let eth1 = WithdrawalCredentials::Eth1(address);
+    assert_eq!(
+        bytes_to_hex_str(&eth1.to_bytes()),
+        "01000000000000000000000000000000219ab540356cbb839cbe05303d7705fa"
+    );
+    let compounding = WithdrawalCredentials::Compounding(address);
+    assert_eq!(
+        bytes_to_hex_str(&compounding.to_bytes()),
+        "02000000000000000000000000000000219ab540356cbb839cbe05303d7705fa"
+    );
+    for credentials in &[eth1, compounding] {
+        assert_eq!(credentials.execution_address(), Some(address));
+        assert_eq!(
+            WithdrawalCredentials::from_bytes(&credentials.to_bytes()).unwrap(),
+            *credentials
+        );
+    }
+}
+
+#[test]
+fn invalid_withdrawal_credentials() {
+    let mut bytes = WithdrawalCredentials::Eth1(Address::from([0x11u8; 20])).to_bytes();
+    bytes[5] = 1;
+    assert_eq!(
+        WithdrawalCredentials::from_bytes(&bytes)
+            .unwrap_err()
+            .downcast::<WithdrawalCredentialsError>()
+            .unwrap(),
+        WithdrawalCredentialsError::NonZeroPadding
+    );
+    bytes[0] = 0x03;
+    assert_eq!(
+        WithdrawalCredentials::from_bytes(&bytes)
+            .unwrap_err()
+            .downcast::<WithdrawalCredentialsError>()
+            .unwrap(),
+        WithdrawalCredentialsError::UnknownPrefix(0x03)
+    );
+    assert!(WithdrawalCredentials::from_bytes(&bytes[..31]).is_err());
+}
+
+#[test]
+fn bls_to_execution_change_root() {
+    use ssz::hash_pair;
+    let change = BlsToExecutionChange {
+        validator_index: 42,
+        from_bls_pubkey: [0xaau8; 48],
+        to_execution_address: Address::from([0x11u8; 20]),
+    };
+    let mut address_chunk = Chunk::default();
+    address_chunk[..20].copy_from_slice(&[0x11u8; 20]);
+    assert_eq!(
+        change.root(),
+        hash_pair(
+            &hash_pair(&uint64_root(42), &bytes_root(&[0xaau8; 48])),
+            &hash_pair(&address_chunk, &Chunk::default())
+        )
+    );
+    let domain = compute_domain(
+        DOMAIN_BLS_TO_EXECUTION_CHANGE,
+        MAINNET_GENESIS_FORK_VERSION,
+        &MAINNET_GENESIS_VALIDATORS_ROOT,
+    );
+    assert_eq!(
+        change.signing_root(
+            MAINNET_GENESIS_FORK_VERSION,
+            &MAINNET_GENESIS_VALIDATORS_ROOT
+        ),
+        hash_pair(&change.root(), &domain)
+    );
+    assert_eq!(
+        change.new_credentials(),
+        WithdrawalCredentials::Eth1(change.to_execution_address)
+    );
+}
@@ -0,0 +1,362 @@
+//! Storage dumps of an account and the changes between two of them.
+//!
+//! Reviewing a proxy upgrade comes down to comparing the storage of the
+//! proxy before and after it: slots that an upgrade was not supposed to
+//! touch should still hold what they held. Nodes dump storage with
+//! `debug_storageRangeAt`, a page at a time, keyed by the Keccak-256 hash
+//! of each slot along with the slot itself when they know it:
+//!
+//! ```rust
+//! # extern crate clarity;
+//! # extern crate serde_json;
+//! use clarity::storage_diff::{diff, StorageDump, StorageRange};
+//!
+//! # fn main() {
+//! let page = |value: &str| -> StorageRange {
+//!     let json = format!(
+//!         r#"{{
+//!             "storage": {{
+//!                 "0x290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563": {{
+//!                     "key": "0x0000000000000000000000000000000000000000000000000000000000000000",
+//!                     "value": "{}"
+//!                 }}
+//!             }},
+//!             "nextKey": null
+//!         }}"#,
+//!         value
+//!     );
+//!     serde_json::from_str(&json).unwrap()
+//! };
+//! let before: StorageDump =
+//!     vec![page("0x0000000000000000000000000000000000000000000000000000000000000001")].into();
+//! let after: StorageDump =
+//!     vec![page("0x0000000000000000000000000000000000000000000000000000000000000002")].into();
+//! let changes = diff(&before, &after);
+//! assert_eq!(changes.len(), 1);
+//! assert_eq!(changes[0].slot, Some([0u8; 32]));
+//! # }
+//! ```
+//!
+//! What the slots hold is up to the contract. A
+//! [SlotDecoder](trait.SlotDecoder.html), such as one built from the
+//! storage layout of the contract, turns a changed slot into changes of
+//! named variables, see [diff_decoded](fn.diff_decoded.html).
+use failure::Error;
+use hash::keccak256;
+use serde_crate::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use std::fmt;
+use utils::{bytes_to_hex_str, hex_str_to_bytes32};
+
+/// A slot of a dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StorageEntry {
+    /// The slot, when the node knows the preimage of its hash
+    pub key: Option<[u8; 32]>,
+    pub value: [u8; 32],
+}
+
+/// A page of storage, as returned by `debug_storageRangeAt`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageRange {
+    /// The slots by the hash of their key
+    pub storage: BTreeMap<[u8; 32], StorageEntry>,
+    /// The hash to ask the next page from, `None` on the last page
+    pub next_key: Option<[u8; 32]>,
+}
+
+#[derive(Deserialize)]
+struct RawEntry {
+    key: Option<String>,
+    value: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RawRange {
+    storage: BTreeMap<String, RawEntry>,
+    next_key: Option<String>,
+}
+
+impl<'de> Deserialize<'de> for StorageRange {
+    fn deserialize<D>(deserializer: D) -> Result<StorageRange, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let raw = RawRange::deserialize(deserializer)?;
+        let convert = || -> Result<StorageRange, Error> {
+            let mut storage = BTreeMap::new();
+            for (hash, entry) in raw.storage {
+                let key = match entry.key {
+                    Some(ref key) => Some(hex_str_to_bytes32(key)?),
+                    None => None,
+                };
+                let value = hex_str_to_bytes32(&entry.value)?;
+                storage.insert(hex_str_to_bytes32(&hash)?, StorageEntry { key, value });
+            }
+            let next_key = match raw.next_key {
+                Some(ref next_key) => Some(hex_str_to_bytes32(next_key)?),
+                None => None,
+            };
+            Ok(StorageRange { storage, next_key })
+        };
+        convert().map_err(de::Error::custom)
+    }
+}
+
+/// The storage of an account, or the part of it that was dumped.
+///
+/// Slots that are not in the dump hold zero, as nodes leave empty slots
+/// out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StorageDump {
+    slots: BTreeMap<[u8; 32], StorageEntry>,
+}
+
+impl StorageDump {
+    pub fn new() -> StorageDump {
+        StorageDump::default()
+    }
+
+    /// Adds the slots of a page.
+    pub fn extend(&mut self, range: StorageRange) {
+        self.slots.extend(range.storage);
+    }
+
+    /// Sets a slot, such as one read with `eth_getStorageAt`.
+    pub fn insert(&mut self, slot: [u8; 32], value: [u8; 32]) {
+        let entry = StorageEntry {
+            key: Some(slot),
+            value,
+        };
+        self.slots.insert(keccak256(&slot), entry);
+    }
+
+    /// What `slot` holds.
+    pub fn get(&self, slot: &[u8; 32]) -> [u8; 32] {
+        self.slots
+            .get(&keccak256(slot))
+            .map(|entry| entry.value)
+            .unwrap_or_default()
+    }
+
+    /// The slots by the hash of their key.
+    pub fn entries(&self) -> &BTreeMap<[u8; 32], StorageEntry> {
+        &self.slots
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+}
+
+impl From<Vec<StorageRange>> for StorageDump {
+    fn from(ranges: Vec<StorageRange>) -> StorageDump {
+        let mut dump = StorageDump::new();
+        for range in ranges {
+            dump.extend(range);
+        }
+        dump
+    }
+}
+
+/// A variable whose value changed, as told by a
+/// [SlotDecoder](trait.SlotDecoder.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedChange {
+    /// The name of the variable, such as `owner` or `balances[0x11..]`
+    pub name: String,
+    pub before: String,
+    pub after: String,
+}
+
+/// Knowledge of what the slots of a contract hold.
+pub trait SlotDecoder {
+    /// The variables of `slot` that differ between `before` and `after`,
+    /// none if the slot is unknown.
+    fn decode_change(
+        &self,
+        slot: &[u8; 32],
+        before: &[u8; 32],
+        after: &[u8; 32],
+    ) -> Vec<DecodedChange>;
+}
+
+/// A slot whose value differs between two dumps.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotChange {
+    /// The hash of the slot, which dumps are keyed by
+    pub hashed_key: [u8; 32],
+    /// The slot, when either dump knows it
+    pub slot: Option<[u8; 32]>,
+    pub before: [u8; 32],
+    pub after: [u8; 32],
+    /// The variables that changed, when the slot could be decoded
+    pub decoded: Vec<DecodedChange>,
+}
+
+impl fmt::Display for SlotChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.slot {
+            Some(ref slot) => write!(f, "slot 0x{}", bytes_to_hex_str(slot))?,
+            None => write!(f, "slot with hash 0x{}", bytes_to_hex_str(&self.hashed_key))?,
+        }
+        write!(
+            f,
+            ": 0x{} -> 0x{}",
+            bytes_to_hex_str(&self.before),
+            bytes_to_hex_str(&self.after)
+        )?;
+        for change in &self.decoded {
+            write!(
+                f,
+                "\n  {}: {} -> {}",
+                change.name, change.before, change.after
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The slots that differ between `before` and `after`, ordered by the hash
+/// of their key.
+pub fn diff(before: &StorageDump, after: &StorageDump) -> Vec<SlotChange> {
+    let mut changes = Vec::new();
+    let hashed_keys = before.slots.keys().chain(after.slots.keys());
+    let mut hashed_keys: Vec<&[u8; 32]> = hashed_keys.collect();
+    hashed_keys.sort();
+    hashed_keys.dedup();
+    for hashed_key in hashed_keys {
+        let old = before.slots.get(hashed_key);
+        let new = after.slots.get(hashed_key);
+        let value = |entry: Option<&StorageEntry>| entry.map(|e| e.value).unwrap_or_default();
+        if value(old) == value(new) {
+            continue;
+        }
+        changes.push(SlotChange {
+            hashed_key: *hashed_key,
+            slot: old.and_then(|e| e.key).or_else(|| new.and_then(|e| e.key)),
+            before: value(old),
+            after: value(new),
+            decoded: Vec::new(),
+        });
+    }
+    changes
+}
+
+/// The slots that differ like [diff](fn.diff.html), with the variables in
+/// them that changed according to `decoder`. Slots whose key neither dump
+/// knows can not be decoded.
+pub fn diff_decoded(
+    before: &StorageDump,
+    after: &StorageDump,
+    decoder: &dyn SlotDecoder,
+) -> Vec<SlotChange> {
+    let mut changes = diff(before, after);
+    for change in &mut changes {
+        if let Some(ref slot) = change.slot {
+            change.decoded = decoder.decode_change(slot, &change.before, &change.after);
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+fn word(value: u8) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[31] = value;
+    word
+}
+
+#[test]
+fn storage_range_json() {
+    let json = r#"{
+        "storage": {
+            "0x290decd9548b62a8d60345a988386fc84ba6bc95484008f6362f93160ef3e563": {
+                "key": "0x0000000000000000000000000000000000000000000000000000000000000000",
+                "value": "0x000000000000000000000000000000000000000000000000000000000000002a"
+            },
+            "0xb10e2d527612073b26eecdfd717e6a320cf44b4afac2b0732d9fcbe2b7fa0cf6": {
+                "key": null,
+                "value": "0x0000000000000000000000000000000000000000000000000000000000000007"
+            }
+        },
+        "nextKey": "0xc2575a0e9e593c00f959f8c92f12db2869c3395a3b0502d05e2516446f71f85b"
+    }"#;
+    let range: StorageRange = serde_json::from_str(json).unwrap();
+    assert_eq!(range.storage.len(), 2);
+    assert_eq!(
+        range.storage[&keccak256(&word(0))],
+        StorageEntry {
+            key: Some(word(0)),
+            value: word(42),
+        }
+    );
+    assert_eq!(range.storage[&keccak256(&word(1))].key, None);
+    assert!(range.next_key.is_some());
+    let dump: StorageDump = vec![range].into();
+    assert_eq!(dump.get(&word(0)), word(42));
+    assert_eq!(dump.get(&word(1)), word(7));
+    assert_eq!(dump.get(&word(2)), [0u8; 32]);
+
+    let bad = json.replace(
+        "0x000000000000000000000000000000000000000000000000000000000000002a",
+        "0x2a",
+    );
+    assert!(serde_json::from_str::<StorageRange>(&bad).is_err());
+}
+
+#[test]
+fn diff_with_decoder() {
+    struct Owner;
+
+    impl SlotDecoder for Owner {
+        fn decode_change(
+            &self,
+            slot: &[u8; 32],
+            before: &[u8; 32],
+            after: &[u8; 32],
+        ) -> Vec<DecodedChange> {
+            if *slot != word(0) {
+                return Vec::new();
+            }
+            vec![DecodedChange {
+                name: "owner".to_string(),
+                before: format!("0x{}", bytes_to_hex_str(&before[12..])),
+                after: format!("0x{}", bytes_to_hex_str(&after[12..])),
+            }]
+        }
+    }
+
+    let mut before = StorageDump::new();
+    before.insert(word(0), word(0x11));
+    before.insert(word(1), word(5));
+    before.insert(word(2), word(9));
+    let mut after = before.clone();
+    after.insert(word(0), word(0x22));
+    after.insert(word(3), word(1));
+    // Emptied slots are left out of dumps
+    after.slots.remove(&keccak256(&word(2)));
+
+    let changes = diff_decoded(&before, &after, &Owner);
+    assert_eq!(changes.len(), 3);
+    let owner = changes.iter().find(|c| c.slot == Some(word(0))).unwrap();
+    assert_eq!(owner.decoded.len(), 1);
+    assert_eq!(owner.decoded[0].name, "owner");
+    let owner_after = format!("0x{}", bytes_to_hex_str(&word(0x22)[12..]));
+    assert_eq!(owner.decoded[0].after, owner_after);
+    assert!(owner.to_string().ends_with(&format!(
+        "owner: {} -> {}",
+        owner.decoded[0].before, owner_after
+    )));
+    let emptied = changes.iter().find(|c| c.slot == Some(word(2))).unwrap();
+    assert_eq!((emptied.before, emptied.after), (word(9), [0u8; 32]));
+    assert!(emptied.decoded.is_empty());
+    let added = changes.iter().find(|c| c.slot == Some(word(3))).unwrap();
+    assert_eq!((added.before, added.after), ([0u8; 32], word(1)));
+    assert!(diff(&after, &after).is_empty());
+}
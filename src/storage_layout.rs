@@ -0,0 +1,667 @@
+//! The storage layout of a Solidity contract, as output by solc.
+//!
+//! With `outputSelection` asking for `storageLayout`, solc tells where each
+//! state variable of a contract is stored: its slot, its offset within the
+//! slot, and its type. A [StorageLayout](struct.StorageLayout.html) reads
+//! that output and turns raw slots, such as those of a storage proof or a
+//! [storage dump](../storage_diff/index.html), back into named variables:
+//!
+//! ```rust
+//! # extern crate clarity;
+//! # extern crate serde_json;
+//! use clarity::storage_layout::StorageLayout;
+//!
+//! # fn main() {
+//! let layout: StorageLayout = serde_json::from_str(r#"{
+//!     "storage": [
+//!         {"astId": 3, "contract": "Vault.sol:Vault", "label": "owner",
+//!          "offset": 0, "slot": "0", "type": "t_address"},
+//!         {"astId": 5, "contract": "Vault.sol:Vault", "label": "paused",
+//!          "offset": 20, "slot": "0", "type": "t_bool"}
+//!     ],
+//!     "types": {
+//!         "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+//!         "t_bool": {"encoding": "inplace", "label": "bool", "numberOfBytes": "1"}
+//!     }
+//! }"#).unwrap();
+//!
+//! let mut value = [0u8; 32];
+//! value[11] = 1;
+//! value[12..].copy_from_slice(&[0x11; 20]);
+//! let decoded = layout.decoder().unwrap().decode_slot(&[0u8; 32], &value);
+//! assert_eq!(decoded[0].name, "owner");
+//! assert_eq!(decoded[1].value, "true");
+//! # }
+//! ```
+//!
+//! Entries of mappings live at slots hashed from their keys, which a
+//! layout alone can not know. They are decoded once their keys are given
+//! to the [LayoutDecoder](struct.LayoutDecoder.html), see
+//! [add_mapping_keys](struct.LayoutDecoder.html#method.add_mapping_keys).
+use abi::{encode_tokens, Token};
+use address::Address;
+use failure::Error;
+use hash::keccak256;
+use num256::Uint256;
+use num_bigint::{BigInt, BigUint, Sign};
+use serde_crate::{de, Deserialize, Deserializer};
+use std::collections::BTreeMap;
+use storage_diff::{DecodedChange, SlotDecoder};
+use utils::{bytes_to_hex_str, uint256_be_bytes};
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum StorageLayoutError {
+    #[fail(display = "Unknown type {}", _0)]
+    UnknownType(String),
+    #[fail(display = "Unknown variable {}", _0)]
+    UnknownVariable(String),
+    #[fail(display = "{} is not a mapping", _0)]
+    NotAMapping(String),
+    #[fail(display = "Type {} has an invalid layout", _0)]
+    InvalidType(String),
+    #[fail(display = "Types nest too deeply")]
+    TooDeep,
+}
+
+/// Types can contain other types this deep, far more than any contract
+/// declares. Solc would not output a type that contains itself, but a
+/// hand written layout could.
+const MAX_DEPTH: usize = 32;
+
+/// A state variable, or a member of a struct.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct StorageVariable {
+    pub label: String,
+    /// The first byte of the variable within its slot, counted from the
+    /// lowest order byte
+    pub offset: usize,
+    /// The slot, relative to the start of the struct for members
+    #[serde(deserialize_with = "decimal_slot")]
+    pub slot: [u8; 32],
+    /// The key of the type in `types`
+    #[serde(rename = "type")]
+    pub type_id: String,
+}
+
+/// How values of a type are stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    /// In the slots of the variable itself: value types, structs and
+    /// fixed size arrays
+    Inplace,
+    /// Entries at slots hashed from their key and the slot of the mapping
+    Mapping,
+    /// The length in the slot of the variable, the items from the hash of
+    /// that slot on
+    DynamicArray,
+    /// `bytes` and `string`: in the slot of the variable when shorter than
+    /// 32 bytes, from the hash of that slot on otherwise
+    Bytes,
+}
+
+/// A type of a storage layout.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageType {
+    pub encoding: Encoding,
+    /// The Solidity type, such as `mapping(address => uint256)`
+    pub label: String,
+    #[serde(deserialize_with = "decimal_usize")]
+    pub number_of_bytes: usize,
+    /// The key type of a mapping
+    #[serde(default)]
+    pub key: Option<String>,
+    /// The value type of a mapping
+    #[serde(default)]
+    pub value: Option<String>,
+    /// The item type of an array
+    #[serde(default)]
+    pub base: Option<String>,
+    /// The members of a struct
+    #[serde(default)]
+    pub members: Option<Vec<StorageVariable>>,
+}
+
+/// The `storageLayout` output of solc for one contract.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct StorageLayout {
+    pub storage: Vec<StorageVariable>,
+    /// The types by their id, `null` in the output of a contract without
+    /// state variables
+    #[serde(deserialize_with = "null_as_empty")]
+    pub types: BTreeMap<String, StorageType>,
+}
+
+fn decimal_slot<'de, D>(deserializer: D) -> Result<[u8; 32], D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    let slot: Uint256 = s.parse().map_err(|_| de::Error::custom("Invalid slot"))?;
+    if slot.bits() > 256 {
+        return Err(de::Error::custom("Slot does not fit in 256 bits"));
+    }
+    Ok(word(&slot))
+}
+
+fn decimal_usize<'de, D>(deserializer: D) -> Result<usize, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(de::Error::custom)
+}
+
+fn null_as_empty<'de, D>(deserializer: D) -> Result<BTreeMap<String, StorageType>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+fn word(value: &Uint256) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    uint256_be_bytes(value, &mut word);
+    word
+}
+
+/// `slot + n`, wrapping around as the EVM does.
+fn slot_add(slot: &[u8; 32], n: u64) -> [u8; 32] {
+    let mut sum = *slot;
+    let mut carry = n as u128;
+    for byte in sum.iter_mut().rev() {
+        if carry == 0 {
+            break;
+        }
+        carry += *byte as u128;
+        *byte = carry as u8;
+        carry >>= 8;
+    }
+    sum
+}
+
+/// The slot of the entry of a mapping at `slot` with the key `key`.
+///
+/// Keys of value types are hashed in their ABI encoding, padded to 32
+/// bytes, and `string` and `bytes` keys as they are.
+pub fn mapping_slot(slot: &[u8; 32], key: &Token) -> [u8; 32] {
+    let mut preimage = match *key {
+        Token::String(ref s) => s.as_bytes().to_vec(),
+        Token::UnboundedBytes(ref bytes) => bytes.clone(),
+        ref key => encode_tokens(std::slice::from_ref(key)),
+    };
+    preimage.extend_from_slice(slot);
+    keccak256(&preimage)
+}
+
+/// The slot the items of a dynamic array at `slot`, or the contents of a
+/// long `bytes` or `string`, start at.
+pub fn dynamic_data_slot(slot: &[u8; 32]) -> [u8; 32] {
+    keccak256(slot)
+}
+
+/// A value type variable at a known place, as found by flattening the
+/// types of a layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LocatedVariable {
+    /// The path to the variable, such as `config.fee`, `owners[2]` or
+    /// `balances[0x1111111111111111111111111111111111111111]`
+    pub name: String,
+    pub slot: [u8; 32],
+    pub offset: usize,
+    /// The size in bytes, within the slot
+    pub size: usize,
+    pub type_id: String,
+}
+
+/// A variable and its value, decoded from a slot.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedVariable {
+    pub name: String,
+    /// The Solidity type
+    pub type_label: String,
+    /// The value, in Solidity syntax where there is one
+    pub value: String,
+}
+
+impl StorageLayout {
+    pub fn storage_type(&self, type_id: &str) -> Result<&StorageType, Error> {
+        match self.types.get(type_id) {
+            Some(storage_type) => Ok(storage_type),
+            None => Err(StorageLayoutError::UnknownType(type_id.to_string()).into()),
+        }
+    }
+
+    pub fn variable(&self, label: &str) -> Result<&StorageVariable, Error> {
+        match self.storage.iter().find(|variable| variable.label == label) {
+            Some(variable) => Ok(variable),
+            None => Err(StorageLayoutError::UnknownVariable(label.to_string()).into()),
+        }
+    }
+
+    /// The value type variables that a variable of `type_id` at `slot` and
+    /// `offset` consists of, named from `name`. Mappings have none, as all
+    /// of their entries are elsewhere, and dynamic arrays only their
+    /// length.
+    pub fn locate(
+        &self,
+        name: &str,
+        type_id: &str,
+        slot: &[u8; 32],
+        offset: usize,
+    ) -> Result<Vec<LocatedVariable>, Error> {
+        let mut located = Vec::new();
+        self.flatten(name, type_id, slot, offset, 0, &mut located)?;
+        Ok(located)
+    }
+
+    fn flatten(
+        &self,
+        name: &str,
+        type_id: &str,
+        slot: &[u8; 32],
+        offset: usize,
+        depth: usize,
+        out: &mut Vec<LocatedVariable>,
+    ) -> Result<(), Error> {
+        if depth > MAX_DEPTH {
+            return Err(StorageLayoutError::TooDeep.into());
+        }
+        let storage_type = self.storage_type(type_id)?;
+        let invalid = || StorageLayoutError::InvalidType(type_id.to_string());
+        let leaf = |type_id: &str, size: usize| LocatedVariable {
+            name: name.to_string(),
+            slot: *slot,
+            offset,
+            size,
+            type_id: type_id.to_string(),
+        };
+        match storage_type.encoding {
+            Encoding::Mapping => {}
+            // Both take a whole slot
+            Encoding::DynamicArray | Encoding::Bytes if offset != 0 => {
+                return Err(invalid().into());
+            }
+            Encoding::DynamicArray => out.push(LocatedVariable {
+                name: format!("{}.length", name),
+                ..leaf("t_uint256", 32)
+            }),
+            Encoding::Bytes => out.push(leaf(type_id, 32)),
+            Encoding::Inplace => {
+                if let Some(ref members) = storage_type.members {
+                    for member in members {
+                        let member_slot = add_slots(slot, &member.slot).ok_or_else(invalid)?;
+                        self.flatten(
+                            &format!("{}.{}", name, member.label),
+                            &member.type_id,
+                            &member_slot,
+                            member.offset,
+                            depth + 1,
+                            out,
+                        )?;
+                    }
+                } else if let Some(ref base) = storage_type.base {
+                    let item_size = self.storage_type(base)?.number_of_bytes;
+                    let length = static_array_length(type_id).ok_or_else(invalid)?;
+                    if item_size == 0 {
+                        return Err(invalid().into());
+                    }
+                    for i in 0..length {
+                        let (item_slot, item_offset) = item_place(slot, i, item_size);
+                        self.flatten(
+                            &format!("{}[{}]", name, i),
+                            base,
+                            &item_slot,
+                            item_offset,
+                            depth + 1,
+                            out,
+                        )?;
+                    }
+                } else {
+                    if storage_type.number_of_bytes + offset > 32 {
+                        return Err(invalid().into());
+                    }
+                    out.push(leaf(type_id, storage_type.number_of_bytes));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// A decoder of the state variables, which are at slots the layout
+    /// gives, without the entries of any mapping.
+    pub fn decoder(&self) -> Result<LayoutDecoder<'_>, Error> {
+        let mut decoder = LayoutDecoder {
+            layout: self,
+            variables: BTreeMap::new(),
+        };
+        for variable in &self.storage {
+            let located = self.locate(
+                &variable.label,
+                &variable.type_id,
+                &variable.slot,
+                variable.offset,
+            )?;
+            decoder.insert(located);
+        }
+        Ok(decoder)
+    }
+
+    /// The value of a located variable, under its name, from the value of
+    /// its slot.
+    pub fn decode(&self, variable: &LocatedVariable, value: &[u8; 32]) -> DecodedVariable {
+        let type_label = match self.types.get(&variable.type_id) {
+            Some(storage_type) => storage_type.label.clone(),
+            None => variable.type_id.clone(),
+        };
+        let bytes = &value[32 - variable.offset - variable.size..32 - variable.offset];
+        let encoding = self.types.get(&variable.type_id).map(|t| t.encoding);
+        DecodedVariable {
+            name: variable.name.clone(),
+            value: decode_value(&variable.type_id, encoding, bytes),
+            type_label,
+        }
+    }
+}
+
+/// Adds a relative slot, which is small, to `slot`.
+fn add_slots(slot: &[u8; 32], relative: &[u8; 32]) -> Option<[u8; 32]> {
+    if relative[..24].iter().any(|b| *b != 0) {
+        return None;
+    }
+    let mut low = [0u8; 8];
+    low.copy_from_slice(&relative[24..]);
+    Some(slot_add(slot, u64::from_be_bytes(low)))
+}
+
+/// The length of a fixed size array from its type id, such as
+/// `t_array(t_uint256)3_storage`.
+fn static_array_length(type_id: &str) -> Option<u64> {
+    let after = &type_id[type_id.rfind(')')? + 1..];
+    after.strip_suffix("_storage")?.parse().ok()
+}
+
+/// Where item `i` of an array starting at `slot` is: items of 16 bytes or
+/// less are packed into slots, larger ones take whole slots.
+fn item_place(slot: &[u8; 32], i: u64, item_size: usize) -> ([u8; 32], usize) {
+    if item_size <= 16 {
+        let per_slot = (32 / item_size) as u64;
+        let offset = (i % per_slot) as usize * item_size;
+        (slot_add(slot, i / per_slot), offset)
+    } else {
+        let slots_per_item = item_size.div_ceil(32) as u64;
+        (slot_add(slot, i * slots_per_item), 0)
+    }
+}
+
+fn decode_value(type_id: &str, encoding: Option<Encoding>, bytes: &[u8]) -> String {
+    if encoding == Some(Encoding::Bytes) {
+        return decode_short_bytes(type_id, bytes);
+    }
+    if type_id == "t_bool" {
+        return match bytes {
+            [0] => "false".to_string(),
+            [1] => "true".to_string(),
+            _ => format!("0x{}", bytes_to_hex_str(bytes)),
+        };
+    }
+    if bytes.len() == 20 && (type_id == "t_address" || type_id.starts_with("t_contract(")) {
+        // Checked above to be 20 bytes
+        return Address::from_slice(bytes).unwrap().to_checksum_string();
+    }
+    if type_id.starts_with("t_uint") || type_id.starts_with("t_enum(") {
+        return BigUint::from_bytes_be(bytes).to_string();
+    }
+    if type_id.starts_with("t_int") {
+        let mut value = BigInt::from_bytes_be(Sign::Plus, bytes);
+        if bytes.first().is_some_and(|b| b & 0x80 != 0) {
+            value -= BigInt::from(1u8) << (8 * bytes.len());
+        }
+        return value.to_string();
+    }
+    format!("0x{}", bytes_to_hex_str(bytes))
+}
+
+/// `bytes` and `string` shorter than 32 bytes are stored with twice their
+/// length in the lowest byte, longer ones with twice their length plus one
+/// in the whole slot.
+fn decode_short_bytes(type_id: &str, slot: &[u8]) -> String {
+    let last = slot[31];
+    if last & 1 == 1 {
+        let length = (BigUint::from_bytes_be(slot) - 1u32) / 2u32;
+        return format!("({} bytes)", length);
+    }
+    let contents = &slot[..((last / 2) as usize).min(31)];
+    if type_id.starts_with("t_string") {
+        format!("{:?}", String::from_utf8_lossy(contents))
+    } else {
+        format!("0x{}", bytes_to_hex_str(contents))
+    }
+}
+
+/// The variables of a layout by their slot, including the entries of
+/// mappings whose keys were added.
+#[derive(Debug, Clone)]
+pub struct LayoutDecoder<'a> {
+    layout: &'a StorageLayout,
+    variables: BTreeMap<[u8; 32], Vec<LocatedVariable>>,
+}
+
+impl<'a> LayoutDecoder<'a> {
+    fn insert(&mut self, located: Vec<LocatedVariable>) {
+        for variable in located {
+            self.variables
+                .entry(variable.slot)
+                .or_default()
+                .push(variable);
+        }
+    }
+
+    /// Adds the entry of the mapping `variable` at `keys`, one for each
+    /// level of nested mappings, such as the owner and the spender of
+    /// `allowances`. Fewer keys than levels add nothing, as the entries of
+    /// a mapping are other mappings.
+    pub fn add_mapping_keys(&mut self, variable: &str, keys: &[Token]) -> Result<(), Error> {
+        let layout = self.layout;
+        let root = layout.variable(variable)?;
+        let mut name = root.label.clone();
+        let mut slot = root.slot;
+        let mut type_id = &root.type_id;
+        for key in keys {
+            let storage_type = layout.storage_type(type_id)?;
+            let value = match (storage_type.encoding, storage_type.value.as_ref()) {
+                (Encoding::Mapping, Some(value)) => value,
+                _ => return Err(StorageLayoutError::NotAMapping(name).into()),
+            };
+            name = format!("{}[{}]", name, key_string(key));
+            slot = mapping_slot(&slot, key);
+            type_id = value;
+        }
+        let located = layout.locate(&name, type_id, &slot, 0)?;
+        self.insert(located);
+        Ok(())
+    }
+
+    /// The variables known to be in `slot`, from lowest to highest order.
+    pub fn variables_at(&self, slot: &[u8; 32]) -> &[LocatedVariable] {
+        match self.variables.get(slot) {
+            Some(variables) => variables,
+            None => &[],
+        }
+    }
+
+    /// The variables of `slot` with their values, when it holds `value`.
+    pub fn decode_slot(&self, slot: &[u8; 32], value: &[u8; 32]) -> Vec<DecodedVariable> {
+        self.variables_at(slot)
+            .iter()
+            .map(|variable| self.layout.decode(variable, value))
+            .collect()
+    }
+}
+
+impl<'a> SlotDecoder for LayoutDecoder<'a> {
+    fn decode_change(
+        &self,
+        slot: &[u8; 32],
+        before: &[u8; 32],
+        after: &[u8; 32],
+    ) -> Vec<DecodedChange> {
+        let mut changes = Vec::new();
+        for variable in self.variables_at(slot) {
+            let old = self.layout.decode(variable, before);
+            let new = self.layout.decode(variable, after);
+            if old.value != new.value {
+                changes.push(DecodedChange {
+                    name: new.name,
+                    before: old.value,
+                    after: new.value,
+                });
+            }
+        }
+        changes
+    }
+}
+
+fn key_string(key: &Token) -> String {
+    match *key {
+        Token::Address(ref address) => address.to_checksum_string(),
+        Token::Uint(ref value) => value.to_string(),
+        Token::Int(ref value) => value.to_string(),
+        Token::Bool(value) => value.to_string(),
+        Token::String(ref s) => format!("{:?}", s),
+        Token::Bytes(ref bytes) | Token::UnboundedBytes(ref bytes) => {
+            format!("0x{}", bytes_to_hex_str(bytes))
+        }
+        ref other => format!("{:?}", other),
+    }
+}
+
+#[cfg(test)]
+const VAULT_LAYOUT: &str = r#"{
+    "storage": [
+        {"astId": 3, "contract": "Vault.sol:Vault", "label": "owner", "offset": 0, "slot": "0", "type": "t_address"},
+        {"astId": 5, "contract": "Vault.sol:Vault", "label": "paused", "offset": 20, "slot": "0", "type": "t_bool"},
+        {"astId": 7, "contract": "Vault.sol:Vault", "label": "delta", "offset": 21, "slot": "0", "type": "t_int8"},
+        {"astId": 11, "contract": "Vault.sol:Vault", "label": "balances", "offset": 0, "slot": "1", "type": "t_mapping(t_address,t_uint256)"},
+        {"astId": 17, "contract": "Vault.sol:Vault", "label": "allowances", "offset": 0, "slot": "2", "type": "t_mapping(t_address,t_mapping(t_address,t_uint256))"},
+        {"astId": 22, "contract": "Vault.sol:Vault", "label": "config", "offset": 0, "slot": "3", "type": "t_struct(Config)20_storage"},
+        {"astId": 26, "contract": "Vault.sol:Vault", "label": "limits", "offset": 0, "slot": "5", "type": "t_array(t_uint64)3_storage"},
+        {"astId": 29, "contract": "Vault.sol:Vault", "label": "holders", "offset": 0, "slot": "6", "type": "t_array(t_address)dyn_storage"},
+        {"astId": 31, "contract": "Vault.sol:Vault", "label": "name", "offset": 0, "slot": "7", "type": "t_string_storage"}
+    ],
+    "types": {
+        "t_address": {"encoding": "inplace", "label": "address", "numberOfBytes": "20"},
+        "t_array(t_address)dyn_storage": {"base": "t_address", "encoding": "dynamic_array", "label": "address[]", "numberOfBytes": "32"},
+        "t_array(t_uint64)3_storage": {"base": "t_uint64", "encoding": "inplace", "label": "uint64[3]", "numberOfBytes": "32"},
+        "t_bool": {"encoding": "inplace", "label": "bool", "numberOfBytes": "1"},
+        "t_int8": {"encoding": "inplace", "label": "int8", "numberOfBytes": "1"},
+        "t_mapping(t_address,t_mapping(t_address,t_uint256))": {"encoding": "mapping", "key": "t_address", "label": "mapping(address => mapping(address => uint256))", "numberOfBytes": "32", "value": "t_mapping(t_address,t_uint256)"},
+        "t_mapping(t_address,t_uint256)": {"encoding": "mapping", "key": "t_address", "label": "mapping(address => uint256)", "numberOfBytes": "32", "value": "t_uint256"},
+        "t_string_storage": {"encoding": "bytes", "label": "string", "numberOfBytes": "32"},
+        "t_struct(Config)20_storage": {"encoding": "inplace", "label": "struct Vault.Config", "numberOfBytes": "64", "members": [
+            {"astId": 16, "contract": "Vault.sol:Vault", "label": "fee", "offset": 0, "slot": "0", "type": "t_uint256"},
+            {"astId": 19, "contract": "Vault.sol:Vault", "label": "treasury", "offset": 0, "slot": "1", "type": "t_address"}
+        ]},
+        "t_uint256": {"encoding": "inplace", "label": "uint256", "numberOfBytes": "32"},
+        "t_uint64": {"encoding": "inplace", "label": "uint64", "numberOfBytes": "8"}
+    }
+}"#;
+
+#[cfg(test)]
+fn slot(n: u64) -> [u8; 32] {
+    slot_add(&[0u8; 32], n)
+}
+
+#[test]
+fn locate_state_variables() {
+    let layout: StorageLayout = serde_json::from_str(VAULT_LAYOUT).unwrap();
+    let decoder = layout.decoder().unwrap();
+    let names = |n: u64| -> Vec<String> {
+        decoder
+            .variables_at(&slot(n))
+            .iter()
+            .map(|variable| variable.name.clone())
+            .collect()
+    };
+    assert_eq!(names(0), vec!["owner", "paused", "delta"]);
+    assert!(names(1).is_empty());
+    assert_eq!(names(3), vec!["config.fee"]);
+    assert_eq!(names(4), vec!["config.treasury"]);
+    assert_eq!(names(5), vec!["limits[0]", "limits[1]", "limits[2]"]);
+    assert_eq!(decoder.variables_at(&slot(5))[2].offset, 16);
+    assert_eq!(names(6), vec!["holders.length"]);
+    assert_eq!(names(7), vec!["name"]);
+
+    let mut value = [0u8; 32];
+    value[10] = 0xfe;
+    value[11] = 1;
+    value[12..].copy_from_slice(&[0x11; 20]);
+    let decoded = decoder.decode_slot(&slot(0), &value);
+    assert_eq!(
+        decoded[0].value,
+        Address::from([0x11; 20]).to_checksum_string()
+    );
+    assert_eq!(decoded[0].type_label, "address");
+    assert_eq!(decoded[1].value, "true");
+    assert_eq!(decoded[2].value, "-2");
+
+    let mut name = [0u8; 32];
+    name[..5].copy_from_slice(b"Vault");
+    name[31] = 10;
+    assert_eq!(decoder.decode_slot(&slot(7), &name)[0].value, "\"Vault\"");
+    assert_eq!(
+        decoder.decode_slot(&slot(7), &slot(2 * 100 + 1))[0].value,
+        "(100 bytes)"
+    );
+
+    let empty: StorageLayout = serde_json::from_str(r#"{"storage": [], "types": null}"#).unwrap();
+    assert!(empty.types.is_empty());
+}
+
+#[test]
+fn decode_mapping_entries() {
+    use storage_diff::{diff_decoded, StorageDump};
+    let layout: StorageLayout = serde_json::from_str(VAULT_LAYOUT).unwrap();
+    let holder = Address::from([0x22; 20]);
+    let spender = Address::from([0x33; 20]);
+    let mut decoder = layout.decoder().unwrap();
+    decoder
+        .add_mapping_keys("balances", &[holder.into()])
+        .unwrap();
+    decoder
+        .add_mapping_keys("allowances", &[holder.into(), spender.into()])
+        .unwrap();
+    assert!(decoder.add_mapping_keys("owner", &[holder.into()]).is_err());
+
+    // keccak256(pad32(holder) . pad32(1))
+    let balance_slot = mapping_slot(&slot(1), &holder.into());
+    let mut preimage = encode_tokens(&[holder.into()]);
+    preimage.extend_from_slice(&slot(1));
+    assert_eq!(balance_slot, keccak256(&preimage));
+    let allowance_slot = mapping_slot(&mapping_slot(&slot(2), &holder.into()), &spender.into());
+
+    let mut before = StorageDump::new();
+    before.insert(balance_slot, slot(5));
+    let mut after = before.clone();
+    after.insert(balance_slot, slot(7));
+    after.insert(allowance_slot, slot(9));
+    after.insert(slot(4), word(&Uint256::from(0x44u8)));
+    let changes = diff_decoded(&before, &after, &decoder);
+    let decoded: Vec<(String, String, String)> = changes
+        .iter()
+        .flat_map(|change| change.decoded.iter())
+        .map(|c| (c.name.clone(), c.before.clone(), c.after.clone()))
+        .collect();
+    let balance = format!("balances[{}]", holder.to_checksum_string());
+    let allowance = format!(
+        "allowances[{}][{}]",
+        holder.to_checksum_string(),
+        spender.to_checksum_string()
+    );
+    assert!(decoded.contains(&(balance, "5".to_string(), "7".to_string())));
+    assert!(decoded.contains(&(allowance, "0".to_string(), "9".to_string())));
+    assert!(decoded
+        .iter()
+        .any(|c| c.0 == "config.treasury" && c.2.to_lowercase().ends_with("44")));
+}
@@ -0,0 +1,355 @@
+//! Transaction templates with placeholders.
+//!
+//! A payout pipeline can have a template pre-approved, such as "transfer
+//! tokens from this contract to *someone*, for *some amount*", and only fill
+//! in the placeholders when it is time to sign. Filling a template checks
+//! that nothing but the placeholders was provided, and an existing
+//! transaction can be checked against a template as well.
+use abi::{
+    decode_tokens, derive_method_id, encode_call, encode_tokens, parse_param_types, ParamType,
+    Token,
+};
+use address::Address;
+use failure::Error;
+use num256::Uint256;
+use private_key::PrivateKey;
+use std::slice;
+use transaction::Transaction;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum TemplateError {
+    #[fail(display = "No value was provided for placeholder {}", _0)]
+    MissingValue(&'static str),
+    #[fail(
+        display = "A value was provided for {}, which is not a placeholder",
+        _0
+    )]
+    UnexpectedValue(&'static str),
+    #[fail(
+        display = "Expected {} placeholder arguments but {} were provided",
+        _0, _1
+    )]
+    ArgumentCount(usize, usize),
+    #[fail(display = "Argument {} is not a valid {}", _0, _1)]
+    ArgumentType(usize, ParamType),
+    #[fail(display = "Transaction {} differs from the template", _0)]
+    FieldChanged(&'static str),
+    #[fail(display = "Call argument {} differs from the template", _0)]
+    ArgumentChanged(usize),
+}
+
+/// A field of a template, either fixed in advance or filled in later.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Field<T> {
+    Fixed(T),
+    Placeholder,
+}
+
+impl<T: Clone + PartialEq> Field<T> {
+    fn fill(&self, name: &'static str, value: &Option<T>) -> Result<T, TemplateError> {
+        match (self, value) {
+            (Field::Fixed(fixed), None) => Ok(fixed.clone()),
+            (Field::Fixed(_), Some(_)) => Err(TemplateError::UnexpectedValue(name)),
+            (Field::Placeholder, Some(value)) => Ok(value.clone()),
+            (Field::Placeholder, None) => Err(TemplateError::MissingValue(name)),
+        }
+    }
+
+    fn check(&self, name: &'static str, value: &T) -> Result<(), TemplateError> {
+        match *self {
+            Field::Fixed(ref fixed) if fixed != value => Err(TemplateError::FieldChanged(name)),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Template for the `data` of a transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DataTemplate {
+    /// The data is fixed, i.e. empty for plain ether transfers
+    Fixed(Vec<u8>),
+    /// A contract call where some of the arguments are placeholders
+    Call {
+        signature: String,
+        args: Vec<Field<Token>>,
+    },
+}
+
+/// Values for the placeholders of a template.
+///
+/// Values for call arguments are given in the order the placeholders
+/// appear in the signature.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TemplateValues {
+    pub nonce: Option<Uint256>,
+    pub gas_price: Option<Uint256>,
+    pub gas_limit: Option<Uint256>,
+    pub to: Option<Address>,
+    pub value: Option<Uint256>,
+    pub args: Vec<Token>,
+}
+
+/// A transaction where selected fields and call arguments are placeholders.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxTemplate {
+    pub nonce: Field<Uint256>,
+    pub gas_price: Field<Uint256>,
+    pub gas_limit: Field<Uint256>,
+    pub to: Field<Address>,
+    pub value: Field<Uint256>,
+    pub data: DataTemplate,
+}
+
+/// Checks that `token` is a valid value of type `kind`, by making sure it
+/// survives an encoding roundtrip.
+fn token_matches(kind: &ParamType, token: &Token) -> bool {
+    let encoded = encode_tokens(slice::from_ref(token));
+    match decode_tokens(slice::from_ref(kind), &encoded) {
+        Ok(decoded) => decoded[0] == *token,
+        Err(_) => false,
+    }
+}
+
+impl TxTemplate {
+    /// Fills in the placeholders, returning an unsigned transaction.
+    ///
+    /// Every placeholder needs a value, and values can only be provided for
+    /// placeholders. Call arguments are type checked against the signature.
+    pub fn fill(&self, values: &TemplateValues) -> Result<Transaction, Error> {
+        Ok(Transaction {
+            nonce: self.nonce.fill("nonce", &values.nonce)?,
+            gas_price: self.gas_price.fill("gas_price", &values.gas_price)?,
+            gas_limit: self.gas_limit.fill("gas_limit", &values.gas_limit)?,
+            to: self.to.fill("to", &values.to)?,
+            value: self.value.fill("value", &values.value)?,
+            data: self.fill_data(&values.args)?,
+            signature: None,
+        })
+    }
+
+    /// Fills in the placeholders and signs the result.
+    pub fn sign(
+        &self,
+        values: &TemplateValues,
+        key: &PrivateKey,
+        network_id: Option<u64>,
+    ) -> Result<Transaction, Error> {
+        Ok(self.fill(values)?.sign(key, network_id))
+    }
+
+    fn fill_data(&self, values: &[Token]) -> Result<Vec<u8>, Error> {
+        let (signature, args) = match self.data {
+            DataTemplate::Fixed(ref data) => {
+                if !values.is_empty() {
+                    return Err(TemplateError::UnexpectedValue("data").into());
+                }
+                return Ok(data.clone());
+            }
+            DataTemplate::Call {
+                ref signature,
+                ref args,
+            } => (signature, args),
+        };
+        let types = parse_param_types(signature)?;
+        ensure!(
+            types.len() == args.len(),
+            "Template has {} arguments but {} takes {}",
+            args.len(),
+            signature,
+            types.len()
+        );
+        let placeholders = args
+            .iter()
+            .filter(|arg| **arg == Field::Placeholder)
+            .count();
+        if placeholders != values.len() {
+            return Err(TemplateError::ArgumentCount(placeholders, values.len()).into());
+        }
+        let mut values = values.iter();
+        let mut tokens = Vec::with_capacity(args.len());
+        for (index, (arg, kind)) in args.iter().zip(types.iter()).enumerate() {
+            let token = match *arg {
+                Field::Fixed(ref token) => token.clone(),
+                Field::Placeholder => values.next().unwrap().clone(),
+            };
+            if !token_matches(kind, &token) {
+                return Err(TemplateError::ArgumentType(index, kind.clone()).into());
+            }
+            tokens.push(token);
+        }
+        Ok(encode_call(signature, &tokens))
+    }
+
+    /// Checks that a transaction could have been produced by this template,
+    /// which means it only differs from it in placeholders.
+    pub fn check(&self, tx: &Transaction) -> Result<(), Error> {
+        self.nonce.check("nonce", &tx.nonce)?;
+        self.gas_price.check("gas_price", &tx.gas_price)?;
+        self.gas_limit.check("gas_limit", &tx.gas_limit)?;
+        self.to.check("to", &tx.to)?;
+        self.value.check("value", &tx.value)?;
+        match self.data {
+            DataTemplate::Fixed(ref data) => {
+                if *data != tx.data {
+                    return Err(TemplateError::FieldChanged("data").into());
+                }
+            }
+            DataTemplate::Call {
+                ref signature,
+                ref args,
+            } => {
+                if tx.data.len() < 4 || tx.data[..4] != derive_method_id(signature) {
+                    return Err(TemplateError::FieldChanged("data").into());
+                }
+                let types = parse_param_types(signature)?;
+                let tokens = decode_tokens(&types, &tx.data[4..])?;
+                // Anything after the arguments would not be covered by the checks
+                if encode_call(signature, &tokens) != tx.data {
+                    return Err(TemplateError::FieldChanged("data").into());
+                }
+                for (index, (arg, token)) in args.iter().zip(tokens.iter()).enumerate() {
+                    if let Field::Fixed(ref fixed) = *arg {
+                        if fixed != token {
+                            return Err(TemplateError::ArgumentChanged(index).into());
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+fn payout_template() -> TxTemplate {
+    TxTemplate {
+        nonce: Field::Placeholder,
+        gas_price: Field::Fixed(1_000_000_000u64.into()),
+        gas_limit: Field::Fixed(60_000u32.into()),
+        to: Field::Fixed([0x11u8; 20].into()),
+        value: Field::Fixed(0u32.into()),
+        data: DataTemplate::Call {
+            signature: "transfer(address,uint256)".to_string(),
+            args: vec![Field::Placeholder, Field::Placeholder],
+        },
+    }
+}
+
+#[cfg(test)]
+fn payout_values() -> TemplateValues {
+    TemplateValues {
+        nonce: Some(5u32.into()),
+        args: vec![Token::Address([0x22u8; 20].into()), 1000u32.into()],
+        ..Default::default()
+    }
+}
+
+#[test]
+fn fill_template() {
+    let template = payout_template();
+    let tx = template.fill(&payout_values()).unwrap();
+    assert_eq!(tx.nonce, 5u32.into());
+    assert_eq!(tx.to, [0x11u8; 20].into());
+    assert_eq!(
+        tx.data,
+        encode_call(
+            "transfer(address,uint256)",
+            &[Token::Address([0x22u8; 20].into()), 1000u32.into()]
+        )
+    );
+    assert!(template.check(&tx).is_ok());
+
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let signed = template.sign(&payout_values(), &key, Some(1)).unwrap();
+    assert_eq!(signed.sender().unwrap(), key.to_public_key().unwrap());
+    assert!(template.check(&signed).is_ok());
+}
+
+#[test]
+fn fill_rejects_wrong_values() {
+    let template = payout_template();
+    let error = |values: TemplateValues| {
+        template
+            .fill(&values)
+            .unwrap_err()
+            .downcast::<TemplateError>()
+            .unwrap()
+    };
+    assert_eq!(
+        error(TemplateValues {
+            nonce: None,
+            ..payout_values()
+        }),
+        TemplateError::MissingValue("nonce")
+    );
+    assert_eq!(
+        error(TemplateValues {
+            to: Some(Address::default()),
+            ..payout_values()
+        }),
+        TemplateError::UnexpectedValue("to")
+    );
+    assert_eq!(
+        error(TemplateValues {
+            args: vec![Token::Address([0x22u8; 20].into())],
+            ..payout_values()
+        }),
+        TemplateError::ArgumentCount(2, 1)
+    );
+    assert_eq!(
+        error(TemplateValues {
+            args: vec![1000u32.into(), Token::Address([0x22u8; 20].into())],
+            ..payout_values()
+        }),
+        TemplateError::ArgumentType(0, ParamType::Address)
+    );
+}
+
+#[test]
+fn check_detects_changes() {
+    let template = payout_template();
+    let tx = template.fill(&payout_values()).unwrap();
+
+    let mut changed = tx.clone();
+    changed.gas_price = 2_000_000_000u64.into();
+    assert_eq!(
+        template
+            .check(&changed)
+            .unwrap_err()
+            .downcast::<TemplateError>()
+            .unwrap(),
+        TemplateError::FieldChanged("gas_price")
+    );
+
+    let mut trailing = tx.clone();
+    trailing.data.push(0);
+    assert!(template.check(&trailing).is_err());
+
+    let mut other_call = tx.clone();
+    other_call.data = encode_call(
+        "approve(address,uint256)",
+        &[Token::Address([0x22u8; 20].into()), 1000u32.into()],
+    );
+    assert!(template.check(&other_call).is_err());
+
+    let fixed_recipient = TxTemplate {
+        data: DataTemplate::Call {
+            signature: "transfer(address,uint256)".to_string(),
+            args: vec![
+                Field::Fixed(Token::Address([0x33u8; 20].into())),
+                Field::Placeholder,
+            ],
+        },
+        ..payout_template()
+    };
+    assert_eq!(
+        fixed_recipient
+            .check(&tx)
+            .unwrap_err()
+            .downcast::<TemplateError>()
+            .unwrap(),
+        TemplateError::ArgumentChanged(0)
+    );
+}
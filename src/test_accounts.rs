@@ -0,0 +1,93 @@
+//! The deterministic accounts of local development nodes.
+//!
+//! **These keys are public. Never use them outside of tests:** anything sent
+//! to their addresses on a live network is taken within seconds.
+//!
+//! Hardhat funds twenty accounts by default, Anvil the first ten of them,
+//! and `ganache --deterministic` funds ten of its own. They are only available with the
+//! `test-keys` feature, and
+//! [PrivateKey::from_slice](../private_key/struct.PrivateKey.html#method.from_slice)
+//! rejects them, so they are built with
+//! [from_slice_allow_test_keys](../private_key/struct.PrivateKey.html#method.from_slice_allow_test_keys).
+use address::Address;
+use private_key::{PrivateKey, GANACHE_KEYS, HARDHAT_KEYS};
+use utils::hex_str_to_bytes;
+
+/// An insecure, publicly known account.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TestAccount {
+    pub private_key: PrivateKey,
+    pub address: Address,
+}
+
+fn accounts(keys: &[&str]) -> Vec<TestAccount> {
+    keys.iter()
+        .map(|key| {
+            let private_key =
+                PrivateKey::from_slice_allow_test_keys(&hex_str_to_bytes(key).unwrap()).unwrap();
+            TestAccount {
+                private_key,
+                address: private_key.to_public_key().unwrap(),
+            }
+        })
+        .collect()
+}
+
+/// The twenty accounts Hardhat funds by default, in order.
+pub fn hardhat_accounts() -> Vec<TestAccount> {
+    accounts(&HARDHAT_KEYS)
+}
+
+/// The ten accounts Anvil funds by default, which are the first ten of
+/// [hardhat_accounts](fn.hardhat_accounts.html).
+pub fn anvil_accounts() -> Vec<TestAccount> {
+    accounts(&HARDHAT_KEYS[..10])
+}
+
+/// The ten accounts of `ganache --deterministic`, in order.
+pub fn ganache_accounts() -> Vec<TestAccount> {
+    accounts(&GANACHE_KEYS)
+}
+
+#[test]
+fn known_addresses() {
+    let hardhat = hardhat_accounts();
+    assert_eq!(hardhat.len(), 20);
+    assert_eq!(anvil_accounts()[..], hardhat[..10]);
+    assert_eq!(
+        hardhat[0].address,
+        "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266"
+            .parse()
+            .unwrap()
+    );
+    assert_eq!(
+        hardhat[9].address,
+        "0xa0Ee7A142d267C1f36714E4a8F75612F20a79720"
+            .parse()
+            .unwrap()
+    );
+    assert_eq!(
+        hardhat[19].address,
+        "0x8626f6940E2eb28930eFb4CeF49B2d1F2C9C1199"
+            .parse()
+            .unwrap()
+    );
+    let ganache = ganache_accounts();
+    assert_eq!(ganache.len(), 10);
+    assert_eq!(
+        ganache[9].address,
+        "0x1dF62f291b2E969fB0849d99D9Ce41e2F137006e"
+            .parse()
+            .unwrap()
+    );
+    assert_eq!(
+        ganache[0].address,
+        "0x90F8bf6A479f320ead074411a4B0e7944Ea8c9C1"
+            .parse()
+            .unwrap()
+    );
+    assert!(hardhat
+        .iter()
+        .chain(ganache.iter())
+        .all(|account| account.private_key.is_weak()));
+}
@@ -0,0 +1,391 @@
+//! Signed transactions held back until a window of time.
+//!
+//! Transactions are often prepared and signed well before they are meant to
+//! be broadcast, by someone other than whoever releases them: a payout
+//! signed on an offline machine on Friday for Monday, or a rescue
+//! transaction that is only good for a day. A
+//! [TimeLockedTransaction](struct.TimeLockedTransaction.html) wraps such a
+//! signed transaction with the window it may be broadcast in, and is itself
+//! signed by whoever prepared it, so that the window can not be changed on
+//! the way:
+//!
+//! ```rust
+//! use clarity::timelock::TimeLockedTransaction;
+//! use clarity::{PrivateKey, Transaction};
+//!
+//! let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+//!     .parse()
+//!     .unwrap();
+//! let tx = Transaction {
+//!     nonce: 0u32.into(),
+//!     gas_price: 1_000_000_000u64.into(),
+//!     gas_limit: 21_000u32.into(),
+//!     to: "0x3535353535353535353535353535353535353535".parse().unwrap(),
+//!     value: 1u32.into(),
+//!     data: Vec::new(),
+//!     signature: None,
+//! }
+//! .sign(&key, Some(1));
+//!
+//! let locked = TimeLockedTransaction::new(tx.into(), Some(1_700_000_000), None)
+//!     .unwrap()
+//!     .sign(&key);
+//! let bytes = locked.to_bytes();
+//!
+//! // Later, before broadcasting
+//! let locked = TimeLockedTransaction::from_bytes(&bytes).unwrap();
+//! let signer = key.to_public_key().unwrap();
+//! assert!(locked.verify(&signer, 1_600_000_000).is_err());
+//! locked.verify(&signer, 1_800_000_000).unwrap();
+//! let raw = locked.transaction.to_bytes().unwrap();
+//! ```
+//!
+//! Nothing of this is enforced on chain: a transaction that leaves the
+//! envelope early can be included whenever it is valid. The window is
+//! rather a check of the tools that release transactions.
+//!
+//! The envelope is encoded as the RLP list `[version, not_before,
+//! not_after, transaction, signature]`, where each bound is a list of zero
+//! or one timestamps, the transaction is its raw encoding and the
+//! signature its 65 bytes, or empty when unsigned. The signature is over
+//! the Keccak-256 hash of the RLP list `["clarity-timelock", version,
+//! not_before, not_after, transaction]`, which can not be mistaken for a
+//! transaction or a message.
+use address::Address;
+use failure::Error;
+use hash::keccak256;
+use private_key::PrivateKey;
+use rlp::{decode, encode, RlpDecodeError};
+use serde_bytes::ByteBuf;
+use signature::Signature;
+use transaction::TypedTransaction;
+
+/// The version of the encoding.
+pub const TIMELOCK_VERSION: u64 = 1;
+
+/// What the signing hash starts with.
+const DOMAIN: &str = "clarity-timelock";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum TimeLockError {
+    #[fail(display = "Unsupported time lock version {}", _0)]
+    UnsupportedVersion(u64),
+    #[fail(
+        display = "Window ends at {} before it starts at {}",
+        not_after, not_before
+    )]
+    InvalidWindow { not_before: u64, not_after: u64 },
+    #[fail(display = "Transaction is not signed")]
+    UnsignedTransaction,
+    #[fail(display = "Transaction is not canonically encoded")]
+    NonCanonicalTransaction,
+    #[fail(display = "Time lock is not signed")]
+    Unsigned,
+    #[fail(display = "Time lock is signed by {} instead of {}", _1, _0)]
+    WrongSigner(Address, Address),
+    #[fail(
+        display = "Transaction is not valid before {}, it is {}",
+        not_before, now
+    )]
+    TooEarly { not_before: u64, now: u64 },
+    #[fail(display = "Transaction expired at {}, it is {}", not_after, now)]
+    Expired { not_after: u64, now: u64 },
+}
+
+/// A signed transaction with the window it may be broadcast in.
+///
+/// The bounds are Unix timestamps in seconds, as block timestamps are, and
+/// both are inclusive. The envelope is signed separately from the
+/// transaction, usually but not necessarily by the same key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeLockedTransaction {
+    /// The first second the transaction may be broadcast at
+    pub not_before: Option<u64>,
+    /// The last second the transaction may be broadcast at
+    pub not_after: Option<u64>,
+    pub transaction: TypedTransaction,
+    /// The signature over [signing_hash](#method.signing_hash)
+    pub signature: Option<Signature>,
+}
+
+impl TimeLockedTransaction {
+    /// An unsigned envelope. Fails if the window is empty or if the
+    /// transaction is not signed, as it could not be broadcast anyway.
+    pub fn new(
+        transaction: TypedTransaction,
+        not_before: Option<u64>,
+        not_after: Option<u64>,
+    ) -> Result<TimeLockedTransaction, Error> {
+        let locked = TimeLockedTransaction {
+            not_before,
+            not_after,
+            transaction,
+            signature: None,
+        };
+        locked.check_contents()?;
+        Ok(locked)
+    }
+
+    fn check_contents(&self) -> Result<(), Error> {
+        if let (Some(not_before), Some(not_after)) = (self.not_before, self.not_after) {
+            if not_after < not_before {
+                return Err(TimeLockError::InvalidWindow {
+                    not_before,
+                    not_after,
+                }
+                .into());
+            }
+        }
+        if self.transaction.signature().is_none() {
+            return Err(TimeLockError::UnsignedTransaction.into());
+        }
+        Ok(())
+    }
+
+    fn bounds(&self) -> (Vec<u64>, Vec<u64>) {
+        (
+            self.not_before.into_iter().collect(),
+            self.not_after.into_iter().collect(),
+        )
+    }
+
+    fn transaction_bytes(&self) -> ByteBuf {
+        // Every transaction the crate decodes can be encoded again
+        ByteBuf::from(self.transaction.to_bytes().unwrap())
+    }
+
+    /// The hash the signature is over.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let (not_before, not_after) = self.bounds();
+        keccak256(&encode(&(
+            DOMAIN,
+            TIMELOCK_VERSION,
+            not_before,
+            not_after,
+            self.transaction_bytes(),
+        )))
+    }
+
+    /// Signs the envelope, replacing any previous signature.
+    pub fn sign(&self, key: &PrivateKey) -> TimeLockedTransaction {
+        TimeLockedTransaction {
+            signature: Some(key.sign_hash(&self.signing_hash())),
+            ..self.clone()
+        }
+    }
+
+    /// Recovers who signed the envelope.
+    pub fn signer(&self) -> Result<Address, Error> {
+        match self.signature {
+            Some(ref signature) => signature.recover(&self.signing_hash()),
+            None => Err(TimeLockError::Unsigned.into()),
+        }
+    }
+
+    /// Whether `now` is within the window.
+    pub fn is_open_at(&self, now: u64) -> bool {
+        self.check_time(now).is_ok()
+    }
+
+    /// Checks that `now` is within the window.
+    pub fn check_time(&self, now: u64) -> Result<(), TimeLockError> {
+        match (self.not_before, self.not_after) {
+            (Some(not_before), _) if now < not_before => {
+                Err(TimeLockError::TooEarly { not_before, now })
+            }
+            (_, Some(not_after)) if now > not_after => {
+                Err(TimeLockError::Expired { not_after, now })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks everything needed before releasing the transaction at `now`:
+    /// that the envelope is signed by `signer`, that `now` is within the
+    /// window and that the sender of the transaction can be recovered,
+    /// which is returned.
+    pub fn verify(&self, signer: &Address, now: u64) -> Result<Address, Error> {
+        let found = self.signer()?;
+        if found != *signer {
+            return Err(TimeLockError::WrongSigner(*signer, found).into());
+        }
+        self.check_time(now)?;
+        self.transaction.sender()
+    }
+
+    /// The canonical encoding of the envelope.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let (not_before, not_after) = self.bounds();
+        let signature = match self.signature {
+            Some(ref signature) => ByteBuf::from(signature.to_bytes().to_vec()),
+            None => ByteBuf::new(),
+        };
+        encode(&(
+            TIMELOCK_VERSION,
+            not_before,
+            not_after,
+            self.transaction_bytes(),
+            signature,
+        ))
+    }
+
+    /// Decodes an envelope, accepting only its canonical encoding so that
+    /// the same envelope always has the same bytes.
+    ///
+    /// The signature is not checked, see [verify](#method.verify).
+    pub fn from_bytes(bytes: &[u8]) -> Result<TimeLockedTransaction, Error> {
+        let (version, not_before, not_after, transaction, signature): (
+            u64,
+            Vec<u64>,
+            Vec<u64>,
+            ByteBuf,
+            ByteBuf,
+        ) = decode(bytes)?;
+        if version != TIMELOCK_VERSION {
+            return Err(TimeLockError::UnsupportedVersion(version).into());
+        }
+        let bound = |bound: Vec<u64>| -> Result<Option<u64>, Error> {
+            match bound[..] {
+                [] => Ok(None),
+                [timestamp] => Ok(Some(timestamp)),
+                _ => Err(RlpDecodeError::ListLength(1, bound.len()).into()),
+            }
+        };
+        let decoded = TypedTransaction::from_bytes(&transaction)?;
+        if decoded.to_bytes()?[..] != transaction[..] {
+            return Err(TimeLockError::NonCanonicalTransaction.into());
+        }
+        let locked = TimeLockedTransaction {
+            not_before: bound(not_before)?,
+            not_after: bound(not_after)?,
+            transaction: decoded,
+            signature: match signature.len() {
+                0 => None,
+                _ => Some(Signature::from_bytes(&signature)?),
+            },
+        };
+        locked.check_contents()?;
+        Ok(locked)
+    }
+}
+
+#[cfg(test)]
+fn key() -> PrivateKey {
+    "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap()
+}
+
+#[cfg(test)]
+fn payout() -> TypedTransaction {
+    use transaction::Transaction;
+    Transaction {
+        nonce: 7u32.into(),
+        gas_price: 1_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 5u32.into(),
+        data: Vec::new(),
+        signature: None,
+    }
+    .sign(&key(), Some(1))
+    .into()
+}
+
+#[test]
+fn time_locked_round_trip() {
+    let locked = TimeLockedTransaction::new(payout(), Some(1_000), Some(2_000))
+        .unwrap()
+        .sign(&key());
+    let bytes = locked.to_bytes();
+    let decoded = TimeLockedTransaction::from_bytes(&bytes).unwrap();
+    assert_eq!(decoded, locked);
+    assert_eq!(decoded.to_bytes(), bytes);
+
+    let signer = key().to_public_key().unwrap();
+    assert_eq!(decoded.verify(&signer, 1_000).unwrap(), signer);
+    assert_eq!(decoded.verify(&signer, 2_000).unwrap(), signer);
+    assert_eq!(
+        decoded.check_time(999),
+        Err(TimeLockError::TooEarly {
+            not_before: 1_000,
+            now: 999,
+        })
+    );
+    assert_eq!(
+        decoded.check_time(2_001),
+        Err(TimeLockError::Expired {
+            not_after: 2_000,
+            now: 2_001,
+        })
+    );
+    let other = Address::from([0x22u8; 20]);
+    assert_eq!(
+        decoded
+            .verify(&other, 1_500)
+            .unwrap_err()
+            .downcast::<TimeLockError>()
+            .unwrap(),
+        TimeLockError::WrongSigner(other, signer)
+    );
+
+    // Open ended and unsigned envelopes
+    let locked = TimeLockedTransaction::new(payout(), None, None).unwrap();
+    assert!(locked.is_open_at(0));
+    assert!(locked.is_open_at(u64::MAX));
+    assert_eq!(
+        TimeLockedTransaction::from_bytes(&locked.to_bytes()).unwrap(),
+        locked
+    );
+    assert!(locked.verify(&signer, 0).is_err());
+}
+
+#[test]
+fn time_lock_is_bound_to_its_window() {
+    let locked = TimeLockedTransaction::new(payout(), Some(0), None)
+        .unwrap()
+        .sign(&key());
+    // A lower bound of zero is still a bound, and signed as one
+    let widened = TimeLockedTransaction {
+        not_before: None,
+        ..locked.clone()
+    };
+    assert_ne!(widened.signing_hash(), locked.signing_hash());
+    assert_ne!(widened.signer().unwrap(), key().to_public_key().unwrap());
+    assert_ne!(widened.to_bytes(), locked.to_bytes());
+
+    assert_eq!(
+        TimeLockedTransaction::new(payout(), Some(2), Some(1))
+            .unwrap_err()
+            .downcast::<TimeLockError>()
+            .unwrap(),
+        TimeLockError::InvalidWindow {
+            not_before: 2,
+            not_after: 1,
+        }
+    );
+    let mut unsigned = payout();
+    if let TypedTransaction::Legacy(ref mut tx) = unsigned {
+        tx.signature = None;
+    }
+    assert_eq!(
+        TimeLockedTransaction::new(unsigned, None, None)
+            .unwrap_err()
+            .downcast::<TimeLockError>()
+            .unwrap(),
+        TimeLockError::UnsignedTransaction
+    );
+
+    let tx = ByteBuf::from(payout().to_bytes().unwrap());
+    let no_bound: Vec<u64> = Vec::new();
+    let bytes = encode(&(2u64, &no_bound, &no_bound, &tx, ByteBuf::new()));
+    assert_eq!(
+        TimeLockedTransaction::from_bytes(&bytes)
+            .unwrap_err()
+            .downcast::<TimeLockError>()
+            .unwrap(),
+        TimeLockError::UnsupportedVersion(2)
+    );
+    let bytes = encode(&(1u64, vec![1u64, 2], &no_bound, &tx, ByteBuf::new()));
+    assert!(TimeLockedTransaction::from_bytes(&bytes).is_err());
+}
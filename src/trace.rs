@@ -0,0 +1,394 @@
+//! Decoding call traces.
+//!
+//! `debug_traceTransaction` with the `callTracer` returns the tree of calls a
+//! transaction made, with raw inputs and outputs. A [TraceDecoder](struct.TraceDecoder.html)
+//! knows function signatures, custom errors and names for addresses, and
+//! turns such a trace into a [DecodedFrame](struct.DecodedFrame.html) tree
+//! whose `Display` form reads like a stack trace:
+//!
+//! ```text
+//! CALL Router(0x1111111111111111111111111111111111111111).swap(100) reverted: Router.Slippage(90)
+//!   STATICCALL Pool(0x2222222222222222222222222222222222222222).quote(100) returned (90)
+//! ```
+use abi::{decode_tokens, derive_method_id, parse_param_types, ParamType, Token};
+use address::Address;
+use failure::Error;
+use num256::Uint256;
+use num_traits::Zero;
+use revert::{ErrorRegistry, Revert};
+use serde::{hex_bytes, quantity};
+use std::collections::HashMap;
+use std::fmt;
+use utils::bytes_to_hex_str;
+
+/// A frame of a `callTracer` trace, as returned by the node.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct CallFrame {
+    /// `CALL`, `STATICCALL`, `DELEGATECALL`, `CREATE` and so on
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub from: Address,
+    /// Callee, or the created contract
+    #[serde(default)]
+    pub to: Option<Address>,
+    #[serde(default, with = "quantity")]
+    pub value: Uint256,
+    #[serde(with = "hex_bytes")]
+    pub input: Vec<u8>,
+    /// Return data, or revert data if the frame failed
+    #[serde(default, with = "hex_bytes")]
+    pub output: Vec<u8>,
+    /// Why the frame failed, such as `execution reverted`
+    #[serde(default)]
+    pub error: Option<String>,
+    #[serde(default)]
+    pub calls: Vec<CallFrame>,
+}
+
+/// The input of a frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodedInput {
+    /// A call of a known function
+    Function { signature: String, args: Vec<Token> },
+    /// A plain transfer, without data
+    Empty,
+    /// Data that does not match a known function, such as init code
+    Unknown(Vec<u8>),
+}
+
+/// How a frame ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    /// The frame returned, with decoded results if the function is known
+    Returned(Option<Vec<Token>>),
+    /// The frame reverted
+    Reverted(Revert),
+    /// The frame failed without revert data, e.g. out of gas; holds the
+    /// error reported by the tracer
+    Failed(String),
+}
+
+/// A frame with its input, output and addresses decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodedFrame {
+    pub kind: String,
+    pub from: Address,
+    pub from_label: Option<String>,
+    pub to: Option<Address>,
+    pub to_label: Option<String>,
+    pub value: Uint256,
+    pub input: DecodedInput,
+    pub outcome: Outcome,
+    pub calls: Vec<DecodedFrame>,
+}
+
+pub(crate) fn format_token(token: &Token) -> String {
+    match *token {
+        Token::Uint(ref value) => value.to_string(),
+        Token::Int(ref value) => value.to_string(),
+        Token::Address(ref address) => address.to_string(),
+        Token::Bool(value) => value.to_string(),
+        Token::String(ref s) => format!("{:?}", s),
+        Token::Bytes(ref bytes) | Token::UnboundedBytes(ref bytes) => {
+            format!("0x{}", bytes_to_hex_str(bytes))
+        }
+        Token::Dynamic(ref tokens) | Token::FixedArray(ref tokens) => {
+            format!("[{}]", format_tokens(tokens))
+        }
+        Token::Tuple(ref tokens) => format!("({})", format_tokens(tokens)),
+    }
+}
+
+fn format_tokens(tokens: &[Token]) -> String {
+    tokens
+        .iter()
+        .map(format_token)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+impl DecodedFrame {
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{:width$}{} ", "", self.kind, width = depth * 2)?;
+        match (self.to, &self.to_label) {
+            (Some(to), Some(label)) => write!(f, "{}({})", label, to)?,
+            (Some(to), None) => write!(f, "{}", to)?,
+            (None, _) => write!(f, "?")?,
+        }
+        match self.input {
+            DecodedInput::Function {
+                ref signature,
+                ref args,
+            } => {
+                let name = &signature[..signature.find('(').unwrap_or(signature.len())];
+                write!(f, ".{}({})", name, format_tokens(args))?
+            }
+            DecodedInput::Empty => {}
+            DecodedInput::Unknown(ref data) => write!(f, " with {} bytes of data", data.len())?,
+        }
+        if !self.value.is_zero() {
+            write!(f, " value {}", self.value)?;
+        }
+        match self.outcome {
+            Outcome::Returned(Some(ref tokens)) => {
+                write!(f, " returned ({})", format_tokens(tokens))?
+            }
+            Outcome::Returned(None) => write!(f, " returned")?,
+            Outcome::Reverted(Revert::Custom(ref error)) => {
+                let signature = &error.signature;
+                let name = &signature[..signature.find('(').unwrap_or(signature.len())];
+                write!(
+                    f,
+                    " reverted: {}.{}({})",
+                    error.contract,
+                    name,
+                    format_tokens(&error.args)
+                )?
+            }
+            Outcome::Reverted(ref revert) => write!(f, " reverted: {}", revert)?,
+            Outcome::Failed(ref error) => write!(f, " failed: {}", error)?,
+        }
+        for call in &self.calls {
+            writeln!(f)?;
+            call.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for DecodedFrame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+#[derive(Debug, Clone)]
+struct KnownFunction {
+    signature: String,
+    inputs: Vec<ParamType>,
+    outputs: Vec<ParamType>,
+}
+
+/// Decodes traces with known functions, errors and address labels.
+#[derive(Debug, Clone, Default)]
+pub struct TraceDecoder {
+    functions: HashMap<[u8; 4], Vec<KnownFunction>>,
+    labels: HashMap<Address, String>,
+    /// Custom errors used to decode reverts
+    pub errors: ErrorRegistry,
+}
+
+impl TraceDecoder {
+    pub fn new() -> TraceDecoder {
+        TraceDecoder::default()
+    }
+
+    /// Registers a function by its signature and the comma separated types
+    /// it returns, e.g. `("balanceOf(address)", "uint256")`.
+    pub fn register_function(&mut self, signature: &str, outputs: &str) -> Result<(), Error> {
+        let function = KnownFunction {
+            signature: signature.to_string(),
+            inputs: parse_param_types(signature)?,
+            outputs: parse_param_types(&format!("returns({})", outputs))?,
+        };
+        self.functions
+            .entry(derive_method_id(signature))
+            .or_default()
+            .push(function);
+        Ok(())
+    }
+
+    /// Names an address, such as the contract deployed there.
+    pub fn label(&mut self, address: Address, name: &str) {
+        self.labels.insert(address, name.to_string());
+    }
+
+    /// The name given to an address with [label](#method.label).
+    pub(crate) fn label_of(&self, address: &Address) -> Option<&str> {
+        self.labels.get(address).map(String::as_str)
+    }
+
+    /// The signature and arguments of the registered function that decodes
+    /// `input`.
+    pub(crate) fn decode_call(&self, input: &[u8]) -> Option<(&str, Vec<Token>)> {
+        self.function(input)
+            .map(|(function, args)| (function.signature.as_str(), args))
+    }
+
+    /// Finds the registered function that decodes `input`.
+    fn function(&self, input: &[u8]) -> Option<(&KnownFunction, Vec<Token>)> {
+        if input.len() < 4 {
+            return None;
+        }
+        let mut selector = [0u8; 4];
+        selector.copy_from_slice(&input[..4]);
+        self.functions.get(&selector)?.iter().find_map(|function| {
+            decode_tokens(&function.inputs, &input[4..])
+                .ok()
+                .map(|args| (function, args))
+        })
+    }
+
+    /// Decodes a frame and all the calls it made.
+    pub fn decode(&self, frame: &CallFrame) -> DecodedFrame {
+        let creates = frame.kind.starts_with("CREATE");
+        let function = if creates {
+            None
+        } else {
+            self.function(&frame.input)
+        };
+        let input = match function {
+            Some((function, ref args)) => DecodedInput::Function {
+                signature: function.signature.clone(),
+                args: args.clone(),
+            },
+            None if frame.input.is_empty() => DecodedInput::Empty,
+            None => DecodedInput::Unknown(frame.input.clone()),
+        };
+        let outcome = match frame.error {
+            Some(ref error) if frame.output.is_empty() && error != "execution reverted" => {
+                Outcome::Failed(error.clone())
+            }
+            Some(_) => Outcome::Reverted(self.errors.decode(&frame.output)),
+            None => Outcome::Returned(
+                function
+                    .and_then(|(function, _)| decode_tokens(&function.outputs, &frame.output).ok()),
+            ),
+        };
+        DecodedFrame {
+            kind: frame.kind.clone(),
+            from: frame.from,
+            from_label: self.labels.get(&frame.from).cloned(),
+            to: frame.to,
+            to_label: frame.to.and_then(|to| self.labels.get(&to).cloned()),
+            value: frame.value.clone(),
+            input,
+            outcome,
+            calls: frame.calls.iter().map(|call| self.decode(call)).collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+const TRACE: &str = r#"{
+    "type": "CALL",
+    "from": "0x3333333333333333333333333333333333333333",
+    "to": "0x1111111111111111111111111111111111111111",
+    "value": "0x0",
+    "gas": "0x1e8480",
+    "gasUsed": "0x8a2c",
+    "input": "0x94b918de0000000000000000000000000000000000000000000000000000000000000064",
+    "output": "0x5188f80d000000000000000000000000000000000000000000000000000000000000005a",
+    "error": "execution reverted",
+    "calls": [
+        {
+            "type": "STATICCALL",
+            "from": "0x1111111111111111111111111111111111111111",
+            "to": "0x2222222222222222222222222222222222222222",
+            "gas": "0x1d0000",
+            "gasUsed": "0x1000",
+            "input": "0xed1bd76c0000000000000000000000000000000000000000000000000000000000000064",
+            "output": "0x000000000000000000000000000000000000000000000000000000000000005a"
+        },
+        {
+            "type": "CALL",
+            "from": "0x1111111111111111111111111111111111111111",
+            "to": "0x4444444444444444444444444444444444444444",
+            "value": "0xde0b6b3a7640000",
+            "gas": "0x0",
+            "gasUsed": "0x0",
+            "input": "0x",
+            "error": "out of gas"
+        }
+    ]
+}"#;
+
+#[cfg(test)]
+fn test_decoder() -> TraceDecoder {
+    let mut decoder = TraceDecoder::new();
+    decoder.register_function("swap(uint256)", "").unwrap();
+    decoder
+        .register_function("quote(uint256)", "uint256")
+        .unwrap();
+    decoder
+        .errors
+        .register("Router", "Slippage(uint256)")
+        .unwrap();
+    decoder.label(
+        "0x1111111111111111111111111111111111111111"
+            .parse()
+            .unwrap(),
+        "Router",
+    );
+    decoder.label(
+        "0x2222222222222222222222222222222222222222"
+            .parse()
+            .unwrap(),
+        "Pool",
+    );
+    decoder
+}
+
+#[test]
+fn decode_call_tree() {
+    assert_eq!(derive_method_id("swap(uint256)"), [0x94, 0xb9, 0x18, 0xde]);
+    assert_eq!(derive_method_id("quote(uint256)"), [0xed, 0x1b, 0xd7, 0x6c]);
+    assert_eq!(
+        derive_method_id("Slippage(uint256)"),
+        [0x51, 0x88, 0xf8, 0x0d]
+    );
+    let frame: CallFrame = serde_json::from_str(TRACE).unwrap();
+    let decoded = test_decoder().decode(&frame);
+    assert_eq!(
+        decoded.input,
+        DecodedInput::Function {
+            signature: "swap(uint256)".to_string(),
+            args: vec![100u8.into()],
+        }
+    );
+    match decoded.outcome {
+        Outcome::Reverted(Revert::Custom(ref error)) => {
+            assert_eq!(error.contract, "Router");
+            assert_eq!(error.args, vec![90u8.into()]);
+        }
+        ref outcome => panic!("Unexpected outcome {:?}", outcome),
+    }
+    assert_eq!(
+        decoded.calls[0].outcome,
+        Outcome::Returned(Some(vec![90u8.into()]))
+    );
+    assert_eq!(decoded.calls[0].to_label, Some("Pool".to_string()));
+    assert_eq!(
+        decoded.calls[1].outcome,
+        Outcome::Failed("out of gas".to_string())
+    );
+    assert_eq!(
+        decoded.to_string(),
+        concat!(
+            "CALL Router(0x1111111111111111111111111111111111111111).swap(100) reverted: Router.Slippage(90)\n",
+            "  STATICCALL Pool(0x2222222222222222222222222222222222222222).quote(100) returned (90)\n",
+            "  CALL 0x4444444444444444444444444444444444444444 value 1000000000000000000 failed: out of gas",
+        )
+    );
+}
+
+#[test]
+fn decode_unknown_frames() {
+    let frame = CallFrame {
+        kind: "CREATE".to_string(),
+        from: Address::default(),
+        to: None,
+        value: 0u8.into(),
+        input: vec![0x60, 0x80],
+        output: Vec::new(),
+        error: None,
+        calls: Vec::new(),
+    };
+    let decoded = TraceDecoder::new().decode(&frame);
+    assert_eq!(decoded.input, DecodedInput::Unknown(vec![0x60, 0x80]));
+    assert_eq!(decoded.outcome, Outcome::Returned(None));
+    assert_eq!(
+        decoded.to_string(),
+        "CREATE ? with 2 bytes of data returned"
+    );
+}
@@ -4,30 +4,48 @@ use constants::TT256;
 use context::SECP256K1;
 use error::ClarityError;
 use failure::Error;
+use hash::keccak256;
+use instrument::{outcome, Span};
 use num256::Uint256;
 use num_traits::ToPrimitive;
 use num_traits::Zero;
+use num_traits::{CheckedAdd, CheckedMul};
 use opcodes::GTXCOST;
 use opcodes::GTXDATANONZERO;
 use opcodes::GTXDATAZERO;
+use opcodes::{
+    GACCESSLISTADDRESS, GACCESSLISTSTORAGE, GAS_PER_BLOB, GAUTHORIZATION, GTXCREATE,
+    GTXDATANONZERO_EIP2028,
+};
 use private_key::PrivateKey;
-use rlp::AddressDef;
+use rlp::{decode_bytes_list, decode_item, AddressDef, RlpDecodeError, RlpItem};
 use secp256k1::recovery::{RecoverableSignature, RecoveryId};
 use secp256k1::Message;
-use serde::Serialize;
-use serde::Serializer;
 use serde_bytes::ByteBuf;
+use serde_crate::Serialize;
+use serde_crate::Serializer;
 use serde_rlp::ser::to_bytes;
-use sha3::{Digest, Keccak256};
+use sha2::Sha256;
 use signature::Signature;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
 use std::fmt;
 use std::fmt::Display;
-use types::BigEndianInt;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, OnceLock};
+use types::{fixed_bytes, BigEndianInt, Gas, Nonce, StorageKey};
 use utils::bytes_to_hex_str;
 use utils::zpad;
 
 /// Transaction as explained in the Ethereum Yellow paper section 4.2
-#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+///
+/// Two transactions are equal when their RLP encodings are equal, and they
+/// hash by their transaction hash, so they can be used as keys in maps and
+/// sets directly. They have no `Ord`, since the order they are included in
+/// a block takes an expensive recovery of the sender; sort them by
+/// [inclusion_key](#method.inclusion_key), or keep them as
+/// [SignedTx](struct.SignedTx.html), which orders by the cached sender.
+#[derive(Clone, Debug)]
 pub struct Transaction {
     pub nonce: Uint256,
     pub gas_price: Uint256,
@@ -107,22 +125,234 @@ impl Serialize for Transaction {
     }
 }
 
+impl PartialEq for Transaction {
+    fn eq(&self, other: &Transaction) -> bool {
+        // A missing signature encodes the same way as a zeroed one, so the
+        // encoding is compared instead of the fields.
+        self.to_bytes().ok() == other.to_bytes().ok()
+    }
+}
+
+impl Eq for Transaction {}
+
+impl Hash for Transaction {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Transaction::hash(self).hash(state)
+    }
+}
+
+/// Largest gas limit a transaction can have (EIP-1985)
+const MAX_GAS_LIMIT: u64 = (1 << 63) - 1;
+
+/// A problem found by [validity_report](struct.Transaction.html#method.validity_report).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidityIssue {
+    /// A field does not fit in 256 bits
+    FieldOverflow(&'static str),
+    /// The nonce is 2^64 - 1 or more, which no account can reach (EIP-2681)
+    NonceTooLarge,
+    /// The gas limit does not cover the intrinsic gas
+    IntrinsicGas { required: Uint256 },
+    /// The gas limit is above 2^63 - 1 (EIP-1985)
+    GasLimitTooLarge,
+    /// The maximum cost, `gas_price * gas_limit + value`, does not fit in 256
+    /// bits, so no balance can pay for it
+    CostOverflow,
+    /// `r` or `s` is out of range
+    InvalidSignature,
+    /// `s` is in the upper half of the curve order, which is not accepted
+    /// since Homestead (EIP-2)
+    HighS,
+    /// `v` is neither 27, 28 nor a valid EIP-155 value
+    InvalidV,
+    /// The signature does not commit to a network id (pre EIP-155)
+    MissingNetworkId,
+    /// The signature is for another network
+    NetworkIdMismatch { expected: u64, found: Uint256 },
+    /// The priority fee is above the maximum fee per gas (EIP-1559)
+    PriorityFeeAboveMaxFee,
+    /// A blob transaction has no recipient, which it needs (EIP-4844)
+    BlobContractCreation,
+    /// A blob transaction carries no blobs (EIP-4844)
+    NoBlobs,
+    /// A blob versioned hash is not of the KZG version (EIP-4844)
+    BlobHashVersion,
+    /// A set code transaction has no recipient, which it needs (EIP-7702)
+    SetCodeContractCreation,
+    /// A set code transaction has no authorizations (EIP-7702)
+    NoAuthorizations,
+}
+
+impl ValidityIssue {
+    /// Whether the transaction can not be executed at all with this issue,
+    /// as opposed to breaking a rule that depends on the network.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            *self,
+            ValidityIssue::FieldOverflow(_) | ValidityIssue::IntrinsicGas { .. }
+        )
+    }
+}
+
+impl Display for ValidityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ValidityIssue::FieldOverflow(field) => write!(f, "{} does not fit in 256 bits", field),
+            ValidityIssue::NonceTooLarge => write!(f, "Nonce is 2^64 - 1 or more"),
+            ValidityIssue::IntrinsicGas { ref required } => {
+                write!(f, "Gas limit is below the intrinsic gas of {}", required)
+            }
+            ValidityIssue::GasLimitTooLarge => write!(f, "Gas limit is above 2^63 - 1"),
+            ValidityIssue::CostOverflow => write!(f, "Maximum cost does not fit in 256 bits"),
+            ValidityIssue::InvalidSignature => write!(f, "Signature values are out of range"),
+            ValidityIssue::HighS => write!(f, "Signature S value is not canonical"),
+            ValidityIssue::InvalidV => write!(f, "Signature V value is invalid"),
+            ValidityIssue::MissingNetworkId => write!(f, "Signature has no network id"),
+            ValidityIssue::NetworkIdMismatch {
+                expected,
+                ref found,
+            } => write!(
+                f,
+                "Signature is for network {} instead of {}",
+                found, expected
+            ),
+            ValidityIssue::PriorityFeeAboveMaxFee => {
+                write!(f, "Priority fee is above the maximum fee per gas")
+            }
+            ValidityIssue::BlobContractCreation => {
+                write!(f, "Blob transaction can not create a contract")
+            }
+            ValidityIssue::NoBlobs => write!(f, "Blob transaction has no blobs"),
+            ValidityIssue::BlobHashVersion => {
+                write!(f, "Blob versioned hash is not of the KZG version")
+            }
+            ValidityIssue::SetCodeContractCreation => {
+                write!(f, "Set code transaction can not create a contract")
+            }
+            ValidityIssue::NoAuthorizations => {
+                write!(f, "Set code transaction has no authorizations")
+            }
+        }
+    }
+}
+
+/// Everything [validity_report](struct.Transaction.html#method.validity_report)
+/// found wrong with a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ValidityReport {
+    pub issues: Vec<ValidityIssue>,
+}
+
+impl ValidityReport {
+    /// Whether no issues were found.
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+
+    /// Whether any of the issues is [fatal](enum.ValidityIssue.html#method.is_fatal).
+    pub fn is_fatal(&self) -> bool {
+        self.issues.iter().any(ValidityIssue::is_fatal)
+    }
+}
+
+/// The rules on transaction signatures that changed over the history of a
+/// chain, for [sender_at](struct.Transaction.html#method.sender_at).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SignatureRules {
+    pub network_id: u64,
+    /// First block of Homestead, from which EIP-2 rejects S values above
+    /// half the curve order
+    pub homestead_block: u64,
+    /// First block from which EIP-155 signatures are accepted, `None` if
+    /// they never are
+    pub eip155_block: Option<u64>,
+    /// First block from which signatures without a network id are
+    /// rejected, `None` if they are always accepted
+    pub unprotected_rejected_block: Option<u64>,
+}
+
+impl SignatureRules {
+    /// The rules of Ethereum mainnet, which still accepts unprotected
+    /// signatures.
+    pub fn mainnet() -> SignatureRules {
+        SignatureRules {
+            network_id: 1,
+            homestead_block: 1_150_000,
+            eip155_block: Some(2_675_000),
+            unprotected_rejected_block: None,
+        }
+    }
+}
+
 impl Transaction {
+    /// Checks that the transaction could be executed at all, i.e. that its
+    /// fields fit in 256 bits and that it pays for its intrinsic gas.
+    ///
+    /// See [validity_report](#method.validity_report) for stricter checks.
     pub fn is_valid(&self) -> bool {
-        if self.gas_price >= *TT256
-            || self.gas_limit >= *TT256
-            || self.value >= *TT256
-            || self.nonce >= *TT256
-        {
-            // Way too high values
-            return false;
+        !self.validity_report(None).is_fatal()
+    }
+
+    /// Checks field ranges, the signature and, if `network_id` is given,
+    /// that the signature is for that network, and reports every issue
+    /// found. Unsigned transactions only get their fields checked.
+    pub fn validity_report(&self, network_id: Option<u64>) -> ValidityReport {
+        let mut issues = Vec::new();
+        let fields = [
+            ("Nonce", &self.nonce),
+            ("Gas price", &self.gas_price),
+            ("Gas limit", &self.gas_limit),
+            ("Value", &self.value),
+        ];
+        let mut overflow = false;
+        for &(name, field) in fields.iter() {
+            if *field >= *TT256 {
+                issues.push(ValidityIssue::FieldOverflow(name));
+                overflow = true;
+            }
+        }
+        if self.nonce >= Uint256::from(u64::MAX) {
+            issues.push(ValidityIssue::NonceTooLarge);
+        }
+        let required = self.intrinsic_gas_used();
+        if self.gas_limit < required {
+            issues.push(ValidityIssue::IntrinsicGas { required });
+        }
+        if self.gas_limit > MAX_GAS_LIMIT.into() {
+            issues.push(ValidityIssue::GasLimitTooLarge);
+        }
+        let cost = self
+            .gas_price
+            .checked_mul(&self.gas_limit)
+            .and_then(|fee| fee.checked_add(&self.value));
+        if !overflow && cost.is_none_or(|cost| cost >= *TT256) {
+            issues.push(ValidityIssue::CostOverflow);
         }
 
-        if self.gas_limit < self.intrinsic_gas_used() {
-            return false;
+        if let Some(ref sig) = self.signature {
+            if !sig.is_valid() {
+                issues.push(ValidityIssue::InvalidSignature);
+            } else if sig.check_low_s_homestead().is_err() {
+                issues.push(ValidityIssue::HighS);
+            }
+            let legacy = sig.v == 27u32.into() || sig.v == 28u32.into();
+            if !legacy && sig.v < 35u32.into() {
+                issues.push(ValidityIssue::InvalidV);
+            } else if let Some(expected) = network_id {
+                match sig.network_id() {
+                    None => issues.push(ValidityIssue::MissingNetworkId),
+                    Some(ref found) if *found != expected.into() => {
+                        issues.push(ValidityIssue::NetworkIdMismatch {
+                            expected,
+                            found: found.clone(),
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
         }
 
-        true
+        ValidityReport { issues }
     }
 
     pub fn intrinsic_gas_used(&self) -> Uint256 {
@@ -173,7 +403,7 @@ impl Transaction {
             None => self.to_unsigned_tx_params(),
         };
         // Prepare a raw hash of RLP encoded TX params
-        let rawhash = Keccak256::digest(&rlpdata);
+        let rawhash = keccak256(&rlpdata);
         let mut sig = key.sign_hash(&rawhash);
         if let Some(network_id) = network_id {
             // Account v for the network_id value
@@ -184,9 +414,66 @@ impl Transaction {
         tx
     }
 
+    /// An unsigned copy of the transaction with its gas price raised by
+    /// `percent`, rounded up, and by at least one wei. Nodes only replace a
+    /// pending transaction with one that pays at least 10% more.
+    ///
+    /// Returns `None` if the gas price would overflow.
+    pub fn bumped(&self, percent: u32) -> Option<Transaction> {
+        let hundred: Uint256 = 100u32.into();
+        let raised = self
+            .gas_price
+            .checked_mul(&(hundred.clone() + percent.into()))?
+            .checked_add(&99u32.into())?
+            / hundred;
+        let gas_price = if raised > self.gas_price {
+            raised
+        } else {
+            self.gas_price.checked_add(&1u32.into())?
+        };
+        Some(Transaction {
+            gas_price,
+            signature: None,
+            ..self.clone()
+        })
+    }
+
+    /// The sender of a transaction included in `block`, which additionally
+    /// checks the signature against the rules that applied at that block.
+    ///
+    /// [sender](#method.sender) accepts any signature valid under some
+    /// fork, which is what archival processing has to avoid.
+    pub fn sender_at(&self, rules: &SignatureRules, block: u64) -> Result<Address, Error> {
+        if let Some(ref sig) = self.signature {
+            if block >= rules.homestead_block {
+                sig.check_low_s_homestead()?;
+            }
+            if sig.v == 27u32.into() || sig.v == 28u32.into() {
+                if rules.unprotected_rejected_block.is_some_and(|b| block >= b) {
+                    return Err(ClarityError::UnprotectedSignature.into());
+                }
+            } else {
+                if rules.eip155_block.is_none_or(|b| block < b) {
+                    return Err(ClarityError::ReplayProtectionNotActive.into());
+                }
+                if sig.network_id() != Some(rules.network_id.into()) {
+                    return Err(ClarityError::InvalidNetworkId.into());
+                }
+            }
+        }
+        self.sender()
+    }
+
     /// Get the sender's `Address`; derived from the `signature` field, null ETH address if the
     /// field is `None`.
     pub fn sender(&self) -> Result<Address, Error> {
+        let span = Span::recover_sender();
+        let result = self.recover_sender();
+        span.finish(|| outcome(&result));
+        result
+    }
+
+    fn recover_sender(&self) -> Result<Address, Error> {
         if self.signature.is_none() {
             // Returns a "null" address
             return Ok(Address::from([0xffu8; 20]));
@@ -199,7 +486,7 @@ impl Transaction {
             let (vee, sighash) = if sig.v == 27u32.into() || sig.v == 28u32.into() {
                 // Valid V values are in {27, 28} according to Ethereum Yellow paper Appendix F (282).
                 let vee = sig.v.clone();
-                let sighash = Keccak256::digest(&self.to_unsigned_tx_params());
+                let sighash = keccak256(&self.to_unsigned_tx_params());
                 (vee, sighash)
             } else if sig.v >= 37u32.into() {
                 let network_id = sig.network_id().ok_or(ClarityError::InvalidNetworkId)?;
@@ -210,7 +497,7 @@ impl Transaction {
                 // In this case hash of the transaction is usual RLP paremeters but "VRS" params
                 // are swapped for [network_id, '', '']. See Appendix F (285)
                 let rlp_data = self.to_unsigned_tx_params_for_network(&network_id);
-                let sighash = Keccak256::digest(&rlp_data);
+                let sighash = keccak256(&rlp_data);
                 (vee, sighash)
             } else {
                 // All other V values would be errorneous for our calculations
@@ -260,157 +547,2313 @@ impl Transaction {
                 return Err(ClarityError::ZeroPrivKey.into());
             }
             // Finally an address is last 20 bytes of a hash of the public key.
-            let sender = Keccak256::digest(&pkey[1..]);
-            debug_assert_eq!(sender.len(), 32);
+            let sender = keccak256(&pkey[1..]);
             Address::from_slice(&sender[12..])
         }
     }
     /// Creates a hash of a transaction given all TX attributes
     /// including signature (VRS) whether it is present, or not.
     pub fn hash(&self) -> Vec<u8> {
-        Keccak256::digest(&to_bytes(&self).unwrap()).to_vec()
+        keccak256(&to_bytes(&self).unwrap()).to_vec()
+    }
+
+    /// The key that sorts transactions by sender and then by nonce, which is
+    /// the order they have to be included in a block. Transactions that are
+    /// unsigned, or whose sender can not be recovered, come first, and ties
+    /// are broken by transaction hash, so that only equal transactions have
+    /// equal keys.
+    ///
+    /// The sender is recovered every time, so sort with
+    /// `sort_by_cached_key` rather than comparing keys over and over.
+    pub fn inclusion_key(&self) -> (Option<Address>, Uint256, Vec<u8>) {
+        let sender = self.signature.as_ref().and_then(|_| self.sender().ok());
+        (sender, self.nonce.clone(), self.hash())
     }
     /// Creates a byte representation of this transaction
     pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
         Ok(to_bytes(&self)?)
     }
+
+    /// Decodes a transaction from its RLP encoding, such as a raw
+    /// transaction from the mempool.
+    ///
+    /// Integers have to fit 256 bits and be canonically encoded, and the
+    /// recipient has to be 20 bytes, or empty for a contract creation. A
+    /// transaction whose "vrs" params are all zero is decoded without a
+    /// signature, as that is how an unsigned transaction is encoded; the
+    /// signature is otherwise decoded as is, and checked by
+    /// [sender](#method.sender) or [validity_report](#method.validity_report).
+    pub fn from_bytes(bytes: &[u8]) -> Result<Transaction, Error> {
+        let items = decode_bytes_list(bytes)?;
+        ensure!(
+            items.len() == 9,
+            "Transaction should have 9 fields but {} were found",
+            items.len()
+        );
+        Ok(Transaction {
+            nonce: decode_uint(items[0])?,
+            gas_price: decode_uint(items[1])?,
+            gas_limit: decode_uint(items[2])?,
+            to: decode_to(items[3])?,
+            value: decode_uint(items[4])?,
+            data: items[5].to_vec(),
+            signature: decode_signature(items[6], items[7], items[8])?,
+        })
+    }
 }
 
-#[test]
-fn test_vitaliks_eip_158_vitalik_12_json() {
-    use serde_rlp::ser::to_bytes;
-    use utils::{bytes_to_hex_str, hex_str_to_bytes};
-    // https://github.com/ethereum/tests/blob/69f55e8608126e6470c2888a5b344c93c1550f40/TransactionTests/ttEip155VitaliksEip158/Vitalik_12.json
-    let tx = Transaction {
-        nonce: Uint256::from_str_radix("0e", 16).unwrap(),
-        gas_price: Uint256::from_str_radix("00", 16).unwrap(),
-        gas_limit: Uint256::from_str_radix("0493e0", 16).unwrap(),
-        to: Address::default(), // "" - zeros only
-        value: Uint256::from_str_radix("00", 16).unwrap(),
-        data: hex_str_to_bytes("60f2ff61000080610011600039610011565b6000f3").unwrap(),
-        signature: Some(Signature::new(
-            Uint256::from_str_radix("1c", 16).unwrap(),
-            Uint256::from_str_radix(
-                "a310f4d0b26207db76ba4e1e6e7cf1857ee3aa8559bcbc399a6b09bfea2d30b4",
-                16,
-            )
-            .unwrap(),
-            Uint256::from_str_radix(
-                "6dff38c645a1486651a717ddf3daccb4fd9a630871ecea0758ddfcf2774f9bc6",
-                16,
-            )
-            .unwrap(),
-        )),
-    };
-    let lhs = to_bytes(&tx).unwrap();
-    let lhs = bytes_to_hex_str(&lhs);
-    let rhs = "f8610e80830493e080809560f2ff61000080610011600039610011565b6000f31ca0a310f4d0b26207db76ba4e1e6e7cf1857ee3aa8559bcbc399a6b09bfea2d30b4a06dff38c645a1486651a717ddf3daccb4fd9a630871ecea0758ddfcf2774f9bc6".to_owned();
-    assert_eq!(lhs, rhs);
+fn decode_uint(item: &[u8]) -> Result<Uint256, Error> {
+    ensure!(item.len() <= 32, "Integer field is larger than 256 bits");
+    if item.first() == Some(&0) {
+        return Err(RlpDecodeError::NonCanonical.into());
+    }
+    Ok(Uint256::from_bytes_be(item))
+}
 
-    assert_eq!(
-        bytes_to_hex_str(&tx.sender().unwrap().as_bytes()),
-        "874b54a8bd152966d63f706bae1ffeb0411921e5"
-    );
+fn decode_to(item: &[u8]) -> Result<Address, Error> {
+    if item.is_empty() {
+        Ok(Address::default())
+    } else {
+        Address::from_slice(item)
+    }
 }
 
-#[test]
-fn test_vitaliks_eip_158_vitalik_1_json() {
-    use serde_rlp::ser::to_bytes;
-    use utils::bytes_to_hex_str;
-    // https://github.com/ethereum/tests/blob/69f55e8608126e6470c2888a5b344c93c1550f40/TransactionTests/ttEip155VitaliksEip158/Vitalik_12.json
-    let tx = Transaction {
-        nonce: Uint256::from_str_radix("00", 16).unwrap(),
-        gas_price: Uint256::from_str_radix("04a817c800", 16).unwrap(),
-        gas_limit: Uint256::from_str_radix("5208", 16).unwrap(),
-        to: "3535353535353535353535353535353535353535".parse().unwrap(),
-        value: Uint256::from_str_radix("00", 16).unwrap(),
-        data: Vec::new(),
-        signature: Some(Signature::new(
-            Uint256::from_str_radix("25", 16).unwrap(),
-            Uint256::from_str_radix(
-                "044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116d",
-                16,
-            )
-            .unwrap(),
-            Uint256::from_str_radix(
-                "044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116d",
-                16,
+/// Decodes the "vrs" params, where all zero means unsigned.
+fn decode_signature(v: &[u8], r: &[u8], s: &[u8]) -> Result<Option<Signature>, Error> {
+    let signature = Signature::new(decode_uint(v)?, decode_uint(r)?, decode_uint(s)?);
+    Ok(if signature == Signature::default() {
+        None
+    } else {
+        Some(signature)
+    })
+}
+
+/// Builds a legacy transaction, keeping the nonce and the gas limit as
+/// [Nonce](../types/struct.Nonce.html) and [Gas](../types/struct.Gas.html)
+/// until it is built.
+///
+/// The gas limit defaults to that of a plain transfer, everything else to
+/// zero or empty.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransactionBuilder {
+    nonce: Nonce,
+    gas_price: Uint256,
+    gas_limit: Gas,
+    to: Address,
+    value: Uint256,
+    data: Vec<u8>,
+}
+
+impl Default for TransactionBuilder {
+    fn default() -> TransactionBuilder {
+        TransactionBuilder {
+            nonce: Nonce::default(),
+            gas_price: Uint256::zero(),
+            gas_limit: Gas(u64::from(GTXCOST)),
+            to: Address::default(),
+            value: Uint256::zero(),
+            data: Vec::new(),
+        }
+    }
+}
+
+impl TransactionBuilder {
+    pub fn new() -> TransactionBuilder {
+        TransactionBuilder::default()
+    }
+
+    pub fn nonce(mut self, nonce: Nonce) -> TransactionBuilder {
+        self.nonce = nonce;
+        self
+    }
+
+    pub fn gas_price(mut self, gas_price: Uint256) -> TransactionBuilder {
+        self.gas_price = gas_price;
+        self
+    }
+
+    pub fn gas_limit(mut self, gas_limit: Gas) -> TransactionBuilder {
+        self.gas_limit = gas_limit;
+        self
+    }
+
+    /// The recipient, leave it unset to create a contract.
+    pub fn to(mut self, to: Address) -> TransactionBuilder {
+        self.to = to;
+        self
+    }
+
+    pub fn value(mut self, value: Uint256) -> TransactionBuilder {
+        self.value = value;
+        self
+    }
+
+    pub fn data(mut self, data: Vec<u8>) -> TransactionBuilder {
+        self.data = data;
+        self
+    }
+
+    /// The most the transaction can cost, `gas_price * gas_limit + value`.
+    pub fn max_cost(&self) -> Uint256 {
+        self.gas_limit.cost(&self.gas_price) + self.value.clone()
+    }
+
+    /// The unsigned transaction.
+    pub fn build(self) -> Transaction {
+        Transaction {
+            nonce: self.nonce.into(),
+            gas_price: self.gas_price,
+            gas_limit: self.gas_limit.into(),
+            to: self.to,
+            value: self.value,
+            data: self.data,
+            signature: None,
+        }
+    }
+}
+
+/// Type byte of EIP-2930 access list transactions.
+pub const EIP2930_TX_TYPE: u8 = 0x01;
+/// Type byte of EIP-1559 dynamic fee transactions.
+pub const EIP1559_TX_TYPE: u8 = 0x02;
+/// Type byte of EIP-4844 blob transactions.
+pub const EIP4844_TX_TYPE: u8 = 0x03;
+/// Type byte of EIP-7702 set code transactions.
+pub const EIP7702_TX_TYPE: u8 = 0x04;
+/// Prefix of the payload an EIP-7702 authorization signs.
+pub const SET_CODE_AUTHORIZATION_MAGIC: u8 = 0x05;
+/// Version byte of versioned hashes of KZG commitments.
+pub const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+
+/// The versioned hash of the KZG commitment to a blob, which is what a blob
+/// transaction refers to the blob by.
+pub fn blob_versioned_hash(commitment: &[u8; 48]) -> [u8; 32] {
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&<Sha256 as sha2::Digest>::digest(commitment));
+    hash[0] = VERSIONED_HASH_VERSION_KZG;
+    hash
+}
+
+/// An address and the storage keys of it a transaction declares it will
+/// access, which makes those accesses cheaper (EIP-2930).
+#[derive(Clone, Debug, PartialEq, Eq, Default)]
+pub struct AccessListItem {
+    pub address: Address,
+    pub storage_keys: Vec<[u8; 32]>,
+}
+
+/// The addresses and storage keys a transaction declares it will access.
+pub type AccessList = Vec<AccessListItem>;
+
+/// The access list in the form it is RLP encoded.
+fn access_list_rlp(access_list: &[AccessListItem]) -> Vec<(ByteBuf, Vec<ByteBuf>)> {
+    access_list
+        .iter()
+        .map(|item| {
+            (
+                ByteBuf::from(item.address.as_bytes().to_vec()),
+                item.storage_keys
+                    .iter()
+                    .map(|key| ByteBuf::from(key.to_vec()))
+                    .collect(),
             )
-            .unwrap(),
-        )),
-    };
-    let lhs = to_bytes(&tx).unwrap();
-    let lhs = bytes_to_hex_str(&lhs);
-    let rhs = "f864808504a817c800825208943535353535353535353535353535353535353535808025a0044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116da0044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116d".to_owned();
-    assert_eq!(lhs, rhs);
+        })
+        .collect()
 }
 
-#[test]
-fn test_basictests_txtest_1() {
-    use serde_rlp::ser::to_bytes;
-    use utils::bytes_to_hex_str;
-    // https://github.com/ethereum/tests/blob/b44cea1cccf1e4b63a05d1ca9f70f2063f28da6d/BasicTests/txtest.json
-    let tx = Transaction {
-        nonce: Uint256::from_str_radix("00", 16).unwrap(),
-        gas_price: "1000000000000".parse().unwrap(),
-        gas_limit: "10000".parse().unwrap(),
-        to: "13978aee95f38490e9769c39b2773ed763d9cd5f".parse().unwrap(),
-        value: "10000000000000000".parse().unwrap(),
-        data: Vec::new(),
-        signature: None,
+/// Decodes an RLP encoded access list.
+fn decode_access_list(item: &RlpItem) -> Result<AccessList, Error> {
+    let mut access_list = Vec::new();
+    for entry in item.as_list()? {
+        let fields = entry.as_list()?;
+        ensure!(
+            fields.len() == 2,
+            "Access list entry should have 2 fields but {} were found",
+            fields.len()
+        );
+        let mut storage_keys = Vec::new();
+        for key in fields[1].as_list()? {
+            storage_keys.push(StorageKey::try_from(key.as_bytes()?)?.into());
+        }
+        access_list.push(AccessListItem {
+            address: Address::from_slice(fields[0].as_bytes()?)?,
+            storage_keys,
+        });
+    }
+    Ok(access_list)
+}
+
+/// Decodes the RLP list after the type byte of a typed transaction, which
+/// should have `count` fields.
+fn decode_typed_fields(bytes: &[u8], tx_type: u8, count: usize) -> Result<Vec<RlpItem<'_>>, Error> {
+    ensure!(
+        bytes.first() == Some(&tx_type),
+        "Transaction is not of type {}",
+        tx_type
+    );
+    let items = match decode_item(&bytes[1..])? {
+        RlpItem::List(items) => items,
+        RlpItem::Bytes(_) => return Err(RlpDecodeError::ExpectedList.into()),
     };
-    // Unsigned
-    let lhs = to_bytes(&tx).unwrap();
-    let lhs = bytes_to_hex_str(&lhs);
-    let rhs =
-        "eb8085e8d4a510008227109413978aee95f38490e9769c39b2773ed763d9cd5f872386f26fc1000080808080"
-            .to_owned();
-    assert_eq!(lhs, rhs);
+    ensure!(
+        items.len() == count,
+        "Transaction should have {} fields but {} were found",
+        count,
+        items.len()
+    );
+    Ok(items)
+}
 
-    // Signed
-    let key: PrivateKey = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4"
-        .parse()
-        .unwrap();
-    let signed_tx = tx.sign(&key, None);
+/// Decodes the "vrs" params that end the fields of a typed transaction.
+fn decode_typed_signature(items: &[RlpItem]) -> Result<Option<Signature>, Error> {
+    let vrs = &items[items.len() - 3..];
+    decode_signature(vrs[0].as_bytes()?, vrs[1].as_bytes()?, vrs[2].as_bytes()?)
+}
 
-    let lhs = to_bytes(&signed_tx).unwrap();
-    let lhs = bytes_to_hex_str(&lhs);
-    let rhs = "f86b8085e8d4a510008227109413978aee95f38490e9769c39b2773ed763d9cd5f872386f26fc10000801ba0eab47c1a49bf2fe5d40e01d313900e19ca485867d462fe06e139e3a536c6d4f4a014a569d327dcda4b29f74f93c0e9729d2f49ad726e703f9cd90dbb0fbf6649f1".to_owned();
+/// The type byte followed by the RLP encoding of `fields`.
+fn typed_encoding<T: Serialize>(tx_type: u8, fields: &T) -> Result<Vec<u8>, Error> {
+    let mut bytes = vec![tx_type];
+    bytes.extend(to_bytes(fields)?);
+    Ok(bytes)
+}
 
-    assert_eq!(lhs, rhs);
+/// Signs the signing hash of a typed transaction, whose signature has the
+/// y parity as `v`.
+fn sign_typed(key: &PrivateKey, hash: &[u8; 32]) -> Signature {
+    let mut sig = key.sign_hash(hash);
+    sig.v -= 27u32.into();
+    sig
 }
 
-#[test]
-fn test_basictests_txtest_2() {
-    use serde_rlp::ser::to_bytes;
-    use utils::{bytes_to_hex_str, hex_str_to_bytes};
-    // https://github.com/ethereum/tests/blob/b44cea1cccf1e4b63a05d1ca9f70f2063f28da6d/BasicTests/txtest.json
-    let tx = Transaction {
-        nonce: "0".parse().unwrap(),
-        gas_price: "1000000000000".parse().unwrap(),
-        gas_limit: "10000".parse().unwrap(),
-        to: Address::default(),
-        value: "0".parse().unwrap(),
-        data: hex_str_to_bytes("6025515b525b600a37f260003556601b596020356000355760015b525b54602052f260255860005b525b54602052f2").unwrap(),
-        signature: None
-    };
-    // Unsigned
-    let lhs = to_bytes(&tx).unwrap();
-    let lhs = bytes_to_hex_str(&lhs);
-    let rhs = "f83f8085e8d4a510008227108080af6025515b525b600a37f260003556601b596020356000355760015b525b54602052f260255860005b525b54602052f2808080".to_owned();
-    assert_eq!(lhs, rhs);
+/// Recovers the signer of a typed transaction.
+fn recover_typed(signature: &Option<Signature>, hash: &[u8; 32]) -> Result<Address, Error> {
+    ensure!(signature.is_some(), "Transaction is not signed");
+    let sig = signature.as_ref().unwrap();
+    if sig.v > 1u32.into() {
+        return Err(ClarityError::InvalidV.into());
+    }
+    if !sig.is_valid() {
+        return Err(ClarityError::InvalidSignatureValues.into());
+    }
+    sig.check_low_s_homestead()?;
+    let legacy = Signature::new(sig.v.clone() + 27u32.into(), sig.r.clone(), sig.s.clone());
+    legacy.recover(hash)
+}
 
-    // Signed
-    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
-        .parse()
-        .unwrap();
-    let signed_tx = tx.sign(&key, None);
+/// Intrinsic gas of a typed transaction, with the calldata pricing of
+/// EIP-2028 and the cost of the access list.
+fn typed_intrinsic_gas(to: &Address, data: &[u8], access_list: &[AccessListItem]) -> Uint256 {
+    let num_zero_bytes = bytecount::count(data, 0u8);
+    let num_non_zero_bytes = data.len() - num_zero_bytes;
+    let num_storage_keys: usize = access_list.iter().map(|item| item.storage_keys.len()).sum();
+    let mut gas = Uint256::from(GTXCOST)
+        + Uint256::from(GTXDATAZERO) * Uint256::from(num_zero_bytes as u64)
+        + Uint256::from(GTXDATANONZERO_EIP2028) * Uint256::from(num_non_zero_bytes as u64)
+        + Uint256::from(GACCESSLISTADDRESS) * Uint256::from(access_list.len() as u64)
+        + Uint256::from(GACCESSLISTSTORAGE) * Uint256::from(num_storage_keys as u64);
+    if *to == Address::default() {
+        gas += GTXCREATE.into();
+    }
+    gas
+}
 
-    let lhs = to_bytes(&signed_tx).unwrap();
-    let lhs = bytes_to_hex_str(&lhs);
+/// What a typed transaction is checked on.
+struct TypedFields<'a> {
+    chain_id: &'a Uint256,
+    nonce: &'a Uint256,
+    gas_limit: &'a Uint256,
+    /// The gas price, or the maximum fee per gas
+    gas_price: &'a Uint256,
+    value: &'a Uint256,
+    intrinsic_gas: Uint256,
+    signature: &'a Option<Signature>,
+}
 
-    // This value is wrong
-    let rhs = "f87f8085e8d4a510008227108080af6025515b525b600a37f260003556601b596020356000355760015b525b54602052f260255860005b525b54602052f21ca05afed0244d0da90b67cf8979b0f246432a5112c0d31e8d5eedd2bc17b171c694a044efca37cb9883d1ee7a47236f3592df152931a930566933de2dc6e341c11426".to_owned();
+impl<'a> TypedFields<'a> {
+    /// The checks shared by typed transactions, on top of the named fields
+    /// being checked for overflows.
+    fn issues(
+        &self,
+        fields: &[(&'static str, &Uint256)],
+        network_id: Option<u64>,
+    ) -> Vec<ValidityIssue> {
+        let mut issues = Vec::new();
+        let mut overflow = false;
+        for &(name, field) in fields {
+            if *field >= *TT256 {
+                issues.push(ValidityIssue::FieldOverflow(name));
+                overflow = true;
+            }
+        }
+        if *self.nonce >= Uint256::from(u64::MAX) {
+            issues.push(ValidityIssue::NonceTooLarge);
+        }
+        if *self.gas_limit < self.intrinsic_gas {
+            issues.push(ValidityIssue::IntrinsicGas {
+                required: self.intrinsic_gas.clone(),
+            });
+        }
+        if *self.gas_limit > MAX_GAS_LIMIT.into() {
+            issues.push(ValidityIssue::GasLimitTooLarge);
+        }
+        let cost = self
+            .gas_price
+            .checked_mul(self.gas_limit)
+            .and_then(|fee| fee.checked_add(self.value));
+        if !overflow && cost.is_none_or(|cost| cost >= *TT256) {
+            issues.push(ValidityIssue::CostOverflow);
+        }
+        if let Some(ref sig) = *self.signature {
+            if !sig.is_valid() {
+                issues.push(ValidityIssue::InvalidSignature);
+            } else if sig.check_low_s_homestead().is_err() {
+                issues.push(ValidityIssue::HighS);
+            }
+            if sig.v > 1u32.into() {
+                issues.push(ValidityIssue::InvalidV);
+            }
+        }
+        if let Some(expected) = network_id {
+            if *self.chain_id != expected.into() {
+                issues.push(ValidityIssue::NetworkIdMismatch {
+                    expected,
+                    found: self.chain_id.clone(),
+                });
+            }
+        }
+        issues
+    }
+}
 
-    assert_eq!(lhs, rhs);
+/// An access list transaction, as introduced by EIP-2930 (type 1).
+///
+/// It is a legacy transaction with an access list, always replay protected
+/// by its `chain_id`. Its signature has the y parity, 0 or 1, as `v`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eip2930Transaction {
+    pub chain_id: Uint256,
+    pub nonce: Uint256,
+    pub gas_price: Uint256,
+    pub gas_limit: Uint256,
+    /// The recipient, the zero address to create a contract
+    pub to: Address,
+    pub value: Uint256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+    pub signature: Option<Signature>,
+}
+
+impl Eip2930Transaction {
+    /// The type byte followed by the RLP encoding of the fields the
+    /// signature covers.
+    fn signing_payload(&self) -> Vec<u8> {
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.gas_price.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &AddressDef(&self.to),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+        );
+        typed_encoding(EIP2930_TX_TYPE, &fields).unwrap()
+    }
+
+    /// The hash the signature is over.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.signing_payload())
+    }
+
+    /// Signs the transaction.
+    pub fn sign(&self, key: &PrivateKey) -> Eip2930Transaction {
+        Eip2930Transaction {
+            signature: Some(sign_typed(key, &self.signing_hash())),
+            ..self.clone()
+        }
+    }
+
+    /// Recovers the sender from the signature.
+    pub fn sender(&self) -> Result<Address, Error> {
+        recover_typed(&self.signature, &self.signing_hash())
+    }
+
+    /// Gas used before any code runs, including the access list.
+    pub fn intrinsic_gas_used(&self) -> Uint256 {
+        typed_intrinsic_gas(&self.to, &self.data, &self.access_list)
+    }
+
+    /// Checks field ranges, the signature and, if `network_id` is given,
+    /// the chain id, like [Transaction::validity_report](struct.Transaction.html#method.validity_report).
+    pub fn validity_report(&self, network_id: Option<u64>) -> ValidityReport {
+        let checked = TypedFields {
+            chain_id: &self.chain_id,
+            nonce: &self.nonce,
+            gas_limit: &self.gas_limit,
+            gas_price: &self.gas_price,
+            value: &self.value,
+            intrinsic_gas: self.intrinsic_gas_used(),
+            signature: &self.signature,
+        };
+        let issues = checked.issues(
+            &[
+                ("Chain id", &self.chain_id),
+                ("Nonce", &self.nonce),
+                ("Gas price", &self.gas_price),
+                ("Gas limit", &self.gas_limit),
+                ("Value", &self.value),
+            ],
+            network_id,
+        );
+        ValidityReport { issues }
+    }
+
+    /// The type byte followed by the RLP encoding of the transaction. An
+    /// unsigned transaction is encoded with a zero signature.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let sig = self.signature.clone().unwrap_or_default();
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.gas_price.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &AddressDef(&self.to),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+            &BigEndianInt(sig.v),
+            &BigEndianInt(sig.r),
+            &BigEndianInt(sig.s),
+        );
+        typed_encoding(EIP2930_TX_TYPE, &fields)
+    }
+
+    /// The transaction hash, the hash of its encoding.
+    pub fn hash(&self) -> Result<[u8; 32], Error> {
+        Ok(keccak256(&self.to_bytes()?))
+    }
+
+    /// Decodes a transaction from its type byte and RLP encoding.
+    ///
+    /// A transaction whose "vrs" params are all zero is decoded without a
+    /// signature, as that is how an unsigned transaction is encoded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Eip2930Transaction, Error> {
+        let items = decode_typed_fields(bytes, EIP2930_TX_TYPE, 11)?;
+        let uint = |i: usize| -> Result<Uint256, Error> { decode_uint(items[i].as_bytes()?) };
+        Ok(Eip2930Transaction {
+            chain_id: uint(0)?,
+            nonce: uint(1)?,
+            gas_price: uint(2)?,
+            gas_limit: uint(3)?,
+            to: decode_to(items[4].as_bytes()?)?,
+            value: uint(5)?,
+            data: items[6].as_bytes()?.to_vec(),
+            access_list: decode_access_list(&items[7])?,
+            signature: decode_typed_signature(&items)?,
+        })
+    }
+}
+
+/// A dynamic fee transaction, as introduced by EIP-1559 (type 2).
+///
+/// Instead of a gas price it names the most it pays per gas, and the tip
+/// the block producer gets on top of the base fee. It is always replay
+/// protected by its `chain_id`, and its signature has the y parity, 0 or
+/// 1, as `v`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eip1559Transaction {
+    pub chain_id: Uint256,
+    pub nonce: Uint256,
+    pub max_priority_fee_per_gas: Uint256,
+    pub max_fee_per_gas: Uint256,
+    pub gas_limit: Uint256,
+    /// The recipient, the zero address to create a contract
+    pub to: Address,
+    pub value: Uint256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+    pub signature: Option<Signature>,
+}
+
+impl Eip1559Transaction {
+    /// The type byte followed by the RLP encoding of the fields the
+    /// signature covers.
+    fn signing_payload(&self) -> Vec<u8> {
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.max_priority_fee_per_gas.clone()),
+            &BigEndianInt(self.max_fee_per_gas.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &AddressDef(&self.to),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+        );
+        typed_encoding(EIP1559_TX_TYPE, &fields).unwrap()
+    }
+
+    /// The hash the signature is over.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.signing_payload())
+    }
+
+    /// Signs the transaction.
+    pub fn sign(&self, key: &PrivateKey) -> Eip1559Transaction {
+        Eip1559Transaction {
+            signature: Some(sign_typed(key, &self.signing_hash())),
+            ..self.clone()
+        }
+    }
+
+    /// Recovers the sender from the signature.
+    pub fn sender(&self) -> Result<Address, Error> {
+        recover_typed(&self.signature, &self.signing_hash())
+    }
+
+    /// Gas used before any code runs, including the access list.
+    pub fn intrinsic_gas_used(&self) -> Uint256 {
+        typed_intrinsic_gas(&self.to, &self.data, &self.access_list)
+    }
+
+    /// Checks field ranges, the fees, the signature and, if `network_id`
+    /// is given, the chain id, like [Transaction::validity_report](struct.Transaction.html#method.validity_report).
+    pub fn validity_report(&self, network_id: Option<u64>) -> ValidityReport {
+        let checked = TypedFields {
+            chain_id: &self.chain_id,
+            nonce: &self.nonce,
+            gas_limit: &self.gas_limit,
+            gas_price: &self.max_fee_per_gas,
+            value: &self.value,
+            intrinsic_gas: self.intrinsic_gas_used(),
+            signature: &self.signature,
+        };
+        let mut issues = checked.issues(
+            &[
+                ("Chain id", &self.chain_id),
+                ("Nonce", &self.nonce),
+                ("Max priority fee per gas", &self.max_priority_fee_per_gas),
+                ("Max fee per gas", &self.max_fee_per_gas),
+                ("Gas limit", &self.gas_limit),
+                ("Value", &self.value),
+            ],
+            network_id,
+        );
+        if self.max_priority_fee_per_gas > self.max_fee_per_gas {
+            issues.push(ValidityIssue::PriorityFeeAboveMaxFee);
+        }
+        ValidityReport { issues }
+    }
+
+    /// The type byte followed by the RLP encoding of the transaction. An
+    /// unsigned transaction is encoded with a zero signature.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let sig = self.signature.clone().unwrap_or_default();
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.max_priority_fee_per_gas.clone()),
+            &BigEndianInt(self.max_fee_per_gas.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &AddressDef(&self.to),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+            &BigEndianInt(sig.v),
+            &BigEndianInt(sig.r),
+            &BigEndianInt(sig.s),
+        );
+        typed_encoding(EIP1559_TX_TYPE, &fields)
+    }
+
+    /// The transaction hash, the hash of its encoding.
+    pub fn hash(&self) -> Result<[u8; 32], Error> {
+        Ok(keccak256(&self.to_bytes()?))
+    }
+
+    /// Decodes a transaction from its type byte and RLP encoding.
+    ///
+    /// A transaction whose "vrs" params are all zero is decoded without a
+    /// signature, as that is how an unsigned transaction is encoded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Eip1559Transaction, Error> {
+        let items = decode_typed_fields(bytes, EIP1559_TX_TYPE, 12)?;
+        let uint = |i: usize| -> Result<Uint256, Error> { decode_uint(items[i].as_bytes()?) };
+        Ok(Eip1559Transaction {
+            chain_id: uint(0)?,
+            nonce: uint(1)?,
+            max_priority_fee_per_gas: uint(2)?,
+            max_fee_per_gas: uint(3)?,
+            gas_limit: uint(4)?,
+            to: decode_to(items[5].as_bytes()?)?,
+            value: uint(6)?,
+            data: items[7].as_bytes()?.to_vec(),
+            access_list: decode_access_list(&items[8])?,
+            signature: decode_typed_signature(&items)?,
+        })
+    }
+}
+
+/// A blob transaction, as introduced by EIP-4844 (type 3).
+///
+/// It is a dynamic fee transaction that also pays for blobs, which travel
+/// next to it and are referred to by the versioned hashes of their
+/// commitments. It can not create a contract, and its signature has the y
+/// parity, 0 or 1, as `v`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eip4844Transaction {
+    pub chain_id: Uint256,
+    pub nonce: Uint256,
+    pub max_priority_fee_per_gas: Uint256,
+    pub max_fee_per_gas: Uint256,
+    pub gas_limit: Uint256,
+    /// The recipient, always encoded in full as there is no contract creation
+    pub to: Address,
+    pub value: Uint256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+    pub max_fee_per_blob_gas: Uint256,
+    /// See [blob_versioned_hash](fn.blob_versioned_hash.html)
+    pub blob_versioned_hashes: Vec<[u8; 32]>,
+    pub signature: Option<Signature>,
+}
+
+impl Eip4844Transaction {
+    fn blob_hashes_rlp(&self) -> Vec<ByteBuf> {
+        self.blob_versioned_hashes
+            .iter()
+            .map(|hash| ByteBuf::from(hash.to_vec()))
+            .collect()
+    }
+
+    /// The type byte followed by the RLP encoding of the fields the
+    /// signature covers.
+    fn signing_payload(&self) -> Vec<u8> {
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.max_priority_fee_per_gas.clone()),
+            &BigEndianInt(self.max_fee_per_gas.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &ByteBuf::from(self.to.as_bytes().to_vec()),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+            &BigEndianInt(self.max_fee_per_blob_gas.clone()),
+            &self.blob_hashes_rlp(),
+        );
+        typed_encoding(EIP4844_TX_TYPE, &fields).unwrap()
+    }
+
+    /// The hash the signature is over.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.signing_payload())
+    }
+
+    /// Signs the transaction.
+    pub fn sign(&self, key: &PrivateKey) -> Eip4844Transaction {
+        Eip4844Transaction {
+            signature: Some(sign_typed(key, &self.signing_hash())),
+            ..self.clone()
+        }
+    }
+
+    /// Recovers the sender from the signature.
+    pub fn sender(&self) -> Result<Address, Error> {
+        recover_typed(&self.signature, &self.signing_hash())
+    }
+
+    /// Gas used before any code runs, including the access list. Blob gas
+    /// is paid for separately, see [blob_gas](#method.blob_gas).
+    pub fn intrinsic_gas_used(&self) -> Uint256 {
+        typed_intrinsic_gas(&self.to, &self.data, &self.access_list)
+    }
+
+    /// The blob gas the blobs of the transaction use.
+    pub fn blob_gas(&self) -> Uint256 {
+        Uint256::from(GAS_PER_BLOB) * Uint256::from(self.blob_versioned_hashes.len() as u64)
+    }
+
+    /// Checks field ranges, the fees, the blobs, the signature and, if
+    /// `network_id` is given, the chain id, like [Transaction::validity_report](struct.Transaction.html#method.validity_report).
+    ///
+    /// The maximum cost includes the blob fee.
+    pub fn validity_report(&self, network_id: Option<u64>) -> ValidityReport {
+        let checked = TypedFields {
+            chain_id: &self.chain_id,
+            nonce: &self.nonce,
+            gas_limit: &self.gas_limit,
+            gas_price: &self.max_fee_per_gas,
+            value: &self.value,
+            intrinsic_gas: self.intrinsic_gas_used(),
+            signature: &self.signature,
+        };
+        let mut issues = checked.issues(
+            &[
+                ("Chain id", &self.chain_id),
+                ("Nonce", &self.nonce),
+                ("Max priority fee per gas", &self.max_priority_fee_per_gas),
+                ("Max fee per gas", &self.max_fee_per_gas),
+                ("Gas limit", &self.gas_limit),
+                ("Value", &self.value),
+                ("Max fee per blob gas", &self.max_fee_per_blob_gas),
+            ],
+            network_id,
+        );
+        if self.max_priority_fee_per_gas > self.max_fee_per_gas {
+            issues.push(ValidityIssue::PriorityFeeAboveMaxFee);
+        }
+        let cost_overflows = issues.iter().any(|issue| {
+            matches!(
+                *issue,
+                ValidityIssue::FieldOverflow(_) | ValidityIssue::CostOverflow
+            )
+        });
+        if !cost_overflows {
+            let cost = self
+                .max_fee_per_gas
+                .checked_mul(&self.gas_limit)
+                .and_then(|fee| fee.checked_add(&self.value))
+                .and_then(|cost| {
+                    self.max_fee_per_blob_gas
+                        .checked_mul(&self.blob_gas())
+                        .and_then(|blob_fee| cost.checked_add(&blob_fee))
+                });
+            if cost.is_none_or(|cost| cost >= *TT256) {
+                issues.push(ValidityIssue::CostOverflow);
+            }
+        }
+        if self.to == Address::default() {
+            issues.push(ValidityIssue::BlobContractCreation);
+        }
+        if self.blob_versioned_hashes.is_empty() {
+            issues.push(ValidityIssue::NoBlobs);
+        }
+        if self
+            .blob_versioned_hashes
+            .iter()
+            .any(|hash| hash[0] != VERSIONED_HASH_VERSION_KZG)
+        {
+            issues.push(ValidityIssue::BlobHashVersion);
+        }
+        ValidityReport { issues }
+    }
+
+    /// The type byte followed by the RLP encoding of the transaction, as
+    /// included in blocks. An unsigned transaction is encoded with a zero
+    /// signature.
+    ///
+    /// This is not the network form sent with `eth_sendRawTransaction`,
+    /// which also carries the blobs, commitments and proofs.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let sig = self.signature.clone().unwrap_or_default();
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.max_priority_fee_per_gas.clone()),
+            &BigEndianInt(self.max_fee_per_gas.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &ByteBuf::from(self.to.as_bytes().to_vec()),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+            &BigEndianInt(self.max_fee_per_blob_gas.clone()),
+            &self.blob_hashes_rlp(),
+            &BigEndianInt(sig.v),
+            &BigEndianInt(sig.r),
+            &BigEndianInt(sig.s),
+        );
+        typed_encoding(EIP4844_TX_TYPE, &fields)
+    }
+
+    /// The transaction hash, the hash of its encoding.
+    pub fn hash(&self) -> Result<[u8; 32], Error> {
+        Ok(keccak256(&self.to_bytes()?))
+    }
+
+    /// Decodes a transaction from its type byte and RLP encoding, as
+    /// returned by [to_bytes](#method.to_bytes).
+    ///
+    /// A transaction whose "vrs" params are all zero is decoded without a
+    /// signature, as that is how an unsigned transaction is encoded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Eip4844Transaction, Error> {
+        let items = decode_typed_fields(bytes, EIP4844_TX_TYPE, 14)?;
+        let uint = |i: usize| -> Result<Uint256, Error> { decode_uint(items[i].as_bytes()?) };
+        let mut blob_versioned_hashes = Vec::new();
+        for hash in items[10].as_list()? {
+            blob_versioned_hashes.push(fixed_bytes("Blob versioned hash", hash.as_bytes()?)?);
+        }
+        Ok(Eip4844Transaction {
+            chain_id: uint(0)?,
+            nonce: uint(1)?,
+            max_priority_fee_per_gas: uint(2)?,
+            max_fee_per_gas: uint(3)?,
+            gas_limit: uint(4)?,
+            to: Address::from_slice(items[5].as_bytes()?)?,
+            value: uint(6)?,
+            data: items[7].as_bytes()?.to_vec(),
+            access_list: decode_access_list(&items[8])?,
+            max_fee_per_blob_gas: uint(9)?,
+            blob_versioned_hashes,
+            signature: decode_typed_signature(&items)?,
+        })
+    }
+}
+
+/// Permission for an account to run the code of `address` (EIP-7702).
+///
+/// It is signed by the account, its authority, separately from the set
+/// code transaction that carries it, which anyone can send.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Authorization {
+    /// The chain the authorization is valid on, or 0 for every chain
+    pub chain_id: Uint256,
+    /// The account whose code is to be used
+    pub address: Address,
+    /// The nonce the authority has when the authorization is processed
+    pub nonce: Uint256,
+}
+
+impl Authorization {
+    /// The hash the signature is over, of the magic byte followed by the
+    /// RLP encoding of the authorization.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &ByteBuf::from(self.address.as_bytes().to_vec()),
+            &BigEndianInt(self.nonce.clone()),
+        );
+        keccak256(&typed_encoding(SET_CODE_AUTHORIZATION_MAGIC, &fields).unwrap())
+    }
+
+    /// Signs the authorization with the key of the authority.
+    pub fn sign(&self, key: &PrivateKey) -> SignedAuthorization {
+        SignedAuthorization {
+            authorization: self.clone(),
+            signature: sign_typed(key, &self.signing_hash()),
+        }
+    }
+}
+
+/// An authorization with the signature of its authority, whose `v` is the
+/// y parity, 0 or 1.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedAuthorization {
+    pub authorization: Authorization,
+    pub signature: Signature,
+}
+
+impl SignedAuthorization {
+    /// Recovers the account that gave the authorization.
+    pub fn authority(&self) -> Result<Address, Error> {
+        recover_typed(
+            &Some(self.signature.clone()),
+            &self.authorization.signing_hash(),
+        )
+    }
+}
+
+/// The authorization list in the form it is RLP encoded.
+fn authorization_list_rlp(
+    authorizations: &[SignedAuthorization],
+) -> Vec<(
+    BigEndianInt,
+    ByteBuf,
+    BigEndianInt,
+    BigEndianInt,
+    BigEndianInt,
+    BigEndianInt,
+)> {
+    authorizations
+        .iter()
+        .map(|signed| {
+            (
+                BigEndianInt(signed.authorization.chain_id.clone()),
+                ByteBuf::from(signed.authorization.address.as_bytes().to_vec()),
+                BigEndianInt(signed.authorization.nonce.clone()),
+                BigEndianInt(signed.signature.v.clone()),
+                BigEndianInt(signed.signature.r.clone()),
+                BigEndianInt(signed.signature.s.clone()),
+            )
+        })
+        .collect()
+}
+
+/// Decodes an RLP encoded authorization list.
+fn decode_authorization_list(item: &RlpItem) -> Result<Vec<SignedAuthorization>, Error> {
+    let mut authorizations = Vec::new();
+    for entry in item.as_list()? {
+        let fields = entry.as_list()?;
+        ensure!(
+            fields.len() == 6,
+            "Authorization should have 6 fields but {} were found",
+            fields.len()
+        );
+        let uint = |i: usize| -> Result<Uint256, Error> { decode_uint(fields[i].as_bytes()?) };
+        authorizations.push(SignedAuthorization {
+            authorization: Authorization {
+                chain_id: uint(0)?,
+                address: Address::from_slice(fields[1].as_bytes()?)?,
+                nonce: uint(2)?,
+            },
+            signature: Signature::new(uint(3)?, uint(4)?, uint(5)?),
+        });
+    }
+    Ok(authorizations)
+}
+
+/// A set code transaction, as introduced by EIP-7702 (type 4).
+///
+/// It is a dynamic fee transaction that also sets the code of the
+/// authorities of its authorization list to delegate to the authorized
+/// addresses. It can not create a contract, and its signature has the y
+/// parity, 0 or 1, as `v`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Eip7702Transaction {
+    pub chain_id: Uint256,
+    pub nonce: Uint256,
+    pub max_priority_fee_per_gas: Uint256,
+    pub max_fee_per_gas: Uint256,
+    pub gas_limit: Uint256,
+    /// The recipient, always encoded in full as there is no contract creation
+    pub to: Address,
+    pub value: Uint256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+    pub authorization_list: Vec<SignedAuthorization>,
+    pub signature: Option<Signature>,
+}
+
+impl Eip7702Transaction {
+    /// The type byte followed by the RLP encoding of the fields the
+    /// signature covers.
+    fn signing_payload(&self) -> Vec<u8> {
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.max_priority_fee_per_gas.clone()),
+            &BigEndianInt(self.max_fee_per_gas.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &ByteBuf::from(self.to.as_bytes().to_vec()),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+            &authorization_list_rlp(&self.authorization_list),
+        );
+        typed_encoding(EIP7702_TX_TYPE, &fields).unwrap()
+    }
+
+    /// The hash the signature is over.
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.signing_payload())
+    }
+
+    /// Signs the transaction.
+    pub fn sign(&self, key: &PrivateKey) -> Eip7702Transaction {
+        Eip7702Transaction {
+            signature: Some(sign_typed(key, &self.signing_hash())),
+            ..self.clone()
+        }
+    }
+
+    /// Recovers the sender from the signature.
+    pub fn sender(&self) -> Result<Address, Error> {
+        recover_typed(&self.signature, &self.signing_hash())
+    }
+
+    /// Gas used before any code runs, including the access list and every
+    /// authorization.
+    pub fn intrinsic_gas_used(&self) -> Uint256 {
+        typed_intrinsic_gas(&self.to, &self.data, &self.access_list)
+            + Uint256::from(GAUTHORIZATION) * Uint256::from(self.authorization_list.len() as u64)
+    }
+
+    /// Checks field ranges, the fees, the authorization list, the signature
+    /// and, if `network_id` is given, the chain id, like [Transaction::validity_report](struct.Transaction.html#method.validity_report).
+    ///
+    /// Authorizations that can not be applied do not make the transaction
+    /// invalid, they are skipped when it runs, so they are not checked.
+    pub fn validity_report(&self, network_id: Option<u64>) -> ValidityReport {
+        let checked = TypedFields {
+            chain_id: &self.chain_id,
+            nonce: &self.nonce,
+            gas_limit: &self.gas_limit,
+            gas_price: &self.max_fee_per_gas,
+            value: &self.value,
+            intrinsic_gas: self.intrinsic_gas_used(),
+            signature: &self.signature,
+        };
+        let mut issues = checked.issues(
+            &[
+                ("Chain id", &self.chain_id),
+                ("Nonce", &self.nonce),
+                ("Max priority fee per gas", &self.max_priority_fee_per_gas),
+                ("Max fee per gas", &self.max_fee_per_gas),
+                ("Gas limit", &self.gas_limit),
+                ("Value", &self.value),
+            ],
+            network_id,
+        );
+        if self.max_priority_fee_per_gas > self.max_fee_per_gas {
+            issues.push(ValidityIssue::PriorityFeeAboveMaxFee);
+        }
+        if self.to == Address::default() {
+            issues.push(ValidityIssue::SetCodeContractCreation);
+        }
+        if self.authorization_list.is_empty() {
+            issues.push(ValidityIssue::NoAuthorizations);
+        }
+        ValidityReport { issues }
+    }
+
+    /// The type byte followed by the RLP encoding of the transaction. An
+    /// unsigned transaction is encoded with a zero signature.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let sig = self.signature.clone().unwrap_or_default();
+        let fields = (
+            &BigEndianInt(self.chain_id.clone()),
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.max_priority_fee_per_gas.clone()),
+            &BigEndianInt(self.max_fee_per_gas.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &ByteBuf::from(self.to.as_bytes().to_vec()),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            &access_list_rlp(&self.access_list),
+            &authorization_list_rlp(&self.authorization_list),
+            &BigEndianInt(sig.v),
+            &BigEndianInt(sig.r),
+            &BigEndianInt(sig.s),
+        );
+        typed_encoding(EIP7702_TX_TYPE, &fields)
+    }
+
+    /// The transaction hash, the hash of its encoding.
+    pub fn hash(&self) -> Result<[u8; 32], Error> {
+        Ok(keccak256(&self.to_bytes()?))
+    }
+
+    /// Decodes a transaction from its type byte and RLP encoding.
+    ///
+    /// A transaction whose "vrs" params are all zero is decoded without a
+    /// signature, as that is how an unsigned transaction is encoded.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Eip7702Transaction, Error> {
+        let items = decode_typed_fields(bytes, EIP7702_TX_TYPE, 13)?;
+        let uint = |i: usize| -> Result<Uint256, Error> { decode_uint(items[i].as_bytes()?) };
+        Ok(Eip7702Transaction {
+            chain_id: uint(0)?,
+            nonce: uint(1)?,
+            max_priority_fee_per_gas: uint(2)?,
+            max_fee_per_gas: uint(3)?,
+            gas_limit: uint(4)?,
+            to: Address::from_slice(items[5].as_bytes()?)?,
+            value: uint(6)?,
+            data: items[7].as_bytes()?.to_vec(),
+            access_list: decode_access_list(&items[8])?,
+            authorization_list: decode_authorization_list(&items[9])?,
+            signature: decode_typed_signature(&items)?,
+        })
+    }
+}
+
+/// A transaction of any of the types the crate supports, as wrapped in the
+/// envelope of EIP-2718: typed transactions start with their type byte,
+/// legacy transactions with an RLP list prefix.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TypedTransaction {
+    Legacy(Transaction),
+    Eip2930(Eip2930Transaction),
+    Eip1559(Eip1559Transaction),
+    Eip4844(Eip4844Transaction),
+    Eip7702(Eip7702Transaction),
+}
+
+impl TypedTransaction {
+    /// Decodes a transaction of any type, telling them apart by their first
+    /// byte.
+    pub fn from_bytes(bytes: &[u8]) -> Result<TypedTransaction, Error> {
+        match bytes.first() {
+            None => Err(RlpDecodeError::UnexpectedEnd.into()),
+            Some(&EIP2930_TX_TYPE) => Ok(Eip2930Transaction::from_bytes(bytes)?.into()),
+            Some(&EIP1559_TX_TYPE) => Ok(Eip1559Transaction::from_bytes(bytes)?.into()),
+            Some(&EIP4844_TX_TYPE) => Ok(Eip4844Transaction::from_bytes(bytes)?.into()),
+            Some(&EIP7702_TX_TYPE) => Ok(Eip7702Transaction::from_bytes(bytes)?.into()),
+            // Type bytes go up to 0x7f, and an RLP list starts at 0xc0
+            Some(&prefix) if prefix >= 0xc0 => Ok(Transaction::from_bytes(bytes)?.into()),
+            Some(&tx_type) => Err(ClarityError::UnknownTransactionType(tx_type).into()),
+        }
+    }
+
+    /// The type byte, 0 for legacy transactions.
+    pub fn tx_type(&self) -> u8 {
+        match *self {
+            TypedTransaction::Legacy(_) => 0,
+            TypedTransaction::Eip2930(_) => EIP2930_TX_TYPE,
+            TypedTransaction::Eip1559(_) => EIP1559_TX_TYPE,
+            TypedTransaction::Eip4844(_) => EIP4844_TX_TYPE,
+            TypedTransaction::Eip7702(_) => EIP7702_TX_TYPE,
+        }
+    }
+
+    pub fn nonce(&self) -> &Uint256 {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => &tx.nonce,
+            TypedTransaction::Eip2930(ref tx) => &tx.nonce,
+            TypedTransaction::Eip1559(ref tx) => &tx.nonce,
+            TypedTransaction::Eip4844(ref tx) => &tx.nonce,
+            TypedTransaction::Eip7702(ref tx) => &tx.nonce,
+        }
+    }
+
+    /// The recipient, the zero address for a contract creation.
+    pub fn to(&self) -> &Address {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => &tx.to,
+            TypedTransaction::Eip2930(ref tx) => &tx.to,
+            TypedTransaction::Eip1559(ref tx) => &tx.to,
+            TypedTransaction::Eip4844(ref tx) => &tx.to,
+            TypedTransaction::Eip7702(ref tx) => &tx.to,
+        }
+    }
+
+    pub fn value(&self) -> &Uint256 {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => &tx.value,
+            TypedTransaction::Eip2930(ref tx) => &tx.value,
+            TypedTransaction::Eip1559(ref tx) => &tx.value,
+            TypedTransaction::Eip4844(ref tx) => &tx.value,
+            TypedTransaction::Eip7702(ref tx) => &tx.value,
+        }
+    }
+
+    pub fn data(&self) -> &[u8] {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => &tx.data,
+            TypedTransaction::Eip2930(ref tx) => &tx.data,
+            TypedTransaction::Eip1559(ref tx) => &tx.data,
+            TypedTransaction::Eip4844(ref tx) => &tx.data,
+            TypedTransaction::Eip7702(ref tx) => &tx.data,
+        }
+    }
+
+    /// The access list, which legacy transactions do not have.
+    pub fn access_list(&self) -> &[AccessListItem] {
+        match *self {
+            TypedTransaction::Legacy(_) => &[],
+            TypedTransaction::Eip2930(ref tx) => &tx.access_list,
+            TypedTransaction::Eip1559(ref tx) => &tx.access_list,
+            TypedTransaction::Eip4844(ref tx) => &tx.access_list,
+            TypedTransaction::Eip7702(ref tx) => &tx.access_list,
+        }
+    }
+
+    /// The authorizations of set code transactions, none for the others.
+    pub fn authorization_list(&self) -> &[SignedAuthorization] {
+        match *self {
+            TypedTransaction::Eip7702(ref tx) => &tx.authorization_list,
+            _ => &[],
+        }
+    }
+
+    /// The chain the transaction is for, which legacy transactions only
+    /// have once they are signed with replay protection.
+    pub fn chain_id(&self) -> Option<Uint256> {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => {
+                tx.signature.as_ref().and_then(Signature::network_id)
+            }
+            TypedTransaction::Eip2930(ref tx) => Some(tx.chain_id.clone()),
+            TypedTransaction::Eip1559(ref tx) => Some(tx.chain_id.clone()),
+            TypedTransaction::Eip4844(ref tx) => Some(tx.chain_id.clone()),
+            TypedTransaction::Eip7702(ref tx) => Some(tx.chain_id.clone()),
+        }
+    }
+
+    /// The most the transaction pays per gas: the gas price of legacy and
+    /// EIP-2930 transactions, the max fee per gas of the others.
+    pub fn max_fee_per_gas(&self) -> &Uint256 {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => &tx.gas_price,
+            TypedTransaction::Eip2930(ref tx) => &tx.gas_price,
+            TypedTransaction::Eip1559(ref tx) => &tx.max_fee_per_gas,
+            TypedTransaction::Eip4844(ref tx) => &tx.max_fee_per_gas,
+            TypedTransaction::Eip7702(ref tx) => &tx.max_fee_per_gas,
+        }
+    }
+
+    pub fn signature(&self) -> Option<&Signature> {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => tx.signature.as_ref(),
+            TypedTransaction::Eip2930(ref tx) => tx.signature.as_ref(),
+            TypedTransaction::Eip1559(ref tx) => tx.signature.as_ref(),
+            TypedTransaction::Eip4844(ref tx) => tx.signature.as_ref(),
+            TypedTransaction::Eip7702(ref tx) => tx.signature.as_ref(),
+        }
+    }
+
+    /// Signs the transaction. Legacy transactions are signed for
+    /// `network_id`, typed transactions for their own chain id, which
+    /// `network_id` has to match if it is given.
+    pub fn sign(
+        &self,
+        key: &PrivateKey,
+        network_id: Option<u64>,
+    ) -> Result<TypedTransaction, Error> {
+        let check_chain_id = |chain_id: &Uint256| -> Result<(), Error> {
+            if network_id.is_some_and(|id| *chain_id != id.into()) {
+                return Err(ClarityError::InvalidNetworkId.into());
+            }
+            Ok(())
+        };
+        Ok(match *self {
+            TypedTransaction::Legacy(ref tx) => tx.sign(key, network_id).into(),
+            TypedTransaction::Eip2930(ref tx) => {
+                check_chain_id(&tx.chain_id)?;
+                tx.sign(key).into()
+            }
+            TypedTransaction::Eip1559(ref tx) => {
+                check_chain_id(&tx.chain_id)?;
+                tx.sign(key).into()
+            }
+            TypedTransaction::Eip4844(ref tx) => {
+                check_chain_id(&tx.chain_id)?;
+                tx.sign(key).into()
+            }
+            TypedTransaction::Eip7702(ref tx) => {
+                check_chain_id(&tx.chain_id)?;
+                tx.sign(key).into()
+            }
+        })
+    }
+
+    /// Recovers the sender from the signature.
+    pub fn sender(&self) -> Result<Address, Error> {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => tx.sender(),
+            TypedTransaction::Eip2930(ref tx) => tx.sender(),
+            TypedTransaction::Eip1559(ref tx) => tx.sender(),
+            TypedTransaction::Eip4844(ref tx) => tx.sender(),
+            TypedTransaction::Eip7702(ref tx) => tx.sender(),
+        }
+    }
+
+    /// See the `validity_report` of each transaction type.
+    pub fn validity_report(&self, network_id: Option<u64>) -> ValidityReport {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => tx.validity_report(network_id),
+            TypedTransaction::Eip2930(ref tx) => tx.validity_report(network_id),
+            TypedTransaction::Eip1559(ref tx) => tx.validity_report(network_id),
+            TypedTransaction::Eip4844(ref tx) => tx.validity_report(network_id),
+            TypedTransaction::Eip7702(ref tx) => tx.validity_report(network_id),
+        }
+    }
+
+    /// The encoding of the transaction, as sent with
+    /// `eth_sendRawTransaction`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        match *self {
+            TypedTransaction::Legacy(ref tx) => tx.to_bytes(),
+            TypedTransaction::Eip2930(ref tx) => tx.to_bytes(),
+            TypedTransaction::Eip1559(ref tx) => tx.to_bytes(),
+            TypedTransaction::Eip4844(ref tx) => tx.to_bytes(),
+            TypedTransaction::Eip7702(ref tx) => tx.to_bytes(),
+        }
+    }
+
+    /// The transaction hash, the hash of its encoding.
+    pub fn hash(&self) -> Result<[u8; 32], Error> {
+        Ok(keccak256(&self.to_bytes()?))
+    }
+}
+
+impl From<Transaction> for TypedTransaction {
+    fn from(tx: Transaction) -> TypedTransaction {
+        TypedTransaction::Legacy(tx)
+    }
+}
+
+impl From<Eip2930Transaction> for TypedTransaction {
+    fn from(tx: Eip2930Transaction) -> TypedTransaction {
+        TypedTransaction::Eip2930(tx)
+    }
+}
+
+impl From<Eip1559Transaction> for TypedTransaction {
+    fn from(tx: Eip1559Transaction) -> TypedTransaction {
+        TypedTransaction::Eip1559(tx)
+    }
+}
+
+impl From<Eip4844Transaction> for TypedTransaction {
+    fn from(tx: Eip4844Transaction) -> TypedTransaction {
+        TypedTransaction::Eip4844(tx)
+    }
+}
+
+impl From<Eip7702Transaction> for TypedTransaction {
+    fn from(tx: Eip7702Transaction) -> TypedTransaction {
+        TypedTransaction::Eip7702(tx)
+    }
+}
+
+/// A signed transaction kept in its raw encoded form.
+///
+/// Relays and mempools mostly pass transactions around as bytes, and only
+/// occasionally need to look inside them. `SignedTx` keeps the canonical
+/// encoding, and decodes the transaction, its hash, and its sender only when
+/// first asked for them. Cloning is cheap, and clones share those results.
+#[derive(Clone)]
+pub struct SignedTx(Arc<SignedTxInner>);
+
+struct SignedTxInner {
+    raw: Vec<u8>,
+    hash: OnceLock<[u8; 32]>,
+    transaction: OnceLock<Result<Transaction, String>>,
+    sender: OnceLock<Result<Address, String>>,
+}
+
+impl SignedTx {
+    /// Wraps raw transaction bytes, such as the argument of
+    /// `eth_sendRawTransaction`.
+    ///
+    /// The bytes are not decoded until a field is needed, so invalid data is
+    /// only reported by [transaction](#method.transaction) and
+    /// [sender](#method.sender).
+    pub fn from_raw(raw: Vec<u8>) -> SignedTx {
+        SignedTx(Arc::new(SignedTxInner {
+            raw,
+            hash: OnceLock::new(),
+            transaction: OnceLock::new(),
+            sender: OnceLock::new(),
+        }))
+    }
+
+    /// Encodes a signed transaction.
+    pub fn new(transaction: Transaction) -> Result<SignedTx, Error> {
+        ensure!(transaction.signature.is_some(), "Transaction is not signed");
+        let tx = SignedTx::from_raw(transaction.to_bytes()?);
+        let _ = tx.0.transaction.set(Ok(transaction));
+        Ok(tx)
+    }
+
+    /// The canonical encoding of the transaction.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0.raw
+    }
+
+    /// The transaction hash, which is the Keccak-256 of the raw bytes.
+    pub fn hash(&self) -> [u8; 32] {
+        *self.0.hash.get_or_init(|| keccak256(&self.0.raw))
+    }
+
+    /// The decoded transaction.
+    pub fn transaction(&self) -> Result<&Transaction, Error> {
+        self.0
+            .transaction
+            .get_or_init(|| {
+                let tx = Transaction::from_bytes(&self.0.raw).map_err(|e| e.to_string())?;
+                if tx.signature.is_none() {
+                    return Err("Transaction is not signed".to_string());
+                }
+                Ok(tx)
+            })
+            .as_ref()
+            .map_err(|e| format_err!("{}", e))
+    }
+
+    /// The address that signed the transaction.
+    pub fn sender(&self) -> Result<Address, Error> {
+        self.0
+            .sender
+            .get_or_init(|| {
+                self.transaction()
+                    .and_then(|tx| tx.sender())
+                    .map_err(|e| e.to_string())
+            })
+            .clone()
+            .map_err(|e| format_err!("{}", e))
+    }
+}
+
+impl From<SignedTx> for Vec<u8> {
+    fn from(tx: SignedTx) -> Vec<u8> {
+        tx.as_bytes().to_vec()
+    }
+}
+
+impl PartialEq for SignedTx {
+    fn eq(&self, other: &SignedTx) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl Eq for SignedTx {}
+
+impl Hash for SignedTx {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.as_bytes().hash(state)
+    }
+}
+
+impl Ord for SignedTx {
+    /// Orders transactions by sender and then by nonce, like
+    /// [inclusion_key](struct.Transaction.html#method.inclusion_key), with
+    /// the sender recovered once and cached. Bytes that do not decode come
+    /// first, and ties are broken by transaction hash, which only equal
+    /// bytes share.
+    fn cmp(&self, other: &SignedTx) -> Ordering {
+        let key = |tx: &SignedTx| {
+            (
+                tx.sender().ok(),
+                tx.transaction().ok().map(|tx| tx.nonce.clone()),
+            )
+        };
+        key(self)
+            .cmp(&key(other))
+            .then_with(|| self.hash().cmp(&other.hash()))
+    }
+}
+
+impl PartialOrd for SignedTx {
+    fn partial_cmp(&self, other: &SignedTx) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Debug for SignedTx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SignedTx({})", self)
+    }
+}
+
+impl Display for SignedTx {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{}", bytes_to_hex_str(self.as_bytes()))
+    }
+}
+
+#[test]
+fn test_vitaliks_eip_158_vitalik_12_json() {
+    use serde_rlp::ser::to_bytes;
+    use utils::{bytes_to_hex_str, hex_str_to_bytes};
+    // https://github.com/ethereum/tests/blob/69f55e8608126e6470c2888a5b344c93c1550f40/TransactionTests/ttEip155VitaliksEip158/Vitalik_12.json
+    let tx = Transaction {
+        nonce: Uint256::from_str_radix("0e", 16).unwrap(),
+        gas_price: Uint256::from_str_radix("00", 16).unwrap(),
+        gas_limit: Uint256::from_str_radix("0493e0", 16).unwrap(),
+        to: Address::default(), // "" - zeros only
+        value: Uint256::from_str_radix("00", 16).unwrap(),
+        data: hex_str_to_bytes("60f2ff61000080610011600039610011565b6000f3").unwrap(),
+        signature: Some(Signature::new(
+            Uint256::from_str_radix("1c", 16).unwrap(),
+            Uint256::from_str_radix(
+                "a310f4d0b26207db76ba4e1e6e7cf1857ee3aa8559bcbc399a6b09bfea2d30b4",
+                16,
+            )
+            .unwrap(),
+            Uint256::from_str_radix(
+                "6dff38c645a1486651a717ddf3daccb4fd9a630871ecea0758ddfcf2774f9bc6",
+                16,
+            )
+            .unwrap(),
+        )),
+    };
+    let lhs = to_bytes(&tx).unwrap();
+    let lhs = bytes_to_hex_str(&lhs);
+    let rhs = "f8610e80830493e080809560f2ff61000080610011600039610011565b6000f31ca0a310f4d0b26207db76ba4e1e6e7cf1857ee3aa8559bcbc399a6b09bfea2d30b4a06dff38c645a1486651a717ddf3daccb4fd9a630871ecea0758ddfcf2774f9bc6".to_owned();
+    assert_eq!(lhs, rhs);
+
+    assert_eq!(
+        bytes_to_hex_str(&tx.sender().unwrap().as_bytes()),
+        "874b54a8bd152966d63f706bae1ffeb0411921e5"
+    );
+}
+
+#[test]
+fn test_vitaliks_eip_158_vitalik_1_json() {
+    use serde_rlp::ser::to_bytes;
+    use utils::bytes_to_hex_str;
+    // https://github.com/ethereum/tests/blob/69f55e8608126e6470c2888a5b344c93c1550f40/TransactionTests/ttEip155VitaliksEip158/Vitalik_12.json
+    let tx = Transaction {
+        nonce: Uint256::from_str_radix("00", 16).unwrap(),
+        gas_price: Uint256::from_str_radix("04a817c800", 16).unwrap(),
+        gas_limit: Uint256::from_str_radix("5208", 16).unwrap(),
+        to: "3535353535353535353535353535353535353535".parse().unwrap(),
+        value: Uint256::from_str_radix("00", 16).unwrap(),
+        data: Vec::new(),
+        signature: Some(Signature::new(
+            Uint256::from_str_radix("25", 16).unwrap(),
+            Uint256::from_str_radix(
+                "044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116d",
+                16,
+            )
+            .unwrap(),
+            Uint256::from_str_radix(
+                "044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116d",
+                16,
+            )
+            .unwrap(),
+        )),
+    };
+    let lhs = to_bytes(&tx).unwrap();
+    let lhs = bytes_to_hex_str(&lhs);
+    let rhs = "f864808504a817c800825208943535353535353535353535353535353535353535808025a0044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116da0044852b2a670ade5407e78fb2863c51de9fcb96542a07186fe3aeda6bb8a116d".to_owned();
+    assert_eq!(lhs, rhs);
+}
+
+#[test]
+fn test_basictests_txtest_1() {
+    use serde_rlp::ser::to_bytes;
+    use utils::bytes_to_hex_str;
+    // https://github.com/ethereum/tests/blob/b44cea1cccf1e4b63a05d1ca9f70f2063f28da6d/BasicTests/txtest.json
+    let tx = Transaction {
+        nonce: Uint256::from_str_radix("00", 16).unwrap(),
+        gas_price: "1000000000000".parse().unwrap(),
+        gas_limit: "10000".parse().unwrap(),
+        to: "13978aee95f38490e9769c39b2773ed763d9cd5f".parse().unwrap(),
+        value: "10000000000000000".parse().unwrap(),
+        data: Vec::new(),
+        signature: None,
+    };
+    // Unsigned
+    let lhs = to_bytes(&tx).unwrap();
+    let lhs = bytes_to_hex_str(&lhs);
+    let rhs =
+        "eb8085e8d4a510008227109413978aee95f38490e9769c39b2773ed763d9cd5f872386f26fc1000080808080"
+            .to_owned();
+    assert_eq!(lhs, rhs);
+
+    // Signed
+    let key: PrivateKey = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4"
+        .parse()
+        .unwrap();
+    let signed_tx = tx.sign(&key, None);
+
+    let lhs = to_bytes(&signed_tx).unwrap();
+    let lhs = bytes_to_hex_str(&lhs);
+    let rhs = "f86b8085e8d4a510008227109413978aee95f38490e9769c39b2773ed763d9cd5f872386f26fc10000801ba0eab47c1a49bf2fe5d40e01d313900e19ca485867d462fe06e139e3a536c6d4f4a014a569d327dcda4b29f74f93c0e9729d2f49ad726e703f9cd90dbb0fbf6649f1".to_owned();
+
+    assert_eq!(lhs, rhs);
+}
+
+#[test]
+fn test_basictests_txtest_2() {
+    use serde_rlp::ser::to_bytes;
+    use utils::{bytes_to_hex_str, hex_str_to_bytes};
+    // https://github.com/ethereum/tests/blob/b44cea1cccf1e4b63a05d1ca9f70f2063f28da6d/BasicTests/txtest.json
+    let tx = Transaction {
+        nonce: "0".parse().unwrap(),
+        gas_price: "1000000000000".parse().unwrap(),
+        gas_limit: "10000".parse().unwrap(),
+        to: Address::default(),
+        value: "0".parse().unwrap(),
+        data: hex_str_to_bytes("6025515b525b600a37f260003556601b596020356000355760015b525b54602052f260255860005b525b54602052f2").unwrap(),
+        signature: None
+    };
+    // Unsigned
+    let lhs = to_bytes(&tx).unwrap();
+    let lhs = bytes_to_hex_str(&lhs);
+    let rhs = "f83f8085e8d4a510008227108080af6025515b525b600a37f260003556601b596020356000355760015b525b54602052f260255860005b525b54602052f2808080".to_owned();
+    assert_eq!(lhs, rhs);
+
+    // Signed
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let signed_tx = tx.sign(&key, None);
+
+    let lhs = to_bytes(&signed_tx).unwrap();
+    let lhs = bytes_to_hex_str(&lhs);
+
+    // This value is wrong
+    let rhs = "f87f8085e8d4a510008227108080af6025515b525b600a37f260003556601b596020356000355760015b525b54602052f260255860005b525b54602052f21ca05afed0244d0da90b67cf8979b0f246432a5112c0d31e8d5eedd2bc17b171c694a044efca37cb9883d1ee7a47236f3592df152931a930566933de2dc6e341c11426".to_owned();
+
+    assert_eq!(lhs, rhs);
+}
+
+#[cfg(test)]
+fn test_transaction(nonce: u64) -> Transaction {
+    Transaction {
+        nonce: nonce.into(),
+        gas_price: 1_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 1u32.into(),
+        data: Vec::new(),
+        signature: None,
+    }
+}
+
+#[test]
+fn equality_follows_encoding() {
+    let tx = test_transaction(0);
+    let mut zero_sig = tx.clone();
+    zero_sig.signature = Some(Signature::default());
+    assert_eq!(tx, zero_sig);
+    assert_eq!(tx.hash(), zero_sig.hash());
+
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    assert_ne!(tx.sign(&key, Some(1)), tx);
+    assert_ne!(tx.sign(&key, Some(1)), tx.sign(&key, Some(2)));
+}
+
+#[test]
+fn usable_as_map_key() {
+    use std::collections::HashSet;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let signed = test_transaction(0).sign(&key, Some(1));
+    let mut set = HashSet::new();
+    assert!(set.insert(signed.clone()));
+    assert!(!set.insert(signed.clone()));
+    assert!(set.insert(test_transaction(0)));
+    assert_eq!(set.len(), 2);
+}
+
+#[test]
+fn ordered_by_sender_and_nonce() {
+    use std::collections::BTreeSet;
+    let alice: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let bob: PrivateKey = "c85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4"
+        .parse()
+        .unwrap();
+    let (first, second) = if alice.to_public_key().unwrap() < bob.to_public_key().unwrap() {
+        (alice, bob)
+    } else {
+        (bob, alice)
+    };
+    let mut txs = vec![
+        test_transaction(1).sign(&second, Some(1)),
+        test_transaction(2).sign(&first, Some(1)),
+        test_transaction(0).sign(&second, Some(1)),
+        test_transaction(1).sign(&first, Some(1)),
+    ];
+    let first = first.to_public_key().unwrap();
+    let second = second.to_public_key().unwrap();
+    let expected: Vec<(Address, Uint256)> = vec![
+        (first, 1u32.into()),
+        (first, 2u32.into()),
+        (second, 0u32.into()),
+        (second, 1u32.into()),
+    ];
+    let signed: BTreeSet<SignedTx> = txs
+        .iter()
+        .map(|tx| SignedTx::new(tx.clone()).unwrap())
+        .chain(Some(SignedTx::from_raw(vec![0xc0])))
+        .collect();
+    let order: Vec<(Option<Address>, Uint256)> = signed
+        .iter()
+        .map(|tx| {
+            let nonce = tx.transaction().map(|tx| tx.nonce.clone());
+            (tx.sender().ok(), nonce.unwrap_or_default())
+        })
+        .collect();
+    // The bytes that do not decode, then the transactions
+    assert_eq!(order[0], (None, 0u8.into()));
+    assert_eq!(
+        order[1..].to_vec(),
+        expected
+            .iter()
+            .map(|(sender, nonce)| (Some(*sender), nonce.clone()))
+            .collect::<Vec<_>>()
+    );
+
+    // Unsigned transactions have no sender, and only equal ones compare
+    // equal
+    txs.push(test_transaction(1));
+    txs.push(test_transaction(0));
+    txs.sort_by_cached_key(Transaction::inclusion_key);
+    assert!(txs[0].signature.is_none() && txs[1].signature.is_none());
+    assert!(txs[0].inclusion_key() != txs[1].inclusion_key());
+    let order: Vec<(Address, Uint256)> = txs[2..]
+        .iter()
+        .map(|tx| (tx.sender().unwrap(), tx.nonce.clone()))
+        .collect();
+    assert_eq!(order, expected);
+}
+
+#[test]
+fn decode_roundtrip() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let mut tx = test_transaction(7);
+    tx.data = vec![0u8, 1, 2, 3];
+    let signed = tx.sign(&key, Some(1));
+    let decoded = Transaction::from_bytes(&signed.to_bytes().unwrap()).unwrap();
+    assert_eq!(decoded.signature, signed.signature);
+    assert_eq!(decoded.nonce, signed.nonce);
+    assert_eq!(decoded.data, signed.data);
+    assert_eq!(decoded.to, signed.to);
+    assert_eq!(decoded.sender().unwrap(), key.to_public_key().unwrap());
+    assert_eq!(decoded.hash(), signed.hash());
+
+    let unsigned = Transaction::from_bytes(&tx.to_bytes().unwrap()).unwrap();
+    assert_eq!(unsigned.signature, None);
+    let mut contract_creation = tx.clone();
+    contract_creation.to = Address::default();
+    assert_eq!(
+        Transaction::from_bytes(&contract_creation.to_bytes().unwrap())
+            .unwrap()
+            .to,
+        Address::default()
+    );
+}
+
+#[test]
+fn decode_invalid() {
+    assert!(Transaction::from_bytes(&[]).is_err());
+    assert!(Transaction::from_bytes(&[0xc0]).is_err());
+    let mut bytes = test_transaction(1).to_bytes().unwrap();
+    bytes.push(0x80);
+    assert!(Transaction::from_bytes(&bytes).is_err());
+    // Leading zero in the nonce
+    assert!(Transaction::from_bytes(&[
+        0xca, 0x81, 0x00, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80
+    ])
+    .is_err());
+
+    let with_fields = |gas_price: Vec<u8>, to: Vec<u8>| {
+        let empty = ByteBuf::new();
+        to_bytes(&(
+            &empty,
+            &ByteBuf::from(gas_price),
+            &empty,
+            &ByteBuf::from(to),
+            &empty,
+            &empty,
+            &empty,
+            &empty,
+            &empty,
+        ))
+        .unwrap()
+    };
+    assert!(Transaction::from_bytes(&with_fields(vec![1], vec![0x11; 20])).is_ok());
+    // Integers of more than 256 bits
+    assert!(Transaction::from_bytes(&with_fields(vec![1; 33], vec![0x11; 20])).is_err());
+    // Recipients that are not addresses
+    assert!(Transaction::from_bytes(&with_fields(vec![1], vec![0x11; 19])).is_err());
+    assert!(Transaction::from_bytes(&with_fields(vec![1], vec![0x11; 21])).is_err());
+}
+
+#[test]
+fn signed_tx_lazy_fields() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let tx = test_transaction(3).sign(&key, Some(1));
+    let signed = SignedTx::from_raw(tx.to_bytes().unwrap());
+    let clone = signed.clone();
+    assert_eq!(signed.transaction().unwrap(), &tx);
+    assert_eq!(clone.sender().unwrap(), key.to_public_key().unwrap());
+    assert_eq!(signed.hash().to_vec(), tx.hash());
+    assert_eq!(signed, SignedTx::new(tx.clone()).unwrap());
+    assert_eq!(Vec::from(signed), tx.to_bytes().unwrap());
+}
+
+#[test]
+fn signed_tx_invalid() {
+    assert!(SignedTx::new(test_transaction(0)).is_err());
+    let unsigned = SignedTx::from_raw(test_transaction(0).to_bytes().unwrap());
+    assert!(unsigned.transaction().is_err());
+    let garbage = SignedTx::from_raw(vec![0xde, 0xad]);
+    assert!(garbage.transaction().is_err());
+    assert!(garbage.sender().is_err());
+    // The hash is available even when the bytes are not a transaction
+    assert_eq!(garbage.hash().len(), 32);
+    assert_eq!(garbage.to_string(), "0xdead");
+}
+
+#[test]
+fn validity_report_fields() {
+    use constants::TT256M1;
+    let tx = test_transaction(0);
+    assert!(tx.validity_report(None).is_valid());
+
+    let mut tx = test_transaction(u64::MAX);
+    tx.gas_limit = 20_000u32.into();
+    assert_eq!(
+        tx.validity_report(None).issues,
+        vec![
+            ValidityIssue::NonceTooLarge,
+            ValidityIssue::IntrinsicGas {
+                required: 21_000u32.into()
+            },
+        ]
+    );
+    assert!(!tx.is_valid());
+
+    let mut tx = test_transaction(0);
+    tx.gas_limit = (1u64 << 63).into();
+    tx.gas_price = TT256M1.clone();
+    let report = tx.validity_report(None);
+    assert_eq!(
+        report.issues,
+        vec![ValidityIssue::GasLimitTooLarge, ValidityIssue::CostOverflow]
+    );
+    // Neither makes the transaction impossible to encode
+    assert!(!report.is_fatal());
+    assert!(tx.is_valid());
+
+    tx.value = TT256.clone();
+    assert_eq!(
+        tx.validity_report(None).issues,
+        vec![
+            ValidityIssue::FieldOverflow("Value"),
+            ValidityIssue::GasLimitTooLarge
+        ]
+    );
+    assert!(!tx.is_valid());
+}
+
+#[cfg(test)]
+fn test_eip1559_transaction() -> Eip1559Transaction {
+    Eip1559Transaction {
+        chain_id: 1u8.into(),
+        nonce: 0u8.into(),
+        max_priority_fee_per_gas: 1_000_000_000u64.into(),
+        max_fee_per_gas: 2_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 1u32.into(),
+        data: Vec::new(),
+        access_list: Vec::new(),
+        signature: None,
+    }
+}
+
+#[test]
+fn eip1559_signing() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let tx = test_eip1559_transaction();
+    assert_eq!(
+        bytes_to_hex_str(&tx.signing_payload()),
+        concat!(
+            "02e70180843b9aca00847735940082520894",
+            "1111111111111111111111111111111111111111",
+            "0180c0"
+        )
+    );
+    assert!(tx.sender().is_err());
+
+    let signed = tx.sign(&key);
+    let sig = signed.signature.clone().unwrap();
+    assert!(sig.v == 0u32.into() || sig.v == 1u32.into());
+    assert_eq!(signed.sender().unwrap(), key.to_public_key().unwrap());
+    // The signature covers the chain id
+    let other_chain = Eip1559Transaction {
+        chain_id: 5u8.into(),
+        ..signed.clone()
+    };
+    assert_ne!(other_chain.sender().unwrap(), key.to_public_key().unwrap());
+
+    let bytes = signed.to_bytes().unwrap();
+    assert_eq!(bytes[0], EIP1559_TX_TYPE);
+    assert_eq!(signed.hash().unwrap(), keccak256(&bytes));
+
+    let mut legacy_v = signed;
+    legacy_v.signature.as_mut().unwrap().v = 27u32.into();
+    assert!(legacy_v.sender().is_err());
+}
+
+#[test]
+fn bump_gas_price() {
+    use constants::TT256M1;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let tx = test_transaction(3).sign(&key, Some(1));
+    let bumped = tx.bumped(10).unwrap();
+    assert_eq!(bumped.gas_price, 1_100_000_000u64.into());
+    assert_eq!(bumped.nonce, tx.nonce);
+    assert!(bumped.signature.is_none());
+    // Rounded up, and always higher
+    let cheap = Transaction {
+        gas_price: 5u32.into(),
+        ..test_transaction(0)
+    };
+    assert_eq!(cheap.bumped(10).unwrap().gas_price, 6u32.into());
+    assert_eq!(cheap.bumped(0).unwrap().gas_price, 6u32.into());
+    let expensive = Transaction {
+        gas_price: TT256M1.clone(),
+        ..test_transaction(0)
+    };
+    assert!(expensive.bumped(10).is_none());
+}
+
+#[test]
+fn sender_under_fork_rules() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let sender = key.to_public_key().unwrap();
+    let rules = SignatureRules::mainnet();
+    let error = |tx: &Transaction, block: u64| {
+        tx.sender_at(&rules, block)
+            .unwrap_err()
+            .downcast::<ClarityError>()
+            .unwrap()
+            .to_string()
+    };
+
+    let protected = test_transaction(0).sign(&key, Some(1));
+    assert_eq!(protected.sender_at(&rules, 2_675_000).unwrap(), sender);
+    assert_eq!(
+        error(&protected, 2_674_999),
+        ClarityError::ReplayProtectionNotActive.to_string()
+    );
+    let other_network = test_transaction(0).sign(&key, Some(4));
+    assert_eq!(
+        error(&other_network, 3_000_000),
+        ClarityError::InvalidNetworkId.to_string()
+    );
+
+    // High S values were only accepted before Homestead
+    let mut high_s = test_transaction(0).sign(&key, None);
+    {
+        let sig = high_s.signature.as_mut().unwrap();
+        sig.s = SECPK1N.clone() - sig.s.clone();
+        sig.v = if sig.v == 27u32.into() {
+            28u32.into()
+        } else {
+            27u32.into()
+        };
+    }
+    assert_eq!(high_s.sender_at(&rules, 1_149_999).unwrap(), sender);
+    assert_eq!(
+        error(&high_s, 1_150_000),
+        ClarityError::InvalidS.to_string()
+    );
+
+    let unprotected = test_transaction(0).sign(&key, None);
+    assert_eq!(unprotected.sender_at(&rules, 20_000_000).unwrap(), sender);
+    let strict = SignatureRules {
+        unprotected_rejected_block: Some(10_000_000),
+        ..rules
+    };
+    assert!(unprotected.sender_at(&strict, 9_999_999).is_ok());
+    assert!(unprotected.sender_at(&strict, 10_000_000).is_err());
+}
+
+#[test]
+fn validity_report_signature() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let tx = test_transaction(0).sign(&key, Some(4));
+    assert!(tx.validity_report(Some(4)).is_valid());
+    assert_eq!(
+        tx.validity_report(Some(1)).issues,
+        vec![ValidityIssue::NetworkIdMismatch {
+            expected: 1,
+            found: 4u32.into()
+        }]
+    );
+    let legacy = test_transaction(0).sign(&key, None);
+    assert!(legacy.validity_report(None).is_valid());
+    assert_eq!(
+        legacy.validity_report(Some(1)).issues,
+        vec![ValidityIssue::MissingNetworkId]
+    );
+
+    // The same signature with the other s value
+    let mut high_s = legacy.clone();
+    {
+        let sig = high_s.signature.as_mut().unwrap();
+        sig.s = SECPK1N.clone() - sig.s.clone();
+        sig.v = if sig.v == 27u32.into() {
+            28u32.into()
+        } else {
+            27u32.into()
+        };
+    }
+    assert_eq!(
+        high_s.validity_report(None).issues,
+        vec![ValidityIssue::HighS]
+    );
+
+    let mut bad_v = legacy;
+    bad_v.signature.as_mut().unwrap().v = 30u32.into();
+    assert_eq!(
+        bad_v.validity_report(Some(1)).issues,
+        vec![ValidityIssue::InvalidV]
+    );
+}
+
+#[test]
+fn typed_transaction_envelope() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let legacy = Transaction {
+        nonce: 0u8.into(),
+        gas_price: 1_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 1u32.into(),
+        data: Vec::new(),
+        signature: None,
+    };
+    let eip2930 = Eip2930Transaction {
+        chain_id: 1u8.into(),
+        nonce: 1u8.into(),
+        gas_price: 1_000_000_000u64.into(),
+        gas_limit: 30_000u32.into(),
+        to: Address::default(),
+        value: 0u8.into(),
+        data: vec![0x60, 0x00],
+        access_list: vec![AccessListItem {
+            address: Address::from([0x22u8; 20]),
+            storage_keys: vec![[0x33u8; 32]],
+        }],
+        signature: None,
+    };
+    let txs: Vec<TypedTransaction> = vec![
+        legacy.into(),
+        eip2930.into(),
+        test_eip1559_transaction().into(),
+    ];
+    for (tx, tx_type) in txs.iter().zip(&[0u8, 1, 2]) {
+        assert_eq!(tx.tx_type(), *tx_type);
+        let signed = tx.sign(&key, Some(1)).unwrap();
+        assert_eq!(signed.sender().unwrap(), key.to_public_key().unwrap());
+        let bytes = signed.to_bytes().unwrap();
+        assert_eq!(signed.hash().unwrap(), keccak256(&bytes));
+        let decoded = TypedTransaction::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded, signed);
+        assert_eq!(decoded.tx_type(), *tx_type);
+        // Unsigned transactions round trip too
+        assert_eq!(
+            TypedTransaction::from_bytes(&tx.to_bytes().unwrap()).unwrap(),
+            *tx
+        );
+    }
+
+    assert!(txs[2].sign(&key, Some(5)).is_err());
+    assert!(TypedTransaction::from_bytes(&[]).is_err());
+    assert!(TypedTransaction::from_bytes(&[0x03, 0xc0]).is_err());
+    let mut truncated = txs[1].to_bytes().unwrap();
+    truncated.pop();
+    assert!(TypedTransaction::from_bytes(&truncated).is_err());
+}
+
+#[test]
+fn eip4844_blob_transaction() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let versioned_hash = blob_versioned_hash(&[0xc0u8; 48]);
+    assert_eq!(versioned_hash[0], VERSIONED_HASH_VERSION_KZG);
+    assert_eq!(
+        versioned_hash[1..],
+        <Sha256 as sha2::Digest>::digest(&[0xc0u8; 48][..])[1..]
+    );
+    let tx = Eip4844Transaction {
+        chain_id: 1u8.into(),
+        nonce: 0u8.into(),
+        max_priority_fee_per_gas: 1_000_000_000u64.into(),
+        max_fee_per_gas: 2_000_000_000u64.into(),
+        gas_limit: 21_000u32.into(),
+        to: Address::from([0x11u8; 20]),
+        value: 0u8.into(),
+        data: Vec::new(),
+        access_list: Vec::new(),
+        max_fee_per_blob_gas: 1u8.into(),
+        blob_versioned_hashes: vec![versioned_hash],
+        signature: None,
+    };
+    assert_eq!(tx.blob_gas(), 131_072u32.into());
+    let signed = tx.sign(&key);
+    assert_eq!(signed.sender().unwrap(), key.to_public_key().unwrap());
+    assert!(signed.validity_report(Some(1)).is_valid());
+    let bytes = signed.to_bytes().unwrap();
+    assert_eq!(bytes[0], EIP4844_TX_TYPE);
+    assert_eq!(
+        TypedTransaction::from_bytes(&bytes).unwrap(),
+        TypedTransaction::Eip4844(signed.clone())
+    );
+
+    // The recipient is encoded in full even when it is zero
+    let create = Eip4844Transaction {
+        to: Address::default(),
+        blob_versioned_hashes: vec![[0u8; 32]],
+        ..tx.clone()
+    };
+    assert_eq!(
+        Eip4844Transaction::from_bytes(&create.to_bytes().unwrap()).unwrap(),
+        create
+    );
+    assert_eq!(
+        create.validity_report(None).issues,
+        vec![
+            ValidityIssue::IntrinsicGas {
+                required: 53_000u32.into()
+            },
+            ValidityIssue::BlobContractCreation,
+            ValidityIssue::BlobHashVersion
+        ]
+    );
+    let no_blobs = Eip4844Transaction {
+        blob_versioned_hashes: Vec::new(),
+        ..tx.clone()
+    };
+    assert_eq!(
+        no_blobs.validity_report(None).issues,
+        vec![ValidityIssue::NoBlobs]
+    );
+    // Only the blob fee overflows
+    let blob_fee_overflow = Eip4844Transaction {
+        max_fee_per_blob_gas: Uint256::from_bytes_be(&[0xffu8; 31]),
+        ..tx
+    };
+    assert_eq!(
+        blob_fee_overflow.validity_report(None).issues,
+        vec![ValidityIssue::CostOverflow]
+    );
+}
+
+#[test]
+fn eip7702_authorizations() {
+    use utils::hex_str_to_bytes;
+    let sender: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let authority = PrivateKey::from([0x42u8; 32]);
+    let authorization = Authorization {
+        chain_id: 0u8.into(),
+        address: Address::from([0x77u8; 20]),
+        nonce: 3u8.into(),
+    };
+    let mut payload = vec![SET_CODE_AUTHORIZATION_MAGIC];
+    payload.extend(hex_str_to_bytes("d78094777777777777777777777777777777777777777703").unwrap());
+    assert_eq!(authorization.signing_hash(), keccak256(&payload));
+    let signed_authorization = authorization.sign(&authority);
+    assert_eq!(
+        signed_authorization.authority().unwrap(),
+        authority.to_public_key().unwrap()
+    );
+
+    let tx = Eip7702Transaction {
+        chain_id: 1u8.into(),
+        nonce: 0u8.into(),
+        max_priority_fee_per_gas: 1_000_000_000u64.into(),
+        max_fee_per_gas: 2_000_000_000u64.into(),
+        gas_limit: 50_000u32.into(),
+        to: authority.to_public_key().unwrap(),
+        value: 0u8.into(),
+        data: Vec::new(),
+        access_list: Vec::new(),
+        authorization_list: vec![signed_authorization],
+        signature: None,
+    };
+    assert_eq!(tx.intrinsic_gas_used(), 46_000u32.into());
+    let signed = tx.sign(&sender);
+    assert_eq!(signed.sender().unwrap(), sender.to_public_key().unwrap());
+    assert!(signed.validity_report(Some(1)).is_valid());
+    let decoded = TypedTransaction::from_bytes(&signed.to_bytes().unwrap()).unwrap();
+    assert_eq!(decoded, TypedTransaction::Eip7702(signed.clone()));
+    match decoded {
+        TypedTransaction::Eip7702(ref tx) => assert_eq!(
+            tx.authorization_list[0].authority().unwrap(),
+            authority.to_public_key().unwrap()
+        ),
+        ref other => panic!("unexpected {:?}", other),
+    }
+
+    let empty = Eip7702Transaction {
+        authorization_list: Vec::new(),
+        ..tx
+    };
+    assert_eq!(
+        empty.validity_report(None).issues,
+        vec![ValidityIssue::NoAuthorizations]
+    );
+}
+
+#[test]
+fn typed_transactions_sent() {
+    use utils::{bytes_to_hex_str, hex_str_to_bytes};
+    // Transactions that were included in a chain, with the hash and the
+    // sender it reported for them
+    let vectors = [
+        // Slot 5097936 of mainnet, from the tests of ethers-rs, whose sender
+        // is the one recovered here
+        (
+            EIP2930_TX_TYPE,
+            "01f8ee0182034c853d9f1b88158307a120940087bb802d9c0e343f00510000729031ce00bf2780b8841e1326a300000000000000000000000088e6a0c2ddd26feeb64f039a2c41296fcb3f56400000000000000000000000000000000000000000000000000000001d3b3e730000000000000000000000000000000000000000000000000596b93e53696740000000000000000000000000000000000000000000000000000000000000000001c001a0bbfd754ed51b34d0a8577f69b4c42ce6b47fee6ecf49114bb135e7e8eadbb336a0433692134eb7e7686e9aefafa9f69c601aa977c00cc85c827782f5fb1f1cff0f",
+            "6d38fc8aee934858815ed41273cece3b676c368e9c6e39f172313a0685e1f175",
+            "2360f8fedf7d0e2a121fae5bd83e8ff55e8e9745",
+        ),
+        // With an access list, from the tests of ethers-rs, whose hash is the
+        // one computed here
+        (
+            EIP2930_TX_TYPE,
+            "01f90126018223ff850a02ffee00830f4240940000000000a8fb09af944ab3baf7a9b3e1ab29d880b876200200001525000000000b69ffb300000000557b933a7c2c45672b610f8954a3deb39a51a8cae53ec727dbdeb9e2d5456c3be40cff031ab40a55724d5c9c618a2152e99a45649a3b8cf198321f46720b722f4ec38f99ba3bb1303258d2e816e6a95b25647e01bd0967c1b9599fa3521939871d1d0888f845d694724d5c9c618a2152e99a45649a3b8cf198321f46c0d694720b722f4ec38f99ba3bb1303258d2e816e6a95bc0d69425647e01bd0967c1b9599fa3521939871d1d0888c001a08323efae7b9993bd31a58da7924359d24b5504aa2b33194fcc5ae206e65d2e62a054ce201e3b4b5cd38eb17c56ee2f9111b2e164efcd57b3e70fa308a0a51f7014",
+            "26cb56eafde9500db3fc4dda805f80c765afda70917aa9ab7b756a8c1832ff37",
+            "e9c790e8fde820ded558a4771b72eec916c04763",
+        ),
+        // https://etherscan.io/tx/0xce4dc6d7a7549a98ee3b071b67e970879ff51b5b95d1c340bacd80fa1e1aab31
+        (
+            EIP1559_TX_TYPE,
+            "02f86f0102843b9aca0085029e7822d68298f094d9e1459a7a482635700cbc20bbaf52d495ab9c9680841b55ba3ac080a0c199674fcb29f353693dd779c017823b954b3c69dffa3cd6b2a6ff7888798039a028ca912de909e7e6cdef9cdcaf24c54dd8c1032946dfa1d85c206b32a9064fe8",
+            "ce4dc6d7a7549a98ee3b071b67e970879ff51b5b95d1c340bacd80fa1e1aab31",
+            "001e2b7de757ba469a57bf6b23d982458a07efce",
+        ),
+        // https://sepolia.etherscan.io/tx/0x9a22ccb0029bc8b0ddd073be1a1d923b7ae2b2ea52100bae0db4424f9107e9c0
+        (
+            EIP4844_TX_TYPE,
+            "03f9011d83aa36a7820fa28477359400852e90edd0008252089411e9ca82a3a762b4b5bd264d4173a242e7a770648080c08504a817c800f8a5a0012ec3d6f66766bedb002a190126b3549fce0047de0d4c25cffce0dc1c57921aa00152d8e24762ff22b1cfd9f8c0683786a7ca63ba49973818b3d1e9512cd2cec4a0013b98c6c83e066d5b14af2b85199e3d4fc7d1e778dd53130d180f5077e2d1c7a001148b495d6e859114e670ca54fb6e2657f0cbae5b08063605093a4b3dc9f8f1a0011ac212f13c5dff2b2c6b600a79635103d6f580a4221079951181b25c7e654901a0c8de4cced43169f9aa3d36506363b2d2c44f6c49fc1fd91ea114c86f3757077ea01e11fdd0d1934eda0492606ee0bb80a7bf8f35cc5f86ec60fe5031ba48bfd544",
+            "9a22ccb0029bc8b0ddd073be1a1d923b7ae2b2ea52100bae0db4424f9107e9c0",
+            "a83c816d4f9b2783761a22ba6fadb0eb0606d7b2",
+        ),
+        // https://github.com/alloy-rs/alloy/issues/1643, encoded from the
+        // fields that the node returned for it
+        (
+            EIP7702_TX_TYPE,
+            "04f8d28501a5ee289c1a840e078998840e0789a082f8ac946d2d4e1c2326a069f36f5d6337470dc26adb71568080c0f861f85f8501a5ee289c94529f773125642b12a44bd543005650989eceaa2a1a80a09b3de20cf8bd07f3c5c55c38c920c146f081bc5ab4580d0c87786b256cdab3c2a0074841956f4832bace3c02aed34b8f0a2812450da3728752edbb5b5e1da0449701a0b3bf7d6877864913bba04d6f93d98009a5af16ee9c12295cd634962a2346b67ca031ca4a874afa964ec7643e58c6b56b35b1bcc7698eb1b5e15e61e78b353bd42d",
+            "adc3f24d05f05f1065debccb1c4b033eaa35917b69b343d88d9062cdf8ecad83",
+            "6d2d4e1c2326a069f36f5d6337470dc26adb7156",
+        ),
+    ];
+    for &(tx_type, raw, hash, sender) in vectors.iter() {
+        let bytes = hex_str_to_bytes(raw).unwrap();
+        let tx = TypedTransaction::from_bytes(&bytes).unwrap();
+        assert_eq!(tx.tx_type(), tx_type);
+        assert_eq!(tx.to_bytes().unwrap(), bytes);
+        assert_eq!(bytes_to_hex_str(&tx.hash().unwrap()), hash);
+        assert_eq!(bytes_to_hex_str(tx.sender().unwrap().as_bytes()), sender);
+    }
+}
+
+#[test]
+fn build_transaction() {
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let builder = TransactionBuilder::new()
+        .nonce(Nonce(7))
+        .gas_price(1_000_000_000u64.into())
+        .to(Address::from([0x11u8; 20]))
+        .value(1u32.into());
+    assert_eq!(builder.max_cost(), 21_000_000_000_001u64.into());
+    let tx = builder.build();
+    assert_eq!(tx.nonce, 7u8.into());
+    assert_eq!(tx.gas_limit, 21_000u32.into());
+    assert!(tx.sign(&key, Some(1)).validity_report(Some(1)).is_valid());
+
+    let next = Nonce::try_from(&tx.nonce).unwrap().next().unwrap();
+    let call = TransactionBuilder::new()
+        .nonce(next)
+        .gas_limit(Gas(21_000).checked_add(Gas(30_000)).unwrap())
+        .data(vec![0xd0, 0x9d, 0xe0, 0x8a])
+        .build();
+    assert_eq!(call.nonce, 8u8.into());
+    assert_eq!(call.gas_limit, 51_000u32.into());
+    assert_eq!(call.to, Address::default());
 }
@@ -0,0 +1,398 @@
+//! Consistency checks of a batch of signed transactions before broadcast.
+//!
+//! A batch of payouts is signed in one go and broadcast in one go, and a
+//! mistake in it, such as a skipped nonce or a transaction for the wrong
+//! chain, leaves everything after it stuck or lost. [audit](fn.audit.html)
+//! looks at the batch as a whole and reports what would go wrong:
+//!
+//! * transactions that are unsigned, or signed by another sender,
+//! * nonces missing between the expected first one and the last one,
+//! * several different transactions with the same nonce, of which at most
+//!   one can be included,
+//! * fees that go down as nonces go up, as the later transactions can not
+//!   be included before the earlier ones anyway,
+//! * chain ids that differ from the expected one or from each other.
+use address::Address;
+use num256::Uint256;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use transaction::TypedTransaction;
+
+/// What the batch should be, each check being skipped when `None`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Expectations {
+    /// The sender of every transaction. Without one the sender of the first
+    /// signed transaction is expected.
+    pub sender: Option<Address>,
+    /// The current nonce of the sender, which the batch should start with
+    pub next_nonce: Option<Uint256>,
+    /// The chain every transaction should be for. Without one they should
+    /// all be for the same chain.
+    pub chain_id: Option<Uint256>,
+}
+
+/// A problem with a batch. Transactions are referred to by their index in
+/// the batch.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditIssue {
+    /// The transaction is not signed, or its sender can not be recovered
+    Unsigned {
+        index: usize,
+    },
+    WrongSender {
+        index: usize,
+        sender: Address,
+    },
+    /// Nonces from `from` to `to`, inclusive, are missing
+    NonceGap {
+        from: Uint256,
+        to: Uint256,
+    },
+    /// Different transactions share a nonce
+    ConflictingNonce {
+        nonce: Uint256,
+        indices: Vec<usize>,
+    },
+    /// The same transaction is in the batch more than once
+    Duplicate {
+        indices: Vec<usize>,
+    },
+    /// The transaction pays less per gas than `previous`, which has a
+    /// lower nonce
+    FeeRegression {
+        index: usize,
+        fee: Uint256,
+        previous: usize,
+        previous_fee: Uint256,
+    },
+    /// The transaction is for another chain than expected, or than the
+    /// first transaction, or for none at all
+    ChainIdMismatch {
+        index: usize,
+        expected: Uint256,
+        found: Option<Uint256>,
+    },
+}
+
+impl fmt::Display for AuditIssue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AuditIssue::Unsigned { index } => write!(f, "Transaction {} is not signed", index),
+            AuditIssue::WrongSender { index, sender } => {
+                write!(f, "Transaction {} is signed by {}", index, sender)
+            }
+            AuditIssue::NonceGap { ref from, ref to } if from == to => {
+                write!(f, "Nonce {} is missing", from)
+            }
+            AuditIssue::NonceGap { ref from, ref to } => {
+                write!(f, "Nonces {} to {} are missing", from, to)
+            }
+            AuditIssue::ConflictingNonce {
+                ref nonce,
+                ref indices,
+            } => write!(
+                f,
+                "Transactions {:?} conflict, all having nonce {}",
+                indices, nonce
+            ),
+            AuditIssue::Duplicate { ref indices } => {
+                write!(f, "Transactions {:?} are the same", indices)
+            }
+            AuditIssue::FeeRegression {
+                index,
+                ref fee,
+                previous,
+                ref previous_fee,
+            } => write!(
+                f,
+                "Transaction {} pays {} per gas, less than the {} of transaction {} before it",
+                index, fee, previous_fee, previous
+            ),
+            AuditIssue::ChainIdMismatch {
+                index,
+                ref expected,
+                found: Some(ref found),
+            } => write!(
+                f,
+                "Transaction {} is for chain {} instead of {}",
+                index, found, expected
+            ),
+            AuditIssue::ChainIdMismatch {
+                index,
+                ref expected,
+                found: None,
+            } => write!(
+                f,
+                "Transaction {} is not replay protected, expected chain {}",
+                index, expected
+            ),
+        }
+    }
+}
+
+/// The result of an [audit](fn.audit.html).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AuditReport {
+    /// The sender of the batch, if any transaction recovers
+    pub sender: Option<Address>,
+    /// The nonce after the last one of the batch
+    pub next_nonce: Option<Uint256>,
+    pub issues: Vec<AuditIssue>,
+}
+
+impl AuditReport {
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+/// Checks a batch of signed transactions of one sender, see the
+/// [module](index.html).
+pub fn audit(txs: &[TypedTransaction], expected: &Expectations) -> AuditReport {
+    let mut issues = Vec::new();
+
+    let mut sender = expected.sender;
+    for (index, tx) in txs.iter().enumerate() {
+        match tx.signature().and(tx.sender().ok()) {
+            None => issues.push(AuditIssue::Unsigned { index }),
+            Some(found) => match sender {
+                None => sender = Some(found),
+                Some(sender) if sender != found => issues.push(AuditIssue::WrongSender {
+                    index,
+                    sender: found,
+                }),
+                Some(_) => {}
+            },
+        }
+    }
+
+    let mut chain_id = expected.chain_id.clone();
+    for (index, tx) in txs.iter().enumerate() {
+        let found = tx.chain_id();
+        match chain_id {
+            None => chain_id = found,
+            Some(ref expected) if found.as_ref() != Some(expected) => {
+                issues.push(AuditIssue::ChainIdMismatch {
+                    index,
+                    expected: expected.clone(),
+                    found,
+                })
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut by_nonce: BTreeMap<&Uint256, Vec<usize>> = BTreeMap::new();
+    for (index, tx) in txs.iter().enumerate() {
+        by_nonce.entry(tx.nonce()).or_default().push(index);
+    }
+    for (nonce, indices) in &by_nonce {
+        if indices.len() < 2 {
+            continue;
+        }
+        // Transactions with the same hash are the same transaction
+        let mut distinct: Vec<Vec<usize>> = Vec::new();
+        for &index in indices {
+            let hash = txs[index].hash().ok();
+            match distinct
+                .iter_mut()
+                .find(|group| hash.is_some() && txs[group[0]].hash().ok() == hash)
+            {
+                Some(group) => group.push(index),
+                None => distinct.push(vec![index]),
+            }
+        }
+        for group in distinct.iter().filter(|group| group.len() > 1) {
+            issues.push(AuditIssue::Duplicate {
+                indices: group.clone(),
+            });
+        }
+        if distinct.len() > 1 {
+            issues.push(AuditIssue::ConflictingNonce {
+                nonce: (*nonce).clone(),
+                indices: distinct.iter().map(|group| group[0]).collect(),
+            });
+        }
+    }
+
+    let mut next_nonce = expected.next_nonce.clone();
+    for &nonce in by_nonce.keys() {
+        if let Some(next) = next_nonce {
+            if *nonce > next {
+                issues.push(AuditIssue::NonceGap {
+                    from: next,
+                    to: nonce.clone() - 1u8.into(),
+                });
+            }
+        }
+        next_nonce = Some(nonce.clone() + 1u8.into());
+    }
+
+    // The cheapest transaction of each nonce is compared with the most
+    // expensive one of the nonces before it
+    let mut highest: Option<usize> = None;
+    for indices in by_nonce.values() {
+        let cheapest = *indices
+            .iter()
+            .min_by_key(|&&index| txs[index].max_fee_per_gas())
+            .unwrap();
+        if let Some(previous) = highest {
+            let fee = txs[cheapest].max_fee_per_gas();
+            let previous_fee = txs[previous].max_fee_per_gas();
+            if fee < previous_fee {
+                issues.push(AuditIssue::FeeRegression {
+                    index: cheapest,
+                    fee: fee.clone(),
+                    previous,
+                    previous_fee: previous_fee.clone(),
+                });
+            }
+        }
+        let expensive = *indices
+            .iter()
+            .min_by_key(|&&index| Reverse(txs[index].max_fee_per_gas()))
+            .unwrap();
+        if highest.is_none_or(|h| txs[expensive].max_fee_per_gas() > txs[h].max_fee_per_gas()) {
+            highest = Some(expensive);
+        }
+    }
+
+    AuditReport {
+        sender,
+        next_nonce,
+        issues,
+    }
+}
+
+#[cfg(test)]
+fn payout(nonce: u64, gas_price: u64, value: u64, chain_id: Option<u64>) -> TypedTransaction {
+    use private_key::PrivateKey;
+    use transaction::Transaction;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    TypedTransaction::Legacy(
+        Transaction {
+            nonce: nonce.into(),
+            gas_price: gas_price.into(),
+            gas_limit: 21_000u32.into(),
+            to: Address::from([0x11u8; 20]),
+            value: value.into(),
+            data: Vec::new(),
+            signature: None,
+        }
+        .sign(&key, chain_id),
+    )
+}
+
+#[test]
+fn clean_batch() {
+    let batch: Vec<TypedTransaction> = (5..8).map(|n| payout(n, 10, n, Some(1))).collect();
+    let report = audit(
+        &batch,
+        &Expectations {
+            next_nonce: Some(5u8.into()),
+            chain_id: Some(1u8.into()),
+            ..Expectations::default()
+        },
+    );
+    assert!(report.is_clean(), "{:?}", report.issues);
+    assert_eq!(report.sender, batch[0].sender().ok());
+    assert_eq!(report.next_nonce, Some(8u8.into()));
+}
+
+#[test]
+fn inconsistent_batch() {
+    let batch = vec![
+        payout(5, 10, 1, Some(1)),
+        payout(7, 10, 2, Some(1)),
+        // A conflicting replacement of the first, and a copy of it
+        payout(5, 12, 3, Some(1)),
+        payout(5, 12, 3, Some(1)),
+        payout(8, 9, 4, Some(1)),
+        payout(9, 10, 5, Some(5)),
+        payout(10, 10, 6, None),
+    ];
+    let report = audit(
+        &batch,
+        &Expectations {
+            next_nonce: Some(3u8.into()),
+            ..Expectations::default()
+        },
+    );
+    assert_eq!(
+        report.issues,
+        vec![
+            AuditIssue::ChainIdMismatch {
+                index: 5,
+                expected: 1u8.into(),
+                found: Some(5u8.into()),
+            },
+            AuditIssue::ChainIdMismatch {
+                index: 6,
+                expected: 1u8.into(),
+                found: None,
+            },
+            AuditIssue::Duplicate {
+                indices: vec![2, 3]
+            },
+            AuditIssue::ConflictingNonce {
+                nonce: 5u8.into(),
+                indices: vec![0, 2],
+            },
+            AuditIssue::NonceGap {
+                from: 3u8.into(),
+                to: 4u8.into(),
+            },
+            AuditIssue::NonceGap {
+                from: 6u8.into(),
+                to: 6u8.into(),
+            },
+            AuditIssue::FeeRegression {
+                index: 1,
+                fee: 10u8.into(),
+                previous: 2,
+                previous_fee: 12u8.into(),
+            },
+            AuditIssue::FeeRegression {
+                index: 4,
+                fee: 9u8.into(),
+                previous: 2,
+                previous_fee: 12u8.into(),
+            },
+            AuditIssue::FeeRegression {
+                index: 5,
+                fee: 10u8.into(),
+                previous: 2,
+                previous_fee: 12u8.into(),
+            },
+            AuditIssue::FeeRegression {
+                index: 6,
+                fee: 10u8.into(),
+                previous: 2,
+                previous_fee: 12u8.into(),
+            },
+        ]
+    );
+    assert_eq!(report.issues[5].to_string(), "Nonce 6 is missing");
+    assert_eq!(
+        report.issues[1].to_string(),
+        "Transaction 6 is not replay protected, expected chain 1"
+    );
+
+    let other = audit(
+        &batch[..1],
+        &Expectations {
+            sender: Some(Address::from([0x22u8; 20])),
+            ..Expectations::default()
+        },
+    );
+    assert_eq!(
+        other.issues,
+        vec![AuditIssue::WrongSender {
+            index: 0,
+            sender: batch[0].sender().unwrap(),
+        }]
+    );
+}
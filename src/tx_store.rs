@@ -0,0 +1,327 @@
+//! A compact in-memory store of raw transactions.
+//!
+//! Decoded transactions keep every field as a `Uint256` on the heap, which
+//! adds up to hundreds of bytes per transaction. [TxStore](struct.TxStore.html)
+//! keeps only the canonical raw bytes, all in one buffer, and a fixed size
+//! entry for each transaction with its hash, sender and nonce. Transactions
+//! are found by hash, or by sender and nonce, and decoded when they are
+//! needed.
+use address::Address;
+use failure::Error;
+use std::collections::{BTreeSet, HashMap};
+use std::convert::TryFrom;
+use transaction::TypedTransaction;
+use types::Nonce;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum TxStoreError {
+    #[fail(display = "Transaction is not signed")]
+    Unsigned,
+    #[fail(display = "Transaction is larger than 4 GiB")]
+    TooLarge,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    hash: [u8; 32],
+    offset: u64,
+    len: u32,
+    sender: u32,
+    nonce: u64,
+    removed: bool,
+}
+
+/// A stored transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StoredTx<'a> {
+    pub hash: [u8; 32],
+    pub sender: Address,
+    pub nonce: Nonce,
+    /// The canonical encoding, which [TypedTransaction::from_bytes](../transaction/enum.TypedTransaction.html#method.from_bytes) decodes
+    pub raw: &'a [u8],
+}
+
+/// Raw transactions by hash, indexed by sender and nonce.
+///
+/// Removed transactions leave their bytes behind until
+/// [compact](#method.compact) is called.
+#[derive(Debug, Clone, Default)]
+pub struct TxStore {
+    data: Vec<u8>,
+    entries: Vec<Entry>,
+    by_hash: HashMap<[u8; 32], u32>,
+    senders: Vec<Address>,
+    sender_ids: HashMap<Address, u32>,
+    /// (sender, nonce, entry), so every sender's transactions are together
+    /// in nonce order, replacements included
+    by_sender: BTreeSet<(u32, u64, u32)>,
+    removed_bytes: u64,
+}
+
+impl TxStore {
+    pub fn new() -> TxStore {
+        TxStore::default()
+    }
+
+    /// A store with room for `transactions` transactions of `bytes` bytes
+    /// in total.
+    pub fn with_capacity(transactions: usize, bytes: usize) -> TxStore {
+        TxStore {
+            data: Vec::with_capacity(bytes),
+            entries: Vec::with_capacity(transactions),
+            by_hash: HashMap::with_capacity(transactions),
+            ..TxStore::default()
+        }
+    }
+
+    /// Decodes a signed raw transaction of any type, recovers its sender and
+    /// stores it. Returns its hash, for a transaction that is already
+    /// stored as well.
+    pub fn insert(&mut self, raw: &[u8]) -> Result<[u8; 32], Error> {
+        let tx = TypedTransaction::from_bytes(raw)?;
+        if tx.signature().is_none() {
+            return Err(TxStoreError::Unsigned.into());
+        }
+        let hash = tx.hash()?;
+        if self.by_hash.contains_key(&hash) {
+            return Ok(hash);
+        }
+        let nonce = Nonce::try_from(tx.nonce())?;
+        let sender = tx.sender()?;
+        self.insert_recovered(hash, sender, nonce, raw)?;
+        Ok(hash)
+    }
+
+    /// Stores a transaction whose hash, sender and nonce are already known,
+    /// such as one read back from a trusted source, without decoding it.
+    pub fn insert_recovered(
+        &mut self,
+        hash: [u8; 32],
+        sender: Address,
+        nonce: Nonce,
+        raw: &[u8],
+    ) -> Result<(), Error> {
+        if self.by_hash.contains_key(&hash) {
+            return Ok(());
+        }
+        let len = u32::try_from(raw.len()).map_err(|_| TxStoreError::TooLarge)?;
+        let sender = match self.sender_ids.get(&sender) {
+            Some(id) => *id,
+            None => {
+                let id = self.senders.len() as u32;
+                self.senders.push(sender);
+                self.sender_ids.insert(sender, id);
+                id
+            }
+        };
+        let index = self.entries.len() as u32;
+        self.entries.push(Entry {
+            hash,
+            offset: self.data.len() as u64,
+            len,
+            sender,
+            nonce: nonce.0,
+            removed: false,
+        });
+        self.data.extend_from_slice(raw);
+        self.by_hash.insert(hash, index);
+        self.by_sender.insert((sender, nonce.0, index));
+        Ok(())
+    }
+
+    fn stored(&self, index: u32) -> StoredTx<'_> {
+        let entry = &self.entries[index as usize];
+        let start = entry.offset as usize;
+        StoredTx {
+            hash: entry.hash,
+            sender: self.senders[entry.sender as usize],
+            nonce: Nonce(entry.nonce),
+            raw: &self.data[start..start + entry.len as usize],
+        }
+    }
+
+    pub fn get(&self, hash: &[u8; 32]) -> Option<StoredTx<'_>> {
+        self.by_hash.get(hash).map(|index| self.stored(*index))
+    }
+
+    pub fn contains(&self, hash: &[u8; 32]) -> bool {
+        self.by_hash.contains_key(hash)
+    }
+
+    /// The transactions of `sender` in nonce order. Transactions with the
+    /// same nonce, which replace each other, are in the order they were
+    /// stored.
+    pub fn by_sender<'a>(&'a self, sender: &Address) -> impl Iterator<Item = StoredTx<'a>> + 'a {
+        let range = self.sender_ids.get(sender).map(|id| {
+            self.by_sender
+                .range((*id, 0, 0)..=(*id, u64::MAX, u32::MAX))
+        });
+        range
+            .into_iter()
+            .flatten()
+            .map(move |(_, _, index)| self.stored(*index))
+    }
+
+    /// The transactions of `sender` with `nonce`.
+    pub fn by_sender_nonce<'a>(
+        &'a self,
+        sender: &Address,
+        nonce: Nonce,
+    ) -> impl Iterator<Item = StoredTx<'a>> + 'a {
+        let range = self.sender_ids.get(sender).map(|id| {
+            self.by_sender
+                .range((*id, nonce.0, 0)..=(*id, nonce.0, u32::MAX))
+        });
+        range
+            .into_iter()
+            .flatten()
+            .map(move |(_, _, index)| self.stored(*index))
+    }
+
+    /// Removes a transaction, returning whether it was stored.
+    pub fn remove(&mut self, hash: &[u8; 32]) -> bool {
+        let index = match self.by_hash.remove(hash) {
+            Some(index) => index,
+            None => return false,
+        };
+        let entry = &mut self.entries[index as usize];
+        entry.removed = true;
+        self.by_sender.remove(&(entry.sender, entry.nonce, index));
+        self.removed_bytes += u64::from(entry.len);
+        true
+    }
+
+    /// Number of stored transactions.
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    /// Bytes of raw transactions that were removed but are still kept.
+    pub fn removed_bytes(&self) -> u64 {
+        self.removed_bytes
+    }
+
+    /// Drops the bytes and entries of removed transactions.
+    pub fn compact(&mut self) {
+        let mut compacted =
+            TxStore::with_capacity(self.len(), self.data.len() - self.removed_bytes as usize);
+        for (index, entry) in self.entries.iter().enumerate() {
+            if !entry.removed {
+                let stored = self.stored(index as u32);
+                compacted
+                    .insert_recovered(stored.hash, stored.sender, stored.nonce, stored.raw)
+                    .expect("stored transactions fit");
+            }
+        }
+        *self = compacted;
+    }
+}
+
+#[cfg(test)]
+fn test_transactions() -> Vec<Vec<u8>> {
+    use private_key::PrivateKey;
+    use transaction::Transaction;
+    let keys = [
+        PrivateKey::from([0x42u8; 32]),
+        PrivateKey::from([0x43u8; 32]),
+    ];
+    let mut raw = Vec::new();
+    for key in &keys {
+        for nonce in 0..3u32 {
+            let tx = Transaction {
+                nonce: nonce.into(),
+                gas_price: 1_000_000_000u64.into(),
+                gas_limit: 21_000u32.into(),
+                to: Address::from([0x11u8; 20]),
+                value: 1u32.into(),
+                data: Vec::new(),
+                signature: None,
+            };
+            raw.push(tx.sign(key, Some(1)).to_bytes().unwrap());
+        }
+    }
+    raw
+}
+
+#[test]
+fn store_and_find() {
+    use private_key::PrivateKey;
+    let raw = test_transactions();
+    let mut store = TxStore::new();
+    let hashes: Vec<[u8; 32]> = raw.iter().map(|tx| store.insert(tx).unwrap()).collect();
+    assert_eq!(store.len(), 6);
+    // Inserting again changes nothing
+    assert_eq!(store.insert(&raw[0]).unwrap(), hashes[0]);
+    assert_eq!(store.len(), 6);
+
+    let sender = PrivateKey::from([0x43u8; 32]).to_public_key().unwrap();
+    let stored = store.get(&hashes[4]).unwrap();
+    assert_eq!(stored.sender, sender);
+    assert_eq!(stored.nonce, Nonce(1));
+    assert_eq!(stored.raw, &raw[4][..]);
+    assert_eq!(
+        TypedTransaction::from_bytes(stored.raw)
+            .unwrap()
+            .hash()
+            .unwrap(),
+        hashes[4]
+    );
+    assert_eq!(
+        store
+            .by_sender(&sender)
+            .map(|tx| tx.nonce)
+            .collect::<Vec<_>>(),
+        vec![Nonce(0), Nonce(1), Nonce(2)]
+    );
+    assert_eq!(store.by_sender_nonce(&sender, Nonce(2)).count(), 1);
+    assert_eq!(store.by_sender(&Address::from([0x99u8; 20])).count(), 0);
+
+    let mut unsigned = raw[0].clone();
+    let tx = TypedTransaction::from_bytes(&unsigned).unwrap();
+    if let TypedTransaction::Legacy(mut legacy) = tx {
+        legacy.signature = None;
+        unsigned = legacy.to_bytes().unwrap();
+    }
+    assert_eq!(
+        store
+            .insert(&unsigned)
+            .unwrap_err()
+            .downcast::<TxStoreError>()
+            .unwrap(),
+        TxStoreError::Unsigned
+    );
+}
+
+#[test]
+fn remove_and_compact() {
+    let raw = test_transactions();
+    let mut store = TxStore::new();
+    let hashes: Vec<[u8; 32]> = raw.iter().map(|tx| store.insert(tx).unwrap()).collect();
+    let sender = store.get(&hashes[0]).unwrap().sender;
+
+    assert!(store.remove(&hashes[1]));
+    assert!(!store.remove(&hashes[1]));
+    assert!(store.get(&hashes[1]).is_none());
+    assert_eq!(store.removed_bytes(), raw[1].len() as u64);
+    assert_eq!(
+        store
+            .by_sender(&sender)
+            .map(|tx| tx.nonce)
+            .collect::<Vec<_>>(),
+        vec![Nonce(0), Nonce(2)]
+    );
+
+    store.compact();
+    assert_eq!(store.removed_bytes(), 0);
+    assert_eq!(store.len(), 5);
+    for (hash, raw) in hashes.iter().zip(&raw) {
+        if *hash != hashes[1] {
+            assert_eq!(store.get(hash).unwrap().raw, &raw[..]);
+        }
+    }
+    assert_eq!(store.by_sender(&sender).count(), 2);
+}
@@ -1,5 +1,9 @@
+use failure::Error;
 use num256::Uint256;
-use utils::{big_endian_uint256_deserialize, big_endian_uint256_serialize};
+use num_traits::ToPrimitive;
+use std::convert::TryFrom;
+use std::fmt;
+use utils::{big_endian_uint256_deserialize, big_endian_uint256_serialize, HexFmt};
 
 /// A thin wrapper type to change the way Uint256 is serialized.
 ///
@@ -14,6 +18,164 @@ pub struct BigEndianInt(
     pub Uint256,
 );
 
+#[derive(Fail, Debug, PartialEq)]
+pub enum RangeError {
+    #[fail(display = "{} does not fit 64 bits", _0)]
+    TooLarge(&'static str),
+}
+
+/// An account nonce.
+///
+/// Nonces fit 64 bits, and can not reach 2^64 - 1 (EIP-2681), so they are
+/// kept as `u64` and only turned into a `Uint256` when a transaction is
+/// encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Nonce(pub u64);
+
+impl Nonce {
+    /// The nonce after this one, unless it would be out of range.
+    pub fn next(self) -> Option<Nonce> {
+        self.0
+            .checked_add(1)
+            .filter(|next| *next != u64::MAX)
+            .map(Nonce)
+    }
+
+    /// The nonce `count` after this one, unless it would be out of range.
+    pub fn checked_add(self, count: u64) -> Option<Nonce> {
+        self.0
+            .checked_add(count)
+            .filter(|nonce| *nonce != u64::MAX)
+            .map(Nonce)
+    }
+}
+
+/// An amount of gas.
+///
+/// Gas limits are capped at 2^63 - 1 (EIP-1985) and used gas is below the
+/// limit, so both fit a `u64`, which keeps them apart from wei amounts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Gas(pub u64);
+
+impl Gas {
+    pub fn checked_add(self, other: Gas) -> Option<Gas> {
+        self.0.checked_add(other.0).map(Gas)
+    }
+
+    pub fn checked_sub(self, other: Gas) -> Option<Gas> {
+        self.0.checked_sub(other.0).map(Gas)
+    }
+
+    /// The gas of `count` times this.
+    pub fn checked_mul(self, count: u64) -> Option<Gas> {
+        self.0.checked_mul(count).map(Gas)
+    }
+
+    /// What this gas costs at `price` wei per gas, which always fits 256
+    /// bits for a price that does.
+    pub fn cost(self, price: &Uint256) -> Uint256 {
+        Uint256::from(self.0) * price.clone()
+    }
+}
+
+macro_rules! u64_newtype_conversions {
+    ($name:ident, $label:expr) => {
+        impl From<u64> for $name {
+            fn from(value: u64) -> $name {
+                $name(value)
+            }
+        }
+
+        impl From<$name> for Uint256 {
+            fn from(value: $name) -> Uint256 {
+                value.0.into()
+            }
+        }
+
+        impl<'a> TryFrom<&'a Uint256> for $name {
+            type Error = Error;
+
+            fn try_from(value: &'a Uint256) -> Result<$name, Error> {
+                Ok($name(value.to_u64().ok_or(RangeError::TooLarge($label))?))
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+    };
+}
+
+u64_newtype_conversions!(Nonce, "Nonce");
+u64_newtype_conversions!(Gas, "Gas");
+
+/// A slice that should have been a fixed number of bytes.
+#[derive(Fail, Debug, PartialEq)]
+#[fail(
+    display = "{} should be {} bytes but {} were found",
+    what, expected, found
+)]
+pub struct LengthError {
+    pub what: &'static str,
+    pub expected: usize,
+    pub found: usize,
+}
+
+/// Copies `bytes` into an array, if it has exactly `N` of them.
+pub(crate) fn fixed_bytes<const N: usize>(
+    what: &'static str,
+    bytes: &[u8],
+) -> Result<[u8; N], LengthError> {
+    <[u8; N]>::try_from(bytes).map_err(|_| LengthError {
+        what,
+        expected: N,
+        found: bytes.len(),
+    })
+}
+
+/// The hash of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct TxHash(pub [u8; 32]);
+
+/// A storage slot of a contract, as in access lists.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct StorageKey(pub [u8; 32]);
+
+macro_rules! bytes32_newtype_conversions {
+    ($name:ident, $label:expr) => {
+        impl From<[u8; 32]> for $name {
+            fn from(value: [u8; 32]) -> $name {
+                $name(value)
+            }
+        }
+
+        impl From<$name> for [u8; 32] {
+            fn from(value: $name) -> [u8; 32] {
+                value.0
+            }
+        }
+
+        impl<'a> TryFrom<&'a [u8]> for $name {
+            type Error = LengthError;
+
+            fn try_from(bytes: &'a [u8]) -> Result<$name, LengthError> {
+                fixed_bytes($label, bytes).map($name)
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{:#x}", HexFmt(&self.0))
+            }
+        }
+    };
+}
+
+bytes32_newtype_conversions!(TxHash, "Transaction hash");
+bytes32_newtype_conversions!(StorageKey, "Storage key");
+
 #[test]
 fn serialize() {
     use serde_rlp::ser::to_bytes;
@@ -29,3 +191,48 @@ fn serialize() {
         ]
     );
 }
+
+#[test]
+fn nonce_and_gas() {
+    assert_eq!(Nonce(5).next(), Some(Nonce(6)));
+    assert_eq!(Nonce(u64::MAX - 2).next(), Some(Nonce(u64::MAX - 1)));
+    assert_eq!(Nonce(u64::MAX - 1).next(), None);
+    assert_eq!(Nonce(1).checked_add(u64::MAX - 1), None);
+    assert_eq!(Uint256::from(Nonce(7)), 7u8.into());
+    assert_eq!(Nonce::try_from(&Uint256::from(7u8)).unwrap(), Nonce(7));
+    assert_eq!(
+        Nonce::try_from(&Uint256::from(u128::MAX))
+            .unwrap_err()
+            .downcast::<RangeError>()
+            .unwrap(),
+        RangeError::TooLarge("Nonce")
+    );
+
+    assert_eq!(Gas(21_000).checked_add(Gas(4)), Some(Gas(21_004)));
+    assert_eq!(Gas(1).checked_sub(Gas(2)), None);
+    assert_eq!(Gas(u64::MAX).checked_mul(2), None);
+    assert_eq!(
+        Gas(21_000).cost(&1_000_000_000u64.into()),
+        21_000_000_000_000u64.into()
+    );
+    assert_eq!(Gas(21_000).to_string(), "21000");
+}
+
+#[test]
+fn length_checked_conversions() {
+    let hash = TxHash::try_from(&[0xabu8; 32][..]).unwrap();
+    assert_eq!(<[u8; 32]>::from(hash), [0xab; 32]);
+    assert_eq!(hash.to_string(), format!("0x{}", "ab".repeat(32)));
+    assert_eq!(
+        StorageKey::try_from(&[0u8; 31][..]).unwrap_err(),
+        LengthError {
+            what: "Storage key",
+            expected: 32,
+            found: 31,
+        }
+    );
+    assert_eq!(
+        TxHash::try_from(&[0u8; 33][..]).unwrap_err().to_string(),
+        "Transaction hash should be 32 bytes but 33 were found"
+    );
+}
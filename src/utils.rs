@@ -1,9 +1,12 @@
 use failure::Error;
 use num256::Uint256;
-use serde::{
+use num_traits::ToPrimitive;
+use serde_bytes::ByteBuf;
+use serde_crate::{
     de::{Deserialize, Deserializer},
     ser::Serializer,
 };
+use std::fmt;
 use std::num::ParseIntError;
 use std::str;
 
@@ -13,41 +16,104 @@ pub enum ByteDecodeError {
     DecodeError(str::Utf8Error),
     #[fail(display = "{}", _0)]
     ParseError(ParseIntError),
+    #[fail(display = "Invalid hexadecimal character {:?}", _0)]
+    InvalidCharacter(char),
+}
+
+/// Strips an optional `0x` (or `0X`) prefix from a hexadecimal string.
+///
+/// Every hexadecimal parser in Clarity goes through this, so all of them
+/// accept the same inputs.
+pub fn strip_hex_prefix(s: &str) -> &str {
+    s.strip_prefix("0x")
+        .or_else(|| s.strip_prefix("0X"))
+        .unwrap_or(s)
 }
 
 /// A function that takes a hexadecimal representation of bytes
 /// back into a stream of bytes.
 pub fn hex_str_to_bytes(s: &str) -> Result<Vec<u8>, Error> {
-    let s = if s.starts_with("0x") { &s[2..] } else { s };
-    s.as_bytes()
+    strip_hex_prefix(s)
+        .as_bytes()
         .chunks(2)
         // .into_iter()
         .map(|ch| {
             str::from_utf8(&ch)
                 .map_err(ByteDecodeError::DecodeError)
-                .and_then(|res| u8::from_str_radix(&res, 16).map_err(ByteDecodeError::ParseError))
+                .and_then(|res| {
+                    // `from_str_radix` tolerates a leading sign, hex does not
+                    if res.starts_with('+') {
+                        return Err(ByteDecodeError::InvalidCharacter('+'));
+                    }
+                    u8::from_str_radix(&res, 16).map_err(ByteDecodeError::ParseError)
+                })
                 .map_err(Error::from)
         })
         .collect()
 }
 
+/// Parses a hexadecimal representation of a 32 byte value, such as a
+/// transaction or block hash.
+pub fn hex_str_to_bytes32(s: &str) -> Result<[u8; 32], Error> {
+    let digits = strip_hex_prefix(s);
+    ensure!(
+        digits.len() == 64,
+        "Expected 64 hexadecimal characters but {} were found",
+        digits.len()
+    );
+    let mut result = [0u8; 32];
+    result.copy_from_slice(&hex_str_to_bytes(digits)?);
+    Ok(result)
+}
+
+/// Parses a hexadecimal number (i.e. a JSON-RPC quantity) into an `Uint256`.
+pub fn hex_str_to_uint256(s: &str) -> Result<Uint256, Error> {
+    let digits = strip_hex_prefix(s);
+    ensure!(
+        !digits.is_empty(),
+        "Expected at least one hexadecimal digit"
+    );
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(ByteDecodeError::InvalidCharacter(c).into());
+    }
+    let value = Uint256::from_str_radix(digits, 16)?;
+    ensure!(value.bits() <= 256, "Value does not fit in 256 bits");
+    Ok(value)
+}
+
 pub fn big_endian_uint256_serialize<S>(x: &Uint256, s: S) -> Result<S::Ok, S::Error>
 where
     S: Serializer,
 {
-    if x == &0u32.into() {
-        s.serialize_bytes(&[])
-    } else {
-        let bytes = x.to_bytes_be();
-        s.serialize_bytes(&bytes)
-    }
+    let mut buf = [0u8; 32];
+    s.serialize_bytes(uint256_be_bytes(x, &mut buf))
+}
+
+/// The big endian bytes of `value` without leading zeros, none for zero,
+/// written to the end of `buf`.
+///
+/// Values that fit 128 bits, which are nearly all nonces, gas amounts and
+/// wei amounts, are written without allocating.
+pub(crate) fn uint256_be_bytes<'a>(value: &Uint256, buf: &'a mut [u8; 32]) -> &'a [u8] {
+    let len = match value.to_u128() {
+        Some(small) => {
+            buf[16..].copy_from_slice(&small.to_be_bytes());
+            16 - small.leading_zeros() as usize / 8
+        }
+        None => {
+            let bytes = value.to_bytes_be();
+            buf[32 - bytes.len()..].copy_from_slice(&bytes);
+            bytes.len()
+        }
+    };
+    &buf[32 - len..]
 }
 
 pub fn big_endian_uint256_deserialize<'de, D>(d: D) -> Result<Uint256, D::Error>
 where
     D: Deserializer<'de>,
 {
-    Ok(Uint256::from_bytes_be(&Vec::<u8>::deserialize(d)?))
+    Ok(Uint256::from_bytes_be(&ByteBuf::deserialize(d)?))
 }
 
 #[test]
@@ -103,11 +169,130 @@ fn parse_prefixed_non_empty() {
     );
 }
 
+#[test]
+fn strip_prefixes() {
+    assert_eq!(strip_hex_prefix("0xdead"), "dead");
+    assert_eq!(strip_hex_prefix("0Xdead"), "dead");
+    assert_eq!(strip_hex_prefix("dead"), "dead");
+    assert_eq!(strip_hex_prefix("0x"), "");
+    // Only a single prefix is stripped
+    assert_eq!(strip_hex_prefix("0x0xdead"), "0xdead");
+}
+
+#[test]
+fn parse_uppercase_prefix() {
+    assert_eq!(
+        hex_str_to_bytes("0XDEADBEEF").unwrap(),
+        vec![0xde, 0xad, 0xbe, 0xef]
+    );
+}
+
+#[test]
+fn bytes_reject_signs() {
+    let e = hex_str_to_bytes("+f+f")
+        .unwrap_err()
+        .downcast::<ByteDecodeError>()
+        .unwrap();
+    assert_eq!(e, ByteDecodeError::InvalidCharacter('+'));
+    assert!(hex_str_to_bytes("-f").is_err());
+}
+
+#[test]
+fn parse_bytes32() {
+    let hash = "0x2c1a5a5e7e7bd6d4a3ad7d1d9e8f8e27c1b4b6a4f6bd1c7e5a8a1b5d2f3e4c5d";
+    let bytes = hex_str_to_bytes32(hash).unwrap();
+    assert_eq!(bytes[0], 0x2c);
+    assert_eq!(bytes[31], 0x5d);
+    assert_eq!(hex_str_to_bytes32(&hash[2..]).unwrap(), bytes);
+    assert!(hex_str_to_bytes32(&hash[..64]).is_err());
+    assert!(hex_str_to_bytes32(&format!("{}00", hash)).is_err());
+    assert!(hex_str_to_bytes32(&hash.replace("2c", "zz")).is_err());
+}
+
+#[test]
+fn parse_uint256() {
+    assert_eq!(hex_str_to_uint256("0x0").unwrap(), 0u32.into());
+    assert_eq!(
+        hex_str_to_uint256("0x1bc16d674ec80000").unwrap(),
+        2_000_000_000_000_000_000u64.into()
+    );
+    assert_eq!(hex_str_to_uint256("0XfF").unwrap(), 255u32.into());
+    assert_eq!(hex_str_to_uint256("ff").unwrap(), 255u32.into());
+    let max = format!("0x{}", "f".repeat(64));
+    assert_eq!(
+        hex_str_to_uint256(&max).unwrap(),
+        Uint256::from([0xffu8; 32])
+    );
+    assert!(hex_str_to_uint256(&format!("{}f", max)).is_err());
+    assert!(hex_str_to_uint256("0x").is_err());
+    assert!(hex_str_to_uint256("").is_err());
+    assert!(hex_str_to_uint256("0x+1").is_err());
+    assert!(hex_str_to_uint256("0x1_0").is_err());
+    assert!(hex_str_to_uint256("0xg").is_err());
+}
+
+/// Bytes formatted as hexadecimal, without allocating.
+///
+/// `Display` and `{:x}` write lowercase digits and `{:X}` uppercase ones.
+/// The alternate flag adds a `0x` prefix, as it does for integers:
+///
+/// ```rust
+/// use clarity::utils::HexFmt;
+///
+/// let bytes = [0xde, 0xad, 0xbe, 0xef];
+/// assert_eq!(format!("{}", HexFmt(&bytes)), "deadbeef");
+/// assert_eq!(format!("{:#X}", HexFmt(&bytes)), "0xDEADBEEF");
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HexFmt<'a>(pub &'a [u8]);
+
+impl<'a> HexFmt<'a> {
+    fn write(&self, f: &mut fmt::Formatter, digits: &[u8; 16]) -> fmt::Result {
+        if f.alternate() {
+            f.write_str("0x")?;
+        }
+        let mut buf = [0u8; 64];
+        for chunk in self.0.chunks(32) {
+            for (i, b) in chunk.iter().enumerate() {
+                buf[2 * i] = digits[(b >> 4) as usize];
+                buf[2 * i + 1] = digits[(b & 0xf) as usize];
+            }
+            // The digits are ASCII
+            f.write_str(str::from_utf8(&buf[..2 * chunk.len()]).unwrap())?;
+        }
+        Ok(())
+    }
+}
+
+const LOWER_DIGITS: &[u8; 16] = b"0123456789abcdef";
+const UPPER_DIGITS: &[u8; 16] = b"0123456789ABCDEF";
+
+impl<'a> fmt::Display for HexFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, LOWER_DIGITS)
+    }
+}
+
+impl<'a> fmt::LowerHex for HexFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, LOWER_DIGITS)
+    }
+}
+
+impl<'a> fmt::UpperHex for HexFmt<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, UPPER_DIGITS)
+    }
+}
+
+/// Lowercase hexadecimal, without a prefix.
 pub fn bytes_to_hex_str(bytes: &[u8]) -> String {
-    bytes
-        .iter()
-        .map(|b| format!("{:0>2x?}", b))
-        .fold(String::new(), |acc, x| acc + &x)
+    HexFmt(bytes).to_string()
+}
+
+/// Uppercase hexadecimal, without a prefix.
+pub fn bytes_to_hex_str_upper(bytes: &[u8]) -> String {
+    format!("{:X}", HexFmt(bytes))
 }
 
 #[test]
@@ -118,6 +303,18 @@ fn encode_bytes() {
         bytes_to_hex_str(&[0xde, 0xad, 0xbe, 0xef]),
         "deadbeef".to_owned()
     );
+    assert_eq!(bytes_to_hex_str(&[]), "");
+    assert_eq!(bytes_to_hex_str_upper(&[0x0a, 0xbc]), "0ABC");
+}
+
+#[test]
+fn format_hex() {
+    let long: Vec<u8> = (0..=255).collect();
+    let expected: String = long.iter().map(|b| format!("{:02x}", b)).collect();
+    assert_eq!(format!("{:x}", HexFmt(&long)), expected);
+    assert_eq!(format!("{:X}", HexFmt(&long)), expected.to_uppercase());
+    assert_eq!(format!("{:#x}", HexFmt(&[0x01])), "0x01");
+    assert_eq!(format!("{:#x}", HexFmt(&[])), "0x");
 }
 
 /// Pad bytes with zeros at the beggining.
@@ -144,3 +341,22 @@ fn verify_zpad_exact() {
 fn verify_zpad_less_than_size() {
     assert_eq!(zpad(&[1, 2, 3, 4], 2), [1, 2, 3, 4]);
 }
+
+#[test]
+fn minimal_big_endian_bytes() {
+    let mut buf = [0u8; 32];
+    assert_eq!(uint256_be_bytes(&0u8.into(), &mut buf), &[] as &[u8]);
+    assert_eq!(uint256_be_bytes(&1u8.into(), &mut buf), &[1]);
+    assert_eq!(uint256_be_bytes(&0x0400u16.into(), &mut buf), &[0x04, 0x00]);
+    assert_eq!(
+        uint256_be_bytes(&u128::MAX.into(), &mut buf),
+        &[0xff; 16][..]
+    );
+    let large = Uint256::from(u128::MAX) * Uint256::from(0x100u16);
+    assert_eq!(
+        uint256_be_bytes(&large, &mut buf),
+        &[&[0xffu8; 16][..], &[0x00]].concat()[..]
+    );
+    let max = Uint256::from_bytes_be(&[0xff; 32]);
+    assert_eq!(uint256_be_bytes(&max, &mut buf), &[0xff; 32][..]);
+}
@@ -0,0 +1,331 @@
+//! Versioned serialization of long lived data.
+//!
+//! Data that a signer stores, such as its database of pending transactions,
+//! has to stay readable after the structs it was written from change.
+//! [Versioned](struct.Versioned.html) writes a value along with the version
+//! of its format, and reads it back through
+//! [Migrate::from_version](trait.Migrate.html#tymethod.from_version), which
+//! decodes older versions with the structs they were written from and
+//! migrates them:
+//!
+//! ```rust
+//! # #[macro_use]
+//! # extern crate serde_derive;
+//! # extern crate clarity;
+//! # extern crate serde;
+//! # extern crate serde_json;
+//! use clarity::versioned::{upgrade, Migrate, Versioned, VersionedError};
+//! use serde::{de, Deserialize, Deserializer};
+//!
+//! #[derive(Deserialize)]
+//! struct AccountV1 {
+//!     nonce: u64,
+//! }
+//!
+//! #[derive(Debug, PartialEq, Serialize, Deserialize)]
+//! struct Account {
+//!     nonce: u64,
+//!     label: String,
+//! }
+//!
+//! impl From<AccountV1> for Account {
+//!     fn from(v1: AccountV1) -> Account {
+//!         Account { nonce: v1.nonce, label: String::new() }
+//!     }
+//! }
+//!
+//! impl Migrate for Account {
+//!     const VERSION: u32 = 2;
+//!
+//!     fn from_version<'de, D: Deserializer<'de>>(version: u32, data: D) -> Result<Account, D::Error> {
+//!         match version {
+//!             1 => upgrade::<AccountV1, Account, D>(data),
+//!             2 => Account::deserialize(data),
+//!             _ => Err(de::Error::custom(VersionedError::UnknownVersion(version))),
+//!         }
+//!     }
+//! }
+//!
+//! # fn main() {
+//! let old = r#"{"version": 1, "data": {"nonce": 7}}"#;
+//! let account: Versioned<Account> = serde_json::from_str(old).unwrap();
+//! assert_eq!(account.0, Account { nonce: 7, label: String::new() });
+//! // Saving writes the current version
+//! assert_eq!(
+//!     serde_json::to_string(&account).unwrap(),
+//!     r#"{"version":2,"data":{"nonce":7,"label":""}}"#
+//! );
+//! # }
+//! ```
+//!
+//! The version is written before the data, and has to come first when it
+//! is read, so that the data can be decoded without buffering it. Keystores
+//! have versions of their own and are not wrapped.
+use serde_crate::de::{self, DeserializeOwned, DeserializeSeed, Visitor};
+use serde_crate::ser::SerializeStruct;
+use serde_crate::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::marker::PhantomData;
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum VersionedError {
+    #[fail(display = "Data was written by a newer version of the format ({})", _0)]
+    NewerVersion(u32),
+    #[fail(display = "Unknown version {} of the format", _0)]
+    UnknownVersion(u32),
+}
+
+/// A type whose serialized format has versions.
+pub trait Migrate: Serialize + DeserializeOwned {
+    /// Version of the format that is written.
+    const VERSION: u32;
+
+    /// Decodes `data` written with the given `version` of the format.
+    ///
+    /// The default only reads the current version. Types that changed
+    /// decode each older version with the struct it was written from, and
+    /// convert it, e.g. with [upgrade](fn.upgrade.html).
+    fn from_version<'de, D: Deserializer<'de>>(version: u32, data: D) -> Result<Self, D::Error> {
+        if version == Self::VERSION {
+            Self::deserialize(data)
+        } else if version > Self::VERSION {
+            Err(de::Error::custom(VersionedError::NewerVersion(version)))
+        } else {
+            Err(de::Error::custom(VersionedError::UnknownVersion(version)))
+        }
+    }
+}
+
+/// Decodes data of an older format as `Old`, and migrates it to `New`.
+pub fn upgrade<'de, Old, New, D>(data: D) -> Result<New, D::Error>
+where
+    Old: Deserialize<'de>,
+    New: From<Old>,
+    D: Deserializer<'de>,
+{
+    Old::deserialize(data).map(New::from)
+}
+
+/// A value that serializes along with the version of its format, as a
+/// struct with a `version` and a `data` field.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Versioned<T>(pub T);
+
+impl<T> Versioned<T> {
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: Migrate> Serialize for Versioned<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Versioned", 2)?;
+        state.serialize_field("version", &T::VERSION)?;
+        state.serialize_field("data", &self.0)?;
+        state.end()
+    }
+}
+
+const FIELDS: &[&str] = &["version", "data"];
+
+/// Decodes the data of the version that was read before it.
+struct DataSeed<T> {
+    version: u32,
+    marker: PhantomData<T>,
+}
+
+impl<'de, T: Migrate> DeserializeSeed<'de> for DataSeed<T> {
+    type Value = T;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<T, D::Error> {
+        T::from_version(self.version, deserializer)
+    }
+}
+
+struct VersionedVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Migrate> Visitor<'de> for VersionedVisitor<T> {
+    type Value = Versioned<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a version followed by data")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<Versioned<T>, A::Error> {
+        let version: u32 = seq
+            .next_element()?
+            .ok_or_else(|| de::Error::invalid_length(0, &self))?;
+        let seed = DataSeed {
+            version,
+            marker: PhantomData,
+        };
+        let data = seq
+            .next_element_seed(seed)?
+            .ok_or_else(|| de::Error::invalid_length(1, &self))?;
+        Ok(Versioned(data))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, mut map: A) -> Result<Versioned<T>, A::Error> {
+        match map.next_key::<String>()? {
+            Some(ref key) if key == "version" => {}
+            Some(ref key) if key == "data" => {
+                return Err(de::Error::custom("version has to come before data"))
+            }
+            Some(ref key) => return Err(de::Error::unknown_field(key, FIELDS)),
+            None => return Err(de::Error::missing_field("version")),
+        }
+        let version: u32 = map.next_value()?;
+        match map.next_key::<String>()? {
+            Some(ref key) if key == "data" => {}
+            Some(ref key) if key == "version" => return Err(de::Error::duplicate_field("version")),
+            Some(ref key) => return Err(de::Error::unknown_field(key, FIELDS)),
+            None => return Err(de::Error::missing_field("data")),
+        }
+        let data = map.next_value_seed(DataSeed {
+            version,
+            marker: PhantomData,
+        })?;
+        match map.next_key::<String>()? {
+            None => Ok(Versioned(data)),
+            Some(ref key) if key == "version" => Err(de::Error::duplicate_field("version")),
+            Some(ref key) if key == "data" => Err(de::Error::duplicate_field("data")),
+            Some(ref key) => Err(de::Error::unknown_field(key, FIELDS)),
+        }
+    }
+}
+
+impl<'de, T: Migrate> Deserialize<'de> for Versioned<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Versioned<T>, D::Error> {
+        deserializer.deserialize_struct("Versioned", FIELDS, VersionedVisitor(PhantomData))
+    }
+}
+
+#[cfg(test)]
+#[derive(Deserialize)]
+struct TestV1 {
+    nonce: u64,
+}
+
+#[cfg(test)]
+#[derive(Deserialize)]
+struct TestV2 {
+    nonce: u64,
+    pending: bool,
+}
+
+#[cfg(test)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TestRecord {
+    nonce: u64,
+    pending: bool,
+    label: String,
+}
+
+#[cfg(test)]
+impl From<TestV1> for TestV2 {
+    fn from(v1: TestV1) -> TestV2 {
+        TestV2 {
+            nonce: v1.nonce,
+            pending: false,
+        }
+    }
+}
+
+#[cfg(test)]
+impl From<TestV2> for TestRecord {
+    fn from(v2: TestV2) -> TestRecord {
+        TestRecord {
+            nonce: v2.nonce,
+            pending: v2.pending,
+            label: "unnamed".to_owned(),
+        }
+    }
+}
+
+#[cfg(test)]
+impl Migrate for TestRecord {
+    const VERSION: u32 = 3;
+
+    fn from_version<'de, D: Deserializer<'de>>(
+        version: u32,
+        data: D,
+    ) -> Result<TestRecord, D::Error> {
+        match version {
+            // Migrations chain through every version in between
+            1 => upgrade::<TestV1, TestV2, D>(data).map(TestRecord::from),
+            2 => upgrade::<TestV2, TestRecord, D>(data),
+            3 => TestRecord::deserialize(data),
+            _ => Err(de::Error::custom(VersionedError::UnknownVersion(version))),
+        }
+    }
+}
+
+#[test]
+fn migrate_json() {
+    let record: Versioned<TestRecord> =
+        serde_json::from_str(r#"{"version": 1, "data": {"nonce": 5}}"#).unwrap();
+    assert_eq!(
+        record.0,
+        TestRecord {
+            nonce: 5,
+            pending: false,
+            label: "unnamed".to_owned(),
+        }
+    );
+    let record: Versioned<TestRecord> =
+        serde_json::from_str(r#"{"version": 2, "data": {"nonce": 5, "pending": true}}"#).unwrap();
+    assert!(record.0.pending);
+
+    let json = serde_json::to_string(&record).unwrap();
+    assert_eq!(
+        json,
+        r#"{"version":3,"data":{"nonce":5,"pending":true,"label":"unnamed"}}"#
+    );
+    assert_eq!(
+        serde_json::from_str::<Versioned<TestRecord>>(&json).unwrap(),
+        record
+    );
+
+    assert!(
+        serde_json::from_str::<Versioned<TestRecord>>(r#"{"version": 4, "data": {}}"#).is_err()
+    );
+    assert!(serde_json::from_str::<Versioned<TestRecord>>(
+        r#"{"data": {"nonce": 5}, "version": 1}"#
+    )
+    .is_err());
+    assert!(serde_json::from_str::<Versioned<TestRecord>>(r#"{"version": 1}"#).is_err());
+}
+
+#[cfg(test)]
+impl Migrate for ::signature::Signature {
+    const VERSION: u32 = 1;
+}
+
+#[test]
+fn default_migration() {
+    use signature::Signature;
+    let signature = Signature::new(27u8.into(), 1u8.into(), 2u8.into());
+    let json = serde_json::to_string(&Versioned(signature.clone())).unwrap();
+    assert_eq!(
+        serde_json::from_str::<Versioned<Signature>>(&json).unwrap(),
+        Versioned(signature)
+    );
+    let newer = json.replace("\"version\":1", "\"version\":2");
+    let error = serde_json::from_str::<Versioned<Signature>>(&newer).unwrap_err();
+    assert!(error.to_string().contains("newer version"));
+}
+
+#[test]
+fn migrate_rlp() {
+    use rlp_serde::{from_bytes, to_bytes};
+    // Binary formats see the wrapper as a sequence
+    let v2 = to_bytes(&(2u32, (9u64, true))).unwrap();
+    let record: Versioned<TestRecord> = from_bytes(&v2).unwrap();
+    assert_eq!(record.0.nonce, 9);
+    assert_eq!(record.0.label, "unnamed");
+    let encoded = to_bytes(&record).unwrap();
+    assert_eq!(
+        from_bytes::<Versioned<TestRecord>>(&encoded).unwrap(),
+        record
+    );
+}
@@ -0,0 +1,303 @@
+//! zkSync Era EIP-712 transactions.
+//!
+//! Besides the Ethereum transaction types, zkSync Era accepts its own type
+//! 113 (`0x71`) transaction, which carries the bytecode of contracts to
+//! deploy ("factory deps") and the paymaster that pays for it. It is signed
+//! as EIP-712 typed data rather than over its RLP encoding, and the
+//! signature is part of the encoding as a plain byte string, so that smart
+//! contract accounts can put whatever their validation expects there.
+use abi::{encode_tokens, Token};
+use address::Address;
+use failure::Error;
+use hash::keccak256;
+use num256::Uint256;
+use private_key::PrivateKey;
+use serde_bytes::ByteBuf;
+use serde_rlp::ser::to_bytes;
+use sha2::Sha256;
+use types::BigEndianInt;
+
+/// Type byte of zkSync EIP-712 transactions.
+pub const EIP712_TX_TYPE: u8 = 0x71;
+
+/// The gas per pubdata byte limit wallets use by default.
+pub const DEFAULT_GAS_PER_PUBDATA_LIMIT: u64 = 50_000;
+
+/// The EIP-712 type of the transaction, as hashed for the type hash.
+pub const TRANSACTION_TYPE: &str = "Transaction(uint256 txType,uint256 from,uint256 to,\
+uint256 gasLimit,uint256 gasPerPubdataByteLimit,uint256 maxFeePerGas,\
+uint256 maxPriorityFeePerGas,uint256 paymaster,uint256 nonce,uint256 value,bytes data,\
+bytes32[] factoryDeps,bytes paymasterInput)";
+
+const DOMAIN_TYPE: &str = "EIP712Domain(string name,string version,uint256 chainId)";
+
+#[derive(Fail, Debug, PartialEq)]
+pub enum ZkSyncError {
+    #[fail(display = "Bytecode length {} is not a multiple of 32", _0)]
+    BytecodeLength(usize),
+    #[fail(display = "Bytecode has an even number of words ({})", _0)]
+    EvenWordCount(usize),
+    #[fail(display = "Bytecode of {} words is too long", _0)]
+    BytecodeTooLong(usize),
+}
+
+/// Hash of contract bytecode as zkSync identifies it: a version byte, a
+/// zero byte, the length in words and the end of its sha256 hash.
+///
+/// zkSync bytecode is made of 32 byte words, and has an odd number of them.
+pub fn hash_bytecode(bytecode: &[u8]) -> Result<[u8; 32], Error> {
+    if !bytecode.len().is_multiple_of(32) {
+        return Err(ZkSyncError::BytecodeLength(bytecode.len()).into());
+    }
+    let words = bytecode.len() / 32;
+    if words > u16::MAX as usize {
+        return Err(ZkSyncError::BytecodeTooLong(words).into());
+    }
+    if words.is_multiple_of(2) {
+        return Err(ZkSyncError::EvenWordCount(words).into());
+    }
+    let mut hash = [0u8; 32];
+    hash.copy_from_slice(&<Sha256 as sha2::Digest>::digest(bytecode));
+    hash[0] = 1;
+    hash[1] = 0;
+    hash[2..4].copy_from_slice(&(words as u16).to_be_bytes());
+    Ok(hash)
+}
+
+/// The paymaster of a transaction and the input of its validation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymasterParams {
+    pub paymaster: Address,
+    pub paymaster_input: Vec<u8>,
+}
+
+/// A zkSync Era EIP-712 transaction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Eip712Transaction {
+    pub chain_id: u64,
+    pub nonce: Uint256,
+    /// The account the transaction is sent from. Unlike on Ethereum, it is
+    /// part of the transaction, since accounts are not necessarily keys.
+    pub from: Address,
+    pub to: Address,
+    pub gas_limit: Uint256,
+    pub gas_per_pubdata_byte_limit: Uint256,
+    pub max_fee_per_gas: Uint256,
+    pub max_priority_fee_per_gas: Uint256,
+    pub value: Uint256,
+    pub data: Vec<u8>,
+    /// Bytecode of the contracts the transaction deploys
+    pub factory_deps: Vec<Vec<u8>>,
+    pub paymaster_params: Option<PaymasterParams>,
+    /// The signature, an ECDSA signature for externally owned accounts
+    pub signature: Option<Vec<u8>>,
+}
+
+impl Eip712Transaction {
+    /// The EIP-712 domain separator of the chain.
+    pub fn domain_separator(&self) -> [u8; 32] {
+        keccak256(&encode_tokens(&[
+            Token::Bytes(keccak256(DOMAIN_TYPE.as_bytes()).to_vec()),
+            Token::Bytes(keccak256(b"zkSync").to_vec()),
+            Token::Bytes(keccak256(b"2").to_vec()),
+            self.chain_id.into(),
+        ]))
+    }
+
+    /// The EIP-712 struct hash of the transaction.
+    pub fn struct_hash(&self) -> Result<[u8; 32], Error> {
+        let mut factory_deps = Vec::with_capacity(self.factory_deps.len() * 32);
+        for bytecode in &self.factory_deps {
+            factory_deps.extend_from_slice(&hash_bytecode(bytecode)?);
+        }
+        let (paymaster, paymaster_input) = match self.paymaster_params {
+            Some(ref params) => (params.paymaster, &params.paymaster_input[..]),
+            None => (Address::default(), &[][..]),
+        };
+        Ok(keccak256(&encode_tokens(&[
+            Token::Bytes(keccak256(TRANSACTION_TYPE.as_bytes()).to_vec()),
+            EIP712_TX_TYPE.into(),
+            Token::Address(self.from),
+            Token::Address(self.to),
+            Token::Uint(self.gas_limit.clone()),
+            Token::Uint(self.gas_per_pubdata_byte_limit.clone()),
+            Token::Uint(self.max_fee_per_gas.clone()),
+            Token::Uint(self.max_priority_fee_per_gas.clone()),
+            Token::Address(paymaster),
+            Token::Uint(self.nonce.clone()),
+            Token::Uint(self.value.clone()),
+            Token::Bytes(keccak256(&self.data).to_vec()),
+            Token::Bytes(keccak256(&factory_deps).to_vec()),
+            Token::Bytes(keccak256(paymaster_input).to_vec()),
+        ])))
+    }
+
+    /// The digest the account signs, `keccak256(0x1901 || domain || struct)`.
+    pub fn signing_hash(&self) -> Result<[u8; 32], Error> {
+        let mut data = vec![0x19, 0x01];
+        data.extend_from_slice(&self.domain_separator());
+        data.extend_from_slice(&self.struct_hash()?);
+        Ok(keccak256(&data))
+    }
+
+    /// Signs the transaction as an externally owned account.
+    pub fn sign(&self, key: &PrivateKey) -> Result<Eip712Transaction, Error> {
+        let signature = key.sign_hash(&self.signing_hash()?);
+        Ok(Eip712Transaction {
+            signature: Some(signature.to_bytes().to_vec()),
+            ..self.clone()
+        })
+    }
+
+    /// The hash the transaction is known by on zkSync,
+    /// `keccak256(signing hash || keccak256(signature))`. `None` until it is
+    /// signed.
+    pub fn hash(&self) -> Result<Option<[u8; 32]>, Error> {
+        let signature = match self.signature {
+            Some(ref signature) => signature,
+            None => return Ok(None),
+        };
+        let mut data = self.signing_hash()?.to_vec();
+        data.extend_from_slice(&keccak256(signature));
+        Ok(Some(keccak256(&data)))
+    }
+
+    /// The type byte followed by the RLP encoding of the transaction, as
+    /// sent with `eth_sendRawTransaction`.
+    pub fn to_bytes(&self) -> Result<Vec<u8>, Error> {
+        let chain_id = BigEndianInt(self.chain_id.into());
+        let factory_deps: Vec<ByteBuf> = self
+            .factory_deps
+            .iter()
+            .map(|bytecode| ByteBuf::from(bytecode.clone()))
+            .collect();
+        let paymaster_params: Vec<ByteBuf> = match self.paymaster_params {
+            Some(ref params) => vec![
+                ByteBuf::from(params.paymaster.as_bytes().to_vec()),
+                ByteBuf::from(params.paymaster_input.clone()),
+            ],
+            None => Vec::new(),
+        };
+        let empty = ByteBuf::new();
+        let fields = (
+            &BigEndianInt(self.nonce.clone()),
+            &BigEndianInt(self.max_priority_fee_per_gas.clone()),
+            &BigEndianInt(self.max_fee_per_gas.clone()),
+            &BigEndianInt(self.gas_limit.clone()),
+            &ByteBuf::from(self.to.as_bytes().to_vec()),
+            &BigEndianInt(self.value.clone()),
+            &ByteBuf::from(self.data.clone()),
+            // In place of v, r and s, as the signature comes later
+            &chain_id,
+            &empty,
+            &empty,
+            &chain_id,
+            &ByteBuf::from(self.from.as_bytes().to_vec()),
+            &BigEndianInt(self.gas_per_pubdata_byte_limit.clone()),
+            &factory_deps,
+            &ByteBuf::from(self.signature.clone().unwrap_or_default()),
+            &paymaster_params,
+        );
+        let mut bytes = vec![EIP712_TX_TYPE];
+        bytes.extend(to_bytes(&fields)?);
+        Ok(bytes)
+    }
+}
+
+#[cfg(test)]
+fn test_transaction() -> Eip712Transaction {
+    Eip712Transaction {
+        chain_id: 324,
+        nonce: 3u8.into(),
+        from: "0x1111111111111111111111111111111111111111"
+            .parse()
+            .unwrap(),
+        to: Address::from([0x22u8; 20]),
+        gas_limit: 1_000_000u32.into(),
+        gas_per_pubdata_byte_limit: DEFAULT_GAS_PER_PUBDATA_LIMIT.into(),
+        max_fee_per_gas: 250_000_000u32.into(),
+        max_priority_fee_per_gas: 0u8.into(),
+        value: 0u8.into(),
+        data: vec![0xd0, 0x9d, 0xe0, 0x8a],
+        factory_deps: Vec::new(),
+        paymaster_params: None,
+        signature: None,
+    }
+}
+
+#[test]
+fn bytecode_hashes() {
+    let bytecode = vec![0xaau8; 32 * 3];
+    let hash = hash_bytecode(&bytecode).unwrap();
+    assert_eq!(&hash[..4], &[0x01, 0x00, 0x00, 0x03]);
+    assert_eq!(
+        &hash[4..],
+        &<Sha256 as sha2::Digest>::digest(&bytecode)[4..]
+    );
+    assert_eq!(
+        hash_bytecode(&[0u8; 33])
+            .unwrap_err()
+            .downcast::<ZkSyncError>()
+            .unwrap(),
+        ZkSyncError::BytecodeLength(33)
+    );
+    assert_eq!(
+        hash_bytecode(&[0u8; 64])
+            .unwrap_err()
+            .downcast::<ZkSyncError>()
+            .unwrap(),
+        ZkSyncError::EvenWordCount(2)
+    );
+}
+
+#[test]
+fn sign_and_encode() {
+    use signature::Signature;
+    use utils::bytes_to_hex_str;
+    let key: PrivateKey = "c87f65ff3f271bf5dc8643484f66b200109caffe4bf98c4cb393dc35740b28c0"
+        .parse()
+        .unwrap();
+    let tx = Eip712Transaction {
+        from: key.to_public_key().unwrap(),
+        ..test_transaction()
+    };
+    assert_eq!(tx.hash().unwrap(), None);
+    let signed = tx.sign(&key).unwrap();
+    let signature = Signature::from_bytes(signed.signature.as_ref().unwrap()).unwrap();
+    assert_eq!(
+        signature.recover(&tx.signing_hash().unwrap()).unwrap(),
+        tx.from
+    );
+    assert!(signed.hash().unwrap().is_some());
+
+    // The signature is not part of the signed data, everything else is
+    assert_eq!(signed.signing_hash().unwrap(), tx.signing_hash().unwrap());
+    let other_chain = Eip712Transaction {
+        chain_id: 300,
+        ..tx.clone()
+    };
+    assert_ne!(other_chain.domain_separator(), tx.domain_separator());
+    let sponsored = Eip712Transaction {
+        paymaster_params: Some(PaymasterParams {
+            paymaster: Address::from([0x33u8; 20]),
+            paymaster_input: vec![0x01],
+        }),
+        ..tx.clone()
+    };
+    assert_ne!(sponsored.struct_hash().unwrap(), tx.struct_hash().unwrap());
+
+    let bytes = signed.to_bytes().unwrap();
+    assert_eq!(bytes[0], EIP712_TX_TYPE);
+    // Ends with the signature and the empty paymaster params
+    let mut tail = vec![0xb8, 0x41];
+    tail.extend_from_slice(&signature.to_bytes());
+    tail.push(0xc0);
+    assert!(bytes_to_hex_str(&bytes).ends_with(&bytes_to_hex_str(&tail)));
+
+    // Factory deps have to be valid bytecode
+    let deploy = Eip712Transaction {
+        factory_deps: vec![vec![0u8; 31]],
+        ..tx
+    };
+    assert!(deploy.sign(&key).is_err());
+}